@@ -0,0 +1,7 @@
+fn main() {
+    // Reported by `mdcopy --version --json`
+    println!(
+        "cargo:rustc-env=MDCOPY_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+}
@@ -0,0 +1,116 @@
+use markdown::mdast::{Blockquote, Node};
+
+/// GitHub alert types (`> [!NOTE]` and friends)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AlertKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "NOTE" => Some(AlertKind::Note),
+            "TIP" => Some(AlertKind::Tip),
+            "IMPORTANT" => Some(AlertKind::Important),
+            "WARNING" => Some(AlertKind::Warning),
+            "CAUTION" => Some(AlertKind::Caution),
+            _ => None,
+        }
+    }
+
+    /// Title shown above the alert
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertKind::Note => "Note",
+            AlertKind::Tip => "Tip",
+            AlertKind::Important => "Important",
+            AlertKind::Warning => "Warning",
+            AlertKind::Caution => "Caution",
+        }
+    }
+
+    /// GitHub's accent color for the alert type
+    pub fn color(self) -> (u8, u8, u8) {
+        match self {
+            AlertKind::Note => (0x09, 0x69, 0xda),
+            AlertKind::Tip => (0x1a, 0x7f, 0x37),
+            AlertKind::Important => (0x82, 0x50, 0xdf),
+            AlertKind::Warning => (0x9a, 0x67, 0x00),
+            AlertKind::Caution => (0xd1, 0x24, 0x2f),
+        }
+    }
+}
+
+/// The alert type of a blockquote starting with a `[!TYPE]` marker, and its
+/// content with the marker removed
+pub fn alert(quote: &Blockquote) -> Option<(AlertKind, Vec<Node>)> {
+    let Some(Node::Paragraph(first)) = quote.children.first() else {
+        return None;
+    };
+    let Some(Node::Text(text)) = first.children.first() else {
+        return None;
+    };
+    let rest = text.value.strip_prefix("[!")?;
+    let (name, rest) = rest.split_once(']')?;
+    let kind = AlertKind::parse(name)?;
+    // The marker must be alone on its line
+    let rest = match rest.split_once('\n') {
+        Some((same_line, next)) if same_line.trim().is_empty() => next,
+        None if rest.trim().is_empty() => "",
+        _ => return None,
+    };
+
+    let mut children = quote.children.clone();
+    let Some(Node::Paragraph(para)) = children.first_mut() else {
+        unreachable!("checked above");
+    };
+    match &mut para.children[0] {
+        Node::Text(t) if !rest.is_empty() => t.value = rest.to_string(),
+        _ => {
+            para.children.remove(0);
+        }
+    }
+    // A line break right after the marker would start the content
+    if matches!(para.children.first(), Some(Node::Break(_))) {
+        para.children.remove(0);
+    }
+    if para.children.is_empty() {
+        children.remove(0);
+    }
+    Some((kind, children))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn first_alert(md: &str) -> Option<(AlertKind, Vec<Node>)> {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        match &ast.children().unwrap()[0] {
+            Node::Blockquote(quote) => alert(quote),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_alert_markers() {
+        let (kind, children) = first_alert("> [!WARNING]\n> Mind the *gap*.").unwrap();
+        assert_eq!(kind, AlertKind::Warning);
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].to_string(), "Mind the gap.");
+
+        // Content in its own paragraph, lowercase marker
+        let (kind, children) = first_alert("> [!tip]\n>\n> Use it.").unwrap();
+        assert_eq!(kind, AlertKind::Tip);
+        assert_eq!(children[0].to_string(), "Use it.");
+
+        assert!(first_alert("> [!NOTE] inline text").is_none());
+        assert!(first_alert("> [!UNKNOWN]\n> text").is_none());
+        assert!(first_alert("> plain quote").is_none());
+    }
+}
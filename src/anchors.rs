@@ -0,0 +1,153 @@
+//! Heading anchors, shared by every format that can link within a document
+//! (HTML ids, EPUB navigation, RTF bookmarks, PDF destinations), so a
+//! `#fragment` link lands on the same heading whatever the output. Anchors
+//! are GitHub's heading slugs, with repeated headings numbered `-1`, `-2`, ...
+//! in document order.
+
+use markdown::mdast::Node;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::HashSet;
+
+/// The anchor of every heading in a document, handed out in document order
+#[derive(Debug, Default)]
+pub struct Anchors {
+    slugs: Vec<String>,
+    /// Index of the next heading to render
+    next: Cell<usize>,
+}
+
+impl Anchors {
+    pub fn collect(root: &Node) -> Self {
+        fn walk(node: &Node, slugs: &mut Vec<String>, seen: &mut HashSet<String>) {
+            if let Node::Heading(_) = node {
+                let base = slugify(&node.to_string());
+                let mut slug = base.clone();
+                let mut n = 0;
+                while !seen.insert(slug.clone()) {
+                    n += 1;
+                    slug = format!("{}-{}", base, n);
+                }
+                slugs.push(slug);
+                return;
+            }
+            for child in node.children().into_iter().flatten() {
+                walk(child, slugs, seen);
+            }
+        }
+
+        let mut slugs = Vec::new();
+        walk(root, &mut slugs, &mut HashSet::new());
+        Self {
+            slugs,
+            next: Cell::new(0),
+        }
+    }
+
+    /// Every anchor, in document order
+    pub fn slugs(&self) -> &[String] {
+        &self.slugs
+    }
+
+    /// The anchor of the next heading rendered; renderers call this once
+    /// per heading, in document order
+    pub fn next(&self) -> Option<&str> {
+        let index = self.next.replace(self.next.get() + 1);
+        self.slugs.get(index).map(String::as_str)
+    }
+
+    /// The heading a `#fragment` link points at, matched the way GitHub
+    /// does: `#Release-Notes` and `#release%20notes` both name
+    /// `release-notes`. None for other URLs and unknown fragments.
+    pub fn resolve(&self, url: &str) -> Option<&str> {
+        let fragment = url.strip_prefix('#')?;
+        let slug = slugify(&percent_decode(fragment));
+        self.slugs.iter().find(|s| **s == slug).map(String::as_str)
+    }
+
+    /// `url` pointed at its heading's anchor when it names one, unchanged
+    /// otherwise
+    pub fn resolve_url<'u>(&self, url: &'u str) -> Cow<'u, str> {
+        match self.resolve(url) {
+            Some(slug) if url[1..] != *slug => format!("#{}", slug).into(),
+            _ => url.into(),
+        }
+    }
+}
+
+/// GitHub's heading slug: lowercase, spaces to hyphens, and punctuation
+/// other than `-` and `_` dropped
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("API v2.0 (beta)"), "api-v20-beta");
+        assert_eq!(slugify("snake_case & kebab-case"), "snake_case--kebab-case");
+        assert_eq!(slugify("Über Café"), "über-café");
+    }
+
+    #[test]
+    fn test_anchors() {
+        let ast = markdown::to_mdast(
+            "# Setup\n\n> ## Setup\n\n## Setup 1\n\n## Setup\n",
+            &ParseOptions::gfm(),
+        )
+        .unwrap();
+        let anchors = Anchors::collect(&ast);
+        // The quoted heading already took `setup-1`, so the literal "Setup 1"
+        // is numbered past it
+        assert_eq!(
+            anchors.slugs(),
+            &["setup", "setup-1", "setup-1-1", "setup-2"]
+        );
+        assert_eq!(anchors.next(), Some("setup"));
+        assert_eq!(anchors.next(), Some("setup-1"));
+
+        assert_eq!(anchors.resolve("#SETUP-2"), Some("setup-2"));
+        assert_eq!(anchors.resolve("#setup%201"), Some("setup-1"));
+        assert_eq!(anchors.resolve("#missing"), None);
+        assert_eq!(anchors.resolve("https://x.io/#setup"), None);
+        assert_eq!(anchors.resolve_url("#Setup"), "#setup");
+        assert_eq!(anchors.resolve_url("#missing"), "#missing");
+    }
+}
@@ -0,0 +1,343 @@
+use log::debug;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Expand an output filename template for an input file and output extension.
+///
+/// Placeholders: `{stem}` (file name without extension), `{name}` (file name),
+/// `{ext}` (output extension) and `{dir}` (the input's parent directory).
+/// The result is always relative, so it stays inside the output directory.
+pub fn expand_name_template(template: &str, input: &Path, ext: &str) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = input
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = input
+        .parent()
+        .map(|p| relative_components(p).to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let expanded = template
+        .replace("{stem}", &stem)
+        .replace("{name}", &name)
+        .replace("{ext}", ext)
+        .replace("{dir}", &dir);
+
+    relative_components(Path::new(&expanded))
+}
+
+/// Expand the template for every input and extension, giving each input's
+/// output paths in `exts` order. Two inputs expanding to the same path would
+/// overwrite each other (and race when converted in parallel), so that's an
+/// error naming both.
+pub fn plan_outputs(
+    template: &str,
+    inputs: &[PathBuf],
+    exts: &[&str],
+) -> Result<Vec<Vec<PathBuf>>, String> {
+    let mut claimed: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut planned = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let mut paths = Vec::with_capacity(exts.len());
+        for ext in exts {
+            let path = expand_name_template(template, input, ext);
+            if let Some(other) = claimed.insert(path.clone(), input) {
+                return Err(if other == input {
+                    format!(
+                        "{} would be written to {} more than once (add {{ext}} to the template)",
+                        input.display(),
+                        path.display()
+                    )
+                } else {
+                    format!(
+                        "{} and {} would both be written to {}",
+                        other.display(),
+                        input.display(),
+                        path.display()
+                    )
+                });
+            }
+            paths.push(path);
+        }
+        planned.push(paths);
+    }
+    Ok(planned)
+}
+
+/// Recursively list markdown files (`.md`, `.markdown`) under `dir`, sorted.
+/// Hidden files and directories are skipped and symlinks are not followed.
+pub fn collect_markdown_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            files.extend(collect_markdown_files(&path)?);
+        } else if file_type.is_file() && is_markdown_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("markdown"))
+}
+
+/// Timing of a pool run
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    pub workers: usize,
+    pub elapsed: Duration,
+    /// Time each job took, in completion order
+    pub latencies: Vec<Duration>,
+    /// Longest time a job waited in the queue before a worker picked it up
+    pub max_wait: Duration,
+}
+
+impl PoolStats {
+    /// The latency below which `p` percent of jobs finished
+    pub fn percentile(&self, p: usize) -> Duration {
+        percentile(&self.latencies, p)
+    }
+}
+
+/// The duration below which `p` percent of `latencies` fall
+pub fn percentile(latencies: &[Duration], p: usize) -> Duration {
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+    match sorted.len() {
+        0 => Duration::ZERO,
+        n => sorted[((n - 1) * p / 100).min(n - 1)],
+    }
+}
+
+/// Threads for a `--jobs` value (0 = one per CPU)
+pub fn worker_count(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} document(s) on {} worker(s) in {:.2?} (p50 {:.2?}, p95 {:.2?}, longest queue wait {:.2?})",
+            self.latencies.len(),
+            self.workers,
+            self.elapsed,
+            self.percentile(50),
+            self.percentile(95),
+            self.max_wait
+        )
+    }
+}
+
+/// Run `job` on every item with up to `workers` threads (0 = one per CPU).
+/// Workers share whatever `job` borrows, such as the syntax and theme sets
+/// and the image cache. After the first error no new jobs are started and
+/// that error is returned.
+pub fn run_pool<T, F>(items: &[T], workers: usize, job: F) -> io::Result<PoolStats>
+where
+    T: Sync,
+    F: Fn(&T) -> io::Result<()> + Sync,
+{
+    let workers = worker_count(workers).clamp(1, items.len().max(1));
+    debug!(
+        "Converting {} document(s) with {} worker(s)",
+        items.len(),
+        workers
+    );
+
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let error = Mutex::new(None);
+    let stats = Mutex::new(PoolStats {
+        workers,
+        ..PoolStats::default()
+    });
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while error.lock().unwrap().is_none()
+                    && let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed))
+                {
+                    let picked = Instant::now();
+                    let result = job(item);
+                    let mut stats = stats.lock().unwrap();
+                    stats.latencies.push(picked.elapsed());
+                    stats.max_wait = stats.max_wait.max(picked - start);
+                    drop(stats);
+                    if let Err(e) = result {
+                        error.lock().unwrap().get_or_insert(e);
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = error.into_inner().unwrap() {
+        return Err(e);
+    }
+    let mut stats = stats.into_inner().unwrap();
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+/// Keep only normal components (drops roots, `.` and `..`)
+fn relative_components(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_default_template() {
+        assert_eq!(
+            expand_name_template("{stem}.{ext}", Path::new("notes/today.md"), "html"),
+            PathBuf::from("today.html")
+        );
+    }
+
+    #[test]
+    fn test_expand_dir_template() {
+        assert_eq!(
+            expand_name_template(
+                "{dir}/{stem}.{ext}",
+                Path::new("notes/2024/today.md"),
+                "rtf"
+            ),
+            PathBuf::from("notes/2024/today.rtf")
+        );
+        // Inputs without a directory don't produce an absolute path
+        assert_eq!(
+            expand_name_template("{dir}/{stem}.{ext}", Path::new("today.md"), "rtf"),
+            PathBuf::from("today.rtf")
+        );
+    }
+
+    #[test]
+    fn test_plan_outputs() {
+        let inputs = [PathBuf::from("a/readme.md"), PathBuf::from("b/readme.md")];
+        assert_eq!(
+            plan_outputs("{dir}/{stem}.{ext}", &inputs, &["html", "rtf"]).unwrap(),
+            [
+                [
+                    PathBuf::from("a/readme.html"),
+                    PathBuf::from("a/readme.rtf")
+                ],
+                [
+                    PathBuf::from("b/readme.html"),
+                    PathBuf::from("b/readme.rtf")
+                ],
+            ]
+        );
+        assert_eq!(
+            plan_outputs("{stem}.{ext}", &inputs, &["html"]).unwrap_err(),
+            "a/readme.md and b/readme.md would both be written to readme.html"
+        );
+        // Several formats of one input need {ext} apart
+        assert!(
+            plan_outputs("{dir}/{stem}", &inputs, &["html", "rtf"])
+                .unwrap_err()
+                .starts_with("a/readme.md would be written to a/readme more than once")
+        );
+    }
+
+    #[test]
+    fn test_collect_markdown_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("guide/deep")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        for file in [
+            "index.md",
+            "notes.txt",
+            "guide/intro.markdown",
+            "guide/deep/ref.MD",
+            ".git/README.md",
+            ".hidden.md",
+        ] {
+            fs::write(root.join(file), "# x").unwrap();
+        }
+
+        let files: Vec<_> = collect_markdown_files(root)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("guide/deep/ref.MD"),
+                PathBuf::from("guide/intro.markdown"),
+                PathBuf::from("index.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_pool() {
+        let done = AtomicUsize::new(0);
+        let items: Vec<usize> = (1..=20).collect();
+        let stats = run_pool(&items, 4, |n| {
+            done.fetch_add(*n, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(done.into_inner(), 210);
+        assert_eq!(stats.workers, 4);
+        assert_eq!(stats.latencies.len(), 20);
+        assert!(stats.percentile(50) <= stats.percentile(95));
+        assert!(
+            stats
+                .to_string()
+                .starts_with("20 document(s) on 4 worker(s)")
+        );
+
+        // More workers than jobs are never started
+        assert_eq!(run_pool(&[1], 8, |_| Ok(())).unwrap().workers, 1);
+
+        let err = run_pool(&items, 2, |n| match n {
+            5 => Err(io::Error::other("bad input")),
+            _ => Ok(()),
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "bad input");
+    }
+
+    #[test]
+    fn test_expand_stays_relative() {
+        assert_eq!(
+            expand_name_template("{dir}/{name}.{ext}", Path::new("/abs/../x/a.md"), "html"),
+            PathBuf::from("abs/x/a.md.html")
+        );
+    }
+}
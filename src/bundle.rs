@@ -0,0 +1,285 @@
+//! Theme and syntax bundles shared as a `.zip` or `.tar.gz` archive, locally
+//! or at a URL, unpacked into the cache so they load like a themes or
+//! syntaxes directory.
+
+use crate::image::{content_digest, fetch_bytes};
+use flate2::read::MultiGzDecoder;
+use log::{debug, info};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use ureq::tls::TlsConfig;
+
+/// Largest bundle downloaded or unpacked
+const MAX_BUNDLE_BYTES: u64 = 64 * 1024 * 1024;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Extensions of the files kept from a bundle
+const BUNDLE_EXTENSIONS: &[&str] = &["tmTheme", "sublime-syntax"];
+
+/// Size of a tar header and the unit file data is padded to
+const TAR_BLOCK: usize = 512;
+
+/// Whether `source` names a bundle to download rather than a local archive
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
+
+/// Directory with the themes and syntaxes of the bundle at `source`, a path
+/// or URL. A URL is downloaded once, a local archive unpacked again after it
+/// changes.
+pub fn bundle_dir(source: &str) -> Result<PathBuf, String> {
+    let cache = dirs::cache_dir()
+        .ok_or("no cache directory")?
+        .join("mdcopy")
+        .join("bundles");
+    bundle_dir_in(source, &cache)
+}
+
+fn bundle_dir_in(source: &str, cache: &Path) -> Result<PathBuf, String> {
+    let key = if is_url(source) {
+        source.to_string()
+    } else {
+        let meta = fs::metadata(source).map_err(|e| format!("cannot read {}: {}", source, e))?;
+        let path = fs::canonicalize(source).unwrap_or_else(|_| PathBuf::from(source));
+        format!(
+            "{}\n{}\n{:?}",
+            path.display(),
+            meta.len(),
+            meta.modified().ok()
+        )
+    };
+    let dir = cache.join(&content_digest(key.as_bytes())[..16]);
+    if dir.is_dir() {
+        debug!("Using bundle {} unpacked at {:?}", source, dir);
+        return Ok(dir);
+    }
+
+    let data = if is_url(source) {
+        debug!("Downloading bundle {}", source);
+        fetch_bytes(
+            source,
+            &[],
+            &TlsConfig::default(),
+            Some(FETCH_TIMEOUT),
+            MAX_BUNDLE_BYTES,
+        )
+        .map_err(|e| e.to_string())?
+        .1
+    } else {
+        fs::read(source).map_err(|e| format!("cannot read {}: {}", source, e))?
+    };
+    let files = unpack(&data)?;
+    if files.is_empty() {
+        return Err("no .tmTheme or .sublime-syntax files in the archive".to_string());
+    }
+
+    // Unpack next to the final directory, then move it in place, so an
+    // interrupted run never leaves a partial bundle behind
+    fs::create_dir_all(cache).map_err(|e| e.to_string())?;
+    let temp = tempfile::Builder::new()
+        .prefix(".partial-")
+        .tempdir_in(cache)
+        .map_err(|e| e.to_string())?;
+    for (name, contents) in &files {
+        fs::write(temp.path().join(name), contents).map_err(|e| e.to_string())?;
+    }
+    fs::rename(temp.keep(), &dir).map_err(|e| e.to_string())?;
+    info!(
+        "Unpacked {} theme and syntax files from {}",
+        files.len(),
+        source
+    );
+    Ok(dir)
+}
+
+/// The theme and syntax files in a zip or gzipped tar archive, by file name
+fn unpack(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let entries = if data.starts_with(b"PK\x03\x04") {
+        unzip(data)?
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        let mut tar = Vec::new();
+        MultiGzDecoder::new(data)
+            .take(MAX_BUNDLE_BYTES)
+            .read_to_end(&mut tar)
+            .map_err(|e| format!("invalid gzip data: {}", e))?;
+        untar(&tar)?
+    } else {
+        return Err("not a .zip or .tar.gz archive".to_string());
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|(path, contents)| Some((bundle_file_name(&path)?, contents)))
+        .collect())
+}
+
+/// File name to unpack an archive entry at `path` as, when it's a theme or
+/// syntax. Folders are flattened, as themes are only read from the top level.
+fn bundle_file_name(path: &str) -> Option<String> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    let extension = Path::new(name).extension()?.to_str()?;
+    (BUNDLE_EXTENSIONS.contains(&extension) && !name.starts_with('.')).then(|| name.to_string())
+}
+
+fn unzip(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).map_err(|e| format!("invalid zip: {}", e))?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| format!("invalid zip: {}", e))?;
+        if !file.is_file() || bundle_file_name(file.name()).is_none() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)
+            .map_err(|e| format!("invalid zip: {}", e))?;
+        entries.push((file.name().to_string(), contents));
+    }
+    Ok(entries)
+}
+
+/// Regular files in a tar archive with their paths, including the long
+/// paths of GNU and pax extension headers
+fn untar(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut pos = 0;
+    while pos + TAR_BLOCK <= data.len() {
+        let header = &data[pos..pos + TAR_BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_size(&header[124..136]).ok_or("invalid tar header")?;
+        let start = pos + TAR_BLOCK;
+        let contents = data
+            .get(start..start + size)
+            .ok_or("truncated tar archive")?;
+        pos = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+        match header[156] {
+            b'L' => long_name = Some(tar_str(contents)),
+            b'x' => long_name = pax_path(contents).or(long_name),
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = tar_str(&header[..100]);
+                    let prefix = tar_str(&header[345..500]);
+                    if header[257..262] == *b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                entries.push((name, contents.to_vec()));
+            }
+            _ => long_name = None,
+        }
+    }
+    Ok(entries)
+}
+
+/// A NUL-terminated tar header field
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// An octal tar size field
+fn tar_size(field: &[u8]) -> Option<usize> {
+    let digits = tar_str(field);
+    let digits = digits.trim();
+    if digits.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(digits, 8).ok()
+}
+
+/// The `path` record of a pax extended header (`<length> path=<value>\n`)
+fn pax_path(contents: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(contents)
+        .lines()
+        .filter_map(|record| record.split_once(' ')?.1.strip_prefix("path="))
+        .next_back()
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    fn tar_entry(tar: &mut Vec<u8>, kind: u8, name: &str, contents: &[u8]) {
+        let mut header = [0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+        header[156] = kind;
+        header[257..262].copy_from_slice(b"ustar");
+        tar.extend_from_slice(&header);
+        tar.extend_from_slice(contents);
+        tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    }
+
+    #[test]
+    fn test_unpack_tar_gz() {
+        let long = format!("{}/Long.sublime-syntax", "nested".repeat(20));
+        let mut tar = Vec::new();
+        tar_entry(&mut tar, b'5', "bundle/", b"");
+        tar_entry(&mut tar, b'0', "bundle/themes/Team.tmTheme", b"<plist/>");
+        tar_entry(&mut tar, b'0', "bundle/README.md", b"# Bundle");
+        tar_entry(&mut tar, b'L', "././@LongLink", long.as_bytes());
+        tar_entry(&mut tar, b'0', "truncated-name", b"name: Long");
+        tar.extend_from_slice(&[0; 2 * TAR_BLOCK]);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar).unwrap();
+
+        assert_eq!(
+            unpack(&gz.finish().unwrap()).unwrap(),
+            vec![
+                ("Team.tmTheme".to_string(), b"<plist/>".to_vec()),
+                ("Long.sublime-syntax".to_string(), b"name: Long".to_vec()),
+            ]
+        );
+        assert!(unpack(b"plain text").is_err());
+    }
+
+    #[test]
+    fn test_bundle_dir_unpacks_zip_once() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("bundle.zip");
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [
+            ("syntaxes/Team.sublime-syntax", "name: Team"),
+            ("themes/Team.tmTheme", "<plist/>"),
+            ("LICENSE", "MIT"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        fs::write(&archive, zip.finish().unwrap().into_inner()).unwrap();
+
+        let cache = temp.path().join("cache");
+        let source = archive.to_str().unwrap();
+        let dir = bundle_dir_in(source, &cache).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Team.sublime-syntax", "Team.tmTheme"]);
+
+        // Unchanged archives reuse what was unpacked
+        fs::remove_file(dir.join("Team.tmTheme")).unwrap();
+        assert_eq!(bundle_dir_in(source, &cache).unwrap(), dir);
+        assert!(!dir.join("Team.tmTheme").exists());
+
+        assert!(bundle_dir_in("/nonexistent/bundle.zip", &cache).is_err());
+    }
+}
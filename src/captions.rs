@@ -0,0 +1,316 @@
+use log::warn;
+use markdown::mdast::{Node, Paragraph, Strong, Text};
+use std::collections::HashMap;
+
+/// Running numbers for each kind of captioned block
+#[derive(Default)]
+struct Counters {
+    figures: usize,
+    tables: usize,
+    listings: usize,
+}
+
+/// Number figures, tables and code listings and give each a caption:
+///
+/// - a paragraph holding only an image is a figure, captioned below with its
+///   alt text; `![Alt](a.png){#fig:id}` labels it
+/// - a table is captioned above from a `Table: text {#tbl:id}` (or `: text`)
+///   paragraph right after or before it
+/// - a fenced code block is captioned above, using
+///   `` ```rust {#lst:id caption="text"} `` for the label and text
+///
+/// `[@fig:id]` style references anywhere in the text then become
+/// "Figure 1" and so on; unknown labels are left as written with a warning.
+pub fn number_captions(root: &mut Node) {
+    let mut counters = Counters::default();
+    let mut labels = HashMap::new();
+    caption_blocks(root, &mut counters, &mut labels);
+    resolve_references(root, &labels);
+}
+
+fn caption_blocks(node: &mut Node, counters: &mut Counters, labels: &mut HashMap<String, String>) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    let mut blocks: Vec<Node> = Vec::with_capacity(children.len());
+    let mut rest = std::mem::take(children).into_iter().peekable();
+    while let Some(mut child) = rest.next() {
+        match &mut child {
+            Node::Paragraph(para) => {
+                let Some((alt, id)) = figure(para) else {
+                    blocks.push(child);
+                    continue;
+                };
+                counters.figures += 1;
+                let name = format!("Figure {}", counters.figures);
+                if let Some(id) = id {
+                    labels.insert(id, name.clone());
+                }
+                let inlines = if alt.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![text(alt)]
+                };
+                blocks.push(child);
+                blocks.push(caption(&name, inlines));
+            }
+            Node::Table(_) => {
+                // Pandoc places the caption after the table, but accept it before too
+                let found = match rest.peek().and_then(table_caption) {
+                    Some(found) => {
+                        rest.next();
+                        Some(found)
+                    }
+                    None => match blocks.last().and_then(table_caption) {
+                        Some(found) => {
+                            blocks.pop();
+                            Some(found)
+                        }
+                        None => None,
+                    },
+                };
+                let (inlines, id) = found.unwrap_or_default();
+                counters.tables += 1;
+                let name = format!("Table {}", counters.tables);
+                if let Some(id) = id {
+                    labels.insert(id, name.clone());
+                }
+                blocks.push(caption(&name, inlines));
+                blocks.push(child);
+            }
+            Node::Code(code) => {
+                let mut inlines = Vec::new();
+                if let Some(meta) = code.meta.take() {
+                    match split_attributes(&meta) {
+                        Some((before, attributes)) => {
+                            if let Some(id) = attributes.id {
+                                labels.insert(id, format!("Listing {}", counters.listings + 1));
+                            }
+                            if let Some(value) = attributes.caption {
+                                inlines.push(text(value));
+                            }
+                            code.meta = (!before.is_empty()).then(|| before.to_string());
+                        }
+                        None => code.meta = Some(meta),
+                    }
+                }
+                counters.listings += 1;
+                blocks.push(caption(&format!("Listing {}", counters.listings), inlines));
+                blocks.push(child);
+            }
+            _ => {
+                caption_blocks(&mut child, counters, labels);
+                blocks.push(child);
+            }
+        }
+    }
+    *children = blocks;
+}
+
+/// The alt text and label of a paragraph that holds a single image, removing
+/// the trailing `{#fig:id}` attribute block
+fn figure(para: &mut Paragraph) -> Option<(String, Option<String>)> {
+    let alt = match para.children.as_slice() {
+        [Node::Image(image)] => image.alt.clone(),
+        [Node::Image(image), Node::Text(t)] if t.value.trim().starts_with('{') => image.alt.clone(),
+        _ => return None,
+    };
+    let mut id = None;
+    if let Some(Node::Text(t)) = para.children.get(1) {
+        let (before, attributes) = split_attributes(&t.value)?;
+        if !before.is_empty() {
+            return None;
+        }
+        id = attributes.id;
+        para.children.truncate(1);
+    }
+    Some((alt, id))
+}
+
+/// The caption and label of a `Table: text {#tbl:id}` or `: text` paragraph
+fn table_caption(node: &Node) -> Option<(Vec<Node>, Option<String>)> {
+    let Node::Paragraph(para) = node else {
+        return None;
+    };
+    let Some(Node::Text(first)) = para.children.first() else {
+        return None;
+    };
+    let stripped = first
+        .value
+        .strip_prefix("Table:")
+        .or_else(|| first.value.strip_prefix(':'))?;
+
+    let mut inlines = para.children.clone();
+    inlines[0] = text(stripped.trim_start().to_string());
+    let mut id = None;
+    if let Some(Node::Text(last)) = inlines.last_mut()
+        && let Some((before, attributes)) = split_attributes(&last.value)
+    {
+        last.value = before.to_string();
+        id = attributes.id;
+    }
+    inlines.retain(|n| !matches!(n, Node::Text(t) if t.value.is_empty()));
+    Some((inlines, id))
+}
+
+/// Attributes from a `{#id key="value" .class}` block
+#[derive(Debug, Default, PartialEq)]
+struct Attributes {
+    id: Option<String>,
+    caption: Option<String>,
+}
+
+/// Split a trailing attribute block off a string, returning the trimmed text
+/// before it
+fn split_attributes(s: &str) -> Option<(&str, Attributes)> {
+    let trimmed = s.trim_end();
+    let inner = trimmed.strip_suffix('}')?;
+    let open = inner.rfind('{')?;
+    let body = &inner[open + 1..];
+
+    let mut attributes = Attributes::default();
+    let mut chars = body.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        let mut token = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next_if(|c| quoted || !c.is_whitespace()) {
+            if c == '"' {
+                quoted = !quoted;
+            } else {
+                token.push(c);
+            }
+        }
+        if first == '#' {
+            attributes.id = Some(token[1..].to_string());
+        } else if let Some(value) = token.strip_prefix("caption=") {
+            attributes.caption = Some(value.to_string());
+        }
+    }
+    Some((inner[..open].trim_end(), attributes))
+}
+
+/// Replace `[@label]` references in text with the numbered name
+fn resolve_references(node: &mut Node, labels: &HashMap<String, String>) {
+    if let Node::Text(t) = node {
+        if t.value.contains("[@") {
+            t.value = replace_references(&t.value, labels);
+        }
+        return;
+    }
+    if let Some(children) = node.children_mut() {
+        for child in children {
+            resolve_references(child, labels);
+        }
+    }
+}
+
+fn replace_references(s: &str, labels: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("[@") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find(']') else {
+            rest = &rest[start..];
+            break;
+        };
+        let label = after[..end].trim();
+        match labels.get(label) {
+            Some(name) => out.push_str(name),
+            None => {
+                warn!("Unknown cross-reference: @{}", label);
+                out.push_str(&rest[start..start + 2 + end + 1]);
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A `**Figure 1.** text` caption paragraph
+fn caption(name: &str, mut inlines: Vec<Node>) -> Node {
+    let mut children = vec![Node::Strong(Strong {
+        children: vec![text(format!("{}.", name))],
+        position: None,
+    })];
+    if !inlines.is_empty() {
+        children.push(text(" ".to_string()));
+        children.append(&mut inlines);
+    }
+    Node::Paragraph(Paragraph {
+        children,
+        position: None,
+    })
+}
+
+fn text(value: String) -> Node {
+    Node::Text(Text {
+        value,
+        position: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ImageConfig, ListStyle};
+    use crate::image::ImageCache;
+    use crate::to_markdown::mdast_to_markdown;
+    use markdown::ParseOptions;
+    use std::path::Path;
+
+    fn captioned(md: &str) -> String {
+        let mut ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        number_captions(&mut ast);
+        let image_config = ImageConfig {
+            embed_local: false,
+            embed_remote: false,
+            ..ImageConfig::default()
+        };
+        mdast_to_markdown(
+            &ast,
+            Path::new("."),
+            &image_config,
+            false,
+            &ImageCache::new(),
+            ListStyle::Decimal,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_attributes() {
+        let (before, attributes) =
+            split_attributes("rust {#lst:main .numbered caption=\"Entry point\"}").unwrap();
+        assert_eq!(before, "rust");
+        assert_eq!(attributes.id.as_deref(), Some("lst:main"));
+        assert_eq!(attributes.caption.as_deref(), Some("Entry point"));
+        assert!(split_attributes("rust").is_none());
+    }
+
+    #[test]
+    fn test_figures_and_references() {
+        let md = "See [@fig:arch].\n\n![Architecture](arch.png){#fig:arch}\n\n![](b.png)\n";
+        let out = captioned(md);
+        assert!(out.contains("See Figure 1."), "{}", out);
+        assert!(out.contains("![Architecture](arch.png)\n\n**Figure 1.** Architecture"));
+        assert!(out.contains("![](b.png)\n\n**Figure 2.**\n"));
+        assert!(!out.contains("{#fig"));
+    }
+
+    #[test]
+    fn test_tables_and_listings() {
+        let md = "| a |\n|---|\n| 1 |\n\nTable: Results {#tbl:res}\n\n\
+                  ```rust {#lst:hi caption=\"Greeting\"}\nfn main() {}\n```\n\n\
+                  [@tbl:res] and [@lst:hi], not [@fig:none].\n";
+        let out = captioned(md);
+        assert!(out.starts_with("**Table 1.** Results\n\n| a "), "{}", out);
+        assert!(out.contains("**Listing 1.** Greeting\n\n```rust\n"));
+        assert!(out.contains("Table 1 and Listing 1, not [@fig:none]."));
+    }
+}
@@ -0,0 +1,994 @@
+//! Citations. `[@key]` references are looked up in a BibTeX or CSL-JSON
+//! bibliography and written out author-date, as in "(Smith and Jones 2020,
+//! p. 4)", and the cited works are listed in a references section at the
+//! end of the document.
+
+use log::warn;
+use markdown::mdast::{Emphasis, Heading, Link, Node, Paragraph, Text};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Heading of the generated references section
+const REFERENCES_TITLE: &str = "References";
+
+/// Headings that mark where the references go when they end the document
+const REFERENCES_HEADINGS: &[&str] = &["references", "bibliography", "works cited"];
+
+/// `[@fig:id]` style labels belong to `--captions`, not the bibliography
+const CROSS_REFERENCES: &[&str] = &["fig:", "tbl:", "lst:"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Article,
+    Book,
+    /// A part of a larger work: chapters and conference papers
+    Chapter,
+    Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Name {
+    family: String,
+    /// Empty for organizations and other literal names
+    given: String,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: Kind,
+    authors: Vec<Name>,
+    year: Option<String>,
+    title: Option<String>,
+    /// Journal for articles, book or proceedings for chapters
+    container: Option<String>,
+    volume: Option<String>,
+    issue: Option<String>,
+    pages: Option<String>,
+    publisher: Option<String>,
+    doi: Option<String>,
+    url: Option<String>,
+}
+
+/// Entries by citation key
+#[derive(Debug, Default)]
+pub struct Bibliography {
+    entries: HashMap<String, Entry>,
+}
+
+impl Bibliography {
+    /// Load a CSL-JSON (`.json`) or BibTeX (anything else) file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read bibliography {}: {}", path.display(), e),
+            )
+        })?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            Self::from_csl_json(&text).map_err(|e| {
+                io::Error::other(format!("Invalid CSL-JSON in {}: {}", path.display(), e))
+            })
+        } else {
+            Ok(Self::from_bibtex(&text))
+        }
+    }
+
+    /// Add the entries of `other`, which win over entries with the same key
+    pub fn merge(&mut self, other: Self) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn from_bibtex(source: &str) -> Self {
+        let mut entries = HashMap::new();
+        let mut rest = source;
+        while let Some(at) = rest.find('@') {
+            rest = &rest[at + 1..];
+            let Some(open) = rest.find(['{', '(']) else {
+                break;
+            };
+            let kind = rest[..open].trim().to_lowercase();
+            // An `@` in a comment between entries
+            if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphabetic()) {
+                continue;
+            }
+            let Some(len) = group_len(&rest[open..]) else {
+                break;
+            };
+            let body = &rest[open + 1..open + len - 1];
+            rest = &rest[open + len..];
+            if matches!(kind.as_str(), "comment" | "preamble" | "string") {
+                continue;
+            }
+            let Some((key, fields)) = body.split_once(',') else {
+                continue;
+            };
+            let entry = Entry::from_bibtex(&kind, &bibtex_fields(fields));
+            entries.insert(key.trim().to_string(), entry);
+        }
+        Self { entries }
+    }
+
+    fn from_csl_json(text: &str) -> serde_json::Result<Self> {
+        let items: Vec<Value> = serde_json::from_str(text)?;
+        let entries = items
+            .iter()
+            .filter_map(|item| Some((csl_string(item, "id")?, Entry::from_csl(item))))
+            .collect();
+        Ok(Self { entries })
+    }
+}
+
+impl Entry {
+    fn from_bibtex(kind: &str, fields: &HashMap<String, String>) -> Self {
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .map(|value| latex_text(value))
+                .filter(|value| !value.is_empty())
+        };
+        // Identifiers are taken as written, dashes included
+        let verbatim = |name: &str| {
+            fields
+                .get(name)
+                .map(|value| value.replace(['{', '}'], "").trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let kind = match kind {
+            "article" => Kind::Article,
+            "book" | "booklet" | "manual" | "proceedings" | "phdthesis" | "mastersthesis"
+            | "thesis" | "techreport" | "report" => Kind::Book,
+            "incollection" | "inbook" | "inproceedings" | "conference" => Kind::Chapter,
+            _ => Kind::Other,
+        };
+        let container = match kind {
+            Kind::Article => field("journal").or_else(|| field("journaltitle")),
+            _ => field("booktitle"),
+        };
+        Self {
+            kind,
+            authors: fields
+                .get("author")
+                .or_else(|| fields.get("editor"))
+                .map(|names| bibtex_names(names))
+                .unwrap_or_default(),
+            year: field("year").or_else(|| field("date").map(|d| d.chars().take(4).collect())),
+            title: field("title"),
+            container,
+            volume: field("volume"),
+            issue: field("number").or_else(|| field("issue")),
+            pages: field("pages"),
+            publisher: field("publisher")
+                .or_else(|| field("institution"))
+                .or_else(|| field("school"))
+                .or_else(|| field("organization")),
+            doi: verbatim("doi"),
+            url: verbatim("url"),
+        }
+    }
+
+    fn from_csl(item: &Value) -> Self {
+        let kind = match item.get("type").and_then(Value::as_str).unwrap_or("") {
+            "article-journal" | "article-magazine" | "article-newspaper" | "article" => {
+                Kind::Article
+            }
+            "book" | "report" | "thesis" | "manuscript" => Kind::Book,
+            "chapter" | "paper-conference" | "entry-encyclopedia" | "entry-dictionary" => {
+                Kind::Chapter
+            }
+            _ => Kind::Other,
+        };
+        let names = item
+            .get("author")
+            .or_else(|| item.get("editor"))
+            .and_then(Value::as_array);
+        let authors = names
+            .into_iter()
+            .flatten()
+            .filter_map(|name| match csl_string(name, "literal") {
+                Some(literal) => Some(Name {
+                    family: literal,
+                    given: String::new(),
+                }),
+                None => Some(Name {
+                    family: csl_string(name, "family")?,
+                    given: csl_string(name, "given").unwrap_or_default(),
+                }),
+            })
+            .collect();
+        Self {
+            kind,
+            authors,
+            year: item.get("issued").and_then(csl_year),
+            title: csl_string(item, "title"),
+            container: csl_string(item, "container-title"),
+            volume: csl_string(item, "volume"),
+            issue: csl_string(item, "issue"),
+            pages: csl_string(item, "page").map(|p| p.replace("--", "–").replace('-', "–")),
+            publisher: csl_string(item, "publisher"),
+            doi: csl_string(item, "DOI"),
+            url: csl_string(item, "URL"),
+        }
+    }
+
+    /// "Smith", "Smith and Jones" or "Smith et al.", falling back to the title
+    fn short_author(&self) -> String {
+        match self.authors.as_slice() {
+            [] => self.title.clone().unwrap_or_default(),
+            [one] => one.family.clone(),
+            [first, second] => format!("{} and {}", first.family, second.family),
+            [first, ..] => format!("{} et al.", first.family),
+        }
+    }
+
+    fn year(&self) -> &str {
+        self.year.as_deref().unwrap_or("n.d.")
+    }
+
+    /// Authors in reference list form: "Smith, J., Lee, K. A., & Jones, A."
+    fn author_list(&self) -> String {
+        let names: Vec<String> = self
+            .authors
+            .iter()
+            .map(|name| match initials(&name.given) {
+                initials if initials.is_empty() => name.family.clone(),
+                initials => format!("{}, {}", name.family, initials),
+            })
+            .collect();
+        match names.as_slice() {
+            [] => String::new(),
+            [one] => one.clone(),
+            [rest @ .., last] => format!("{}, & {}", rest.join(", "), last),
+        }
+    }
+
+    fn sort_key(&self) -> (String, String, String) {
+        let first = self
+            .authors
+            .first()
+            .map(|name| name.family.clone())
+            .or_else(|| self.title.clone())
+            .unwrap_or_default();
+        (
+            first.to_lowercase(),
+            self.year().to_string(),
+            self.title.clone().unwrap_or_default().to_lowercase(),
+        )
+    }
+
+    /// The reference list paragraph, loosely APA: authors, year, title, the
+    /// work it appeared in, and a DOI or URL link
+    fn reference(&self) -> Node {
+        let mut inlines = Inlines::default();
+        let title = self.title.as_deref().unwrap_or("Untitled");
+        if self.authors.is_empty() {
+            self.push_title(title, &mut inlines);
+            inlines.text(&format!(" ({}).", self.year()));
+        } else {
+            inlines.text(&format!(
+                "{} ({}). ",
+                sentence(&self.author_list()),
+                self.year()
+            ));
+            self.push_title(title, &mut inlines);
+        }
+        match self.kind {
+            Kind::Article => {
+                if let Some(journal) = &self.container {
+                    inlines.text(" ");
+                    inlines.emphasis(journal);
+                    if let Some(volume) = &self.volume {
+                        inlines.text(&format!(", {}", volume));
+                    }
+                    if let Some(issue) = &self.issue {
+                        inlines.text(&format!("({})", issue));
+                    }
+                    if let Some(pages) = &self.pages {
+                        inlines.text(&format!(", {}", pages));
+                    }
+                    inlines.text(".");
+                }
+            }
+            Kind::Chapter => {
+                if let Some(book) = &self.container {
+                    inlines.text(" In ");
+                    inlines.emphasis(book);
+                    if let Some(pages) = &self.pages {
+                        inlines.text(&format!(" (pp. {})", pages));
+                    }
+                    inlines.text(".");
+                }
+            }
+            Kind::Book | Kind::Other => {
+                if let Some(container) =
+                    self.container.as_ref().filter(|_| self.kind == Kind::Other)
+                {
+                    inlines.text(&format!(" {}", sentence(container)));
+                }
+            }
+        }
+        if self.kind != Kind::Article
+            && let Some(publisher) = &self.publisher
+        {
+            inlines.text(&format!(" {}", sentence(publisher)));
+        }
+        let link = match (&self.doi, &self.url) {
+            (Some(doi), _) if doi.starts_with("http") => Some(doi.clone()),
+            (Some(doi), _) => Some(format!("https://doi.org/{}", doi)),
+            (None, Some(url)) => Some(url.clone()),
+            (None, None) => None,
+        };
+        if let Some(url) = link {
+            inlines.text(" ");
+            inlines.link(&url);
+        }
+        Node::Paragraph(Paragraph {
+            children: inlines.0,
+            position: None,
+        })
+    }
+
+    /// Whole works are italic; articles and chapters are plain
+    fn push_title(&self, title: &str, inlines: &mut Inlines) {
+        match self.kind {
+            Kind::Article | Kind::Chapter => inlines.text(&sentence(title)),
+            Kind::Book | Kind::Other => {
+                inlines.emphasis(title);
+                if !ends_sentence(title) {
+                    inlines.text(".");
+                }
+            }
+        }
+    }
+}
+
+/// Inline nodes with adjacent text merged
+#[derive(Default)]
+struct Inlines(Vec<Node>);
+
+impl Inlines {
+    fn text(&mut self, value: &str) {
+        if let Some(Node::Text(last)) = self.0.last_mut() {
+            last.value.push_str(value);
+        } else {
+            self.0.push(text_node(value));
+        }
+    }
+
+    fn emphasis(&mut self, value: &str) {
+        self.0.push(Node::Emphasis(Emphasis {
+            children: vec![text_node(value)],
+            position: None,
+        }));
+    }
+
+    fn link(&mut self, url: &str) {
+        self.0.push(Node::Link(Link {
+            children: vec![text_node(url)],
+            url: url.to_string(),
+            title: None,
+            position: None,
+        }));
+    }
+}
+
+/// Replace `[@key]` citations with author-date text and add a references
+/// section listing the cited works. Citations with a key missing from the
+/// bibliography are left as written, with a warning.
+pub fn cite(root: &mut Node, bibliography: &Bibliography) {
+    let mut cited = Vec::new();
+    resolve_citations(root, bibliography, &mut cited);
+    if cited.is_empty() {
+        return;
+    }
+    let mut entries: Vec<&Entry> = cited
+        .iter()
+        .filter_map(|key| bibliography.entries.get(key))
+        .collect();
+    entries.sort_by_cached_key(|entry| entry.sort_key());
+    append_references(root, &entries);
+}
+
+/// One cited work in a `[see @key, p. 4; @other]` group
+#[derive(Debug, PartialEq)]
+struct Cite<'a> {
+    prefix: &'a str,
+    key: &'a str,
+    locator: &'a str,
+    /// `-@key` leaves out the author, for "Smith argues (2020)"
+    suppress_author: bool,
+}
+
+fn resolve_citations(node: &mut Node, bibliography: &Bibliography, cited: &mut Vec<String>) {
+    if let Node::Text(t) = node {
+        if t.value.contains("@") {
+            t.value = replace_citations(&t.value, bibliography, cited);
+        }
+        return;
+    }
+    if let Some(children) = node.children_mut() {
+        for child in children {
+            resolve_citations(child, bibliography, cited);
+        }
+    }
+}
+
+fn replace_citations(s: &str, bibliography: &Bibliography, cited: &mut Vec<String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(open) = rest.find('[') {
+        let after = &rest[open + 1..];
+        let Some(close) = after.find(']') else {
+            break;
+        };
+        let inner = &after[..close];
+        // The citation, if any, is the innermost bracket
+        if inner.contains('[') {
+            out.push_str(&rest[..open + 1]);
+            rest = after;
+            continue;
+        }
+        let group = parse_citation(inner).filter(|cites| {
+            let missing: Vec<&str> = cites
+                .iter()
+                .map(|cite| cite.key)
+                .filter(|key| !bibliography.entries.contains_key(*key))
+                .collect();
+            for key in &missing {
+                if !CROSS_REFERENCES
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix))
+                {
+                    warn!("Unknown citation: @{}", key);
+                }
+            }
+            missing.is_empty()
+        });
+        match group {
+            Some(cites) => {
+                out.push_str(&rest[..open]);
+                let parts: Vec<String> = cites
+                    .iter()
+                    .map(|cite| {
+                        if !cited.iter().any(|key| key == cite.key) {
+                            cited.push(cite.key.to_string());
+                        }
+                        in_text(cite, &bibliography.entries[cite.key])
+                    })
+                    .collect();
+                out.push_str(&format!("({})", parts.join("; ")));
+            }
+            None => out.push_str(&rest[..open + 1 + close + 1]),
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The citations in a bracket, or None when it isn't one (every `;` part
+/// needs an `@key` starting a word)
+fn parse_citation(inner: &str) -> Option<Vec<Cite<'_>>> {
+    let mut cites = Vec::new();
+    for part in inner.split(';') {
+        let part = part.trim();
+        let (at, suppress_author) = part.char_indices().find_map(|(i, c)| {
+            if c != '@' {
+                return None;
+            }
+            let before = &part[..i];
+            if before.is_empty() || before.ends_with(char::is_whitespace) {
+                Some((i, false))
+            } else if let Some(outer) = before.strip_suffix('-')
+                && (outer.is_empty() || outer.ends_with(char::is_whitespace))
+            {
+                Some((i, true))
+            } else {
+                None
+            }
+        })?;
+        let prefix_end = if suppress_author { at - 1 } else { at };
+        let after = &part[at + 1..];
+        let end = after
+            .find(|c: char| !(c.is_alphanumeric() || "_:.#$%&+?<>~/-".contains(c)))
+            .unwrap_or(after.len());
+        // Trailing punctuation ends the sentence, not the key
+        let key = after[..end].trim_end_matches(['.', ':', '?', '/', '-']);
+        if key.is_empty() {
+            return None;
+        }
+        cites.push(Cite {
+            prefix: part[..prefix_end].trim(),
+            key,
+            locator: after[key.len()..].trim_start_matches(',').trim(),
+            suppress_author,
+        });
+    }
+    Some(cites)
+}
+
+/// "see Smith 2020, p. 4"
+fn in_text(cite: &Cite, entry: &Entry) -> String {
+    let mut out = String::new();
+    if !cite.prefix.is_empty() {
+        out.push_str(cite.prefix);
+        out.push(' ');
+    }
+    if !cite.suppress_author {
+        out.push_str(&entry.short_author());
+        out.push(' ');
+    }
+    out.push_str(entry.year());
+    if !cite.locator.is_empty() {
+        out.push_str(", ");
+        out.push_str(cite.locator);
+    }
+    out
+}
+
+/// Add the reference list before any trailing footnote definitions, under
+/// the document's own closing "References" heading if it has one
+fn append_references(root: &mut Node, entries: &[&Entry]) {
+    let Some(children) = root.children_mut() else {
+        return;
+    };
+    let at = children
+        .iter()
+        .rposition(|node| !matches!(node, Node::FootnoteDefinition(_)))
+        .map_or(0, |i| i + 1);
+    let has_heading = at > 0
+        && matches!(&children[at - 1], Node::Heading(_))
+        && REFERENCES_HEADINGS
+            .contains(&children[at - 1].to_string().trim().to_lowercase().as_str());
+    let mut section = Vec::with_capacity(entries.len() + 1);
+    if !has_heading {
+        section.push(Node::Heading(Heading {
+            depth: 2,
+            children: vec![text_node(REFERENCES_TITLE)],
+            position: None,
+        }));
+    }
+    section.extend(entries.iter().map(|entry| entry.reference()));
+    children.splice(at..at, section);
+}
+
+/// "J. R." for "John Ronald", "J.-P." for "Jean-Pierre"
+fn initials(given: &str) -> String {
+    given
+        .split_whitespace()
+        .map(|part| {
+            part.split('-')
+                .filter_map(|piece| piece.chars().next())
+                .map(|c| format!("{}.", c.to_uppercase()))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn ends_sentence(s: &str) -> bool {
+    s.ends_with(['.', '?', '!'])
+}
+
+/// `s` ending in a full stop
+fn sentence(s: &str) -> String {
+    if ends_sentence(s) {
+        s.to_string()
+    } else {
+        format!("{}.", s)
+    }
+}
+
+fn text_node(value: &str) -> Node {
+    Node::Text(Text {
+        value: value.to_string(),
+        position: None,
+    })
+}
+
+/// A string or number field of a CSL-JSON object
+fn csl_string(item: &Value, name: &str) -> Option<String> {
+    match item.get(name)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// The year of a CSL date: `{"date-parts": [[2020, 3]]}` or `{"raw": ...}`
+fn csl_year(date: &Value) -> Option<String> {
+    if let Some(year) = date
+        .get("date-parts")
+        .and_then(|parts| parts.get(0))
+        .and_then(|part| part.get(0))
+    {
+        return match year {
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        };
+    }
+    csl_string(date, "raw")
+        .or_else(|| csl_string(date, "literal"))
+        .map(|raw| raw.chars().take(4).collect())
+}
+
+/// Length of the `{...}` or `(...)` group `s` starts with, delimiters included
+fn group_len(s: &str) -> Option<usize> {
+    let (open, close) = if s.starts_with('(') {
+        ('(', ')')
+    } else {
+        ('{', '}')
+    };
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// `name = {value}`, `name = "value"` and `name = 2020` fields, with `#`
+/// concatenation. Values keep their braces for name splitting.
+fn bibtex_fields(body: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut rest = body;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq]
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_lowercase();
+        rest = rest[eq + 1..].trim_start();
+        let mut value = String::new();
+        loop {
+            let len = if rest.starts_with('{') {
+                let Some(len) = group_len(rest) else {
+                    return fields;
+                };
+                value.push_str(&rest[1..len - 1]);
+                len
+            } else if rest.starts_with('"') {
+                let Some(len) = quoted_len(rest) else {
+                    return fields;
+                };
+                value.push_str(&rest[1..len - 1]);
+                len
+            } else {
+                let len = rest.find([',', '#']).unwrap_or(rest.len());
+                value.push_str(rest[..len].trim());
+                len
+            };
+            rest = rest[len..].trim_start();
+            match rest.strip_prefix('#') {
+                Some(next) => rest = next.trim_start(),
+                None => break,
+            }
+        }
+        fields.insert(name, value);
+    }
+    fields
+}
+
+/// Length of the `"..."` value `s` starts with; quotes inside braces don't
+/// end it
+fn quoted_len(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' if depth == 0 => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split an `author` field on its top-level `and`s; `{Acme and Sons}` is
+/// one name
+fn bibtex_names(field: &str) -> Vec<Name> {
+    let mut names = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let lower = field.to_lowercase();
+    for (i, c) in field.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if depth == 0
+                && c.is_whitespace()
+                && lower[i..].starts_with(|c: char| c.is_whitespace()) =>
+            {
+                let word = lower[i..].trim_start();
+                if let Some(after) = word.strip_prefix("and")
+                    && after.starts_with(char::is_whitespace)
+                    && i >= start
+                {
+                    names.push(&field[start..i]);
+                    start = field.len() - after.len();
+                }
+            }
+            _ => {}
+        }
+    }
+    names.push(&field[start..]);
+    names
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(bibtex_name)
+        .collect()
+}
+
+/// "Last, First", "First Last" or a braced `{Literal Name}`
+fn bibtex_name(name: &str) -> Name {
+    if name.starts_with('{') && group_len(name) == Some(name.len()) {
+        return Name {
+            family: latex_text(name),
+            given: String::new(),
+        };
+    }
+    let name = latex_text(name);
+    if let Some((family, given)) = name.split_once(',') {
+        // "Last, Jr, First" keeps the first name last
+        let given = given.rsplit(',').next().unwrap_or(given);
+        return Name {
+            family: family.trim().to_string(),
+            given: given.trim().to_string(),
+        };
+    }
+    match name.rsplit_once(' ') {
+        Some((given, family)) => Name {
+            family: family.to_string(),
+            given: given.to_string(),
+        },
+        None => Name {
+            family: name,
+            given: String::new(),
+        },
+    }
+}
+
+/// Plain text for a BibTeX value: accents become combining marks, escaped
+/// characters are unescaped, other commands and grouping braces are dropped
+/// and `--`/`---` become dashes
+fn latex_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '~' => out.push('\u{a0}'),
+            '\\' => match chars.next() {
+                Some(accent @ ('\'' | '`' | '^' | '"' | '~' | '=' | '.')) => {
+                    accented(&mut chars, accent_mark(accent), &mut out);
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut command = String::from(c);
+                    while let Some(&next) = chars.peek()
+                        && next.is_ascii_alphabetic()
+                    {
+                        command.push(next);
+                        chars.next();
+                    }
+                    match command.as_str() {
+                        "c" => accented(&mut chars, '\u{327}', &mut out),
+                        "v" => accented(&mut chars, '\u{30c}', &mut out),
+                        "u" => accented(&mut chars, '\u{306}', &mut out),
+                        "H" => accented(&mut chars, '\u{30b}', &mut out),
+                        symbol => {
+                            let replacement = match symbol {
+                                "ss" => "ß",
+                                "o" => "ø",
+                                "O" => "Ø",
+                                "ae" => "æ",
+                                "AE" => "Æ",
+                                "oe" => "œ",
+                                "OE" => "Œ",
+                                "aa" => "å",
+                                "AA" => "Å",
+                                "l" => "ł",
+                                "L" => "Ł",
+                                "i" => "ı",
+                                "textendash" => "–",
+                                "textemdash" => "—",
+                                // \emph{x} and the like keep their argument
+                                _ => "",
+                            };
+                            if !replacement.is_empty() && chars.peek() == Some(&' ') {
+                                chars.next();
+                            }
+                            out.push_str(replacement);
+                        }
+                    }
+                }
+                Some(escaped) => out.push(escaped),
+                None => {}
+            },
+            _ => out.push(c),
+        }
+    }
+    out.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("---", "—")
+        .replace("--", "–")
+}
+
+/// The base letter after an accent command (`\'e`, `\'{e}`, `\c c`) followed
+/// by the combining mark
+fn accented(chars: &mut std::iter::Peekable<std::str::Chars>, mark: char, out: &mut String) {
+    while chars.next_if(|c| *c == '{' || *c == ' ').is_some() {}
+    if let Some(base) = chars.next() {
+        out.push(base);
+        out.push(mark);
+    }
+}
+
+fn accent_mark(accent: char) -> char {
+    match accent {
+        '\'' => '\u{301}',
+        '`' => '\u{300}',
+        '^' => '\u{302}',
+        '"' => '\u{308}',
+        '~' => '\u{303}',
+        '=' => '\u{304}',
+        _ => '\u{307}',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ImageConfig, ListStyle};
+    use crate::image::ImageCache;
+    use crate::to_markdown::mdast_to_markdown;
+    use markdown::ParseOptions;
+    use std::path::Path;
+
+    const BIBTEX: &str = r#"
+% Contact: someone@example.com
+@article{smith2020,
+  author = {Smith, John and Lee, Kim A. and {Acme and Sons}},
+  title = {Why {Rust}?},
+  journal = "Journal of " # "Systems",
+  year = 2020,
+  volume = {12}, number = {3}, pages = {45--67},
+  doi = {10.1000/xyz-1}
+}
+@book{mueller2019,
+  author = {Hans M{\"u}ller},
+  title = {Typesetting},
+  publisher = {Acme Press},
+  year = {2019}
+}
+"#;
+
+    fn cited(md: &str, bibliography: &Bibliography) -> String {
+        let mut ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        cite(&mut ast, bibliography);
+
+        mdast_to_markdown(
+            &ast,
+            Path::new("."),
+            &ImageConfig::default(),
+            false,
+            &ImageCache::new(),
+            ListStyle::Decimal,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_bibtex() {
+        let bibliography = Bibliography::from_bibtex(BIBTEX);
+        assert_eq!(bibliography.len(), 2);
+        let smith = &bibliography.entries["smith2020"];
+        assert_eq!(smith.kind, Kind::Article);
+        assert_eq!(smith.title.as_deref(), Some("Why Rust?"));
+        assert_eq!(smith.container.as_deref(), Some("Journal of Systems"));
+        assert_eq!(smith.pages.as_deref(), Some("45–67"));
+        assert_eq!(smith.doi.as_deref(), Some("10.1000/xyz-1"));
+        assert_eq!(smith.authors.len(), 3);
+        assert_eq!(smith.authors[2].family, "Acme and Sons");
+        assert_eq!(
+            smith.author_list(),
+            "Smith, J., Lee, K. A., & Acme and Sons"
+        );
+        let mueller = &bibliography.entries["mueller2019"];
+        assert_eq!(mueller.authors[0].family, "Mu\u{308}ller");
+        assert_eq!(mueller.authors[0].given, "Hans");
+    }
+
+    #[test]
+    fn test_parse_csl_json() {
+        let bibliography = Bibliography::from_csl_json(
+            r#"[{"id": "doe", "type": "chapter", "title": "Parsing",
+                 "author": [{"family": "Doe", "given": "Jane"}, {"literal": "W3C"}],
+                 "issued": {"date-parts": [[2018, 5]]}, "container-title": "Compilers",
+                 "page": "1-9"}]"#,
+        )
+        .unwrap();
+        let doe = &bibliography.entries["doe"];
+        assert_eq!(doe.kind, Kind::Chapter);
+        assert_eq!(doe.short_author(), "Doe and W3C");
+        assert_eq!(doe.year.as_deref(), Some("2018"));
+        assert_eq!(doe.pages.as_deref(), Some("1–9"));
+        assert!(Bibliography::from_csl_json("{").is_err());
+    }
+
+    #[test]
+    fn test_parse_citation() {
+        assert_eq!(
+            parse_citation("see @smith2020, p. 4; -@doe"),
+            Some(vec![
+                Cite {
+                    prefix: "see",
+                    key: "smith2020",
+                    locator: "p. 4",
+                    suppress_author: false,
+                },
+                Cite {
+                    prefix: "",
+                    key: "doe",
+                    locator: "",
+                    suppress_author: true,
+                },
+            ])
+        );
+        assert_eq!(parse_citation("mail me@example.com"), None);
+        assert_eq!(parse_citation("@smith2020.").unwrap()[0].key, "smith2020");
+    }
+
+    #[test]
+    fn test_cite() {
+        let bibliography = Bibliography::from_bibtex(BIBTEX);
+        let out = cited(
+            "Fast [@smith2020, p. 4; @mueller2019], as [-@mueller2019] shows [@fig:x] [@nope].\n",
+            &bibliography,
+        );
+        assert!(
+            out.starts_with(
+                "Fast (Smith et al. 2020, p. 4; Müller 2019), as (2019) shows [@fig:x] [@nope].\n"
+                    .replace("ü", "u\u{308}")
+                    .as_str()
+            )
+        );
+        assert!(out.contains("## References"));
+        // Sorted by first author
+        let mueller = out
+            .find("Mu\u{308}ller, H. (2019). *Typesetting*. Acme Press.")
+            .unwrap();
+        let smith = out
+            .find(
+                "Smith, J., Lee, K. A., & Acme and Sons. (2020). Why Rust? *Journal of Systems*, \
+                 12(3), 45–67. [https://doi.org/10.1000/xyz-1](https://doi.org/10.1000/xyz-1)",
+            )
+            .unwrap();
+        assert!(mueller < smith, "{}", out);
+
+        // An existing closing heading is reused, and nothing is added
+        // without citations
+        let out = cited("See [@smith2020].\n\n# Bibliography\n", &bibliography);
+        assert!(out.contains("# Bibliography\n\nSmith, J."));
+        assert!(!out.contains("References"));
+        assert_eq!(cited("No citations.\n", &bibliography), "No citations.\n");
+    }
+}
@@ -0,0 +1,1070 @@
+//! Clipboard backends behind one trait: the platform clipboard (clipboard-rs,
+//! optionally held by a background process on Linux), NSPasteboard for native
+//! macOS output, `wl-copy` for Wayland sessions, OSC 52 escape sequences for
+//! SSH sessions, and a file dump for headless runs and debugging. A reader
+//! lists and reads back what the clipboard holds, for `mdcopy inspect`.
+
+use crate::config::{ClipboardBackendKind, ClipboardConfig};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clipboard_rs::common::{RustImage, RustImageData};
+use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Many terminals drop OSC 52 sequences beyond roughly this size
+const OSC52_LIMIT_BYTES: usize = 100_000;
+
+/// Time a background holder gets to take the selection before a read-back
+/// counts as failed
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One kind of content a copy puts on the clipboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flavor {
+    Text,
+    Html,
+    Rtf,
+    ProseMirror,
+    Image,
+}
+
+impl Flavor {
+    pub const ALL: [Flavor; 5] = [
+        Flavor::Text,
+        Flavor::Html,
+        Flavor::Rtf,
+        Flavor::ProseMirror,
+        Flavor::Image,
+    ];
+
+    /// File extension of the flavor's data
+    pub fn extension(self) -> &'static str {
+        match self {
+            Flavor::Text => "txt",
+            Flavor::Html => "html",
+            Flavor::Rtf => "rtf",
+            Flavor::ProseMirror => "json",
+            Flavor::Image => "png",
+        }
+    }
+}
+
+impl fmt::Display for Flavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Flavor::Text => write!(f, "plain text"),
+            Flavor::Html => write!(f, "HTML"),
+            Flavor::Rtf => write!(f, "RTF"),
+            Flavor::ProseMirror => write!(f, "ProseMirror"),
+            Flavor::Image => write!(f, "image"),
+        }
+    }
+}
+
+/// Everything a backend may put on the clipboard
+pub struct Payload<'a> {
+    /// Original markdown source, the plain text fallback
+    pub source: &'a str,
+    /// Text output (`-f markdown`, `-f text`, `-f jira`, `-f slack`,
+    /// `-f mediawiki`, `-f notion`), which replaces the source as plain text
+    pub plain_text: Option<&'a str>,
+    pub html: Option<&'a str>,
+    pub rtf: Option<&'a str>,
+    /// Atlassian editor slice (`-f prosemirror`)
+    pub prosemirror: Option<&'a str>,
+    /// PNG screenshot of the document (`-f image`), which goes on the
+    /// clipboard by itself so apps paste the picture rather than the text
+    pub image: Option<&'a [u8]>,
+    #[cfg(target_os = "macos")]
+    pub native: Option<&'a crate::to_nsattributedstring::NativeConversionResult>,
+}
+
+impl Payload<'_> {
+    fn text(&self) -> &str {
+        self.plain_text.unwrap_or(self.source)
+    }
+
+    /// Whether native macOS output was rendered
+    fn has_native(&self) -> bool {
+        #[cfg(target_os = "macos")]
+        return self.native.is_some();
+        #[cfg(not(target_os = "macos"))]
+        false
+    }
+
+    /// Each flavor in the payload with its data
+    pub fn flavors(&self) -> Vec<(Flavor, &[u8])> {
+        [
+            (Flavor::Text, Some(self.text().as_bytes())),
+            (Flavor::Html, self.html.map(str::as_bytes)),
+            (Flavor::Rtf, self.rtf.map(str::as_bytes)),
+            (Flavor::ProseMirror, self.prosemirror.map(str::as_bytes)),
+            (Flavor::Image, self.image),
+        ]
+        .into_iter()
+        .filter_map(|(flavor, data)| Some((flavor, data?)))
+        .collect()
+    }
+
+    /// The payload as clipboard-rs contents
+    fn contents(&self) -> Vec<ClipboardContent> {
+        contents(&self.flavors())
+    }
+
+    /// Hash of everything this payload puts on the clipboard, None for
+    /// native output, which can't be compared
+    fn digest(&self) -> Option<String> {
+        if self.has_native() {
+            return None;
+        }
+        let mut hasher = Sha256::new();
+        let parts = [
+            Some(self.text().as_bytes()),
+            self.html.map(str::as_bytes),
+            self.rtf.map(str::as_bytes),
+            self.prosemirror.map(str::as_bytes),
+            self.image,
+        ];
+        for part in parts {
+            match part {
+                Some(part) => {
+                    hasher.update((part.len() as u64).to_le_bytes());
+                    hasher.update(part);
+                }
+                None => hasher.update(u64::MAX.to_le_bytes()),
+            }
+        }
+        Some(
+            hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        )
+    }
+
+    /// Whether `single_offer` is all a multi-flavor clipboard would get:
+    /// an image, which goes on the clipboard by itself, or plain text alone
+    fn single_offer_is_complete(&self) -> bool {
+        self.image.is_some() || self.flavors().len() == 1
+    }
+
+    /// The richest content for backends that offer a single type per copy
+    /// (`wl-copy` and OSC 52): an image wins over HTML, which wins over RTF,
+    /// which wins over plain text.
+    pub fn single_offer(&self) -> (&'static str, &[u8]) {
+        if let Some(png) = self.image {
+            ("image/png", png)
+        } else if let Some(html) = self.html {
+            ("text/html", html.as_bytes())
+        } else if let Some(rtf) = self.rtf {
+            ("text/rtf", rtf.as_bytes())
+        } else {
+            ("text/plain;charset=utf-8", self.text().as_bytes())
+        }
+    }
+}
+
+/// Flavors as clipboard-rs contents. An image goes on the clipboard by
+/// itself, unless it can't be read.
+fn contents(flavors: &[(Flavor, &[u8])]) -> Vec<ClipboardContent> {
+    if let Some((_, png)) = flavors.iter().find(|(flavor, _)| *flavor == Flavor::Image) {
+        match RustImageData::from_bytes(png) {
+            Ok(image) => return vec![ClipboardContent::Image(image)],
+            Err(e) => warn!("Failed to read the rendered image: {}", e),
+        }
+    }
+    flavors
+        .iter()
+        .filter_map(|&(flavor, data)| {
+            let text = || String::from_utf8_lossy(data).into_owned();
+            match flavor {
+                Flavor::Text => Some(ClipboardContent::Text(text())),
+                Flavor::Html => Some(ClipboardContent::Html(text())),
+                Flavor::Rtf => Some(ClipboardContent::Rtf(text())),
+                Flavor::ProseMirror => Some(ClipboardContent::Other(
+                    crate::to_prosemirror::MIME_TYPE.to_string(),
+                    data.to_vec(),
+                )),
+                Flavor::Image => None,
+            }
+        })
+        .collect()
+}
+
+/// A way of putting rendered output on the clipboard
+pub trait ClipboardBackend {
+    /// Name used in messages, matching the config value
+    fn name(&self) -> &'static str;
+
+    /// Whether auto-detection should pick this backend for `payload` in the
+    /// current session
+    fn is_available(&self, payload: &Payload) -> bool;
+
+    fn copy(&self, payload: &Payload) -> io::Result<()>;
+
+    /// Why `payload` may be too large to arrive through this backend; None
+    /// when its size is fine
+    fn size_problem(&self, _payload: &Payload) -> Option<String> {
+        None
+    }
+
+    /// Whether the clipboard currently shows `payload`'s content; false
+    /// when the backend can't read the clipboard back
+    fn holds(&self, _payload: &Payload) -> bool {
+        false
+    }
+
+    /// The flavors a copy of `payload` puts on the clipboard
+    fn offered<'a>(&self, payload: &'a Payload) -> Vec<(Flavor, &'a [u8])> {
+        payload.flavors()
+    }
+
+    /// One flavor as the clipboard holds it now; None when the backend
+    /// can't read the clipboard
+    fn read_back(&self, _flavor: Flavor) -> Option<io::Result<Vec<u8>>> {
+        None
+    }
+}
+
+/// The last copy, kept in a small state file so a repeated copy of the same
+/// output (an editor's on-save hook, say) can be skipped instead of adding
+/// another clipboard-manager entry and waking paste listeners
+pub struct CopyHistory {
+    path: PathBuf,
+}
+
+impl CopyHistory {
+    /// The history in the user cache directory
+    pub fn open() -> Option<Self> {
+        dirs::cache_dir().map(|dir| Self::at(dir.join("mdcopy").join("last-copy")))
+    }
+
+    fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn entry(backend: &dyn ClipboardBackend, digest: &str) -> String {
+        format!("{} {}\n", backend.name(), digest)
+    }
+
+    fn matches(&self, backend: &dyn ClipboardBackend, digest: &str) -> bool {
+        fs::read_to_string(&self.path).is_ok_and(|last| last == Self::entry(backend, digest))
+    }
+
+    fn record(&self, backend: &dyn ClipboardBackend, digest: &str) {
+        let written = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, Self::entry(backend, digest)));
+        if let Err(e) = written {
+            debug!("Failed to record the copy in {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Copy `payload` unless `history` shows the same output was the last copy
+/// through this backend and the clipboard still holds it. Returns whether
+/// the clipboard was written.
+pub fn copy_if_changed(
+    backend: &dyn ClipboardBackend,
+    payload: &Payload,
+    history: Option<&CopyHistory>,
+) -> io::Result<bool> {
+    let digest = payload.digest();
+    if let (Some(history), Some(digest)) = (history, &digest)
+        && history.matches(backend, digest)
+        && backend.holds(payload)
+    {
+        return Ok(false);
+    }
+    backend.copy(payload)?;
+    if let (Some(history), Some(digest)) = (history, &digest) {
+        history.record(backend, digest);
+    }
+    Ok(true)
+}
+
+/// Pick the configured backend, or for `auto` the first available one in
+/// the configured order (the system clipboard when none is)
+pub fn select(config: &ClipboardConfig, payload: &Payload) -> Box<dyn ClipboardBackend> {
+    if config.backend != ClipboardBackendKind::Auto {
+        return backend(config.backend, config);
+    }
+    config
+        .order
+        .iter()
+        .map(|&kind| backend(kind, config))
+        .find(|b| {
+            let available = b.is_available(payload);
+            debug!("Clipboard backend {} available: {}", b.name(), available);
+            available
+        })
+        .unwrap_or_else(|| backend(ClipboardBackendKind::System, config))
+}
+
+fn backend(kind: ClipboardBackendKind, config: &ClipboardConfig) -> Box<dyn ClipboardBackend> {
+    match kind {
+        ClipboardBackendKind::Auto | ClipboardBackendKind::System => {
+            Box::new(System { hold: config.hold })
+        }
+        ClipboardBackendKind::Native => Box::new(Native),
+        ClipboardBackendKind::Wayland => Box::new(WlCopy),
+        ClipboardBackendKind::Osc52 => Box::new(Osc52),
+        ClipboardBackendKind::File => Box::new(FileDump {
+            dir: config.dump_dir.clone(),
+        }),
+    }
+}
+
+/// The platform clipboard through clipboard-rs
+struct System {
+    /// Linux: hand the clipboard to a background holder process
+    hold: bool,
+}
+
+impl ClipboardBackend for System {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    fn is_available(&self, _payload: &Payload) -> bool {
+        true
+    }
+
+    fn copy(&self, payload: &Payload) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if self.hold {
+            return set_and_hold(&payload.flavors());
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = self.hold;
+
+        let ctx = ClipboardContext::new()
+            .map_err(|e| io::Error::other(format!("Failed to create clipboard context: {}", e)))?;
+        ctx.set(payload.contents())
+            .map_err(|e| io::Error::other(format!("Failed to set clipboard content: {}", e)))
+    }
+
+    fn holds(&self, payload: &Payload) -> bool {
+        ClipboardContext::new()
+            .and_then(|ctx| ctx.get_text())
+            .is_ok_and(|text| text == payload.text())
+    }
+
+    /// An image goes on the clipboard by itself
+    fn offered<'a>(&self, payload: &'a Payload) -> Vec<(Flavor, &'a [u8])> {
+        let flavors = payload.flavors();
+        match flavors.iter().find(|(flavor, _)| *flavor == Flavor::Image) {
+            Some(&image) => vec![image],
+            None => flavors,
+        }
+    }
+
+    fn read_back(&self, flavor: Flavor) -> Option<io::Result<Vec<u8>>> {
+        let read = ClipboardContext::new().and_then(|ctx| match flavor {
+            Flavor::Text => ctx.get_text().map(String::into_bytes),
+            Flavor::Html => ctx.get_html().map(String::into_bytes),
+            Flavor::Rtf => ctx.get_rich_text().map(String::into_bytes),
+            Flavor::ProseMirror => ctx.get_buffer(crate::to_prosemirror::MIME_TYPE),
+            Flavor::Image => ctx
+                .get_image()
+                .and_then(|image| image.to_png())
+                .map(|png| png.get_bytes().to_vec()),
+        });
+        Some(read.map_err(|e| io::Error::other(e.to_string())))
+    }
+}
+
+/// NSPasteboard with the native attributed string (macOS, `-f native`)
+struct Native;
+
+impl ClipboardBackend for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn is_available(&self, payload: &Payload) -> bool {
+        payload.has_native()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn copy(&self, payload: &Payload) -> io::Result<()> {
+        let Some(native) = payload.native else {
+            return Err(io::Error::other(
+                "The native clipboard backend needs native output (-f native)",
+            ));
+        };
+        // Our HTML replaces the generated one with -f native,html; text
+        // output replaces the generated plain text with -f native,markdown
+        crate::to_nsattributedstring::write_to_pasteboard(
+            native,
+            payload.html.is_some(),
+            payload.html,
+            payload.plain_text,
+        )
+        .map_err(io::Error::other)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn copy(&self, _payload: &Payload) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The native clipboard backend is only available on macOS",
+        ))
+    }
+}
+
+/// `wl-copy` from wl-clipboard, which forks and keeps serving the selection
+/// in the background, so the content survives mdcopy exiting
+struct WlCopy;
+
+impl ClipboardBackend for WlCopy {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    /// Only for copies `single_offer` covers whole, so auto doesn't trade
+    /// the plain text and RTF flavors for HTML alone
+    fn is_available(&self, payload: &Payload) -> bool {
+        cfg!(target_os = "linux")
+            && payload.single_offer_is_complete()
+            && env::var_os("WAYLAND_DISPLAY").is_some_and(|v| !v.is_empty())
+            && find_in_path("wl-copy").is_some()
+    }
+
+    fn copy(&self, payload: &Payload) -> io::Result<()> {
+        let (mime_type, data) = payload.single_offer();
+        debug!("Copying {} bytes as {} with wl-copy", data.len(), mime_type);
+
+        let mut child = Command::new("wl-copy")
+            .arg("--type")
+            .arg(mime_type)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to run wl-copy: {} (is wl-clipboard installed?)", e),
+                )
+            })?;
+        child
+            .stdin
+            .take()
+            .expect("wl-copy stdin is piped")
+            .write_all(data)?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("wl-copy exited with {}", status)));
+        }
+        Ok(())
+    }
+
+    fn holds(&self, payload: &Payload) -> bool {
+        let (mime_type, data) = payload.single_offer();
+        Command::new("wl-paste")
+            .args(["--no-newline", "--type", mime_type])
+            .stderr(Stdio::null())
+            .output()
+            .is_ok_and(|output| output.status.success() && output.stdout == data)
+    }
+
+    fn offered<'a>(&self, payload: &'a Payload) -> Vec<(Flavor, &'a [u8])> {
+        let (mime_type, data) = payload.single_offer();
+        let flavor = match mime_type {
+            "image/png" => Flavor::Image,
+            "text/html" => Flavor::Html,
+            "text/rtf" => Flavor::Rtf,
+            _ => Flavor::Text,
+        };
+        vec![(flavor, data)]
+    }
+
+    fn read_back(&self, flavor: Flavor) -> Option<io::Result<Vec<u8>>> {
+        let mime_type = match flavor {
+            Flavor::Image => "image/png",
+            Flavor::Html => "text/html",
+            Flavor::Rtf => "text/rtf",
+            Flavor::ProseMirror => crate::to_prosemirror::MIME_TYPE,
+            Flavor::Text => "text/plain;charset=utf-8",
+        };
+        Some(wl_paste(&["--no-newline", "--type", mime_type]))
+    }
+}
+
+/// An OSC 52 sequence written to the terminal, which sets the clipboard of
+/// the machine the terminal runs on. The terminal decides what it accepts;
+/// there's no confirmation either way.
+struct Osc52;
+
+impl ClipboardBackend for Osc52 {
+    fn name(&self) -> &'static str {
+        "osc52"
+    }
+
+    /// Inside SSH sessions, where there's no local clipboard to reach
+    fn is_available(&self, _payload: &Payload) -> bool {
+        env::var_os("SSH_TTY").is_some_and(|v| !v.is_empty())
+    }
+
+    fn size_problem(&self, payload: &Payload) -> Option<String> {
+        let (_, data) = payload.single_offer();
+        let size = osc52_sequence(data, env::var_os("TMUX").is_some()).len();
+        (size > OSC52_LIMIT_BYTES).then(|| {
+            format!(
+                "OSC 52 payload is {} bytes; some terminals ignore sequences this large",
+                size
+            )
+        })
+    }
+
+    fn copy(&self, payload: &Payload) -> io::Result<()> {
+        let (mime_type, data) = payload.single_offer();
+        let sequence = osc52_sequence(data, env::var_os("TMUX").is_some());
+        debug!("Copying {} bytes of {} with OSC 52", data.len(), mime_type);
+
+        // The terminal itself, so redirected output doesn't swallow the sequence
+        match OpenOptions::new().write(true).open("/dev/tty") {
+            Ok(mut tty) => tty.write_all(sequence.as_bytes()),
+            Err(_) => io::stderr().write_all(sequence.as_bytes()),
+        }
+    }
+}
+
+/// `ESC ] 52 ; c ; <base64> BEL`, wrapped in a passthrough sequence inside
+/// tmux so it reaches the outer terminal
+fn osc52_sequence(data: &[u8], tmux: bool) -> String {
+    let osc = format!("\x1b]52;c;{}\x07", STANDARD.encode(data));
+    if tmux {
+        format!("\x1bPtmux;\x1b{}\x1b\\", osc)
+    } else {
+        osc
+    }
+}
+
+/// Each clipboard format written to a file in a directory
+/// (`clipboard.txt`, `clipboard.html`, `clipboard.rtf`, `clipboard.json`,
+/// `clipboard.png`);
+/// formats missing from this copy are removed so the directory mirrors one
+/// clipboard state
+struct FileDump {
+    dir: PathBuf,
+}
+
+impl ClipboardBackend for FileDump {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn is_available(&self, _payload: &Payload) -> bool {
+        true
+    }
+
+    fn copy(&self, payload: &Payload) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let flavors = payload.flavors();
+        for flavor in Flavor::ALL {
+            let path = self.path(flavor);
+            match flavors.iter().find(|(f, _)| *f == flavor) {
+                Some((_, content)) => fs::write(&path, content)?,
+                None if path.exists() => fs::remove_file(&path)?,
+                None => {}
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        if let Some(native) = payload.native {
+            crate::to_nsattributedstring::write_rtfd(native, &self.dir.join("clipboard.rtfd"))
+                .map_err(io::Error::other)?;
+        }
+
+        debug!("Dumped clipboard to {:?}", self.dir);
+        Ok(())
+    }
+
+    fn holds(&self, payload: &Payload) -> bool {
+        fs::read_to_string(self.path(Flavor::Text)).is_ok_and(|text| text == payload.text())
+    }
+
+    fn read_back(&self, flavor: Flavor) -> Option<io::Result<Vec<u8>>> {
+        Some(fs::read(self.path(flavor)))
+    }
+}
+
+impl FileDump {
+    fn path(&self, flavor: Flavor) -> PathBuf {
+        self.dir.join(format!("clipboard.{}", flavor.extension()))
+    }
+}
+
+/// Set the clipboard from a background copy of mdcopy that owns the
+/// selection until another application takes it over. The holder is a new
+/// process rather than a fork, since image optimization leaves worker
+/// threads running whose locks a forked child could inherit mid-use.
+#[cfg(target_os = "linux")]
+fn set_and_hold(flavors: &[(Flavor, &[u8])]) -> io::Result<()> {
+    let mut child = Command::new(env::current_exe()?)
+        .arg("--clipboard-holder")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("holder stdin is piped")
+        .write_all(&encode_flavors(flavors))?;
+    debug!("Clipboard holder running as pid {}", child.id());
+    Ok(())
+}
+
+/// Entry point of the holder process: read the flavors from stdin, leave
+/// the terminal's session and serve them. Returns once another application
+/// owns the clipboard, or on failure.
+#[cfg(target_os = "linux")]
+pub fn serve_held() -> io::Result<()> {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let flavors = decode_flavors(&data)?;
+    unsafe { libc::setsid() };
+    detach_stdio();
+    let flavors: Vec<(Flavor, &[u8])> = flavors
+        .iter()
+        .map(|(flavor, data)| (*flavor, data.as_slice()))
+        .collect();
+    hold(contents(&flavors)).map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn serve_held() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The clipboard holder is only used on Linux",
+    ))
+}
+
+/// Flavors framed for the holder's stdin: the flavor's extension and the
+/// data's length on one line, then the data
+#[cfg(target_os = "linux")]
+fn encode_flavors(flavors: &[(Flavor, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (flavor, data) in flavors {
+        out.extend_from_slice(format!("{} {}\n", flavor.extension(), data.len()).as_bytes());
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+fn decode_flavors(mut data: &[u8]) -> io::Result<Vec<(Flavor, Vec<u8>)>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Malformed clipboard holder input",
+        )
+    };
+    let mut flavors = Vec::new();
+    while !data.is_empty() {
+        let end = data.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+        let header = std::str::from_utf8(&data[..end]).map_err(|_| invalid())?;
+        let (extension, len) = header.split_once(' ').ok_or_else(invalid)?;
+        let flavor = Flavor::ALL
+            .into_iter()
+            .find(|flavor| flavor.extension() == extension)
+            .ok_or_else(invalid)?;
+        let len: usize = len.parse().map_err(|_| invalid())?;
+        let body = data.get(end + 1..end + 1 + len).ok_or_else(invalid)?;
+        flavors.push((flavor, body.to_vec()));
+        data = &data[end + 1 + len..];
+    }
+    Ok(flavors)
+}
+
+#[cfg(target_os = "linux")]
+fn hold(contents: Vec<ClipboardContent>) -> clipboard_rs::Result<()> {
+    use clipboard_rs::{ClipboardHandler, ClipboardWatcher, ClipboardWatcherContext};
+
+    struct ExitOnChange;
+
+    impl ClipboardHandler for ExitOnChange {
+        fn on_clipboard_change(&mut self) {
+            // Another client owns the clipboard now; nothing left to serve
+            std::process::exit(0);
+        }
+    }
+
+    let ctx = ClipboardContext::new()?;
+    ctx.set(contents)?;
+    let mut watcher = ClipboardWatcherContext::new()?;
+    watcher.add_handler(ExitOnChange).start_watch();
+    Ok(())
+}
+
+/// Point stdin/stdout/stderr at /dev/null so the holder doesn't keep a
+/// terminal or a pipe (e.g. `$(mdcopy ...)`) open
+#[cfg(target_os = "linux")]
+fn detach_stdio() {
+    use std::os::fd::AsRawFd;
+
+    if let Ok(null) = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+    {
+        for fd in 0..=2 {
+            unsafe { libc::dup2(null.as_raw_fd(), fd) };
+        }
+    }
+}
+
+/// Read the clipboard back after a copy of `payload` and fail with the
+/// flavors that are missing or changed, as when a clipboard manager takes
+/// the selection over and keeps only the text. Images are only checked for
+/// presence, since the clipboard may re-encode them. Backends that can't
+/// read the clipboard (`osc52`, `native`) pass with a warning.
+pub fn verify(backend: &dyn ClipboardBackend, payload: &Payload) -> io::Result<()> {
+    let started = Instant::now();
+    loop {
+        let mut problems = Vec::new();
+        for (flavor, expected) in backend.offered(payload) {
+            let Some(read) = backend.read_back(flavor) else {
+                warn!(
+                    "The {} clipboard backend can't read the clipboard back; not verified",
+                    backend.name()
+                );
+                return Ok(());
+            };
+            match read {
+                Ok(data) if data.is_empty() && !expected.is_empty() => {
+                    problems.push(format!("{} is missing", flavor))
+                }
+                Ok(data) if flavor == Flavor::Image || data == expected => {}
+                Ok(data) => problems.push(format!(
+                    "{} differs ({} bytes, expected {})",
+                    flavor,
+                    data.len(),
+                    expected.len()
+                )),
+                Err(e) => {
+                    debug!("Failed to read {} back: {}", flavor, e);
+                    problems.push(format!("{} is missing", flavor));
+                }
+            }
+        }
+        if problems.is_empty() {
+            debug!("Verified the clipboard contents");
+            return Ok(());
+        }
+        // A background holder may not own the selection yet
+        if started.elapsed() >= VERIFY_TIMEOUT {
+            return Err(io::Error::other(format!(
+                "Clipboard verification failed: {}",
+                problems.join(", ")
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// X11 targets that describe the selection rather than hold content
+const META_TARGETS: &[&str] = &[
+    "TARGETS",
+    "TIMESTAMP",
+    "MULTIPLE",
+    "SAVE_TARGETS",
+    "DELETE",
+    "INCR",
+];
+
+/// Read access to whatever the clipboard holds, for `mdcopy inspect`:
+/// `wl-paste` in Wayland sessions, clipboard-rs elsewhere
+pub enum ClipboardReader {
+    WlPaste,
+    System(ClipboardContext),
+}
+
+impl ClipboardReader {
+    pub fn open() -> io::Result<Self> {
+        if cfg!(target_os = "linux")
+            && env::var_os("WAYLAND_DISPLAY").is_some_and(|v| !v.is_empty())
+            && find_in_path("wl-paste").is_some()
+        {
+            return Ok(Self::WlPaste);
+        }
+        ClipboardContext::new()
+            .map(Self::System)
+            .map_err(|e| io::Error::other(format!("Failed to create clipboard context: {}", e)))
+    }
+
+    /// The formats on the clipboard (MIME types on Linux, UTIs on macOS,
+    /// format names on Windows), in the order the owner offers them
+    pub fn formats(&self) -> io::Result<Vec<String>> {
+        let names = match self {
+            Self::WlPaste => {
+                let types = wl_paste(&["--list-types"])?;
+                String::from_utf8_lossy(&types)
+                    .lines()
+                    .map(str::to_string)
+                    .collect()
+            }
+            Self::System(ctx) => ctx.available_formats().map_err(|e| {
+                io::Error::other(format!("Failed to list clipboard formats: {}", e))
+            })?,
+        };
+        let mut formats: Vec<String> = Vec::with_capacity(names.len());
+        for name in names {
+            if !name.is_empty()
+                && !META_TARGETS.contains(&name.as_str())
+                && !formats.contains(&name)
+            {
+                formats.push(name);
+            }
+        }
+        Ok(formats)
+    }
+
+    /// The data of one format as the owner provides it
+    pub fn read(&self, format: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Self::WlPaste => wl_paste(&["--no-newline", "--type", format]),
+            Self::System(ctx) => ctx
+                .get_buffer(format)
+                .map_err(|e| io::Error::other(format!("Failed to read {}: {}", format, e))),
+        }
+    }
+}
+
+fn wl_paste(args: &[&str]) -> io::Result<Vec<u8>> {
+    let output = Command::new("wl-paste")
+        .args(args)
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to run wl-paste: {}", e)))?;
+    if !output.status.success() {
+        // An empty clipboard is an error to wl-paste
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No selection") || stderr.contains("Nothing is copied") {
+            return Ok(Vec::new());
+        }
+        return Err(io::Error::other(format!(
+            "wl-paste failed: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// The format a name given on the command line means: an exact match
+/// (ignoring case), or else the shortest format containing it, so `html`
+/// finds `text/html` or `public.html`
+pub fn match_format<'a>(query: &str, formats: &'a [String]) -> Option<&'a str> {
+    let query = query.to_lowercase();
+    formats
+        .iter()
+        .find(|format| format.to_lowercase() == query)
+        .or_else(|| {
+            formats
+                .iter()
+                .filter(|format| format.to_lowercase().contains(&query))
+                .min_by_key(|format| format.len())
+        })
+        .map(String::as_str)
+}
+
+/// An executable called `name` in PATH
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload<'a>(html: Option<&'a str>, rtf: Option<&'a str>) -> Payload<'a> {
+        Payload {
+            source: "# Hi",
+            plain_text: None,
+            html,
+            rtf,
+            prosemirror: None,
+            image: None,
+            #[cfg(target_os = "macos")]
+            native: None,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_holder_input_round_trip() {
+        let html = payload(Some("<p>a\nb</p>"), None);
+        let flavors = html.flavors();
+        let decoded = decode_flavors(&encode_flavors(&flavors)).unwrap();
+        assert_eq!(
+            decoded,
+            [
+                (Flavor::Text, b"# Hi".to_vec()),
+                (Flavor::Html, b"<p>a\nb</p>".to_vec()),
+            ]
+        );
+        assert!(decode_flavors(b"txt 10\nshort").is_err());
+        assert!(decode_flavors(b"doc 1\nx").is_err());
+    }
+
+    #[test]
+    fn test_single_offer_prefers_richest() {
+        assert_eq!(
+            payload(Some("<h1>Hi</h1>"), Some("{\\rtf1 Hi}")).single_offer(),
+            ("text/html", "<h1>Hi</h1>".as_bytes())
+        );
+        assert_eq!(
+            payload(None, Some("{\\rtf1 Hi}")).single_offer(),
+            ("text/rtf", "{\\rtf1 Hi}".as_bytes())
+        );
+        assert_eq!(
+            payload(None, None).single_offer(),
+            ("text/plain;charset=utf-8", "# Hi".as_bytes())
+        );
+        let png = [0x89, b'P', b'N', b'G'];
+        let with_image = Payload {
+            image: Some(&png),
+            ..payload(Some("<h1>Hi</h1>"), None)
+        };
+        assert_eq!(with_image.single_offer(), ("image/png", &png[..]));
+    }
+
+    #[test]
+    fn test_match_format() {
+        let formats: Vec<String> = ["text/html;charset=utf-8", "text/html", "TEXT", "text/rtf"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(match_format("text", &formats), Some("TEXT"));
+        assert_eq!(match_format("html", &formats), Some("text/html"));
+        assert_eq!(match_format("RTF", &formats), Some("text/rtf"));
+        assert_eq!(match_format("png", &formats), None);
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence(b"hi", false), "\x1b]52;c;aGk=\x07");
+        assert_eq!(
+            osc52_sequence(b"hi", true),
+            "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\"
+        );
+
+        assert_eq!(Osc52.size_problem(&payload(Some("<p>Hi</p>"), None)), None);
+        let large = "x".repeat(OSC52_LIMIT_BYTES);
+        assert!(Osc52.size_problem(&payload(Some(&large), None)).is_some());
+    }
+
+    #[test]
+    fn test_select_explicit_and_auto_order() {
+        let payload = payload(Some("<h1>Hi</h1>"), None);
+        let mut config = ClipboardConfig {
+            backend: ClipboardBackendKind::Wayland,
+            ..ClipboardConfig::default()
+        };
+        assert_eq!(select(&config, &payload).name(), "wayland");
+
+        // Native output wasn't rendered, so auto skips NSPasteboard
+        config.backend = ClipboardBackendKind::Auto;
+        config.order = vec![ClipboardBackendKind::Native, ClipboardBackendKind::File];
+        assert_eq!(select(&config, &payload).name(), "file");
+
+        config.order = vec![ClipboardBackendKind::Native];
+        assert_eq!(select(&config, &payload).name(), "system");
+    }
+
+    #[test]
+    fn test_auto_keeps_every_flavor() {
+        // wl-copy offers one type, so auto leaves multi-flavor copies to
+        // the system clipboard even under Wayland (OSC 52 is for SSH
+        // sessions, where there's no local clipboard)
+        let rich = payload(Some("<h1>Hi</h1>"), Some("{\\rtf1 Hi}"));
+        assert!(!WlCopy.is_available(&rich));
+        let mut config = ClipboardConfig::default();
+        config
+            .order
+            .retain(|&kind| kind != ClipboardBackendKind::Osc52);
+        let backend = select(&config, &rich);
+        assert_eq!(backend.offered(&rich), rich.flavors());
+
+        assert!(payload(None, None).single_offer_is_complete());
+        assert!(!rich.single_offer_is_complete());
+    }
+
+    #[test]
+    fn test_file_dump_mirrors_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = FileDump {
+            dir: dir.path().join("clip"),
+        };
+        dump.copy(&payload(Some("<h1>Hi</h1>"), Some("{\\rtf1 Hi}")))
+            .unwrap();
+        dump.copy(&payload(Some("<h1>Hi</h1>"), None)).unwrap();
+
+        let read = |name: &str| fs::read_to_string(dump.dir.join(name)).ok();
+        assert_eq!(read("clipboard.txt").as_deref(), Some("# Hi"));
+        assert_eq!(read("clipboard.html").as_deref(), Some("<h1>Hi</h1>"));
+        assert_eq!(read("clipboard.rtf"), None);
+    }
+
+    #[test]
+    fn test_verify_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = FileDump {
+            dir: dir.path().join("clip"),
+        };
+        let payload = payload(Some("<h1>Hi</h1>"), Some("{\\rtf1 Hi}"));
+        dump.copy(&payload).unwrap();
+        assert!(verify(&dump, &payload).is_ok());
+
+        // A clipboard manager that rewrites the HTML and drops the RTF
+        fs::write(dump.dir.join("clipboard.html"), "<p>Hi</p>").unwrap();
+        fs::remove_file(dump.dir.join("clipboard.rtf")).unwrap();
+        let error = verify(&dump, &payload).unwrap_err().to_string();
+        assert!(
+            error.contains("HTML differs (9 bytes, expected 11)"),
+            "{}",
+            error
+        );
+        assert!(error.contains("RTF is missing"), "{}", error);
+
+        // Nothing to read back through OSC 52
+        assert!(verify(&Osc52, &payload).is_ok());
+    }
+
+    #[test]
+    fn test_copy_if_changed_skips_repeats() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = FileDump {
+            dir: dir.path().join("clip"),
+        };
+        let history = CopyHistory::at(dir.path().join("state").join("last-copy"));
+        let first = payload(Some("<h1>Hi</h1>"), None);
+        assert!(copy_if_changed(&dump, &first, Some(&history)).unwrap());
+        assert!(!copy_if_changed(&dump, &first, Some(&history)).unwrap());
+
+        // A different rendering of the same text is a change
+        let second = payload(Some("<h1>Hi!</h1>"), None);
+        assert!(copy_if_changed(&dump, &second, Some(&history)).unwrap());
+
+        // So is someone else copying in between
+        fs::write(dump.dir.join("clipboard.txt"), "other").unwrap();
+        assert!(copy_if_changed(&dump, &second, Some(&history)).unwrap());
+        assert!(copy_if_changed(&dump, &second, None).unwrap());
+    }
+}
@@ -0,0 +1,246 @@
+//! Several input files as one document (`-i a.md -i b.md`). Each file's
+//! relative image and link URLs are rewritten against the directory of the
+//! first, where the combined document resolves them, and front matter is
+//! kept only from the first file. A separator goes between the files.
+
+use crate::config::Separator;
+use crate::image::is_remote_url;
+use markdown::mdast::Node;
+use std::path::{Path, PathBuf};
+
+/// Raw HTML for a page break, the convention print stylesheets and
+/// markdown editors share; PDF and RTF output start a new page there
+pub const PAGE_BREAK: &str = "<div style=\"page-break-after: always\"></div>";
+
+/// Whether raw HTML is a page break: a `<div>` with `page-break-after:
+/// always` or `break-after: page`
+pub fn is_page_break(html: &str) -> bool {
+    let compact: String = html
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    compact.starts_with("<div")
+        && (compact.contains("page-break-after:always") || compact.contains("break-after:page"))
+}
+
+/// One input file: its directory, source and parsed tree
+pub struct Part<'a> {
+    pub dir: &'a Path,
+    pub source: &'a str,
+    pub ast: &'a Node,
+}
+
+fn separator_markdown(separator: &Separator) -> &str {
+    match separator {
+        Separator::Rule => "---",
+        Separator::PageBreak => PAGE_BREAK,
+        Separator::None => "",
+        Separator::Custom(markdown) => markdown,
+    }
+}
+
+/// The files joined into one markdown document whose relative URLs resolve
+/// against `base_dir`
+pub fn concatenate(parts: &[Part], base_dir: &Path, separator: &Separator) -> String {
+    let mut out = String::new();
+    for (index, part) in parts.iter().enumerate() {
+        let source = rebase(part, base_dir, index == 0);
+        if index > 0 {
+            out.push_str("\n\n");
+            let separator = separator_markdown(separator);
+            if !separator.is_empty() {
+                out.push_str(separator);
+                out.push_str("\n\n");
+            }
+        }
+        // Blank lines left where front matter was taken off
+        out.push_str(source.trim_start_matches(['\n', '\r']).trim_end());
+    }
+    out.push('\n');
+    out
+}
+
+/// The part's source with relative URLs rewritten and, unless it's the
+/// first part, its front matter taken off
+fn rebase(part: &Part, base_dir: &Path, first: bool) -> String {
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    if !first
+        && let Some(Node::Yaml(yaml)) = part.ast.children().and_then(|c| c.first())
+        && let Some(position) = &yaml.position
+    {
+        edits.push((position.start.offset, position.end.offset, String::new()));
+    }
+    let (Some(dir), Some(base)) = (absolute(part.dir), absolute(base_dir)) else {
+        return apply(part.source, edits);
+    };
+    if dir != base {
+        collect_urls(part.ast, part.source, &dir, &base, &mut edits);
+    }
+    apply(part.source, edits)
+}
+
+fn absolute(dir: &Path) -> Option<PathBuf> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    std::path::absolute(dir).ok()
+}
+
+fn apply(source: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.0));
+    let mut out = source.to_string();
+    for (start, end, text) in edits {
+        out.replace_range(start..end, &text);
+    }
+    out
+}
+
+/// Edits moving each relative image, link and definition URL from `dir` to
+/// `base`
+fn collect_urls(
+    node: &Node,
+    source: &str,
+    dir: &Path,
+    base: &Path,
+    edits: &mut Vec<(usize, usize, String)>,
+) {
+    let (url, marker) = match node {
+        Node::Image(image) => (Some(&image.url), "]("),
+        Node::Link(link) => (Some(&link.url), "]("),
+        Node::Definition(definition) => (Some(&definition.url), "]:"),
+        _ => (None, ""),
+    };
+    if let (Some(url), Some(position)) = (url.filter(|url| is_relative(url)), node.position())
+        && let Some(edit) = url_edit(
+            source,
+            position.start.offset,
+            position.end.offset,
+            marker,
+            url,
+            &rebased(url, dir, base),
+        )
+    {
+        edits.push(edit);
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_urls(child, source, dir, base, edits);
+    }
+}
+
+/// The edit replacing `url` in the destination of the construct at
+/// `start..end`, which follows the last `marker`; None when the URL isn't
+/// written there as parsed (escaped or percent-encoded)
+fn url_edit(
+    source: &str,
+    start: usize,
+    end: usize,
+    marker: &str,
+    url: &str,
+    new_url: &str,
+) -> Option<(usize, usize, String)> {
+    let text = source.get(start..end)?;
+    let destination = text.rfind(marker)? + marker.len();
+    let at = start + destination + text[destination..].find(url)?;
+    let bracketed = source[..at].ends_with('<');
+    let new_url = if !bracketed && new_url.contains([' ', '(', ')']) {
+        format!("<{}>", new_url)
+    } else {
+        new_url.to_string()
+    };
+    Some((at, at + url.len(), new_url))
+}
+
+/// A URL naming a file relative to the document, not a web address,
+/// absolute path or in-document anchor
+fn is_relative(url: &str) -> bool {
+    let scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    !url.is_empty()
+        && !url.starts_with(['#', '/', '\\'])
+        && !is_remote_url(url)
+        && !scheme
+        && !Path::new(url).is_absolute()
+}
+
+/// `url`, relative to `dir`, as seen from `base`: a relative path when
+/// `dir` is inside `base`, an absolute one otherwise
+fn rebased(url: &str, dir: &Path, base: &Path) -> String {
+    let path = dir.join(url);
+    let path = path.strip_prefix(base).unwrap_or(&path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Node {
+        let options = markdown::ParseOptions {
+            constructs: markdown::Constructs {
+                frontmatter: true,
+                ..markdown::Constructs::gfm()
+            },
+            ..Default::default()
+        };
+        markdown::to_mdast(source, &options).unwrap()
+    }
+
+    #[test]
+    fn test_concatenate_rebases_urls() {
+        let base = std::env::current_dir().unwrap();
+        let first = "---\ntitle: Book\n---\n\n# One\n\n![a](img/a.png)\n";
+        let second = "---\ntitle: Two\n---\n\n# Two\n\n![b](b.png \"B\") [next](three.md#top) \
+                      [home](#one) ![web](https://x.io/c.png)\n\n[ref]: <my pic.png>\n";
+        let (first_ast, second_ast) = (parse(first), parse(second));
+        let parts = [
+            Part {
+                dir: Path::new(""),
+                source: first,
+                ast: &first_ast,
+            },
+            Part {
+                dir: Path::new("chapters"),
+                source: second,
+                ast: &second_ast,
+            },
+        ];
+        assert_eq!(
+            concatenate(&parts, &base, &Separator::Rule),
+            "---\ntitle: Book\n---\n\n# One\n\n![a](img/a.png)\n\n---\n\n\
+             # Two\n\n![b](chapters/b.png \"B\") [next](chapters/three.md#top) \
+             [home](#one) ![web](https://x.io/c.png)\n\n[ref]: <chapters/my pic.png>\n"
+        );
+    }
+
+    #[test]
+    fn test_separators() {
+        let (a, b) = ("A\n", "B\n");
+        let (a_ast, b_ast) = (parse(a), parse(b));
+        let parts = [
+            Part {
+                dir: Path::new("."),
+                source: a,
+                ast: &a_ast,
+            },
+            Part {
+                dir: Path::new("."),
+                source: b,
+                ast: &b_ast,
+            },
+        ];
+        let joined =
+            |separator: &str| concatenate(&parts, Path::new("."), &Separator::parse(separator));
+        assert_eq!(joined("none"), "A\n\nB\n");
+        assert_eq!(joined("* * *"), "A\n\n* * *\n\nB\n");
+        assert!(is_page_break(&joined("pagebreak")[3..]));
+        assert!(is_page_break("<div style=\"break-after: page\"></div>"));
+        assert!(!is_page_break("<div style=\"color: red\"></div>"));
+    }
+}
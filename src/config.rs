@@ -1,7 +1,8 @@
-use log::{debug, trace};
+use log::{debug, trace, warn};
+use regex::Regex;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Highlight configuration from file
 #[derive(Debug, Default, Deserialize)]
@@ -9,10 +10,20 @@ use std::path::PathBuf;
 pub struct FileHighlightConfig {
     pub enable: Option<bool>,
     pub theme: Option<String>,
+    pub theme_light: Option<String>,
+    pub theme_dark: Option<String>,
+    pub appearance: Option<String>,
     pub themes_dir: Option<String>,
     pub syntaxes_dir: Option<String>,
+    pub themes_source: Option<String>,
+    pub syntaxes_source: Option<String>,
+    pub check_contrast: Option<bool>,
+    pub detect_language: Option<bool>,
+    pub badge: Option<String>,
     #[serde(default)]
     pub languages: HashMap<String, String>,
+    #[serde(default)]
+    pub fallbacks: HashMap<String, String>,
 }
 
 /// Image embed configuration from file
@@ -25,73 +36,1257 @@ pub struct FileImageEmbedConfig {
     pub optimize_remote: Option<bool>,
     pub max_dimension: Option<u32>,
     pub quality: Option<u8>,
+    pub format: Option<String>,
+    pub keep_exif: Option<bool>,
+}
+
+/// Persistent image cache configuration from file ([image.cache])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileImageCacheConfig {
+    pub enable: Option<bool>,
+    pub dir: Option<String>,
+    pub ttl_hours: Option<u64>,
+    pub max_size_mb: Option<u64>,
+}
+
+/// Remote image fetch configuration from file ([image.fetch])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileImageFetchConfig {
+    pub timeout_ms: Option<u64>,
+    pub retries: Option<u32>,
+    pub max_bytes: Option<u64>,
+    pub netrc: Option<bool>,
+    pub ca_bundle: Option<String>,
+    pub insecure: Option<bool>,
+}
+
+/// Native clipboard image configuration from file ([image.native])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileImageNativeConfig {
+    pub inline_max_kb: Option<u64>,
+    pub thumbnail_dimension: Option<u32>,
+}
+
+/// Emoji glyph configuration from file ([image.emoji])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileImageEmojiConfig {
+    pub images: Option<String>,
+    pub base_url: Option<String>,
+}
+
+/// Image configuration from file (wrapper for nested [image.*] tables)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileImageConfig {
+    pub mode: Option<String>,
+    pub concurrency: Option<usize>,
+    #[serde(default)]
+    pub embed: FileImageEmbedConfig,
+    #[serde(default)]
+    pub cache: FileImageCacheConfig,
+    #[serde(default)]
+    pub fetch: FileImageFetchConfig,
+    /// Per-domain request headers, e.g. `[image.auth."github.com"] authorization = "..."`
+    #[serde(default)]
+    pub auth: HashMap<String, HashMap<String, String>>,
+    /// URL rewrites in the order written, e.g. `[image.rewrite] "^/assets/" = "https://cdn/assets/"`
+    #[serde(default)]
+    pub rewrite: toml::Table,
+    #[serde(default)]
+    pub native: FileImageNativeConfig,
+    #[serde(default)]
+    pub emoji: FileImageEmojiConfig,
+}
+
+/// Typography configuration from file ([typography])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileTypographyConfig {
+    pub list_style: Option<String>,
+    pub quotes: Option<String>,
+    pub smart_punctuation: Option<bool>,
+    pub headings: Option<String>,
+}
+
+/// Native clipboard configuration from file ([native])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileNativeConfig {
+    pub table_max_rows: Option<usize>,
+    pub table_overflow: Option<String>,
+}
+
+/// Code block layout from file ([html.code])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileHtmlCodeConfig {
+    pub style: Option<String>,
+    pub wrap: Option<bool>,
+    pub max_width: Option<u32>,
+}
+
+/// HTML configuration from file ([html])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileHtmlConfig {
+    #[serde(default)]
+    pub code: FileHtmlCodeConfig,
+}
+
+/// Clipboard configuration from file ([clipboard])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileClipboardConfig {
+    pub backend: Option<String>,
+    pub order: Option<Vec<String>>,
+    pub hold: Option<bool>,
+    pub skip_unchanged: Option<bool>,
+    pub verify: Option<bool>,
+    pub dump_dir: Option<String>,
+    pub excerpt: Option<usize>,
+    pub max_size_mb: Option<u64>,
+}
+
+/// Copy history configuration from file ([history])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileHistoryConfig {
+    pub enable: Option<bool>,
+    pub max_entries: Option<usize>,
+    pub max_size_mb: Option<u64>,
+}
+
+/// Strict mode categories from file ([strict])
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileStrictConfig {
+    pub images: Option<bool>,
+    pub languages: Option<bool>,
+    pub themes: Option<bool>,
+    pub nodes: Option<bool>,
+    pub output_size: Option<bool>,
+    pub clipboard: Option<bool>,
+}
+
+/// `strict` in a config file: `strict = true` for every category, or a
+/// `[strict]` table choosing them
+#[derive(Debug)]
+pub enum FileStrict {
+    All(bool),
+    Categories(FileStrictConfig),
+}
+
+impl<'de> Deserialize<'de> for FileStrict {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = FileStrict;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a boolean or a table of categories")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<FileStrict, E> {
+                Ok(FileStrict::All(v))
+            }
+
+            // Through the map as given, so unknown categories are reported
+            // like any other unknown key
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<FileStrict, A::Error> {
+                FileStrictConfig::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(FileStrict::Categories)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Extra output profile from file (`[[outputs]]`)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileOutputConfig {
+    pub format: String,
+    pub path: Option<String>,
+}
+
+/// Configuration loaded from file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    pub input: Option<String>,
+    pub formats: Option<Vec<String>>,
+    pub from: Option<String>,
+    pub separator: Option<String>,
+    pub output: Option<String>,
+    pub output_dir: Option<String>,
+    pub name_template: Option<String>,
+    pub jobs: Option<usize>,
+    pub sequence_delay_ms: Option<u64>,
+    pub manifest: Option<String>,
+    pub bibliography: Option<String>,
+    pub root: Option<String>,
+    pub strict: Option<FileStrict>,
+    pub prosemirror: Option<bool>,
+    pub collapsible_headings: Option<u8>,
+    pub heading_ids: Option<bool>,
+    pub standalone: Option<bool>,
+    pub email: Option<bool>,
+    pub keep_frontmatter: Option<bool>,
+    pub footnotes: Option<String>,
+    pub captions: Option<bool>,
+    pub unfurl: Option<bool>,
+    pub notify: Option<bool>,
+    #[serde(default)]
+    pub highlight: FileHighlightConfig,
+    #[serde(default)]
+    pub image: FileImageConfig,
+    #[serde(default)]
+    pub typography: FileTypographyConfig,
+    #[serde(default)]
+    pub native: FileNativeConfig,
+    #[serde(default)]
+    pub html: FileHtmlConfig,
+    #[serde(default)]
+    pub clipboard: FileClipboardConfig,
+    #[serde(default)]
+    pub history: FileHistoryConfig,
+    #[serde(default)]
+    pub outputs: Vec<FileOutputConfig>,
+}
+
+impl FileConfig {
+    /// Resolve relative paths against `dir` (`-` for stdin/stdout and URLs
+    /// are kept)
+    pub fn rebase_paths(&mut self, dir: &Path) {
+        let rebase = |path: &mut Option<String>| {
+            if let Some(p) = path.as_mut()
+                && p != "-"
+                && !crate::bundle::is_url(p)
+            {
+                *p = dir.join(&*p).to_string_lossy().into_owned();
+            }
+        };
+        rebase(&mut self.input);
+        rebase(&mut self.output);
+        rebase(&mut self.output_dir);
+        rebase(&mut self.manifest);
+        rebase(&mut self.bibliography);
+        rebase(&mut self.root);
+        rebase(&mut self.highlight.themes_dir);
+        rebase(&mut self.highlight.syntaxes_dir);
+        rebase(&mut self.highlight.themes_source);
+        rebase(&mut self.highlight.syntaxes_source);
+        rebase(&mut self.image.cache.dir);
+        rebase(&mut self.image.fetch.ca_bundle);
+        rebase(&mut self.clipboard.dump_dir);
+        for output in &mut self.outputs {
+            rebase(&mut output.path);
+        }
+    }
+}
+
+/// Appearance used to pick between light and dark highlight themes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Appearance {
+    /// Follow the system appearance (macOS only, otherwise uses `theme`)
+    Auto,
+    Light,
+    Dark,
+}
+
+impl Appearance {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" | "system" => Some(Appearance::Auto),
+            "light" => Some(Appearance::Light),
+            "dark" => Some(Appearance::Dark),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Appearance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Appearance::Auto => write!(f, "auto"),
+            Appearance::Light => write!(f, "light"),
+            Appearance::Dark => write!(f, "dark"),
+        }
+    }
+}
+
+/// Language label on fenced code blocks, since pasted code loses its fence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodeBadge {
+    #[default]
+    None,
+    /// Small label floated into the top right corner of the block
+    Corner,
+    /// Label on its own line above the code, for apps that drop floats
+    Header,
+}
+
+impl CodeBadge {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" | "off" => Some(CodeBadge::None),
+            "corner" => Some(CodeBadge::Corner),
+            "header" => Some(CodeBadge::Header),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CodeBadge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodeBadge::None => write!(f, "none"),
+            CodeBadge::Corner => write!(f, "corner"),
+            CodeBadge::Header => write!(f, "header"),
+        }
+    }
+}
+
+/// Markup of code blocks in HTML, as paste targets take code best in
+/// different shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodeStyle {
+    /// Block colored entirely through inline styles, which survives pasting
+    /// into mail and most editors
+    #[default]
+    DivInline,
+    /// Bare `<pre><code class="language-...">`, for apps that highlight code
+    /// themselves (Confluence, WordPress)
+    PreClasses,
+    /// Two-column table with line numbers beside the code
+    Table,
+}
+
+impl CodeStyle {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "div-inline" | "inline" => Some(CodeStyle::DivInline),
+            "pre-classes" | "classes" => Some(CodeStyle::PreClasses),
+            "table" => Some(CodeStyle::Table),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CodeStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodeStyle::DivInline => write!(f, "div-inline"),
+            CodeStyle::PreClasses => write!(f, "pre-classes"),
+            CodeStyle::Table => write!(f, "table"),
+        }
+    }
+}
+
+/// Detect the system appearance.
+/// On macOS, reads the global `AppleInterfaceStyle` default (only set in dark mode).
+#[cfg(target_os = "macos")]
+fn detect_system_appearance() -> Option<Appearance> {
+    let output = std::process::Command::new("defaults")
+        .args(["read", "-g", "AppleInterfaceStyle"])
+        .output()
+        .ok()?;
+    let style = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() && style.trim().eq_ignore_ascii_case("dark") {
+        Some(Appearance::Dark)
+    } else {
+        // The key is absent in light mode, which makes `defaults` exit non-zero
+        Some(Appearance::Light)
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn detect_system_appearance() -> Option<Appearance> {
+    None
+}
+
+/// Resolved highlight configuration
+#[derive(Debug, Clone)]
+pub struct HighlightConfig {
+    pub enable: bool,
+    pub theme: String,
+    /// Theme used when the appearance resolves to light
+    pub theme_light: Option<String>,
+    /// Theme used when the appearance resolves to dark
+    pub theme_dark: Option<String>,
+    pub appearance: Appearance,
+    pub themes_dir: Option<PathBuf>,
+    pub syntaxes_dir: Option<PathBuf>,
+    /// Archive or URL of a bundle of themes, loaded alongside `themes_dir`
+    pub themes_source: Option<String>,
+    /// Archive or URL of a bundle of syntaxes, loaded alongside `syntaxes_dir`
+    pub syntaxes_source: Option<String>,
+    /// Warn about theme colors below the WCAG AA contrast ratio
+    pub check_contrast: bool,
+    /// Guess the language of code blocks without one, from a shebang or
+    /// what the code looks like
+    pub detect_language: bool,
+    /// Language label on fenced code blocks in HTML and native output
+    pub badge: CodeBadge,
+    pub languages: HashMap<String, String>,
+    /// Fallback languages tried when no grammar matches (e.g. `svelte -> html`)
+    pub fallbacks: HashMap<String, String>,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            theme: "base16-ocean.dark".to_string(),
+            theme_light: None,
+            theme_dark: None,
+            appearance: Appearance::Auto,
+            themes_dir: None,
+            syntaxes_dir: None,
+            themes_source: None,
+            syntaxes_source: None,
+            check_contrast: false,
+            detect_language: false,
+            badge: CodeBadge::None,
+            languages: default_language_mappings(),
+            fallbacks: default_language_fallbacks(),
+        }
+    }
+}
+
+/// How images are represented in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageMode {
+    /// Render images (embedded according to the embed settings)
+    Embed,
+    /// Rewrite every image to a plain link with its alt text
+    LinksOnly,
+}
+
+impl ImageMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "embed" => Some(ImageMode::Embed),
+            "links-only" | "links" => Some(ImageMode::LinksOnly),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ImageMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageMode::Embed => write!(f, "embed"),
+            ImageMode::LinksOnly => write!(f, "links-only"),
+        }
+    }
+}
+
+/// Format optimized images are encoded as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmbedFormat {
+    /// JPEG, or PNG for images with transparency
+    #[default]
+    Auto,
+    /// JPEG even for images with transparency, flattened onto white
+    Jpeg,
+    /// Lossless PNG
+    Png,
+    /// Lossy WebP with transparency, much smaller in apps that show it
+    Webp,
+}
+
+impl EmbedFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Some(EmbedFormat::Auto),
+            "jpeg" | "jpg" => Some(EmbedFormat::Jpeg),
+            "png" => Some(EmbedFormat::Png),
+            "webp" => Some(EmbedFormat::Webp),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EmbedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbedFormat::Auto => write!(f, "auto"),
+            EmbedFormat::Jpeg => write!(f, "jpeg"),
+            EmbedFormat::Png => write!(f, "png"),
+            EmbedFormat::Webp => write!(f, "webp"),
+        }
+    }
+}
+
+/// The format of the input document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    #[default]
+    Markdown,
+    /// An HTML document or fragment, converted to markdown first
+    Html,
+}
+
+impl InputFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Some(InputFormat::Markdown),
+            "html" | "htm" => Some(InputFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFormat::Markdown => write!(f, "markdown"),
+            InputFormat::Html => write!(f, "html"),
+        }
+    }
+}
+
+/// What goes between concatenated files
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Separator {
+    /// A thematic break (`---`)
+    #[default]
+    Rule,
+    /// A page break (PDF, RTF and printed HTML)
+    PageBreak,
+    /// Only a blank line
+    None,
+    /// Markdown of the user's choosing
+    Custom(String),
+}
+
+impl Separator {
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "rule" | "hr" => Separator::Rule,
+            "pagebreak" | "page-break" | "page" => Separator::PageBreak,
+            "none" | "" => Separator::None,
+            _ => Separator::Custom(s.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Separator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Separator::Rule => write!(f, "rule"),
+            Separator::PageBreak => write!(f, "pagebreak"),
+            Separator::None => write!(f, "none"),
+            Separator::Custom(markdown) => write!(f, "{}", markdown),
+        }
+    }
+}
+
+/// Where footnote definitions are placed in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FootnotePlacement {
+    /// Numbered notes at the end of the document
+    End,
+    /// Numbered notes at the end of each H2 section
+    Section,
+    /// Note text in parentheses in place of the reference
+    Inline,
+}
+
+impl FootnotePlacement {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "end" => Some(FootnotePlacement::End),
+            "section" => Some(FootnotePlacement::Section),
+            "inline" => Some(FootnotePlacement::Inline),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FootnotePlacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FootnotePlacement::End => write!(f, "end"),
+            FootnotePlacement::Section => write!(f, "section"),
+            FootnotePlacement::Inline => write!(f, "inline"),
+        }
+    }
+}
+
+/// Which outputs replace emoji with inline glyph images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmojiImages {
+    /// Keep emoji as text everywhere
+    None,
+    /// Replace emoji in RTF only
+    Rtf,
+    /// Replace emoji in RTF and HTML
+    All,
+}
+
+impl EmojiImages {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" | "off" => Some(EmojiImages::None),
+            "rtf" => Some(EmojiImages::Rtf),
+            "all" => Some(EmojiImages::All),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EmojiImages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmojiImages::None => write!(f, "none"),
+            EmojiImages::Rtf => write!(f, "rtf"),
+            EmojiImages::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Extra HTTP header sent with remote image fetches
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageHeader {
+    /// Only send to this host and its subdomains (None = every host)
+    pub domain: Option<String>,
+    pub name: String,
+    pub value: String,
+}
+
+impl ImageHeader {
+    /// Parse `[DOMAIN=]Name: value`, e.g. `github.com=Authorization: token abc`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (name, value) = s.split_once(':')?;
+        let (domain, name) = match name.split_once('=') {
+            Some((domain, name)) => (Some(domain.trim().to_lowercase()), name),
+            None => (None, name),
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            domain,
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+
+    /// Whether this header applies to a request to `host`
+    pub fn matches_host(&self, host: &str) -> bool {
+        match &self.domain {
+            None => true,
+            Some(domain) => {
+                let host = host.to_lowercase();
+                host == *domain || host.ends_with(&format!(".{}", domain))
+            }
+        }
+    }
+}
+
+/// Rewrites image URLs matching `pattern` before they're loaded, with `$1`
+/// and `${name}` in the replacement standing for capture groups
+#[derive(Debug, Clone)]
+pub struct ImageRewrite {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl ImageRewrite {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+        })
+    }
+
+    /// Parse `PATTERN=REPLACEMENT`, e.g. `^/assets/=https://cdn.example.com/assets/`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (pattern, replacement) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected PATTERN=REPLACEMENT, got '{}'", s))?;
+        Self::new(pattern, replacement).map_err(|e| e.to_string())
+    }
+
+    /// `url` as rewritten by the first rule matching it, None when no rule does
+    pub fn apply(rules: &[ImageRewrite], url: &str) -> Option<String> {
+        rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(url))
+            .map(|rule| {
+                rule.pattern
+                    .replace(url, rule.replacement.as_str())
+                    .into_owned()
+            })
+    }
+}
+
+/// Resolved image configuration
+#[derive(Debug, Clone)]
+pub struct ImageConfig {
+    pub mode: ImageMode,
+    pub embed_local: bool,
+    pub embed_remote: bool,
+    pub optimize_local: bool,
+    pub optimize_remote: bool,
+    pub max_dimension: u32,
+    pub quality: u8,
+    /// Format optimized and transcoded images are encoded as
+    pub format: EmbedFormat,
+    /// Leave EXIF, GPS and other metadata in images embedded unoptimized
+    pub keep_exif: bool,
+    /// Keep remote images in a persistent on-disk cache across runs
+    pub cache_enable: bool,
+    /// Custom cache directory (default: platform cache dir + mdcopy/images)
+    pub cache_dir: Option<PathBuf>,
+    /// Age after which cached images are fetched again
+    pub cache_ttl_hours: u64,
+    /// Total cache size limit; oldest entries are evicted first
+    pub cache_max_size_mb: u64,
+    /// Remote fetch timeout for the whole request (0 = no timeout)
+    pub fetch_timeout_ms: u64,
+    /// Retries for transient fetch failures (timeouts, connection errors, 5xx)
+    pub fetch_retries: u32,
+    /// Largest remote image accepted (0 = unlimited)
+    pub fetch_max_bytes: u64,
+    /// Extra request headers (auth tokens, cookies) for remote fetches
+    pub fetch_headers: Vec<ImageHeader>,
+    /// Use credentials from ~/.netrc (or $NETRC) for remote fetches
+    pub fetch_netrc: bool,
+    /// PEM file of root certificates trusted instead of the built-in ones
+    pub fetch_ca_bundle: Option<PathBuf>,
+    /// Skip TLS certificate verification for remote fetches
+    pub fetch_insecure: bool,
+    /// Native output: images larger than this become linked thumbnails (0 = always inline)
+    pub native_inline_max_kb: u64,
+    /// Native output: max dimension of linked thumbnails in pixels
+    pub native_thumbnail_dimension: u32,
+    /// Max images loaded/optimized in parallel (1 = sequential)
+    pub concurrency: usize,
+    /// Outputs where emoji become inline glyph images
+    pub emoji_images: EmojiImages,
+    /// URL or directory holding `<codepoints>.png` emoji glyphs
+    pub emoji_base_url: String,
+    /// Image URL rewrites; the first matching rule applies
+    pub rewrite: Vec<ImageRewrite>,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            mode: ImageMode::Embed,
+            embed_local: true,
+            embed_remote: false,
+            optimize_local: true,
+            optimize_remote: false,
+            max_dimension: 1200,
+            quality: 80,
+            format: EmbedFormat::Auto,
+            keep_exif: false,
+            cache_enable: false,
+            cache_dir: None,
+            cache_ttl_hours: 24 * 7,
+            cache_max_size_mb: 256,
+            fetch_timeout_ms: 10_000,
+            fetch_retries: 2,
+            fetch_max_bytes: 20 * 1024 * 1024,
+            fetch_headers: Vec::new(),
+            fetch_netrc: false,
+            fetch_ca_bundle: None,
+            fetch_insecure: false,
+            native_inline_max_kb: 0,
+            native_thumbnail_dimension: 320,
+            concurrency: 4,
+            emoji_images: EmojiImages::None,
+            emoji_base_url: crate::emoji::DEFAULT_BASE_URL.to_string(),
+            rewrite: Vec::new(),
+        }
+    }
+}
+
+impl ImageConfig {
+    /// Get the persistent cache directory (custom or default)
+    pub fn get_cache_dir(&self) -> Option<PathBuf> {
+        self.cache_dir
+            .clone()
+            .or_else(|| dirs::cache_dir().map(|p| p.join("mdcopy").join("images")))
+    }
+}
+
+/// Ordered list numbering style
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListStyle {
+    /// 1. 2. 3.
+    #[default]
+    Decimal,
+    /// 1) 2) 3)
+    DecimalParen,
+    /// a. b. c.
+    LowerAlpha,
+    /// i. ii. iii.
+    LowerRoman,
+}
+
+impl ListStyle {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "1." | "decimal" => Some(ListStyle::Decimal),
+            "1)" | "decimal-paren" => Some(ListStyle::DecimalParen),
+            "a." | "lower-alpha" => Some(ListStyle::LowerAlpha),
+            "i." | "lower-roman" => Some(ListStyle::LowerRoman),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ListStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListStyle::Decimal => write!(f, "1."),
+            ListStyle::DecimalParen => write!(f, "1)"),
+            ListStyle::LowerAlpha => write!(f, "a."),
+            ListStyle::LowerRoman => write!(f, "i."),
+        }
+    }
+}
+
+/// Capitalization applied to heading text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeadingCase {
+    /// Headings as written
+    #[default]
+    Keep,
+    /// Major words capitalized: "Getting Started with the API"
+    Title,
+    /// Only the first word capitalized: "Getting started with the API"
+    Sentence,
+}
+
+impl HeadingCase {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "none" | "keep" => Some(HeadingCase::Keep),
+            "title-case" | "title" => Some(HeadingCase::Title),
+            "sentence-case" | "sentence" => Some(HeadingCase::Sentence),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for HeadingCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadingCase::Keep => write!(f, "none"),
+            HeadingCase::Title => write!(f, "title-case"),
+            HeadingCase::Sentence => write!(f, "sentence-case"),
+        }
+    }
+}
+
+/// Quotation marks substituted for straight quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteStyle {
+    pub double_open: String,
+    pub double_close: String,
+    pub single_open: String,
+    pub single_close: String,
+}
+
+impl QuoteStyle {
+    fn new(double_open: &str, double_close: &str, single_open: &str, single_close: &str) -> Self {
+        Self {
+            double_open: double_open.to_string(),
+            double_close: double_close.to_string(),
+            single_open: single_open.to_string(),
+            single_close: single_close.to_string(),
+        }
+    }
+
+    /// Quotation marks for a locale (e.g. "de", "fr-FR", "de-CH")
+    pub fn for_locale(locale: &str) -> Option<Self> {
+        let locale = locale.trim().to_lowercase().replace('_', "-");
+        let style = match locale.as_str() {
+            "de-ch" | "fr-ch" | "it-ch" | "ch" => Self::new("«", "»", "‹", "›"),
+            _ => match locale.split('-').next().unwrap_or_default() {
+                "en" => Self::new("“", "”", "‘", "’"),
+                "de" | "cs" | "sk" => Self::new("„", "“", "‚", "‘"),
+                // French uses narrow no-break spaces inside guillemets
+                "fr" => Self::new("«\u{202F}", "\u{202F}»", "‹\u{202F}", "\u{202F}›"),
+                "es" | "it" | "pt" => Self::new("«", "»", "“", "”"),
+                "pl" | "nl" | "hu" | "ro" => Self::new("„", "”", "‚", "’"),
+                "ru" | "uk" => Self::new("«", "»", "„", "“"),
+                "sv" | "fi" => Self::new("”", "”", "’", "’"),
+                _ => return None,
+            },
+        };
+        Some(style)
+    }
+
+    /// Parse a locale name or four literal marks (double open/close, single open/close)
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(style) = Self::for_locale(s) {
+            return Some(style);
+        }
+        let chars: Vec<String> = s.chars().map(String::from).collect();
+        match chars.as_slice() {
+            [a, b, c, d] => Some(Self::new(a, b, c, d)),
+            _ => None,
+        }
+    }
+}
+
+/// Resolved typography configuration
+#[derive(Debug, Clone, Default)]
+pub struct TypographyConfig {
+    pub list_style: ListStyle,
+    /// Quote style spec (locale or four marks); None keeps straight quotes
+    pub quotes: Option<String>,
+    /// Typeset dashes and ellipses, and curl quotes in English style unless
+    /// `quotes` picks a style
+    pub smart_punctuation: bool,
+    pub headings: HeadingCase,
+}
+
+impl TypographyConfig {
+    pub fn quote_style(&self) -> Option<QuoteStyle> {
+        match self.quotes.as_deref() {
+            Some(spec) => QuoteStyle::parse(spec),
+            None if self.smart_punctuation => QuoteStyle::for_locale("en"),
+            None => None,
+        }
+    }
+}
+
+/// What native output does with tables over `NativeConfig::table_max_rows`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableOverflow {
+    /// One monospace text grid instead of a table
+    #[default]
+    Grid,
+    /// Several tables of at most the maximum rows, each repeating the header
+    Split,
+}
+
+impl TableOverflow {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "grid" => Some(TableOverflow::Grid),
+            "split" | "chunk" => Some(TableOverflow::Split),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TableOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableOverflow::Grid => write!(f, "grid"),
+            TableOverflow::Split => write!(f, "split"),
+        }
+    }
+}
+
+/// Native (NSAttributedString) output settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NativeConfig {
+    /// Body rows a table may have before it's handled by `table_overflow`,
+    /// since large NSTextTables can hang Pages on paste (0 = no limit)
+    pub table_max_rows: usize,
+    pub table_overflow: TableOverflow,
+}
+
+impl Default for NativeConfig {
+    fn default() -> Self {
+        Self {
+            table_max_rows: 200,
+            table_overflow: TableOverflow::Grid,
+        }
+    }
+}
+
+/// How code blocks lay out long lines in HTML and RTF
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodeLayout {
+    /// Wrap long lines (true) or keep them whole (false); unset leaves it
+    /// to the app pasted into
+    pub wrap: Option<bool>,
+    /// Widest a code block gets, in characters
+    pub max_width: Option<u32>,
+}
+
+/// HTML output settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HtmlConfig {
+    pub code_style: CodeStyle,
+    pub code: CodeLayout,
+}
+
+/// How the clipboard is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackendKind {
+    /// The first available backend in `ClipboardConfig::order`
+    Auto,
+    /// The platform clipboard through clipboard-rs (X11 on Linux)
+    System,
+    /// NSPasteboard with the native attributed string (macOS)
+    Native,
+    /// `wl-copy` from wl-clipboard
+    Wayland,
+    /// An OSC 52 escape sequence written to the terminal
+    Osc52,
+    /// One file per format in `ClipboardConfig::dump_dir`
+    File,
+}
+
+impl ClipboardBackendKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Some(ClipboardBackendKind::Auto),
+            "system" | "x11" => Some(ClipboardBackendKind::System),
+            "native" | "nspasteboard" => Some(ClipboardBackendKind::Native),
+            "wayland" | "wl-copy" => Some(ClipboardBackendKind::Wayland),
+            "osc52" => Some(ClipboardBackendKind::Osc52),
+            "file" => Some(ClipboardBackendKind::File),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ClipboardBackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClipboardBackendKind::Auto => write!(f, "auto"),
+            ClipboardBackendKind::System => write!(f, "system"),
+            ClipboardBackendKind::Native => write!(f, "native"),
+            ClipboardBackendKind::Wayland => write!(f, "wayland"),
+            ClipboardBackendKind::Osc52 => write!(f, "osc52"),
+            ClipboardBackendKind::File => write!(f, "file"),
+        }
+    }
 }
 
-/// Image configuration from file (wrapper for nested [image.embed])
-#[derive(Debug, Default, Deserialize)]
-#[serde(default)]
-pub struct FileImageConfig {
-    #[serde(default)]
-    pub embed: FileImageEmbedConfig,
+/// Resolved clipboard configuration
+#[derive(Debug, Clone)]
+pub struct ClipboardConfig {
+    pub backend: ClipboardBackendKind,
+    /// Backends tried in turn by `auto`; the system clipboard is the last resort
+    pub order: Vec<ClipboardBackendKind>,
+    /// Linux: keep serving the clipboard from a background process after exit
+    pub hold: bool,
+    /// Leave the clipboard alone when it still holds the output of the last
+    /// identical copy
+    pub skip_unchanged: bool,
+    /// Read the clipboard back after a copy and fail unless every flavor
+    /// came back intact
+    pub verify: bool,
+    /// Directory written by the `file` backend
+    pub dump_dir: PathBuf,
+    /// Words of the document in the plain text flavor, with the rich flavors
+    /// still holding all of it (0 = the whole document)
+    pub excerpt: usize,
+    /// Largest document, and largest clipboard payload, copied (0 = no
+    /// limit); larger ones can still be written with `-o`
+    pub max_size_mb: u64,
 }
 
-/// Configuration loaded from file
-#[derive(Debug, Default, Deserialize)]
-#[serde(default)]
-pub struct FileConfig {
-    pub input: Option<String>,
-    pub output: Option<String>,
-    pub root: Option<String>,
-    pub strict: Option<bool>,
-    pub prosemirror: Option<bool>,
-    #[serde(default)]
-    pub highlight: FileHighlightConfig,
-    #[serde(default)]
-    pub image: FileImageConfig,
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            backend: ClipboardBackendKind::Auto,
+            order: vec![
+                ClipboardBackendKind::Osc52,
+                ClipboardBackendKind::Wayland,
+                ClipboardBackendKind::Native,
+                ClipboardBackendKind::System,
+            ],
+            hold: false,
+            skip_unchanged: true,
+            verify: false,
+            dump_dir: std::env::temp_dir().join("mdcopy-clipboard"),
+            excerpt: 0,
+            max_size_mb: 100,
+        }
+    }
 }
 
-/// Resolved highlight configuration
-#[derive(Debug)]
-pub struct HighlightConfig {
+/// Log of past copies for `mdcopy history` (opt-in)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryConfig {
     pub enable: bool,
-    pub theme: String,
-    pub themes_dir: Option<PathBuf>,
-    pub syntaxes_dir: Option<PathBuf>,
-    pub languages: HashMap<String, String>,
+    /// Copies kept; the oldest are removed first
+    pub max_entries: usize,
+    /// Total size of the kept copies
+    pub max_size_mb: u64,
 }
 
-impl Default for HighlightConfig {
+impl Default for HistoryConfig {
     fn default() -> Self {
         Self {
-            enable: true,
-            theme: "base16-ocean.dark".to_string(),
-            themes_dir: None,
-            syntaxes_dir: None,
-            languages: default_language_mappings(),
+            enable: false,
+            max_entries: 100,
+            max_size_mb: 64,
         }
     }
 }
 
-/// Resolved image configuration
-#[derive(Debug, Clone)]
-pub struct ImageConfig {
-    pub embed_local: bool,
-    pub embed_remote: bool,
-    pub optimize_local: bool,
-    pub optimize_remote: bool,
-    pub max_dimension: u32,
-    pub quality: u8,
+/// Failures that end the run (`--strict`, `[strict]`) instead of being
+/// warned about and worked around
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StrictConfig {
+    /// An image that can't be loaded, instead of leaving a link
+    pub images: bool,
+    /// A code block in a language without a grammar, instead of plain text
+    pub languages: bool,
+    /// A highlight theme that doesn't exist, instead of the default theme
+    pub themes: bool,
+    /// Content an output format drops or simplifies (raw HTML in RTF,
+    /// footnotes in HTML, ...), instead of a summary of the losses
+    pub nodes: bool,
+    /// Output larger than the clipboard backend reliably delivers (OSC 52)
+    pub output_size: bool,
+    /// A clipboard missing a flavor after the copy, which is read back as
+    /// with `verify`
+    pub clipboard: bool,
 }
 
-impl Default for ImageConfig {
-    fn default() -> Self {
+impl StrictConfig {
+    /// Every category fatal, as with `--strict` or `strict = true`
+    pub fn all() -> Self {
         Self {
-            embed_local: true,
-            embed_remote: false,
-            optimize_local: true,
-            optimize_remote: false,
-            max_dimension: 1200,
-            quality: 80,
+            images: true,
+            languages: true,
+            themes: true,
+            nodes: true,
+            output_size: true,
+            clipboard: true,
+        }
+    }
+
+    fn categories(&self) -> [(&'static str, bool); 6] {
+        [
+            ("images", self.images),
+            ("languages", self.languages),
+            ("themes", self.themes),
+            ("nodes", self.nodes),
+            ("output_size", self.output_size),
+            ("clipboard", self.clipboard),
+        ]
+    }
+
+    fn category_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "images" => Some(&mut self.images),
+            "languages" => Some(&mut self.languages),
+            "themes" => Some(&mut self.themes),
+            "nodes" => Some(&mut self.nodes),
+            "output_size" => Some(&mut self.output_size),
+            "clipboard" => Some(&mut self.clipboard),
+            _ => None,
+        }
+    }
+
+    /// A boolean for every category, or a comma-separated list of the
+    /// fatal ones (`images,languages`)
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(all) = parse_bool(s) {
+            return Some(if all { Self::all() } else { Self::default() });
+        }
+        let mut strict = Self::default();
+        for name in s.split(',') {
+            *strict.category_mut(name.trim())? = true;
+        }
+        Some(strict)
+    }
+
+    fn apply_file(&mut self, file: FileStrict) {
+        let file = match file {
+            FileStrict::All(all) => {
+                *self = if all { Self::all() } else { Self::default() };
+                return;
+            }
+            FileStrict::Categories(file) => file,
+        };
+        let values = [
+            ("images", file.images),
+            ("languages", file.languages),
+            ("themes", file.themes),
+            ("nodes", file.nodes),
+            ("output_size", file.output_size),
+            ("clipboard", file.clipboard),
+        ];
+        for (name, value) in values {
+            if let (Some(value), Some(category)) = (value, self.category_mut(name)) {
+                *category = value;
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for StrictConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fatal: Vec<&str> = self
+            .categories()
+            .into_iter()
+            .filter_map(|(name, on)| on.then_some(name))
+            .collect();
+        match fatal.len() {
+            0 => write!(f, "off"),
+            6 => write!(f, "all"),
+            _ => write!(f, "{}", fatal.join(",")),
+        }
+    }
+}
+
+/// Parse a backend order, skipping (and warning about) invalid entries
+fn parse_clipboard_order<S: AsRef<str>>(names: &[S]) -> Vec<ClipboardBackendKind> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let name = name.as_ref().trim();
+            match ClipboardBackendKind::parse(name) {
+                Some(ClipboardBackendKind::Auto) | None => {
+                    warn!("Invalid clipboard backend in order: {}", name);
+                    None
+                }
+                Some(kind) => Some(kind),
+            }
+        })
+        .collect()
+}
+
+/// Additional output profile rendered alongside the primary output
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSpec {
+    /// Comma-separated format list (e.g. "html,rtf")
+    pub format: String,
+    /// Destination file ("-" for stdout), None for the clipboard
+    pub path: Option<PathBuf>,
+}
+
+impl OutputSpec {
+    /// Parse `FORMAT[=PATH]`, e.g. `markdown=notes.md` or `html,rtf`
+    pub fn parse(s: &str) -> Self {
+        match s.split_once('=') {
+            Some((format, path)) => Self {
+                format: format.trim().to_string(),
+                path: Some(PathBuf::from(path)),
+            },
+            None => Self {
+                format: s.trim().to_string(),
+                path: None,
+            },
         }
     }
 }
@@ -103,6 +1298,8 @@ pub enum ConfigSource {
     Default,
     /// From config file
     File(PathBuf),
+    /// From a project-local .mdcopy.toml
+    Project(PathBuf),
     /// From environment variable
     Env(String),
     /// From CLI argument
@@ -114,6 +1311,7 @@ impl std::fmt::Display for ConfigSource {
         match self {
             ConfigSource::Default => write!(f, "default"),
             ConfigSource::File(path) => write!(f, "config: {}", path.display()),
+            ConfigSource::Project(path) => write!(f, "project: {}", path.display()),
             ConfigSource::Env(var) => write!(f, "env: {}", var),
             ConfigSource::Cli => write!(f, "cli"),
         }
@@ -123,29 +1321,35 @@ impl std::fmt::Display for ConfigSource {
 /// Tracks the source of each configuration value
 #[derive(Debug, Clone)]
 pub struct ConfigSources {
+    pub image_mode: ConfigSource,
     pub embed_local: ConfigSource,
     pub embed_remote: ConfigSource,
     pub optimize_local: ConfigSource,
     pub optimize_remote: ConfigSource,
     pub max_dimension: ConfigSource,
     pub quality: ConfigSource,
+    pub image_cache: ConfigSource,
     pub strict: ConfigSource,
     pub highlight_enable: ConfigSource,
     pub highlight_theme: ConfigSource,
+    pub highlight_appearance: ConfigSource,
 }
 
 impl Default for ConfigSources {
     fn default() -> Self {
         Self {
+            image_mode: ConfigSource::Default,
             embed_local: ConfigSource::Default,
             embed_remote: ConfigSource::Default,
             optimize_local: ConfigSource::Default,
             optimize_remote: ConfigSource::Default,
             max_dimension: ConfigSource::Default,
             quality: ConfigSource::Default,
+            image_cache: ConfigSource::Default,
             strict: ConfigSource::Default,
             highlight_enable: ConfigSource::Default,
             highlight_theme: ConfigSource::Default,
+            highlight_appearance: ConfigSource::Default,
         }
     }
 }
@@ -154,6 +1358,10 @@ impl ConfigSources {
     /// Format current settings with their sources for display (used in --help)
     pub fn format_settings(&self, config: &Config) -> String {
         let mut lines = Vec::new();
+        lines.push(format!(
+            "  images: {} ({})",
+            config.image.mode, self.image_mode
+        ));
         lines.push(format!(
             "  embed_local: {} ({})",
             config.image.embed_local, self.embed_local
@@ -178,6 +1386,10 @@ impl ConfigSources {
             "  quality: {} ({})",
             config.image.quality, self.quality
         ));
+        lines.push(format!(
+            "  image_cache: {} ({})",
+            config.image.cache_enable, self.image_cache
+        ));
         lines.push(format!("  strict: {} ({})", config.strict, self.strict));
         lines.push(format!(
             "  highlight: {} ({})",
@@ -187,33 +1399,116 @@ impl ConfigSources {
             "  highlight_theme: {} ({})",
             config.highlight.theme, self.highlight_theme
         ));
+        lines.push(format!(
+            "  highlight_appearance: {} ({})",
+            config.highlight.appearance, self.highlight_appearance
+        ));
         lines.join("\n")
     }
 }
 
+/// Default filename template for batch output (`--output-dir`)
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}.{ext}";
+
 /// Resolved configuration with all sources merged
 #[derive(Debug)]
 pub struct Config {
     pub input: PathBuf,
+    /// Clipboard formats used without `-f` (None = HTML and RTF, with the
+    /// ProseMirror slice when enabled)
+    pub formats: Option<Vec<String>>,
+    /// Format of the input, converted to markdown before rendering
+    pub from: InputFormat,
+    /// Goes between input files concatenated into one document
+    pub separator: Separator,
     pub output: Option<PathBuf>,
+    /// Batch output directory (one file per input and format)
+    pub output_dir: Option<PathBuf>,
+    /// Filename template for batch output
+    pub name_template: String,
+    /// Documents converted in parallel in batch and directory mode
+    /// (0 = one per CPU)
+    pub jobs: usize,
+    /// With --sequence, time before the next document is copied (0 = wait for Enter)
+    pub sequence_delay_ms: u64,
+    /// Where to write the manifest of embedded images (`-` for stdout)
+    pub manifest: Option<PathBuf>,
+    /// BibTeX or CSL-JSON file that `[@key]` citations are resolved against
+    pub bibliography: Option<PathBuf>,
     pub root: Option<PathBuf>,
-    pub strict: bool,
-    /// Emit ProseMirror slice marker for Confluence paste compatibility
+    pub strict: StrictConfig,
+    /// Confluence/Jira paste: mark HTML as a ProseMirror slice and copy the
+    /// editor's own JSON slice alongside it
     pub prosemirror: bool,
+    /// HTML: wrap sections headed at or below this level in `<details>`
+    pub collapsible_headings: Option<u8>,
+    /// Convert only the section under this heading (CLI only)
+    pub section: Option<String>,
+    /// Only match the section heading at this level
+    pub section_level: Option<u8>,
+    /// Give HTML headings slug ids and point `#heading` links at them
+    pub heading_ids: bool,
+    /// Wrap HTML output in a complete document, titled from the front matter
+    pub standalone: bool,
+    /// Lay HTML out for email clients: 600px tables, conservative inline CSS
+    pub email: bool,
+    /// Pass YAML front matter through to markdown output instead of dropping it
+    pub keep_frontmatter: bool,
+    /// Resolve footnotes into the document (None keeps them as footnotes)
+    pub footnotes: Option<FootnotePlacement>,
+    /// Number figures, tables and code listings and resolve `[@fig:id]` references
+    pub captions: bool,
+    /// Turn standalone bare URLs into preview cards (fetches each page)
+    pub unfurl: bool,
+    /// Post a desktop notification when the run finishes
+    pub notify: bool,
     pub highlight: HighlightConfig,
     pub image: ImageConfig,
+    pub typography: TypographyConfig,
+    pub native: NativeConfig,
+    pub html: HtmlConfig,
+    pub clipboard: ClipboardConfig,
+    pub history: HistoryConfig,
+    /// Extra output profiles written in the same run
+    pub outputs: Vec<OutputSpec>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             input: PathBuf::from("-"),
+            formats: None,
+            from: InputFormat::Markdown,
+            separator: Separator::Rule,
             output: None,
+            output_dir: None,
+            name_template: DEFAULT_NAME_TEMPLATE.to_string(),
+            jobs: 0,
+            sequence_delay_ms: 0,
+            manifest: None,
+            bibliography: None,
             root: None,
-            strict: false,
+            strict: StrictConfig::default(),
             prosemirror: true,
+            collapsible_headings: None,
+            section: None,
+            section_level: None,
+            heading_ids: true,
+            standalone: false,
+            email: false,
+            keep_frontmatter: false,
+            footnotes: None,
+            captions: false,
+            unfurl: false,
+            notify: false,
             highlight: HighlightConfig::default(),
             image: ImageConfig::default(),
+            typography: TypographyConfig::default(),
+            native: NativeConfig::default(),
+            html: HtmlConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            history: HistoryConfig::default(),
+            outputs: Vec::new(),
         }
     }
 }
@@ -235,6 +1530,26 @@ fn default_language_mappings() -> HashMap<String, String> {
     m
 }
 
+/// Default fallback languages for grammars not bundled with syntect
+fn default_language_fallbacks() -> HashMap<String, String> {
+    let mut m = HashMap::new();
+    m.insert("svelte".to_string(), "html".to_string());
+    m.insert("vue".to_string(), "html".to_string());
+    m.insert("astro".to_string(), "html".to_string());
+    m.insert("jsx".to_string(), "js".to_string());
+    m.insert("tsx".to_string(), "ts".to_string());
+    m.insert("ts".to_string(), "js".to_string());
+    m.insert("typescript".to_string(), "js".to_string());
+    m.insert("jsonc".to_string(), "json".to_string());
+    m.insert("json5".to_string(), "json".to_string());
+    m.insert("scss".to_string(), "css".to_string());
+    m.insert("less".to_string(), "css".to_string());
+    m.insert("kotlin".to_string(), "java".to_string());
+    m.insert("fish".to_string(), "bash".to_string());
+    m.insert("dockerfile".to_string(), "bash".to_string());
+    m
+}
+
 /// Get the XDG config directory ($XDG_CONFIG_HOME or ~/.config)
 #[cfg(target_os = "macos")]
 fn xdg_config_dir() -> Option<PathBuf> {
@@ -250,6 +1565,20 @@ pub fn default_config_path() -> Option<PathBuf> {
     default_config_dir().map(|p| p.join("config.toml"))
 }
 
+/// Themes directory for shell completion, from the environment, the config
+/// file or the default. A `themes_source` bundle is left out as it may need
+/// fetching, which is too slow while completing.
+pub fn user_themes_dir() -> Option<PathBuf> {
+    env_var("highlight_themes_dir")
+        .or_else(|| {
+            default_config_path()
+                .and_then(|path| load_config_file(&path))
+                .and_then(|file| file.highlight.themes_dir)
+        })
+        .map(PathBuf::from)
+        .or_else(|| default_config_dir().map(|p| p.join("themes")))
+}
+
 /// Get the default config directory
 /// On macOS, checks ~/Library/Application Support/mdcopy/ first, then $XDG_CONFIG_HOME/mdcopy/
 pub fn default_config_dir() -> Option<PathBuf> {
@@ -277,10 +1606,43 @@ pub fn default_config_dir() -> Option<PathBuf> {
     }
 }
 
-/// Load configuration from a TOML file
+/// Name of the project-local config file
+pub const PROJECT_CONFIG_NAME: &str = ".mdcopy.toml";
+
+/// Find a project config by searching upward from the input file's directory
+/// (or the current directory when reading stdin)
+pub fn find_project_config(input: Option<&Path>) -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    let start = match input.filter(|p| p.as_os_str() != "-") {
+        Some(input) => cwd.join(input).parent()?.to_path_buf(),
+        None => cwd,
+    };
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Load configuration from a TOML file, expanding `${VAR}` in string values
 pub fn load_config_file(path: &PathBuf) -> Option<FileConfig> {
+    read_config_file(path, true)
+}
+
+/// Load a project config file. Environment variables are not expanded so a
+/// checked-out repository can't send local secrets (e.g. in auth headers)
+/// to hosts of its choosing.
+pub fn load_project_config_file(path: &PathBuf) -> Option<FileConfig> {
+    read_config_file(path, false)
+}
+
+fn read_config_file(path: &PathBuf, expand_env: bool) -> Option<FileConfig> {
     match std::fs::read_to_string(path) {
-        Ok(content) => match toml::from_str(&content) {
+        Ok(content) => match content.parse::<toml::Table>().and_then(|mut table| {
+            if expand_env {
+                table.iter_mut().for_each(|(_, v)| expand_env_in_value(v));
+            }
+            toml::Value::Table(table).try_into()
+        }) {
             Ok(config) => {
                 debug!("Loaded config from {:?}", path);
                 Some(config)
@@ -301,6 +1663,52 @@ pub fn load_config_file(path: &PathBuf) -> Option<FileConfig> {
     }
 }
 
+fn expand_env_in_value(value: &mut toml::Value) {
+    match value {
+        toml::Value::String(s) => *s = expand_env_vars(s),
+        toml::Value::Array(items) => items.iter_mut().for_each(expand_env_in_value),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| expand_env_in_value(v)),
+        _ => {}
+    }
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` references (the default is used when
+/// VAR is unset or empty). `$${` produces a literal `${`.
+pub fn expand_env_vars(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(after) = after.strip_prefix("${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(body) = after.strip_prefix('{')
+            && let Some(end) = body.find('}')
+        {
+            let expr = &body[..end];
+            let (name, default) = match expr.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (expr, None),
+            };
+            let value = std::env::var(name)
+                .ok()
+                .filter(|v| !v.is_empty() || default.is_none());
+            match (value, default) {
+                (Some(v), _) => out.push_str(&v),
+                (None, Some(default)) => out.push_str(default),
+                (None, None) => warn!("Config references unset environment variable {}", name),
+            }
+            rest = &body[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
 /// Load a setting from environment variable
 fn env_var(name: &str) -> Option<String> {
     let key = format!("MDCOPY_{}", name.to_uppercase());
@@ -310,6 +1718,15 @@ fn env_var(name: &str) -> Option<String> {
     })
 }
 
+/// Set the quote style from a spec; "none"/"straight" disables substitution
+fn apply_quotes(typography: &mut TypographyConfig, spec: String) {
+    match spec.to_lowercase().as_str() {
+        "none" | "straight" | "" => typography.quotes = None,
+        _ if QuoteStyle::parse(&spec).is_some() => typography.quotes = Some(spec),
+        _ => warn!("Unknown quote style: {}", spec),
+    }
+}
+
 fn parse_bool(s: &str) -> Option<bool> {
     match s.to_lowercase().as_str() {
         "true" | "1" | "yes" | "on" => Some(true),
@@ -318,57 +1735,512 @@ fn parse_bool(s: &str) -> Option<bool> {
     }
 }
 
-/// CLI argument values for highlight settings
-pub struct CliHighlightArgs {
-    pub enable: Option<bool>,
-    pub theme: Option<String>,
-    pub themes_dir: Option<PathBuf>,
-    pub syntaxes_dir: Option<PathBuf>,
-}
+fn is_heading_level(level: u8) -> bool {
+    (1..=6).contains(&level)
+}
+
+/// CLI argument values for highlight settings
+pub struct CliHighlightArgs {
+    pub enable: Option<bool>,
+    pub theme: Option<String>,
+    pub appearance: Option<Appearance>,
+    pub themes_dir: Option<PathBuf>,
+    pub syntaxes_dir: Option<PathBuf>,
+    pub themes_source: Option<String>,
+    pub syntaxes_source: Option<String>,
+    pub check_contrast: Option<bool>,
+    pub detect_language: Option<bool>,
+    pub badge: Option<CodeBadge>,
+}
+
+/// CLI argument values for image settings
+pub struct CliImageArgs {
+    pub mode: Option<ImageMode>,
+    pub embed_local: Option<bool>,
+    pub embed_remote: Option<bool>,
+    pub optimize_local: Option<bool>,
+    pub optimize_remote: Option<bool>,
+    pub max_dimension: Option<u32>,
+    pub quality: Option<u8>,
+    pub format: Option<EmbedFormat>,
+    pub keep_exif: Option<bool>,
+    pub cache: Option<bool>,
+    pub fetch_timeout_ms: Option<u64>,
+    pub fetch_retries: Option<u32>,
+    pub fetch_max_bytes: Option<u64>,
+    pub fetch_headers: Vec<ImageHeader>,
+    pub fetch_netrc: Option<bool>,
+    pub rewrite: Vec<ImageRewrite>,
+    pub fetch_ca_bundle: Option<PathBuf>,
+    pub fetch_insecure: Option<bool>,
+    pub native_inline_max_kb: Option<u64>,
+    pub emoji_images: Option<EmojiImages>,
+    pub concurrency: Option<usize>,
+}
+
+/// CLI argument values (None means not specified)
+pub struct CliArgs {
+    pub input: Option<PathBuf>,
+    pub from: Option<InputFormat>,
+    pub separator: Option<Separator>,
+    pub output: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub name_template: Option<String>,
+    pub jobs: Option<usize>,
+    pub sequence_delay_ms: Option<u64>,
+    pub manifest: Option<PathBuf>,
+    pub bibliography: Option<PathBuf>,
+    pub root: Option<PathBuf>,
+    pub strict: Option<bool>,
+    pub prosemirror: Option<bool>,
+    pub collapsible_headings: Option<u8>,
+    pub section: Option<String>,
+    pub section_level: Option<u8>,
+    pub heading_ids: Option<bool>,
+    pub standalone: Option<bool>,
+    pub email: Option<bool>,
+    pub keep_frontmatter: Option<bool>,
+    pub footnotes: Option<FootnotePlacement>,
+    pub captions: Option<bool>,
+    pub unfurl: Option<bool>,
+    pub notify: Option<bool>,
+    pub highlight: CliHighlightArgs,
+    pub image: CliImageArgs,
+    pub list_style: Option<ListStyle>,
+    pub quotes: Option<String>,
+    pub smart_punctuation: Option<bool>,
+    pub headings: Option<HeadingCase>,
+    pub native_table_max_rows: Option<usize>,
+    pub native_table_overflow: Option<TableOverflow>,
+    pub html_code_style: Option<CodeStyle>,
+    pub html_code_wrap: Option<bool>,
+    pub html_code_max_width: Option<u32>,
+    pub clipboard_backend: Option<ClipboardBackendKind>,
+    pub clipboard_hold: Option<bool>,
+    pub clipboard_skip_unchanged: Option<bool>,
+    pub clipboard_verify: Option<bool>,
+    pub clipboard_excerpt: Option<usize>,
+    pub clipboard_max_size_mb: Option<u64>,
+    pub history: Option<bool>,
+    /// Extra output profiles (empty means not specified)
+    pub outputs: Vec<OutputSpec>,
+}
+
+impl HighlightConfig {
+    /// Resolve `Auto` to the detected system appearance (None if undetectable)
+    pub fn resolved_appearance(&self) -> Option<Appearance> {
+        match self.appearance {
+            Appearance::Auto => detect_system_appearance(),
+            other => Some(other),
+        }
+    }
+
+    /// Get the theme name, picking the light/dark variant when configured
+    pub fn effective_theme(&self) -> &str {
+        self.theme_for(self.resolved_appearance())
+    }
+
+    /// Get the theme for a given appearance, falling back to `theme`
+    pub fn theme_for(&self, appearance: Option<Appearance>) -> &str {
+        let variant = match appearance {
+            Some(Appearance::Light) => self.theme_light.as_deref(),
+            Some(Appearance::Dark) => self.theme_dark.as_deref(),
+            _ => None,
+        };
+        variant.unwrap_or(&self.theme)
+    }
+
+    /// Get the themes directory (custom or default)
+    pub fn get_themes_dir(&self) -> Option<PathBuf> {
+        self.themes_dir
+            .clone()
+            .or_else(|| default_config_dir().map(|p| p.join("themes")))
+    }
+
+    /// Get the syntaxes directory (custom or default)
+    pub fn get_syntaxes_dir(&self) -> Option<PathBuf> {
+        self.syntaxes_dir
+            .clone()
+            .or_else(|| default_config_dir().map(|p| p.join("syntaxes")))
+    }
+
+    /// Directories to load themes from: the bundle from `themes_source`,
+    /// fetched on first use, then the themes directory, whose themes win
+    pub fn get_themes_dirs(&self) -> Vec<PathBuf> {
+        with_bundle(self.themes_source.as_deref(), self.get_themes_dir())
+    }
+
+    /// Directories to load syntaxes from: the bundle from `syntaxes_source`,
+    /// then the syntaxes directory
+    pub fn get_syntaxes_dirs(&self) -> Vec<PathBuf> {
+        with_bundle(self.syntaxes_source.as_deref(), self.get_syntaxes_dir())
+    }
+}
+
+/// `dir` after the directory the bundle at `source` unpacks to, skipping a
+/// bundle that can't be fetched
+fn with_bundle(source: Option<&str>, dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let bundle = source.and_then(|source| match crate::bundle::bundle_dir(source) {
+        Ok(bundle) => Some(bundle),
+        Err(e) => {
+            warn!("Ignoring highlighting bundle {}: {}", source, e);
+            None
+        }
+    });
+    bundle.into_iter().chain(dir).collect()
+}
+
+impl Config {
+    /// Apply values from a config file, labelling them with `source`
+    fn apply_file_config(
+        &mut self,
+        file: FileConfig,
+        source: &ConfigSource,
+        sources: &mut ConfigSources,
+    ) {
+        if let Some(v) = file.input {
+            self.input = PathBuf::from(v);
+        }
+        if let Some(v) = file.formats {
+            self.formats = Some(v);
+        }
+        if let Some(v) = file.from {
+            match InputFormat::parse(&v) {
+                Some(format) => self.from = format,
+                None => warn!("Invalid from in config: {}", v),
+            }
+        }
+        if let Some(v) = file.separator {
+            self.separator = Separator::parse(&v);
+        }
+        if let Some(v) = file.output {
+            self.output = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.output_dir {
+            self.output_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.name_template {
+            self.name_template = v;
+        }
+        if let Some(v) = file.jobs {
+            self.jobs = v;
+        }
+        if let Some(v) = file.sequence_delay_ms {
+            self.sequence_delay_ms = v;
+        }
+        if let Some(v) = file.manifest {
+            self.manifest = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.bibliography {
+            self.bibliography = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.root {
+            self.root = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.strict {
+            self.strict.apply_file(v);
+            sources.strict = source.clone();
+        }
+        if let Some(v) = file.prosemirror {
+            self.prosemirror = v;
+        }
+        if let Some(v) = file.collapsible_headings {
+            if is_heading_level(v) {
+                self.collapsible_headings = Some(v);
+            } else {
+                warn!(
+                    "Invalid collapsible_headings in config: {} (expected 1-6)",
+                    v
+                );
+            }
+        }
+        if let Some(v) = file.footnotes {
+            match FootnotePlacement::parse(&v) {
+                Some(placement) => self.footnotes = Some(placement),
+                None => warn!("Invalid footnotes in config: {}", v),
+            }
+        }
+        if let Some(v) = file.heading_ids {
+            self.heading_ids = v;
+        }
+        if let Some(v) = file.standalone {
+            self.standalone = v;
+        }
+        if let Some(v) = file.email {
+            self.email = v;
+        }
+        if let Some(v) = file.keep_frontmatter {
+            self.keep_frontmatter = v;
+        }
+        if let Some(v) = file.captions {
+            self.captions = v;
+        }
+        if let Some(v) = file.unfurl {
+            self.unfurl = v;
+        }
+        if let Some(v) = file.notify {
+            self.notify = v;
+        }
+
+        // Apply highlight config from file
+        if let Some(v) = file.highlight.enable {
+            self.highlight.enable = v;
+            sources.highlight_enable = source.clone();
+        }
+        if let Some(v) = file.highlight.theme {
+            self.highlight.theme = v;
+            sources.highlight_theme = source.clone();
+        }
+        if let Some(v) = file.highlight.theme_light {
+            self.highlight.theme_light = Some(v);
+        }
+        if let Some(v) = file.highlight.theme_dark {
+            self.highlight.theme_dark = Some(v);
+        }
+        if let Some(v) = file.highlight.appearance {
+            match Appearance::parse(&v) {
+                Some(a) => {
+                    self.highlight.appearance = a;
+                    sources.highlight_appearance = source.clone();
+                }
+                None => log::warn!("Invalid highlight appearance in config file: {}", v),
+            }
+        }
+        if let Some(v) = file.highlight.themes_dir {
+            self.highlight.themes_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.highlight.syntaxes_dir {
+            self.highlight.syntaxes_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.highlight.themes_source {
+            self.highlight.themes_source = Some(v);
+        }
+        if let Some(v) = file.highlight.syntaxes_source {
+            self.highlight.syntaxes_source = Some(v);
+        }
+        if let Some(v) = file.highlight.check_contrast {
+            self.highlight.check_contrast = v;
+        }
+        if let Some(v) = file.highlight.detect_language {
+            self.highlight.detect_language = v;
+        }
+        if let Some(v) = file.highlight.badge {
+            match CodeBadge::parse(&v) {
+                Some(badge) => self.highlight.badge = badge,
+                None => warn!("Invalid code badge in config: {}", v),
+            }
+        }
+        for (k, v) in file.highlight.languages {
+            self.highlight.languages.insert(k, v);
+        }
+        for (k, v) in file.highlight.fallbacks {
+            self.highlight.fallbacks.insert(k.to_lowercase(), v);
+        }
+
+        // Apply image config from file
+        if let Some(v) = file.image.embed.local {
+            self.image.embed_local = v;
+            sources.embed_local = source.clone();
+        }
+        if let Some(v) = file.image.embed.remote {
+            self.image.embed_remote = v;
+            sources.embed_remote = source.clone();
+        }
+        if let Some(v) = file.image.embed.optimize_local {
+            self.image.optimize_local = v;
+            sources.optimize_local = source.clone();
+        }
+        if let Some(v) = file.image.embed.optimize_remote {
+            self.image.optimize_remote = v;
+            sources.optimize_remote = source.clone();
+        }
+        if let Some(v) = file.image.embed.max_dimension {
+            self.image.max_dimension = v;
+            sources.max_dimension = source.clone();
+        }
+        if let Some(v) = file.image.embed.quality {
+            self.image.quality = v;
+            sources.quality = source.clone();
+        }
+        if let Some(v) = file.image.embed.format {
+            match EmbedFormat::parse(&v) {
+                Some(format) => self.image.format = format,
+                None => warn!("Invalid image format in config: {}", v),
+            }
+        }
+        if let Some(v) = file.image.embed.keep_exif {
+            self.image.keep_exif = v;
+        }
+        if let Some(v) = file.image.cache.enable {
+            self.image.cache_enable = v;
+            sources.image_cache = source.clone();
+        }
+        if let Some(v) = file.image.cache.dir {
+            self.image.cache_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.image.cache.ttl_hours {
+            self.image.cache_ttl_hours = v;
+        }
+        if let Some(v) = file.image.cache.max_size_mb {
+            self.image.cache_max_size_mb = v;
+        }
+        if let Some(v) = file.image.mode.as_deref().and_then(ImageMode::parse) {
+            self.image.mode = v;
+            sources.image_mode = source.clone();
+        }
+        if let Some(v) = file.image.concurrency {
+            self.image.concurrency = v;
+        }
+        if let Some(v) = file.image.fetch.timeout_ms {
+            self.image.fetch_timeout_ms = v;
+        }
+        if let Some(v) = file.image.fetch.retries {
+            self.image.fetch_retries = v;
+        }
+        if let Some(v) = file.image.fetch.max_bytes {
+            self.image.fetch_max_bytes = v;
+        }
+        if let Some(v) = file.image.fetch.netrc {
+            self.image.fetch_netrc = v;
+        }
+        if let Some(v) = file.image.fetch.ca_bundle {
+            self.image.fetch_ca_bundle = Some(PathBuf::from(v));
+        }
+        if let Some(v) = file.image.fetch.insecure {
+            self.image.fetch_insecure = v;
+        }
+        let mut auth: Vec<_> = file.image.auth.into_iter().collect();
+        auth.sort_by(|a, b| a.0.cmp(&b.0));
+        for (domain, headers) in auth {
+            for (name, value) in headers {
+                self.image.fetch_headers.push(ImageHeader {
+                    domain: Some(domain.to_lowercase()),
+                    name,
+                    value,
+                });
+            }
+        }
+        // Rules from a later file (the project's) are tried first
+        let mut rewrite = Vec::new();
+        for (pattern, replacement) in file.image.rewrite {
+            let Some(replacement) = replacement.as_str() else {
+                warn!("Image rewrite for '{}' in config is not a string", pattern);
+                continue;
+            };
+            match ImageRewrite::new(&pattern, replacement) {
+                Ok(rule) => rewrite.push(rule),
+                Err(e) => warn!("Invalid image rewrite pattern in config: {}", e),
+            }
+        }
+        self.image.rewrite.splice(0..0, rewrite);
+        if let Some(v) = file.image.native.inline_max_kb {
+            self.image.native_inline_max_kb = v;
+        }
+        if let Some(v) = file.image.native.thumbnail_dimension {
+            self.image.native_thumbnail_dimension = v;
+        }
+        if let Some(v) = file.image.emoji.images {
+            match EmojiImages::parse(&v) {
+                Some(images) => self.image.emoji_images = images,
+                None => warn!("Invalid emoji images in config: {}", v),
+            }
+        }
+        if let Some(v) = file.image.emoji.base_url {
+            self.image.emoji_base_url = v;
+        }
+
+        if let Some(v) = file.typography.list_style {
+            match ListStyle::parse(&v) {
+                Some(style) => self.typography.list_style = style,
+                None => warn!("Invalid list_style in config: {}", v),
+            }
+        }
+        if let Some(v) = file.typography.quotes {
+            apply_quotes(&mut self.typography, v);
+        }
+        if let Some(v) = file.typography.smart_punctuation {
+            self.typography.smart_punctuation = v;
+        }
+        if let Some(v) = file.typography.headings {
+            match HeadingCase::parse(&v) {
+                Some(case) => self.typography.headings = case,
+                None => warn!("Invalid headings in config: {}", v),
+            }
+        }
 
-/// CLI argument values for image settings
-pub struct CliImageArgs {
-    pub embed_local: Option<bool>,
-    pub embed_remote: Option<bool>,
-    pub optimize_local: Option<bool>,
-    pub optimize_remote: Option<bool>,
-    pub max_dimension: Option<u32>,
-    pub quality: Option<u8>,
-}
+        if let Some(v) = file.native.table_max_rows {
+            self.native.table_max_rows = v;
+        }
+        if let Some(v) = file.native.table_overflow {
+            match TableOverflow::parse(&v) {
+                Some(overflow) => self.native.table_overflow = overflow,
+                None => warn!("Invalid native table_overflow in config: {}", v),
+            }
+        }
 
-/// CLI argument values (None means not specified)
-pub struct CliArgs {
-    pub input: Option<PathBuf>,
-    pub output: Option<PathBuf>,
-    pub root: Option<PathBuf>,
-    pub strict: Option<bool>,
-    pub prosemirror: Option<bool>,
-    pub highlight: CliHighlightArgs,
-    pub image: CliImageArgs,
-}
+        if let Some(v) = file.html.code.style {
+            match CodeStyle::parse(&v) {
+                Some(style) => self.html.code_style = style,
+                None => warn!("Invalid code style in config: {}", v),
+            }
+        }
+        if let Some(v) = file.html.code.wrap {
+            self.html.code.wrap = Some(v);
+        }
+        if let Some(v) = file.html.code.max_width {
+            self.html.code.max_width = (v > 0).then_some(v);
+        }
 
-impl HighlightConfig {
-    /// Get the theme name
-    pub fn effective_theme(&self) -> &str {
-        &self.theme
-    }
+        if let Some(v) = file.clipboard.backend {
+            match ClipboardBackendKind::parse(&v) {
+                Some(backend) => self.clipboard.backend = backend,
+                None => warn!("Invalid clipboard backend in config: {}", v),
+            }
+        }
+        if let Some(v) = file.clipboard.order {
+            self.clipboard.order = parse_clipboard_order(&v);
+        }
+        if let Some(v) = file.clipboard.hold {
+            self.clipboard.hold = v;
+        }
+        if let Some(v) = file.clipboard.skip_unchanged {
+            self.clipboard.skip_unchanged = v;
+        }
+        if let Some(v) = file.clipboard.verify {
+            self.clipboard.verify = v;
+        }
+        if let Some(v) = file.clipboard.dump_dir {
+            self.clipboard.dump_dir = PathBuf::from(v);
+        }
+        if let Some(v) = file.clipboard.excerpt {
+            self.clipboard.excerpt = v;
+        }
+        if let Some(v) = file.clipboard.max_size_mb {
+            self.clipboard.max_size_mb = v;
+        }
 
-    /// Get the themes directory (custom or default)
-    pub fn get_themes_dir(&self) -> Option<PathBuf> {
-        self.themes_dir
-            .clone()
-            .or_else(|| default_config_dir().map(|p| p.join("themes")))
-    }
+        if let Some(v) = file.history.enable {
+            self.history.enable = v;
+        }
+        if let Some(v) = file.history.max_entries {
+            self.history.max_entries = v;
+        }
+        if let Some(v) = file.history.max_size_mb {
+            self.history.max_size_mb = v;
+        }
 
-    /// Get the syntaxes directory (custom or default)
-    pub fn get_syntaxes_dir(&self) -> Option<PathBuf> {
-        self.syntaxes_dir
-            .clone()
-            .or_else(|| default_config_dir().map(|p| p.join("syntaxes")))
+        if !file.outputs.is_empty() {
+            self.outputs = file
+                .outputs
+                .into_iter()
+                .map(|o| OutputSpec {
+                    format: o.format,
+                    path: o.path.map(PathBuf::from),
+                })
+                .collect();
+        }
     }
-}
 
-impl Config {
     /// Build configuration with precedence: CLI > env vars > config file > defaults
     /// Returns the config along with source tracking for each value
     #[allow(clippy::field_reassign_with_default)]
@@ -390,86 +2262,97 @@ impl Config {
                 .unwrap_or(ConfigSource::Default)
         };
 
-        // Apply config file values
-        if let Some(v) = file_config.input {
-            config.input = PathBuf::from(v);
-        }
-        if let Some(v) = file_config.output {
-            config.output = Some(PathBuf::from(v));
-        }
-        if let Some(v) = file_config.root {
-            config.root = Some(PathBuf::from(v));
-        }
-        if file_config.strict.is_some() {
-            config.strict = file_config.strict.unwrap();
-            sources.strict = file_source(&config_file_path);
-        }
-        if let Some(v) = file_config.prosemirror {
-            config.prosemirror = v;
-        }
+        config.apply_file_config(file_config, &file_source(&config_file_path), &mut sources);
 
-        // Apply highlight config from file
-        if file_config.highlight.enable.is_some() {
-            config.highlight.enable = file_config.highlight.enable.unwrap();
-            sources.highlight_enable = file_source(&config_file_path);
-        }
-        if file_config.highlight.theme.is_some() {
-            config.highlight.theme = file_config.highlight.theme.unwrap();
-            sources.highlight_theme = file_source(&config_file_path);
+        // A project-local .mdcopy.toml sits between the global config and env vars
+        let input = cli
+            .input
+            .clone()
+            .or_else(|| env_var("input").map(PathBuf::from));
+        if let Some(path) = find_project_config(input.as_deref())
+            && let Some(mut project_config) = load_project_config_file(&path)
+        {
+            debug!("Using project config {:?}", path);
+            if let Some(dir) = path.parent() {
+                project_config.rebase_paths(dir);
+            }
+            config.apply_file_config(project_config, &ConfigSource::Project(path), &mut sources);
         }
-        if let Some(v) = file_config.highlight.themes_dir {
-            config.highlight.themes_dir = Some(PathBuf::from(v));
+
+        // Apply environment variables (higher priority than config file)
+        if let Some(v) = env_var("input") {
+            config.input = PathBuf::from(v);
         }
-        if let Some(v) = file_config.highlight.syntaxes_dir {
-            config.highlight.syntaxes_dir = Some(PathBuf::from(v));
+        if let Some(v) = env_var("format") {
+            config.formats = Some(v.split(',').map(|s| s.trim().to_string()).collect());
         }
-        for (k, v) in file_config.highlight.languages {
-            config.highlight.languages.insert(k, v);
+        if let Some(v) = env_var("from").and_then(|s| InputFormat::parse(&s)) {
+            config.from = v;
         }
-
-        // Apply image config from file
-        if file_config.image.embed.local.is_some() {
-            config.image.embed_local = file_config.image.embed.local.unwrap();
-            sources.embed_local = file_source(&config_file_path);
+        if let Some(v) = env_var("separator") {
+            config.separator = Separator::parse(&v);
         }
-        if file_config.image.embed.remote.is_some() {
-            config.image.embed_remote = file_config.image.embed.remote.unwrap();
-            sources.embed_remote = file_source(&config_file_path);
+        if let Some(v) = env_var("output") {
+            config.output = Some(PathBuf::from(v));
         }
-        if file_config.image.embed.optimize_local.is_some() {
-            config.image.optimize_local = file_config.image.embed.optimize_local.unwrap();
-            sources.optimize_local = file_source(&config_file_path);
+        if let Some(v) = env_var("output_dir") {
+            config.output_dir = Some(PathBuf::from(v));
         }
-        if file_config.image.embed.optimize_remote.is_some() {
-            config.image.optimize_remote = file_config.image.embed.optimize_remote.unwrap();
-            sources.optimize_remote = file_source(&config_file_path);
+        if let Some(v) = env_var("name_template") {
+            config.name_template = v;
         }
-        if file_config.image.embed.max_dimension.is_some() {
-            config.image.max_dimension = file_config.image.embed.max_dimension.unwrap();
-            sources.max_dimension = file_source(&config_file_path);
+        if let Some(v) = env_var("jobs").and_then(|s| s.parse().ok()) {
+            config.jobs = v;
         }
-        if file_config.image.embed.quality.is_some() {
-            config.image.quality = file_config.image.embed.quality.unwrap();
-            sources.quality = file_source(&config_file_path);
+        if let Some(v) = env_var("sequence_delay_ms").and_then(|s| s.parse().ok()) {
+            config.sequence_delay_ms = v;
         }
-
-        // Apply environment variables (higher priority than config file)
-        if let Some(v) = env_var("input") {
-            config.input = PathBuf::from(v);
+        if let Some(v) = env_var("manifest") {
+            config.manifest = Some(PathBuf::from(v));
         }
-        if let Some(v) = env_var("output") {
-            config.output = Some(PathBuf::from(v));
+        if let Some(v) = env_var("bibliography") {
+            config.bibliography = Some(PathBuf::from(v));
         }
         if let Some(v) = env_var("root") {
             config.root = Some(PathBuf::from(v));
         }
-        if let Some(v) = env_var("strict").and_then(|s| parse_bool(&s)) {
+        if let Some(v) = env_var("strict").and_then(|s| StrictConfig::parse(&s)) {
             config.strict = v;
             sources.strict = ConfigSource::Env("MDCOPY_STRICT".to_string());
         }
         if let Some(v) = env_var("prosemirror").and_then(|s| parse_bool(&s)) {
             config.prosemirror = v;
         }
+        if let Some(v) = env_var("collapsible_headings")
+            .and_then(|s| s.parse().ok())
+            .filter(|&v| is_heading_level(v))
+        {
+            config.collapsible_headings = Some(v);
+        }
+        if let Some(v) = env_var("footnotes").and_then(|s| FootnotePlacement::parse(&s)) {
+            config.footnotes = Some(v);
+        }
+        if let Some(v) = env_var("heading_ids").and_then(|s| parse_bool(&s)) {
+            config.heading_ids = v;
+        }
+        if let Some(v) = env_var("standalone").and_then(|s| parse_bool(&s)) {
+            config.standalone = v;
+        }
+        if let Some(v) = env_var("email").and_then(|s| parse_bool(&s)) {
+            config.email = v;
+        }
+        if let Some(v) = env_var("keep_frontmatter").and_then(|s| parse_bool(&s)) {
+            config.keep_frontmatter = v;
+        }
+        if let Some(v) = env_var("captions").and_then(|s| parse_bool(&s)) {
+            config.captions = v;
+        }
+        if let Some(v) = env_var("unfurl").and_then(|s| parse_bool(&s)) {
+            config.unfurl = v;
+        }
+        if let Some(v) = env_var("notify").and_then(|s| parse_bool(&s)) {
+            config.notify = v;
+        }
 
         // Highlight env vars (MDCOPY_HIGHLIGHT_*)
         if let Some(v) = env_var("highlight").and_then(|s| parse_bool(&s)) {
@@ -480,12 +2363,38 @@ impl Config {
             config.highlight.theme = v;
             sources.highlight_theme = ConfigSource::Env("MDCOPY_HIGHLIGHT_THEME".to_string());
         }
+        if let Some(v) = env_var("highlight_theme_light") {
+            config.highlight.theme_light = Some(v);
+        }
+        if let Some(v) = env_var("highlight_theme_dark") {
+            config.highlight.theme_dark = Some(v);
+        }
+        if let Some(v) = env_var("highlight_appearance").and_then(|s| Appearance::parse(&s)) {
+            config.highlight.appearance = v;
+            sources.highlight_appearance =
+                ConfigSource::Env("MDCOPY_HIGHLIGHT_APPEARANCE".to_string());
+        }
         if let Some(v) = env_var("highlight_themes_dir") {
             config.highlight.themes_dir = Some(PathBuf::from(v));
         }
         if let Some(v) = env_var("highlight_syntaxes_dir") {
             config.highlight.syntaxes_dir = Some(PathBuf::from(v));
         }
+        if let Some(v) = env_var("highlight_themes_source") {
+            config.highlight.themes_source = Some(v);
+        }
+        if let Some(v) = env_var("highlight_syntaxes_source") {
+            config.highlight.syntaxes_source = Some(v);
+        }
+        if let Some(v) = env_var("highlight_check_contrast").and_then(|s| parse_bool(&s)) {
+            config.highlight.check_contrast = v;
+        }
+        if let Some(v) = env_var("highlight_detect_language").and_then(|s| parse_bool(&s)) {
+            config.highlight.detect_language = v;
+        }
+        if let Some(v) = env_var("highlight_badge").and_then(|s| CodeBadge::parse(&s)) {
+            config.highlight.badge = v;
+        }
 
         // Image env vars (MDCOPY_IMAGE_EMBED_*)
         if let Some(v) = env_var("image_embed_local").and_then(|s| parse_bool(&s)) {
@@ -515,24 +2424,198 @@ impl Config {
             config.image.quality = v;
             sources.quality = ConfigSource::Env("MDCOPY_IMAGE_EMBED_QUALITY".to_string());
         }
+        if let Some(v) = env_var("image_embed_format").and_then(|s| EmbedFormat::parse(&s)) {
+            config.image.format = v;
+        }
+        if let Some(v) = env_var("image_embed_keep_exif").and_then(|s| parse_bool(&s)) {
+            config.image.keep_exif = v;
+        }
+        if let Some(v) = env_var("image_cache").and_then(|s| parse_bool(&s)) {
+            config.image.cache_enable = v;
+            sources.image_cache = ConfigSource::Env("MDCOPY_IMAGE_CACHE".to_string());
+        }
+        if let Some(v) = env_var("image_cache_dir") {
+            config.image.cache_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("image_cache_ttl_hours").and_then(|s| s.parse().ok()) {
+            config.image.cache_ttl_hours = v;
+        }
+        if let Some(v) = env_var("image_cache_max_size_mb").and_then(|s| s.parse().ok()) {
+            config.image.cache_max_size_mb = v;
+        }
+        if let Some(v) = env_var("image_mode").and_then(|s| ImageMode::parse(&s)) {
+            config.image.mode = v;
+            sources.image_mode = ConfigSource::Env("MDCOPY_IMAGE_MODE".to_string());
+        }
+        if let Some(v) = env_var("typography_list_style").and_then(|s| ListStyle::parse(&s)) {
+            config.typography.list_style = v;
+        }
+        if let Some(v) = env_var("typography_quotes") {
+            apply_quotes(&mut config.typography, v);
+        }
+        if let Some(v) = env_var("typography_smart_punctuation").and_then(|s| parse_bool(&s)) {
+            config.typography.smart_punctuation = v;
+        }
+        if let Some(v) = env_var("typography_headings").and_then(|s| HeadingCase::parse(&s)) {
+            config.typography.headings = v;
+        }
+        if let Some(v) = env_var("native_table_max_rows").and_then(|s| s.parse().ok()) {
+            config.native.table_max_rows = v;
+        }
+        if let Some(v) = env_var("native_table_overflow").and_then(|s| TableOverflow::parse(&s)) {
+            config.native.table_overflow = v;
+        }
+        if let Some(v) = env_var("html_code_style").and_then(|s| CodeStyle::parse(&s)) {
+            config.html.code_style = v;
+        }
+        if let Some(v) = env_var("html_code_wrap").and_then(|s| parse_bool(&s)) {
+            config.html.code.wrap = Some(v);
+        }
+        if let Some(v) = env_var("html_code_max_width").and_then(|s| s.parse::<u32>().ok()) {
+            config.html.code.max_width = (v > 0).then_some(v);
+        }
+        if let Some(v) = env_var("clipboard_backend").and_then(|s| ClipboardBackendKind::parse(&s))
+        {
+            config.clipboard.backend = v;
+        }
+        if let Some(v) = env_var("clipboard_order") {
+            config.clipboard.order = parse_clipboard_order(&v.split(',').collect::<Vec<_>>());
+        }
+        if let Some(v) = env_var("clipboard_hold").and_then(|s| parse_bool(&s)) {
+            config.clipboard.hold = v;
+        }
+        if let Some(v) = env_var("clipboard_skip_unchanged").and_then(|s| parse_bool(&s)) {
+            config.clipboard.skip_unchanged = v;
+        }
+        if let Some(v) = env_var("clipboard_verify").and_then(|s| parse_bool(&s)) {
+            config.clipboard.verify = v;
+        }
+        if let Some(v) = env_var("clipboard_dump_dir") {
+            config.clipboard.dump_dir = PathBuf::from(v);
+        }
+        if let Some(v) = env_var("clipboard_excerpt").and_then(|s| s.parse().ok()) {
+            config.clipboard.excerpt = v;
+        }
+        if let Some(v) = env_var("clipboard_max_size_mb").and_then(|s| s.parse().ok()) {
+            config.clipboard.max_size_mb = v;
+        }
+        if let Some(v) = env_var("history").and_then(|s| parse_bool(&s)) {
+            config.history.enable = v;
+        }
+        if let Some(v) = env_var("history_max_entries").and_then(|s| s.parse().ok()) {
+            config.history.max_entries = v;
+        }
+        if let Some(v) = env_var("history_max_size_mb").and_then(|s| s.parse().ok()) {
+            config.history.max_size_mb = v;
+        }
+        if let Some(v) = env_var("image_concurrency").and_then(|s| s.parse().ok()) {
+            config.image.concurrency = v;
+        }
+        if let Some(v) = env_var("image_fetch_timeout_ms").and_then(|s| s.parse().ok()) {
+            config.image.fetch_timeout_ms = v;
+        }
+        if let Some(v) = env_var("image_fetch_retries").and_then(|s| s.parse().ok()) {
+            config.image.fetch_retries = v;
+        }
+        if let Some(v) = env_var("image_fetch_max_bytes").and_then(|s| s.parse().ok()) {
+            config.image.fetch_max_bytes = v;
+        }
+        if let Some(v) = env_var("image_fetch_netrc").and_then(|s| parse_bool(&s)) {
+            config.image.fetch_netrc = v;
+        }
+        if let Some(v) = env_var("image_fetch_ca_bundle") {
+            config.image.fetch_ca_bundle = Some(PathBuf::from(v));
+        }
+        if let Some(v) = env_var("image_fetch_insecure").and_then(|s| parse_bool(&s)) {
+            config.image.fetch_insecure = v;
+        }
+        if let Some(v) = env_var("image_native_inline_max_kb").and_then(|s| s.parse().ok()) {
+            config.image.native_inline_max_kb = v;
+        }
+        if let Some(v) = env_var("image_native_thumbnail_dimension").and_then(|s| s.parse().ok()) {
+            config.image.native_thumbnail_dimension = v;
+        }
+        if let Some(v) = env_var("image_emoji_images").and_then(|s| EmojiImages::parse(&s)) {
+            config.image.emoji_images = v;
+        }
+        if let Some(v) = env_var("image_emoji_base_url") {
+            config.image.emoji_base_url = v;
+        }
 
         // Apply CLI arguments (highest priority)
         if let Some(v) = cli.input {
             config.input = v;
         }
+        if let Some(v) = cli.from {
+            config.from = v;
+        }
+        if let Some(v) = cli.separator {
+            config.separator = v;
+        }
         if let Some(v) = cli.output {
             config.output = Some(v);
         }
+        if let Some(v) = cli.output_dir {
+            config.output_dir = Some(v);
+        }
+        if let Some(v) = cli.name_template {
+            config.name_template = v;
+        }
+        if let Some(v) = cli.jobs {
+            config.jobs = v;
+        }
+        if let Some(v) = cli.sequence_delay_ms {
+            config.sequence_delay_ms = v;
+        }
+        if let Some(v) = cli.manifest {
+            config.manifest = Some(v);
+        }
+        if let Some(v) = cli.bibliography {
+            config.bibliography = Some(v);
+        }
         if let Some(v) = cli.root {
             config.root = Some(v);
         }
         if let Some(v) = cli.strict {
-            config.strict = v;
+            config.strict = if v {
+                StrictConfig::all()
+            } else {
+                StrictConfig::default()
+            };
             sources.strict = ConfigSource::Cli;
         }
         if let Some(v) = cli.prosemirror {
             config.prosemirror = v;
         }
+        if let Some(v) = cli.collapsible_headings {
+            config.collapsible_headings = Some(v);
+        }
+        config.section = cli.section;
+        config.section_level = cli.section_level;
+        if let Some(v) = cli.footnotes {
+            config.footnotes = Some(v);
+        }
+        if let Some(v) = cli.heading_ids {
+            config.heading_ids = v;
+        }
+        if let Some(v) = cli.standalone {
+            config.standalone = v;
+        }
+        if let Some(v) = cli.email {
+            config.email = v;
+        }
+        if let Some(v) = cli.keep_frontmatter {
+            config.keep_frontmatter = v;
+        }
+        if let Some(v) = cli.captions {
+            config.captions = v;
+        }
+        if let Some(v) = cli.unfurl {
+            config.unfurl = v;
+        }
+        if let Some(v) = cli.notify {
+            config.notify = v;
+        }
 
         // Highlight CLI args
         if let Some(v) = cli.highlight.enable {
@@ -543,12 +2626,31 @@ impl Config {
             config.highlight.theme = v;
             sources.highlight_theme = ConfigSource::Cli;
         }
+        if let Some(v) = cli.highlight.appearance {
+            config.highlight.appearance = v;
+            sources.highlight_appearance = ConfigSource::Cli;
+        }
         if let Some(v) = cli.highlight.themes_dir {
             config.highlight.themes_dir = Some(v);
         }
         if let Some(v) = cli.highlight.syntaxes_dir {
             config.highlight.syntaxes_dir = Some(v);
         }
+        if let Some(v) = cli.highlight.themes_source {
+            config.highlight.themes_source = Some(v);
+        }
+        if let Some(v) = cli.highlight.syntaxes_source {
+            config.highlight.syntaxes_source = Some(v);
+        }
+        if let Some(v) = cli.highlight.check_contrast {
+            config.highlight.check_contrast = v;
+        }
+        if let Some(v) = cli.highlight.detect_language {
+            config.highlight.detect_language = v;
+        }
+        if let Some(v) = cli.highlight.badge {
+            config.highlight.badge = v;
+        }
 
         // Image CLI args
         if let Some(v) = cli.image.embed_local {
@@ -575,6 +2677,104 @@ impl Config {
             config.image.quality = v;
             sources.quality = ConfigSource::Cli;
         }
+        if let Some(v) = cli.image.format {
+            config.image.format = v;
+        }
+        if let Some(v) = cli.image.keep_exif {
+            config.image.keep_exif = v;
+        }
+        if let Some(v) = cli.image.cache {
+            config.image.cache_enable = v;
+            sources.image_cache = ConfigSource::Cli;
+        }
+        if let Some(v) = cli.image.fetch_timeout_ms {
+            config.image.fetch_timeout_ms = v;
+        }
+        if let Some(v) = cli.image.fetch_retries {
+            config.image.fetch_retries = v;
+        }
+        if let Some(v) = cli.image.fetch_max_bytes {
+            config.image.fetch_max_bytes = v;
+        }
+        // CLI headers add to (rather than replace) those from the config file
+        config.image.fetch_headers.extend(cli.image.fetch_headers);
+        // CLI rewrites are tried before those from the config file
+        config.image.rewrite.splice(0..0, cli.image.rewrite);
+        if let Some(v) = cli.image.fetch_netrc {
+            config.image.fetch_netrc = v;
+        }
+        if let Some(v) = &cli.image.fetch_ca_bundle {
+            config.image.fetch_ca_bundle = Some(v.clone());
+        }
+        if let Some(v) = cli.image.fetch_insecure {
+            config.image.fetch_insecure = v;
+        }
+        if let Some(v) = cli.image.native_inline_max_kb {
+            config.image.native_inline_max_kb = v;
+        }
+        if let Some(v) = cli.image.emoji_images {
+            config.image.emoji_images = v;
+        }
+        if let Some(v) = cli.image.mode {
+            config.image.mode = v;
+            sources.image_mode = ConfigSource::Cli;
+        }
+        if let Some(v) = cli.image.concurrency {
+            config.image.concurrency = v;
+        }
+
+        if let Some(v) = cli.list_style {
+            config.typography.list_style = v;
+        }
+        if let Some(v) = cli.quotes {
+            apply_quotes(&mut config.typography, v);
+        }
+        if let Some(v) = cli.smart_punctuation {
+            config.typography.smart_punctuation = v;
+        }
+        if let Some(v) = cli.headings {
+            config.typography.headings = v;
+        }
+        if let Some(v) = cli.native_table_max_rows {
+            config.native.table_max_rows = v;
+        }
+        if let Some(v) = cli.native_table_overflow {
+            config.native.table_overflow = v;
+        }
+        if let Some(v) = cli.html_code_style {
+            config.html.code_style = v;
+        }
+        if let Some(v) = cli.html_code_wrap {
+            config.html.code.wrap = Some(v);
+        }
+        if let Some(v) = cli.html_code_max_width {
+            config.html.code.max_width = (v > 0).then_some(v);
+        }
+        if let Some(v) = cli.clipboard_backend {
+            config.clipboard.backend = v;
+        }
+        if let Some(v) = cli.clipboard_hold {
+            config.clipboard.hold = v;
+        }
+        if let Some(v) = cli.clipboard_skip_unchanged {
+            config.clipboard.skip_unchanged = v;
+        }
+        if let Some(v) = cli.clipboard_verify {
+            config.clipboard.verify = v;
+        }
+        if let Some(v) = cli.clipboard_excerpt {
+            config.clipboard.excerpt = v;
+        }
+        if let Some(v) = cli.clipboard_max_size_mb {
+            config.clipboard.max_size_mb = v;
+        }
+        if let Some(v) = cli.history {
+            config.history.enable = v;
+        }
+
+        if !cli.outputs.is_empty() {
+            config.outputs = cli.outputs;
+        }
 
         (config, sources)
     }
@@ -586,11 +2786,44 @@ impl Config {
         } else {
             String::new()
         };
+        let formats_line = self
+            .formats
+            .as_ref()
+            .map(|formats| format!("formats = {:?}\n", formats))
+            .unwrap_or_default();
         let output_line = self
             .output
             .as_ref()
             .map(|p| format!("output = {:?}\n", p.display().to_string()))
             .unwrap_or_default();
+        let output_dir_line = self
+            .output_dir
+            .as_ref()
+            .map(|p| format!("output_dir = {:?}\n", p.display().to_string()))
+            .unwrap_or_default();
+        let name_template_line = if self.name_template != DEFAULT_NAME_TEMPLATE {
+            format!("name_template = {:?}\n", self.name_template)
+        } else {
+            String::new()
+        };
+        let manifest_line = self
+            .manifest
+            .as_ref()
+            .map(|p| format!("manifest = {:?}\n", p.display().to_string()))
+            .unwrap_or_default();
+        let bibliography_line = self
+            .bibliography
+            .as_ref()
+            .map(|p| format!("bibliography = {:?}\n", p.display().to_string()))
+            .unwrap_or_default();
+        let collapsible_headings_line = self
+            .collapsible_headings
+            .map(|level| format!("collapsible_headings = {}\n", level))
+            .unwrap_or_default();
+        let footnotes_line = self
+            .footnotes
+            .map(|placement| format!("footnotes = \"{}\"\n", placement))
+            .unwrap_or_default();
         let root_line = self
             .root
             .as_ref()
@@ -608,30 +2841,249 @@ impl Config {
             .as_ref()
             .map(|p| format!("syntaxes_dir = {:?}\n", p.display().to_string()))
             .unwrap_or_default();
+        let html_code_lines = self
+            .html
+            .code
+            .wrap
+            .map(|wrap| format!("wrap = {}\n", wrap))
+            .into_iter()
+            .chain(
+                self.html
+                    .code
+                    .max_width
+                    .map(|width| format!("max_width = {}\n", width)),
+            )
+            .collect::<String>();
+        let themes_source_line = self
+            .highlight
+            .themes_source
+            .as_ref()
+            .map(|s| format!("themes_source = {:?}\n", s))
+            .unwrap_or_default();
+        let syntaxes_source_line = self
+            .highlight
+            .syntaxes_source
+            .as_ref()
+            .map(|s| format!("syntaxes_source = {:?}\n", s))
+            .unwrap_or_default();
+        let theme_light_line = self
+            .highlight
+            .theme_light
+            .as_ref()
+            .map(|t| format!("theme_light = {:?}\n", t))
+            .unwrap_or_default();
+        let theme_dark_line = self
+            .highlight
+            .theme_dark
+            .as_ref()
+            .map(|t| format!("theme_dark = {:?}\n", t))
+            .unwrap_or_default();
+
+        let cache_dir_line = self
+            .image
+            .cache_dir
+            .as_ref()
+            .map(|p| format!("dir = {:?}\n", p.display().to_string()))
+            .unwrap_or_default();
+
+        let ca_bundle_line = self
+            .image
+            .fetch_ca_bundle
+            .as_ref()
+            .map(|p| format!("ca_bundle = {:?}\n", p.display().to_string()))
+            .unwrap_or_default();
+
+        let quotes_line = self
+            .typography
+            .quotes
+            .as_ref()
+            .map(|q| format!("quotes = {:?}\n", q))
+            .unwrap_or_default();
+
+        let outputs_tables: String = self
+            .outputs
+            .iter()
+            .map(|o| {
+                let path_line = o
+                    .path
+                    .as_ref()
+                    .map(|p| format!("\npath = {:?}", p.display().to_string()))
+                    .unwrap_or_default();
+                format!("\n\n[[outputs]]\nformat = {:?}{}", o.format, path_line)
+            })
+            .collect();
+
+        let rewrite_table = if self.image.rewrite.is_empty() {
+            String::new()
+        } else {
+            let rules: String = self
+                .image
+                .rewrite
+                .iter()
+                .map(|r| format!("{:?} = {:?}\n", r.pattern.as_str(), r.replacement))
+                .collect();
+            format!("\n[image.rewrite]\n{}", rules)
+        };
 
         format!(
-            "{input_line}{output_line}{root_line}strict = {strict}
+            "{input_line}{formats_line}{output_line}{output_dir_line}{name_template_line}{manifest_line}{bibliography_line}{root_line}{collapsible_headings_line}{footnotes_line}from = \"{from}\"
+separator = {separator:?}
+jobs = {jobs}
+sequence_delay_ms = {sequence_delay_ms}
+heading_ids = {heading_ids}
+standalone = {standalone}
+email = {email}
+keep_frontmatter = {keep_frontmatter}
+captions = {captions}
+unfurl = {unfurl}
+notify = {notify}
+
+[strict]
+images = {strict_images}
+languages = {strict_languages}
+themes = {strict_themes}
+nodes = {strict_nodes}
+output_size = {strict_output_size}
+clipboard = {strict_clipboard}
 
 [highlight]
 enable = {highlight_enable}
 theme = {highlight_theme:?}
-{themes_dir_line}{syntaxes_dir_line}
+{theme_light_line}{theme_dark_line}appearance = {highlight_appearance:?}
+check_contrast = {highlight_check_contrast}
+detect_language = {highlight_detect_language}
+badge = {highlight_badge:?}
+{themes_dir_line}{syntaxes_dir_line}{themes_source_line}{syntaxes_source_line}
+[image]
+mode = {image_mode:?}
+concurrency = {image_concurrency}
+
 [image.embed]
 local = {embed_local}
 remote = {embed_remote}
 optimize_local = {optimize_local}
 optimize_remote = {optimize_remote}
 max_dimension = {max_dimension}
-quality = {quality}",
-            strict = self.strict,
+quality = {quality}
+format = {image_format:?}
+keep_exif = {keep_exif}
+
+[image.cache]
+enable = {cache_enable}
+{cache_dir_line}ttl_hours = {cache_ttl_hours}
+max_size_mb = {cache_max_size_mb}
+
+[image.fetch]
+timeout_ms = {fetch_timeout_ms}
+retries = {fetch_retries}
+max_bytes = {fetch_max_bytes}
+netrc = {fetch_netrc}
+{ca_bundle_line}insecure = {fetch_insecure}
+
+[image.native]
+inline_max_kb = {native_inline_max_kb}
+thumbnail_dimension = {native_thumbnail_dimension}
+
+[image.emoji]
+images = {emoji_images:?}
+base_url = {emoji_base_url:?}
+{rewrite_table}
+[native]
+table_max_rows = {native_table_max_rows}
+table_overflow = {native_table_overflow:?}
+
+[html.code]
+style = {html_code_style:?}
+{html_code_lines}
+[clipboard]
+backend = {clipboard_backend:?}
+order = [{clipboard_order}]
+hold = {clipboard_hold}
+skip_unchanged = {clipboard_skip_unchanged}
+verify = {clipboard_verify}
+dump_dir = {clipboard_dump_dir:?}
+excerpt = {clipboard_excerpt}
+max_size_mb = {clipboard_max_size_mb}
+
+[history]
+enable = {history_enable}
+max_entries = {history_max_entries}
+max_size_mb = {history_max_size_mb}
+
+[typography]
+list_style = {list_style:?}
+smart_punctuation = {smart_punctuation}
+headings = {headings:?}
+{quotes_line}{outputs_tables}",
+            strict_images = self.strict.images,
+            strict_languages = self.strict.languages,
+            strict_themes = self.strict.themes,
+            strict_nodes = self.strict.nodes,
+            strict_output_size = self.strict.output_size,
+            strict_clipboard = self.strict.clipboard,
+            jobs = self.jobs,
+            sequence_delay_ms = self.sequence_delay_ms,
+            smart_punctuation = self.typography.smart_punctuation,
+            headings = self.typography.headings.to_string(),
+            native_table_max_rows = self.native.table_max_rows,
+            native_table_overflow = self.native.table_overflow.to_string(),
+            heading_ids = self.heading_ids,
+            standalone = self.standalone,
+            email = self.email,
+            from = self.from,
+            separator = self.separator.to_string(),
+            keep_frontmatter = self.keep_frontmatter,
+            captions = self.captions,
+            unfurl = self.unfurl,
+            notify = self.notify,
             highlight_enable = self.highlight.enable,
             highlight_theme = self.highlight.theme,
+            highlight_appearance = self.highlight.appearance.to_string(),
+            highlight_check_contrast = self.highlight.check_contrast,
+            highlight_detect_language = self.highlight.detect_language,
+            highlight_badge = self.highlight.badge.to_string(),
+            html_code_style = self.html.code_style.to_string(),
             embed_local = self.image.embed_local,
             embed_remote = self.image.embed_remote,
             optimize_local = self.image.optimize_local,
             optimize_remote = self.image.optimize_remote,
             max_dimension = self.image.max_dimension,
             quality = self.image.quality,
+            image_format = self.image.format.to_string(),
+            keep_exif = self.image.keep_exif,
+            cache_enable = self.image.cache_enable,
+            cache_ttl_hours = self.image.cache_ttl_hours,
+            cache_max_size_mb = self.image.cache_max_size_mb,
+            fetch_timeout_ms = self.image.fetch_timeout_ms,
+            fetch_retries = self.image.fetch_retries,
+            fetch_max_bytes = self.image.fetch_max_bytes,
+            fetch_netrc = self.image.fetch_netrc,
+            fetch_insecure = self.image.fetch_insecure,
+            native_inline_max_kb = self.image.native_inline_max_kb,
+            native_thumbnail_dimension = self.image.native_thumbnail_dimension,
+            emoji_images = self.image.emoji_images.to_string(),
+            emoji_base_url = self.image.emoji_base_url,
+            image_mode = self.image.mode.to_string(),
+            image_concurrency = self.image.concurrency,
+            list_style = self.typography.list_style.to_string(),
+            clipboard_backend = self.clipboard.backend.to_string(),
+            clipboard_order = self
+                .clipboard
+                .order
+                .iter()
+                .map(|kind| format!("{:?}", kind.to_string()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            clipboard_hold = self.clipboard.hold,
+            clipboard_skip_unchanged = self.clipboard.skip_unchanged,
+            clipboard_verify = self.clipboard.verify,
+            clipboard_dump_dir = self.clipboard.dump_dir.display().to_string(),
+            clipboard_excerpt = self.clipboard.excerpt,
+            clipboard_max_size_mb = self.clipboard.max_size_mb,
+            history_enable = self.history.enable,
+            history_max_entries = self.history.max_entries,
+            history_max_size_mb = self.history.max_size_mb,
+            rewrite_table = rewrite_table,
         )
     }
 }
@@ -645,24 +3097,81 @@ mod tests {
     fn empty_cli_args() -> CliArgs {
         CliArgs {
             input: None,
+            from: None,
+            separator: None,
             output: None,
+            output_dir: None,
+            name_template: None,
+            jobs: None,
+            sequence_delay_ms: None,
+            manifest: None,
+            bibliography: None,
             root: None,
             strict: None,
             prosemirror: None,
+            collapsible_headings: None,
+            section: None,
+            section_level: None,
+            heading_ids: None,
+            standalone: None,
+            email: None,
+            keep_frontmatter: None,
+            footnotes: None,
+            captions: None,
+            unfurl: None,
+            notify: None,
             highlight: CliHighlightArgs {
                 enable: None,
                 theme: None,
+                appearance: None,
                 themes_dir: None,
                 syntaxes_dir: None,
+                themes_source: None,
+                syntaxes_source: None,
+                check_contrast: None,
+                detect_language: None,
+                badge: None,
             },
             image: CliImageArgs {
+                mode: None,
                 embed_local: None,
                 embed_remote: None,
                 optimize_local: None,
                 optimize_remote: None,
                 max_dimension: None,
                 quality: None,
+                format: None,
+                keep_exif: None,
+                cache: None,
+                fetch_timeout_ms: None,
+                fetch_retries: None,
+                fetch_max_bytes: None,
+                fetch_headers: Vec::new(),
+                fetch_netrc: None,
+                rewrite: Vec::new(),
+                fetch_ca_bundle: None,
+                fetch_insecure: None,
+                native_inline_max_kb: None,
+                emoji_images: None,
+                concurrency: None,
             },
+            list_style: None,
+            quotes: None,
+            smart_punctuation: None,
+            headings: None,
+            native_table_max_rows: None,
+            native_table_overflow: None,
+            html_code_style: None,
+            html_code_wrap: None,
+            html_code_max_width: None,
+            clipboard_backend: None,
+            clipboard_hold: None,
+            clipboard_skip_unchanged: None,
+            clipboard_verify: None,
+            clipboard_excerpt: None,
+            clipboard_max_size_mb: None,
+            history: None,
+            outputs: Vec::new(),
         }
     }
 
@@ -672,7 +3181,7 @@ mod tests {
         assert_eq!(config.input, PathBuf::from("-"));
         assert!(config.output.is_none());
         assert!(config.root.is_none());
-        assert!(!config.strict);
+        assert_eq!(config.strict, StrictConfig::default());
         assert!(config.highlight.enable);
         assert_eq!(config.highlight.theme, "base16-ocean.dark");
         assert!(config.image.embed_local);
@@ -696,6 +3205,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_load_config_file_with_fallbacks() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[highlight.fallbacks]").unwrap();
+        writeln!(file, "Templ = \"go\"").unwrap();
+
+        let (config, _sources) = Config::build(empty_cli_args(), Some(config_path));
+        assert_eq!(
+            config.highlight.fallbacks.get("templ"),
+            Some(&"go".to_string())
+        );
+        // Defaults are kept alongside file entries
+        assert_eq!(
+            config.highlight.fallbacks.get("svelte"),
+            Some(&"html".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_bool() {
         assert_eq!(parse_bool("true"), Some(true));
@@ -723,6 +3253,49 @@ mod tests {
         assert_eq!(config.effective_theme(), "custom-theme");
     }
 
+    #[test]
+    fn test_highlight_config_theme_for_appearance() {
+        let config = HighlightConfig {
+            theme: "fallback".to_string(),
+            theme_light: Some("InspiredGitHub".to_string()),
+            theme_dark: Some("base16-ocean.dark".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.theme_for(Some(Appearance::Light)), "InspiredGitHub");
+        assert_eq!(
+            config.theme_for(Some(Appearance::Dark)),
+            "base16-ocean.dark"
+        );
+        assert_eq!(config.theme_for(None), "fallback");
+    }
+
+    #[test]
+    fn test_highlight_config_explicit_appearance() {
+        let config = HighlightConfig {
+            theme_light: Some("light-theme".to_string()),
+            appearance: Appearance::Light,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_theme(), "light-theme");
+
+        // Missing variant falls back to the base theme
+        let config = HighlightConfig {
+            theme: "base".to_string(),
+            theme_light: Some("light-theme".to_string()),
+            appearance: Appearance::Dark,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_theme(), "base");
+    }
+
+    #[test]
+    fn test_appearance_parse() {
+        assert_eq!(Appearance::parse("auto"), Some(Appearance::Auto));
+        assert_eq!(Appearance::parse("Dark"), Some(Appearance::Dark));
+        assert_eq!(Appearance::parse("light"), Some(Appearance::Light));
+        assert_eq!(Appearance::parse("dim"), None);
+    }
+
     #[test]
     fn test_load_config_file_valid() {
         let temp_dir = TempDir::new().unwrap();
@@ -740,13 +3313,53 @@ mod tests {
         let config = load_config_file(&config_path);
         assert!(config.is_some());
         let config = config.unwrap();
-        assert_eq!(config.strict, Some(true));
+        assert!(matches!(config.strict, Some(FileStrict::All(true))));
         assert_eq!(config.highlight.enable, Some(false));
         assert_eq!(config.highlight.theme, Some("my-theme".to_string()));
         assert_eq!(config.image.embed.local, Some(true));
         assert_eq!(config.image.embed.remote, Some(true));
     }
 
+    #[test]
+    fn test_expand_env_vars() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(expand_env_vars("${PATH}/x"), format!("{}/x", path));
+        assert_eq!(
+            expand_env_vars("${MDCOPY_TEST_UNSET_VAR:-fallback}"),
+            "fallback"
+        );
+        assert_eq!(expand_env_vars("a${MDCOPY_TEST_UNSET_VAR}b"), "ab");
+        assert_eq!(expand_env_vars("$${PATH} costs $5"), "${PATH} costs $5");
+        assert_eq!(expand_env_vars("${unterminated"), "${unterminated");
+    }
+
+    #[test]
+    fn test_load_config_file_expands_env_vars() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            "root = \"${MDCOPY_TEST_UNSET_VAR:-/srv/docs}\"\n\
+             [image.auth.\"github.com\"]\n\
+             authorization = \"token ${MDCOPY_TEST_UNSET_VAR:-abc}\"\n",
+        )
+        .unwrap();
+
+        let config = load_config_file(&config_path).unwrap();
+        assert_eq!(config.root.as_deref(), Some("/srv/docs"));
+        assert_eq!(
+            config.image.auth["github.com"]["authorization"],
+            "token abc"
+        );
+
+        // Project configs are taken literally
+        let config = load_project_config_file(&config_path).unwrap();
+        assert_eq!(
+            config.root.as_deref(),
+            Some("${MDCOPY_TEST_UNSET_VAR:-/srv/docs}")
+        );
+    }
+
     #[test]
     fn test_load_config_file_not_found() {
         let config = load_config_file(&PathBuf::from("/nonexistent/config.toml"));
@@ -795,7 +3408,7 @@ mod tests {
         assert!(config.output.is_none());
         assert!(config.image.embed_local);
         assert!(!config.image.embed_remote);
-        assert!(!config.strict);
+        assert_eq!(config.strict, StrictConfig::default());
         assert!(config.highlight.enable);
     }
 
@@ -803,45 +3416,175 @@ mod tests {
     fn test_config_build_cli_overrides() {
         let cli = CliArgs {
             input: Some(PathBuf::from("input.md")),
+            from: Some(InputFormat::Html),
+            separator: Some(Separator::PageBreak),
             output: Some(PathBuf::from("output.html")),
+            output_dir: Some(PathBuf::from("out")),
+            name_template: Some("{dir}/{stem}.{ext}".to_string()),
+            jobs: Some(3),
+            sequence_delay_ms: Some(1500),
+            manifest: Some(PathBuf::from("manifest.toml")),
+            bibliography: Some(PathBuf::from("refs.bib")),
             root: Some(PathBuf::from("/custom/root")),
             strict: Some(true),
             prosemirror: None,
+            collapsible_headings: Some(3),
+            section: Some("Release Notes".to_string()),
+            section_level: Some(2),
+            heading_ids: Some(false),
+            standalone: Some(true),
+            email: Some(true),
+            keep_frontmatter: Some(true),
+            footnotes: Some(FootnotePlacement::Section),
+            captions: Some(true),
+            unfurl: Some(true),
+            notify: Some(true),
             highlight: CliHighlightArgs {
                 enable: Some(false),
                 theme: Some("custom".to_string()),
+                appearance: None,
                 themes_dir: Some(PathBuf::from("/themes")),
                 syntaxes_dir: Some(PathBuf::from("/syntaxes")),
+                themes_source: Some("https://example.com/themes.zip".to_string()),
+                syntaxes_source: Some("/bundles/syntaxes.tar.gz".to_string()),
+                check_contrast: Some(true),
+                detect_language: Some(true),
+                badge: Some(CodeBadge::Header),
             },
             image: CliImageArgs {
+                mode: Some(ImageMode::LinksOnly),
                 embed_local: Some(true),
                 embed_remote: Some(true),
                 optimize_local: Some(false),
                 optimize_remote: Some(false),
                 max_dimension: Some(800),
                 quality: Some(75),
+                format: Some(EmbedFormat::Webp),
+                keep_exif: Some(true),
+                cache: Some(true),
+                fetch_timeout_ms: Some(500),
+                fetch_retries: Some(0),
+                fetch_max_bytes: Some(1024),
+                fetch_headers: vec![ImageHeader::parse("X-Token: abc").unwrap()],
+                fetch_netrc: Some(true),
+                rewrite: vec![ImageRewrite::parse("^/img/=https://cdn.test/").unwrap()],
+                fetch_ca_bundle: Some(PathBuf::from("/etc/corp-ca.pem")),
+                fetch_insecure: Some(true),
+                native_inline_max_kb: Some(512),
+                emoji_images: Some(EmojiImages::Rtf),
+                concurrency: Some(1),
             },
+            list_style: Some(ListStyle::LowerAlpha),
+            quotes: Some("de".to_string()),
+            smart_punctuation: Some(true),
+            headings: Some(HeadingCase::Sentence),
+            native_table_max_rows: Some(50),
+            native_table_overflow: Some(TableOverflow::Split),
+            html_code_style: Some(CodeStyle::Table),
+            html_code_wrap: Some(false),
+            html_code_max_width: Some(100),
+            clipboard_backend: Some(ClipboardBackendKind::Wayland),
+            clipboard_hold: Some(true),
+            clipboard_skip_unchanged: Some(false),
+            clipboard_verify: Some(true),
+            clipboard_excerpt: Some(40),
+            clipboard_max_size_mb: Some(0),
+            history: Some(true),
+            outputs: vec![OutputSpec::parse("markdown=notes.md")],
         };
 
         let (config, sources) = Config::build(cli, None);
 
         assert_eq!(config.input, PathBuf::from("input.md"));
+        assert_eq!(config.from, InputFormat::Html);
+        assert_eq!(config.separator, Separator::PageBreak);
         assert_eq!(config.output, Some(PathBuf::from("output.html")));
         assert_eq!(config.root, Some(PathBuf::from("/custom/root")));
+        assert!(config.notify);
+        assert_eq!(config.collapsible_headings, Some(3));
+        assert_eq!(config.footnotes, Some(FootnotePlacement::Section));
+        assert!(config.captions);
+        assert!(!config.heading_ids);
+        assert!(config.standalone);
+        assert!(config.email);
+        assert!(config.keep_frontmatter);
+        assert_eq!(config.section.as_deref(), Some("Release Notes"));
+        assert_eq!(config.section_level, Some(2));
+        assert!(config.unfurl);
+        assert_eq!(config.clipboard.backend, ClipboardBackendKind::Wayland);
+        assert!(config.clipboard.hold);
+        assert!(!config.clipboard.skip_unchanged);
+        assert!(config.clipboard.verify);
+        assert_eq!(config.clipboard.excerpt, 40);
+        assert_eq!(config.clipboard.max_size_mb, 0);
+        assert!(config.history.enable);
         assert!(config.image.embed_local);
         assert!(config.image.embed_remote);
-        assert!(config.strict);
+        assert_eq!(config.strict, StrictConfig::all());
         assert!(!config.highlight.enable);
         assert_eq!(config.highlight.theme, "custom");
+        assert!(config.highlight.check_contrast);
+        assert!(config.highlight.detect_language);
+        assert_eq!(config.highlight.badge, CodeBadge::Header);
         assert_eq!(config.highlight.themes_dir, Some(PathBuf::from("/themes")));
         assert_eq!(
             config.highlight.syntaxes_dir,
             Some(PathBuf::from("/syntaxes"))
         );
+        assert_eq!(
+            config.highlight.themes_source.as_deref(),
+            Some("https://example.com/themes.zip")
+        );
+        assert_eq!(
+            config.highlight.syntaxes_source.as_deref(),
+            Some("/bundles/syntaxes.tar.gz")
+        );
         assert!(!config.image.optimize_local);
         assert!(!config.image.optimize_remote);
         assert_eq!(config.image.max_dimension, 800);
         assert_eq!(config.image.quality, 75);
+        assert_eq!(config.image.format, EmbedFormat::Webp);
+        assert!(config.image.keep_exif);
+        assert!(config.image.cache_enable);
+        assert_eq!(config.image.native_inline_max_kb, 512);
+        assert_eq!(config.image.emoji_images, EmojiImages::Rtf);
+        assert_eq!(config.image.fetch_timeout_ms, 500);
+        assert_eq!(config.image.fetch_retries, 0);
+        assert_eq!(config.image.fetch_max_bytes, 1024);
+        assert_eq!(config.image.fetch_headers.len(), 1);
+        assert!(config.image.fetch_netrc);
+        assert_eq!(config.image.rewrite.len(), 1);
+        assert_eq!(
+            config.image.fetch_ca_bundle,
+            Some(PathBuf::from("/etc/corp-ca.pem"))
+        );
+        assert!(config.image.fetch_insecure);
+        assert_eq!(config.image.concurrency, 1);
+        assert_eq!(config.image.mode, ImageMode::LinksOnly);
+        assert_eq!(config.typography.list_style, ListStyle::LowerAlpha);
+        assert_eq!(config.typography.headings, HeadingCase::Sentence);
+        assert_eq!(config.native.table_max_rows, 50);
+        assert_eq!(config.native.table_overflow, TableOverflow::Split);
+        assert_eq!(config.html.code_style, CodeStyle::Table);
+        assert_eq!(
+            config.html.code,
+            CodeLayout {
+                wrap: Some(false),
+                max_width: Some(100),
+            }
+        );
+        assert_eq!(
+            config.typography.quote_style(),
+            QuoteStyle::for_locale("de")
+        );
+        assert!(config.typography.smart_punctuation);
+        assert_eq!(config.output_dir, Some(PathBuf::from("out")));
+        assert_eq!(config.name_template, "{dir}/{stem}.{ext}");
+        assert_eq!(config.jobs, 3);
+        assert_eq!(config.sequence_delay_ms, 1500);
+        assert_eq!(config.manifest, Some(PathBuf::from("manifest.toml")));
+        assert_eq!(config.bibliography, Some(PathBuf::from("refs.bib")));
+        assert_eq!(config.outputs.len(), 1);
 
         // Verify sources are tracked as CLI
         assert!(matches!(sources.embed_local, ConfigSource::Cli));
@@ -858,13 +3601,17 @@ mod tests {
         writeln!(file, "strict = true").unwrap();
         writeln!(file, "[highlight]").unwrap();
         writeln!(file, "theme = \"file-theme\"").unwrap();
+        writeln!(file, "theme_dark = \"file-dark\"").unwrap();
+        writeln!(file, "appearance = \"dark\"").unwrap();
 
         let cli = empty_cli_args();
         let (config, sources) = Config::build(cli, Some(config_path.clone()));
 
         assert_eq!(config.input, PathBuf::from("from-file.md"));
-        assert!(config.strict);
+        assert_eq!(config.strict, StrictConfig::all());
         assert_eq!(config.highlight.theme, "file-theme");
+        assert_eq!(config.highlight.appearance, Appearance::Dark);
+        assert_eq!(config.highlight.effective_theme(), "file-dark");
 
         // Verify sources are tracked as file
         assert!(matches!(sources.strict, ConfigSource::File(ref p) if p == &config_path));
@@ -890,12 +3637,434 @@ mod tests {
 
         // CLI should override file
         assert_eq!(config.input, PathBuf::from("from-cli.md"));
-        assert!(!config.strict);
+        assert_eq!(config.strict, StrictConfig::default());
 
         // Verify CLI overrode file source
         assert!(matches!(sources.strict, ConfigSource::Cli));
     }
 
+    #[test]
+    fn test_config_build_image_cache_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[image.cache]").unwrap();
+        writeln!(file, "enable = true").unwrap();
+        writeln!(file, "dir = \"/tmp/mdcopy-images\"").unwrap();
+        writeln!(file, "ttl_hours = 2").unwrap();
+        writeln!(file, "max_size_mb = 10").unwrap();
+
+        let (config, sources) = Config::build(empty_cli_args(), Some(config_path));
+        assert!(config.image.cache_enable);
+        assert_eq!(
+            config.image.get_cache_dir(),
+            Some(PathBuf::from("/tmp/mdcopy-images"))
+        );
+        assert_eq!(config.image.cache_ttl_hours, 2);
+        assert_eq!(config.image.cache_max_size_mb, 10);
+        assert!(matches!(sources.image_cache, ConfigSource::File(_)));
+    }
+
+    #[test]
+    fn test_config_build_image_fetch_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[image.fetch]").unwrap();
+        writeln!(file, "timeout_ms = 2500").unwrap();
+        writeln!(file, "retries = 5").unwrap();
+        writeln!(file, "max_bytes = 1048576").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path));
+        assert_eq!(config.image.fetch_timeout_ms, 2500);
+        assert_eq!(config.image.fetch_retries, 5);
+        assert_eq!(config.image.fetch_max_bytes, 1048576);
+    }
+
+    #[test]
+    fn test_image_header_parse() {
+        let header = ImageHeader::parse("GitHub.com=Authorization: token abc").unwrap();
+        assert_eq!(header.domain.as_deref(), Some("github.com"));
+        assert_eq!(header.name, "Authorization");
+        assert_eq!(header.value, "token abc");
+        assert!(header.matches_host("github.com"));
+        assert!(header.matches_host("raw.github.com"));
+        assert!(!header.matches_host("notgithub.com"));
+
+        let header = ImageHeader::parse("Cookie: session=1").unwrap();
+        assert_eq!(header.domain, None);
+        assert_eq!(header.value, "session=1");
+        assert!(header.matches_host("example.com"));
+
+        assert!(ImageHeader::parse("no separator").is_none());
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir_all(project.join("docs/guide")).unwrap();
+        std::fs::write(project.join(PROJECT_CONFIG_NAME), "").unwrap();
+
+        let input = project.join("docs/guide/intro.md");
+        assert_eq!(
+            find_project_config(Some(&input)),
+            Some(project.join(PROJECT_CONFIG_NAME))
+        );
+        assert_eq!(
+            find_project_config(Some(&temp_dir.path().join("a.md"))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_config_build_project_config_overrides_global() {
+        let temp_dir = TempDir::new().unwrap();
+        let global_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &global_path,
+            "strict = false\n[highlight]\ntheme = \"global-theme\"\n",
+        )
+        .unwrap();
+
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir_all(project.join("docs")).unwrap();
+        std::fs::write(
+            project.join(PROJECT_CONFIG_NAME),
+            "strict = true\nroot = \"assets\"\n",
+        )
+        .unwrap();
+
+        let cli = CliArgs {
+            input: Some(project.join("docs/a.md")),
+            ..empty_cli_args()
+        };
+        let (config, sources) = Config::build(cli, Some(global_path.clone()));
+
+        assert_eq!(config.strict, StrictConfig::all());
+        assert_eq!(config.highlight.theme, "global-theme");
+        // Relative paths resolve against the project directory
+        assert_eq!(config.root, Some(project.join("assets")));
+        assert!(
+            matches!(&sources.strict, ConfigSource::Project(p) if *p == project.join(PROJECT_CONFIG_NAME))
+        );
+        assert!(matches!(&sources.highlight_theme, ConfigSource::File(p) if *p == global_path));
+    }
+
+    #[test]
+    fn test_config_build_image_auth_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[image.auth.\"github.com\"]").unwrap();
+        writeln!(file, "authorization = \"token abc\"").unwrap();
+
+        let cli = CliArgs {
+            image: CliImageArgs {
+                fetch_headers: vec![ImageHeader::parse("wiki.corp=Cookie: s=1").unwrap()],
+                ..empty_cli_args().image
+            },
+            ..empty_cli_args()
+        };
+        let (config, _) = Config::build(cli, Some(config_path));
+        assert_eq!(
+            config.image.fetch_headers,
+            vec![
+                ImageHeader {
+                    domain: Some("github.com".to_string()),
+                    name: "authorization".to_string(),
+                    value: "token abc".to_string(),
+                },
+                ImageHeader::parse("wiki.corp=Cookie: s=1").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_build_image_rewrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[image.rewrite]").unwrap();
+        writeln!(file, "\"^/assets/(.*)\" = \"https://cdn.test/$1\"").unwrap();
+        writeln!(file, "\"^/\" = \"https://intranet.test/\"").unwrap();
+        writeln!(file, "\"(\" = \"invalid\"").unwrap();
+
+        let cli = CliArgs {
+            image: CliImageArgs {
+                rewrite: vec![ImageRewrite::parse("^/assets/logo=/tmp/logo").unwrap()],
+                ..empty_cli_args().image
+            },
+            ..empty_cli_args()
+        };
+        let (config, _) = Config::build(cli, Some(config_path));
+        let rules = &config.image.rewrite;
+        assert_eq!(rules.len(), 3);
+        // The CLI rule first, then the file's in the order written
+        assert_eq!(
+            ImageRewrite::apply(rules, "/assets/logo.png").as_deref(),
+            Some("/tmp/logo.png")
+        );
+        assert_eq!(
+            ImageRewrite::apply(rules, "/assets/a/b.png").as_deref(),
+            Some("https://cdn.test/a/b.png")
+        );
+        assert_eq!(
+            ImageRewrite::apply(rules, "/team.jpg").as_deref(),
+            Some("https://intranet.test/team.jpg")
+        );
+        assert_eq!(ImageRewrite::apply(rules, "local.png"), None);
+        assert!(ImageRewrite::parse("no separator").is_err());
+
+        let toml = config.to_toml();
+        assert!(toml.contains("[image.rewrite]\n\"^/assets/logo\" = \"/tmp/logo\"\n"));
+    }
+
+    #[test]
+    fn test_config_build_native_images_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[image.native]").unwrap();
+        writeln!(file, "inline_max_kb = 256").unwrap();
+        writeln!(file, "thumbnail_dimension = 200").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path));
+        assert_eq!(config.image.native_inline_max_kb, 256);
+        assert_eq!(config.image.native_thumbnail_dimension, 200);
+    }
+
+    #[test]
+    fn test_config_build_html_code_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            "[html.code]\nstyle = \"pre-classes\"\nwrap = true\nmax_width = 80\n",
+        )
+        .unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path));
+        assert_eq!(config.html.code_style, CodeStyle::PreClasses);
+        assert_eq!(config.html.code.wrap, Some(true));
+        assert_eq!(config.html.code.max_width, Some(80));
+        assert!(
+            config
+                .to_toml()
+                .contains("[html.code]\nstyle = \"pre-classes\"\nwrap = true\nmax_width = 80\n")
+        );
+        assert!(
+            Config::default()
+                .to_toml()
+                .contains("[html.code]\nstyle = \"div-inline\"\n\n[clipboard]")
+        );
+    }
+
+    #[test]
+    fn test_code_style_parse() {
+        assert_eq!(CodeStyle::parse("Table"), Some(CodeStyle::Table));
+        assert_eq!(CodeStyle::parse("pre-classes"), Some(CodeStyle::PreClasses));
+        assert_eq!(CodeStyle::parse("span"), None);
+        assert_eq!(CodeStyle::DivInline.to_string(), "div-inline");
+    }
+
+    #[test]
+    fn test_image_mode_parse() {
+        assert_eq!(ImageMode::parse("embed"), Some(ImageMode::Embed));
+        assert_eq!(ImageMode::parse("Links-Only"), Some(ImageMode::LinksOnly));
+        assert_eq!(ImageMode::parse("inline"), None);
+        assert_eq!(ImageMode::LinksOnly.to_string(), "links-only");
+    }
+
+    #[test]
+    fn test_embed_format_parse() {
+        assert_eq!(EmbedFormat::parse("WebP"), Some(EmbedFormat::Webp));
+        assert_eq!(EmbedFormat::parse("jpg"), Some(EmbedFormat::Jpeg));
+        assert_eq!(EmbedFormat::parse("avif"), None);
+        assert_eq!(EmbedFormat::Auto.to_string(), "auto");
+    }
+
+    #[test]
+    fn test_emoji_images_parse() {
+        assert_eq!(EmojiImages::parse("RTF"), Some(EmojiImages::Rtf));
+        assert_eq!(EmojiImages::parse("off"), Some(EmojiImages::None));
+        assert_eq!(EmojiImages::parse("html"), None);
+        assert_eq!(EmojiImages::All.to_string(), "all");
+    }
+
+    #[test]
+    fn test_clipboard_order_parse() {
+        assert_eq!(
+            parse_clipboard_order(&["wl-copy", " file", "auto", "clippy"]),
+            vec![ClipboardBackendKind::Wayland, ClipboardBackendKind::File]
+        );
+        assert_eq!(ClipboardBackendKind::Osc52.to_string(), "osc52");
+    }
+
+    #[test]
+    fn test_list_style_parse() {
+        assert_eq!(ListStyle::parse("1."), Some(ListStyle::Decimal));
+        assert_eq!(ListStyle::parse("1)"), Some(ListStyle::DecimalParen));
+        assert_eq!(ListStyle::parse("a."), Some(ListStyle::LowerAlpha));
+        assert_eq!(ListStyle::parse("lower-roman"), Some(ListStyle::LowerRoman));
+        assert_eq!(ListStyle::parse("x"), None);
+        assert_eq!(ListStyle::DecimalParen.to_string(), "1)");
+    }
+
+    #[test]
+    fn test_heading_case_parse() {
+        assert_eq!(HeadingCase::parse("title-case"), Some(HeadingCase::Title));
+        assert_eq!(HeadingCase::parse("Sentence"), Some(HeadingCase::Sentence));
+        assert_eq!(HeadingCase::parse("none"), Some(HeadingCase::Keep));
+        assert_eq!(HeadingCase::parse("upper"), None);
+        assert_eq!(TableOverflow::parse("Chunk"), Some(TableOverflow::Split));
+        assert_eq!(TableOverflow::parse("grid"), Some(TableOverflow::Grid));
+        assert_eq!(TableOverflow::parse("drop"), None);
+        assert_eq!(HeadingCase::Sentence.to_string(), "sentence-case");
+    }
+
+    #[test]
+    fn test_quote_style_parse() {
+        let de = QuoteStyle::parse("de-DE").unwrap();
+        assert_eq!(de.double_open, "„");
+        assert_eq!(de.double_close, "“");
+
+        // Region-specific overrides
+        assert_eq!(QuoteStyle::parse("de-CH").unwrap().double_open, "«");
+
+        // Four literal marks
+        let custom = QuoteStyle::parse("»«›‹").unwrap();
+        assert_eq!(custom.double_open, "»");
+        assert_eq!(custom.single_close, "‹");
+
+        assert!(QuoteStyle::parse("klingon").is_none());
+    }
+
+    #[test]
+    fn test_config_build_typography_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[typography]").unwrap();
+        writeln!(file, "list_style = \"i.\"").unwrap();
+        writeln!(file, "quotes = \"fr\"").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path.clone()));
+        assert_eq!(config.typography.list_style, ListStyle::LowerRoman);
+        assert_eq!(config.typography.quotes.as_deref(), Some("fr"));
+
+        // CLI can turn quote substitution back off
+        let cli = CliArgs {
+            quotes: Some("none".to_string()),
+            ..empty_cli_args()
+        };
+        let (config, _) = Config::build(cli, Some(config_path));
+        assert!(config.typography.quote_style().is_none());
+    }
+
+    #[test]
+    fn test_output_spec_parse() {
+        assert_eq!(
+            OutputSpec::parse("markdown=notes.md"),
+            OutputSpec {
+                format: "markdown".to_string(),
+                path: Some(PathBuf::from("notes.md")),
+            }
+        );
+        assert_eq!(
+            OutputSpec::parse("html,rtf"),
+            OutputSpec {
+                format: "html,rtf".to_string(),
+                path: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_build_outputs_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut file = std::fs::File::create(&config_path).unwrap();
+        writeln!(file, "[[outputs]]").unwrap();
+        writeln!(file, "format = \"html\"").unwrap();
+        writeln!(file, "path = \"out.html\"").unwrap();
+        writeln!(file, "[[outputs]]").unwrap();
+        writeln!(file, "format = \"markdown\"").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path.clone()));
+        assert_eq!(config.outputs.len(), 2);
+        assert_eq!(config.outputs[0].path, Some(PathBuf::from("out.html")));
+        assert_eq!(config.outputs[1].path, None);
+
+        // CLI profiles replace the file's list
+        let cli = CliArgs {
+            outputs: vec![OutputSpec::parse("rtf=-")],
+            ..empty_cli_args()
+        };
+        let (config, _) = Config::build(cli, Some(config_path));
+        assert_eq!(config.outputs, vec![OutputSpec::parse("rtf=-")]);
+    }
+
+    #[test]
+    fn test_config_build_formats_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, "formats = [\"html\", \"native\"]\n").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path));
+        assert_eq!(
+            config.formats,
+            Some(vec!["html".to_string(), "native".to_string()])
+        );
+        assert!(
+            config
+                .to_toml()
+                .contains("formats = [\"html\", \"native\"]\n")
+        );
+    }
+
+    #[test]
+    fn test_config_build_strict_table() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, "[strict]\nimages = true\nnodes = true\n").unwrap();
+
+        let (config, _) = Config::build(empty_cli_args(), Some(config_path.clone()));
+        let expected = StrictConfig {
+            images: true,
+            nodes: true,
+            ..Default::default()
+        };
+        assert_eq!(config.strict, expected);
+        assert_eq!(config.strict.to_string(), "images,nodes");
+
+        // --no-strict turns every category off again
+        let cli = CliArgs {
+            strict: Some(false),
+            ..empty_cli_args()
+        };
+        let (config, _) = Config::build(cli, Some(config_path));
+        assert_eq!(config.strict.to_string(), "off");
+
+        assert_eq!(StrictConfig::parse("yes"), Some(StrictConfig::all()));
+        assert_eq!(
+            StrictConfig::parse("languages, themes"),
+            Some(StrictConfig {
+                languages: true,
+                themes: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(StrictConfig::parse("images,fonts"), None);
+    }
+
     #[test]
     fn test_highlight_config_get_themes_dir_custom() {
         let config = HighlightConfig {
@@ -0,0 +1,317 @@
+//! The config file itself: the commented template `mdcopy config init`
+//! writes and the checks behind `mdcopy config validate`.
+
+use crate::bundle;
+use crate::config::{FileConfig, PROJECT_CONFIG_NAME, default_config_dir, expand_env_vars};
+use crate::highlight::HighlightContext;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue};
+
+/// Every setting with its default value, commented out
+pub const TEMPLATE: &str = include_str!("default_config.toml");
+
+/// A problem in a config file, at a 1-based line when it can be placed
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Check the config file at `path`. Relative paths in a project config
+/// resolve against its directory, and environment variables are expanded
+/// only in other configs, as when they're loaded.
+pub fn validate(path: &Path) -> io::Result<Vec<Issue>> {
+    let content = fs::read_to_string(path)?;
+    let project_dir = path
+        .file_name()
+        .is_some_and(|name| name == PROJECT_CONFIG_NAME)
+        .then(|| path.parent().unwrap_or(Path::new("")));
+    Ok(check(&content, project_dir))
+}
+
+/// TOML syntax errors, unknown keys, values of the wrong type, and themes,
+/// directories and files named in `content` that don't exist, in line order
+pub fn check(content: &str, project_dir: Option<&Path>) -> Vec<Issue> {
+    let root = match DeTable::parse(content) {
+        Ok(root) => root.into_inner(),
+        Err(e) => return vec![error_issue(content, &e)],
+    };
+    let mut issues = Vec::new();
+    let mut unknown = Vec::new();
+    let parsed: Result<FileConfig, _> = toml::Deserializer::parse(content)
+        .and_then(|de| serde_ignored::deserialize(de, |path| unknown.push(segments(&path))));
+    for path in unknown {
+        issues.push(Issue {
+            line: key_line(content, &root, &path),
+            message: format!("unknown key `{}`", path.join(".")),
+        });
+    }
+    match parsed {
+        Ok(mut file) => {
+            match project_dir {
+                Some(dir) => file.rebase_paths(dir),
+                None => expand_paths(&mut file),
+            }
+            for (key, message) in missing_references(&file) {
+                issues.push(Issue {
+                    line: key_line(content, &root, &key),
+                    message,
+                });
+            }
+        }
+        Err(e) => issues.push(error_issue(content, &e)),
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+fn error_issue(content: &str, error: &toml::de::Error) -> Issue {
+    Issue {
+        line: error.span().map(|span| line_of(content, span.start)),
+        message: error.message().trim_end().to_string(),
+    }
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+fn segments(path: &serde_ignored::Path) -> Vec<String> {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => Vec::new(),
+        Path::Seq { parent, index } => {
+            let mut segments = segments(parent);
+            segments.push(index.to_string());
+            segments
+        }
+        Path::Map { parent, key } => {
+            let mut segments = segments(parent);
+            segments.push(key.clone());
+            segments
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => segments(parent),
+    }
+}
+
+/// Line of the key at `path` (array items by index), or of the deepest
+/// table on the way that exists
+fn key_line<S: AsRef<str>>(content: &str, table: &DeTable, path: &[S]) -> Option<usize> {
+    let (first, rest) = path.split_first()?;
+    let (key, value) = table
+        .iter()
+        .find(|(key, _)| key.get_ref().as_ref() == first.as_ref())?;
+    let nested = match (value.get_ref(), rest) {
+        (DeValue::Table(table), [_, ..]) => key_line(content, table, rest),
+        (DeValue::Array(items), [index, rest @ ..]) => index
+            .as_ref()
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| items.get(index))
+            .and_then(|item| match item.get_ref() {
+                DeValue::Table(table) => key_line(content, table, rest),
+                _ => None,
+            }),
+        _ => None,
+    };
+    nested.or_else(|| Some(line_of(content, key.span().start)))
+}
+
+/// `${VAR}` references expanded in the paths that are checked
+fn expand_paths(file: &mut FileConfig) {
+    for path in [
+        &mut file.root,
+        &mut file.bibliography,
+        &mut file.highlight.themes_dir,
+        &mut file.highlight.syntaxes_dir,
+        &mut file.highlight.themes_source,
+        &mut file.highlight.syntaxes_source,
+        &mut file.image.fetch.ca_bundle,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = expand_env_vars(path);
+    }
+}
+
+/// Themes, directories and files the config names that aren't there, keyed
+/// by where it names them
+fn missing_references(file: &FileConfig) -> Vec<(Vec<&'static str>, String)> {
+    let mut missing = Vec::new();
+    let dirs = [
+        (vec!["root"], &file.root),
+        (vec!["highlight", "themes_dir"], &file.highlight.themes_dir),
+        (
+            vec!["highlight", "syntaxes_dir"],
+            &file.highlight.syntaxes_dir,
+        ),
+    ];
+    for (key, dir) in dirs {
+        if let Some(dir) = dir.as_ref().filter(|dir| !Path::new(dir).is_dir()) {
+            missing.push((key, format!("directory {} doesn't exist", dir)));
+        }
+    }
+    let files = [
+        (vec!["bibliography"], &file.bibliography),
+        (
+            vec!["image", "fetch", "ca_bundle"],
+            &file.image.fetch.ca_bundle,
+        ),
+    ];
+    for (key, path) in files {
+        if let Some(path) = path.as_ref().filter(|path| !Path::new(path).is_file()) {
+            missing.push((key, format!("file {} doesn't exist", path)));
+        }
+    }
+
+    // Bundles are fetched or unpacked, so themes they bring count as there
+    let mut themes_dirs: Vec<PathBuf> =
+        load_bundle("themes_source", &file.highlight.themes_source, &mut missing)
+            .into_iter()
+            .collect();
+    load_bundle(
+        "syntaxes_source",
+        &file.highlight.syntaxes_source,
+        &mut missing,
+    );
+    themes_dirs.extend(
+        file.highlight
+            .themes_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(|| default_config_dir().map(|p| p.join("themes"))),
+    );
+    let themes = HighlightContext::list_themes(&themes_dirs);
+    let names = [
+        ("theme", &file.highlight.theme),
+        ("theme_light", &file.highlight.theme_light),
+        ("theme_dark", &file.highlight.theme_dark),
+    ];
+    for (key, name) in names {
+        if let Some(name) = name.as_ref().filter(|name| !themes.contains(name)) {
+            missing.push((
+                vec!["highlight", key],
+                format!("theme {:?} doesn't exist (see --list-themes)", name),
+            ));
+        }
+    }
+    missing
+}
+
+/// Directory the bundle at `source` unpacks to, noting under `key` when it
+/// can't be loaded
+fn load_bundle(
+    key: &'static str,
+    source: &Option<String>,
+    missing: &mut Vec<(Vec<&'static str>, String)>,
+) -> Option<PathBuf> {
+    let source = source.as_ref()?;
+    bundle::bundle_dir(source)
+        .map_err(|e| {
+            missing.push((
+                vec!["highlight", key],
+                format!("bundle {} can't be loaded: {}", source, e),
+            ));
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &str) -> Vec<(Option<usize>, String)> {
+        check(content, None)
+            .into_iter()
+            .map(|issue| (issue.line, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_template_is_valid_when_uncommented() {
+        // Settings and example tables are commented as `# key = value` and
+        // `# [table]`, descriptions as prose
+        let setting = regex::Regex::new(r#"(?m)^# ((?:[a-z_]+|"[^"]+") = |\[)"#).unwrap();
+        let uncommented = setting.replace_all(TEMPLATE, "$1");
+        let checked = check(&uncommented, None);
+        assert!(
+            checked
+                .iter()
+                .all(|issue| !issue.message.contains("unknown")
+                    && !issue.message.contains("invalid")),
+            "{:?}",
+            checked
+        );
+
+        // Every setting the defaults print is in the template
+        let keys = |content: &str| -> Vec<String> {
+            let table: toml::Table = content.parse().unwrap();
+            let mut keys = Vec::new();
+            for (key, value) in &table {
+                match value.as_table() {
+                    Some(section) => keys.extend(section.keys().map(|k| format!("{key}.{k}"))),
+                    None => keys.push(key.clone()),
+                }
+            }
+            keys
+        };
+        let template = keys(&uncommented);
+        for key in keys(&crate::config::Config::default().to_toml()) {
+            assert!(template.contains(&key), "{} is missing", key);
+        }
+    }
+
+    #[test]
+    fn test_check_reports_lines() {
+        let content = "strict = true\nstrikt = true\n\n[highlight]\ntheme = \"nope\"\n\n\
+                       [image.cache]\nenable = \"yes\"\n\n[[outputs]]\nformat = \"html\"\npth = \"x\"\n";
+        let issues = messages(content);
+        assert_eq!(issues[0], (Some(2), "unknown key `strikt`".to_string()));
+        assert_eq!(issues[1].0, Some(8));
+        assert!(issues[1].1.contains("expected a boolean"), "{:?}", issues);
+
+        // With the types right, the theme and later keys are checked too
+        let issues = messages(&content.replace("\"yes\"", "true"));
+        assert_eq!(
+            issues,
+            vec![
+                (Some(2), "unknown key `strikt`".to_string()),
+                (
+                    Some(5),
+                    "theme \"nope\" doesn't exist (see --list-themes)".to_string()
+                ),
+                (Some(12), "unknown key `outputs.0.pth`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_syntax_and_paths() {
+        assert_eq!(messages("strict = \n")[0].0, Some(1));
+        assert_eq!(
+            messages("[strict]\nimages = true\nnode = true\n"),
+            vec![(Some(3), "unknown key `strict.node`".to_string())]
+        );
+        let issues = messages("[highlight]\nthemes_dir = \"/nonexistent/themes\"\n");
+        assert_eq!(
+            issues,
+            vec![(
+                Some(2),
+                "directory /nonexistent/themes doesn't exist".to_string()
+            )]
+        );
+        let issues = messages("[highlight]\nthemes_source = \"/nonexistent/themes.zip\"\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].0, Some(2));
+        assert!(
+            issues[0]
+                .1
+                .starts_with("bundle /nonexistent/themes.zip can't be loaded")
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Collapsible sections. `:::details Title` ... `:::` containers are
+//! rewritten to `<details>` HTML before parsing, so HTML and markdown output
+//! keep them collapsible. Formats without raw HTML fold `<details>` blocks
+//! into a bold title followed by the body as an indented quote.
+
+use markdown::mdast::{Blockquote, Node, Paragraph, Strong, Text};
+use std::borrow::Cow;
+
+/// Title used when a section has no summary
+const DEFAULT_TITLE: &str = "Details";
+
+/// Rewrite `:::details Title` containers to `<details>` blocks. Fenced code
+/// is left alone, and text without containers is returned as is.
+pub fn expand_containers(source: &str) -> Cow<'_, str> {
+    if !source.contains(":::") {
+        return Cow::Borrowed(source);
+    }
+    let mut out = String::with_capacity(source.len() + 64);
+    let mut fence: Option<&str> = None;
+    let mut open = 0;
+    let mut changed = false;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            out.push_str(line);
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            fence = Some("```");
+        } else if trimmed.starts_with("~~~") {
+            fence = Some("~~~");
+        } else if let Some(title) = trimmed
+            .strip_prefix(":::")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix("details"))
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        {
+            let title = match title.trim() {
+                "" => DEFAULT_TITLE,
+                title => title,
+            };
+            out.push_str(&format!(
+                "<details>\n<summary>{}</summary>\n\n",
+                escape_html(title)
+            ));
+            open += 1;
+            changed = true;
+            continue;
+        } else if trimmed == ":::" && open > 0 {
+            out.push_str("\n</details>\n\n");
+            open -= 1;
+            continue;
+        }
+        out.push_str(line);
+    }
+    // An unclosed container runs to the end of the document
+    for _ in 0..open {
+        out.push_str("\n\n</details>\n");
+    }
+    if changed {
+        Cow::Owned(out)
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+/// `ast` with its `<details>` blocks folded, for renderers that drop raw
+/// HTML; borrowed when there are none
+pub fn folded(ast: &Node) -> Cow<'_, Node> {
+    if !has_details(ast) {
+        return Cow::Borrowed(ast);
+    }
+    let mut ast = ast.clone();
+    fold(&mut ast);
+    Cow::Owned(ast)
+}
+
+fn has_details(node: &Node) -> bool {
+    match node {
+        Node::Html(html) => html.value.trim_start().starts_with("<details"),
+        _ => node
+            .children()
+            .is_some_and(|children| children.iter().any(has_details)),
+    }
+}
+
+/// Replace every `<details>` block with its summary in bold and its body in
+/// a blockquote
+fn fold(node: &mut Node) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    let mut folded = Vec::with_capacity(children.len());
+    let mut nodes = std::mem::take(children).into_iter();
+    while let Some(child) = nodes.next() {
+        match opening(&child) {
+            Some(Opening::Complete { title, body }) => {
+                folded.extend(section(title, body_paragraph(&body)));
+            }
+            Some(Opening::Start { title }) => {
+                let mut title = title;
+                let mut body = Vec::new();
+                let mut depth = 1;
+                for next in nodes.by_ref() {
+                    if let Node::Html(html) = &next {
+                        let value = html.value.trim();
+                        // A summary separated from `<details>` by a blank line
+                        if title.is_none()
+                            && body.is_empty()
+                            && let Some(summary) = summary(value)
+                        {
+                            title = Some(summary);
+                            continue;
+                        }
+                        if value.starts_with("</details") {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        } else if matches!(opening(&next), Some(Opening::Start { .. })) {
+                            depth += 1;
+                        }
+                    }
+                    body.push(next);
+                }
+                let mut quote = Node::Blockquote(Blockquote {
+                    children: body,
+                    position: None,
+                });
+                // Nested sections and sections inside the body
+                fold(&mut quote);
+                let Node::Blockquote(quote) = quote else {
+                    unreachable!("built above");
+                };
+                folded.extend(section(title, quote.children));
+            }
+            None => {
+                let mut child = child;
+                fold(&mut child);
+                folded.push(child);
+            }
+        }
+    }
+    *node.children_mut().expect("checked above") = folded;
+}
+
+enum Opening {
+    /// `<details>` with its body and closing tag in the same HTML block
+    Complete { title: Option<String>, body: String },
+    /// `<details>` whose body follows as markdown
+    Start { title: Option<String> },
+}
+
+fn opening(node: &Node) -> Option<Opening> {
+    let Node::Html(html) = node else {
+        return None;
+    };
+    let rest = html.value.trim().strip_prefix("<details")?;
+    // `<details>` or `<details open>`, not `<detailsfoo>`
+    let rest = match rest.chars().next() {
+        Some('>') => &rest[1..],
+        Some(c) if c.is_whitespace() => rest.split_once('>')?.1,
+        _ => return None,
+    };
+    let title = summary(rest);
+    let rest = match rest.find("</summary>") {
+        Some(end) => &rest[end + "</summary>".len()..],
+        None => rest,
+    };
+    match rest.rfind("</details>") {
+        Some(end) => Some(Opening::Complete {
+            title,
+            body: rest[..end].to_string(),
+        }),
+        None => Some(Opening::Start { title }),
+    }
+}
+
+/// The text of a `<summary>` element at the start of `html`
+fn summary(html: &str) -> Option<String> {
+    let rest = html.trim_start().strip_prefix("<summary")?;
+    let (_, rest) = rest.split_once('>')?;
+    let (title, _) = rest.split_once("</summary>")?;
+    Some(unescape_html(&strip_tags(title)).trim().to_string())
+}
+
+/// A body given as raw HTML, reduced to its text
+fn body_paragraph(html: &str) -> Vec<Node> {
+    let text = unescape_html(&strip_tags(html));
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    vec![Node::Paragraph(Paragraph {
+        children: vec![text_node(text)],
+        position: None,
+    })]
+}
+
+/// The bold title paragraph and the quoted body
+fn section(title: Option<String>, body: Vec<Node>) -> Vec<Node> {
+    let title = title.filter(|t| !t.is_empty());
+    let mut nodes = vec![Node::Paragraph(Paragraph {
+        children: vec![Node::Strong(Strong {
+            children: vec![text_node(title.as_deref().unwrap_or(DEFAULT_TITLE))],
+            position: None,
+        })],
+        position: None,
+    })];
+    if !body.is_empty() {
+        nodes.push(Node::Blockquote(Blockquote {
+            children: body,
+            position: None,
+        }));
+    }
+    nodes
+}
+
+fn text_node(value: &str) -> Node {
+    Node::Text(Text {
+        value: value.to_string(),
+        position: None,
+    })
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn parse(md: &str) -> Node {
+        markdown::to_mdast(&expand_containers(md), &ParseOptions::gfm()).unwrap()
+    }
+
+    #[test]
+    fn test_expand_containers() {
+        assert_eq!(
+            expand_containers(":::details More & less\nBody *text*\n:::\nAfter\n"),
+            "<details>\n<summary>More &amp; less</summary>\n\nBody *text*\n\n</details>\n\nAfter\n"
+        );
+        let fenced = "```\n:::details Not a container\n:::\n```\n";
+        assert!(matches!(expand_containers(fenced), Cow::Borrowed(_)));
+        assert!(matches!(
+            expand_containers("::: note\n:::\n"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut ast = parse(
+            ":::details Setup\nRun **it**.\n\n:::details Inner\nNested\n:::\n:::\n\n\
+             <details><summary>Raw</summary>Plain &amp; simple</details>\n",
+        );
+        fold(&mut ast);
+        let children = ast.children().unwrap();
+        assert_eq!(children.len(), 4);
+        assert_eq!(children[0].to_string(), "Setup");
+        assert!(matches!(
+            children[0].children().unwrap()[0],
+            Node::Strong(_)
+        ));
+        let Node::Blockquote(body) = &children[1] else {
+            panic!("expected the body in a quote");
+        };
+        assert_eq!(body.children[0].to_string(), "Run it.");
+        assert_eq!(body.children[1].to_string(), "Inner");
+        assert!(matches!(body.children[2], Node::Blockquote(_)));
+        assert_eq!(children[2].to_string(), "Raw");
+        assert_eq!(children[3].to_string(), "Plain & simple");
+    }
+}
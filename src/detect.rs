@@ -0,0 +1,206 @@
+//! Guessing the language of code blocks that don't name one, from a shebang
+//! or from what the code looks like.
+
+/// Marker tokens of languages recognized by what their code contains. Each
+/// distinct marker found scores a point.
+const MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn ", "-> ", "::", "&str", "println!", "use std",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "import ", "self.", "elif ", "None", "print(", "__init__", "):\n",
+        ],
+    ),
+    (
+        "js",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "require(",
+            "===",
+            "export ",
+            "document.",
+        ],
+    ),
+    (
+        "go",
+        &["func ", "package ", ":= ", "fmt.", "go func", "err != nil"],
+    ),
+    (
+        "c",
+        &[
+            "#include", "int main", "printf(", "malloc(", "NULL", "#define",
+        ],
+    ),
+    (
+        "java",
+        &[
+            "public class",
+            "System.out",
+            "private ",
+            "import java",
+            "public static void",
+            "@Override",
+        ],
+    ),
+    (
+        "ruby",
+        &[
+            "puts ",
+            "require '",
+            ".each do",
+            "end\n",
+            "attr_accessor",
+            "do |",
+        ],
+    ),
+];
+
+/// Points the best-scoring language needs, and its lead over the next
+const MIN_SCORE: usize = 2;
+
+/// SQL statements a block can open with
+const SQL_STATEMENTS: &[&str] = &[
+    "SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "ALTER", "DROP", "WITH",
+];
+
+/// A language token for `code`, or None when nothing stands out
+pub fn detect_language(code: &str) -> Option<&'static str> {
+    let code = code.trim();
+    let first_line = code.lines().next()?;
+    if let Some(lang) = first_line.strip_prefix("#!").and_then(shebang_language) {
+        return Some(lang);
+    }
+    if (code.starts_with('{') || code.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(code).is_ok()
+    {
+        return Some("json");
+    }
+    // A shell session: commands after `$` prompts, with their output
+    if first_line.starts_with("$ ") {
+        return Some("console");
+    }
+    if first_line.starts_with("diff --git") || first_line.starts_with("--- ") {
+        return Some("diff");
+    }
+    if code.starts_with('<') && code.ends_with('>') {
+        return Some(if code.starts_with("<?xml") {
+            "xml"
+        } else {
+            "html"
+        });
+    }
+    let statement = first_line.split_whitespace().next().unwrap_or("");
+    if SQL_STATEMENTS
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(statement))
+    {
+        return Some("sql");
+    }
+    if looks_like_yaml(code) {
+        return Some("yaml");
+    }
+
+    let mut scores: Vec<(&str, usize)> = MARKERS
+        .iter()
+        .map(|(lang, markers)| (*lang, markers.iter().filter(|m| code.contains(*m)).count()))
+        .collect();
+    scores.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    match scores.as_slice() {
+        [(lang, best), (_, next), ..] if *best >= MIN_SCORE && best - next >= MIN_SCORE => {
+            Some(lang)
+        }
+        _ => None,
+    }
+}
+
+/// Language of the interpreter a shebang line (after `#!`) names
+fn shebang_language(line: &str) -> Option<&'static str> {
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "sh" | "bash" | "zsh" | "ksh" | "dash" | "ash" => "bash",
+        "python" => "python",
+        "node" | "nodejs" | "deno" | "bun" => "js",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "Rscript" => "r",
+        "tclsh" => "tcl",
+        _ => return None,
+    })
+}
+
+/// Whether every line is a `key: value` pair, a list item or a comment,
+/// with at least two pairs
+fn looks_like_yaml(code: &str) -> bool {
+    let mut pairs = 0;
+    for line in code.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line == "---" {
+            continue;
+        }
+        let item = line.strip_prefix("- ").unwrap_or(line);
+        match item.split_once(':') {
+            Some((key, rest))
+                if !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                    && (rest.is_empty() || rest.starts_with(' ')) =>
+            {
+                pairs += 1;
+            }
+            _ if line.starts_with("- ") => {}
+            _ => return false,
+        }
+    }
+    pairs >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let cases = [
+            ("#!/usr/bin/env python3\nprint('hi')\n", Some("python")),
+            ("#!/bin/bash\necho hi\n", Some("bash")),
+            ("#!/usr/bin/env -S node --harmony\n", Some("js")),
+            ("{\"a\": [1, 2], \"b\": null}", Some("json")),
+            (
+                "$ cargo build\n   Compiling mdcopy\n$ ls\n",
+                Some("console"),
+            ),
+            ("<div>\n  <p>hi</p>\n</div>", Some("html")),
+            ("select * from users\nwhere id = 1;", Some("sql")),
+            ("name: mdcopy\nversion: 1\ndeps:\n  - serde\n", Some("yaml")),
+            (
+                "fn main() {\n    let mut x = 1;\n    println!(\"{}\", x);\n}",
+                Some("rust"),
+            ),
+            (
+                "def greet(name):\n    print(name)\n\nimport os\n",
+                Some("python"),
+            ),
+            ("Just some words in a block", None),
+            ("{not json", None),
+            ("", None),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(detect_language(code), expected, "{:?}", code);
+        }
+    }
+}
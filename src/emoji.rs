@@ -0,0 +1,199 @@
+use crate::config::ImageConfig;
+use crate::image::{EmbeddedImage, ImageCache, ImageError};
+use std::path::Path;
+
+/// Twemoji 72x72 PNGs, named by codepoint
+pub const DEFAULT_BASE_URL: &str =
+    "https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72";
+
+const ZWJ: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+
+/// A run of plain text or a single emoji sequence
+#[derive(Debug, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Emoji(&'a str),
+}
+
+/// Split text into plain runs and emoji sequences (ZWJ sequences, skin tones,
+/// flags and keycaps stay whole). Symbols in the Basic Multilingual Plane such
+/// as ☑ or ★ only count with an explicit emoji variation selector, since old
+/// RTF readers usually have a font for them.
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut iter = text.char_indices().peekable();
+
+    while let Some((start, c)) = iter.next() {
+        let next = iter.peek().map(|&(_, n)| n);
+        let starts_emoji = is_pictographic(c)
+            || (is_symbol(c) && next == Some(VARIATION_SELECTOR))
+            || (is_keycap_base(c) && next == Some(VARIATION_SELECTOR));
+        if !starts_emoji {
+            continue;
+        }
+
+        let mut end = start + c.len_utf8();
+        let mut regional = is_regional_indicator(c);
+        while let Some(&(i, n)) = iter.peek() {
+            let extends = n == VARIATION_SELECTOR
+                || n == KEYCAP
+                || is_skin_tone(n)
+                || is_tag(n)
+                || (regional && is_regional_indicator(n));
+            if extends {
+                // Flags are pairs of regional indicators
+                regional = false;
+            } else if n == ZWJ {
+                // Only join when another emoji follows
+                let mut ahead = iter.clone();
+                ahead.next();
+                if !ahead
+                    .peek()
+                    .is_some_and(|&(_, a)| is_pictographic(a) || is_symbol(a))
+                {
+                    break;
+                }
+            } else if !text[start..end].ends_with(ZWJ) {
+                break;
+            }
+            end = i + n.len_utf8();
+            iter.next();
+        }
+
+        // A bare keycap base (digit without the keycap mark) is just text
+        if is_keycap_base(c) && !text[start..end].contains(KEYCAP) {
+            continue;
+        }
+
+        if text_start < start {
+            segments.push(Segment::Text(&text[text_start..start]));
+        }
+        segments.push(Segment::Emoji(&text[start..end]));
+        text_start = end;
+    }
+
+    if text_start < text.len() {
+        segments.push(Segment::Text(&text[text_start..]));
+    }
+    segments
+}
+
+/// Twemoji file name for an emoji: lowercase hex codepoints joined by `-`,
+/// without the variation selector unless the sequence uses a ZWJ
+pub fn glyph_name(emoji: &str) -> String {
+    let keep_selector = emoji.contains(ZWJ);
+    emoji
+        .chars()
+        .filter(|&c| keep_selector || c != VARIATION_SELECTOR)
+        .map(|c| format!("{:x}", c as u32))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Load the glyph image for an emoji through the image cache. Glyphs are
+/// always embedded, whatever the embed settings say about other images.
+pub fn load_glyph(
+    emoji: &str,
+    base_dir: &Path,
+    image_config: &ImageConfig,
+    strict: bool,
+    image_cache: &ImageCache,
+) -> Result<Option<EmbeddedImage>, ImageError> {
+    let url = format!(
+        "{}/{}.png",
+        image_config.emoji_base_url.trim_end_matches('/'),
+        glyph_name(emoji)
+    );
+    let glyph_config = ImageConfig {
+        embed_local: true,
+        embed_remote: true,
+        ..image_config.clone()
+    };
+    image_cache.get_or_load(&url, base_dir, &glyph_config, strict)
+}
+
+/// Emoji that render as pictographs by default (mostly outside the BMP)
+fn is_pictographic(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF)
+}
+
+/// BMP symbols that become emoji with a variation selector
+fn is_symbol(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x00A9 | 0x00AE | 0x203C | 0x2049 | 0x2122 | 0x2139 | 0x2194..=0x21AA | 0x2300..=0x23FF
+            | 0x24C2 | 0x25AA..=0x25FE | 0x2600..=0x27BF | 0x2934 | 0x2935 | 0x2B05..=0x2B55
+            | 0x3030 | 0x303D | 0x3297 | 0x3299
+    )
+}
+
+fn is_keycap_base(c: char) -> bool {
+    c.is_ascii_digit() || c == '#' || c == '*'
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    matches!(c as u32, 0x1F3FB..=0x1F3FF)
+}
+
+/// Tag characters used by subdivision flags (e.g. Scotland)
+fn is_tag(c: char) -> bool {
+    matches!(c as u32, 0xE0020..=0xE007F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emoji_in(text: &str) -> Vec<&str> {
+        segments(text)
+            .into_iter()
+            .filter_map(|s| match s {
+                Segment::Emoji(e) => Some(e),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            segments("Ship it 🚀!"),
+            vec![
+                Segment::Text("Ship it "),
+                Segment::Emoji("🚀"),
+                Segment::Text("!")
+            ]
+        );
+        assert_eq!(segments("plain"), vec![Segment::Text("plain")]);
+    }
+
+    #[test]
+    fn test_segments_sequences() {
+        assert_eq!(
+            emoji_in("👩‍💻 👍🏽 🇩🇪🇫🇷 1️⃣ ❤️"),
+            vec!["👩‍💻", "👍🏽", "🇩🇪", "🇫🇷", "1️⃣", "❤️"]
+        );
+    }
+
+    #[test]
+    fn test_segments_skip_text_symbols() {
+        // No variation selector: these stay text
+        assert!(emoji_in("☑ done ★ 5 # 1").is_empty());
+    }
+
+    #[test]
+    fn test_glyph_name() {
+        assert_eq!(glyph_name("🚀"), "1f680");
+        assert_eq!(glyph_name("❤️"), "2764");
+        assert_eq!(glyph_name("1️⃣"), "31-20e3");
+        assert_eq!(glyph_name("👩‍💻"), "1f469-200d-1f4bb");
+        assert_eq!(glyph_name("🏳️‍🌈"), "1f3f3-fe0f-200d-1f308");
+    }
+}
@@ -0,0 +1,108 @@
+//! Errors that end a run. Each category exits with its own status, so
+//! scripts can tell a bad argument from an unreadable image or a clipboard
+//! that refused the copy.
+//!
+//! Library modules keep returning `io::Result`; a `MdcopyError` passed
+//! through one as `io::Error::other` comes back out with its category, and
+//! so does an `ImageError` from a renderer.
+
+use crate::image::ImageError;
+use std::io;
+
+/// Exit statuses, for `--help`
+pub const EXIT_CODES: &str = "\
+Exit status:
+  0  Success
+  1  A check found problems (roundtrip, config validate)
+  2  Invalid arguments or settings
+  3  The input couldn't be parsed
+  4  An image couldn't be loaded (with --strict)
+  5  Syntax highlighting couldn't be set up, or a code block's language or
+     the theme is missing (with --strict)
+  6  The clipboard couldn't be read or written, or the output is too large
+     for it (with --strict)
+  7  Reading or writing a file failed
+  8  An output format drops part of the document (with --strict)";
+
+#[derive(Debug, thiserror::Error)]
+pub enum MdcopyError {
+    /// Invalid arguments or settings
+    #[error("{0}")]
+    Usage(String),
+    /// The input couldn't be parsed
+    #[error("Failed to parse the input: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    #[error("{0}")]
+    Highlight(String),
+    #[error("{0}")]
+    Clipboard(#[source] io::Error),
+    #[error(transparent)]
+    Io(io::Error),
+    /// Content an output format can't express, fatal with `--strict`
+    #[error("{0}")]
+    Unsupported(String),
+    /// A check (roundtrip, config validate) ran and found problems, which
+    /// it already reported
+    #[error("{0}")]
+    CheckFailed(String),
+}
+
+impl MdcopyError {
+    /// Process exit status for this category (see `EXIT_CODES`)
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MdcopyError::Usage(_) => 2,
+            MdcopyError::Parse(_) => 3,
+            MdcopyError::Image(_) => 4,
+            MdcopyError::Highlight(_) => 5,
+            MdcopyError::Clipboard(_) => 6,
+            MdcopyError::Io(_) => 7,
+            MdcopyError::Unsupported(_) => 8,
+            MdcopyError::CheckFailed(_) => 1,
+        }
+    }
+}
+
+impl From<io::Error> for MdcopyError {
+    fn from(e: io::Error) -> Self {
+        let e = match e.downcast::<MdcopyError>() {
+            Ok(error) => return error,
+            Err(e) => e,
+        };
+        match e.downcast::<ImageError>() {
+            Ok(image) => MdcopyError::Image(image),
+            Err(e) => MdcopyError::Io(e),
+        }
+    }
+}
+
+impl From<MdcopyError> for io::Error {
+    fn from(e: MdcopyError) -> Self {
+        match e {
+            MdcopyError::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_survives_io_error() {
+        let clipboard = MdcopyError::Clipboard(io::Error::other("no display"));
+        let passed: MdcopyError = io::Error::from(clipboard).into();
+        assert_eq!(passed.exit_code(), 6);
+        assert_eq!(passed.to_string(), "no display");
+
+        let image = io::Error::other(ImageError::NotFound("a.png".to_string()));
+        assert_eq!(MdcopyError::from(image).exit_code(), 4);
+
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let passed = io::Error::from(MdcopyError::from(missing));
+        assert_eq!(passed.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,119 @@
+//! Short plain-text excerpts, for the text/plain clipboard flavor when the
+//! app pasted into previews it: whole paragraphs up to a word budget, an
+//! ellipsis, and a link back to the full document.
+
+/// The first paragraphs of `text` holding at most `words` words, or None
+/// when the whole text fits. A paragraph that would run past the budget is
+/// left out, unless it's the first, which is cut mid-way instead, and a
+/// heading is never left at the end without its section.
+pub fn excerpt(text: &str, words: usize, link: Option<&str>) -> Option<String> {
+    let mut kept: Vec<String> = Vec::new();
+    let mut count = 0;
+    let mut blocks = paragraphs(text).into_iter();
+    let overflow = loop {
+        // Everything fits
+        let block = blocks.next()?;
+        let n = word_count(block);
+        if count + n > words {
+            break block;
+        }
+        kept.push(block.to_string());
+        count += n;
+    };
+    if kept.iter().all(|b| is_heading(b)) && !is_heading(overflow) {
+        // Cut after the last word that fits, keeping the line breaks before it
+        let mut end = 0;
+        for token in overflow.split_whitespace() {
+            if word_count(token) > 0 {
+                if count == words {
+                    break;
+                }
+                count += 1;
+            }
+            end = token.as_ptr() as usize - overflow.as_ptr() as usize + token.len();
+        }
+        kept.push(overflow[..end].to_string());
+    }
+    while kept.last().is_some_and(|b| is_heading(b) || b.is_empty()) {
+        kept.pop();
+    }
+    let mut out = kept.join("\n\n");
+    out.push('…');
+    if let Some(link) = link {
+        out.push_str(&format!("\n\nFull document: {}", link));
+    }
+    Some(out)
+}
+
+/// Words with a letter or digit in them, so markup like `#` or `-` doesn't
+/// count
+fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Blocks separated by blank lines
+fn paragraphs(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut start = None;
+    let mut end = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(s) = start.take() {
+                blocks.push(text[s..end].trim_end());
+            }
+        } else {
+            start.get_or_insert(offset);
+            end = offset + line.len();
+        }
+        offset += line.len();
+    }
+    if let Some(s) = start {
+        blocks.push(text[s..end].trim_end());
+    }
+    blocks
+}
+
+/// A markdown ATX heading, or a heading underlined with `=` or `-` (setext
+/// markdown and rendered plain text)
+fn is_heading(block: &str) -> bool {
+    let mut lines = block.lines();
+    let first = lines.next().unwrap_or("");
+    match (lines.next(), lines.next()) {
+        (None, _) => first.trim_start().starts_with('#'),
+        (Some(underline), None) => {
+            let underline = underline.trim();
+            !underline.is_empty()
+                && (underline.chars().all(|c| c == '=') || underline.chars().all(|c| c == '-'))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excerpt() {
+        let text = "# Title\n\nOne two three.\n\nFour five six.\n\n## Next\n\nSeven eight.\n";
+        assert_eq!(excerpt(text, 100, None), None);
+        // The second paragraph would pass the budget, so only the first fits
+        assert_eq!(
+            excerpt(text, 5, Some("file:///notes.md")).as_deref(),
+            Some("# Title\n\nOne two three.…\n\nFull document: file:///notes.md")
+        );
+        // A heading isn't left hanging at the end
+        assert_eq!(
+            excerpt(text, 8, None).as_deref(),
+            Some("# Title\n\nOne two three.\n\nFour five six.…")
+        );
+        // The first paragraph is cut when nothing else fits
+        assert_eq!(
+            excerpt("Title\n=====\n\nOne\ntwo three four.\n", 3, None).as_deref(),
+            Some("Title\n=====\n\nOne\ntwo…")
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! What each output format leaves out of a document. Renderers drop or
+//! simplify content they have no way to express (raw HTML in RTF, footnotes
+//! in HTML, tables in Slack); this tallies those losses from the tree so the
+//! user hears about them before pasting, as one line per format:
+//! `RTF: 2 raw HTML elements dropped, 1 footnote dropped`.
+
+use crate::ClipboardFormat;
+use crate::details;
+use crate::image::is_remote_url;
+use markdown::mdast::Node;
+
+/// Content a format drops or renders in a simpler form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// Raw HTML blocks and inline tags left out
+    Html,
+    /// Footnotes left out, references and all
+    Footnote,
+    /// Images that become links to the image
+    ImageAsLink,
+    /// Images that become their alt text
+    ImageAsText,
+    /// Tables laid out as monospaced text
+    TableAsText,
+    /// Headings shown as bold text or at a shallower level
+    Heading,
+}
+
+impl Loss {
+    fn describe(self, count: usize) -> String {
+        let plural = |one: &str, many: &str| {
+            if count == 1 {
+                format!("{} {}", count, one)
+            } else {
+                format!("{} {}", count, many)
+            }
+        };
+        match self {
+            Loss::Html => plural("raw HTML element dropped", "raw HTML elements dropped"),
+            Loss::Footnote => plural("footnote dropped", "footnotes dropped"),
+            Loss::ImageAsLink => plural("image shown as a link", "images shown as links"),
+            Loss::ImageAsText => plural("image shown as alt text", "images shown as alt text"),
+            Loss::TableAsText => plural("table drawn as text", "tables drawn as text"),
+            Loss::Heading => plural("heading flattened", "headings flattened"),
+        }
+    }
+}
+
+/// Every loss `format` makes rendering `ast`, with its count, in the order
+/// they first appear
+pub fn losses(ast: &Node, format: ClipboardFormat) -> Vec<(Loss, usize)> {
+    let mut tally: Vec<(Loss, usize)> = Vec::new();
+    // Formats without raw HTML fold `<details>` rather than dropping it
+    walk(
+        &details::folded(ast),
+        format,
+        Within::default(),
+        &mut |loss| match tally.iter_mut().find(|(l, _)| *l == loss) {
+            Some((_, count)) => *count += 1,
+            None => tally.push((loss, 1)),
+        },
+    );
+    tally
+}
+
+/// A one-line summary of `losses` for the format called `name`, None when
+/// nothing is lost
+pub fn summary(name: &str, losses: &[(Loss, usize)]) -> Option<String> {
+    if losses.is_empty() {
+        return None;
+    }
+    let parts: Vec<String> = losses
+        .iter()
+        .map(|(loss, count)| loss.describe(*count))
+        .collect();
+    Some(format!("{}: {}", name, parts.join(", ")))
+}
+
+/// Where a node sits, for the formats that treat images differently there
+#[derive(Debug, Default, Clone, Copy)]
+struct Within {
+    /// Inside a table cell
+    cell: bool,
+    /// The only content of its paragraph
+    alone: bool,
+}
+
+fn walk(node: &Node, format: ClipboardFormat, within: Within, report: &mut impl FnMut(Loss)) {
+    if let Some(loss) = loss(node, format, within) {
+        report(loss);
+        // Nothing inside dropped content is rendered either
+        if matches!(loss, Loss::Html | Loss::Footnote) {
+            return;
+        }
+    }
+    let within = Within {
+        cell: within.cell || matches!(node, Node::TableCell(_)),
+        alone: matches!(node, Node::Paragraph(para) if para.children.len() == 1),
+    };
+    for child in node.children().into_iter().flatten() {
+        walk(child, format, within, report);
+    }
+}
+
+/// What `format` loses rendering `node` itself
+fn loss(node: &Node, format: ClipboardFormat, within: Within) -> Option<Loss> {
+    use ClipboardFormat::*;
+    match node {
+        Node::Html(_) => match format {
+            Html | Markdown | MediaWiki | Mhtml => None,
+            // Drawn from the HTML output, except for the native one on macOS
+            #[cfg(not(target_os = "macos"))]
+            Image => None,
+            _ => Some(Loss::Html),
+        },
+        Node::FootnoteDefinition(_) => match format {
+            Html | Epub | Mhtml | Rtf | Image => Some(Loss::Footnote),
+            #[cfg(target_os = "macos")]
+            Native => Some(Loss::Footnote),
+            _ => None,
+        },
+        Node::Image(_) | Node::ImageReference(_) => match format {
+            Slack => Some(Loss::ImageAsLink),
+            Text => Some(Loss::ImageAsText),
+            Pdf if within.cell => Some(Loss::ImageAsText),
+            // Only a remote image standing alone becomes a media block
+            ProseMirror | Notion => match node {
+                Node::Image(image) if within.alone && is_remote_url(&image.url) => None,
+                _ => Some(Loss::ImageAsLink),
+            },
+            _ => None,
+        },
+        Node::Table(_) => matches!(format, Slack | Text).then_some(Loss::TableAsText),
+        Node::Heading(heading) => match format {
+            Slack => Some(Loss::Heading),
+            Notion if heading.depth > 3 => Some(Loss::Heading),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn parse(md: &str) -> Node {
+        markdown::to_mdast(md, &ParseOptions::gfm()).unwrap()
+    }
+
+    #[test]
+    fn test_losses() {
+        let ast = parse(
+            "<div>raw</div>\n\nText<br>[^1] and ![local](a.png)\n\n\
+             | a |\n|---|\n| 1 |\n\n[^1]: Note with <b>html</b>\n",
+        );
+        assert_eq!(
+            losses(&ast, ClipboardFormat::Rtf),
+            vec![(Loss::Html, 2), (Loss::Footnote, 1)]
+        );
+        assert_eq!(
+            losses(&ast, ClipboardFormat::Html),
+            vec![(Loss::Footnote, 1)]
+        );
+        assert_eq!(losses(&ast, ClipboardFormat::Markdown), vec![]);
+        assert_eq!(
+            losses(&ast, ClipboardFormat::Slack),
+            vec![
+                (Loss::Html, 4),
+                (Loss::ImageAsLink, 1),
+                (Loss::TableAsText, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_standalone_images() {
+        let ast = parse("![a](https://x.io/a.png)\n\nSee ![b](https://x.io/b.png) ![c](c.png)\n");
+        // The first becomes a media node, the others links
+        assert_eq!(
+            losses(&ast, ClipboardFormat::ProseMirror),
+            vec![(Loss::ImageAsLink, 2)]
+        );
+    }
+
+    #[test]
+    fn test_details_are_folded_not_dropped() {
+        let ast = parse("<details>\n<summary>More</summary>\n\nBody\n\n</details>\n");
+        assert_eq!(losses(&ast, ClipboardFormat::Rtf), vec![]);
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary("RTF", &[]), None);
+        assert_eq!(
+            summary("RTF", &[(Loss::Html, 3), (Loss::Footnote, 1)]).unwrap(),
+            "RTF: 3 raw HTML elements dropped, 1 footnote dropped"
+        );
+    }
+}
@@ -0,0 +1,1064 @@
+//! HTML input (`--from html`). A forgiving parser builds an element tree the
+//! way browsers recover from sloppy markup (unclosed `<p>` and `<li>`, stray
+//! end tags), and that tree is mapped onto the markdown tree: headings,
+//! paragraphs, lists and task lists, tables, code blocks, quotes, links and
+//! images, plus the bold, italic and strikethrough styles editors such as
+//! Google Docs put on `<span>`s instead of tags. Scripts, styles, forms and
+//! layout are left out.
+
+use crate::config::{ImageConfig, ListStyle};
+use crate::image::ImageCache;
+use crate::to_markdown;
+use markdown::mdast::{
+    AlignKind, Blockquote, Break, Code, Delete, Emphasis, Heading, Image, InlineCode, Link, List,
+    ListItem, Node, Paragraph, Root, Strong, Table, TableCell, TableRow, Text, ThematicBreak,
+};
+use std::path::Path;
+
+/// Elements without content or an end tag
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is text up to their end tag
+const RAW_TEXT: &[&str] = &["script", "style", "title", "textarea"];
+
+/// Elements left out along with everything inside them
+const SKIPPED: &[&str] = &[
+    "head", "script", "style", "template", "noscript", "title", "textarea", "select", "button",
+    "iframe", "object", "svg", "math", "canvas", "video", "audio",
+];
+
+/// Elements that start a block of their own
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Block elements that end an open paragraph
+const CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// The markdown tree of an HTML document or fragment
+pub fn html_to_mdast(html: &str) -> Node {
+    let root = parse(html);
+    Node::Root(Root {
+        children: blocks(&root.children),
+        position: None,
+    })
+}
+
+/// An HTML document as markdown source, with its `<title>` as front matter
+pub fn html_to_markdown(html: &str) -> String {
+    let mut ast = html_to_mdast(html);
+    escape_text(&mut ast);
+
+    // Nothing is loaded with embedding off; images keep their URLs
+    let images = ImageConfig {
+        embed_local: false,
+        embed_remote: false,
+        ..ImageConfig::default()
+    };
+    let markdown = to_markdown::mdast_to_markdown(
+        &ast,
+        Path::new("."),
+        &images,
+        false,
+        &ImageCache::new(),
+        ListStyle::default(),
+    )
+    .unwrap_or_default();
+
+    match find(&parse(html), "title").map(|title| collapse_whitespace(&text_content(title))) {
+        // A JSON string is a valid YAML scalar
+        Some(title) if !title.trim().is_empty() => format!(
+            "---\ntitle: {}\n---\n\n{}",
+            serde_json::Value::from(title.trim()),
+            markdown
+        ),
+        _ => markdown,
+    }
+}
+
+#[derive(Debug)]
+enum Dom {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Dom>,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// A property of the inline `style` attribute, lowercased
+    fn style(&self, property: &str) -> Option<String> {
+        self.attr("style")?.split(';').find_map(|declaration| {
+            let (name, value) = declaration.split_once(':')?;
+            (name.trim().eq_ignore_ascii_case(property)).then(|| value.trim().to_lowercase())
+        })
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Dom::Element(element) => Some(element),
+            Dom::Text(_) => None,
+        })
+    }
+}
+
+/// Build the element tree, closing what the markup leaves open
+fn parse(html: &str) -> Element {
+    let mut stack = vec![Element::new("#root")];
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            close(&mut stack, &after[..end].trim().to_ascii_lowercase());
+            rest = after.get(end + 1..).unwrap_or("");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let (element, self_closing, after) = start_tag(&rest[1..]);
+            rest = after;
+            if RAW_TEXT.contains(&element.name.as_str()) {
+                let end = find_end_tag(rest, &element.name);
+                let mut element = element;
+                element
+                    .children
+                    .push(Dom::Text(decode_entities(&rest[..end])));
+                rest = &rest[end..];
+                open(&mut stack, element);
+            } else if VOID.contains(&element.name.as_str()) || self_closing {
+                implied_end(&mut stack, &element.name);
+                push(&mut stack, Dom::Element(element));
+            } else {
+                implied_end(&mut stack, &element.name);
+                stack.push(element);
+            }
+        } else {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |i| i + first);
+            push(&mut stack, Dom::Text(decode_entities(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+    while stack.len() > 1 {
+        pop(&mut stack);
+    }
+    stack.pop().expect("the root stays on the stack")
+}
+
+/// The element of a start tag (after its `<`), whether it ended in `/>`,
+/// and the markup after it
+fn start_tag(markup: &str) -> (Element, bool, &str) {
+    let name_end = markup
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(markup.len());
+    let mut element = Element::new(&markup[..name_end].to_ascii_lowercase());
+    let mut rest = &markup[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return (element, true, after);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (element, false, after);
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+        if rest.is_empty() {
+            return (element, false, rest);
+        }
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len())
+            .max(1);
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let end = inner.find(quote).unwrap_or(inner.len());
+                        (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = after
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = after;
+                decode_entities(value)
+            }
+            None => String::new(),
+        };
+        element.attrs.push((name, value));
+    }
+}
+
+/// Where the end tag of a raw text element starts
+fn find_end_tag(markup: &str, name: &str) -> usize {
+    let closing = format!("</{}", name);
+    markup
+        .to_ascii_lowercase()
+        .find(&closing)
+        .unwrap_or(markup.len())
+}
+
+fn push(stack: &mut [Element], node: Dom) {
+    stack
+        .last_mut()
+        .expect("the root stays on the stack")
+        .children
+        .push(node);
+}
+
+fn open(stack: &mut Vec<Element>, element: Element) {
+    implied_end(stack, &element.name);
+    push(stack, Dom::Element(element));
+}
+
+fn pop(stack: &mut Vec<Element>) {
+    let element = stack.pop().expect("never pops the root");
+    push(stack, Dom::Element(element));
+}
+
+/// Close the innermost open `name`, and everything opened inside it; an end
+/// tag with nothing to close is ignored
+fn close(stack: &mut Vec<Element>, name: &str) {
+    if let Some(index) = stack.iter().rposition(|e| e.name == name)
+        && index > 0
+    {
+        while stack.len() > index {
+            pop(stack);
+        }
+    }
+}
+
+/// Close the elements a start tag ends implicitly: a block ends an open
+/// paragraph, a list item the previous item, a cell the previous cell
+fn implied_end(stack: &mut Vec<Element>, name: &str) {
+    let (ends, boundary): (&[&str], &[&str]) = match name {
+        "li" => (&["li"], &["ul", "ol"]),
+        "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+        "tr" => (&["tr"], &["table", "thead", "tbody", "tfoot"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot"], &["table"]),
+        _ if CLOSES_P.contains(&name) => (&["p"], &[]),
+        _ => return,
+    };
+    for index in (1..stack.len()).rev() {
+        let open = stack[index].name.as_str();
+        if ends.contains(&open) {
+            while stack.len() > index {
+                pop(stack);
+            }
+            return;
+        }
+        // A paragraph is only ended from inside its inline content
+        if boundary.contains(&open) || (ends == ["p"] && !is_inline(open)) {
+            return;
+        }
+    }
+}
+
+fn is_inline(name: &str) -> bool {
+    !BLOCKS.contains(&name) && !SKIPPED.contains(&name)
+}
+
+/// The first element called `name`, depth-first
+fn find<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    element.elements().find_map(|child| {
+        if child.name == name {
+            Some(child)
+        } else {
+            find(child, name)
+        }
+    })
+}
+
+/// Block content, with each run of inline content as a paragraph
+fn blocks<'a>(children: impl IntoIterator<Item = &'a Dom>) -> Vec<Node> {
+    let mut out = Vec::new();
+    let mut run: Vec<&Dom> = Vec::new();
+    for child in children {
+        match child {
+            Dom::Element(element) if !is_inline(&element.name) => {
+                paragraph(&run, &mut out);
+                run.clear();
+                block(element, &mut out);
+            }
+            _ => run.push(child),
+        }
+    }
+    paragraph(&run, &mut out);
+    out
+}
+
+fn paragraph(run: &[&Dom], out: &mut Vec<Node>) {
+    let children = tidy(run.iter().flat_map(|dom| inline(dom)).collect());
+    if !children.is_empty() {
+        out.push(Node::Paragraph(Paragraph {
+            children,
+            position: None,
+        }));
+    }
+}
+
+fn block(element: &Element, out: &mut Vec<Node>) {
+    match element.name.as_str() {
+        name if SKIPPED.contains(&name) => {}
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let children = tidy(inlines(&element.children));
+            if !children.is_empty() {
+                out.push(Node::Heading(Heading {
+                    children,
+                    position: None,
+                    depth: element.name.as_bytes()[1] - b'0',
+                }));
+            }
+        }
+        "blockquote" => out.push(Node::Blockquote(Blockquote {
+            children: blocks(&element.children),
+            position: None,
+        })),
+        "ul" | "ol" => out.push(list(element)),
+        "pre" => out.push(code(element)),
+        "hr" => out.push(Node::ThematicBreak(ThematicBreak { position: None })),
+        "table" => out.extend(table(element)),
+        // A bold title over the body in a quote, as folded `<details>` are
+        "details" => {
+            if let Some(summary) = element.elements().find(|e| e.name == "summary") {
+                out.extend(strong_paragraph(&summary.children));
+            }
+            let body = blocks(
+                element
+                    .children
+                    .iter()
+                    .filter(|child| !matches!(child, Dom::Element(e) if e.name == "summary")),
+            );
+            if !body.is_empty() {
+                out.push(Node::Blockquote(Blockquote {
+                    children: body,
+                    position: None,
+                }));
+            }
+        }
+        "dt" => out.extend(strong_paragraph(&element.children)),
+        _ => out.extend(blocks(&element.children)),
+    }
+}
+
+fn strong_paragraph(children: &[Dom]) -> Option<Node> {
+    let children = tidy(inlines(children));
+    (!children.is_empty()).then(|| {
+        Node::Paragraph(Paragraph {
+            children: vec![Node::Strong(Strong {
+                children,
+                position: None,
+            })],
+            position: None,
+        })
+    })
+}
+
+fn list(element: &Element) -> Node {
+    let ordered = element.name == "ol";
+    let mut items: Vec<Node> = Vec::new();
+    for child in element.elements() {
+        match child.name.as_str() {
+            "li" => items.push(Node::ListItem(ListItem {
+                children: blocks(&child.children),
+                position: None,
+                spread: false,
+                checked: checkbox(child),
+            })),
+            // A list written straight inside a list belongs to the item
+            // before it
+            "ul" | "ol" => match items.last_mut() {
+                Some(Node::ListItem(item)) => item.children.push(list(child)),
+                _ => items.push(Node::ListItem(ListItem {
+                    children: vec![list(child)],
+                    position: None,
+                    spread: false,
+                    checked: None,
+                })),
+            },
+            _ => {}
+        }
+    }
+    Node::List(List {
+        children: items,
+        position: None,
+        ordered,
+        start: ordered.then(|| {
+            element
+                .attr("start")
+                .and_then(|start| start.trim().parse().ok())
+                .unwrap_or(1)
+        }),
+        spread: false,
+    })
+}
+
+/// The state of a task list item's checkbox, an `<input type="checkbox">`
+/// leading the item or its first paragraph
+fn checkbox(item: &Element) -> Option<bool> {
+    for child in &item.children {
+        match child {
+            Dom::Text(text) if text.trim().is_empty() => {}
+            Dom::Element(e) if e.name == "input" => {
+                return e
+                    .attr("type")
+                    .is_some_and(|t| t.eq_ignore_ascii_case("checkbox"))
+                    .then(|| e.attr("checked").is_some());
+            }
+            Dom::Element(e) if matches!(e.name.as_str(), "p" | "label" | "span") => {
+                return checkbox(e);
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn code(pre: &Element) -> Node {
+    let inner = pre.elements().find(|e| e.name == "code");
+    let lang = [Some(pre), inner].into_iter().flatten().find_map(language);
+    let text = text_content(pre);
+    // A line break right after `<pre>` isn't part of the content
+    let text = text.strip_prefix('\n').unwrap_or(&text);
+    Node::Code(Code {
+        value: text.trim_end_matches('\n').to_string(),
+        position: None,
+        lang,
+        meta: None,
+    })
+}
+
+/// The code language of a `language-x` or `lang-x` class, or a `lang`
+/// attribute
+fn language(element: &Element) -> Option<String> {
+    element
+        .attr("class")
+        .into_iter()
+        .flat_map(str::split_whitespace)
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .or_else(|| element.attr("lang"))
+        .or_else(|| element.attr("data-lang"))
+        .filter(|lang| !lang.is_empty())
+        .map(str::to_string)
+}
+
+/// A table, its rows padded to the widest; the first row is the header
+fn table(element: &Element) -> Option<Node> {
+    let mut rows = Vec::new();
+    collect_rows(element, &mut rows);
+    let width = rows.iter().map(Vec::len).max().filter(|&w| w > 0)?;
+    let align = (0..width)
+        .map(|column| {
+            rows[0]
+                .get(column)
+                .map_or(AlignKind::None, |c| alignment(c))
+        })
+        .collect();
+    let children = rows
+        .iter()
+        .map(|cells| {
+            let mut children: Vec<Node> = cells
+                .iter()
+                .map(|cell| {
+                    Node::TableCell(TableCell {
+                        children: tidy(inlines(&cell.children)),
+                        position: None,
+                    })
+                })
+                .collect();
+            children.resize_with(width, || {
+                Node::TableCell(TableCell {
+                    children: Vec::new(),
+                    position: None,
+                })
+            });
+            Node::TableRow(TableRow {
+                children,
+                position: None,
+            })
+        })
+        .collect();
+    Some(Node::Table(Table {
+        children,
+        position: None,
+        align,
+    }))
+}
+
+/// The cells of each row, through `<thead>`, `<tbody>` and `<tfoot>` but
+/// not into nested tables
+fn collect_rows<'a>(element: &'a Element, rows: &mut Vec<Vec<&'a Element>>) {
+    for child in element.elements() {
+        match child.name.as_str() {
+            "tr" => {
+                let cells: Vec<&Element> = child
+                    .elements()
+                    .filter(|cell| cell.name == "td" || cell.name == "th")
+                    .collect();
+                if !cells.is_empty() {
+                    rows.push(cells);
+                }
+            }
+            "thead" | "tbody" | "tfoot" => collect_rows(child, rows),
+            _ => {}
+        }
+    }
+}
+
+fn alignment(cell: &Element) -> AlignKind {
+    let align = cell
+        .attr("align")
+        .map(str::to_lowercase)
+        .or_else(|| cell.style("text-align"));
+    match align.as_deref() {
+        Some("left") => AlignKind::Left,
+        Some("center") => AlignKind::Center,
+        Some("right") => AlignKind::Right,
+        _ => AlignKind::None,
+    }
+}
+
+fn inlines(children: &[Dom]) -> Vec<Node> {
+    children.iter().flat_map(inline).collect()
+}
+
+fn inline(dom: &Dom) -> Vec<Node> {
+    let element = match dom {
+        Dom::Text(text) => return vec![text_node(collapse_whitespace(text))],
+        Dom::Element(element) => element,
+    };
+    match element.name.as_str() {
+        name if SKIPPED.contains(&name) => Vec::new(),
+        "input" => Vec::new(),
+        "br" => vec![Node::Break(Break { position: None })],
+        "img" => element
+            .attr("src")
+            .map(|src| {
+                Node::Image(Image {
+                    position: None,
+                    alt: collapse_whitespace(element.attr("alt").unwrap_or_default()),
+                    url: src.trim().to_string(),
+                    title: element.attr("title").map(str::to_string),
+                })
+            })
+            .into_iter()
+            .collect(),
+        "a" => match element.attr("href") {
+            Some(href) => vec![Node::Link(Link {
+                children: inlines(&element.children),
+                position: None,
+                url: href.trim().to_string(),
+                title: element.attr("title").map(str::to_string),
+            })],
+            None => inlines(&element.children),
+        },
+        "code" | "kbd" | "samp" | "tt" => {
+            let value = collapse_whitespace(&text_content(element));
+            if value.trim().is_empty() {
+                vec![text_node(value)]
+            } else {
+                vec![Node::InlineCode(InlineCode {
+                    value,
+                    position: None,
+                })]
+            }
+        }
+        // Blocks inside inline content (a paragraph in a table cell) run
+        // together, kept apart by a space
+        name if !is_inline(name) => {
+            let mut children = inlines(&element.children);
+            children.push(text_node(" ".to_string()));
+            children
+        }
+        _ => styled(element, inlines(&element.children)),
+    }
+}
+
+/// `children` with the emphasis the element's tag or inline style gives
+/// them; a `font-weight` or `font-style` in the style wins over the tag
+fn styled(element: &Element, children: Vec<Node>) -> Vec<Node> {
+    let name = element.name.as_str();
+    let bold = match element.style("font-weight").as_deref() {
+        Some("bold" | "bolder") => true,
+        Some(weight) => weight.parse::<u16>().is_ok_and(|weight| weight >= 600),
+        None => matches!(name, "b" | "strong"),
+    };
+    let italic = match element.style("font-style").as_deref() {
+        Some(style) => style == "italic" || style == "oblique",
+        None => matches!(name, "i" | "em" | "cite" | "dfn" | "var"),
+    };
+    let struck = matches!(name, "del" | "s" | "strike")
+        || element
+            .style("text-decoration")
+            .or_else(|| element.style("text-decoration-line"))
+            .is_some_and(|decoration| decoration.contains("line-through"));
+
+    let mut children = children;
+    if struck {
+        children = vec![Node::Delete(Delete {
+            children,
+            position: None,
+        })];
+    }
+    if italic {
+        children = vec![Node::Emphasis(Emphasis {
+            children,
+            position: None,
+        })];
+    }
+    if bold {
+        children = vec![Node::Strong(Strong {
+            children,
+            position: None,
+        })];
+    }
+    children
+}
+
+fn text_node(value: String) -> Node {
+    Node::Text(Text {
+        value,
+        position: None,
+    })
+}
+
+/// The text inside an element as written, with `<br>` as a line break
+fn text_content(element: &Element) -> String {
+    let mut out = String::new();
+    for child in &element.children {
+        match child {
+            Dom::Text(text) => out.push_str(text),
+            Dom::Element(e) if e.name == "br" => out.push('\n'),
+            Dom::Element(e) if e.name == "script" || e.name == "style" => {}
+            Dom::Element(e) => out.push_str(&text_content(e)),
+        }
+    }
+    out
+}
+
+/// Runs of whitespace as single spaces, the way HTML displays text
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+            }
+            space = false;
+            out.push(c);
+        }
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+/// Inline content with the spaces HTML wouldn't display removed, spaces
+/// moved out of emphasis and links, and empty elements left out
+fn tidy(nodes: Vec<Node>) -> Vec<Node> {
+    let mut nodes = hoist_spaces(nodes);
+    collapse_spaces(&mut nodes, &mut true);
+    trim_end(&mut nodes);
+    prune(&mut nodes);
+    nodes
+}
+
+fn is_container(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Strong(_) | Node::Emphasis(_) | Node::Delete(_) | Node::Link(_)
+    )
+}
+
+/// Move spaces at the edges of emphasis and links outside them, since
+/// `** bold**` isn't bold in markdown
+fn hoist_spaces(nodes: Vec<Node>) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    for mut node in nodes {
+        if !is_container(&node) {
+            out.push(node);
+            continue;
+        }
+        let children = node.children_mut().expect("containers have children");
+        *children = hoist_spaces(std::mem::take(children));
+        let mut leading = false;
+        if let Some(Node::Text(text)) = children.first_mut()
+            && text.value.starts_with(' ')
+        {
+            text.value = text.value.trim_start().to_string();
+            leading = true;
+        }
+        let mut trailing = false;
+        if let Some(Node::Text(text)) = children.last_mut()
+            && text.value.ends_with(' ')
+        {
+            text.value = text.value.trim_end().to_string();
+            trailing = true;
+        }
+        if leading {
+            out.push(text_node(" ".to_string()));
+        }
+        out.push(node);
+        if trailing {
+            out.push(text_node(" ".to_string()));
+        }
+    }
+    out
+}
+
+/// Drop spaces that follow a space, a line break or the start of the content
+fn collapse_spaces(nodes: &mut [Node], at_space: &mut bool) {
+    for node in nodes {
+        if let Node::Text(text) = node {
+            if *at_space {
+                text.value = text.value.trim_start().to_string();
+            }
+            if !text.value.is_empty() {
+                *at_space = text.value.ends_with(' ');
+            }
+        } else if let Node::Break(_) = node {
+            *at_space = true;
+        } else if is_container(node) {
+            collapse_spaces(
+                node.children_mut().expect("containers have children"),
+                at_space,
+            );
+        } else {
+            *at_space = false;
+        }
+    }
+}
+
+/// Drop trailing spaces and line breaks
+fn trim_end(nodes: &mut Vec<Node>) {
+    while let Some(last) = nodes.last_mut() {
+        match last {
+            Node::Text(text) => {
+                text.value.truncate(text.value.trim_end().len());
+                if !text.value.is_empty() {
+                    return;
+                }
+            }
+            Node::Break(_) => {}
+            _ if is_container(last) => {
+                let children = last.children_mut().expect("containers have children");
+                trim_end(children);
+                if !children.is_empty() {
+                    return;
+                }
+            }
+            _ => return,
+        }
+        nodes.pop();
+    }
+}
+
+/// Leave out empty text and emphasis, and join neighboring text
+fn prune(nodes: &mut Vec<Node>) {
+    let mut out: Vec<Node> = Vec::with_capacity(nodes.len());
+    for mut node in nodes.drain(..) {
+        if is_container(&node) {
+            let children = node.children_mut().expect("containers have children");
+            prune(children);
+            if children.is_empty() {
+                continue;
+            }
+        }
+        match (&node, out.last_mut()) {
+            (Node::Text(text), _) if text.value.is_empty() => continue,
+            (Node::Text(text), Some(Node::Text(previous))) => {
+                previous.value.push_str(&text.value);
+                continue;
+            }
+            _ => {}
+        }
+        out.push(node);
+    }
+    *nodes = out;
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 32)
+            .and_then(|end| entity(&rest[..end]).map(|c| (c, end + 1)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The character of a numeric or common named entity; a non-breaking
+/// space reads as a space
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "times" => '×',
+        "divide" => '÷',
+        "deg" => '°',
+        "euro" => '€',
+        "pound" => '£',
+        "sect" => '§',
+        "larr" => '←',
+        "rarr" => '→',
+        _ => return None,
+    })
+}
+
+/// Backslash-escape text that markdown would read as syntax, so the source
+/// parses back to the same text
+fn escape_text(node: &mut Node) {
+    if let Node::Text(text) = node {
+        let mut escaped = String::with_capacity(text.value.len());
+        for c in text.value.chars() {
+            if matches!(
+                c,
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '&' | '~'
+            ) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        text.value = escaped;
+        return;
+    }
+    for child in node.children_mut().into_iter().flatten() {
+        escape_text(child);
+    }
+    // A paragraph that would start a heading or list item
+    if let Node::Paragraph(paragraph) = node
+        && let Some(Node::Text(text)) = paragraph.children.first_mut()
+    {
+        let digits = text.value.len()
+            - text
+                .value
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if text.value.starts_with(['#', '-', '+']) {
+            text.value.insert(0, '\\');
+        } else if digits > 0 && text.value[digits..].starts_with(['.', ')']) {
+            text.value.insert(digits, '\\');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(html: &str) -> String {
+        html_to_markdown(html)
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            markdown(
+                "<h2>Notes</h2><p>Some <b>bold</b> and <a href=\"https://x.io\" title=\"X\">a link</a>\n\
+                 <p>Then <code>code</code><br>and more\
+                 <ul><li>one<li>two<ul><li>nested</ul></ul>\
+                 <ol start=3><li><input type=checkbox checked> done</li></ol>"
+            ),
+            "## Notes\n\nSome **bold** and [a link](https://x.io \"X\")\n\n\
+             Then `code`  \nand more\n\n- one\n- two\n    - nested\n\n3. [x] done\n"
+        );
+    }
+
+    #[test]
+    fn test_code_and_tables() {
+        let md = markdown(
+            "<pre><code class=\"language-rust\">fn main() {\n    &lt;&gt;\n}\n</code></pre>\
+             <table><thead><tr><th>A</th><th align=right>B</th></tr></thead>\
+             <tbody><tr><td>1</td></tr></tbody></table>",
+        );
+        assert!(md.starts_with("```rust\nfn main() {\n    <>\n}\n```\n"));
+        assert!(md.ends_with("| A   | B   |\n| --- | --: |\n| 1   |     |\n"));
+    }
+
+    #[test]
+    fn test_google_docs_styles() {
+        // Docs wraps the clipboard in a bold tag that isn't bold, and marks
+        // emphasis on spans
+        let html = "<meta charset=\"utf-8\"><b style=\"font-weight:normal;\" id=\"docs-internal-guid-1\">\
+                    <p dir=\"ltr\"><span style=\"font-weight:700\">Bold </span>\
+                    <span style=\"font-style:italic\">italic</span>\
+                    <span style=\"text-decoration:line-through\">gone</span></p></b>";
+        assert_eq!(markdown(html), "**Bold** *italic*~~gone~~\n");
+    }
+
+    #[test]
+    fn test_title_and_escaping() {
+        assert_eq!(
+            markdown(
+                "<html><head><title>My &quot;Doc&quot;</title><style>p{}</style></head>\
+                 <body><p>1. not a *list* &amp; [text]</p><script>x()</script></body></html>"
+            ),
+            "---\ntitle: \"My \\\"Doc\\\"\"\n---\n\n1\\. not a \\*list\\* \\& \\[text\\]\n"
+        );
+    }
+
+    #[test]
+    fn test_html_to_mdast() {
+        let ast =
+            html_to_mdast("<blockquote><p>Quoted<p>Twice</blockquote><hr><img src=a.png alt=A>");
+        let children = ast.children().unwrap();
+        assert!(matches!(&children[0], Node::Blockquote(quote) if quote.children.len() == 2));
+        assert!(matches!(children[1], Node::ThematicBreak(_)));
+        assert!(
+            matches!(&children[2], Node::Paragraph(p) if matches!(&p.children[0], Node::Image(image) if image.url == "a.png" && image.alt == "A"))
+        );
+    }
+}
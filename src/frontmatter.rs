@@ -0,0 +1,165 @@
+use markdown::mdast::Node;
+
+/// YAML front matter taken off the top of a document
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FrontMatter {
+    /// The YAML between the `---` fences
+    pub raw: String,
+    pub title: Option<String>,
+    /// Authors, joined with ", " when given as a list
+    pub author: Option<String>,
+    /// Bibliography files, joined with ", " when given as a list
+    pub bibliography: Option<String>,
+}
+
+impl FrontMatter {
+    /// The front matter as written, fences included
+    pub fn to_markdown(&self) -> String {
+        format!("---\n{}\n---\n", self.raw)
+    }
+}
+
+/// The source without a leading `#!` interpreter line, so a markdown file
+/// starting with `#!/usr/bin/env mdcopy` can be run as a script
+pub fn strip_shebang(source: String) -> String {
+    if !source.starts_with("#!/") {
+        return source;
+    }
+    match source.find('\n') {
+        Some(end) => source[end + 1..].to_string(),
+        None => String::new(),
+    }
+}
+
+/// Remove the front matter node from the document, returning it along with
+/// the byte offset where the rest of the source starts
+pub fn take_front_matter(root: &mut Node) -> Option<(FrontMatter, usize)> {
+    let children = root.children_mut()?;
+    let Some(Node::Yaml(yaml)) = children.first() else {
+        return None;
+    };
+    let end = yaml.position.as_ref().map_or(0, |p| p.end.offset);
+    let front_matter = parse(&yaml.value);
+    children.remove(0);
+    Some((front_matter, end))
+}
+
+/// Read the `title` and `author` fields. Only top-level scalars and simple
+/// `- item` lists are understood, which covers what documents put there.
+fn parse(raw: &str) -> FrontMatter {
+    let mut front_matter = FrontMatter {
+        raw: raw.to_string(),
+        ..FrontMatter::default()
+    };
+    let mut lines = raw.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with([' ', '\t', '#', '-']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let slot = match key.trim() {
+            "title" => &mut front_matter.title,
+            "author" | "authors" => &mut front_matter.author,
+            "bibliography" => &mut front_matter.bibliography,
+            _ => continue,
+        };
+        let value = value.trim();
+        let value = if value.is_empty() {
+            // Block list on the following lines
+            let mut items = Vec::new();
+            while let Some(item) = lines.next_if(|l| l.trim_start().starts_with('-')) {
+                items.push(scalar(&item.trim_start()[1..]));
+            }
+            items.join(", ")
+        } else if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            inner.split(',').map(scalar).collect::<Vec<_>>().join(", ")
+        } else {
+            scalar(value)
+        };
+        if !value.is_empty() && slot.is_none() {
+            *slot = Some(value);
+        }
+    }
+    front_matter
+}
+
+/// A plain or quoted YAML scalar
+fn scalar(s: &str) -> String {
+    let s = s.trim();
+    if let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        inner.replace("\\\"", "\"")
+    } else if let Some(inner) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        inner.replace("''", "'")
+    } else {
+        // Drop a trailing comment
+        s.split(" #").next().unwrap_or(s).trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::{Constructs, ParseOptions};
+
+    fn parse_markdown(md: &str) -> Node {
+        let options = ParseOptions {
+            constructs: Constructs {
+                frontmatter: true,
+                ..Constructs::gfm()
+            },
+            ..ParseOptions::default()
+        };
+        markdown::to_mdast(md, &options).unwrap()
+    }
+
+    #[test]
+    fn test_take_front_matter() {
+        let md = "---\ntitle: \"Release: v2\"\nauthor:\n  - Ada\n  - 'Grace'\ntags: [a]\n---\n\n# Body\n";
+        let mut ast = parse_markdown(md);
+        let (front_matter, end) = take_front_matter(&mut ast).unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Release: v2"));
+        assert_eq!(front_matter.author.as_deref(), Some("Ada, Grace"));
+        assert_eq!(md[end..].trim(), "# Body");
+        assert!(matches!(ast.children().unwrap()[0], Node::Heading(_)));
+        assert!(front_matter.to_markdown().starts_with("---\ntitle:"));
+
+        let mut plain = parse_markdown("# No front matter\n");
+        assert!(take_front_matter(&mut plain).is_none());
+    }
+
+    #[test]
+    fn test_section_after_front_matter() {
+        // `--section` slices the source with the section's range, which
+        // counts the front matter bytes
+        let md =
+            "---\ntitle: Guide\nauthor: Ada Lovelace\n---\n\n# Intro\n\nHi\n\n## Usage\n\nRun it\n";
+        let mut ast = parse_markdown(md);
+        let (_, end) = take_front_matter(&mut ast).unwrap();
+        let range = crate::transform::extract_section(&mut ast, "Usage", None).unwrap();
+        assert!(range.start > end);
+        assert_eq!(&md[range], "## Usage\n\nRun it");
+    }
+
+    #[test]
+    fn test_strip_shebang() {
+        let md = "#!/usr/bin/env -S mdcopy -f html\n---\ntitle: Notes\n---\n";
+        assert_eq!(strip_shebang(md.to_string()), "---\ntitle: Notes\n---\n");
+        assert_eq!(strip_shebang("#!/usr/bin/env mdcopy".to_string()), "");
+        assert_eq!(strip_shebang("#!important\n".to_string()), "#!important\n");
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let front_matter = parse("title: Notes # draft\nauthors: [Ada, \"Grace\"]\n");
+        assert_eq!(front_matter.title.as_deref(), Some("Notes"));
+        assert_eq!(front_matter.author.as_deref(), Some("Ada, Grace"));
+        assert_eq!(parse("date: 2024-01-01").title, None);
+        let front_matter = parse("bibliography:\n  - refs.bib\n  - more.json\n");
+        assert_eq!(
+            front_matter.bibliography.as_deref(),
+            Some("refs.bib, more.json")
+        );
+    }
+}
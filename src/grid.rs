@@ -0,0 +1,93 @@
+//! Tables laid out as monospace text with box-drawing borders, for outputs
+//! that can't hold a real table (or a large one).
+
+use markdown::mdast::{AlignKind, Node, Table};
+
+/// The cells of `table` as plain text, one row per header or body row
+pub fn table_cells(table: &Table) -> Vec<Vec<String>> {
+    table
+        .children
+        .iter()
+        .map(|row| {
+            row.children()
+                .into_iter()
+                .flatten()
+                .map(|cell| Node::to_string(cell).replace('\n', " "))
+                .collect()
+        })
+        .collect()
+}
+
+/// `rows` in a box-drawing grid, the first row as the header. Columns are
+/// padded to their widest cell and aligned as `align` says.
+pub fn table_grid(rows: &[Vec<String>], align: &[AlignKind]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+    let mut widths = vec![0; columns];
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(cell.chars().count());
+        }
+    }
+
+    let rule = |left: &str, middle: &str, right: &str| {
+        let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}\n", left, lines.join(middle), right)
+    };
+    let mut out = rule("┌", "┬", "┐");
+    for (index, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = (0..columns)
+            .map(|column| {
+                let cell = row.get(column).map_or("", String::as_str);
+                let align = align.get(column).unwrap_or(&AlignKind::None);
+                format!(" {} ", pad(cell, widths[column], align))
+            })
+            .collect();
+        out.push_str(&format!("│{}│\n", cells.join("│")));
+        if index == 0 && rows.len() > 1 {
+            out.push_str(&rule("├", "┼", "┤"));
+        }
+    }
+    out.push_str(&rule("└", "┴", "┘"));
+    out
+}
+
+fn pad(cell: &str, width: usize, align: &AlignKind) -> String {
+    let fill = width - cell.chars().count();
+    let (left, right) = match align {
+        AlignKind::Right => (fill, 0),
+        AlignKind::Center => (fill / 2, fill - fill / 2),
+        AlignKind::Left | AlignKind::None => (0, fill),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    #[test]
+    fn test_table_grid() {
+        let ast = markdown::to_mdast(
+            "| Name | Qty |\n|---|--:|\n| **apple** | 3 |\n| fig | 12 |\n",
+            &ParseOptions::gfm(),
+        )
+        .unwrap();
+        let Node::Table(table) = &ast.children().unwrap()[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            table_grid(&table_cells(table), &table.align),
+            "┌───────┬─────┐\n\
+             │ Name  │ Qty │\n\
+             ├───────┼─────┤\n\
+             │ apple │   3 │\n\
+             │ fig   │  12 │\n\
+             └───────┴─────┘\n"
+        );
+        assert_eq!(table_grid(&[], &[]), "");
+    }
+}
@@ -1,24 +1,298 @@
+use crate::config::HighlightConfig;
 use log::{debug, info, trace, warn};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use syntect::highlighting::{Theme, ThemeSet};
+use markdown::mdast::Node;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, FontStyle, Style, Theme, ThemeSet};
 use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Maximum number of fallback hops before giving up (guards against cycles)
+const MAX_FALLBACK_DEPTH: usize = 8;
+
+/// WCAG AA minimum contrast ratio for normal-size text
+pub const MIN_CONTRAST_RATIO: f64 = 4.5;
+
+/// Background the HTML output uses for themes that don't set one
+const FALLBACK_BACKGROUND: Color = Color {
+    r: 0x2b,
+    g: 0x30,
+    b: 0x3b,
+    a: 0xff,
+};
+
+/// Foreground the HTML output uses for themes that don't set one
+const FALLBACK_FOREGROUND: Color = Color {
+    r: 0xc0,
+    g: 0xc5,
+    b: 0xce,
+    a: 0xff,
+};
+
+/// Opacity of shell prompts over the block's background
+const PROMPT_ALPHA: u8 = 0x80;
+
+/// Languages of shell session transcripts: commands after `$` or `#`
+/// prompts, interleaved with their output
+const SESSION_LANGUAGES: &[&str] = &["console", "shellsession", "shell-session", "sh-session"];
+
+/// Highlighting that loads its syntaxes and theme on first use, so copying
+/// prose without code blocks never pays for them
+pub struct LazyHighlight {
+    cfg: HighlightConfig,
+    theme: String,
+    loaded: OnceLock<Option<HighlightContext>>,
+}
+
+impl LazyHighlight {
+    /// Highlighting with `theme`, the configured theme for the appearance
+    /// in use
+    pub fn new(cfg: &HighlightConfig, theme: &str) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            theme: theme.to_string(),
+            loaded: OnceLock::new(),
+        }
+    }
+
+    /// The context, loading it on the first call; None when no theme could
+    /// be loaded
+    pub fn get(&self) -> Option<&HighlightContext> {
+        self.loaded
+            .get_or_init(|| {
+                HighlightContext::new(
+                    &self.theme,
+                    &self.cfg.languages,
+                    &self.cfg.fallbacks,
+                    &self.cfg.get_themes_dirs(),
+                    &self.cfg.get_syntaxes_dirs(),
+                )
+                .map(|hl| hl.detect_languages(self.cfg.detect_language))
+            })
+            .as_ref()
+    }
+
+    /// The context if an earlier `get` loaded it
+    pub fn loaded(&self) -> Option<&HighlightContext> {
+        self.loaded.get().and_then(Option::as_ref)
+    }
+}
+
+/// Whether `ast` has a code block to highlight
+pub fn has_code_blocks(ast: &Node) -> bool {
+    matches!(ast, Node::Code(_))
+        || ast
+            .children()
+            .is_some_and(|children| children.iter().any(has_code_blocks))
+}
+
+/// Background of code blocks rendered without a theme
+pub const PLAIN_BACKGROUND: Color = Color {
+    r: 0xf6,
+    g: 0xf8,
+    b: 0xfa,
+    a: 0xff,
+};
+
+/// Tints laid over a code block's background for added and removed lines
+const DIFF_ADDED_TINT: Color = Color {
+    r: 0x2e,
+    g: 0xa0,
+    b: 0x43,
+    a: 0x40,
+};
+
+const DIFF_REMOVED_TINT: Color = Color {
+    r: 0xf8,
+    g: 0x51,
+    b: 0x49,
+    a: 0x40,
+};
+
+/// How a line of a diff code block changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Removed,
+}
+
+impl LineChange {
+    /// Background of the line: a green or red tint of the block's
+    /// `background`
+    pub fn background(self, background: Color) -> Color {
+        let tint = match self {
+            LineChange::Added => DIFF_ADDED_TINT,
+            LineChange::Removed => DIFF_REMOVED_TINT,
+        };
+        blend(tint, background)
+    }
+}
+
+/// A code block split into lines, each with its change when the block is a
+/// diff. `diff` (or `patch`) blocks keep their `+`/`-` markers and highlight
+/// as diffs; `rust,diff` style blocks lose the marker column and highlight
+/// as the language before the comma.
+pub struct CodeLines<'a> {
+    /// Language to highlight and label the block with
+    pub lang: Option<&'a str>,
+    /// Lines with their endings
+    pub lines: Vec<(&'a str, Option<LineChange>)>,
+}
+
+impl<'a> CodeLines<'a> {
+    pub fn new(lang: Option<&'a str>, code: &'a str) -> Self {
+        let (lang, markers) = diff_language(lang);
+        let lines = LinesWithEndings::from(code)
+            .map(|line| match markers {
+                DiffMarkers::None => (line, None),
+                DiffMarkers::Kept => (line, marked_change(line, true)),
+                DiffMarkers::Stripped => {
+                    let text = match line.as_bytes().first() {
+                        Some(b'+' | b'-' | b' ') => &line[1..],
+                        _ => line,
+                    };
+                    (text, marked_change(line, false))
+                }
+            })
+            .collect();
+        Self { lang, lines }
+    }
+
+    /// The language a block with the info string `lang` highlights as
+    pub fn language(lang: Option<&str>) -> Option<&str> {
+        diff_language(lang).0
+    }
+}
+
+enum DiffMarkers {
+    None,
+    Kept,
+    Stripped,
+}
+
+fn diff_language(lang: Option<&str>) -> (Option<&str>, DiffMarkers) {
+    let is_diff = |lang: &str| {
+        let lang = lang.trim();
+        lang.eq_ignore_ascii_case("diff") || lang.eq_ignore_ascii_case("patch")
+    };
+    match lang {
+        Some(lang) if is_diff(lang) => (Some(lang), DiffMarkers::Kept),
+        Some(lang) => match lang.rsplit_once(',') {
+            Some((base, diff)) if is_diff(diff) => {
+                let base = base.trim();
+                ((!base.is_empty()).then_some(base), DiffMarkers::Stripped)
+            }
+            _ => (Some(lang), DiffMarkers::None),
+        },
+        None => (None, DiffMarkers::None),
+    }
+}
+
+/// The change a diff line's marker stands for. In a full diff, `+++` and
+/// `---` file headers aren't changes.
+fn marked_change(line: &str, full_diff: bool) -> Option<LineChange> {
+    if full_diff && (line.starts_with("+++") || line.starts_with("---")) {
+        return None;
+    }
+    match line.as_bytes().first() {
+        Some(b'+') => Some(LineChange::Added),
+        Some(b'-') => Some(LineChange::Removed),
+        _ => None,
+    }
+}
+
+/// Highlights a code block line by line
+pub struct CodeHighlighter<'a> {
+    lines: HighlightLines<'a>,
+    syntax_set: &'a SyntaxSet,
+    /// Styles of a shell session's prompts and output
+    session: Option<Session>,
+}
+
+struct Session {
+    prompt: Style,
+    output: Style,
+    /// The last command ended in `\` and goes on in the next line
+    continues: bool,
+}
+
+impl CodeHighlighter<'_> {
+    /// Styled pieces of `line`, the next line of the block
+    pub fn highlight_line<'l>(
+        &mut self,
+        line: &'l str,
+    ) -> Result<Vec<(Style, &'l str)>, syntect::Error> {
+        let Some(session) = &mut self.session else {
+            return self.lines.highlight_line(line, self.syntax_set);
+        };
+        let (prompt, command) = match split_prompt(line) {
+            Some((prompt, command)) => (Some(prompt), command),
+            None if session.continues => (None, line),
+            None => return Ok(vec![(session.output, line)]),
+        };
+        session.continues = command.trim_end().ends_with('\\');
+        let prompt = prompt.map(|prompt| (session.prompt, prompt));
+        let command = self.lines.highlight_line(command, self.syntax_set)?;
+        Ok(prompt.into_iter().chain(command).collect())
+    }
+}
+
+/// A shell prompt line split after its `$ ` or `# ` prompt, which may
+/// follow a `user@host:~` style location and a `(venv)` environment
+fn split_prompt(line: &str) -> Option<(&str, &str)> {
+    let rest = match line.strip_prefix('(') {
+        Some(env) => env.split_once(") ")?.1,
+        None => line,
+    };
+    let location = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let end = line.len() - rest.len() + location;
+    if !line[..end].ends_with(['$', '#']) {
+        return None;
+    }
+    let end = if line[end..].starts_with(' ') {
+        end + 1
+    } else {
+        end
+    };
+    Some(line.split_at(end))
+}
+
+/// A theme color pair below the minimum contrast ratio
+#[derive(Debug, PartialEq)]
+pub struct ContrastIssue {
+    /// Scope selectors using the pair (`text` for the default color)
+    pub scopes: Vec<String>,
+    pub foreground: Color,
+    pub background: Color,
+    pub ratio: f64,
+}
 
 pub struct HighlightContext {
     pub syntax_set: SyntaxSet,
     pub theme: Theme,
     language_map: HashMap<String, String>,
+    fallback_map: HashMap<String, String>,
+    /// Guess the language of code blocks without one
+    detect_language: bool,
+    /// Languages already reported as missing, so each is only warned about once per run
+    missing_reported: Mutex<HashSet<String>>,
 }
 
 impl HighlightContext {
     pub fn new(
         theme_name: &str,
         language_map: &HashMap<String, String>,
-        themes_dir: Option<&PathBuf>,
-        syntaxes_dir: Option<&PathBuf>,
+        fallback_map: &HashMap<String, String>,
+        themes_dirs: &[PathBuf],
+        syntaxes_dirs: &[PathBuf],
     ) -> Option<Self> {
-        let syntax_set = load_syntax_set(syntaxes_dir);
-        let theme_set = load_theme_set(themes_dir);
+        let cache_dir = dirs::cache_dir().map(|p| p.join("mdcopy").join("syntaxes"));
+        let syntax_set = load_syntax_set(syntaxes_dirs, cache_dir.as_deref());
+        let theme_set = load_theme_set(themes_dirs);
 
         let theme = theme_set.themes.get(theme_name).cloned().or_else(|| {
             warn!(
@@ -40,79 +314,322 @@ impl HighlightContext {
                 syntax_set,
                 theme,
                 language_map: language_map.clone(),
+                fallback_map: fallback_map.clone(),
+                detect_language: false,
+                missing_reported: Mutex::new(HashSet::new()),
             }
         })
     }
 
-    /// Find syntax for a language, using the language map for aliases
+    /// Find syntax for a language, using the language map for aliases and
+    /// following the fallback map (e.g. `svelte -> html`) when no grammar matches
     pub fn find_syntax(&self, lang: &str) -> &SyntaxReference {
+        self.resolve_syntax(lang).unwrap_or_else(|| {
+            self.report_missing(lang);
+            self.syntax_set.find_syntax_plain_text()
+        })
+    }
+
+    /// Guess the language of code blocks without one
+    pub fn detect_languages(mut self, detect: bool) -> Self {
+        self.detect_language = detect;
+        self
+    }
+
+    /// Syntax for a code block in `lang`, or for one without a language,
+    /// a guess from its `code` when detection is on and plain text otherwise
+    fn syntax_for(&self, lang: Option<&str>, code: &str) -> &SyntaxReference {
+        match lang {
+            Some(lang) => self.find_syntax(lang),
+            None => self
+                .detect_syntax(code)
+                .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text()),
+        }
+    }
+
+    /// The syntax a first line such as a shebang or `<?xml` matches, then
+    /// the detector's guess
+    fn detect_syntax(&self, code: &str) -> Option<&SyntaxReference> {
+        if !self.detect_language {
+            return None;
+        }
+        let first_line = code.lines().next()?;
+        let syntax = self
+            .syntax_set
+            .find_syntax_by_first_line(first_line)
+            .or_else(|| {
+                crate::detect::detect_language(code).and_then(|lang| self.resolve_syntax(lang))
+            })?;
+        debug!(
+            "Detected {} in a code block without a language",
+            syntax.name
+        );
+        Some(syntax)
+    }
+
+    /// Highlighter for the lines of a code block in `lang`. Shell sessions
+    /// get dim prompts, highlighted commands and unstyled output.
+    pub fn highlighter(&self, lang: Option<&str>, code: &str) -> CodeHighlighter<'_> {
+        let session = match lang {
+            Some(lang) => self.is_session(lang),
+            None => self.detect_language && crate::detect::detect_language(code) == Some("console"),
+        };
+        if !session {
+            return CodeHighlighter {
+                lines: HighlightLines::new(self.syntax_for(lang, code), &self.theme),
+                syntax_set: &self.syntax_set,
+                session: None,
+            };
+        }
+        let foreground = self
+            .theme
+            .settings
+            .foreground
+            .unwrap_or(FALLBACK_FOREGROUND);
+        let style = |foreground| Style {
+            foreground,
+            background: self.background(),
+            font_style: FontStyle::empty(),
+        };
+        let prompt = Color {
+            a: PROMPT_ALPHA,
+            ..foreground
+        };
+        let shell = self
+            .resolve_syntax("bash")
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        CodeHighlighter {
+            lines: HighlightLines::new(shell, &self.theme),
+            syntax_set: &self.syntax_set,
+            session: Some(Session {
+                prompt: style(blend(prompt, self.background())),
+                output: style(foreground),
+                continues: false,
+            }),
+        }
+    }
+
+    /// Whether `lang` is a shell session that no grammar covers
+    fn is_session(&self, lang: &str) -> bool {
+        let lang = lang.trim();
+        SESSION_LANGUAGES
+            .iter()
+            .any(|session| session.eq_ignore_ascii_case(lang))
+            && self.resolve_syntax(lang).is_none()
+    }
+
+    /// Background of code blocks in the theme
+    pub fn background(&self) -> Color {
+        self.theme
+            .settings
+            .background
+            .unwrap_or(FALLBACK_BACKGROUND)
+    }
+
+    /// Languages of the code blocks in `ast` that no grammar matches, even
+    /// through the fallback map, each once in the order they first appear
+    pub fn missing_languages(&self, ast: &Node) -> Vec<String> {
+        fn walk(ctx: &HighlightContext, node: &Node, missing: &mut Vec<String>) {
+            if let Node::Code(code) = node
+                && let Some(lang) = CodeLines::language(code.lang.as_deref())
+                && ctx.resolve_syntax(lang).is_none()
+                && !ctx.is_session(lang)
+                && !missing.iter().any(|m| m.eq_ignore_ascii_case(lang))
+            {
+                missing.push(lang.to_string());
+            }
+            for child in node.children().into_iter().flatten() {
+                walk(ctx, child, missing);
+            }
+        }
+        let mut missing = Vec::new();
+        walk(self, ast, &mut missing);
+        missing
+    }
+
+    fn resolve_syntax(&self, lang: &str) -> Option<&SyntaxReference> {
+        let mut current = lang.to_string();
+        for _ in 0..=MAX_FALLBACK_DEPTH {
+            if let Some(syntax) = self.lookup_syntax(&current) {
+                if current != lang {
+                    debug!("Highlighting '{}' using fallback '{}'", lang, current);
+                }
+                return Some(syntax);
+            }
+            current = self.fallback_map.get(&current.to_lowercase())?.clone();
+        }
+        None
+    }
+
+    /// Look up a syntax without consulting the fallback map
+    fn lookup_syntax(&self, lang: &str) -> Option<&SyntaxReference> {
         let lang_lower = lang.to_lowercase();
 
         // First try the mapped language name
         if let Some(mapped) = self.language_map.get(&lang_lower) {
             if let Some(syntax) = self.syntax_set.find_syntax_by_name(mapped) {
-                return syntax;
+                return Some(syntax);
             }
             // Also try as token (extension)
             if let Some(syntax) = self.syntax_set.find_syntax_by_token(mapped) {
-                return syntax;
+                return Some(syntax);
             }
         }
 
-        // Try direct lookup by token (handles extensions like "rs", "py")
-        if let Some(syntax) = self.syntax_set.find_syntax_by_token(lang) {
-            return syntax;
-        }
+        // Try direct lookup by token (handles extensions like "rs", "py"), then by name
+        self.syntax_set
+            .find_syntax_by_token(lang)
+            .or_else(|| self.syntax_set.find_syntax_by_name(lang))
+    }
 
-        // Try by name
-        if let Some(syntax) = self.syntax_set.find_syntax_by_name(lang) {
-            return syntax;
+    /// Warn (once per language) that a code block will be rendered as plain text
+    fn report_missing(&self, lang: &str) {
+        let mut reported = self.missing_reported.lock().unwrap();
+        if reported.insert(lang.to_lowercase()) {
+            warn!(
+                "No syntax found for language '{}', rendering as plain text. \
+                 Add a mapping under [highlight.fallbacks] to highlight it",
+                lang
+            );
         }
+    }
 
-        // Fall back to plain text
-        self.syntax_set.find_syntax_plain_text()
+    /// The bundled themes and those in `themes_dirs`, by name
+    pub fn load_themes(themes_dirs: &[PathBuf]) -> BTreeMap<String, Theme> {
+        load_theme_set(themes_dirs).themes
     }
 
-    pub fn list_themes(themes_dir: Option<&PathBuf>) -> Vec<String> {
-        let theme_set = load_theme_set(themes_dir);
+    pub fn list_themes(themes_dirs: &[PathBuf]) -> Vec<String> {
+        let theme_set = load_theme_set(themes_dirs);
         let mut themes: Vec<_> = theme_set.themes.keys().cloned().collect();
         themes.sort();
         themes
     }
 }
 
-fn get_config_dir() -> Option<PathBuf> {
-    dirs::config_local_dir().map(|p| p.join("mdcopy"))
+/// Check the default text color and every scope color of `theme` against its
+/// background, returning the pairs below the WCAG AA ratio. Scopes sharing a
+/// pair are reported together, in theme order.
+pub fn contrast_issues(theme: &Theme) -> Vec<ContrastIssue> {
+    let background = theme.settings.background.unwrap_or(FALLBACK_BACKGROUND);
+    let default = theme
+        .settings
+        .foreground
+        .map(|fg| (vec!["text".to_string()], fg, background));
+    let scopes = theme.scopes.iter().filter_map(|item| {
+        let scopes = item
+            .scope
+            .selectors
+            .iter()
+            .map(|s| s.path.to_string().trim_end().to_string())
+            .collect();
+        let bg = item.style.background.unwrap_or(background);
+        item.style.foreground.map(|fg| (scopes, fg, bg))
+    });
+
+    let mut issues: Vec<ContrastIssue> = Vec::new();
+    for (scopes, foreground, background) in default.into_iter().chain(scopes) {
+        let ratio = contrast_ratio(blend(foreground, background), background);
+        if ratio >= MIN_CONTRAST_RATIO {
+            continue;
+        }
+        match issues
+            .iter_mut()
+            .find(|i| i.foreground == foreground && i.background == background)
+        {
+            Some(issue) => issue.scopes.extend(scopes),
+            None => issues.push(ContrastIssue {
+                scopes,
+                foreground,
+                background,
+                ratio,
+            }),
+        }
+    }
+    issues
+}
+
+/// WCAG contrast ratio between two opaque colors, from 1 to 21
+pub fn contrast_ratio(a: Color, b: Color) -> f64 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+fn relative_luminance(c: Color) -> f64 {
+    let channel = |v: u8| {
+        let v = v as f64 / 255.0;
+        if v <= 0.03928 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(c.r) + 0.7152 * channel(c.g) + 0.0722 * channel(c.b)
 }
 
-fn load_syntax_set(custom_dir: Option<&PathBuf>) -> SyntaxSet {
-    // Determine the syntax directory to use
-    let syntax_dir = custom_dir
-        .cloned()
-        .or_else(|| get_config_dir().map(|p| p.join("syntaxes")));
+/// The color a translucent foreground shows as over `background`
+fn blend(fg: Color, background: Color) -> Color {
+    let alpha = fg.a as f64 / 255.0;
+    let mix = |f: u8, b: u8| (f as f64 * alpha + b as f64 * (1.0 - alpha)).round() as u8;
+    Color {
+        r: mix(fg.r, background.r),
+        g: mix(fg.g, background.g),
+        b: mix(fg.b, background.b),
+        a: 0xff,
+    }
+}
+
+/// Defaults plus the syntaxes in `syntax_dirs`, which are compiled once and
+/// then read back from a dump in `cache_dir` until the directories change
+fn load_syntax_set(syntax_dirs: &[PathBuf], cache_dir: Option<&Path>) -> SyntaxSet {
+    let syntax_dirs: Vec<&PathBuf> = syntax_dirs
+        .iter()
+        .filter(|dir| {
+            let exists = dir.is_dir();
+            if !exists {
+                trace!("No custom syntax directory at {:?}", dir);
+            }
+            exists
+        })
+        .collect();
 
     // Check if we have custom syntaxes to load
-    if let Some(syntax_dir) = syntax_dir {
-        if syntax_dir.is_dir() {
-            // Build a new syntax set with defaults + custom syntaxes
-            let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
-            match builder.add_from_folder(&syntax_dir, true) {
-                Ok(()) => {
-                    info!("Loaded custom syntaxes from {:?}", syntax_dir);
-                    let ss = builder.build();
-                    debug!("Total syntaxes loaded: {}", ss.syntaxes().len());
-                    return ss;
-                }
+    if !syntax_dirs.is_empty() {
+        let cached = cache_dir.map(|dir| dir.join(syntax_cache_name(&syntax_dirs)));
+        if let Some(ss) = cached.as_deref().and_then(read_syntax_cache) {
+            debug!(
+                "Loaded {} syntaxes from the cache for {:?}",
+                ss.syntaxes().len(),
+                syntax_dirs
+            );
+            return ss;
+        }
+
+        // Build a new syntax set with defaults + custom syntaxes
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        let mut complete = true;
+        for syntax_dir in &syntax_dirs {
+            match builder.add_from_folder(syntax_dir, true) {
+                Ok(()) => info!("Loaded custom syntaxes from {:?}", syntax_dir),
                 Err(e) => {
                     warn!(
                         "Failed to load custom syntaxes from {:?}: {}",
                         syntax_dir, e
                     );
+                    complete = false;
                 }
             }
-        } else {
-            trace!("No custom syntax directory at {:?}", syntax_dir);
         }
+        let ss = builder.build();
+        debug!("Total syntaxes loaded: {}", ss.syntaxes().len());
+        // A failed directory is retried, and warned about, next time
+        if let Some(path) = cached.filter(|_| complete) {
+            match write_syntax_cache(&ss, &path) {
+                Ok(()) => debug!("Cached compiled syntaxes at {:?}", path),
+                Err(e) => debug!("Failed to cache compiled syntaxes: {}", e),
+            }
+        }
+        return ss;
     }
 
     // Fall back to just defaults
@@ -121,41 +638,112 @@ fn load_syntax_set(custom_dir: Option<&PathBuf>) -> SyntaxSet {
     ss
 }
 
-fn load_theme_set(custom_dir: Option<&PathBuf>) -> ThemeSet {
+/// Extension of compiled syntax set dumps
+const SYNTAX_CACHE_EXTENSION: &str = "packdump";
+
+/// Cache file name for `syntax_dirs`: a digest of mdcopy's version, the
+/// directories' paths, and the number and newest modification time of the
+/// entries under them, so adding, editing or removing a syntax misses
+fn syntax_cache_name(syntax_dirs: &[&PathBuf]) -> String {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    let mut entries = 0;
+    let mut pending: Vec<PathBuf> = syntax_dirs.iter().map(|dir| dir.to_path_buf()).collect();
+    while let Some(dir) = pending.pop() {
+        if let Ok(modified) = fs::metadata(&dir).and_then(|m| m.modified()) {
+            newest = newest.max(modified);
+        }
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            entries += 1;
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                newest = newest.max(modified);
+            }
+        }
+    }
+    let key = format!(
+        "{}\n{:?}\n{}\n{:?}",
+        env!("CARGO_PKG_VERSION"),
+        syntax_dirs,
+        entries,
+        newest
+    );
+    format!(
+        "{}.{}",
+        &crate::image::content_digest(key.as_bytes())[..16],
+        SYNTAX_CACHE_EXTENSION
+    )
+}
+
+fn read_syntax_cache(path: &Path) -> Option<SyntaxSet> {
+    match syntect::dumps::from_uncompressed_dump_file(path) {
+        Ok(ss) => Some(ss),
+        Err(e) => {
+            if path.exists() {
+                debug!("Ignoring unreadable syntax cache {:?}: {}", path, e);
+            }
+            None
+        }
+    }
+}
+
+/// Dump `ss` to `path` through a temporary file, then remove any other
+/// dumps, so only the latest is kept
+fn write_syntax_cache(ss: &SyntaxSet, path: &Path) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let temp = tempfile::Builder::new()
+        .prefix(".partial-")
+        .tempfile_in(dir)?;
+    syntect::dumps::dump_to_uncompressed_file(ss, temp.path()).map_err(std::io::Error::other)?;
+    temp.persist(path).map_err(|e| e.error)?;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let stale = entry.path();
+        if stale != path
+            && stale
+                .extension()
+                .is_some_and(|ext| ext == SYNTAX_CACHE_EXTENSION)
+        {
+            let _ = fs::remove_file(stale);
+        }
+    }
+    Ok(())
+}
+
+/// Defaults plus the themes in `theme_dirs`, where later directories win
+/// over earlier ones for themes with the same name
+fn load_theme_set(theme_dirs: &[PathBuf]) -> ThemeSet {
     let mut theme_set = ThemeSet::load_defaults();
     debug!("Loaded {} default themes", theme_set.themes.len());
 
-    // Determine the theme directory to use
-    let theme_dir = custom_dir
-        .cloned()
-        .or_else(|| get_config_dir().map(|p| p.join("themes")));
-
     // Load custom themes
-    if let Some(theme_dir) = theme_dir {
-        if theme_dir.is_dir() {
-            if let Ok(entries) = std::fs::read_dir(&theme_dir) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.extension() == Some(std::ffi::OsStr::new("tmTheme")) {
-                        match ThemeSet::get_theme(&path) {
-                            Ok(theme) => {
-                                let name = path
-                                    .file_stem()
-                                    .and_then(|s| s.to_str())
-                                    .unwrap_or("unknown")
-                                    .to_string();
-                                info!("Loaded custom theme: {}", name);
-                                theme_set.themes.insert(name, theme);
-                            }
-                            Err(e) => {
-                                warn!("Failed to load theme {:?}: {}", path, e);
-                            }
-                        }
+    for theme_dir in theme_dirs {
+        if !theme_dir.is_dir() {
+            trace!("No custom theme directory at {:?}", theme_dir);
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(theme_dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension() == Some(std::ffi::OsStr::new("tmTheme")) {
+                match ThemeSet::get_theme(&path) {
+                    Ok(theme) => {
+                        let name = path
+                            .file_stem()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or("unknown")
+                            .to_string();
+                        info!("Loaded custom theme: {}", name);
+                        theme_set.themes.insert(name, theme);
+                    }
+                    Err(e) => {
+                        warn!("Failed to load theme {:?}: {}", path, e);
                     }
                 }
             }
-        } else {
-            trace!("No custom theme directory at {:?}", theme_dir);
         }
     }
 
@@ -170,7 +758,13 @@ mod tests {
     fn test_invalid_theme_falls_back() {
         // Test MY fallback logic when given an invalid theme name
         let language_map = HashMap::new();
-        let ctx = HighlightContext::new("nonexistent-theme-xyz", &language_map, None, None);
+        let ctx = HighlightContext::new(
+            "nonexistent-theme-xyz",
+            &language_map,
+            &HashMap::new(),
+            &[],
+            &[],
+        );
         // Should succeed by falling back to a default theme
         assert!(ctx.is_some());
     }
@@ -181,7 +775,14 @@ mod tests {
         let mut language_map = HashMap::new();
         language_map.insert("customlang".to_string(), "Rust".to_string());
 
-        let ctx = HighlightContext::new("base16-ocean.dark", &language_map, None, None).unwrap();
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &language_map,
+            &HashMap::new(),
+            &[],
+            &[],
+        )
+        .unwrap();
 
         // My code should look up "customlang" in the map and find "Rust"
         let syntax = ctx.find_syntax("customlang");
@@ -194,7 +795,14 @@ mod tests {
         let mut language_map = HashMap::new();
         language_map.insert("jsx".to_string(), "JavaScript".to_string());
 
-        let ctx = HighlightContext::new("base16-ocean.dark", &language_map, None, None).unwrap();
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &language_map,
+            &HashMap::new(),
+            &[],
+            &[],
+        )
+        .unwrap();
 
         // My code lowercases the input, so "JSX" should match "jsx" in the map
         let syntax = ctx.find_syntax("JSX");
@@ -205,17 +813,116 @@ mod tests {
     fn test_find_syntax_unknown_returns_plain_text() {
         // Test MY fallback to plain text logic
         let language_map = HashMap::new();
-        let ctx = HighlightContext::new("base16-ocean.dark", &language_map, None, None).unwrap();
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &language_map,
+            &HashMap::new(),
+            &[],
+            &[],
+        )
+        .unwrap();
 
         // Unknown language should fall back to plain text
         let syntax = ctx.find_syntax("unknown-language-xyz-123");
         assert_eq!(syntax.name, "Plain Text");
     }
 
+    #[test]
+    fn test_find_syntax_follows_fallback_chain() {
+        let mut fallback_map = HashMap::new();
+        fallback_map.insert("svelte".to_string(), "html".to_string());
+        fallback_map.insert("mylang".to_string(), "otherlang".to_string());
+        fallback_map.insert("otherlang".to_string(), "rust".to_string());
+
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &HashMap::new(),
+            &fallback_map,
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(ctx.find_syntax("svelte").name, "HTML");
+        // Multi-hop chains are followed
+        assert_eq!(ctx.find_syntax("MyLang").name, "Rust");
+    }
+
+    #[test]
+    fn test_find_syntax_fallback_cycle_returns_plain_text() {
+        let mut fallback_map = HashMap::new();
+        fallback_map.insert("a-lang".to_string(), "b-lang".to_string());
+        fallback_map.insert("b-lang".to_string(), "a-lang".to_string());
+
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &HashMap::new(),
+            &fallback_map,
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(ctx.find_syntax("a-lang").name, "Plain Text");
+    }
+
+    #[test]
+    fn test_missing_languages() {
+        let mut fallback_map = HashMap::new();
+        fallback_map.insert("svelte".to_string(), "html".to_string());
+        let ctx = HighlightContext::new(
+            "base16-ocean.dark",
+            &HashMap::new(),
+            &fallback_map,
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        let source = "```rust\n```\n\n```svelte\n```\n\n> ```nolang\n> ```\n\n```NoLang\n```\n\n```\n```\n\n\
+             ```rust,diff\n```\n";
+        let ast = markdown::to_mdast(source, &markdown::ParseOptions::gfm()).unwrap();
+        assert_eq!(ctx.missing_languages(&ast), vec!["nolang".to_string()]);
+    }
+
+    #[test]
+    fn test_code_lines_diff() {
+        let changes = |lines: &CodeLines| -> Vec<Option<LineChange>> {
+            lines.lines.iter().map(|(_, change)| *change).collect()
+        };
+        use LineChange::{Added, Removed};
+
+        // Full diffs keep their markers, and file headers aren't changes
+        let diff = CodeLines::new(
+            Some("diff"),
+            "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-old\n+new\n ctx",
+        );
+        assert_eq!(diff.lang, Some("diff"));
+        assert_eq!(diff.lines[3].0, "-old\n");
+        assert_eq!(
+            changes(&diff),
+            vec![None, None, None, Some(Removed), Some(Added), None]
+        );
+
+        // Language diffs lose the marker column
+        let rust = CodeLines::new(
+            Some("rust, diff"),
+            " fn main() {\n-    old();\n+    new();\n }\n",
+        );
+        assert_eq!(rust.lang, Some("rust"));
+        let text: String = rust.lines.iter().map(|(line, _)| *line).collect();
+        assert_eq!(text, "fn main() {\n    old();\n    new();\n}\n");
+        assert_eq!(changes(&rust), vec![None, Some(Removed), Some(Added), None]);
+
+        let plain = CodeLines::new(Some("rust"), "-1\n");
+        assert_eq!(changes(&plain), vec![None]);
+        assert_eq!(CodeLines::language(Some(",diff")), None);
+    }
+
     #[test]
     fn test_list_themes_returns_sorted() {
         // Test that MY list_themes function sorts the output
-        let themes = HighlightContext::list_themes(None);
+        let themes = HighlightContext::list_themes(&[]);
         assert!(!themes.is_empty());
 
         let mut sorted = themes.clone();
@@ -224,11 +931,173 @@ mod tests {
     }
 
     #[test]
-    fn test_get_config_dir_appends_mdcopy() {
-        // Test that MY config dir function appends "mdcopy" subdirectory
-        let config_dir = get_config_dir();
-        if let Some(path) = config_dir {
-            assert!(path.ends_with("mdcopy"));
+    fn test_contrast_ratio() {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0xff,
+        };
+        let white = Color {
+            r: 0xff,
+            g: 0xff,
+            b: 0xff,
+            a: 0xff,
+        };
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 1e-9);
+        assert!((contrast_ratio(white, white) - 1.0).abs() < 1e-9);
+
+        // Half-transparent white over black is mid grey
+        let faded = Color { a: 0x80, ..white };
+        let ratio = contrast_ratio(blend(faded, black), black);
+        assert!(ratio > 4.5 && ratio < 6.0, "{}", ratio);
+    }
+
+    #[test]
+    fn test_contrast_issues_flags_low_contrast_scopes() {
+        let theme_set = ThemeSet::load_defaults();
+        let mut theme = theme_set.themes["InspiredGitHub"].clone();
+        let background = theme.settings.background.unwrap();
+        theme.scopes[0].style.foreground = Some(background);
+        theme.scopes[1].style.foreground = Some(background);
+
+        let issues = contrast_issues(&theme);
+        let issue = issues
+            .iter()
+            .find(|i| i.foreground == background)
+            .expect("background-colored scope is flagged");
+        assert!((issue.ratio - 1.0).abs() < 1e-9);
+        assert!(issue.scopes.len() >= 2, "{:?}", issue.scopes);
+        assert!(issues.iter().all(|i| i.ratio < MIN_CONTRAST_RATIO));
+    }
+
+    #[test]
+    fn test_syntax_set_cache() {
+        let syntaxes = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let syntax = |name: &str| {
+            format!(
+                "%YAML 1.2\n---\nname: {name}\nfile_extensions: [{name}]\nscope: source.{name}\n\
+                 contexts:\n  main:\n    - match: '\\\\w+'\n      scope: keyword.{name}\n"
+            )
+        };
+        fs::write(syntaxes.path().join("one.sublime-syntax"), syntax("one")).unwrap();
+        let dir = syntaxes.path().to_path_buf();
+        let dumps = || -> Vec<PathBuf> {
+            fs::read_dir(cache.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect()
+        };
+
+        let ss = load_syntax_set(std::slice::from_ref(&dir), Some(cache.path()));
+        assert!(ss.find_syntax_by_extension("one").is_some());
+        let first = dumps();
+        assert_eq!(first.len(), 1);
+
+        // Read back from the dump
+        let cached = load_syntax_set(std::slice::from_ref(&dir), Some(cache.path()));
+        assert!(cached.find_syntax_by_extension("one").is_some());
+        assert_eq!(dumps(), first);
+
+        // A new syntax misses the cache and replaces the dump
+        fs::write(syntaxes.path().join("two.sublime-syntax"), syntax("two")).unwrap();
+        let ss = load_syntax_set(std::slice::from_ref(&dir), Some(cache.path()));
+        assert!(ss.find_syntax_by_extension("two").is_some());
+        let second = dumps();
+        assert_eq!(second.len(), 1);
+        assert_ne!(second, first);
+    }
+
+    #[test]
+    fn test_lazy_highlight_loads_on_first_use() {
+        let lazy = LazyHighlight::new(&HighlightConfig::default(), "InspiredGitHub");
+        assert!(lazy.loaded().is_none());
+        let hl = lazy.get().unwrap();
+        assert!(hl.syntax_set.find_syntax_by_token("rust").is_some());
+        assert!(lazy.loaded().is_some());
+
+        let parse = |md| markdown::to_mdast(md, &markdown::ParseOptions::gfm()).unwrap();
+        assert!(!has_code_blocks(&parse(
+            "# Title\n\nProse with `inline` code\n"
+        )));
+        assert!(has_code_blocks(&parse(
+            "- item\n\n  ```rust\n  fn main() {}\n  ```\n"
+        )));
+    }
+
+    #[test]
+    fn test_syntax_for_detects_unlabeled_code() {
+        let lazy = LazyHighlight::new(&HighlightConfig::default(), "InspiredGitHub");
+        let hl = lazy.get().unwrap();
+        let script = "#!/usr/bin/env python3\nprint('hi')\n";
+        assert_eq!(hl.syntax_for(None, script).name, "Plain Text");
+        assert_eq!(hl.syntax_for(Some("rust"), script).name, "Rust");
+
+        let cfg = HighlightConfig {
+            detect_language: true,
+            ..HighlightConfig::default()
+        };
+        let lazy = LazyHighlight::new(&cfg, "InspiredGitHub");
+        let hl = lazy.get().unwrap();
+        assert_eq!(hl.syntax_for(None, script).name, "Python");
+        assert_eq!(hl.syntax_for(None, "{\"a\": 1}").name, "JSON");
+        assert_eq!(hl.syntax_for(None, "Just words").name, "Plain Text");
+    }
+
+    #[test]
+    fn test_split_prompt() {
+        let cases = [
+            ("$ ls -la\n", Some(("$ ", "ls -la\n"))),
+            ("# apt update\n", Some(("# ", "apt update\n"))),
+            (
+                "user@host:~/src$ make\n",
+                Some(("user@host:~/src$ ", "make\n")),
+            ),
+            (
+                "(venv) $ pip install\n",
+                Some(("(venv) $ ", "pip install\n")),
+            ),
+            ("$\n", Some(("$", "\n"))),
+            ("total 8\n", None),
+            ("   Compiling mdcopy\n", None),
+            ("#include <stdio.h>\n", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(split_prompt(line), expected, "{:?}", line);
         }
     }
+
+    #[test]
+    fn test_session_highlighting() {
+        let lazy = LazyHighlight::new(&HighlightConfig::default(), "InspiredGitHub");
+        let hl = lazy.get().unwrap();
+        let code = "$ echo \\\n  hi\nhi\n";
+        let mut highlighter = hl.highlighter(Some("console"), code);
+        let foreground = hl.theme.settings.foreground.unwrap();
+
+        let prompt = highlighter.highlight_line("$ echo \\\n").unwrap();
+        assert_eq!(prompt[0].1, "$ ");
+        assert_ne!(prompt[0].0.foreground, foreground);
+        assert!(
+            prompt[1..]
+                .iter()
+                .any(|(style, _)| style.foreground != foreground)
+        );
+
+        let continued = highlighter.highlight_line("  hi\n").unwrap();
+        assert_eq!(
+            continued.iter().map(|(_, text)| *text).collect::<String>(),
+            "  hi\n"
+        );
+        assert_ne!(continued[0].0.foreground, prompt[0].0.foreground);
+
+        let output = highlighter.highlight_line("hi\n").unwrap();
+        assert_eq!(output, vec![(output[0].0, "hi\n")]);
+        assert_eq!(output[0].0.foreground, foreground);
+
+        let ast =
+            markdown::to_mdast("```console\n$ ls\n```\n", &markdown::ParseOptions::gfm()).unwrap();
+        assert!(hl.missing_languages(&ast).is_empty());
+    }
 }
@@ -0,0 +1,332 @@
+//! Opt-in log of past copies (`history.enable`), for `mdcopy history`. Each
+//! copy is a directory under `history/` in the config directory, named by
+//! its time in milliseconds, holding the markdown source, each clipboard
+//! flavor as a `clipboard.<ext>` file (the `file` backend's layout) and a
+//! small JSON record of where it came from. The oldest copies are removed
+//! once there are more than `history.max_entries` or they take more than
+//! `history.max_size_mb`.
+
+use crate::clipboard::{Flavor, Payload};
+use crate::config::HistoryConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SOURCE_FILE: &str = "source.md";
+const RECORD_FILE: &str = "entry.json";
+
+/// Where a copy came from
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Record {
+    /// Input file, None for stdin
+    pub input: Option<PathBuf>,
+    /// Output formats asked for
+    pub formats: Vec<String>,
+}
+
+/// One copy in the history
+#[derive(Debug)]
+pub struct Entry {
+    pub dir: PathBuf,
+    pub time: SystemTime,
+    pub record: Record,
+    /// Bytes on disk
+    pub size: u64,
+}
+
+/// The content of a copy, as read back from the history
+pub struct Stored {
+    pub source: String,
+    flavors: Vec<(Flavor, Vec<u8>)>,
+}
+
+impl Stored {
+    fn flavor(&self, flavor: Flavor) -> Option<&[u8]> {
+        self.flavors
+            .iter()
+            .find(|(f, _)| *f == flavor)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    fn text(&self, flavor: Flavor) -> Option<&str> {
+        self.flavor(flavor)
+            .and_then(|data| std::str::from_utf8(data).ok())
+    }
+
+    /// The copy as a clipboard payload; native macOS output isn't kept, so
+    /// it comes back as the HTML and RTF flavors
+    pub fn payload(&self) -> Payload<'_> {
+        Payload {
+            source: &self.source,
+            plain_text: self.text(Flavor::Text),
+            html: self.text(Flavor::Html),
+            rtf: self.text(Flavor::Rtf),
+            prosemirror: self.text(Flavor::ProseMirror),
+            image: self.flavor(Flavor::Image),
+            #[cfg(target_os = "macos")]
+            native: None,
+        }
+    }
+}
+
+pub struct History {
+    dir: PathBuf,
+    max_entries: usize,
+    max_bytes: u64,
+}
+
+impl History {
+    /// The history in the user config directory
+    pub fn open(config: &HistoryConfig) -> Option<Self> {
+        crate::config::default_config_dir().map(|dir| Self::at(dir.join("history"), config))
+    }
+
+    fn at(dir: PathBuf, config: &HistoryConfig) -> Self {
+        Self {
+            dir,
+            max_entries: config.max_entries,
+            max_bytes: config.max_size_mb * 1024 * 1024,
+        }
+    }
+
+    /// Add a copy of `payload`, then trim the history to its limits
+    pub fn record(&self, payload: &Payload, record: &Record) -> io::Result<()> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut dir = self.dir.join(millis.to_string());
+        // Two copies within a millisecond (`--sequence` with no delay)
+        let mut n = 1;
+        while dir.exists() {
+            dir = self.dir.join(format!("{}-{}", millis, n));
+            n += 1;
+        }
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(SOURCE_FILE), payload.source)?;
+        for (flavor, data) in payload.flavors() {
+            fs::write(flavor_path(&dir, flavor), data)?;
+        }
+        fs::write(
+            dir.join(RECORD_FILE),
+            serde_json::to_string_pretty(record).map_err(io::Error::other)?,
+        )?;
+        self.prune()
+    }
+
+    /// Copies, newest first
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let listing = match fs::read_dir(&self.dir) {
+            Ok(listing) => listing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for item in listing {
+            let dir = item?.path();
+            let Some(millis) = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('-').next())
+                .and_then(|millis| millis.parse().ok())
+            else {
+                continue;
+            };
+            if !dir.is_dir() {
+                continue;
+            }
+            let record = fs::read_to_string(dir.join(RECORD_FILE))
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            entries.push(Entry {
+                size: dir_size(&dir),
+                time: UNIX_EPOCH + Duration::from_millis(millis),
+                record,
+                dir,
+            });
+        }
+        entries.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| b.dir.cmp(&a.dir)));
+        Ok(entries)
+    }
+
+    /// The `n`th newest copy, counting from 1
+    pub fn get(&self, n: usize) -> io::Result<Entry> {
+        let entries = self.entries()?;
+        let count = entries.len();
+        n.checked_sub(1)
+            .and_then(|index| entries.into_iter().nth(index))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No copy {} in the history ({} kept)", n, count),
+                )
+            })
+    }
+
+    /// Remove the oldest copies beyond the entry and size limits; the newest
+    /// is always kept
+    fn prune(&self) -> io::Result<()> {
+        let mut total = 0;
+        for (index, entry) in self.entries()?.into_iter().enumerate() {
+            total += entry.size;
+            if index > 0 && (index >= self.max_entries || total > self.max_bytes) {
+                fs::remove_dir_all(&entry.dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    pub fn load(&self) -> io::Result<Stored> {
+        let flavors = Flavor::ALL
+            .into_iter()
+            .filter_map(|flavor| Some((flavor, fs::read(flavor_path(&self.dir, flavor)).ok()?)))
+            .collect();
+        Ok(Stored {
+            source: fs::read_to_string(self.dir.join(SOURCE_FILE))?,
+            flavors,
+        })
+    }
+
+    /// The first line of the source, shortened
+    pub fn preview(&self) -> String {
+        fs::read_to_string(self.dir.join(SOURCE_FILE))
+            .ok()
+            .and_then(|source| {
+                source
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty() && *line != "---")
+                    .map(|line| line.chars().take(50).collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// The formats of the copy, from the record or else the files it has
+    pub fn formats(&self) -> String {
+        if !self.record.formats.is_empty() {
+            return self.record.formats.join(", ");
+        }
+        Flavor::ALL
+            .into_iter()
+            .filter(|&flavor| flavor_path(&self.dir, flavor).exists())
+            .map(|flavor| flavor.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn flavor_path(dir: &Path, flavor: Flavor) -> PathBuf {
+    dir.join(format!("clipboard.{}", flavor.extension()))
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.ok()?.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// How long ago `time` was, roughly
+pub fn age(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(source: &str) -> Payload<'_> {
+        Payload {
+            source,
+            plain_text: None,
+            html: Some("<p>Hi</p>"),
+            rtf: None,
+            prosemirror: None,
+            image: None,
+            #[cfg(target_os = "macos")]
+            native: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::at(dir.path().join("history"), &HistoryConfig::default());
+        assert!(history.entries().unwrap().is_empty());
+
+        let record = Record {
+            input: Some(PathBuf::from("notes.md")),
+            formats: vec!["HTML".to_string()],
+        };
+        history.record(&payload("first"), &record).unwrap();
+        history
+            .record(&payload("second"), &Record::default())
+            .unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(history.get(2).unwrap().record.input, record.input);
+        assert!(history.get(3).is_err());
+        assert!(history.get(0).is_err());
+
+        let stored = history.get(1).unwrap().load().unwrap();
+        assert_eq!(stored.source, "second");
+        let payload = stored.payload();
+        assert_eq!(payload.plain_text, Some("second"));
+        assert_eq!(payload.html, Some("<p>Hi</p>"));
+        assert_eq!(payload.rtf, None);
+        assert_eq!(entries[0].formats(), "plain text, HTML");
+        assert_eq!(entries[0].preview(), "second");
+    }
+
+    #[test]
+    fn test_prune_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = HistoryConfig {
+            max_entries: 2,
+            ..HistoryConfig::default()
+        };
+        let history = History::at(dir.path().to_path_buf(), &config);
+        for source in ["one", "two", "three"] {
+            history
+                .record(&payload(source), &Record::default())
+                .unwrap();
+        }
+        let sources: Vec<String> = history
+            .entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.load().unwrap().source)
+            .collect();
+        assert_eq!(sources, ["three", "two"]);
+
+        // Nothing fits in 0 MB, but the newest copy stays
+        let history = History::at(
+            dir.path().to_path_buf(),
+            &HistoryConfig {
+                max_size_mb: 0,
+                ..config
+            },
+        );
+        history
+            .record(&payload("four"), &Record::default())
+            .unwrap();
+        assert_eq!(history.entries().unwrap().len(), 1);
+    }
+}
@@ -1,19 +1,31 @@
-use crate::config::ImageConfig;
+use crate::config::{EmbedFormat, ImageConfig};
+use crate::inline::Definitions;
 use base64::{Engine, engine::general_purpose::STANDARD};
 use log::{debug, trace, warn};
+use markdown::mdast::Node;
+use moxcms::{ColorProfile, Layout, TransformOptions};
+#[cfg(feature = "avif")]
+use rimage::codecs::avif::AvifDecoder;
 use rimage::codecs::mozjpeg::{MozJpegEncoder, MozJpegOptions};
 use rimage::codecs::oxipng::OxiPngEncoder;
+use rimage::codecs::webp::{WebPDecoder, WebPEncoder, WebPOptions};
 use rimage::operations::resize::{FilterType, Resize, ResizeAlg};
-use std::collections::HashMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{BufReader, Cursor};
+use std::io::{BufReader, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
 use tempfile::TempDir;
+use ureq::tls::{PemItem, RootCerts, TlsConfig};
 use zune_core::colorspace::ColorSpace;
 use zune_core::options::DecoderOptions;
 use zune_image::image::Image;
-use zune_image::traits::{EncoderTrait, OperationsTrait};
+use zune_image::traits::{DecoderTrait, EncoderTrait, OperationsTrait};
 
 #[derive(Debug)]
 pub enum ImageError {
@@ -21,6 +33,7 @@ pub enum ImageError {
     FetchFailed(String, String),
     ReadFailed(String, String),
     InvalidImage(String),
+    TooLarge(String, u64),
 }
 
 impl std::fmt::Display for ImageError {
@@ -34,6 +47,9 @@ impl std::fmt::Display for ImageError {
                 write!(f, "Failed to read image '{}': {}", path, reason)
             }
             ImageError::InvalidImage(url) => write!(f, "Invalid image data: {}", url),
+            ImageError::TooLarge(url, limit) => {
+                write!(f, "Image '{}' exceeds size limit of {} bytes", url, limit)
+            }
         }
     }
 }
@@ -74,7 +90,7 @@ pub fn load_image(
     if is_remote_url(url) {
         if image_config.embed_remote {
             debug!("Fetching remote image: {}", url);
-            return fetch_remote_image(url).map(Some);
+            return fetch_remote_image(url, image_config).map(Some);
         }
         trace!("Skipping remote image (embed_remote: false): {}", url);
         return Ok(None);
@@ -103,31 +119,43 @@ pub fn load_image(
     Ok(Some(EmbeddedImage { data, mime_type }))
 }
 
-fn fetch_remote_image(url: &str) -> Result<EmbeddedImage, ImageError> {
+/// Fetch a remote image, applying the configured timeout, retries and size limit
+fn fetch_remote_image(url: &str, image_config: &ImageConfig) -> Result<EmbeddedImage, ImageError> {
     let url = if url.starts_with("//") {
         format!("https:{}", url)
     } else {
         url.to_string()
     };
 
-    let response = ureq::get(&url)
-        .call()
-        .map_err(|e| ImageError::FetchFailed(url.clone(), e.to_string()))?;
-
-    let status = response.status();
-    trace!("HTTP {} for {}", status, url);
-
-    let mime_type = response
-        .headers()
-        .get("Content-Type")
-        .and_then(|h| h.to_str().ok())
-        .map(|s| s.split(';').next().unwrap_or(s).trim().to_string())
-        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let timeout = (image_config.fetch_timeout_ms > 0)
+        .then(|| Duration::from_millis(image_config.fetch_timeout_ms));
+    let max_bytes = match image_config.fetch_max_bytes {
+        0 => u64::MAX,
+        n => n,
+    };
 
-    let data = response
-        .into_body()
-        .read_to_vec()
-        .map_err(|e| ImageError::FetchFailed(url.clone(), e.to_string()))?;
+    let headers = request_headers(&url, image_config);
+    let tls = tls_config(image_config).map_err(|e| ImageError::FetchFailed(url.clone(), e))?;
+
+    let mut attempt = 0;
+    let (mime_type, data) = loop {
+        match fetch_bytes(&url, &headers, &tls, timeout, max_bytes) {
+            Ok(result) => break result,
+            Err(e) if attempt < image_config.fetch_retries && is_retryable(&e) => {
+                attempt += 1;
+                let delay = Duration::from_millis(250 << (attempt - 1).min(4));
+                debug!(
+                    "Retrying {} in {:?} (attempt {}/{}): {}",
+                    url, delay, attempt, image_config.fetch_retries, e
+                );
+                std::thread::sleep(delay);
+            }
+            Err(ureq::Error::BodyExceedsLimit(limit)) => {
+                return Err(ImageError::TooLarge(url, limit));
+            }
+            Err(e) => return Err(ImageError::FetchFailed(url, e.to_string())),
+        }
+    };
 
     trace!("Fetched {} bytes, content-type: {}", data.len(), mime_type);
 
@@ -166,9 +194,42 @@ fn guess_mime_type_from_data(data: &[u8]) -> String {
     if data.starts_with(b"BM") {
         return "image/bmp".to_string();
     }
+    if let Some(mime_type) = heif_mime_type(data) {
+        return mime_type.to_string();
+    }
     "application/octet-stream".to_string()
 }
 
+/// AVIF or HEIC, from the brands of an ISO media file's `ftyp` box
+fn heif_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let size = u32::from_be_bytes(data[..4].try_into().ok()?) as usize;
+    let major = data.get(8..12)?;
+    // The major brand, then the compatible ones after the minor version
+    let brands = std::iter::once(major).chain(
+        data.get(16..size.min(data.len()))
+            .unwrap_or_default()
+            .chunks_exact(4),
+    );
+    let mut heif = false;
+    for brand in brands {
+        match brand {
+            b"avif" | b"avis" => return Some("image/avif"),
+            b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => heif = true,
+            _ => {}
+        }
+    }
+    heif.then_some("image/heic")
+}
+
+/// Formats decoded here but shown by few paste targets, so embedded as
+/// JPEG or PNG instead
+fn needs_transcoding(mime_type: &str) -> bool {
+    matches!(mime_type, "image/avif" | "image/heic")
+}
+
 fn guess_mime_type_from_path(path: &Path, data: &[u8]) -> String {
     let from_data = guess_mime_type_from_data(data);
     if from_data != "application/octet-stream" {
@@ -184,6 +245,8 @@ fn guess_mime_type_from_path(path: &Path, data: &[u8]) -> String {
         Some("svg") => "image/svg+xml",
         Some("ico") => "image/x-icon",
         Some("bmp") => "image/bmp",
+        Some("avif") => "image/avif",
+        Some("heic") | Some("heif") => "image/heic",
         _ => "application/octet-stream",
     }
     .to_string()
@@ -228,13 +291,229 @@ pub fn load_image_with_fallback(
     }
 }
 
+/// Persistent on-disk cache for remote images, shared across runs.
+/// Entries are keyed by URL + optimization settings and expire by age;
+/// the oldest entries are evicted once the size limit is exceeded.
+struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_bytes: u64,
+}
+
+impl DiskCache {
+    fn open(dir: PathBuf, ttl: Duration, max_bytes: u64) -> Option<Self> {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("Failed to create image cache directory {:?}: {}", dir, e);
+            return None;
+        }
+        let cache = Self {
+            dir,
+            ttl,
+            max_bytes,
+        };
+        cache.evict();
+        Some(cache)
+    }
+
+    /// Path of a fresh entry for the key, if present. Entries that don't
+    /// start like an image (left by an older version or damaged on disk) are
+    /// removed rather than embedded.
+    fn get(&self, key: &str) -> Option<PathBuf> {
+        let path = self.dir.join(key);
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if is_expired(modified, self.ttl) {
+            trace!("Persistent image cache entry expired: {:?}", path);
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        if !starts_like_image(&path) {
+            warn!("Discarding damaged image cache entry {:?}", path);
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        Some(path)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) {
+        let path = self.dir.join(key);
+        if let Err(e) = write_atomic(&path, data) {
+            trace!("Failed to write persistent image cache entry: {}", e);
+            return;
+        }
+        self.evict();
+    }
+
+    /// Remove expired entries, then the oldest ones until under the size limit
+    fn evict(&self) {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = read_dir
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                if !meta.is_file() {
+                    return None;
+                }
+                // Writes in progress, possibly from another run
+                if e.file_name().to_string_lossy().starts_with(PARTIAL_PREFIX) {
+                    if meta.modified().is_ok_and(|m| is_expired(m, PARTIAL_TTL)) {
+                        let _ = fs::remove_file(e.path());
+                    }
+                    return None;
+                }
+                Some((e.path(), meta.len(), meta.modified().ok()?))
+            })
+            .collect();
+
+        entries.retain(|(path, _, modified)| {
+            if is_expired(*modified, self.ttl) {
+                let _ = fs::remove_file(path);
+                false
+            } else {
+                true
+            }
+        });
+
+        // Newest first, so everything past the limit is the oldest
+        entries.sort_by_key(|e| std::cmp::Reverse(e.2));
+        let mut total = 0u64;
+        for (path, len, _) in entries {
+            total += len;
+            if total > self.max_bytes {
+                trace!("Evicting persistent image cache entry: {:?}", path);
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+/// Prefix of the temporary files cache entries are written to
+const PARTIAL_PREFIX: &str = ".partial-";
+
+/// Age after which a temporary file is taken to be left by a killed run
+const PARTIAL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Write `data` to a temporary file next to `path` and rename it into place,
+/// so a run killed mid-write never leaves a truncated file under `path`
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut file = tempfile::Builder::new()
+        .prefix(PARTIAL_PREFIX)
+        .tempfile_in(dir)?;
+    file.write_all(data)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Whether the file starts with the magic bytes of an image format mdcopy
+/// embeds, or with SVG markup
+fn starts_like_image(path: &Path) -> bool {
+    let mut head = Vec::with_capacity(256);
+    let read = fs::File::open(path).and_then(|file| file.take(256).read_to_end(&mut head));
+    if read.is_err() {
+        return false;
+    }
+    if guess_mime_type_from_data(&head) != "application/octet-stream" {
+        return true;
+    }
+    let text = String::from_utf8_lossy(&head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    ["<svg", "<?xml", "<!--"]
+        .iter()
+        .any(|start| text.starts_with(start))
+}
+
+fn is_expired(modified: SystemTime, ttl: Duration) -> bool {
+    SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age >= ttl)
+}
+
+/// Cache key for a remote image under the given optimization settings
+fn disk_cache_key(url: &str, image_config: &ImageConfig) -> String {
+    url_to_filename(&format!(
+        "{}|{}|{}|{}|{}",
+        url,
+        image_config.optimize_remote,
+        image_config.max_dimension,
+        image_config.quality,
+        image_config.format
+    ))
+}
+
 /// Cache for images to avoid duplicate loads/fetches/optimization.
-/// Maps source URL/path to cached file path in temp directory.
+/// Maps source URL/path to cached file path in temp directory, and
+/// optionally persists remote images to disk across runs.
 pub struct ImageCache {
     /// Temp directory for cached images (cleaned up on drop)
     temp_dir: Option<TempDir>,
-    /// Maps source URL/path to cached file path
+    /// Maps `resolved_key` to cached file path
     cache: Mutex<HashMap<String, PathBuf>>,
+    /// Persistent cache for remote images (None when disabled)
+    disk: Option<DiskCache>,
+    /// `resolved_key`s that already failed to load (and were reported) in
+    /// graceful mode
+    failed: Mutex<HashSet<String>>,
+    /// Every image handed out for embedding, keyed by source
+    embedded: Mutex<BTreeMap<String, ManifestEntry>>,
+    /// Final outcome of every image loaded so far, keyed by `resolved_key`, so
+    /// each output format sees the same image (or the same fallback)
+    resolved: Mutex<HashMap<String, Option<EmbeddedImage>>>,
+    /// What each distinct picture became, keyed by the SHA-256 of its
+    /// loaded bytes, so one found under several paths is optimized once and
+    /// embedded identically everywhere
+    by_content: Mutex<HashMap<String, EmbeddedImage>>,
+}
+
+/// How many distinct pictures a document's images come down to
+#[derive(Debug, Default, PartialEq)]
+pub struct DedupStats {
+    /// Images embedded, one per source
+    pub sources: usize,
+    /// Distinct contents among them
+    pub unique: usize,
+    /// Bytes of the sources that repeat an earlier one's content
+    pub duplicate_bytes: usize,
+}
+
+impl std::fmt::Display for DedupStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} image(s), {} unique", self.sources, self.unique)?;
+        if self.sources > self.unique {
+            write!(
+                f,
+                " ({} duplicate(s) sharing {})",
+                self.sources - self.unique,
+                crate::notify::format_size(self.duplicate_bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Audit record of an embedded image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    /// URL or path as written in the document
+    pub source: String,
+    /// SHA-256 of the embedded bytes (after optimization)
+    pub sha256: String,
+    pub mime_type: String,
+    pub bytes: usize,
+}
+
+impl ManifestEntry {
+    fn new(source: &str, img: &EmbeddedImage) -> Self {
+        Self {
+            source: source.to_string(),
+            sha256: content_digest(&img.data),
+            mime_type: img.mime_type.clone(),
+            bytes: img.data.len(),
+        }
+    }
 }
 
 impl ImageCache {
@@ -246,7 +525,28 @@ impl ImageCache {
         Self {
             temp_dir,
             cache: Mutex::new(HashMap::new()),
+            disk: None,
+            failed: Mutex::new(HashSet::new()),
+            embedded: Mutex::new(BTreeMap::new()),
+            resolved: Mutex::new(HashMap::new()),
+            by_content: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a cache, enabling the persistent cache if configured
+    pub fn with_config(image_config: &ImageConfig) -> Self {
+        let mut cache = Self::new();
+        if image_config.cache_enable {
+            cache.disk = image_config.get_cache_dir().and_then(|dir| {
+                debug!("Using persistent image cache at {:?}", dir);
+                DiskCache::open(
+                    dir,
+                    Duration::from_secs(image_config.cache_ttl_hours * 3600),
+                    image_config.cache_max_size_mb * 1024 * 1024,
+                )
+            });
         }
+        cache
     }
 
     /// Load an image, using cache to avoid duplicate work.
@@ -258,6 +558,103 @@ impl ImageCache {
         base_dir: &Path,
         image_config: &ImageConfig,
         strict: bool,
+    ) -> Result<Option<EmbeddedImage>, ImageError> {
+        let key = (!is_data_url(url)).then(|| resolved_key(url, base_dir));
+        let cached = key
+            .as_ref()
+            .and_then(|key| self.resolved.lock().unwrap().get(key).cloned())
+            // Strict mode retries sources that failed gracefully
+            .filter(|result| result.is_some() || !strict);
+        let result = match cached {
+            Some(result) => result,
+            None => {
+                let result = self.load(url, base_dir, image_config, strict)?;
+                let result = self.prepare(url, base_dir, result, image_config, strict)?;
+                if let Some(key) = key {
+                    self.resolved.lock().unwrap().insert(key, result.clone());
+                }
+                result
+            }
+        };
+        // Data URLs are already part of the document
+        if let Some(img) = &result
+            && !is_data_url(url)
+        {
+            let mut embedded = self.embedded.lock().unwrap();
+            if !embedded.contains_key(url) {
+                embedded.insert(url.to_string(), ManifestEntry::new(url, img));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Turn a loaded image into the one embedded: AVIF and HEIC become JPEG
+    /// or PNG, and metadata is stripped unless kept
+    fn prepare(
+        &self,
+        url: &str,
+        base_dir: &Path,
+        img: Option<EmbeddedImage>,
+        image_config: &ImageConfig,
+        strict: bool,
+    ) -> Result<Option<EmbeddedImage>, ImageError> {
+        let Some(img) = img else {
+            return Ok(None);
+        };
+        let img = if needs_transcoding(&img.mime_type) {
+            match transcode_for_embedding(&img, image_config.quality, image_config.format) {
+                Ok(converted) => converted,
+                Err(e) if strict => return Err(e),
+                Err(e) => {
+                    warn!("{} ({})", e, url);
+                    self.failed
+                        .lock()
+                        .unwrap()
+                        .insert(resolved_key(url, base_dir));
+                    return Ok(None);
+                }
+            }
+        } else {
+            img
+        };
+        Ok(Some(if image_config.keep_exif {
+            img
+        } else {
+            strip_metadata(img)
+        }))
+    }
+
+    /// Images embedded so far, sorted by source
+    pub fn manifest(&self) -> Vec<ManifestEntry> {
+        self.embedded.lock().unwrap().values().cloned().collect()
+    }
+
+    /// How the resolved images among `urls` (relative to `base_dir`) share
+    /// content, counting each source once
+    pub fn dedup_stats(&self, urls: &[String], base_dir: &Path) -> DedupStats {
+        let resolved = self.resolved.lock().unwrap();
+        let mut seen = HashSet::new();
+        let mut stats = DedupStats::default();
+        for url in urls.iter().filter(|url| !is_data_url(url)) {
+            let Some(Some(img)) = resolved.get(&resolved_key(url, base_dir)) else {
+                continue;
+            };
+            stats.sources += 1;
+            if seen.insert(content_digest(&img.data)) {
+                stats.unique += 1;
+            } else {
+                stats.duplicate_bytes += img.data.len();
+            }
+        }
+        stats
+    }
+
+    fn load(
+        &self,
+        url: &str,
+        base_dir: &Path,
+        image_config: &ImageConfig,
+        strict: bool,
     ) -> Result<Option<EmbeddedImage>, ImageError> {
         // Skip if embedding is completely disabled or it's a data URL
         if (!image_config.embed_local && !image_config.embed_remote) || is_data_url(url) {
@@ -274,22 +671,50 @@ impl ImageCache {
             return Ok(None);
         }
 
+        // Relative paths are only the same image under the same directory
+        let source_key = resolved_key(url, base_dir);
+
         // Check cache first
         {
             let cache = self.cache.lock().unwrap();
-            if let Some(cached_path) = cache.get(url) {
+            if let Some(cached_path) = cache.get(&source_key) {
                 trace!("Image cache hit: {}", url);
                 return load_cached_image(cached_path);
             }
         }
 
+        // Don't retry (and re-warn about) sources that already failed
+        if !strict && self.failed.lock().unwrap().contains(&source_key) {
+            return Ok(None);
+        }
+
+        // Check the persistent cache for remote images
+        let disk_key = is_remote_url(url).then(|| disk_cache_key(url, image_config));
+        if let (Some(disk), Some(key)) = (&self.disk, &disk_key)
+            && let Some(path) = disk.get(key)
+        {
+            trace!("Persistent image cache hit: {}", url);
+            self.cache.lock().unwrap().insert(source_key, path.clone());
+            return load_cached_image(&path);
+        }
+
         // Load the original image
         let original = if is_remote_url(url) {
-            self.fetch_remote(url, strict)?
+            self.fetch_remote(url, image_config, strict)?
         } else {
             load_image_with_fallback(url, base_dir, image_config, strict)?
         };
 
+        // The same picture under another path becomes what that one did
+        let digest = original.as_ref().map(|img| content_digest(&img.data));
+        if let Some(img) = digest
+            .as_ref()
+            .and_then(|digest| self.by_content.lock().unwrap().get(digest).cloned())
+        {
+            trace!("Image {} has the same content as one already loaded", url);
+            return Ok(Some(img));
+        }
+
         // If optimization enabled for this image type, optimize and cache
         let should_optimize = if is_remote_url(url) {
             image_config.optimize_remote
@@ -297,19 +722,80 @@ impl ImageCache {
             image_config.optimize_local
         };
 
-        if should_optimize && let Some(ref img) = original {
-            return self.optimize_and_cache(url, img, image_config, strict);
+        let result = if should_optimize && let Some(ref img) = original {
+            self.optimize_and_cache(&source_key, img, image_config, strict)?
+        } else {
+            original
+        };
+
+        if result.is_none() {
+            self.failed.lock().unwrap().insert(source_key);
+        }
+        if let (Some(img), Some(digest)) = (&result, digest) {
+            self.by_content
+                .lock()
+                .unwrap()
+                .entry(digest)
+                .or_insert_with(|| img.clone());
+        }
+
+        if let (Some(disk), Some(key), Some(img)) = (&self.disk, &disk_key, &result) {
+            disk.put(key, &img.data);
+        }
+        Ok(result)
+    }
+
+    /// Resolve every image of a document before rendering, loading up to
+    /// `concurrency` at once. Renderers then only look up the outcome, so
+    /// every format embeds the same images; in strict mode the first failure
+    /// is returned here instead of from a renderer.
+    pub fn resolve_all(
+        &self,
+        urls: &[String],
+        base_dir: &Path,
+        image_config: &ImageConfig,
+        strict: bool,
+        concurrency: usize,
+    ) -> Result<(), ImageError> {
+        let urls: Vec<&String> = urls.iter().filter(|url| !is_data_url(url)).collect();
+        let workers = concurrency.clamp(1, urls.len().max(1));
+        if !urls.is_empty() {
+            debug!("Resolving {} images with {} workers", urls.len(), workers);
+        }
+
+        let next = AtomicUsize::new(0);
+        let error = Mutex::new(None);
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while error.lock().unwrap().is_none()
+                        && let Some(url) = urls.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if let Err(e) = self.get_or_load(url, base_dir, image_config, strict) {
+                            error.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                });
+            }
+        });
+        match error.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
-        Ok(original)
     }
 
     /// Fetch a remote image, caching the raw download
-    fn fetch_remote(&self, url: &str, strict: bool) -> Result<Option<EmbeddedImage>, ImageError> {
+    fn fetch_remote(
+        &self,
+        url: &str,
+        image_config: &ImageConfig,
+        strict: bool,
+    ) -> Result<Option<EmbeddedImage>, ImageError> {
         let temp_dir = match &self.temp_dir {
             Some(dir) => dir.path(),
             None => {
                 // No temp dir, fetch directly without caching
-                return match fetch_remote_image(url) {
+                return match fetch_remote_image(url, image_config) {
                     Ok(img) => Ok(Some(img)),
                     Err(e) if strict => Err(e),
                     Err(e) => {
@@ -324,7 +810,7 @@ impl ImageCache {
         let filename = url_to_filename(url);
         let cached_path = temp_dir.join(&filename);
 
-        match fetch_and_save_remote_image(url, &cached_path) {
+        match fetch_and_save_remote_image(url, &cached_path, image_config) {
             Ok(()) => {
                 self.cache
                     .lock()
@@ -340,10 +826,10 @@ impl ImageCache {
         }
     }
 
-    /// Optimize an image and cache the result
+    /// Optimize an image and cache the result under `key`, its `resolved_key`
     fn optimize_and_cache(
         &self,
-        source: &str,
+        key: &str,
         img: &EmbeddedImage,
         image_config: &ImageConfig,
         strict: bool,
@@ -358,16 +844,16 @@ impl ImageCache {
 
                 // Cache to temp file
                 if let Some(temp_dir) = &self.temp_dir {
-                    let filename = url_to_filename(source);
+                    let filename = url_to_filename(key);
                     let cached_path = temp_dir.path().join(filename);
 
-                    if let Err(e) = fs::write(&cached_path, &optimized.data) {
+                    if let Err(e) = write_atomic(&cached_path, &optimized.data) {
                         trace!("Failed to cache optimized image: {}", e);
                     } else {
                         self.cache
                             .lock()
                             .unwrap()
-                            .insert(source.to_string(), cached_path);
+                            .insert(key.to_string(), cached_path);
                     }
                 }
 
@@ -391,6 +877,23 @@ impl Default for ImageCache {
     }
 }
 
+/// Lowercase hex SHA-256 of `data`
+pub fn content_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Key of a resolved image: relative paths depend on the document's directory
+fn resolved_key(url: &str, base_dir: &Path) -> String {
+    if is_remote_url(url) {
+        url.to_string()
+    } else {
+        base_dir.join(url).display().to_string()
+    }
+}
+
 /// Generate a filesystem-safe filename from a URL (hash-based)
 fn url_to_filename(url: &str) -> String {
     use std::collections::hash_map::DefaultHasher;
@@ -401,32 +904,263 @@ fn url_to_filename(url: &str) -> String {
     format!("{:016x}", hasher.finish())
 }
 
-/// Fetch a remote image and save it to a file
-fn fetch_and_save_remote_image(url: &str, dest: &Path) -> Result<(), ImageError> {
-    let url = if url.starts_with("//") {
-        format!("https:{}", url)
+/// Collect image URLs from the AST in document order, without duplicates,
+/// including reference-style images that have a definition
+pub fn collect_image_urls(node: &Node) -> Vec<String> {
+    fn walk(
+        node: &Node,
+        definitions: &Definitions,
+        seen: &mut HashSet<String>,
+        urls: &mut Vec<String>,
+    ) {
+        let url = match node {
+            Node::Image(image) => Some(image.url.as_str()),
+            Node::ImageReference(imgref) => definitions.get(&imgref.identifier),
+            _ => None,
+        };
+        if let Some(url) = url
+            && seen.insert(url.to_string())
+        {
+            urls.push(url.to_string());
+        }
+        if let Some(children) = node.children() {
+            for child in children {
+                walk(child, definitions, seen, urls);
+            }
+        }
+    }
+
+    let mut urls = Vec::new();
+    walk(
+        node,
+        &Definitions::collect(node),
+        &mut HashSet::new(),
+        &mut urls,
+    );
+    urls
+}
+
+/// Human-readable, stable asset name stem for an image source:
+/// a slug of the original filename plus a short hash of the full URL/path,
+/// e.g. `https://example.com/img/Diagram%201.png` -> `diagram-201-1a2b3c4d`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn asset_stem(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    let name = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 40 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = if slug.is_empty() { "image" } else { slug };
+
+    format!("{}-{}", slug, &url_to_filename(url)[..8])
+}
+
+/// Host part of a URL, without userinfo or port
+pub fn url_host(url: &str) -> Option<&str> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = if let Some(v6) = host.strip_prefix('[') {
+        v6.split(']').next()?
     } else {
-        url.to_string()
+        host.split(':').next()?
     };
+    (!host.is_empty()).then_some(host)
+}
 
-    debug!("Fetching remote image: {}", url);
+/// Headers to send when fetching `url`: matching configured headers, plus
+/// basic auth from netrc unless an Authorization header is already set
+pub fn request_headers(url: &str, image_config: &ImageConfig) -> Vec<(String, String)> {
+    let Some(host) = url_host(url) else {
+        return Vec::new();
+    };
+
+    let mut headers: Vec<(String, String)> = image_config
+        .fetch_headers
+        .iter()
+        .filter(|h| h.matches_host(host))
+        .map(|h| (h.name.clone(), h.value.clone()))
+        .collect();
+
+    let has_auth = headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"));
+    if image_config.fetch_netrc
+        && !has_auth
+        && let Some((login, password)) = netrc_credentials(host)
+    {
+        debug!("Using netrc credentials for {}", host);
+        let token = STANDARD.encode(format!("{}:{}", login, password));
+        headers.push(("Authorization".to_string(), format!("Basic {}", token)));
+    }
+
+    trace!(
+        "Sending {} extra header(s) to {}: {:?}",
+        headers.len(),
+        host,
+        headers.iter().map(|(name, _)| name).collect::<Vec<_>>()
+    );
+    headers
+}
+
+/// Look up `host` in $NETRC or ~/.netrc
+fn netrc_credentials(host: &str) -> Option<(String, String)> {
+    let path = std::env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")))?;
+    let content = fs::read_to_string(&path).ok()?;
+    parse_netrc(&content, host)
+}
+
+/// Find the login and password for `host` in netrc content, falling back to
+/// the `default` entry
+fn parse_netrc(content: &str, host: &str) -> Option<(String, String)> {
+    // (machine, login, password); machine None is the `default` entry
+    let mut entries: Vec<(Option<String>, Option<String>, Option<String>)> = Vec::new();
+    let mut tokens = content.split_whitespace();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((Some(tokens.next()?.to_lowercase()), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" | "password" | "account" => {
+                let value = tokens.next()?.to_string();
+                if let Some(entry) = entries.last_mut() {
+                    match token {
+                        "login" => entry.1 = Some(value),
+                        "password" => entry.2 = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            // Macros run until the next blank line; we don't support them
+            "macdef" => return None,
+            _ => {}
+        }
+    }
+
+    let host = host.to_lowercase();
+    let entry = entries
+        .iter()
+        .find(|(machine, _, _)| machine.as_deref() == Some(host.as_str()))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))?;
+    Some((entry.1.clone()?, entry.2.clone().unwrap_or_default()))
+}
+
+/// TLS settings for remote fetches: the roots from `fetch_ca_bundle` in
+/// place of the built-in ones (like curl's `--cacert`), and no certificate
+/// verification at all with `fetch_insecure`
+pub fn tls_config(image_config: &ImageConfig) -> Result<TlsConfig, String> {
+    let mut builder = TlsConfig::builder().disable_verification(image_config.fetch_insecure);
+    if let Some(path) = &image_config.fetch_ca_bundle {
+        builder = builder.root_certs(load_ca_bundle(path)?);
+    }
+    Ok(builder.build())
+}
+
+fn load_ca_bundle(path: &Path) -> Result<RootCerts, String> {
+    let pem =
+        fs::read(path).map_err(|e| format!("cannot read CA bundle {}: {}", path.display(), e))?;
+    let mut certs = Vec::new();
+    for item in ureq::tls::parse_pem(&pem) {
+        match item {
+            Ok(PemItem::Certificate(cert)) => certs.push(cert),
+            Ok(_) => {}
+            Err(e) => return Err(format!("invalid CA bundle {}: {}", path.display(), e)),
+        }
+    }
+    if certs.is_empty() {
+        return Err(format!("no certificates in CA bundle {}", path.display()));
+    }
+    debug!(
+        "Trusting {} certificates from {}",
+        certs.len(),
+        path.display()
+    );
+    Ok(RootCerts::from(certs))
+}
+
+/// Single HTTP attempt, returning the content type and body
+pub fn fetch_bytes(
+    url: &str,
+    headers: &[(String, String)],
+    tls: &TlsConfig,
+    timeout: Option<Duration>,
+    max_bytes: u64,
+) -> Result<(String, Vec<u8>), ureq::Error> {
+    let mut request = ureq::get(url);
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let response = request
+        .config()
+        .timeout_global(timeout)
+        .tls_config(tls.clone())
+        .build()
+        .call()?;
+
+    trace!("HTTP {} for {}", response.status(), url);
+
+    // Reject early when the server announces an oversized body
+    if response
+        .body()
+        .content_length()
+        .is_some_and(|len| len > max_bytes)
+    {
+        return Err(ureq::Error::BodyExceedsLimit(max_bytes));
+    }
 
-    let response = ureq::get(&url)
-        .call()
-        .map_err(|e| ImageError::FetchFailed(url.clone(), e.to_string()))?;
+    let mime_type = response
+        .headers()
+        .get("Content-Type")
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.split(';').next().unwrap_or(s).trim().to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
 
     let data = response
         .into_body()
-        .read_to_vec()
-        .map_err(|e| ImageError::FetchFailed(url.clone(), e.to_string()))?;
+        .with_config()
+        .limit(max_bytes)
+        .read_to_vec()?;
 
-    // Verify it's actually an image
-    let mime = guess_mime_type_from_data(&data);
-    if !mime.starts_with("image/") && mime != "application/octet-stream" {
-        return Err(ImageError::InvalidImage(url));
+    Ok((mime_type, data))
+}
+
+/// Transient failures worth retrying: timeouts, connection problems, 5xx and 429
+fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::StatusCode(code) => *code >= 500 || *code == 429,
+        ureq::Error::Timeout(_)
+        | ureq::Error::Io(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed => true,
+        _ => false,
     }
+}
+
+/// Fetch a remote image and save it to a file
+fn fetch_and_save_remote_image(
+    url: &str,
+    dest: &Path,
+    image_config: &ImageConfig,
+) -> Result<(), ImageError> {
+    debug!("Fetching remote image: {}", url);
+    let data = fetch_remote_image(url, image_config)?.data;
 
-    fs::write(dest, &data)
+    write_atomic(dest, &data)
         .map_err(|e| ImageError::ReadFailed(dest.display().to_string(), e.to_string()))?;
 
     trace!("Cached remote image to {:?}", dest);
@@ -454,10 +1188,16 @@ pub fn optimize_image(
     data: &[u8],
     image_config: &ImageConfig,
 ) -> Result<EmbeddedImage, ImageError> {
-    // Decode image using BufReader<Cursor> which implements BufRead + Seek
-    let reader = BufReader::new(Cursor::new(data));
-    let mut img = Image::read(reader, DecoderOptions::default())
-        .map_err(|e| ImageError::InvalidImage(format!("Failed to decode image: {:?}", e)))?;
+    let mime_type = guess_mime_type_from_data(data);
+    let img = if needs_transcoding(&mime_type) {
+        decode_data(data, &mime_type)?
+    } else {
+        // Decode image using BufReader<Cursor> which implements BufRead + Seek
+        let reader = BufReader::new(Cursor::new(data));
+        Image::read(reader, DecoderOptions::default())
+            .map_err(|e| ImageError::InvalidImage(format!("Failed to decode image: {:?}", e)))?
+    };
+    let mut img = normalize_pixels(img);
 
     // Get dimensions
     let (width, height) = img.dimensions();
@@ -489,52 +1229,597 @@ pub fn optimize_image(
             .map_err(|e| ImageError::InvalidImage(format!("Failed to resize image: {:?}", e)))?;
     }
 
+    encode_for_embedding(&img, image_config.quality, image_config.format)
+}
+
+/// Decode an image and encode it at full size as `format`, for formats the
+/// destination can't show (AVIF and HEIC almost anywhere, WebP in RTF)
+pub fn transcode_for_embedding(
+    img: &EmbeddedImage,
+    quality: u8,
+    format: EmbedFormat,
+) -> Result<EmbeddedImage, ImageError> {
+    let decoded = drop_opaque_alpha(normalize_pixels(decode(img)?));
+    debug!(
+        "Transcoding {} ({}x{})",
+        img.mime_type,
+        decoded.dimensions().0,
+        decoded.dimensions().1
+    );
+    encode_for_embedding(&decoded, quality, format)
+}
+
+/// RGB copy of an RGBA image whose pixels are all opaque. Decoders hand out
+/// RGBA even for photos, which would otherwise be encoded as large PNGs.
+fn drop_opaque_alpha(mut img: Image) -> Image {
+    let opaque = img.colorspace() == ColorSpace::RGBA
+        && img
+            .flatten_to_u8()
+            .first()
+            .is_some_and(|rgba| rgba.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    if opaque && let Err(e) = img.convert_color(ColorSpace::RGB) {
+        debug!("Keeping alpha channel: {:?}", e);
+    }
+    img
+}
+
+/// JPEG for opaque images, PNG for images with transparency
+fn encode_for_embedding(
+    img: &Image,
+    quality: u8,
+    format: EmbedFormat,
+) -> Result<EmbeddedImage, ImageError> {
     // Check if image has alpha channel
     let has_alpha = matches!(
         img.colorspace(),
         ColorSpace::RGBA | ColorSpace::BGRA | ColorSpace::ARGB | ColorSpace::LumaA
     );
 
-    // Encode based on transparency
-    if has_alpha {
+    let (data, mime_type) = if format == EmbedFormat::Webp {
+        debug!("Encoding as WebP (quality={})", quality);
+        let mut options = WebPOptions::new()
+            .map_err(|()| ImageError::InvalidImage("Failed to set up WebP encoder".to_string()))?;
+        options.quality = quality as f32;
+        let encoder = WebPEncoder::new_with_options(options);
+        (encode_with(encoder, img, "WebP")?, "image/webp")
+    } else if format == EmbedFormat::Png || (format == EmbedFormat::Auto && has_alpha) {
         // PNG for transparency
-        debug!("Encoding as PNG (has alpha channel)");
-        let mut encoder = OxiPngEncoder::new();
-        let mut result = Vec::new();
-        encoder
-            .encode(&img, &mut result)
-            .map_err(|e| ImageError::InvalidImage(format!("Failed to encode PNG: {:?}", e)))?;
-        Ok(EmbeddedImage {
-            data: result,
-            mime_type: "image/png".to_string(),
-        })
+        debug!("Encoding as PNG (has alpha channel: {})", has_alpha);
+        (encode_with(OxiPngEncoder::new(), img, "PNG")?, "image/png")
     } else {
         // JPEG for opaque (better compression)
-        debug!(
-            "Encoding as JPEG (opaque, quality={})",
-            image_config.quality
-        );
+        debug!("Encoding as JPEG (quality={})", quality);
         let options = MozJpegOptions {
-            quality: image_config.quality as f32,
+            quality: quality as f32,
             ..Default::default()
         };
-        let mut encoder = MozJpegEncoder::new_with_options(options);
-        let mut result = Vec::new();
-        encoder
-            .encode(&img, &mut result)
-            .map_err(|e| ImageError::InvalidImage(format!("Failed to encode JPEG: {:?}", e)))?;
-        Ok(EmbeddedImage {
-            data: result,
-            mime_type: "image/jpeg".to_string(),
-        })
-    }
+        let encoder = MozJpegEncoder::new_with_options(options);
+        let data = if has_alpha {
+            encode_with(encoder, &flatten_alpha(img)?, "JPEG")?
+        } else {
+            encode_with(encoder, img, "JPEG")?
+        };
+        (data, "image/jpeg")
+    };
+    Ok(EmbeddedImage {
+        data,
+        mime_type: mime_type.to_string(),
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::TempDir;
+fn encode_with(
+    mut encoder: impl EncoderTrait,
+    img: &Image,
+    name: &str,
+) -> Result<Vec<u8>, ImageError> {
+    let mut result = Vec::new();
+    encoder
+        .encode(img, &mut result)
+        .map_err(|e| ImageError::InvalidImage(format!("Failed to encode {}: {:?}", name, e)))?;
+    Ok(result)
+}
+
+/// Composite a transparent image onto white, for JPEG, which has no alpha
+fn flatten_alpha(img: &Image) -> Result<Image, ImageError> {
+    let mut rgba = img.clone();
+    rgba.convert_color(ColorSpace::RGBA)
+        .map_err(|e| ImageError::InvalidImage(format!("Failed to convert to RGBA: {:?}", e)))?;
+    let (width, height) = rgba.dimensions();
+    let pixels = rgba
+        .flatten_to_u8()
+        .into_iter()
+        .next()
+        .ok_or_else(|| ImageError::InvalidImage("No frames to encode".to_string()))?;
+    let rgb: Vec<u8> = pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as u16;
+            [0, 1, 2].map(|c| ((pixel[c] as u16 * alpha + 255 * (255 - alpha) + 127) / 255) as u8)
+        })
+        .collect();
+    Ok(Image::from_u8(&rgb, width, height, ColorSpace::RGB))
+}
+
+/// Bake EXIF orientation and the embedded ICC profile into the pixels. The
+/// encoders drop both, and many paste targets ignore them anyway, so photos
+/// would show up sideways and Display P3 screenshots washed out.
+fn normalize_pixels(img: Image) -> Image {
+    let orientation = img
+        .metadata()
+        .exif()
+        .into_iter()
+        .flatten()
+        .find(|field| field.tag.number() == EXIF_ORIENTATION && field.ifd_num.index() == 0)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|o| (2..=8).contains(o));
+    let icc = img.metadata().icc_chunk().cloned();
+    if orientation.is_none() && icc.is_none() {
+        return img;
+    }
+
+    let colorspace = img.colorspace();
+    let (width, height) = img.dimensions();
+    let Some(mut pixels) = img.flatten_to_u8().into_iter().next() else {
+        return img;
+    };
+    if let Some(icc) = icc {
+        let layout = match colorspace {
+            ColorSpace::RGB => Some(Layout::Rgb),
+            ColorSpace::RGBA => Some(Layout::Rgba),
+            _ => None,
+        };
+        match layout.map(|layout| convert_to_srgb(&mut pixels, layout, &icc)) {
+            Some(Ok(())) => debug!("Converted embedded color profile to sRGB"),
+            Some(Err(e)) => warn!("Ignoring embedded color profile: {}", e),
+            None => debug!("Ignoring color profile on {:?} image", colorspace),
+        }
+    }
+    let (pixels, width, height) = match orientation {
+        Some(orientation) => {
+            debug!("Applying EXIF orientation {}", orientation);
+            orient(
+                &pixels,
+                width,
+                height,
+                colorspace.num_components(),
+                orientation,
+            )
+        }
+        None => (pixels, width, height),
+    };
+    Image::from_u8(&pixels, width, height, colorspace)
+}
+
+const EXIF_ORIENTATION: u16 = 0x0112;
+
+/// Drop EXIF (camera, GPS), XMP and text metadata from a JPEG, PNG or WebP
+/// embedded as it is. Only the orientation survives, in a one-entry EXIF
+/// block, so photos still show upright. Other formats, and files that don't
+/// parse, are left alone.
+pub fn strip_metadata(img: EmbeddedImage) -> EmbeddedImage {
+    let stripped = match img.mime_type.as_str() {
+        "image/jpeg" => strip_jpeg(&img.data),
+        "image/png" => strip_png(&img.data),
+        "image/webp" => strip_webp(&img.data),
+        _ => None,
+    };
+    match stripped {
+        Some(data) if data.len() < img.data.len() => {
+            debug!("Stripped {} bytes of metadata", img.data.len() - data.len());
+            EmbeddedImage {
+                data,
+                mime_type: img.mime_type,
+            }
+        }
+        _ => img,
+    }
+}
+
+/// JPEG without APP1 (EXIF, XMP), APP13 (IPTC) and comment segments
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    const EXIF_HEADER: &[u8] = b"Exif\0\0";
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = data[..2].to_vec();
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan: the rest is image data
+            0xDA => {
+                out.extend_from_slice(&data[pos..]);
+                return Some(out);
+            }
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&data[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let segment = data.get(pos..pos + 2 + len)?;
+        let payload = &segment[4..];
+        match marker {
+            0xE1 => {
+                let orientation = payload.strip_prefix(EXIF_HEADER).and_then(exif_orientation);
+                if let Some(orientation) = orientation {
+                    let exif = [EXIF_HEADER, &orientation_exif(orientation)].concat();
+                    out.extend_from_slice(&[0xFF, 0xE1]);
+                    out.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+                    out.extend_from_slice(&exif);
+                }
+            }
+            0xED | 0xFE => {}
+            _ => out.extend_from_slice(segment),
+        }
+        pos += 2 + len;
+    }
+}
+
+/// PNG without eXIf and text chunks (tEXt, zTXt, iTXt, which holds XMP)
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut out = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+    while pos < data.len() {
+        let len = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let chunk = data.get(pos..pos + 12 + len)?;
+        let kind = &chunk[4..8];
+        match kind {
+            b"eXIf" => {
+                if let Some(orientation) = exif_orientation(&chunk[8..8 + len]) {
+                    let exif = orientation_exif(orientation);
+                    let mut crc = flate2::Crc::new();
+                    crc.update(b"eXIf");
+                    crc.update(&exif);
+                    out.extend_from_slice(&(exif.len() as u32).to_be_bytes());
+                    out.extend_from_slice(b"eXIf");
+                    out.extend_from_slice(&exif);
+                    out.extend_from_slice(&crc.sum().to_be_bytes());
+                }
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" => {}
+            _ => out.extend_from_slice(chunk),
+        }
+        pos += chunk.len();
+    }
+    Some(out)
+}
+
+/// WebP without EXIF and XMP chunks
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    /// VP8X flags announcing the chunks
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut chunks = Vec::new();
+    let mut exif_kept = false;
+    let mut pos = 12;
+    while pos < data.len() {
+        let len = u32::from_le_bytes(data.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // Chunks are padded to an even size
+        let chunk = data.get(pos..(pos + 8 + len + (len & 1)).min(data.len()))?;
+        match &chunk[..4] {
+            b"EXIF" => {
+                if let Some(orientation) = exif_orientation(&chunk[8..8 + len]) {
+                    let exif = orientation_exif(orientation);
+                    chunks.extend_from_slice(b"EXIF");
+                    chunks.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+                    chunks.extend_from_slice(&exif);
+                    exif_kept = true;
+                }
+            }
+            b"XMP " => {}
+            _ => chunks.extend_from_slice(chunk),
+        }
+        pos += chunk.len();
+    }
+    if chunks.starts_with(b"VP8X") && chunks.len() > 8 {
+        chunks[8] &= !XMP_FLAG;
+        if !exif_kept {
+            chunks[8] &= !EXIF_FLAG;
+        }
+    }
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&chunks);
+    Some(out)
+}
+
+/// Orientation other than upright in a TIFF-structured EXIF block
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let ifd = tiff.get(4..8)?.try_into().ok()?;
+    let ifd = if big_endian {
+        u32::from_be_bytes(ifd)
+    } else {
+        u32::from_le_bytes(ifd)
+    } as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(EXIF_ORIENTATION))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|o| (2..=8).contains(o))
+}
+
+/// Big-endian TIFF block holding nothing but the orientation tag
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+    tiff.extend_from_slice(&EXIF_ORIENTATION.to_be_bytes());
+    // SHORT, one value, left-aligned in the value field
+    tiff.extend_from_slice(&[0, 3, 0, 0, 0, 1]);
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0; 6]);
+    tiff
+}
+
+/// Convert interleaved 8-bit pixels from an ICC profile to sRGB
+fn convert_to_srgb(pixels: &mut [u8], layout: Layout, icc: &[u8]) -> Result<(), String> {
+    let source = ColorProfile::new_from_slice(icc).map_err(|e| e.to_string())?;
+    let transform = source
+        .create_transform_8bit(
+            layout,
+            &ColorProfile::new_srgb(),
+            layout,
+            TransformOptions::default(),
+        )
+        .map_err(|e| e.to_string())?;
+    let src = pixels.to_vec();
+    transform.transform(&src, pixels).map_err(|e| e.to_string())
+}
+
+/// Rearrange interleaved pixels so an image with the given EXIF orientation
+/// (2-8) displays upright; returns the pixels and new dimensions
+fn orient(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    orientation: u32,
+) -> (Vec<u8>, usize, usize) {
+    // Orientations 5-8 swap the axes
+    let (out_width, out_height) = if orientation >= 5 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let mut out = Vec::with_capacity(pixels.len());
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let (sx, sy) = match orientation {
+                2 => (width - 1 - x, y),
+                3 => (width - 1 - x, height - 1 - y),
+                4 => (x, height - 1 - y),
+                5 => (y, x),
+                6 => (y, height - 1 - x),
+                7 => (width - 1 - y, height - 1 - x),
+                8 => (width - 1 - y, x),
+                _ => (x, y),
+            };
+            let start = (sy * width + sx) * channels;
+            out.extend_from_slice(&pixels[start..start + channels]);
+        }
+    }
+    (out, out_width, out_height)
+}
+
+/// Re-encode a raster image (WebP, GIF, BMP, ...) as PNG for formats that
+/// only accept PNG/JPEG. Animated images keep their first frame.
+pub fn transcode_to_png(img: &EmbeddedImage) -> Result<EmbeddedImage, ImageError> {
+    let decoded = decode(img)?;
+    debug!(
+        "Transcoding {} ({}x{}) to PNG",
+        img.mime_type,
+        decoded.dimensions().0,
+        decoded.dimensions().1
+    );
+    let mut result = Vec::new();
+    OxiPngEncoder::new()
+        .encode(&decoded, &mut result)
+        .map_err(|e| ImageError::InvalidImage(format!("Failed to encode PNG: {:?}", e)))?;
+    Ok(EmbeddedImage {
+        data: result,
+        mime_type: "image/png".to_string(),
+    })
+}
+
+/// 8-bit pixels of a decoded image
+pub struct Pixels {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major RGB triples
+    pub rgb: Vec<u8>,
+    /// One alpha byte per pixel, when any pixel isn't opaque
+    pub alpha: Option<Vec<u8>>,
+}
+
+/// Upright width and height of `img`, for outputs that size images
+/// themselves
+pub fn dimensions(img: &EmbeddedImage) -> Result<(usize, usize), ImageError> {
+    Ok(normalize_pixels(decode(img)?).dimensions())
+}
+
+/// Decode `img` to RGB pixels and alpha, upright and in sRGB, for outputs
+/// that draw images themselves
+pub fn decode_pixels(img: &EmbeddedImage) -> Result<Pixels, ImageError> {
+    let mut decoded = normalize_pixels(decode(img)?);
+    decoded
+        .convert_color(ColorSpace::RGBA)
+        .map_err(|e| ImageError::InvalidImage(format!("Failed to convert to RGBA: {:?}", e)))?;
+    let (width, height) = decoded.dimensions();
+    let rgba = decoded
+        .flatten_to_u8()
+        .into_iter()
+        .next()
+        .ok_or_else(|| ImageError::InvalidImage(format!("No frames in {}", img.mime_type)))?;
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    let mut alpha = Vec::with_capacity(width * height);
+    for pixel in rgba.chunks_exact(4) {
+        rgb.extend_from_slice(&pixel[..3]);
+        alpha.push(pixel[3]);
+    }
+    Ok(Pixels {
+        width,
+        height,
+        rgb,
+        alpha: alpha.iter().any(|&a| a < 255).then_some(alpha),
+    })
+}
+
+/// Encode pixels as PNG, for outputs that draw images themselves
+pub fn encode_png(pixels: &Pixels) -> Result<Vec<u8>, ImageError> {
+    let image = match &pixels.alpha {
+        Some(alpha) => {
+            let rgba: Vec<u8> = pixels
+                .rgb
+                .chunks_exact(3)
+                .zip(alpha)
+                .flat_map(|(rgb, &a)| [rgb[0], rgb[1], rgb[2], a])
+                .collect();
+            Image::from_u8(&rgba, pixels.width, pixels.height, ColorSpace::RGBA)
+        }
+        None => Image::from_u8(&pixels.rgb, pixels.width, pixels.height, ColorSpace::RGB),
+    };
+    let mut result = Vec::new();
+    OxiPngEncoder::new()
+        .encode(&image, &mut result)
+        .map_err(|e| ImageError::InvalidImage(format!("Failed to encode PNG: {:?}", e)))?;
+    Ok(result)
+}
+
+fn decode(img: &EmbeddedImage) -> Result<Image, ImageError> {
+    decode_data(&img.data, &img.mime_type)
+}
+
+fn decode_data(data: &[u8], mime_type: &str) -> Result<Image, ImageError> {
+    match mime_type {
+        "image/gif" => decode_gif(data),
+        "image/webp" => WebPDecoder::try_new(Cursor::new(data))
+            .and_then(|mut decoder| decoder.decode())
+            .map_err(|e| format!("{:?}", e)),
+        #[cfg(feature = "avif")]
+        "image/avif" => AvifDecoder::try_new(Cursor::new(data))
+            .and_then(|mut decoder| decoder.decode())
+            .map_err(|e| format!("{:?}", e)),
+        #[cfg(not(feature = "avif"))]
+        "image/avif" => Err("mdcopy was built without AVIF support".to_string()),
+        "image/heic" => decode_heic(data),
+        _ => Image::read(BufReader::new(Cursor::new(data)), DecoderOptions::default())
+            .map_err(|e| format!("{:?}", e)),
+    }
+    .map_err(|e| ImageError::InvalidImage(format!("Failed to decode {}: {}", mime_type, e)))
+}
+
+/// Converters tried for HEIC, which has no decoder linked in: `sips` ships
+/// with macOS, `heif-dec` (formerly `heif-convert`) with libheif
+const HEIC_CONVERTERS: &[&str] = if cfg!(target_os = "macos") {
+    &["sips"]
+} else {
+    &["heif-dec", "heif-convert"]
+};
+
+/// Decode HEIC by converting it to PNG with the first converter installed
+fn decode_heic(data: &[u8]) -> Result<Image, String> {
+    let dir = TempDir::new().map_err(|e| e.to_string())?;
+    let input = dir.path().join("image.heic");
+    let output = dir.path().join("image.png");
+    fs::write(&input, data).map_err(|e| e.to_string())?;
+    for &tool in HEIC_CONVERTERS {
+        let mut command = Command::new(tool);
+        if tool == "sips" {
+            command
+                .args(["-s", "format", "png"])
+                .arg(&input)
+                .arg("--out");
+        } else {
+            command.arg(&input);
+        }
+        let result = command
+            .arg(&output)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output();
+        match result {
+            Ok(out) if out.status.success() => {
+                debug!("Converted HEIC with {}", tool);
+                let png = fs::read(&output).map_err(|e| format!("{}: {}", tool, e))?;
+                return Image::read(BufReader::new(Cursor::new(png)), DecoderOptions::default())
+                    .map_err(|e| format!("{:?}", e));
+            }
+            Ok(out) => {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                return Err(format!("{} failed: {}", tool, stderr.trim()));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("{}: {}", tool, e)),
+        }
+    }
+    Err(format!(
+        "HEIC needs {} installed",
+        HEIC_CONVERTERS.join(" or ")
+    ))
+}
+
+/// Decode the first frame of a GIF onto its full-size RGBA canvas
+fn decode_gif(data: &[u8]) -> Result<Image, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(data).map_err(|e| e.to_string())?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let frame = decoder
+        .read_next_frame()
+        .map_err(|e| e.to_string())?
+        .ok_or("GIF has no frames")?;
+
+    let mut canvas = vec![0u8; width * height * 4];
+    let (left, top) = (frame.left as usize, frame.top as usize);
+    let frame_width = frame.width as usize;
+    for (y, row) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+        let y = top + y;
+        if y >= height || left >= width {
+            break;
+        }
+        let len = row.len().min((width - left) * 4);
+        let start = (y * width + left) * 4;
+        canvas[start..start + len].copy_from_slice(&row[..len]);
+    }
+    Ok(Image::from_u8(&canvas, width, height, ColorSpace::RGBA))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
 
     // Helper to create ImageConfig for tests
     fn config_embed_local() -> ImageConfig {
@@ -545,6 +1830,7 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         }
     }
 
@@ -556,6 +1842,7 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         }
     }
 
@@ -567,6 +1854,7 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         }
     }
 
@@ -681,6 +1969,315 @@ mod tests {
         assert_eq!(img.to_rtf_hex(), "00ffab12");
     }
 
+    #[test]
+    fn test_transcode_gif_to_png() {
+        let mut data = Vec::new();
+        {
+            let palette = [255, 0, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(&mut data, 2, 2, &palette).unwrap();
+            let frame = gif::Frame {
+                width: 2,
+                height: 2,
+                buffer: std::borrow::Cow::Borrowed(&[0, 1, 1, 0]),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        let gif_image = EmbeddedImage {
+            data,
+            mime_type: "image/gif".to_string(),
+        };
+        assert_eq!(gif_image.rtf_format(), None);
+
+        let png = transcode_to_png(&gif_image).unwrap();
+        assert_eq!(png.mime_type, "image/png");
+        assert_eq!(guess_mime_type_from_data(&png.data), "image/png");
+        assert_eq!(png.rtf_format(), Some("\\pngblip"));
+
+        let pixels = decode_pixels(&gif_image).unwrap();
+        assert_eq!((pixels.width, pixels.height), (2, 2));
+        assert_eq!(&pixels.rgb[..6], &[255, 0, 0, 0, 0, 255]);
+        assert!(pixels.alpha.is_none());
+    }
+
+    #[test]
+    fn test_transcode_webp_to_png() {
+        use rimage::codecs::webp::WebPEncoder;
+
+        let pixels = vec![128u8; 4 * 4 * 3];
+        let img = Image::from_u8(&pixels, 4, 4, ColorSpace::RGB);
+        let mut data = Vec::new();
+        WebPEncoder::new().encode(&img, &mut data).unwrap();
+        let webp = EmbeddedImage {
+            mime_type: guess_mime_type_from_data(&data),
+            data,
+        };
+        assert_eq!(webp.mime_type, "image/webp");
+
+        let png = transcode_to_png(&webp).unwrap();
+        assert_eq!(guess_mime_type_from_data(&png.data), "image/png");
+    }
+
+    #[test]
+    fn test_orient() {
+        // 3x2 single-channel image:
+        // 1 2 3
+        // 4 5 6
+        let pixels = [1, 2, 3, 4, 5, 6];
+        assert_eq!(orient(&pixels, 3, 2, 1, 2), (vec![3, 2, 1, 6, 5, 4], 3, 2));
+        assert_eq!(orient(&pixels, 3, 2, 1, 3), (vec![6, 5, 4, 3, 2, 1], 3, 2));
+        // Rotated 90 degrees clockwise and back
+        assert_eq!(orient(&pixels, 3, 2, 1, 6), (vec![4, 1, 5, 2, 6, 3], 2, 3));
+        assert_eq!(orient(&pixels, 3, 2, 1, 8), (vec![3, 6, 2, 5, 1, 4], 2, 3));
+        // Whole pixels move together
+        let rgb = [1, 1, 1, 2, 2, 2];
+        assert_eq!(orient(&rgb, 2, 1, 3, 2).0, vec![2, 2, 2, 1, 1, 1]);
+    }
+
+    /// Little-endian EXIF block with an orientation and a camera model
+    fn camera_exif(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"II\x2a\0\x08\0\0\0\x02\0".to_vec();
+        tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+        // Model, 8 ASCII characters after the IFD
+        tiff.extend_from_slice(&[0x10, 0x01, 2, 0, 8, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend_from_slice(&[0; 4]);
+        tiff.extend_from_slice(b"Phone 15");
+        tiff
+    }
+
+    #[test]
+    fn test_strip_jpeg_metadata() {
+        let pixels = [120u8; 4 * 4 * 3];
+        let mut jpeg = Vec::new();
+        MozJpegEncoder::new()
+            .encode(&Image::from_u8(&pixels, 4, 4, ColorSpace::RGB), &mut jpeg)
+            .unwrap();
+        let with_exif = |orientation| {
+            let exif = [b"Exif\0\0".as_slice(), &camera_exif(orientation)].concat();
+            let mut data = jpeg[..2].to_vec();
+            data.extend_from_slice(&[0xFF, 0xE1]);
+            data.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(&exif);
+            data.extend_from_slice(&[0xFF, 0xFE, 0, 6, b'n', b'o', b't', b'e']);
+            data.extend_from_slice(&jpeg[2..]);
+            EmbeddedImage {
+                data,
+                mime_type: "image/jpeg".to_string(),
+            }
+        };
+
+        // Only the orientation is left of the EXIF block
+        let rotated = strip_metadata(with_exif(6));
+        let contains = |data: &[u8], needle: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(&rotated.data, b"Phone 15"));
+        assert!(!contains(&rotated.data, b"note"));
+        let app1 = rotated
+            .data
+            .windows(6)
+            .position(|w| w == b"Exif\0\0")
+            .unwrap();
+        assert_eq!(exif_orientation(&rotated.data[app1 + 6..]), Some(6));
+        assert!(dimensions(&rotated).is_ok());
+
+        // Upright photos lose the block entirely
+        assert_eq!(strip_metadata(with_exif(1)).data, jpeg);
+        // Files without metadata stay as they are
+        let plain = EmbeddedImage {
+            data: jpeg.clone(),
+            mime_type: "image/jpeg".to_string(),
+        };
+        assert_eq!(strip_metadata(plain).data, jpeg);
+    }
+
+    #[test]
+    fn test_strip_png_metadata() {
+        let mut png = Vec::new();
+        OxiPngEncoder::new()
+            .encode(&Image::from_u8(&[0u8; 4], 1, 1, ColorSpace::RGBA), &mut png)
+            .unwrap();
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut crc = flate2::Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            [
+                &(data.len() as u32).to_be_bytes(),
+                kind,
+                data,
+                &crc.sum().to_be_bytes(),
+            ]
+            .concat()
+        };
+        // After the signature and IHDR
+        let mut data = png[..33].to_vec();
+        data.extend(chunk(b"tEXt", b"Author\0someone"));
+        data.extend(chunk(b"eXIf", &camera_exif(1)));
+        data.extend_from_slice(&png[33..]);
+
+        let stripped = strip_png(&data).unwrap();
+        assert_eq!(stripped, png);
+        assert!(strip_png(b"not a png").is_none());
+    }
+
+    #[test]
+    fn test_heif_mime_type() {
+        let ftyp = |brands: &[&[u8; 4]]| {
+            let mut data = ((16 + 4 * brands.len()) as u32).to_be_bytes().to_vec();
+            data.extend_from_slice(b"ftyp");
+            data.extend_from_slice(brands[0]);
+            data.extend_from_slice(&[0; 4]);
+            for brand in brands {
+                data.extend_from_slice(*brand);
+            }
+            data.extend_from_slice(b"\0\0\0\x08meta");
+            guess_mime_type_from_data(&data)
+        };
+        assert_eq!(ftyp(&[b"avif", b"mif1"]), "image/avif");
+        assert_eq!(ftyp(&[b"heic", b"mif1"]), "image/heic");
+        // Generic HEIF brand, AVIF only among the compatible ones
+        assert_eq!(ftyp(&[b"mif1", b"miaf", b"avif"]), "image/avif");
+        assert_eq!(ftyp(&[b"mif1", b"miaf"]), "image/heic");
+        assert_eq!(ftyp(&[b"isom", b"mp41"]), "application/octet-stream");
+        assert_eq!(
+            guess_mime_type_from_path(Path::new("IMG_0001.heic"), b"??"),
+            "image/heic"
+        );
+    }
+
+    #[test]
+    fn test_undecodable_heic_falls_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut data = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic".to_vec();
+        data.extend_from_slice(b"not really a picture");
+        fs::write(temp_dir.path().join("photo.heic"), &data).unwrap();
+
+        let config = ImageConfig {
+            optimize_local: false,
+            ..ImageConfig::default()
+        };
+        let cache = ImageCache::new();
+        // Never embedded as HEIC: a link when graceful, an error when strict
+        let graceful = cache.get_or_load("photo.heic", temp_dir.path(), &config, false);
+        assert!(graceful.unwrap().is_none());
+        let strict = ImageCache::new().get_or_load("photo.heic", temp_dir.path(), &config, true);
+        assert!(matches!(strict, Err(ImageError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn test_optimize_image_formats() {
+        // Left half transparent, right half opaque red
+        let row = [[0u8, 0, 0, 0].repeat(8), [255, 0, 0, 255].repeat(8)].concat();
+        let pixels = row.repeat(16);
+        let mut png = Vec::new();
+        OxiPngEncoder::new()
+            .encode(&Image::from_u8(&pixels, 16, 16, ColorSpace::RGBA), &mut png)
+            .unwrap();
+        let optimize = |format| {
+            let config = ImageConfig {
+                format,
+                ..ImageConfig::default()
+            };
+            optimize_image(&png, &config).unwrap()
+        };
+
+        assert_eq!(optimize(EmbedFormat::Auto).mime_type, "image/png");
+        assert_eq!(optimize(EmbedFormat::Png).mime_type, "image/png");
+        let webp = optimize(EmbedFormat::Webp);
+        assert_eq!(webp.mime_type, "image/webp");
+        assert_eq!(guess_mime_type_from_data(&webp.data), "image/webp");
+        assert!(decode_pixels(&webp).unwrap().alpha.is_some());
+
+        // JPEG has no transparency, so it turns white
+        let jpeg = optimize(EmbedFormat::Jpeg);
+        assert_eq!(jpeg.mime_type, "image/jpeg");
+        let pixels = decode_pixels(&jpeg).unwrap();
+        assert!(
+            pixels.rgb[..3].iter().all(|&c| c > 240),
+            "{:?}",
+            &pixels.rgb[..3]
+        );
+    }
+
+    #[test]
+    fn test_drop_opaque_alpha() {
+        let opaque = drop_opaque_alpha(Image::from_u8(
+            &[9u8, 8, 7, 255].repeat(4),
+            2,
+            2,
+            ColorSpace::RGBA,
+        ));
+        assert_eq!(opaque.colorspace(), ColorSpace::RGB);
+        assert_eq!(opaque.flatten_to_u8()[0], [9u8, 8, 7].repeat(4));
+
+        let translucent = [[9u8, 8, 7, 255], [9, 8, 7, 0]].concat().repeat(2);
+        let kept = drop_opaque_alpha(Image::from_u8(&translucent, 2, 2, ColorSpace::RGBA));
+        assert_eq!(kept.colorspace(), ColorSpace::RGBA);
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn test_transcode_avif() {
+        use rimage::codecs::avif::AvifEncoder;
+
+        let pixels = [200u8, 60, 40].repeat(16);
+        let mut avif = Vec::new();
+        AvifEncoder::new()
+            .encode(&Image::from_u8(&pixels, 4, 4, ColorSpace::RGB), &mut avif)
+            .unwrap();
+        let img = EmbeddedImage {
+            mime_type: guess_mime_type_from_data(&avif),
+            data: avif,
+        };
+        assert_eq!(img.mime_type, "image/avif");
+        let jpeg = transcode_for_embedding(&img, 80, EmbedFormat::Auto).unwrap();
+        assert_eq!(jpeg.mime_type, "image/jpeg");
+        assert_eq!(dimensions(&jpeg).unwrap(), (4, 4));
+    }
+
+    #[test]
+    fn test_orientation_exif_round_trip() {
+        for orientation in 2..=8 {
+            assert_eq!(
+                exif_orientation(&orientation_exif(orientation)),
+                Some(orientation)
+            );
+        }
+        assert_eq!(exif_orientation(&camera_exif(8)), Some(8));
+        assert_eq!(exif_orientation(&camera_exif(1)), None);
+        assert_eq!(exif_orientation(b"XX"), None);
+    }
+
+    #[test]
+    fn test_normalize_pixels_converts_to_srgb() {
+        let pixels = [200u8, 60, 40].repeat(4);
+        let mut img = Image::from_u8(&pixels, 2, 2, ColorSpace::RGB);
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        img.metadata_mut().set_icc_chunk(p3);
+
+        let normalized = normalize_pixels(img);
+        let out = &normalized.flatten_to_u8()[0];
+        // Display P3 red is more saturated than sRGB red can show
+        assert!(out[0] > 200 && out[1] < 60, "{:?}", &out[..3]);
+        assert!(normalized.metadata().icc_chunk().is_none());
+
+        // Untagged images are left alone
+        let plain = normalize_pixels(Image::from_u8(&pixels, 2, 2, ColorSpace::RGB));
+        assert_eq!(plain.flatten_to_u8()[0], pixels);
+    }
+
+    #[test]
+    fn test_transcode_invalid_data() {
+        let svg = EmbeddedImage {
+            data: b"<svg></svg>".to_vec(),
+            mime_type: "image/svg+xml".to_string(),
+        };
+        assert!(matches!(
+            transcode_to_png(&svg),
+            Err(ImageError::InvalidImage(_))
+        ));
+    }
+
     #[test]
     fn test_embedded_image_rtf_format() {
         let png = EmbeddedImage {
@@ -805,11 +2402,257 @@ mod tests {
 
         let err = ImageError::InvalidImage("http://example.com".to_string());
         assert_eq!(err.to_string(), "Invalid image data: http://example.com");
+
+        let err = ImageError::TooLarge("http://example.com".to_string(), 1024);
+        assert_eq!(
+            err.to_string(),
+            "Image 'http://example.com' exceeds size limit of 1024 bytes"
+        );
+    }
+
+    /// Serve a single canned HTTP response on localhost, returning the URL
+    fn serve_once(response: Vec<u8>) -> String {
+        use std::io::Read;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(&response);
+            }
+        });
+        format!("http://{}/image.png", addr)
+    }
+
+    #[test]
+    fn test_fetch_remote_image_max_bytes() {
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 64\r\n\r\n".to_vec();
+        response.extend_from_slice(&[0u8; 64]);
+        let url = serve_once(response);
+
+        let config = ImageConfig {
+            fetch_max_bytes: 16,
+            fetch_retries: 0,
+            ..config_embed_all()
+        };
+        assert!(matches!(
+            fetch_remote_image(&url, &config),
+            Err(ImageError::TooLarge(_, 16))
+        ));
     }
 
     #[test]
-    fn test_image_cache_local_not_cached() {
-        // Local images are NOT cached - they're read fresh each time
+    fn test_fetch_remote_image_timeout() {
+        // Accept the connection but never respond
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+
+        let config = ImageConfig {
+            fetch_timeout_ms: 100,
+            fetch_retries: 0,
+            ..config_embed_all()
+        };
+        assert!(matches!(
+            fetch_remote_image(&url, &config),
+            Err(ImageError::FetchFailed(_, _))
+        ));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_tls_config_ca_bundle() {
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        let config = ImageConfig {
+            fetch_ca_bundle: Some(bundle.clone()),
+            ..config_embed_all()
+        };
+
+        let err = tls_config(&config).unwrap_err();
+        assert!(err.starts_with("cannot read CA bundle"), "{}", err);
+
+        fs::write(&bundle, "not a certificate\n").unwrap();
+        let err = tls_config(&config).unwrap_err();
+        assert!(err.starts_with("no certificates in CA bundle"), "{}", err);
+
+        fs::write(
+            &bundle,
+            "-----BEGIN CERTIFICATE-----\nMAA=\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let tls = tls_config(&config).unwrap();
+        assert!(matches!(tls.root_certs(), RootCerts::Specific(certs) if certs.len() == 1));
+        assert!(!tls.disable_verification());
+
+        // A bad bundle fails the fetch instead of silently using the default roots
+        fs::remove_file(&bundle).unwrap();
+        assert!(matches!(
+            fetch_remote_image("https://example.com/a.png", &config),
+            Err(ImageError::FetchFailed(_, reason)) if reason.contains("CA bundle")
+        ));
+    }
+
+    #[test]
+    fn test_fetch_remote_image_sends_domain_headers() {
+        use crate::config::ImageHeader;
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 2048];
+            let n = stream.read(&mut buf).unwrap();
+            let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+                png.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(&png);
+            stream.write_all(&response).unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let config = ImageConfig {
+            fetch_retries: 0,
+            fetch_headers: vec![
+                ImageHeader::parse("127.0.0.1=Authorization: token abc").unwrap(),
+                ImageHeader::parse("example.com=Cookie: leaked=1").unwrap(),
+            ],
+            ..config_embed_all()
+        };
+        assert!(fetch_remote_image(&url, &config).is_ok());
+
+        let request = server.join().unwrap();
+        assert!(request.contains("authorization: token abc"));
+        assert!(!request.contains("cookie"));
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("https://github.com/a.png"), Some("github.com"));
+        assert_eq!(url_host("http://user:pw@host:8080/x"), Some("host"));
+        assert_eq!(url_host("https://[::1]:8080/x"), Some("::1"));
+        assert_eq!(url_host("https://example.com?x=1"), Some("example.com"));
+        assert_eq!(url_host("https:///x"), None);
+    }
+
+    #[test]
+    fn test_parse_netrc() {
+        let netrc = "machine github.com login octo password secret\n\
+                     default login anon password guest\n";
+        assert_eq!(
+            parse_netrc(netrc, "GitHub.com"),
+            Some(("octo".to_string(), "secret".to_string()))
+        );
+        assert_eq!(
+            parse_netrc(netrc, "example.com"),
+            Some(("anon".to_string(), "guest".to_string()))
+        );
+        assert_eq!(parse_netrc("machine a login x", "b"), None);
+    }
+
+    #[test]
+    fn test_request_headers_netrc_does_not_override_authorization() {
+        use crate::config::ImageHeader;
+
+        let config = ImageConfig {
+            fetch_headers: vec![ImageHeader::parse("Authorization: token abc").unwrap()],
+            fetch_netrc: true,
+            ..config_embed_all()
+        };
+        let headers = request_headers("https://example.com/a.png", &config);
+        assert_eq!(
+            headers,
+            vec![("Authorization".to_string(), "token abc".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_image_cache_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        std::fs::write(temp_dir.path().join("a.png"), png).unwrap();
+
+        let cache = ImageCache::new();
+        let config = config_embed_all();
+        cache
+            .get_or_load("a.png", temp_dir.path(), &config, false)
+            .unwrap();
+        cache
+            .get_or_load("a.png", temp_dir.path(), &config, false)
+            .unwrap();
+        cache
+            .get_or_load("missing.png", temp_dir.path(), &config, false)
+            .unwrap();
+        cache
+            .get_or_load(
+                "data:image/png;base64,iVBORw0KGgo=",
+                temp_dir.path(),
+                &config,
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            cache.manifest(),
+            vec![ManifestEntry {
+                source: "a.png".to_string(),
+                sha256: "4c4b6a3be1314ab86138bef4314dde022e600960d8689a2c8f8631802d20dab6"
+                    .to_string(),
+                mime_type: "image/png".to_string(),
+                bytes: 8,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_image_cache_shares_identical_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        std::fs::create_dir(temp_dir.path().join("copies")).unwrap();
+        std::fs::write(temp_dir.path().join("a.png"), png).unwrap();
+        std::fs::write(temp_dir.path().join("copies/a.png"), png).unwrap();
+        std::fs::write(temp_dir.path().join("b.png"), b"GIF89a").unwrap();
+
+        let cache = ImageCache::new();
+        let urls: Vec<String> = ["a.png", "copies/a.png", "b.png", "missing.png"]
+            .iter()
+            .map(|url| url.to_string())
+            .collect();
+        cache
+            .resolve_all(&urls, temp_dir.path(), &config_embed_local(), false, 2)
+            .unwrap();
+        assert_eq!(
+            cache.dedup_stats(&urls, temp_dir.path()),
+            DedupStats {
+                sources: 3,
+                unique: 2,
+                duplicate_bytes: 8,
+            }
+        );
+        assert_eq!(
+            cache.dedup_stats(&urls, temp_dir.path()).to_string(),
+            "3 image(s), 2 unique (1 duplicate(s) sharing 8 B)"
+        );
+        assert_eq!(cache.by_content.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&ureq::Error::StatusCode(503)));
+        assert!(is_retryable(&ureq::Error::StatusCode(429)));
+        assert!(!is_retryable(&ureq::Error::StatusCode(404)));
+        assert!(is_retryable(&ureq::Error::ConnectionFailed));
+        assert!(!is_retryable(&ureq::Error::BodyExceedsLimit(10)));
+    }
+
+    #[test]
+    fn test_image_cache_local_resolved_once() {
+        // A local image is read once per resolved path, then reused as is
         let temp_dir = TempDir::new().unwrap();
         let image_path = temp_dir.path().join("test.png");
 
@@ -827,13 +2670,48 @@ mod tests {
         let img1 = result1.unwrap().unwrap();
         assert_eq!(img1.mime_type, "image/png");
 
-        // Delete the file - second load should FAIL (no caching for local files)
+        // Delete the file - the second load reuses the resolved image, so
+        // every output format embeds the same thing
         std::fs::remove_file(&image_path).unwrap();
 
         let result2 = cache.get_or_load("test.png", temp_dir.path(), &config, false);
-        assert!(result2.is_ok());
-        // Returns None because file not found (graceful mode)
-        assert!(result2.unwrap().is_none());
+        assert_eq!(result2.unwrap().unwrap().data, img1.data);
+
+        // The same relative path under another directory is a different image
+        let other = TempDir::new().unwrap();
+        let result3 = cache.get_or_load("test.png", other.path(), &config, false);
+        assert!(result3.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_image_cache_optimized_per_directory() {
+        // Batch mode shares one cache between documents in different
+        // directories that may use the same relative path
+        let dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+        for (dir, color) in dirs.iter().zip([[255u8, 0, 0], [0, 0, 255]]) {
+            let mut png = Vec::new();
+            OxiPngEncoder::new()
+                .encode(
+                    &Image::from_u8(&color.repeat(64), 8, 8, ColorSpace::RGB),
+                    &mut png,
+                )
+                .unwrap();
+            std::fs::write(dir.path().join("img.png"), png).unwrap();
+        }
+
+        let cache = ImageCache::new();
+        let config = ImageConfig {
+            optimize_local: true,
+            ..config_embed_local()
+        };
+        let [a, b] = dirs.each_ref().map(|dir| {
+            cache
+                .get_or_load("img.png", dir.path(), &config, false)
+                .unwrap()
+                .unwrap()
+        });
+        assert_ne!(a.data, b.data);
+        assert_eq!(cache.cache.lock().unwrap().len(), 2);
     }
 
     #[test]
@@ -846,6 +2724,177 @@ mod tests {
         assert!(result.unwrap().is_none());
     }
 
+    #[test]
+    fn test_disk_cache_put_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DiskCache::open(
+            temp_dir.path().join("images"),
+            Duration::from_secs(3600),
+            1024,
+        )
+        .unwrap();
+
+        assert!(cache.get("missing").is_none());
+        cache.put("key", b"GIF89a-data");
+        let path = cache.get("key").unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"GIF89a-data");
+        cache.put("svg", b"\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>");
+        assert!(cache.get("svg").is_some());
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&cache.dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_disk_cache_discards_damaged_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DiskCache::open(
+            temp_dir.path().to_path_buf(),
+            Duration::from_secs(3600),
+            1024,
+        )
+        .unwrap();
+
+        std::fs::write(temp_dir.path().join("key"), b"<html>error page").unwrap();
+        assert!(cache.get("key").is_none());
+        assert!(!temp_dir.path().join("key").exists());
+
+        // Stale partial writes are cleaned up; fresh ones belong to a
+        // concurrent run and are left alone
+        let stale = temp_dir.path().join(".partial-stale");
+        let fresh = temp_dir.path().join(".partial-fresh");
+        std::fs::write(&stale, b"\x89PN").unwrap();
+        std::fs::write(&fresh, b"\x89PN").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - PARTIAL_TTL)
+            .unwrap();
+        cache.evict();
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+
+    #[test]
+    fn test_disk_cache_ttl_expiry() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DiskCache::open(temp_dir.path().to_path_buf(), Duration::ZERO, 1024).unwrap();
+
+        cache.put("key", b"data");
+        assert!(cache.get("key").is_none());
+        assert!(!temp_dir.path().join("key").exists());
+    }
+
+    #[test]
+    fn test_disk_cache_evicts_oldest() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache =
+            DiskCache::open(temp_dir.path().to_path_buf(), Duration::from_secs(3600), 10).unwrap();
+
+        cache.put("old", b"GIF89a");
+        // Backdate so ordering doesn't depend on timestamp resolution
+        let old = std::fs::File::options()
+            .write(true)
+            .open(temp_dir.path().join("old"))
+            .unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+
+        cache.put("new", b"GIF89a");
+        assert!(cache.get("old").is_none());
+        assert!(cache.get("new").is_some());
+    }
+
+    #[test]
+    fn test_disk_cache_key_depends_on_settings() {
+        let url = "https://example.com/image.png";
+        let config = config_embed_all();
+        let mut other = config_embed_all();
+        other.quality = 50;
+
+        assert_eq!(disk_cache_key(url, &config), disk_cache_key(url, &config));
+        assert_ne!(disk_cache_key(url, &config), disk_cache_key(url, &other));
+    }
+
+    #[test]
+    fn test_collect_image_urls() {
+        let ast = markdown::to_mdast(
+            "![a](one.png)\n\n- ![b](https://example.com/two.png)\n\n![c](one.png)\n\n\
+             ![d][three] ![e][none]\n\n[three]: three.png",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        assert_eq!(
+            collect_image_urls(&ast),
+            vec!["one.png", "https://example.com/two.png", "three.png"]
+        );
+    }
+
+    #[test]
+    fn test_image_cache_resolve_all_local() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut urls = Vec::new();
+        for i in 0..4 {
+            let name = format!("img{}.png", i);
+            std::fs::write(temp_dir.path().join(&name), b"not really an image").unwrap();
+            urls.push(name);
+        }
+
+        let cache = ImageCache::new();
+        let config = ImageConfig {
+            optimize_local: true,
+            ..config_embed_local()
+        };
+        cache
+            .resolve_all(&urls, temp_dir.path(), &config, false, 4)
+            .unwrap();
+
+        // Optimization fails on bogus data, so the originals are returned uncached
+        let img = cache
+            .get_or_load("img0.png", temp_dir.path(), &config, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(img.data, b"not really an image");
+    }
+
+    #[test]
+    fn test_image_cache_failed_not_retried() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = ImageCache::new();
+        let config = config_embed_local();
+
+        // Missing file fails gracefully and is remembered
+        let result = cache.get_or_load("late.png", temp_dir.path(), &config, false);
+        assert!(result.unwrap().is_none());
+
+        // Appearing later doesn't matter within the same run
+        std::fs::write(temp_dir.path().join("late.png"), b"data").unwrap();
+        let result = cache.get_or_load("late.png", temp_dir.path(), &config, false);
+        assert!(result.unwrap().is_none());
+
+        // Strict mode always retries
+        let result = cache.get_or_load("late.png", temp_dir.path(), &config, true);
+        assert!(result.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_asset_stem() {
+        let stem = asset_stem("https://example.com/img/Diagram%201.png?v=2");
+        assert!(stem.starts_with("diagram-201-"));
+        assert_eq!(stem.len(), "diagram-201-".len() + 8);
+
+        // Stable for the same source, distinct for different sources
+        assert_eq!(
+            asset_stem("./assets/logo.png"),
+            asset_stem("./assets/logo.png")
+        );
+        assert_ne!(asset_stem("./a/logo.png"), asset_stem("./b/logo.png"));
+        assert!(asset_stem("./a/logo.png").starts_with("logo-"));
+
+        // Falls back to a generic name when nothing usable remains
+        assert!(asset_stem("https://example.com/").starts_with("image-"));
+    }
+
     #[test]
     fn test_url_to_filename() {
         let f1 = url_to_filename("https://example.com/image.png");
@@ -0,0 +1,245 @@
+//! Inline content flattened into styled runs. Nested strong, emphasis,
+//! strikethrough, code and links become a flat list of spans, each carrying
+//! the formatting and link that apply to it, with reference-style links and
+//! images resolved against the document's definitions. Attribute-based
+//! backends serialize runs directly instead of re-walking the inline tree.
+
+use markdown::mdast::Node;
+use std::collections::HashMap;
+
+/// Formatting applied to a run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+}
+
+impl Style {
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+}
+
+/// The content of a run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span<'a> {
+    Text(&'a str),
+    Break,
+    Image { url: &'a str, alt: &'a str },
+    FootnoteReference(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run<'a> {
+    pub span: Span<'a>,
+    pub style: Style,
+    /// URL of the enclosing link
+    pub link: Option<&'a str>,
+}
+
+/// Link reference definitions by identifier
+#[derive(Debug, Default)]
+pub struct Definitions<'a> {
+    urls: HashMap<String, &'a str>,
+}
+
+impl<'a> Definitions<'a> {
+    /// Collect every definition in the document; the first one wins, as in
+    /// CommonMark
+    pub fn collect(root: &'a Node) -> Self {
+        let mut definitions = Definitions::default();
+        definitions.visit(root);
+        definitions
+    }
+
+    fn visit(&mut self, node: &'a Node) {
+        if let Node::Definition(def) = node {
+            self.urls
+                .entry(def.identifier.to_lowercase())
+                .or_insert(&def.url);
+            return;
+        }
+        for child in node.children().into_iter().flatten() {
+            self.visit(child);
+        }
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<&'a str> {
+        self.urls.get(&identifier.to_lowercase()).copied()
+    }
+}
+
+/// Flatten inline nodes into runs
+pub fn runs<'a>(children: &'a [Node], definitions: &Definitions<'a>) -> Vec<Run<'a>> {
+    let mut out = Vec::new();
+    for child in children {
+        push_runs(child, Style::default(), None, definitions, &mut out);
+    }
+    out
+}
+
+fn push_runs<'a>(
+    node: &'a Node,
+    style: Style,
+    link: Option<&'a str>,
+    definitions: &Definitions<'a>,
+    out: &mut Vec<Run<'a>>,
+) {
+    let mut push = |span| out.push(Run { span, style, link });
+    match node {
+        Node::Text(text) => push(Span::Text(&text.value)),
+        Node::InlineCode(code) => out.push(Run {
+            span: Span::Text(&code.value),
+            style: Style {
+                code: true,
+                ..style
+            },
+            link,
+        }),
+        Node::Break(_) => push(Span::Break),
+        Node::Image(image) => push(Span::Image {
+            url: &image.url,
+            alt: &image.alt,
+        }),
+        Node::ImageReference(imgref) => match definitions.get(&imgref.identifier) {
+            Some(url) => push(Span::Image {
+                url,
+                alt: &imgref.alt,
+            }),
+            None => push(Span::Text(&imgref.alt)),
+        },
+        Node::FootnoteReference(fnref) => push(Span::FootnoteReference(&fnref.identifier)),
+        Node::Strong(strong) => {
+            let style = Style {
+                strong: true,
+                ..style
+            };
+            for child in &strong.children {
+                push_runs(child, style, link, definitions, out);
+            }
+        }
+        Node::Emphasis(em) => {
+            let style = Style {
+                emphasis: true,
+                ..style
+            };
+            for child in &em.children {
+                push_runs(child, style, link, definitions, out);
+            }
+        }
+        Node::Delete(del) => {
+            let style = Style {
+                strikethrough: true,
+                ..style
+            };
+            for child in &del.children {
+                push_runs(child, style, link, definitions, out);
+            }
+        }
+        Node::Link(l) => {
+            for child in &l.children {
+                push_runs(child, style, Some(&l.url), definitions, out);
+            }
+        }
+        Node::LinkReference(linkref) => {
+            let link = definitions.get(&linkref.identifier).or(link);
+            for child in &linkref.children {
+                push_runs(child, style, link, definitions, out);
+            }
+        }
+        // Raw HTML and anything else without inline text is dropped
+        _ => {}
+    }
+}
+
+/// Split runs into consecutive groups sharing the same link
+pub fn link_groups<'r, 'a>(
+    runs: &'r [Run<'a>],
+) -> impl Iterator<Item = (Option<&'a str>, &'r [Run<'a>])> {
+    runs.chunk_by(|a, b| a.link == b.link)
+        .map(|group| (group[0].link, group))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn parse(md: &str) -> Node {
+        markdown::to_mdast(md, &ParseOptions::gfm()).unwrap()
+    }
+
+    fn first_paragraph(root: &Node) -> &[Node] {
+        match &root.children().unwrap()[0] {
+            Node::Paragraph(para) => &para.children,
+            other => panic!("expected paragraph, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_nested_styles_flatten() {
+        let root = parse("**bold *both* `code`** ~~gone~~");
+        let defs = Definitions::collect(&root);
+        let runs = runs(first_paragraph(&root), &defs);
+        let styled: Vec<_> = runs
+            .iter()
+            .map(|r| match r.span {
+                Span::Text(t) => (t, r.style),
+                _ => panic!("unexpected span"),
+            })
+            .collect();
+        let strong = Style {
+            strong: true,
+            ..Style::default()
+        };
+        assert_eq!(styled[0], ("bold ", strong));
+        assert_eq!(
+            styled[1],
+            (
+                "both",
+                Style {
+                    emphasis: true,
+                    ..strong
+                }
+            )
+        );
+        assert_eq!(
+            styled[3],
+            (
+                "code",
+                Style {
+                    code: true,
+                    ..strong
+                }
+            )
+        );
+        assert!(styled[4].1.is_plain());
+        assert!(styled[5].1.strikethrough);
+    }
+
+    #[test]
+    fn test_reference_links_resolve() {
+        let root = parse(
+            "See [the docs][Docs] and ![logo][img].\n\n[docs]: https://example.com/docs\n[img]: logo.png\n",
+        );
+        let defs = Definitions::collect(&root);
+        let runs = runs(first_paragraph(&root), &defs);
+        let groups: Vec<_> = link_groups(&runs)
+            .map(|(link, g)| (link, g.len()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![(None, 1), (Some("https://example.com/docs"), 1), (None, 3)]
+        );
+        assert!(runs.contains(&Run {
+            span: Span::Image {
+                url: "logo.png",
+                alt: "logo"
+            },
+            style: Style::default(),
+            link: None,
+        }));
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, BufRead, Write};
+
+/// Ask until the answer parses; an empty answer (or end of input) keeps the default
+pub fn ask<T>(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    label: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> io::Result<T> {
+    loop {
+        write!(output, "{} [{}]: ", label, default)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        let answer = if input.read_line(&mut line)? == 0 {
+            default
+        } else {
+            match line.trim() {
+                "" => default,
+                answer => answer,
+            }
+        };
+        match parse(answer) {
+            Ok(value) => return Ok(value),
+            Err(e) if answer == default => return Err(io::Error::other(e)),
+            Err(e) => writeln!(output, "  {}", e)?,
+        }
+    }
+}
+
+/// Wait for Enter before going on; `q` (or the end of input) stops instead
+pub fn wait_for_enter(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    label: &str,
+) -> io::Result<bool> {
+    write!(output, "{}? [Enter, or q to stop]: ", label)?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        writeln!(output)?;
+        return Ok(false);
+    }
+    Ok(!line.trim().eq_ignore_ascii_case("q"))
+}
+
+/// Answers come from the controlling terminal, since stdin may carry the markdown
+pub fn terminal_input() -> io::Result<Box<dyn BufRead>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(io::BufReader::new(std::fs::File::open(
+            "/dev/tty",
+        )?)))
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(Box::new(io::BufReader::new(io::stdin())))
+    }
+}
+
+/// Parse an embed mode answer into (embed_local, embed_remote)
+pub fn parse_embed(s: &str) -> Result<(bool, bool), String> {
+    match s.to_lowercase().as_str() {
+        "local" => Ok((true, false)),
+        "all" => Ok((true, true)),
+        "none" => Ok((false, false)),
+        other => Err(format!("Expected local, all or none, got '{}'", other)),
+    }
+}
+
+/// Name of the embed mode for (embed_local, embed_remote)
+pub fn embed_name(embed_local: bool, embed_remote: bool) -> &'static str {
+    match (embed_local, embed_remote) {
+        (true, true) => "all",
+        (false, false) => "none",
+        _ => "local",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_ask_default_on_empty_answer() {
+        let mut input = Cursor::new("\n");
+        let mut output = Vec::new();
+        let value = ask(&mut input, &mut output, "Embed", "local", parse_embed).unwrap();
+        assert_eq!(value, (true, false));
+        assert_eq!(String::from_utf8(output).unwrap(), "Embed [local]: ");
+    }
+
+    #[test]
+    fn test_ask_retries_invalid_answer() {
+        let mut input = Cursor::new("sometimes\nall\n");
+        let mut output = Vec::new();
+        let value = ask(&mut input, &mut output, "Embed", "local", parse_embed).unwrap();
+        assert_eq!(value, (true, true));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Expected local, all or none, got 'sometimes'"));
+    }
+
+    #[test]
+    fn test_ask_end_of_input_uses_default() {
+        let mut input = Cursor::new("");
+        let mut output = Vec::new();
+        let value = ask(&mut input, &mut output, "Embed", "none", parse_embed).unwrap();
+        assert_eq!(value, (false, false));
+    }
+
+    #[test]
+    fn test_wait_for_enter() {
+        let mut output = Vec::new();
+        let mut input = Cursor::new("\nq\n");
+        assert!(wait_for_enter(&mut input, &mut output, "Copy b.md").unwrap());
+        assert!(!wait_for_enter(&mut input, &mut output, "Copy c.md").unwrap());
+        // End of input stops too
+        assert!(!wait_for_enter(&mut input, &mut output, "Copy d.md").unwrap());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Copy b.md? [Enter, or q to stop]: "));
+    }
+
+    #[test]
+    fn test_embed_name_roundtrip() {
+        for name in ["local", "all", "none"] {
+            let (local, remote) = parse_embed(name).unwrap();
+            assert_eq!(embed_name(local, remote), name);
+        }
+    }
+}
@@ -1,44 +1,166 @@
+mod alerts;
+mod anchors;
+mod batch;
+mod bundle;
+mod captions;
+mod citations;
+mod clipboard;
+mod concat;
 mod config;
+mod config_file;
+mod details;
+mod detect;
+mod emoji;
+mod error;
+mod excerpt;
+mod fidelity;
+mod from_html;
+mod frontmatter;
+mod grid;
 mod highlight;
+mod history;
 mod image;
+mod inline;
+mod interactive;
+mod notify;
+mod render;
+mod roundtrip;
+mod serve;
+mod theme_preview;
+mod to_epub;
 mod to_html;
+mod to_image;
+mod to_jira;
 mod to_markdown;
+mod to_mediawiki;
+mod to_mhtml;
+mod to_notion;
 #[cfg(target_os = "macos")]
 mod to_nsattributedstring;
+mod to_pdf;
+mod to_prosemirror;
 mod to_rtf;
+mod to_slack;
+mod to_text;
+mod transform;
+mod unfurl;
+mod version;
+mod warnings;
 
 use clap::Parser;
-use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext};
-use config::{CliArgs, CliHighlightArgs, CliImageArgs, Config, default_config_dir};
+use config::{
+    Appearance, CliArgs, CliHighlightArgs, CliImageArgs, ClipboardBackendKind, CodeBadge,
+    CodeStyle, Config, EmbedFormat, EmojiImages, FootnotePlacement, HeadingCase, ImageConfig,
+    ImageHeader, ImageMode, ImageRewrite, InputFormat, ListStyle, OutputSpec, Separator,
+    TableOverflow, default_config_dir,
+};
+use error::MdcopyError;
 use log::{LevelFilter, debug, info};
 use markdown::{Constructs, Options, ParseOptions};
+use std::borrow::Cow;
 use std::fs;
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClipboardFormat {
     Html,
     Rtf,
     Markdown,
+    Jira,
+    Slack,
+    MediaWiki,
+    Notion,
+    Text,
+    Pdf,
+    Epub,
+    Mhtml,
+    Image,
+    ProseMirror,
     #[cfg(target_os = "macos")]
     Native,
 }
 
+impl ClipboardFormat {
+    /// File extension used for batch output names
+    fn extension(self) -> &'static str {
+        match self {
+            ClipboardFormat::Html => "html",
+            ClipboardFormat::Rtf => "rtf",
+            ClipboardFormat::Markdown => "md",
+            ClipboardFormat::Jira => "jira",
+            ClipboardFormat::Slack => "slack",
+            ClipboardFormat::MediaWiki => "wiki",
+            ClipboardFormat::Notion => "notion.json",
+            ClipboardFormat::Text => "txt",
+            ClipboardFormat::Pdf => "pdf",
+            ClipboardFormat::Epub => "epub",
+            ClipboardFormat::Mhtml => "mhtml",
+            ClipboardFormat::Image => "png",
+            ClipboardFormat::ProseMirror => "json",
+            #[cfg(target_os = "macos")]
+            ClipboardFormat::Native => "rtfd",
+        }
+    }
+
+    /// Content type of the format's output, for `mdcopy serve`
+    fn mime_type(self) -> &'static str {
+        match self {
+            ClipboardFormat::Html => "text/html; charset=utf-8",
+            ClipboardFormat::Rtf => "application/rtf",
+            ClipboardFormat::Markdown => "text/markdown; charset=utf-8",
+            ClipboardFormat::Notion | ClipboardFormat::ProseMirror => "application/json",
+            ClipboardFormat::Jira
+            | ClipboardFormat::Slack
+            | ClipboardFormat::MediaWiki
+            | ClipboardFormat::Text => "text/plain; charset=utf-8",
+            ClipboardFormat::Pdf => "application/pdf",
+            ClipboardFormat::Epub => "application/epub+zip",
+            ClipboardFormat::Mhtml => "multipart/related",
+            ClipboardFormat::Image => "image/png",
+            #[cfg(target_os = "macos")]
+            ClipboardFormat::Native => "application/octet-stream",
+        }
+    }
+
+    /// Text formats, which go on the clipboard as plain text
+    fn is_plain_text(self) -> bool {
+        matches!(
+            self,
+            ClipboardFormat::Markdown
+                | ClipboardFormat::Jira
+                | ClipboardFormat::Slack
+                | ClipboardFormat::MediaWiki
+                | ClipboardFormat::Notion
+                | ClipboardFormat::Text
+        )
+    }
+
+    /// Binary documents, which can't go on the clipboard
+    fn is_file_only(self) -> bool {
+        matches!(
+            self,
+            ClipboardFormat::Pdf | ClipboardFormat::Epub | ClipboardFormat::Mhtml
+        )
+    }
+}
+
 fn parse_formats(s: &str) -> Result<Vec<ClipboardFormat>, String> {
     let mut formats = Vec::new();
     for part in s.split(',') {
-        match part.trim().to_lowercase().as_str() {
-            "html" => formats.push(ClipboardFormat::Html),
-            "rtf" => formats.push(ClipboardFormat::Rtf),
-            "markdown" | "md" => formats.push(ClipboardFormat::Markdown),
-            #[cfg(target_os = "macos")]
-            "native" | "nsattributedstring" => formats.push(ClipboardFormat::Native),
-            #[cfg(not(target_os = "macos"))]
-            "native" | "nsattributedstring" => {
-                return Err("Native format is only available on macOS".to_string());
+        let name = part.trim().to_lowercase();
+        let registration =
+            render::lookup(&name).ok_or_else(|| format!("Unknown format: {}", name))?;
+        match (registration.format(), registration.unavailable) {
+            (Some(format), _) => formats.push(format),
+            (None, reason) => {
+                return Err(format!(
+                    "The {} format is {}",
+                    registration.name(),
+                    reason.unwrap_or("not available")
+                ));
             }
-            other => return Err(format!("Unknown format: {}", other)),
         }
     }
     if formats.is_empty() {
@@ -47,41 +169,255 @@ fn parse_formats(s: &str) -> Result<Vec<ClipboardFormat>, String> {
     Ok(formats)
 }
 
+/// Parse an option value, naming the valid values when it isn't one
+fn parse_arg<T>(
+    value: Option<&str>,
+    parse: fn(&str) -> Option<T>,
+    what: &str,
+    valid: &str,
+) -> Result<Option<T>, MdcopyError> {
+    value
+        .map(|s| {
+            parse(s).ok_or_else(|| {
+                MdcopyError::Usage(format!("Invalid {} '{}'. Valid: {}", what, s, valid))
+            })
+        })
+        .transpose()
+}
+
+fn parse_image_header(s: &str) -> Result<ImageHeader, String> {
+    ImageHeader::parse(s).ok_or_else(|| format!("Expected [DOMAIN=]NAME: VALUE, got '{}'", s))
+}
+
 #[derive(Parser)]
 #[command(name = "mdcopy")]
 #[command(version)]
 #[command(about = "Convert markdown to clipboard with text, HTML, and RTF formats")]
-#[command(disable_help_flag = true)]
+#[command(disable_help_flag = true, disable_version_flag = true)]
 struct Args {
     /// Print help (includes current settings with sources)
     #[arg(long, action = clap::ArgAction::SetTrue)]
     help: bool,
-    /// Input file (use - for stdin, default: stdin)
+
+    /// Print version (with --json: build target, features and bundled syntax/theme counts)
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Print --version as JSON
+    #[arg(long, requires = "version")]
+    json: bool,
+    /// Input file (use - for stdin, default: stdin); repeat to concatenate
+    /// several files into one document
     #[arg(short, long)]
-    input: Option<PathBuf>,
+    input: Vec<PathBuf>,
+
+    /// What goes between concatenated input files: rule (default, `---`),
+    /// pagebreak, none, or markdown of your own
+    #[arg(long, value_name = "SEP")]
+    separator: Option<String>,
+
+    /// Input format: markdown (default) or html, which is converted to
+    /// markdown and then to the output formats
+    #[arg(long, value_name = "FORMAT")]
+    from: Option<String>,
 
     /// Output to file instead of clipboard (use - for stdout)
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "output_dir")]
     output: Option<PathBuf>,
 
+    /// Write one file per input and format into this directory (batch mode)
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Output filename template for --output-dir: {stem}, {name}, {ext}, {dir} (default: {stem}.{ext})
+    #[arg(long, value_name = "TEMPLATE")]
+    name_template: Option<String>,
+
+    /// Documents converted in parallel with --output-dir, `convert` and `serve` (default: one per CPU)
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Write a manifest of embedded images (source and SHA-256) to this file (use - for stdout)
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// BibTeX or CSL-JSON bibliography for `[@key]` citations, listed in a References section
+    #[arg(long, value_name = "FILE")]
+    bibliography: Option<PathBuf>,
+
+    /// Input files (`-` for stdin), the same as -i: one document, several
+    /// concatenated into one, converted one by one with --output-dir or
+    /// copied one after another with --sequence
+    #[arg(value_name = "FILES")]
+    inputs: Vec<PathBuf>,
+
+    /// Copy the input files to the clipboard one at a time, pressing Enter
+    /// (or waiting --sequence-delay) before each next one
+    #[arg(long)]
+    sequence: bool,
+
+    /// With --sequence, milliseconds before the next file is copied instead
+    /// of waiting for Enter (0 = wait for Enter)
+    #[arg(long, value_name = "MS")]
+    sequence_delay: Option<u64>,
+
     /// Root directory for resolving relative image paths (default: input file's directory or cwd)
     #[arg(short, long)]
     root: Option<PathBuf>,
 
-    /// Fail on errors instead of falling back gracefully
+    /// Fail instead of falling back: on images that can't be loaded, unknown
+    /// languages and themes, content a format drops, oversized clipboard
+    /// output and clipboard flavors that go missing (choose with [strict])
     #[arg(short = 's', long, overrides_with = "no_strict")]
     strict: bool,
 
     #[arg(short = 'S', long, overrides_with = "strict", hide = true)]
     no_strict: bool,
 
-    /// Emit ProseMirror slice marker for Confluence paste compatibility
+    /// Confluence/Jira paste: mark HTML as a ProseMirror slice and copy the
+    /// editor's JSON slice with the clipboard formats
     #[arg(short = 'p', long, overrides_with = "no_prosemirror")]
     prosemirror: bool,
 
     #[arg(short = 'P', long, overrides_with = "prosemirror", hide = true)]
     no_prosemirror: bool,
 
+    /// Clipboard backend: auto (default; first available of clipboard.order),
+    /// system, native, wayland, osc52, or file
+    #[arg(
+        long = "clipboard",
+        alias = "clipboard-backend",
+        value_name = "BACKEND"
+    )]
+    clipboard_backend: Option<String>,
+
+    /// Copy through the terminal with an OSC 52 sequence (same as --clipboard osc52)
+    #[arg(long, conflicts_with = "clipboard_backend")]
+    osc52: bool,
+
+    /// Linux: keep serving the clipboard from a background process after exit,
+    /// until another application copies something
+    #[arg(long, visible_alias = "hold", overrides_with = "no_clipboard_hold")]
+    clipboard_hold: bool,
+
+    #[arg(
+        long,
+        alias = "no-hold",
+        overrides_with = "clipboard_hold",
+        hide = true
+    )]
+    no_clipboard_hold: bool,
+
+    /// Run as the background process that serves a held clipboard, reading
+    /// what to serve from stdin
+    #[arg(long, hide = true)]
+    clipboard_holder: bool,
+
+    /// Skip the copy when the clipboard still holds identical output from the
+    /// last one (default: on)
+    #[arg(long, overrides_with = "no_clipboard_skip_unchanged")]
+    clipboard_skip_unchanged: bool,
+
+    #[arg(long, overrides_with = "clipboard_skip_unchanged", hide = true)]
+    no_clipboard_skip_unchanged: bool,
+
+    /// Keep each copy in the history for `mdcopy history` (off by default)
+    #[arg(long, overrides_with = "no_history")]
+    history: bool,
+
+    #[arg(long, overrides_with = "history", hide = true)]
+    no_history: bool,
+
+    /// Read the clipboard back after copying and exit with an error unless
+    /// every flavor is there, byte for byte
+    #[arg(long, overrides_with = "no_verify")]
+    verify: bool,
+
+    #[arg(long, overrides_with = "verify", hide = true)]
+    no_verify: bool,
+
+    /// Put only the first WORDS words (whole paragraphs, with a link to the
+    /// file) in the plain text flavor; rich flavors keep the whole document
+    #[arg(long, value_name = "WORDS")]
+    excerpt: Option<usize>,
+
+    /// Refuse to copy a document, or clipboard payload, over MB megabytes
+    /// (0 = no limit); write it with -o instead (default: 100)
+    #[arg(long, value_name = "MB")]
+    max_output_size: Option<u64>,
+
+    /// Give HTML headings GitHub-style id slugs and point `#heading` links at
+    /// them (default: on)
+    #[arg(long, overrides_with = "no_heading_ids")]
+    heading_ids: bool,
+
+    #[arg(long, overrides_with = "heading_ids", hide = true)]
+    no_heading_ids: bool,
+
+    /// HTML: output a complete document, using the front matter's title and
+    /// author for its metadata
+    #[arg(long, overrides_with = "no_standalone")]
+    standalone: bool,
+
+    #[arg(long, overrides_with = "standalone", hide = true)]
+    no_standalone: bool,
+
+    /// HTML: lay out for email clients (Outlook, Gmail) with a 600px table
+    /// layout, conservative inline CSS and sized images
+    #[arg(long, overrides_with = "no_email")]
+    email: bool,
+
+    #[arg(long, overrides_with = "email", hide = true)]
+    no_email: bool,
+
+    /// Keep YAML front matter in markdown output (it is dropped by default)
+    #[arg(long, overrides_with = "no_keep_frontmatter")]
+    keep_frontmatter: bool,
+
+    #[arg(long, overrides_with = "keep_frontmatter", hide = true)]
+    no_keep_frontmatter: bool,
+
+    /// Number figures, tables and code listings with captions and resolve
+    /// `[@fig:id]` cross-references
+    #[arg(long, overrides_with = "no_captions")]
+    captions: bool,
+
+    #[arg(long, overrides_with = "captions", hide = true)]
+    no_captions: bool,
+
+    /// Turn standalone bare URLs into preview cards with the page's title,
+    /// description and image (fetches every such page)
+    #[arg(long, overrides_with = "no_unfurl")]
+    unfurl: bool,
+
+    #[arg(long, overrides_with = "unfurl", hide = true)]
+    no_unfurl: bool,
+
+    /// Post a desktop notification when done (for hotkey-invoked runs)
+    #[arg(long, overrides_with = "no_notify")]
+    notify: bool,
+
+    #[arg(long, overrides_with = "notify", hide = true)]
+    no_notify: bool,
+
+    /// HTML: fold each section headed at or below LEVEL into a <details> block
+    #[arg(long, value_name = "LEVEL", value_parser = clap::value_parser!(u8).range(1..=6))]
+    collapsible_headings: Option<u8>,
+
+    /// Convert only the section under this heading, up to the next heading of
+    /// the same or a higher level
+    #[arg(long, value_name = "HEADING")]
+    section: Option<String>,
+
+    /// Only match the --section heading at this level
+    #[arg(long, value_name = "LEVEL", requires = "section", value_parser = clap::value_parser!(u8).range(1..=6))]
+    section_level: Option<u8>,
+
+    /// Resolve footnotes: end (notes at the end), section (after each H2 section),
+    /// or inline (note text in parentheses)
+    #[arg(long, value_name = "PLACEMENT")]
+    footnotes: Option<String>,
+
     /// Enable syntax highlighting
     #[arg(short = 'h', long, overrides_with = "no_highlight")]
     highlight: bool,
@@ -90,9 +426,21 @@ struct Args {
     no_highlight: bool,
 
     /// Syntax highlighting theme
-    #[arg(short = 't', long = "highlight-theme")]
+    #[arg(
+        short = 't',
+        long = "highlight-theme",
+        add = clap_complete::ArgValueCompleter::new(complete_theme)
+    )]
     highlight_theme: Option<String>,
 
+    /// Use the dark theme variant (highlight.theme_dark) regardless of system appearance
+    #[arg(long = "highlight-dark", overrides_with = "highlight_light")]
+    highlight_dark: bool,
+
+    /// Use the light theme variant (highlight.theme_light) regardless of system appearance
+    #[arg(long = "highlight-light", overrides_with = "highlight_dark")]
+    highlight_light: bool,
+
     /// Custom themes directory
     #[arg(long = "highlight-themes-dir")]
     highlight_themes_dir: Option<PathBuf>,
@@ -101,6 +449,44 @@ struct Args {
     #[arg(short = 'x', long = "highlight-syntaxes-dir")]
     highlight_syntaxes_dir: Option<PathBuf>,
 
+    /// Themes from a .zip or .tar.gz bundle, as a path or URL
+    #[arg(long = "highlight-themes-source", value_name = "PATH|URL")]
+    highlight_themes_source: Option<String>,
+
+    /// Syntaxes from a .zip or .tar.gz bundle, as a path or URL
+    #[arg(long = "highlight-syntaxes-source", value_name = "PATH|URL")]
+    highlight_syntaxes_source: Option<String>,
+
+    /// Warn about theme colors below the WCAG AA contrast ratio (4.5:1)
+    #[arg(long)]
+    check_contrast: bool,
+
+    /// Guess the language of code blocks without one, from a shebang or what
+    /// the code looks like
+    #[arg(long)]
+    detect_language: bool,
+
+    /// Language label on fenced code blocks in HTML and native output: corner, header or none (default)
+    #[arg(long, value_name = "STYLE")]
+    code_badge: Option<String>,
+
+    /// Markup of code blocks in HTML output: div-inline (inline colors,
+    /// default), pre-classes (bare <pre><code class>) or table (line numbers)
+    #[arg(long, value_name = "STYLE")]
+    code_style: Option<String>,
+
+    /// Wrap long lines of code blocks in HTML and RTF output (--no-code-wrap
+    /// keeps them whole; default: up to the app pasted into)
+    #[arg(long, overrides_with = "no_code_wrap")]
+    code_wrap: bool,
+
+    #[arg(long, overrides_with = "code_wrap", hide = true)]
+    no_code_wrap: bool,
+
+    /// Widest code blocks get in HTML and RTF output, in characters (0 = no limit)
+    #[arg(long, value_name = "COLUMNS")]
+    code_max_width: Option<u32>,
+
     /// Embed all images (sets both local and remote)
     #[arg(short = 'e', long, overrides_with_all = ["no_embed", "embed_local", "no_embed_local", "embed_remote", "no_embed_remote"])]
     embed: bool,
@@ -151,6 +537,104 @@ struct Args {
     #[arg(long)]
     quality: Option<u8>,
 
+    /// Format of optimized images: auto (JPEG, or PNG with transparency;
+    /// default), jpeg, png or webp
+    #[arg(long, value_name = "FORMAT")]
+    image_format: Option<String>,
+
+    /// Keep EXIF, GPS and other metadata in images embedded without
+    /// optimization (stripped by default)
+    #[arg(long)]
+    keep_exif: bool,
+
+    /// Native output: link images larger than this (in KB) as thumbnails instead of inlining them
+    #[arg(long, value_name = "KB")]
+    native_inline_max_kb: Option<u64>,
+
+    /// Replace emoji with inline glyph images: none (default), rtf, or all (RTF and HTML)
+    #[arg(long, value_name = "MODE")]
+    emoji_images: Option<String>,
+
+    /// Image handling: embed (default) or links-only (every image becomes a plain link)
+    #[arg(long, value_name = "MODE")]
+    images: Option<String>,
+
+    /// Ordered list numbering: 1. (default), 1), a., i.
+    #[arg(long, value_name = "STYLE")]
+    list_style: Option<String>,
+
+    /// Replace straight quotes with a locale's quotation marks (e.g. en, de, fr, de-CH),
+    /// four literal marks like „“‚‘, or none
+    #[arg(long, value_name = "LOCALE")]
+    quotes: Option<String>,
+
+    /// Recapitalize headings: title-case, sentence-case or none (default)
+    #[arg(long, value_name = "CASE")]
+    headings: Option<String>,
+
+    /// Native output: tables with more body rows than this become a monospace grid or are
+    /// split (default: 200, 0 = no limit)
+    #[arg(long, value_name = "N")]
+    native_table_max_rows: Option<usize>,
+
+    /// Native output: what to do with tables over --native-table-max-rows: grid (default) or split
+    #[arg(long, value_name = "MODE")]
+    native_table_overflow: Option<String>,
+
+    /// Typeset prose: -- and --- become en and em dashes, ... an ellipsis, and
+    /// straight quotes curly (English style unless --quotes picks one)
+    #[arg(long, overrides_with = "no_smart_punctuation")]
+    smart_punctuation: bool,
+
+    #[arg(long, overrides_with = "smart_punctuation", hide = true)]
+    no_smart_punctuation: bool,
+
+    /// Max images to load and optimize in parallel (default: 4, 1 = sequential)
+    #[arg(long, value_name = "N")]
+    image_concurrency: Option<usize>,
+
+    /// Remote image fetch timeout in milliseconds (default: 10000, 0 = none)
+    #[arg(long, value_name = "MS")]
+    fetch_timeout_ms: Option<u64>,
+
+    /// Retries for transient remote image fetch failures (default: 2)
+    #[arg(long, value_name = "N")]
+    fetch_retries: Option<u32>,
+
+    /// Largest remote image to download in bytes (default: 20 MiB, 0 = unlimited)
+    #[arg(long, value_name = "BYTES")]
+    fetch_max_bytes: Option<u64>,
+
+    /// Extra header for remote image fetches, optionally limited to a domain
+    /// (repeatable), e.g. "github.com=Authorization: token abc"
+    #[arg(long, value_name = "[DOMAIN=]NAME: VALUE", value_parser = parse_image_header)]
+    image_header: Vec<ImageHeader>,
+
+    /// Rewrite image URLs matching a regex before loading them (repeatable,
+    /// tried in order), e.g. "^/assets/=https://cdn.example.com/assets/"
+    #[arg(long, value_name = "PATTERN=REPLACEMENT", value_parser = ImageRewrite::parse)]
+    image_rewrite: Vec<ImageRewrite>,
+
+    /// Use credentials from ~/.netrc (or $NETRC) for remote image fetches
+    #[arg(long)]
+    netrc: bool,
+
+    /// PEM file of root certificates to trust for remote fetches, e.g. a
+    /// corporate proxy's CA (replaces the built-in roots)
+    #[arg(long, value_name = "FILE")]
+    ca_bundle: Option<PathBuf>,
+
+    /// Skip TLS certificate verification for remote fetches (unsafe)
+    #[arg(long)]
+    insecure: bool,
+
+    /// Keep remote images in a persistent cache across runs
+    #[arg(long, overrides_with = "no_image_cache")]
+    image_cache: bool,
+
+    #[arg(long, overrides_with = "image_cache", hide = true)]
+    no_image_cache: bool,
+
     /// Path to configuration file
     #[arg(short, long)]
     config: Option<PathBuf>,
@@ -159,6 +643,16 @@ struct Args {
     #[arg(long)]
     list_themes: bool,
 
+    /// List output formats, whether this platform supports them, and where
+    /// each works best, then exit
+    #[arg(long)]
+    list_formats: bool,
+
+    /// Pick formats, image embedding and theme at a prompt before copying
+    /// (requires a terminal)
+    #[arg(long)]
+    interactive: bool,
+
     /// Show current configuration as TOML and exit
     #[arg(long)]
     show_config: bool,
@@ -174,9 +668,177 @@ struct Args {
     /// Output format(s): html, rtf, markdown, native (comma-separated for clipboard, single for file output)
     ///
     /// Native format (macOS only) uses NSAttributedString for best clipboard compatibility
-    /// with native apps like TextEdit, Notes, Mail. Native is clipboard-only.
-    #[arg(short, long)]
+    /// with native apps like TextEdit, Notes, Mail. As a file it is written as RTFD
+    /// (a bundle for `.rtfd` paths, flat RTFD data otherwise).
+    ///
+    /// Without it the clipboard gets the `formats` from the config file or
+    /// MDCOPY_FORMAT.
+    #[arg(short, long, global = true)]
+    format: Option<String>,
+
+    /// Extra output profile as FORMAT[=PATH], written alongside the primary output (repeatable)
+    ///
+    /// Without a path the profile targets the clipboard, e.g. `-o doc.html --also html,rtf`
+    /// exports a file and sets the clipboard in one run.
+    #[arg(long = "also", value_name = "FORMAT[=PATH]")]
+    also: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// The first input file, with -i or as an argument, which the project
+/// config is looked up from
+fn first_input(args: &Args) -> Option<PathBuf> {
+    args.input.iter().chain(&args.inputs).next().cloned()
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Convert files or directory trees into an output directory, rewriting
+    /// links between markdown documents to the converted files
+    Convert(ConvertArgs),
+    /// Render a document to HTML, convert that back to markdown and report
+    /// the constructs that changed on the way (exits with status 1 if any did)
+    Roundtrip(RoundtripArgs),
+    /// List the formats on the clipboard with their sizes, or write the data
+    /// of one of them out
+    Inspect(InspectArgs),
+    /// List, show and re-copy earlier copies (with `history.enable`)
+    History(HistoryArgs),
+    /// Print a shell completion script, which asks mdcopy for candidates as
+    /// you type (so `--highlight-theme` offers themes added since)
+    Completions(CompletionsArgs),
+    /// Run as a conversion daemon: POST markdown to /convert and get the
+    /// rendered document back; GET /status for queue and latency metrics
+    Serve(ServeArgs),
+    /// Write a commented default config file, or check one for mistakes
+    Config(ConfigArgs),
+    /// Preview the highlighting themes on sample code
+    Themes(ThemesArgs),
+}
+
+#[derive(clap::Args)]
+struct ConvertArgs {
+    /// Convert every markdown file under the given directories
+    #[arg(short = 'R', long)]
+    recursive: bool,
+
+    /// Output directory (relative structure is preserved)
+    #[arg(short, long, value_name = "DIR")]
+    output: PathBuf,
+
+    /// Markdown files or directories to convert
+    #[arg(value_name = "PATHS", required = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct RoundtripArgs {
+    /// Markdown file to check (`-` for stdin)
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7373")]
+    listen: String,
+
+    /// Requests waiting for a worker before new ones get a 503
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    queue: usize,
+}
+
+#[derive(clap::Args)]
+struct InspectArgs {
+    /// Format to dump: a MIME type or UTI, or part of one (`html` picks
+    /// `text/html` or `public.html`)
+    #[arg(value_name = "FORMAT")]
     format: Option<String>,
+
+    /// Write the dumped format to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE", requires = "format")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct CompletionsArgs {
+    /// Shell to generate completions for
+    #[arg(value_name = "SHELL")]
+    shell: clap_complete::Shell,
+}
+
+#[derive(clap::Args)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    action: ConfigAction,
+}
+
+#[derive(clap::Subcommand)]
+enum ConfigAction {
+    /// Write config.toml with every setting commented out to the config
+    /// directory
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Report unknown keys, values of the wrong type and missing themes,
+    /// directories and files in a config file (exits with status 1 if any)
+    Validate {
+        /// Config file to check (default: the one mdcopy loads)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct ThemesArgs {
+    #[command(subcommand)]
+    action: ThemesAction,
+}
+
+#[derive(clap::Subcommand)]
+enum ThemesAction {
+    /// Highlight a sample code block in every available theme, in the
+    /// terminal or as an HTML gallery
+    Preview {
+        /// Language of the sample
+        #[arg(long, default_value = "rust")]
+        lang: String,
+
+        /// Highlight the code in FILE instead of the built-in sample
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Write an HTML gallery to FILE instead of printing to the terminal
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct HistoryArgs {
+    #[command(subcommand)]
+    action: HistoryAction,
+}
+
+#[derive(clap::Subcommand)]
+enum HistoryAction {
+    /// List the kept copies, newest first
+    List,
+    /// Print the markdown source of copy N (1 is the newest)
+    Show {
+        #[arg(value_name = "N")]
+        n: usize,
+    },
+    /// Put copy N back on the clipboard with all of its formats
+    Recopy {
+        #[arg(value_name = "N")]
+        n: usize,
+    },
 }
 
 fn init_logger(verbose: u8, quiet: bool) {
@@ -191,21 +853,27 @@ fn init_logger(verbose: u8, quiet: bool) {
         }
     };
 
-    env_logger::Builder::new()
+    let logger = env_logger::Builder::new()
         .filter_level(level)
         .format_target(false)
         .format_timestamp(None)
-        .init();
+        .build();
+    let max_level = logger.filter();
+    // Warnings are collected and printed as a summary at the end of the run
+    if log::set_boxed_logger(Box::new(warnings::SummaryLogger::new(logger))).is_ok() {
+        log::set_max_level(max_level);
+    }
 }
 
 fn read_input(path: &PathBuf) -> io::Result<String> {
-    if path.as_os_str() == "-" {
+    let content = if path.as_os_str() == "-" {
         let mut content = String::new();
         io::stdin().read_to_string(&mut content)?;
-        Ok(content)
+        content
     } else {
-        fs::read_to_string(path)
-    }
+        fs::read_to_string(path)?
+    };
+    Ok(frontmatter::strip_shebang(content))
 }
 
 fn resolve_base_dir(input: &std::path::Path, root: Option<PathBuf>) -> PathBuf {
@@ -221,9 +889,57 @@ fn resolve_base_dir(input: &std::path::Path, root: Option<PathBuf>) -> PathBuf {
     }
 }
 
-fn main() -> io::Result<()> {
+fn main() -> ExitCode {
+    // Answer the shell's completion requests (COMPLETE=<shell> mdcopy ...)
+    clap_complete::CompleteEnv::with_factory(<Args as clap::CommandFactory>::command).complete();
     let args = Args::parse();
+    if args.clipboard_holder {
+        return match clipboard::serve_held() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(_) => ExitCode::FAILURE,
+        };
+    }
     init_logger(args.verbose, args.quiet);
+    let result = run(args);
+    if let Err(e) = &result {
+        notify::send("mdcopy failed", &e.to_string());
+    }
+    warnings::print_summary();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), MdcopyError> {
+    if args.version {
+        if args.json {
+            println!("{}", version::BuildInfo::current().to_json());
+        } else {
+            println!("mdcopy {}", env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(());
+    }
+
+    if args.list_formats {
+        for registration in render::REGISTRY {
+            let status = match registration.unavailable {
+                None => "available".to_string(),
+                Some(reason) => format!("unavailable: {}", reason),
+            };
+            let aliases = match &registration.names[1..] {
+                [] => String::new(),
+                rest => format!(" (alias: {})", rest.join(", ")),
+            };
+            println!("{}{} [{}]", registration.name(), aliases, status);
+            println!("  {}", registration.description);
+            println!("  Recommended for: {}", registration.recommended_for);
+        }
+        return Ok(());
+    }
 
     // Handle --list-themes early (before config loading)
     if args.list_themes {
@@ -232,7 +948,7 @@ fn main() -> io::Result<()> {
             .highlight_themes_dir
             .clone()
             .or_else(|| default_config_dir().map(|p| p.join("themes")));
-        let themes = highlight::HighlightContext::list_themes(themes_dir.as_ref());
+        let themes = highlight::HighlightContext::list_themes(themes_dir.as_slice());
         println!("Available themes:");
         for theme in themes {
             println!("  {}", theme);
@@ -240,6 +956,23 @@ fn main() -> io::Result<()> {
         return Ok(());
     }
 
+    // The completion script only registers mdcopy with the shell; candidates
+    // come from `COMPLETE=<shell> mdcopy` at completion time
+    if let Some(Command::Completions(completions)) = &args.command {
+        print_completions(completions.shell)?;
+        return Ok(());
+    }
+
+    // Config file commands work on the file itself, not the loaded config
+    if let Some(Command::Config(config)) = &args.command {
+        return run_config(&config.action, args.config.clone());
+    }
+
+    // Clipboard inspection reads the clipboard and needs no configuration
+    if let Some(Command::Inspect(inspect)) = &args.command {
+        return inspect_clipboard(inspect);
+    }
+
     // Build configuration from CLI args, env vars, and config file
     // --embed / --no-embed are shorthands that set both embed_local and embed_remote
     let (embed_local_base, embed_remote_base) = match (args.embed, args.no_embed) {
@@ -293,6 +1026,20 @@ fn main() -> io::Result<()> {
         _ => None,
     };
 
+    // --highlight-dark / --highlight-light
+    let appearance = match (args.highlight_dark, args.highlight_light) {
+        (true, false) => Some(Appearance::Dark),
+        (false, true) => Some(Appearance::Light),
+        _ => None,
+    };
+
+    // --image-cache / --no-image-cache
+    let cache = match (args.image_cache, args.no_image_cache) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
     // --prosemirror / --no-prosemirror
     let prosemirror = match (args.prosemirror, args.no_prosemirror) {
         (true, false) => Some(true),
@@ -300,29 +1047,273 @@ fn main() -> io::Result<()> {
         _ => None,
     };
 
+    // --heading-ids / --no-heading-ids
+    let heading_ids = match (args.heading_ids, args.no_heading_ids) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --standalone / --no-standalone
+    let standalone = match (args.standalone, args.no_standalone) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --email / --no-email
+    let email = match (args.email, args.no_email) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --keep-frontmatter / --no-keep-frontmatter
+    let keep_frontmatter = match (args.keep_frontmatter, args.no_keep_frontmatter) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --code-wrap / --no-code-wrap
+    let html_code_wrap = match (args.code_wrap, args.no_code_wrap) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --smart-punctuation / --no-smart-punctuation
+    let smart_punctuation = match (args.smart_punctuation, args.no_smart_punctuation) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --captions / --no-captions
+    let captions = match (args.captions, args.no_captions) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --unfurl / --no-unfurl
+    let unfurl = match (args.unfurl, args.no_unfurl) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --notify / --no-notify
+    let notify = match (args.notify, args.no_notify) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --images <MODE>
+    let image_mode = parse_arg(
+        args.images.as_deref(),
+        ImageMode::parse,
+        "image mode",
+        "embed, links-only",
+    )?;
+
+    // --image-format <FORMAT>
+    let image_format = parse_arg(
+        args.image_format.as_deref(),
+        EmbedFormat::parse,
+        "image format",
+        "auto, jpeg, png, webp",
+    )?;
+
+    // --clipboard <BACKEND>
+    let clipboard_backend = parse_arg(
+        args.clipboard_backend.as_deref(),
+        ClipboardBackendKind::parse,
+        "clipboard backend",
+        "auto, system, native, wayland, osc52, file",
+    )?;
+    let clipboard_backend = if args.osc52 {
+        Some(ClipboardBackendKind::Osc52)
+    } else {
+        clipboard_backend
+    };
+
+    // --clipboard-hold / --no-clipboard-hold
+    let clipboard_hold = match (args.clipboard_hold, args.no_clipboard_hold) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --clipboard-skip-unchanged / --no-clipboard-skip-unchanged
+    let clipboard_skip_unchanged = match (
+        args.clipboard_skip_unchanged,
+        args.no_clipboard_skip_unchanged,
+    ) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --history / --no-history
+    let history = match (args.history, args.no_history) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --verify / --no-verify
+    let clipboard_verify = match (args.verify, args.no_verify) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+
+    // --from <FORMAT>
+    let from = parse_arg(
+        args.from.as_deref(),
+        InputFormat::parse,
+        "input format",
+        "markdown, html",
+    )?;
+
+    // --footnotes <PLACEMENT>
+    let footnotes = parse_arg(
+        args.footnotes.as_deref(),
+        FootnotePlacement::parse,
+        "footnote placement",
+        "end, section, inline",
+    )?;
+
+    // --emoji-images <MODE>
+    let emoji_images = parse_arg(
+        args.emoji_images.as_deref(),
+        EmojiImages::parse,
+        "emoji images mode",
+        "none, rtf, all",
+    )?;
+
+    // --code-badge <STYLE>
+    let code_badge = parse_arg(
+        args.code_badge.as_deref(),
+        CodeBadge::parse,
+        "code badge",
+        "corner, header, none",
+    )?;
+
+    // --code-style <STYLE>
+    let html_code_style = parse_arg(
+        args.code_style.as_deref(),
+        CodeStyle::parse,
+        "code style",
+        "div-inline, pre-classes, table",
+    )?;
+
+    // --list-style <STYLE>
+    let list_style = parse_arg(
+        args.list_style.as_deref(),
+        ListStyle::parse,
+        "list style",
+        "1., 1), a., i.",
+    )?;
+
+    // --headings <CASE>
+    let headings = parse_arg(
+        args.headings.as_deref(),
+        HeadingCase::parse,
+        "heading case",
+        "title-case, sentence-case, none",
+    )?;
+
+    // --native-table-overflow <MODE>
+    let native_table_overflow = parse_arg(
+        args.native_table_overflow.as_deref(),
+        TableOverflow::parse,
+        "table overflow",
+        "grid, split",
+    )?;
+
     let cli_args = CliArgs {
-        input: args.input,
+        input: first_input(&args),
+        separator: args.separator.as_deref().map(Separator::parse),
+        from,
         output: args.output.clone(),
+        output_dir: args.output_dir.clone(),
+        name_template: args.name_template.clone(),
+        jobs: args.jobs,
+        sequence_delay_ms: args.sequence_delay,
+        manifest: args.manifest.clone(),
+        bibliography: args.bibliography,
         root: args.root,
         strict,
         prosemirror,
+        collapsible_headings: args.collapsible_headings,
+        section: args.section,
+        section_level: args.section_level,
+        heading_ids,
+        standalone,
+        email,
+        keep_frontmatter,
+        footnotes,
+        captions,
+        unfurl,
+        notify,
         highlight: CliHighlightArgs {
             enable: highlight,
             theme: args.highlight_theme,
+            appearance,
             themes_dir: args.highlight_themes_dir,
             syntaxes_dir: args.highlight_syntaxes_dir,
+            themes_source: args.highlight_themes_source,
+            syntaxes_source: args.highlight_syntaxes_source,
+            check_contrast: args.check_contrast.then_some(true),
+            detect_language: args.detect_language.then_some(true),
+            badge: code_badge,
         },
         image: CliImageArgs {
+            mode: image_mode,
             embed_local,
             embed_remote,
             optimize_local,
             optimize_remote,
             max_dimension: args.max_dimension,
             quality: args.quality,
+            format: image_format,
+            keep_exif: args.keep_exif.then_some(true),
+            cache,
+            fetch_timeout_ms: args.fetch_timeout_ms,
+            fetch_retries: args.fetch_retries,
+            fetch_max_bytes: args.fetch_max_bytes,
+            fetch_headers: args.image_header,
+            rewrite: args.image_rewrite,
+            fetch_netrc: args.netrc.then_some(true),
+            fetch_ca_bundle: args.ca_bundle,
+            fetch_insecure: args.insecure.then_some(true),
+            native_inline_max_kb: args.native_inline_max_kb,
+            emoji_images,
+            concurrency: args.image_concurrency,
         },
+        list_style,
+        quotes: args.quotes,
+        smart_punctuation,
+        headings,
+        native_table_max_rows: args.native_table_max_rows,
+        native_table_overflow,
+        html_code_style,
+        html_code_wrap,
+        html_code_max_width: args.code_max_width,
+        clipboard_backend,
+        clipboard_hold,
+        clipboard_skip_unchanged,
+        clipboard_verify,
+        clipboard_excerpt: args.excerpt,
+        clipboard_max_size_mb: args.max_output_size,
+        history,
+        outputs: args.also.iter().map(|s| OutputSpec::parse(s)).collect(),
     };
 
-    let (cfg, sources) = Config::build(cli_args, args.config);
+    let (mut cfg, sources) = Config::build(cli_args, args.config);
 
     // Handle --help (after config loading so we can show current settings)
     if args.help {
@@ -339,8 +1330,22 @@ fn main() -> io::Result<()> {
             .replace("-z, --optimize", "-z, -Z, --[no-]optimize")
             .replace("-s, --strict", "-s, -S, --[no-]strict")
             .replace("-h, --highlight", "-h, -H, --[no-]highlight")
-            .replace("-p, --prosemirror", "-p, -P, --[no-]prosemirror");
+            .replace("-p, --prosemirror", "-p, -P, --[no-]prosemirror")
+            .replace("--notify", "--[no-]notify")
+            .replace("--unfurl", "--[no-]unfurl")
+            .replace("--captions", "--[no-]captions")
+            .replace("--smart-punctuation", "--[no-]smart-punctuation")
+            .replace("--heading-ids", "--[no-]heading-ids")
+            .replace("--standalone", "--[no-]standalone")
+            .replace("--email", "--[no-]email")
+            .replace("--keep-frontmatter", "--[no-]keep-frontmatter")
+            .replace("--clipboard-hold", "--[no-]clipboard-hold")
+            .replace(
+                "--clipboard-skip-unchanged",
+                "--[no-]clipboard-skip-unchanged",
+            );
         println!("{help}");
+        println!("\n{}", error::EXIT_CODES);
         println!("\nCurrent settings:");
         println!("{}", sources.format_settings(&cfg));
         return Ok(());
@@ -352,6 +1357,28 @@ fn main() -> io::Result<()> {
         return Ok(());
     }
 
+    if cfg.notify {
+        notify::enable();
+    }
+
+    let is_file_output = cfg.output.is_some() || cfg.output_dir.is_some() || args.command.is_some();
+
+    // --interactive: adjust settings for this copy at a prompt. The
+    // configured formats are the clipboard's defaults; files keep theirs.
+    let mut format = args.format.clone().or_else(|| {
+        cfg.formats
+            .as_ref()
+            .filter(|_| !is_file_output)
+            .map(|formats| formats.join(","))
+    });
+    if args.interactive {
+        if io::stdout().is_terminal() {
+            prompt_settings(&mut cfg, &mut format, is_file_output)?;
+        } else {
+            log::warn!("--interactive needs a terminal, using current settings");
+        }
+    }
+
     let effective_theme = cfg.highlight.effective_theme();
     debug!("Input: {:?}", cfg.input);
     debug!("Strict mode: {}", cfg.strict);
@@ -370,51 +1397,54 @@ fn main() -> io::Result<()> {
     let highlight_ctx = if !cfg.highlight.enable {
         None
     } else {
-        highlight::HighlightContext::new(
+        if cfg.strict.themes
+            && !highlight::HighlightContext::list_themes(&cfg.highlight.get_themes_dirs())
+                .iter()
+                .any(|theme| theme == effective_theme)
+        {
+            return Err(MdcopyError::Highlight(format!(
+                "Theme '{}' not found (see --list-themes)",
+                effective_theme
+            )));
+        }
+        // Loaded by the first document with a code block
+        Some(highlight::LazyHighlight::new(
+            &cfg.highlight,
             effective_theme,
-            &cfg.highlight.languages,
-            cfg.highlight.get_themes_dir().as_ref(),
-            cfg.highlight.get_syntaxes_dir().as_ref(),
-        )
-    };
-
-    let markdown_text = read_input(&cfg.input)?;
-    info!("Read {} bytes of markdown", markdown_text.len());
-
-    let base_dir = resolve_base_dir(&cfg.input, cfg.root);
-    debug!("Base directory for images: {:?}", base_dir);
-
-    let options = Options {
-        parse: ParseOptions {
-            constructs: Constructs::gfm(),
-            ..Default::default()
-        },
-        ..Default::default()
+        ))
     };
 
-    let ast = markdown::to_mdast(&markdown_text, &options.parse).expect("Failed to parse markdown");
-    debug!("Parsed markdown AST");
+    if cfg.highlight.check_contrast
+        && let Some(ref lazy) = highlight_ctx
+    {
+        let hl = load_highlight(lazy)?;
+        for issue in highlight::contrast_issues(&hl.theme) {
+            let hex =
+                |c: syntect::highlighting::Color| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+            log::warn!(
+                "Low contrast in theme '{}': {} ({} on {}) is {:.1}:1, WCAG AA needs {}:1",
+                effective_theme,
+                issue.scopes.join(", "),
+                hex(issue.foreground),
+                hex(issue.background),
+                issue.ratio,
+                highlight::MIN_CONTRAST_RATIO
+            );
+        }
+    }
 
     // Determine formats based on output mode and explicit --format flag
-    let is_file_output = cfg.output.is_some();
-    let formats = match (&args.format, is_file_output) {
+    let formats = match (&format, is_file_output) {
         // Explicit format specified
-        (Some(fmt), true) => {
-            let parsed = parse_formats(fmt).expect("Invalid format specification");
-            if parsed.len() > 1 {
-                eprintln!("Error: File output only supports a single format");
-                std::process::exit(1);
-            }
-            #[cfg(target_os = "macos")]
-            if parsed.contains(&ClipboardFormat::Native) {
-                eprintln!("Error: Native format is only supported for clipboard output");
-                std::process::exit(1);
-            }
-            parsed
-        }
-        (Some(fmt), false) => parse_formats(fmt).expect("Invalid format specification"),
+        (Some(fmt), _) => parse_formats(fmt)
+            .map_err(|e| MdcopyError::Usage(format!("Invalid format '{}': {}", fmt, e)))?,
         // No format specified - use context-aware defaults
         (None, true) => vec![ClipboardFormat::Html],
+        (None, false) if cfg.prosemirror => vec![
+            ClipboardFormat::Html,
+            ClipboardFormat::Rtf,
+            ClipboardFormat::ProseMirror,
+        ],
         (None, false) => vec![ClipboardFormat::Html, ClipboardFormat::Rtf],
     };
 
@@ -432,223 +1462,1410 @@ fn main() -> io::Result<()> {
         );
     }
 
-    // Create shared image cache to avoid duplicate loads across formats
-    let image_cache = image::ImageCache::new();
-
-    // Generate requested outputs
-    let html_output = if formats.contains(&ClipboardFormat::Html) {
-        Some(
-            to_html::mdast_to_html(
-                &ast,
-                &base_dir,
-                &cfg.image,
-                cfg.strict,
-                highlight_ctx.as_ref(),
-                &image_cache,
-                cfg.prosemirror,
-            )
-            .map_err(io::Error::other)?,
-        )
-    } else {
-        None
-    };
+    if cfg.image.fetch_insecure && (cfg.image.embed_remote || cfg.unfurl) {
+        log::warn!(
+            "TLS certificate verification is DISABLED for remote fetches (insecure = true). \
+             Anyone on the network can read or replace what is downloaded; prefer ca_bundle"
+        );
+    }
 
-    let rtf_output = if formats.contains(&ClipboardFormat::Rtf) {
-        Some(
-            to_rtf::mdast_to_rtf(
-                &ast,
-                &base_dir,
-                &cfg.image,
-                cfg.strict,
-                highlight_ctx.as_ref(),
-                &image_cache,
-            )
-            .map_err(io::Error::other)?,
-        )
+    // Input files from the command line, with -i or as arguments
+    let files: Vec<PathBuf> = args.input.iter().chain(&args.inputs).cloned().collect();
+
+    // Create shared image cache to avoid duplicate loads across formats (and files)
+    let image_cache = image::ImageCache::with_config(&cfg.image);
+
+    if let Some(Command::History(history)) = &args.command {
+        return run_history(&history.action, &cfg);
+    }
+
+    if let Some(Command::Themes(themes)) = &args.command {
+        return run_themes(&themes.action, &cfg);
+    }
+
+    // Daemon mode: every request shares the highlighting sets and the image
+    // cache, and runs on the --jobs worker pool
+    if let Some(Command::Serve(serve)) = &args.command {
+        let ctx = RenderContext {
+            cfg: &cfg,
+            image_cache: &image_cache,
+        };
+        let listener = std::net::TcpListener::bind(&serve.listen)
+            .map_err(|e| MdcopyError::Usage(format!("Can't listen on {}: {}", serve.listen, e)))?;
+        serve::serve(listener, cfg.jobs, serve.queue, |request| {
+            serve_request(request, formats[0], highlight_ctx.as_ref(), &ctx)
+        })?;
+        return Ok(());
+    }
+
+    // Directory conversion: mirror the input tree under the output directory
+    if let Some(Command::Convert(convert)) = &args.command {
+        let ctx = RenderContext {
+            cfg: &cfg,
+            image_cache: &image_cache,
+        };
+        let options = render::RenderOptions::new(&cfg)
+            .formats(&formats)
+            .highlight(highlight_ctx.as_ref());
+        let count = convert_tree(convert, &options, &ctx)?;
+        write_manifest(&cfg, &image_cache)?;
+        notify_done(&format!(
+            "Converted {} file(s) into {}",
+            count,
+            convert.output.display()
+        ));
+        return Ok(());
+    }
+
+    if let Some(Command::Roundtrip(roundtrip)) = &args.command {
+        let ctx = RenderContext {
+            cfg: &cfg,
+            image_cache: &image_cache,
+        };
+        // Images keep their URLs so they compare equal
+        let options = render::RenderOptions::new(&cfg)
+            .formats(&[ClipboardFormat::Html])
+            .image(ImageConfig {
+                embed_local: false,
+                embed_remote: false,
+                ..cfg.image.clone()
+            });
+        if check_roundtrip(&roundtrip.file, &options, &ctx)? {
+            return Err(MdcopyError::CheckFailed(format!(
+                "{} changed in the round trip",
+                roundtrip.file.display()
+            )));
+        }
+        return Ok(());
+    }
+
+    // Sequence mode: each input goes to the clipboard in turn
+    if args.sequence {
+        if cfg.output.is_some() || cfg.output_dir.is_some() {
+            return Err(MdcopyError::Usage(
+                "--sequence copies to the clipboard and can't write files".to_string(),
+            ));
+        }
+        if files.is_empty() {
+            return Err(MdcopyError::Usage(
+                "--sequence requires input files".to_string(),
+            ));
+        }
+        let target = OutputTarget {
+            formats: formats.clone(),
+            path: None,
+        };
+        validate_targets(&[target]).map_err(MdcopyError::Usage)?;
+        let ctx = RenderContext {
+            cfg: &cfg,
+            image_cache: &image_cache,
+        };
+        let options = render::RenderOptions::new(&cfg)
+            .formats(&formats)
+            .highlight(highlight_ctx.as_ref());
+        let copied = copy_sequence(&files, &options, &ctx)?;
+        write_manifest(&cfg, &image_cache)?;
+        notify_done(&format!(
+            "Copied {} of {} file(s) in sequence",
+            copied,
+            files.len()
+        ));
+        return Ok(());
+    }
+
+    // Batch mode: one output file per input and format
+    if let Some(output_dir) = &cfg.output_dir {
+        if cfg.output.is_some() {
+            return Err(MdcopyError::Usage(
+                "--output and --output-dir cannot be used together".to_string(),
+            ));
+        }
+        let inputs = if !files.is_empty() {
+            files.clone()
+        } else if cfg.input.as_os_str() != "-" {
+            vec![cfg.input.clone()]
+        } else {
+            return Err(MdcopyError::Usage(
+                "--output-dir requires input files".to_string(),
+            ));
+        };
+        if !cfg.outputs.is_empty() {
+            log::warn!("Extra output profiles are ignored with --output-dir");
+        }
+        let ctx = RenderContext {
+            cfg: &cfg,
+            image_cache: &image_cache,
+        };
+        let options = render::RenderOptions::new(&cfg)
+            .formats(&formats)
+            .highlight(highlight_ctx.as_ref());
+        convert_batch(&inputs, output_dir, &options, &ctx)?;
+        write_manifest(&cfg, &image_cache)?;
+        notify_done(&format!(
+            "Converted {} file(s) into {}",
+            inputs.len(),
+            output_dir.display()
+        ));
+        return Ok(());
+    }
+
+    // Primary target plus any extra profiles from --also / config `outputs`
+    let mut targets = vec![OutputTarget {
+        formats,
+        path: cfg.output.clone(),
+    }];
+    for spec in &cfg.outputs {
+        let formats = parse_formats(&spec.format).map_err(|e| {
+            MdcopyError::Usage(format!("Invalid output profile '{}': {}", spec.format, e))
+        })?;
+        targets.push(OutputTarget {
+            formats,
+            path: spec.path.clone(),
+        });
+    }
+    validate_targets(&targets).map_err(MdcopyError::Usage)?;
+
+    // Union of formats across all targets, each rendered once
+    let mut formats: Vec<ClipboardFormat> = Vec::new();
+    for target in &targets {
+        for format in &target.formats {
+            if !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+    }
+
+    // Several files make one document
+    let input = files.first().unwrap_or(&cfg.input).clone();
+    let markdown_text = if files.len() > 1 {
+        if cfg.from != InputFormat::Markdown {
+            return Err(MdcopyError::Usage(
+                "Only markdown input files can be concatenated".to_string(),
+            ));
+        }
+        read_concatenated(&files, &cfg)?
     } else {
-        None
+        read_input(&input)?
     };
+    info!("Read {} bytes of markdown", markdown_text.len());
+    // Before rendering, which holds several copies of the document at once
+    if targets.iter().any(|target| target.path.is_none()) {
+        check_clipboard_size("Document", markdown_text.len(), &cfg)?;
+    }
 
-    let markdown_output = if formats.contains(&ClipboardFormat::Markdown) {
-        Some(
-            to_markdown::mdast_to_markdown(&ast, &base_dir, &cfg.image, cfg.strict, &image_cache)
-                .map_err(io::Error::other)?,
-        )
+    let ctx = RenderContext {
+        cfg: &cfg,
+        image_cache: &image_cache,
+    };
+    let options = render::RenderOptions::new(&cfg)
+        .formats(&formats)
+        .highlight(highlight_ctx.as_ref());
+
+    // A single file target in a format that streams is written as it
+    // renders, without the whole output in memory
+    if let [
+        target @ OutputTarget {
+            path: Some(path), ..
+        },
+    ] = targets.as_slice()
+        && render::renderer(target.formats[0]).streams()
+    {
+        let document = prepare_document(markdown_text, &input, &options, &ctx)?;
+        let size = stream_file_output(path, target.formats[0], &document, &options, &ctx)?;
+        write_manifest(&cfg, &image_cache)?;
+        notify_outputs(vec![describe_target(target, size)], &image_cache);
+        return Ok(());
+    }
+
+    let outputs = render_document(markdown_text, &input, &options, &ctx)?;
+
+    for (format, output) in &outputs.rendered {
+        match output.as_bytes() {
+            Some(bytes) => debug!("Generated {}: {} bytes", format_name(*format), bytes.len()),
+            None => debug!("Generated {}", format_name(*format)),
+        }
+    }
+
+    for target in &targets {
+        match target.path {
+            Some(ref path) => write_file_output(path, target.formats[0], &outputs)?,
+            None => write_clipboard(&target.formats, &outputs, &cfg)?,
+        }
+    }
+
+    write_manifest(&cfg, &image_cache)?;
+
+    let summary = targets
+        .iter()
+        .map(|target| describe_target(target, outputs.size(&target.formats)))
+        .collect();
+    notify_outputs(summary, &image_cache);
+    Ok(())
+}
+
+/// Notify success with a line per target and the number of embedded images
+fn notify_outputs(mut summary: Vec<String>, image_cache: &image::ImageCache) {
+    let images = image_cache.manifest().len();
+    if images > 0 {
+        summary.push(format!("{} embedded image(s)", images));
+    }
+    notify_done(&summary.join("\n"));
+}
+
+/// Notify success, mentioning any warnings waiting in the summary
+fn notify_done(body: &str) {
+    match warnings::count() {
+        0 => notify::send("mdcopy", body),
+        n => notify::send("mdcopy", &format!("{}\n{} warning(s)", body, n)),
+    }
+}
+
+/// One-line description of what a target received, `size` bytes of it
+fn describe_target(target: &OutputTarget, size: Option<usize>) -> String {
+    let names: Vec<&str> = target.formats.iter().map(|f| format_name(*f)).collect();
+    let size = size
+        .map(|size| format!(" ({})", notify::format_size(size)))
+        .unwrap_or_default();
+    let destination = match &target.path {
+        None => "the clipboard".to_string(),
+        Some(path) if path.as_os_str() == "-" => "stdout".to_string(),
+        Some(path) => path.display().to_string(),
+    };
+    format!("{} to {}{}", names.join(" + "), destination, size)
+}
+
+/// Write the embedded image manifest as TOML, if requested
+fn write_manifest(cfg: &Config, image_cache: &image::ImageCache) -> io::Result<()> {
+    let Some(path) = &cfg.manifest else {
+        return Ok(());
+    };
+
+    #[derive(serde::Serialize)]
+    struct Manifest {
+        images: Vec<image::ManifestEntry>,
+    }
+    let manifest = Manifest {
+        images: image_cache.manifest(),
+    };
+    let output = toml::to_string(&manifest).map_err(io::Error::other)?;
+
+    if path.as_os_str() == "-" {
+        io::stdout().write_all(output.as_bytes())?;
     } else {
-        None
+        fs::write(path, output)?;
+        info!(
+            "Wrote manifest of {} embedded image(s) to {:?}",
+            manifest.images.len(),
+            path
+        );
+    }
+    Ok(())
+}
+
+/// Ask for formats, image embedding and theme, updating the config in place
+fn prompt_settings(
+    cfg: &mut Config,
+    format: &mut Option<String>,
+    is_file_output: bool,
+) -> io::Result<()> {
+    let mut input = interactive::terminal_input()?;
+    let mut output = io::stderr();
+    let default_format = match (&*format, is_file_output) {
+        (Some(f), _) => f.clone(),
+        (None, true) => "html".to_string(),
+        (None, false) => "html,rtf".to_string(),
     };
 
-    #[cfg(target_os = "macos")]
-    let native_output = if formats.contains(&ClipboardFormat::Native) {
-        Some(
-            to_nsattributedstring::mdast_to_nsattributed_string(
-                &ast,
-                &base_dir,
-                &cfg.image,
-                cfg.strict,
-                highlight_ctx.as_ref(),
-                &image_cache,
-            )
-            .map_err(io::Error::other)?,
-        )
+    let answer = interactive::ask(
+        &mut *input,
+        &mut output,
+        "Formats (html, rtf, markdown, native)",
+        &default_format,
+        |s| parse_formats(s).map(|_| s.to_string()),
+    )?;
+    *format = Some(answer);
+
+    let (embed_local, embed_remote) = interactive::ask(
+        &mut *input,
+        &mut output,
+        "Embed images (local, all, none)",
+        interactive::embed_name(cfg.image.embed_local, cfg.image.embed_remote),
+        interactive::parse_embed,
+    )?;
+    cfg.image.embed_local = embed_local;
+    cfg.image.embed_remote = embed_remote;
+
+    let themes = highlight::HighlightContext::list_themes(&cfg.highlight.get_themes_dirs());
+    let default_theme = if cfg.highlight.enable {
+        cfg.highlight.effective_theme().to_string()
     } else {
-        None
+        "none".to_string()
     };
+    let theme = interactive::ask(
+        &mut *input,
+        &mut output,
+        "Theme (name or none)",
+        &default_theme,
+        |s| {
+            if s == "none" || themes.iter().any(|t| t == s) {
+                Ok(s.to_string())
+            } else {
+                Err(format!("Unknown theme '{}', see --list-themes", s))
+            }
+        },
+    )?;
+    if theme == "none" {
+        cfg.highlight.enable = false;
+    } else if theme != default_theme {
+        cfg.highlight.enable = true;
+        cfg.highlight.theme = theme;
+        cfg.highlight.theme_light = None;
+        cfg.highlight.theme_dark = None;
+    }
+    Ok(())
+}
 
-    #[cfg(target_os = "macos")]
-    debug!(
-        "Generated: HTML={}, RTF={}, Markdown={}, Native={}",
-        html_output.as_ref().map(|s| s.len()).unwrap_or(0),
-        rtf_output.as_ref().map(|s| s.len()).unwrap_or(0),
-        markdown_output.as_ref().map(|s| s.len()).unwrap_or(0),
-        native_output.is_some(),
-    );
+/// Shared state for rendering one or more documents
+struct RenderContext<'a> {
+    cfg: &'a Config,
+    image_cache: &'a image::ImageCache,
+}
 
-    #[cfg(not(target_os = "macos"))]
-    debug!(
-        "Generated: HTML={}, RTF={}, Markdown={}",
-        html_output.as_ref().map(|s| s.len()).unwrap_or(0),
-        rtf_output.as_ref().map(|s| s.len()).unwrap_or(0),
-        markdown_output.as_ref().map(|s| s.len()).unwrap_or(0),
+/// Several input files as one document, each file's relative URLs rewritten
+/// for the directory of the first
+fn read_concatenated(files: &[PathBuf], cfg: &Config) -> Result<String, MdcopyError> {
+    let sources = files
+        .iter()
+        .map(read_input)
+        .collect::<io::Result<Vec<_>>>()?;
+    let asts: Vec<_> = sources
+        .iter()
+        .map(|source| parse_markdown(source))
+        .collect::<Result<_, _>>()?;
+    let dirs: Vec<PathBuf> = files
+        .iter()
+        .map(|file| resolve_base_dir(file, cfg.root.clone()))
+        .collect();
+    let parts: Vec<concat::Part> = (0..files.len())
+        .map(|i| concat::Part {
+            dir: &dirs[i],
+            source: &sources[i],
+            ast: &asts[i],
+        })
+        .collect();
+    info!("Concatenating {} input files", files.len());
+    Ok(concat::concatenate(&parts, &dirs[0], &cfg.separator))
+}
+
+fn parse_markdown(markdown_text: &str) -> Result<markdown::mdast::Node, MdcopyError> {
+    let parse_options = Options {
+        parse: ParseOptions {
+            constructs: Constructs {
+                frontmatter: true,
+                ..Constructs::gfm()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    markdown::to_mdast(markdown_text, &parse_options.parse)
+        .map_err(|e| MdcopyError::Parse(e.to_string()))
+}
+
+/// Print the outline diff between a document and the markdown read back
+/// from its HTML rendering; returns whether anything changed
+fn check_roundtrip(
+    input: &PathBuf,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<bool, MdcopyError> {
+    let source = read_input(input)?;
+    let outputs = render_document(source.clone(), input, options, ctx)?;
+    let html = outputs.text(ClipboardFormat::Html).unwrap_or_default();
+    let rendered = from_html::html_to_markdown(html);
+
+    let mut original = parse_markdown(&details::expand_containers(&source))?;
+    // HTML has no front matter to carry it through
+    frontmatter::take_front_matter(&mut original);
+    let before = roundtrip::outline(&original);
+    let after = roundtrip::outline(&parse_markdown(&rendered)?);
+    let changes = roundtrip::compare(&before, &after);
+
+    if changes.is_empty() {
+        println!("{} nodes survive the round trip unchanged", before.len());
+        return Ok(false);
+    }
+    println!(
+        "{} of {} nodes changed in the round trip (line: - original, + round-tripped):",
+        changes
+            .iter()
+            .filter(|c| matches!(c, roundtrip::Change::Removed(_)))
+            .count(),
+        before.len()
     );
+    for change in &changes {
+        println!("{}", change);
+    }
+    Ok(true)
+}
 
-    match cfg.output {
-        Some(ref path) if path.as_os_str() == "-" => {
-            let output = match formats[0] {
-                ClipboardFormat::Html => html_output.as_ref().expect("HTML output missing"),
-                ClipboardFormat::Rtf => rtf_output.as_ref().expect("RTF output missing"),
-                ClipboardFormat::Markdown => {
-                    markdown_output.as_ref().expect("Markdown output missing")
-                }
-                #[cfg(target_os = "macos")]
-                ClipboardFormat::Native => {
-                    unreachable!("Native format is clipboard-only")
-                }
+/// Answer one `mdcopy serve` request: the markdown body of `POST /convert`
+/// rendered in the `format` query parameter, or `default_format`
+fn serve_request(
+    request: &serve::Request,
+    default_format: ClipboardFormat,
+    highlight: Option<&highlight::LazyHighlight>,
+    ctx: &RenderContext,
+) -> serve::Response {
+    use serve::Response;
+
+    if request.path != "/convert" {
+        return Response::text(404, "Not found; POST markdown to /convert or GET /status");
+    }
+    if request.method != "POST" {
+        return Response::text(405, "POST markdown to /convert");
+    }
+    let format = match request.param("format").map(parse_formats) {
+        None => default_format,
+        Some(Ok(formats)) if formats.len() == 1 => formats[0],
+        Some(Ok(_)) => return Response::text(400, "Request one format at a time"),
+        Some(Err(e)) => return Response::text(400, e),
+    };
+    let Ok(markdown_text) = String::from_utf8(request.body.clone()) else {
+        return Response::text(400, "The request body isn't UTF-8");
+    };
+
+    let options = render::RenderOptions::new(ctx.cfg)
+        .formats(&[format])
+        .highlight(highlight);
+    match render_document(markdown_text, Path::new("-"), &options, ctx) {
+        Ok(outputs) => match outputs.get(format).and_then(render::Output::as_bytes) {
+            Some(bytes) => Response::new(200, format.mime_type(), bytes),
+            None => Response::text(
+                400,
+                format!("{} output can't be served", format_name(format)),
+            ),
+        },
+        Err(e) => Response::text(422, e),
+    }
+}
+
+/// Keep a copy in the history; failing to is only worth a warning
+fn record_history(
+    payload: &clipboard::Payload,
+    outputs: &RenderedOutputs,
+    format_names: &[&str],
+    cfg: &Config,
+) {
+    let Some(history) = history::History::open(&cfg.history) else {
+        log::warn!("No config directory to keep the copy history in");
+        return;
+    };
+    let record = history::Record {
+        input: (outputs.input.as_os_str() != "-")
+            .then(|| std::path::absolute(&outputs.input).unwrap_or(outputs.input.clone())),
+        formats: format_names.iter().map(|name| name.to_string()).collect(),
+    };
+    if let Err(e) = history.record(payload, &record) {
+        log::warn!("Failed to add the copy to the history: {}", e);
+    }
+}
+
+fn run_history(action: &HistoryAction, cfg: &Config) -> Result<(), MdcopyError> {
+    let history = history::History::open(&cfg.history)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+    match *action {
+        HistoryAction::List => {
+            let entries = history.entries()?;
+            if entries.is_empty() {
+                eprintln!(
+                    "No copies in the history{}",
+                    if cfg.history.enable {
+                        ""
+                    } else {
+                        " (enable it with --history or history.enable = true)"
+                    }
+                );
+            }
+            for (index, entry) in entries.iter().enumerate() {
+                let input = entry
+                    .record
+                    .input
+                    .as_deref()
+                    .and_then(Path::file_name)
+                    .map_or("stdin".into(), |name| name.to_string_lossy());
+                println!(
+                    "{:>3}  {:<12}  {:<20}  {:>9}  {:<24}  {}",
+                    index + 1,
+                    history::age(entry.time),
+                    input,
+                    notify::format_size(entry.size as usize),
+                    entry.formats(),
+                    entry.preview()
+                );
+            }
+        }
+        HistoryAction::Show { n } => print!("{}", history.get(n)?.load()?.source),
+        HistoryAction::Recopy { n } => {
+            let entry = history.get(n)?;
+            let stored = entry.load()?;
+            let payload = stored.payload();
+            let backend = clipboard::select(&cfg.clipboard, &payload);
+            backend.copy(&payload)?;
+            if cfg.clipboard.verify {
+                clipboard::verify(backend.as_ref(), &payload)?;
+            }
+            eprintln!(
+                "Copied {} from {} ({}) to the clipboard",
+                n,
+                history::age(entry.time),
+                entry.formats()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Write the completion script for `shell` to stdout, offering the theme
+/// names from `themes_dirs` and the bundled set for `--highlight-theme`
+/// Write the script registering mdcopy's completions with `shell`, the same
+/// one `COMPLETE=<shell> mdcopy` prints
+fn print_completions(shell: clap_complete::Shell) -> io::Result<()> {
+    let name = shell.to_string();
+    let shells = clap_complete::env::Shells::builtins();
+    let completer = shells
+        .completer(&name)
+        .ok_or_else(|| io::Error::other(format!("No completion support for {}", name)))?;
+    completer.write_registration("COMPLETE", "mdcopy", "mdcopy", "mdcopy", &mut io::stdout())
+}
+
+/// Themes starting with `current`, listed when the shell asks so themes
+/// added since the completion script was installed are offered too
+fn complete_theme(current: &std::ffi::OsStr) -> Vec<clap_complete::CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let themes_dir = config::user_themes_dir();
+    highlight::HighlightContext::list_themes(themes_dir.as_slice())
+        .into_iter()
+        .filter(|theme| theme.starts_with(current))
+        .map(clap_complete::CompletionCandidate::new)
+        .collect()
+}
+
+fn run_themes(action: &ThemesAction, cfg: &Config) -> Result<(), MdcopyError> {
+    let ThemesAction::Preview { lang, file, output } = action;
+    let code = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => theme_preview::sample(lang)
+            .ok_or_else(|| {
+                MdcopyError::Usage(format!(
+                    "No sample for '{}' (use --file, or one of: {})",
+                    lang,
+                    theme_preview::sample_languages().join(", ")
+                ))
+            })?
+            .to_string(),
+    };
+    let themes_dirs = cfg.highlight.get_themes_dirs();
+    let mut hl = highlight::HighlightContext::new(
+        &cfg.highlight.theme,
+        &cfg.highlight.languages,
+        &cfg.highlight.fallbacks,
+        &themes_dirs,
+        &cfg.highlight.get_syntaxes_dirs(),
+    )
+    .ok_or_else(|| MdcopyError::Highlight("No themes available".to_string()))?;
+    let themes = highlight::HighlightContext::load_themes(&themes_dirs);
+    let samples = theme_preview::highlight_in_each(&mut hl, themes, lang, &code);
+    match output {
+        Some(path) => {
+            fs::write(path, theme_preview::to_html(&samples, lang))?;
+            eprintln!("Wrote {} themes to {}", samples.len(), path.display());
+        }
+        None => print!("{}", theme_preview::to_ansi(&samples)),
+    }
+    Ok(())
+}
+
+fn run_config(action: &ConfigAction, config_path: Option<PathBuf>) -> Result<(), MdcopyError> {
+    let missing = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not determine the config directory",
+        )
+    };
+    match action {
+        ConfigAction::Init { force } => {
+            let path = config::default_config_path().ok_or_else(missing)?;
+            if path.exists() && !force {
+                return Err(MdcopyError::Usage(format!(
+                    "{} exists (use --force to replace it)",
+                    path.display()
+                )));
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, config_file::TEMPLATE)?;
+            println!("Wrote {}", path.display());
+        }
+        ConfigAction::Validate { path } => {
+            let path = match path.clone().or(config_path) {
+                Some(path) => path,
+                None => config::default_config_path().ok_or_else(missing)?,
             };
-            io::stdout().write_all(output.as_bytes())?;
-        }
-        Some(ref path) => {
-            let output = match formats[0] {
-                ClipboardFormat::Html => html_output.as_ref().expect("HTML output missing"),
-                ClipboardFormat::Rtf => rtf_output.as_ref().expect("RTF output missing"),
-                ClipboardFormat::Markdown => {
-                    markdown_output.as_ref().expect("Markdown output missing")
+            let issues = config_file::validate(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            for issue in &issues {
+                match issue.line {
+                    Some(line) => println!("{}:{}: {}", path.display(), line, issue.message),
+                    None => println!("{}: {}", path.display(), issue.message),
                 }
-                #[cfg(target_os = "macos")]
-                ClipboardFormat::Native => {
-                    unreachable!("Native format is clipboard-only")
+            }
+            if !issues.is_empty() {
+                return Err(MdcopyError::CheckFailed(format!(
+                    "{} has {} problem(s)",
+                    path.display(),
+                    issues.len()
+                )));
+            }
+            println!("{}: OK", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn inspect_clipboard(args: &InspectArgs) -> Result<(), MdcopyError> {
+    let reader = clipboard::ClipboardReader::open().map_err(MdcopyError::Clipboard)?;
+    let formats = reader.formats().map_err(MdcopyError::Clipboard)?;
+
+    let Some(query) = &args.format else {
+        if formats.is_empty() {
+            eprintln!("The clipboard is empty");
+            return Ok(());
+        }
+        let width = formats.iter().map(String::len).max().unwrap_or(0);
+        for format in &formats {
+            let size = match reader.read(format) {
+                Ok(data) => notify::format_size(data.len()),
+                Err(e) => {
+                    debug!("{}", e);
+                    "unreadable".to_string()
                 }
             };
-            fs::write(path, output)?;
-            info!("Wrote {:?} output to {:?}", formats[0], path);
+            println!("{:width$}  {:>10}", format, size, width = width);
         }
-        None => {
-            debug!("Writing to clipboard");
+        return Ok(());
+    };
 
-            #[cfg(target_os = "macos")]
-            let use_native = formats.contains(&ClipboardFormat::Native);
+    let Some(format) = clipboard::match_format(query, &formats) else {
+        return Err(MdcopyError::Clipboard(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No '{}' on the clipboard. Available: {}",
+                query,
+                formats.join(", ")
+            ),
+        )));
+    };
+    let data = reader.read(format).map_err(MdcopyError::Clipboard)?;
+    match &args.output {
+        Some(path) if path.as_os_str() != "-" => {
+            fs::write(path, &data)?;
+            eprintln!(
+                "Wrote {} ({}) to {}",
+                format,
+                notify::format_size(data.len()),
+                path.display()
+            );
+        }
+        _ => io::stdout().write_all(&data)?,
+    }
+    Ok(())
+}
 
-            #[cfg(target_os = "macos")]
-            if use_native {
-                // Use native NSAttributedString clipboard on macOS
-                let native_result = native_output.as_ref().expect("Native output missing");
-
-                // If -f native,html was specified, use our HTML generator
-                let use_our_html = formats.contains(&ClipboardFormat::Html);
-
-                // Pass markdown if -f native,markdown was specified
-                let text_for_pasteboard = if formats.contains(&ClipboardFormat::Markdown) {
-                    markdown_output.as_deref().or(Some(&markdown_text))
-                } else {
-                    None
-                };
-
-                to_nsattributedstring::write_to_pasteboard(
-                    native_result,
-                    use_our_html,
-                    html_output.as_deref(),
-                    text_for_pasteboard,
-                )
-                .expect("Failed to write NSAttributedString to pasteboard");
-
-                let format_names: Vec<&str> = formats
-                    .iter()
-                    .map(|f| match f {
-                        ClipboardFormat::Html => "HTML",
-                        ClipboardFormat::Rtf => "RTF",
-                        ClipboardFormat::Markdown => "Markdown",
-                        ClipboardFormat::Native => "Native",
-                    })
-                    .collect();
-                info!("Copied to clipboard ({})", format_names.join(", "));
-            } else {
-                // Use clipboard-rs for non-native formats
-                let ctx = ClipboardContext::new().expect("Failed to create clipboard context");
+/// Parse markdown, apply AST transforms, and render every requested format
+fn render_document(
+    markdown_text: String,
+    input: &Path,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<RenderedOutputs, MdcopyError> {
+    let document = prepare_document(markdown_text, input, options, ctx)?;
+    let rendered = render::render_all(
+        &document.ast,
+        &options.formats,
+        &document.render_context(options, ctx),
+    )?;
+
+    Ok(RenderedOutputs {
+        source: document.source,
+        input: input.to_path_buf(),
+        source_link: source_link(input),
+        rendered,
+    })
+}
+
+/// A document parsed and transformed, its images resolved, ready to render
+struct PreparedDocument {
+    /// Markdown the AST was parsed from (plain text clipboard fallback)
+    source: String,
+    ast: markdown::mdast::Node,
+    base_dir: PathBuf,
+    front_matter: Option<frontmatter::FrontMatter>,
+}
 
-                let mut contents = Vec::new();
+impl PreparedDocument {
+    fn render_context<'a>(
+        &'a self,
+        options: &'a render::RenderOptions,
+        ctx: &'a RenderContext,
+    ) -> render::Context<'a> {
+        render::Context {
+            cfg: ctx.cfg,
+            base_dir: &self.base_dir,
+            highlight: options.highlight.and_then(highlight::LazyHighlight::loaded),
+            image: &options.image,
+            image_cache: ctx.image_cache,
+            front_matter: self.front_matter.as_ref(),
+        }
+    }
+}
 
-                // Always include plain text (original markdown) as fallback
-                contents.push(ClipboardContent::Text(markdown_text.clone()));
+/// Parse markdown, apply AST transforms, resolve images and check what each
+/// requested format would drop
+fn prepare_document(
+    markdown_text: String,
+    input: &Path,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<PreparedDocument, MdcopyError> {
+    let cfg = ctx.cfg;
+    let base_dir = resolve_base_dir(input, cfg.root.clone());
+    debug!("Base directory for images: {:?}", base_dir);
 
-                if let Some(ref html) = html_output {
-                    contents.push(ClipboardContent::Html(html.clone()));
-                }
-                if let Some(ref rtf) = rtf_output {
-                    contents.push(ClipboardContent::Rtf(rtf.clone()));
-                }
-                if let Some(ref md) = markdown_output {
-                    // Markdown with embedded images replaces plain text
-                    contents[0] = ClipboardContent::Text(md.clone());
-                }
+    // Everything after this works on markdown, which is also the plain text
+    // fallback
+    let markdown_text = match cfg.from {
+        InputFormat::Markdown => markdown_text,
+        InputFormat::Html => {
+            debug!("Converting HTML input to markdown");
+            from_html::html_to_markdown(&markdown_text)
+        }
+    };
 
-                let format_names: Vec<&str> = formats
+    // Section ranges and the plain text fallback index into the parsed text
+    let mut markdown_text = match details::expand_containers(&markdown_text) {
+        Cow::Owned(expanded) => {
+            debug!("Expanded :::details containers");
+            expanded
+        }
+        Cow::Borrowed(_) => markdown_text,
+    };
+    let mut ast = parse_markdown(&markdown_text)?;
+    debug!("Parsed markdown AST");
+
+    let front_matter = frontmatter::take_front_matter(&mut ast);
+    if front_matter.is_some() {
+        debug!("Took front matter");
+    }
+
+    // First, so the other transforms only see the section
+    if let Some(ref title) = cfg.section {
+        let range =
+            transform::extract_section(&mut ast, title, cfg.section_level).ok_or_else(|| {
+                MdcopyError::Usage(format!("No section titled '{}' in {:?}", title, input))
+            })?;
+        // The plain text fallback is the section's source. The range points
+        // into the text as parsed, so this happens before front matter is
+        // cut off it, and the section never includes the front matter anyway.
+        markdown_text = markdown_text[range].to_string();
+        debug!("Extracted section '{}'", title);
+    } else if let Some((_, end)) = front_matter.as_ref().filter(|_| !cfg.keep_frontmatter) {
+        markdown_text = markdown_text[*end..].trim_start().to_string();
+    }
+    let front_matter = front_matter.map(|(front_matter, _)| front_matter);
+
+    transform::inline_footnote_syntax(&mut ast);
+
+    // Before captions, which warn about every `[@...]` they can't resolve
+    let bibliography = bibliography_paths(cfg, front_matter.as_ref(), input);
+    if !bibliography.is_empty() {
+        let mut entries = citations::Bibliography::default();
+        for path in &bibliography {
+            entries.merge(citations::Bibliography::load(path)?);
+        }
+        citations::cite(&mut ast, &entries);
+        debug!("Resolved citations against {} entries", entries.len());
+    }
+
+    // First, so captions taken from alt text get smart quotes
+    if cfg.captions {
+        captions::number_captions(&mut ast);
+        debug!("Numbered captions");
+    }
+
+    if cfg.typography.headings != HeadingCase::Keep {
+        transform::heading_case(&mut ast, cfg.typography.headings);
+        debug!("Applied heading case {}", cfg.typography.headings);
+    }
+
+    // Before quotes, so a quote after a dash opens
+    if cfg.typography.smart_punctuation {
+        transform::smart_punctuation(&mut ast);
+        debug!("Applied smart punctuation");
+    }
+
+    if let Some(style) = cfg.typography.quote_style() {
+        transform::smart_quotes(&mut ast, &style);
+        debug!("Applied quote style {:?}", cfg.typography.quotes);
+    }
+
+    // Before the images-as-links rewrite so card thumbnails follow it too
+    if cfg.unfurl {
+        unfurl::unfurl_links(&mut ast, &options.image);
+        debug!("Unfurled bare links");
+    }
+
+    // Before the images-as-links rewrite, so the links point at the
+    // rewritten URLs too
+    if !options.image.rewrite.is_empty() {
+        transform::rewrite_image_urls(&mut ast, &options.image.rewrite);
+        debug!("Rewrote image URLs");
+    }
+
+    if options.image.mode == ImageMode::LinksOnly {
+        transform::images_to_links(&mut ast);
+        debug!("Rewrote images as links");
+    }
+
+    if let Some(ext) = options.link_extension {
+        transform::rewrite_md_links(&mut ast, ext);
+    }
+
+    if let Some(placement) = cfg.footnotes {
+        transform::place_footnotes(&mut ast, placement);
+        debug!("Placed footnotes: {}", placement);
+    }
+
+    // Syntaxes and themes only load once there's code to highlight
+    let highlight = match options.highlight {
+        Some(lazy) if highlight::has_code_blocks(&ast) => Some(load_highlight(lazy)?),
+        _ => None,
+    };
+    if cfg.strict.languages
+        && let Some(hl) = highlight
+    {
+        let missing = hl.missing_languages(&ast);
+        if !missing.is_empty() {
+            return Err(MdcopyError::Highlight(format!(
+                "No syntax found for {}. Add a mapping under [highlight.fallbacks] to highlight it",
+                missing
                     .iter()
-                    .map(|f| match f {
-                        ClipboardFormat::Html => "HTML",
-                        ClipboardFormat::Rtf => "RTF",
-                        ClipboardFormat::Markdown => "Markdown",
-                        ClipboardFormat::Native => "Native",
-                    })
-                    .collect();
-
-                ctx.set(contents).expect("Failed to set clipboard content");
-                info!("Copied to clipboard ({})", format_names.join(", "));
+                    .map(|lang| format!("'{}'", lang))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    }
+
+    let image_urls = image::collect_image_urls(&ast);
+    ctx.image_cache
+        .resolve_all(
+            &image_urls,
+            &base_dir,
+            &options.image,
+            cfg.strict.images,
+            options.image.concurrency,
+        )
+        .map_err(io::Error::other)?;
+    let stats = ctx.image_cache.dedup_stats(&image_urls, &base_dir);
+    if stats.sources > 0 {
+        info!("Images: {}", stats);
+    }
+
+    for &format in &options.formats {
+        if let Some(summary) =
+            fidelity::summary(format_name(format), &fidelity::losses(&ast, format))
+        {
+            if cfg.strict.nodes {
+                return Err(MdcopyError::Unsupported(summary));
             }
+            log::warn!("{}", summary);
+        }
+    }
 
-            #[cfg(not(target_os = "macos"))]
-            {
-                let ctx = ClipboardContext::new().expect("Failed to create clipboard context");
+    Ok(PreparedDocument {
+        source: markdown_text,
+        ast,
+        base_dir,
+        front_matter,
+    })
+}
 
-                let mut contents = Vec::new();
+/// Highlighting for a document with code, loaded now if it wasn't yet
+fn load_highlight(
+    lazy: &highlight::LazyHighlight,
+) -> Result<&highlight::HighlightContext, MdcopyError> {
+    lazy.get().ok_or_else(|| {
+        MdcopyError::Highlight("No syntax highlighting theme could be loaded".to_string())
+    })
+}
 
-                // Always include plain text (original markdown) as fallback
-                contents.push(ClipboardContent::Text(markdown_text));
+/// A `file://` URL for `input`, None for stdin
+fn source_link(input: &Path) -> Option<String> {
+    if input.as_os_str() == "-" {
+        return None;
+    }
+    let path = std::path::absolute(input).ok()?;
+    Some(format!("file://{}", path.display()))
+}
 
-                if let Some(html) = html_output {
-                    contents.push(ClipboardContent::Html(html));
-                }
-                if let Some(rtf) = rtf_output {
-                    contents.push(ClipboardContent::Rtf(rtf));
-                }
-                if let Some(md) = markdown_output {
-                    // Markdown with embedded images replaces plain text
-                    contents[0] = ClipboardContent::Text(md);
+/// The configured bibliography followed by the front matter's, which is
+/// relative to the document
+fn bibliography_paths(
+    cfg: &Config,
+    front_matter: Option<&frontmatter::FrontMatter>,
+    input: &Path,
+) -> Vec<PathBuf> {
+    let dir = input.parent().unwrap_or(Path::new(""));
+    cfg.bibliography
+        .iter()
+        .cloned()
+        .chain(
+            front_matter
+                .and_then(|fm| fm.bibliography.as_deref())
+                .into_iter()
+                .flat_map(|files| files.split(", "))
+                .map(|file| dir.join(file)),
+        )
+        .collect()
+}
+
+/// Convert each input to one file per format under `output_dir`
+fn convert_batch(
+    inputs: &[PathBuf],
+    output_dir: &Path,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<(), MdcopyError> {
+    // Before rendering anything, so colliding names don't overwrite each other
+    let extensions: Vec<&str> = options.formats.iter().map(|f| f.extension()).collect();
+    let planned = batch::plan_outputs(&ctx.cfg.name_template, inputs, &extensions)
+        .map_err(MdcopyError::Usage)?;
+    let jobs: Vec<(&PathBuf, Vec<PathBuf>)> = inputs.iter().zip(planned).collect();
+    let stats = batch::run_pool(&jobs, ctx.cfg.jobs, |(input, names)| {
+        let markdown_text = read_input(input)?;
+        let outputs = render_document(markdown_text, input, options, ctx)?;
+        for (format, name) in options.formats.iter().zip(names) {
+            let path = output_dir.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_file_output(&path, *format, &outputs)?;
+        }
+        Ok(())
+    })?;
+    info!("Converted into {}: {}", output_dir.display(), stats);
+    Ok(())
+}
+
+/// Copy each input to the clipboard in turn, waiting for Enter (or the
+/// configured delay) between them. Returns how many were copied.
+fn copy_sequence(
+    inputs: &[PathBuf],
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<usize, MdcopyError> {
+    let delay = std::time::Duration::from_millis(ctx.cfg.sequence_delay_ms);
+    // Enter comes from the terminal, since stdin may be busy
+    let mut terminal = if delay.is_zero() {
+        Some(interactive::terminal_input()?)
+    } else {
+        None
+    };
+    for (index, input) in inputs.iter().enumerate() {
+        let outputs = render_document(read_input(input)?, input, options, ctx)?;
+        write_clipboard(&options.formats, &outputs, ctx.cfg)?;
+        eprintln!("Copied {}/{}: {}", index + 1, inputs.len(), input.display());
+        let Some(next) = inputs.get(index + 1) else {
+            return Ok(inputs.len());
+        };
+        match terminal.as_mut() {
+            Some(terminal) => {
+                let label = format!("Copy {}", next.display());
+                if !interactive::wait_for_enter(terminal.as_mut(), &mut io::stderr(), &label)? {
+                    return Ok(index + 1);
                 }
+            }
+            None => std::thread::sleep(delay),
+        }
+    }
+    Ok(inputs.len())
+}
 
-                let format_names: Vec<&str> = formats
-                    .iter()
-                    .map(|f| match f {
-                        ClipboardFormat::Html => "HTML",
-                        ClipboardFormat::Rtf => "RTF",
-                        ClipboardFormat::Markdown => "Markdown",
-                    })
-                    .collect();
-
-                ctx.set(contents).expect("Failed to set clipboard content");
-                info!("Copied to clipboard ({})", format_names.join(", "));
+/// Convert files and directories into `convert.output`, preserving their
+/// relative paths. Each format is rendered separately so links to other
+/// markdown documents point at the file of the same format.
+fn convert_tree(
+    convert: &ConvertArgs,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> Result<usize, MdcopyError> {
+    // (source, path relative to the output directory)
+    let mut files = Vec::new();
+    for path in &convert.paths {
+        if path.is_dir() {
+            if !convert.recursive {
+                return Err(MdcopyError::Usage(format!(
+                    "{} is a directory (use --recursive)",
+                    path.display()
+                )));
+            }
+            for file in batch::collect_markdown_files(path)? {
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_path_buf();
+                files.push((file, relative));
+            }
+        } else {
+            let name = path.file_name().map(PathBuf::from).unwrap_or_default();
+            files.push((path.clone(), name));
+        }
+    }
+
+    let stats = batch::run_pool(&files, ctx.cfg.jobs, |(source, relative)| {
+        let markdown_text = read_input(source)?;
+        for format in &options.formats {
+            let ext = format.extension();
+            let format_options = options
+                .clone()
+                .formats(&[*format])
+                .link_extension((*format != ClipboardFormat::Markdown).then_some(ext));
+            let outputs = render_document(markdown_text.clone(), source, &format_options, ctx)?;
+            let path = convert.output.join(relative.with_extension(ext));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
             }
+            write_file_output(&path, *format, &outputs)?;
+        }
+        Ok(())
+    })?;
+    info!("Converted into {}: {}", convert.output.display(), stats);
+    Ok(files.len())
+}
+
+/// A destination (file, stdout, or clipboard) and the formats it receives
+struct OutputTarget {
+    formats: Vec<ClipboardFormat>,
+    /// None writes to the clipboard, "-" writes to stdout
+    path: Option<PathBuf>,
+}
+
+/// Check that file targets take a single format and at most one target
+/// writes to the clipboard
+fn validate_targets(targets: &[OutputTarget]) -> Result<(), String> {
+    let mut clipboard_targets = 0;
+    for target in targets {
+        match target.path {
+            Some(_) => {
+                if target.formats.len() > 1 {
+                    return Err("File output only supports a single format".to_string());
+                }
+            }
+            None if let Some(format) = target.formats.iter().find(|f| f.is_file_only()) => {
+                return Err(format!(
+                    "{} can only be written to a file (use -o FILE.{})",
+                    format_name(*format),
+                    format.extension()
+                ));
+            }
+            None => clipboard_targets += 1,
+        }
+    }
+    if clipboard_targets > 1 {
+        return Err("Only one output profile can write to the clipboard".to_string());
+    }
+    Ok(())
+}
+
+/// Rendered output for every format requested by any target
+struct RenderedOutputs {
+    /// Original markdown source (plain text clipboard fallback)
+    source: String,
+    /// Input path, `-` for stdin
+    input: PathBuf,
+    /// `file://` URL of the input, None for stdin
+    source_link: Option<String>,
+    rendered: Vec<(ClipboardFormat, render::Output)>,
+}
+
+impl RenderedOutputs {
+    fn get(&self, format: ClipboardFormat) -> Option<&render::Output> {
+        self.rendered
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, output)| output)
+    }
+
+    /// Bytes rendered in `formats`; None when none of them has a size, as
+    /// native output doesn't
+    fn size(&self, formats: &[ClipboardFormat]) -> Option<usize> {
+        let sizes: Vec<usize> = formats
+            .iter()
+            .filter_map(|f| self.get(*f).and_then(render::Output::as_bytes))
+            .map(<[u8]>::len)
+            .collect();
+        (!sizes.is_empty()).then(|| sizes.iter().sum())
+    }
+
+    /// Get the text output for a format (native has no text representation)
+    fn text(&self, format: ClipboardFormat) -> Option<&str> {
+        self.get(format).and_then(render::Output::as_text)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn native(&self) -> Option<&to_nsattributedstring::NativeConversionResult> {
+        match self.get(ClipboardFormat::Native) {
+            Some(render::Output::Native(native)) => Some(native),
+            _ => None,
+        }
+    }
+}
+
+fn format_name(format: ClipboardFormat) -> &'static str {
+    match format {
+        ClipboardFormat::Html => "HTML",
+        ClipboardFormat::Rtf => "RTF",
+        ClipboardFormat::Markdown => "Markdown",
+        ClipboardFormat::Jira => "Jira",
+        ClipboardFormat::Slack => "Slack",
+        ClipboardFormat::MediaWiki => "MediaWiki",
+        ClipboardFormat::Notion => "Notion",
+        ClipboardFormat::Text => "Plain text",
+        ClipboardFormat::Pdf => "PDF",
+        ClipboardFormat::Epub => "EPUB",
+        ClipboardFormat::Mhtml => "MHTML",
+        ClipboardFormat::Image => "Image",
+        ClipboardFormat::ProseMirror => "ProseMirror",
+        #[cfg(target_os = "macos")]
+        ClipboardFormat::Native => "Native",
+    }
+}
+
+/// Write a single format to a file, or stdout when path is "-"
+fn write_file_output(
+    path: &std::path::Path,
+    format: ClipboardFormat,
+    outputs: &RenderedOutputs,
+) -> io::Result<()> {
+    let missing = || io::Error::other(format!("{} output missing", format_name(format)));
+    #[cfg(target_os = "macos")]
+    if format == ClipboardFormat::Native {
+        let native_result = outputs.native().ok_or_else(missing)?;
+        to_nsattributedstring::write_rtfd(native_result, path).map_err(io::Error::other)?;
+        if path.as_os_str() != "-" {
+            info!("Wrote {:?} output to {:?}", format, path);
         }
+        return Ok(());
+    }
+
+    let output = outputs
+        .get(format)
+        .and_then(render::Output::as_bytes)
+        .ok_or_else(missing)?;
+    if path.as_os_str() == "-" {
+        io::stdout().write_all(output)?;
+    } else {
+        fs::write(path, output)?;
+        info!("Wrote {:?} output to {:?}", format, path);
+    }
+    Ok(())
+}
+
+/// Render `format` straight into the file at `path`, or stdout for `-`,
+/// returning the size of the file. A file left incomplete by an error is
+/// removed.
+fn stream_file_output(
+    path: &Path,
+    format: ClipboardFormat,
+    document: &PreparedDocument,
+    options: &render::RenderOptions,
+    ctx: &RenderContext,
+) -> io::Result<Option<usize>> {
+    let renderer = render::renderer(format);
+    let render_ctx = document.render_context(options, ctx);
+    if path.as_os_str() == "-" {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        renderer.render_to(&document.ast, &render_ctx, &mut out)?;
+        out.flush()?;
+        return Ok(None);
+    }
+
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    let written = renderer
+        .render_to(&document.ast, &render_ctx, &mut out)
+        .and_then(|()| out.flush());
+    drop(out);
+    if let Err(e) = written {
+        let _ = fs::remove_file(path);
+        return Err(e);
+    }
+    info!("Streamed {:?} output to {:?}", format, path);
+    Ok(Some(fs::metadata(path)?.len() as usize))
+}
+
+/// Refuse a clipboard copy of `size` bytes over `clipboard.max_size_mb`
+fn check_clipboard_size(what: &str, size: usize, cfg: &Config) -> Result<(), MdcopyError> {
+    let limit = cfg.clipboard.max_size_mb;
+    if limit == 0 || size as u64 <= limit * 1024 * 1024 {
+        return Ok(());
     }
+    Err(MdcopyError::Clipboard(io::Error::other(format!(
+        "{} is {}, over the {} MB clipboard limit. Write it to a file with -o, \
+         or raise the limit with --max-output-size",
+        what,
+        notify::format_size(size),
+        limit
+    ))))
+}
+
+fn write_clipboard(
+    formats: &[ClipboardFormat],
+    outputs: &RenderedOutputs,
+    cfg: &Config,
+) -> Result<(), MdcopyError> {
+    let clipboard_cfg = &cfg.clipboard;
+    debug!("Writing to clipboard");
+    let format_names: Vec<&str> = formats.iter().map(|f| format_name(*f)).collect();
+
+    // Only the formats this target asked for
+    let requested = |format| {
+        formats
+            .contains(&format)
+            .then(|| outputs.text(format))
+            .flatten()
+    };
+    // The first text format asked for replaces the source
+    let plain_text = formats
+        .iter()
+        .filter(|f| f.is_plain_text())
+        .find_map(|f| outputs.text(*f));
+    let excerpt = (clipboard_cfg.excerpt > 0)
+        .then(|| {
+            excerpt::excerpt(
+                plain_text.unwrap_or(&outputs.source),
+                clipboard_cfg.excerpt,
+                outputs.source_link.as_deref(),
+            )
+        })
+        .flatten();
+    let payload = clipboard::Payload {
+        source: &outputs.source,
+        plain_text: excerpt.as_deref().or(plain_text),
+        html: requested(ClipboardFormat::Html),
+        rtf: requested(ClipboardFormat::Rtf),
+        prosemirror: requested(ClipboardFormat::ProseMirror),
+        image: formats
+            .contains(&ClipboardFormat::Image)
+            .then(|| {
+                outputs
+                    .get(ClipboardFormat::Image)
+                    .and_then(render::Output::as_bytes)
+            })
+            .flatten(),
+        #[cfg(target_os = "macos")]
+        native: outputs
+            .native()
+            .filter(|_| formats.contains(&ClipboardFormat::Native)),
+    };
 
+    check_clipboard_size(
+        "Clipboard payload",
+        payload.flavors().iter().map(|(_, data)| data.len()).sum(),
+        cfg,
+    )?;
+
+    let backend = clipboard::select(clipboard_cfg, &payload);
+    debug!("Using the {} clipboard backend", backend.name());
+    if let Some(problem) = backend.size_problem(&payload) {
+        if cfg.strict.output_size {
+            return Err(MdcopyError::Clipboard(io::Error::other(problem)));
+        }
+        log::warn!("{}", problem);
+    }
+    let history = clipboard_cfg
+        .skip_unchanged
+        .then(clipboard::CopyHistory::open)
+        .flatten();
+    match clipboard::copy_if_changed(backend.as_ref(), &payload, history.as_ref()) {
+        Ok(true) => {
+            info!(
+                "Copied to clipboard ({}) using the {} backend",
+                format_names.join(", "),
+                backend.name()
+            );
+            if cfg.history.enable {
+                record_history(&payload, outputs, &format_names, cfg);
+            }
+        }
+        Ok(false) => info!("Clipboard already holds this output; skipped the copy"),
+        Err(e) => return Err(MdcopyError::Clipboard(e)),
+    }
+    if clipboard_cfg.verify || cfg.strict.clipboard {
+        clipboard::verify(backend.as_ref(), &payload).map_err(MdcopyError::Clipboard)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_positional_input_finds_project_config() {
+        // The project config is looked up from the first input, however
+        // it was given
+        let temp_dir = TempDir::new().unwrap();
+        let docs = temp_dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join(config::PROJECT_CONFIG_NAME), "").unwrap();
+        let notes = docs.join("notes.md");
+
+        for argv in [
+            vec!["mdcopy".as_ref(), notes.as_os_str()],
+            vec!["mdcopy".as_ref(), "-i".as_ref(), notes.as_os_str()],
+        ] {
+            let args = Args::try_parse_from(argv).unwrap();
+            let input = first_input(&args);
+            assert_eq!(input.as_ref(), Some(&notes));
+            assert_eq!(
+                config::find_project_config(input.as_deref()),
+                Some(docs.join(config::PROJECT_CONFIG_NAME))
+            );
+        }
+    }
+
+    #[test]
+    fn test_theme_completion() {
+        // --highlight-theme completes to the themes found when asked
+        use clap::CommandFactory;
+        let cmd = Args::command();
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_id() == "highlight_theme")
+            .unwrap();
+        let completer = arg.get::<clap_complete::ArgValueCompleter>().unwrap();
+        let candidates: Vec<_> = completer
+            .complete("Solarized".as_ref())
+            .iter()
+            .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+            .collect();
+        assert!(candidates.contains(&"Solarized (dark)".to_string()));
+        assert!(
+            candidates
+                .iter()
+                .all(|theme| theme.starts_with("Solarized"))
+        );
+    }
+}
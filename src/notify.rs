@@ -0,0 +1,78 @@
+use log::{debug, warn};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn on desktop notifications for this run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Post a desktop notification if enabled. Failures are only logged since
+/// the copy itself already happened.
+pub fn send(title: &str, body: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    debug!("Posting notification: {}: {}", title, body);
+    match notification_command(title, body).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Notification command exited with {}", status),
+        Err(e) => warn!("Failed to post notification: {}", e),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, body: &str) -> Command {
+    let script = format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(title)
+    );
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(script);
+    command
+}
+
+#[cfg(not(target_os = "macos"))]
+fn notification_command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.arg("--app-name=mdcopy").arg(title).arg(body);
+    command
+}
+
+/// Quote a string as an AppleScript string literal
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn applescript_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Human-readable byte count for notification text
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applescript_string() {
+        assert_eq!(
+            applescript_string(r#"Copied "doc" C:\x"#),
+            r#""Copied \"doc\" C:\\x""#
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 + 512 * 1024), "3.5 MB");
+    }
+}
@@ -0,0 +1,463 @@
+//! Output formats behind one trait. Each `to_*` module implements `Renderer`,
+//! and `REGISTRY` lists every format with its renderer, so a new format needs
+//! a renderer and one registry entry.
+
+use crate::ClipboardFormat;
+use crate::config::{Config, ImageConfig};
+use crate::frontmatter::FrontMatter;
+use crate::highlight::{HighlightContext, LazyHighlight};
+use crate::image::ImageCache;
+use markdown::mdast::Node;
+use std::io;
+use std::path::Path;
+use std::thread;
+
+/// What one render of a document produces and how. Starts from the resolved
+/// `Config` and is adjusted per call, so the same document can be rendered
+/// for several targets without touching the shared configuration:
+///
+/// ```ignore
+/// let options = RenderOptions::new(&cfg)
+///     .formats(&[ClipboardFormat::Html])
+///     .highlight(None)
+///     .image(ImageConfig { embed_remote: false, ..cfg.image.clone() });
+/// ```
+#[derive(Clone)]
+pub struct RenderOptions<'a> {
+    pub formats: Vec<ClipboardFormat>,
+    /// Syntax highlighting for code blocks (None renders them plain),
+    /// loaded for the first document that has any
+    pub highlight: Option<&'a LazyHighlight>,
+    /// Image embedding and fetching policy
+    pub image: ImageConfig,
+    /// Rewrite relative links to markdown documents to this extension, for
+    /// targets that convert a whole tree
+    pub link_extension: Option<&'a str>,
+}
+
+impl<'a> RenderOptions<'a> {
+    /// HTML with the configured image policy and no highlighting
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            formats: vec![ClipboardFormat::Html],
+            highlight: None,
+            image: cfg.image.clone(),
+            link_extension: None,
+        }
+    }
+
+    pub fn formats(mut self, formats: &[ClipboardFormat]) -> Self {
+        self.formats = formats.to_vec();
+        self
+    }
+
+    pub fn highlight(mut self, highlight: Option<&'a LazyHighlight>) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    pub fn image(mut self, image: ImageConfig) -> Self {
+        self.image = image;
+        self
+    }
+
+    pub fn link_extension(mut self, ext: Option<&'a str>) -> Self {
+        self.link_extension = ext;
+        self
+    }
+}
+
+/// Everything a renderer needs besides the tree
+pub struct Context<'a> {
+    pub cfg: &'a Config,
+    /// Directory relative image paths resolve against
+    pub base_dir: &'a Path,
+    pub highlight: Option<&'a HighlightContext>,
+    pub image: &'a ImageConfig,
+    pub image_cache: &'a ImageCache,
+    /// Front matter taken off the document, if it had any
+    pub front_matter: Option<&'a FrontMatter>,
+}
+
+/// A rendered document
+pub enum Output {
+    Text(String),
+    /// A binary document (PDF, EPUB, MHTML) or a PNG image
+    Bytes(Vec<u8>),
+    #[cfg(target_os = "macos")]
+    Native(crate::to_nsattributedstring::NativeConversionResult),
+}
+
+impl Output {
+    /// The text of a text format (native output has none)
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Output::Text(text) => Some(text),
+            Output::Bytes(_) => None,
+            #[cfg(target_os = "macos")]
+            Output::Native(_) => None,
+        }
+    }
+
+    /// What a file of this output holds (native output is written as RTFD)
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Output::Text(text) => Some(text.as_bytes()),
+            Output::Bytes(bytes) => Some(bytes),
+            #[cfg(target_os = "macos")]
+            Output::Native(_) => None,
+        }
+    }
+}
+
+/// Renderers run side by side on worker threads (see `render_all`)
+pub trait Renderer: Sync {
+    fn render(&self, ast: &Node, ctx: &Context) -> io::Result<Output>;
+
+    /// Whether `render_to` writes the output a block at a time instead of
+    /// rendering it whole first
+    fn streams(&self) -> bool {
+        false
+    }
+
+    /// Write the output to `out`, as it's rendered for formats that stream
+    fn render_to(&self, ast: &Node, ctx: &Context, out: &mut dyn io::Write) -> io::Result<()> {
+        match self.render(ast, ctx)?.as_bytes() {
+            Some(bytes) => out.write_all(bytes),
+            None => Err(io::Error::other("this output has no byte form to write")),
+        }
+    }
+}
+
+/// A registered output format
+pub struct Registration {
+    /// Names accepted by `--format`, canonical name first
+    pub names: &'static [&'static str],
+    pub description: &'static str,
+    /// Where the format works best
+    pub recommended_for: &'static str,
+    /// Why this build can't render the format (None when it can)
+    pub unavailable: Option<&'static str>,
+    renderer: Option<(ClipboardFormat, &'static dyn Renderer)>,
+}
+
+impl Registration {
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+
+    /// The format, when this build can render it
+    pub fn format(&self) -> Option<ClipboardFormat> {
+        self.renderer.map(|(format, _)| format)
+    }
+}
+
+#[cfg(target_os = "macos")]
+const NATIVE: Option<(ClipboardFormat, &dyn Renderer)> = Some((
+    ClipboardFormat::Native,
+    &crate::to_nsattributedstring::NativeRenderer,
+));
+#[cfg(not(target_os = "macos"))]
+const NATIVE: Option<(ClipboardFormat, &dyn Renderer)> = None;
+
+/// Every output format, available on this platform or not
+pub const REGISTRY: &[Registration] = &[
+    Registration {
+        names: &["html"],
+        description: "HTML with inline styles and embedded images",
+        recommended_for: "web apps, Google Docs, Slack, Notion, email; .html files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Html, &crate::to_html::HtmlRenderer)),
+    },
+    Registration {
+        names: &["rtf"],
+        description: "Rich Text Format with embedded images",
+        recommended_for: "Word, Pages, TextEdit, Outlook; .rtf files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Rtf, &crate::to_rtf::RtfRenderer)),
+    },
+    Registration {
+        names: &["markdown", "md"],
+        description: "Normalized markdown",
+        recommended_for: "chat apps, issue trackers, plain text editors; .md files",
+        unavailable: None,
+        renderer: Some((
+            ClipboardFormat::Markdown,
+            &crate::to_markdown::MarkdownRenderer,
+        )),
+    },
+    Registration {
+        names: &["jira"],
+        description: "Jira wiki markup, pasted as plain text",
+        recommended_for: "Jira Server and Data Center, legacy Confluence wiki editors; .jira files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Jira, &crate::to_jira::JiraRenderer)),
+    },
+    Registration {
+        names: &["slack", "mrkdwn"],
+        description: "Slack mrkdwn, pasted as plain text",
+        recommended_for: "Slack messages, webhooks and bot payloads; .slack files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Slack, &crate::to_slack::SlackRenderer)),
+    },
+    Registration {
+        names: &["mediawiki", "wiki"],
+        description: "MediaWiki markup, pasted as plain text",
+        recommended_for: "MediaWiki sites and wikis that take its syntax; .wiki files",
+        unavailable: None,
+        renderer: Some((
+            ClipboardFormat::MediaWiki,
+            &crate::to_mediawiki::MediaWikiRenderer,
+        )),
+    },
+    Registration {
+        names: &["notion"],
+        description: "Notion API block objects as JSON, pasted as plain text",
+        recommended_for: "Notion API scripts and other tools that write pages; .notion.json files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Notion, &crate::to_notion::NotionRenderer)),
+    },
+    Registration {
+        names: &["text", "txt", "plain"],
+        description: "Rendered plain text: wrapped, with text tables",
+        recommended_for: "plain-text email, terminals, commit messages; .txt files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Text, &crate::to_text::TextRenderer)),
+    },
+    Registration {
+        names: &["pdf"],
+        description: "PDF document with embedded images and highlighted code",
+        recommended_for: "one-off files to share or print; .pdf files only",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Pdf, &crate::to_pdf::PdfRenderer)),
+    },
+    Registration {
+        names: &["epub"],
+        description: "EPUB 3 book with its images and a table of contents",
+        recommended_for: "reading notes on e-readers; .epub files only",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Epub, &crate::to_epub::EpubRenderer)),
+    },
+    Registration {
+        names: &["mhtml", "mht"],
+        description: "MHTML archive with images as MIME parts",
+        recommended_for: "Outlook drafts, archiving a page in one file; .mhtml files only",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Mhtml, &crate::to_mhtml::MhtmlRenderer)),
+    },
+    Registration {
+        names: &["image", "png"],
+        description: "PNG picture of the rendered document",
+        recommended_for: "chats and tools that strip rich text on paste; .png files",
+        unavailable: None,
+        renderer: Some((ClipboardFormat::Image, &crate::to_image::ImageRenderer)),
+    },
+    Registration {
+        names: &["prosemirror", "confluence"],
+        description: "ProseMirror slice in the Atlassian document format",
+        recommended_for: "Confluence Cloud and Jira, next to HTML; .json files",
+        unavailable: None,
+        renderer: Some((
+            ClipboardFormat::ProseMirror,
+            &crate::to_prosemirror::ProseMirrorRenderer,
+        )),
+    },
+    Registration {
+        names: &["native", "nsattributedstring"],
+        description: "NSAttributedString, written as RTFD to files",
+        recommended_for: "Notes, Mail, TextEdit and other native macOS apps; .rtfd files",
+        unavailable: if NATIVE.is_some() {
+            None
+        } else {
+            Some("only available on macOS")
+        },
+        renderer: NATIVE,
+    },
+];
+
+/// The registry entry for a `--format` name
+pub fn lookup(name: &str) -> Option<&'static Registration> {
+    REGISTRY.iter().find(|r| r.names.contains(&name))
+}
+
+/// The renderer for `format`
+pub fn renderer(format: ClipboardFormat) -> &'static dyn Renderer {
+    REGISTRY
+        .iter()
+        .filter_map(|r| r.renderer)
+        .find(|(f, _)| *f == format)
+        .map(|(_, renderer)| renderer)
+        .expect("every format has a registered renderer")
+}
+
+/// `ast` rendered in each of `formats`, in order. Formats render side by
+/// side, one thread each, sharing the context and its image cache; native
+/// output renders on the calling thread meanwhile. The first failing
+/// format's error is returned.
+pub fn render_all(
+    ast: &Node,
+    formats: &[ClipboardFormat],
+    ctx: &Context,
+) -> io::Result<Vec<(ClipboardFormat, Output)>> {
+    if formats.len() < 2 {
+        return formats
+            .iter()
+            .map(|&format| Ok((format, renderer(format).render(ast, ctx)?)))
+            .collect();
+    }
+    thread::scope(|scope| {
+        let workers: Vec<_> = formats
+            .iter()
+            .map(|&format| {
+                let worker = (!stays_on_calling_thread(format)).then(|| {
+                    scope.spawn(move || renderer(format).render(ast, ctx).map(Portable::from))
+                });
+                (format, worker)
+            })
+            .collect();
+        let mut local = formats
+            .iter()
+            .filter(|&&format| stays_on_calling_thread(format))
+            .map(|&format| renderer(format).render(ast, ctx))
+            .collect::<Vec<_>>()
+            .into_iter();
+        workers
+            .into_iter()
+            .map(|(format, worker)| {
+                let output = match worker {
+                    Some(worker) => worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?
+                        .into(),
+                    None => local.next().expect("rendered on the calling thread")?,
+                };
+                Ok((format, output))
+            })
+            .collect()
+    })
+}
+
+/// Native output holds AppKit objects, which can't leave their thread
+fn stays_on_calling_thread(format: ClipboardFormat) -> bool {
+    #[cfg(target_os = "macos")]
+    return format == ClipboardFormat::Native;
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = format;
+        false
+    }
+}
+
+/// Output that can come back from a worker thread: everything but native
+enum Portable {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<Output> for Portable {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Text(text) => Portable::Text(text),
+            Output::Bytes(bytes) => Portable::Bytes(bytes),
+            #[cfg(target_os = "macos")]
+            Output::Native(_) => unreachable!("native output renders on the calling thread"),
+        }
+    }
+}
+
+impl From<Portable> for Output {
+    fn from(output: Portable) -> Self {
+        match output {
+            Portable::Text(text) => Output::Text(text),
+            Portable::Bytes(bytes) => Output::Bytes(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(lookup("md").map(Registration::name), Some("markdown"));
+        assert!(lookup("odt").is_none());
+        // Every available entry round-trips through its format
+        for registration in REGISTRY {
+            if let Some(format) = registration.format() {
+                assert!(registration.unavailable.is_none());
+                assert!(
+                    REGISTRY
+                        .iter()
+                        .filter(|r| r.format() == Some(format))
+                        .count()
+                        == 1
+                );
+                renderer(format);
+            }
+        }
+        assert_eq!(
+            lookup("native").unwrap().unavailable.is_none(),
+            cfg!(target_os = "macos")
+        );
+    }
+
+    #[test]
+    fn test_render_options_builder() {
+        let cfg = Config::default();
+        let base = RenderOptions::new(&cfg);
+        assert_eq!(base.formats, vec![ClipboardFormat::Html]);
+        assert!(base.highlight.is_none());
+
+        // Derived options leave the base (and the config) untouched
+        let derived = base
+            .clone()
+            .formats(&[ClipboardFormat::Rtf, ClipboardFormat::Markdown])
+            .image(ImageConfig {
+                embed_local: !cfg.image.embed_local,
+                ..cfg.image.clone()
+            })
+            .link_extension(Some("html"));
+        assert_eq!(derived.formats.len(), 2);
+        assert_ne!(derived.image.embed_local, base.image.embed_local);
+        assert_eq!(base.image.embed_local, cfg.image.embed_local);
+        assert_eq!(derived.link_extension, Some("html"));
+    }
+
+    #[test]
+    fn test_render_all_keeps_format_order() {
+        let ast = markdown::to_mdast(
+            "# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        let cfg = Config::default();
+        let ctx = Context {
+            cfg: &cfg,
+            base_dir: Path::new("."),
+            highlight: None,
+            image: &cfg.image,
+            image_cache: &ImageCache::with_config(&cfg.image),
+            front_matter: None,
+        };
+        let formats = [
+            ClipboardFormat::Markdown,
+            ClipboardFormat::Html,
+            ClipboardFormat::Pdf,
+            ClipboardFormat::Rtf,
+        ];
+        let rendered = render_all(&ast, &formats, &ctx).unwrap();
+        assert_eq!(
+            rendered.iter().map(|(f, _)| *f).collect::<Vec<_>>(),
+            formats
+        );
+        // Same output as rendering each format in turn
+        for (format, output) in &rendered {
+            let alone = renderer(*format).render(&ast, &ctx).unwrap();
+            match *format {
+                ClipboardFormat::Pdf => assert!(output.as_bytes().unwrap().starts_with(b"%PDF")),
+                _ => assert_eq!(output.as_text(), alone.as_text()),
+            }
+        }
+    }
+}
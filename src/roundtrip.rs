@@ -0,0 +1,229 @@
+//! Round-trip fidelity check: a document is rendered, the output is parsed
+//! back, and the two trees are compared as outlines, one line per node, so
+//! constructs that don't survive the pipeline show up as a line diff.
+
+use markdown::mdast::Node;
+use std::fmt;
+
+/// One node of an outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Source line of the node, when the tree has positions
+    pub line: Option<usize>,
+    /// Indented node kind with its salient attributes and text
+    pub text: String,
+}
+
+/// Outline of a tree, depth-first
+pub fn outline(root: &Node) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for child in root.children().into_iter().flatten() {
+        push_entries(child, 0, &mut entries);
+    }
+    entries
+}
+
+fn push_entries(node: &Node, depth: usize, out: &mut Vec<Entry>) {
+    out.push(Entry {
+        line: node.position().map(|p| p.start.line),
+        text: format!("{}{}", "  ".repeat(depth), describe(node)),
+    });
+    for child in node.children().into_iter().flatten() {
+        push_entries(child, depth + 1, out);
+    }
+}
+
+fn describe(node: &Node) -> String {
+    match node {
+        Node::Heading(h) => format!("heading({})", h.depth),
+        Node::List(l) if l.ordered => format!("list(ordered, start {})", l.start.unwrap_or(1)),
+        Node::List(_) => "list".to_string(),
+        Node::ListItem(item) => match item.checked {
+            Some(true) => "item [x]".to_string(),
+            Some(false) => "item [ ]".to_string(),
+            None => "item".to_string(),
+        },
+        Node::Code(c) => format!("code({}) {:?}", c.lang.as_deref().unwrap_or(""), c.value),
+        Node::Text(t) => format!("text {:?}", t.value),
+        Node::InlineCode(c) => format!("inline code {:?}", c.value),
+        Node::Link(l) => format!("link {}", l.url),
+        Node::Image(i) => format!("image {} {:?}", i.url, i.alt),
+        Node::Html(h) => format!("html {:?}", h.value),
+        Node::Definition(d) => format!("definition [{}] {}", d.identifier, d.url),
+        Node::LinkReference(r) => format!("link reference [{}]", r.identifier),
+        Node::ImageReference(r) => format!("image reference [{}] {:?}", r.identifier, r.alt),
+        Node::FootnoteReference(r) => format!("footnote reference [^{}]", r.identifier),
+        Node::FootnoteDefinition(d) => format!("footnote [^{}]", d.identifier),
+        Node::Table(t) => format!("table({} columns)", t.align.len()),
+        Node::Math(m) => format!("math {:?}", m.value),
+        Node::InlineMath(m) => format!("inline math {:?}", m.value),
+        Node::Paragraph(_) => "paragraph".to_string(),
+        Node::Blockquote(_) => "blockquote".to_string(),
+        Node::Strong(_) => "strong".to_string(),
+        Node::Emphasis(_) => "emphasis".to_string(),
+        Node::Delete(_) => "strikethrough".to_string(),
+        Node::Break(_) => "break".to_string(),
+        Node::ThematicBreak(_) => "rule".to_string(),
+        Node::TableRow(_) => "row".to_string(),
+        Node::TableCell(_) => "cell".to_string(),
+        Node::Yaml(_) | Node::Toml(_) => "front matter".to_string(),
+        _ => "other".to_string(),
+    }
+}
+
+/// A difference between two outlines
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    /// In the original only
+    Removed(&'a Entry),
+    /// In the round-tripped document only
+    Added(&'a Entry),
+}
+
+impl fmt::Display for Change<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Removed(entry) => match entry.line {
+                Some(line) => write!(f, "{:>5} - {}", line, entry.text),
+                None => write!(f, "      - {}", entry.text),
+            },
+            Change::Added(entry) => write!(f, "      + {}", entry.text),
+        }
+    }
+}
+
+/// Line diff of two outlines (longest common subsequence, after trimming the
+/// shared prefix and suffix)
+pub fn compare<'a>(before: &'a [Entry], after: &'a [Entry]) -> Vec<Change<'a>> {
+    let same = |a: &Entry, b: &Entry| a.text == b.text;
+    let prefix = before
+        .iter()
+        .zip(after)
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let before = &before[prefix..before.len() - suffix];
+    let after = &after[prefix..after.len() - suffix];
+
+    // lengths[i][j]: LCS length of before[i..] and after[j..]
+    let mut lengths = vec![vec![0u32; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lengths[i][j] = if same(&before[i], &after[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && same(&before[i], &after[j]) {
+            i += 1;
+            j += 1;
+        } else if j == after.len() || (i < before.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            changes.push(Change::Removed(&before[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&after[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn parse(md: &str) -> Node {
+        markdown::to_mdast(md, &ParseOptions::gfm()).unwrap()
+    }
+
+    #[test]
+    fn test_outline() {
+        let texts: Vec<String> = outline(&parse("# Hi\n\n- [x] *done*\n"))
+            .into_iter()
+            .map(|e| e.text)
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "heading(1)",
+                "  text \"Hi\"",
+                "list",
+                "  item [x]",
+                "    paragraph",
+                "      emphasis",
+                "        text \"done\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let before = outline(&parse("# Title\n\nSome *text*.\n\n---\n"));
+        let after = outline(&parse("# Title\n\nSome text.\n\n---\n"));
+        let changes: Vec<String> = compare(&before, &after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "    3 -   text \"Some \"",
+                "    3 -   emphasis",
+                "    3 -     text \"text\"",
+                "    3 -   text \".\"",
+                "      +   text \"Some text.\"",
+            ]
+        );
+        assert!(compare(&before, &before).is_empty());
+    }
+
+    /// Changes after markdown → HTML → markdown, as `mdcopy roundtrip` runs it
+    fn html_round_trip(md: &str) -> Vec<String> {
+        use crate::config::ImageConfig;
+        use crate::image::ImageCache;
+        use crate::to_html::{HtmlOptions, mdast_to_html};
+        use std::path::Path;
+
+        let original = parse(md);
+        let html = mdast_to_html(
+            &original,
+            Path::new("."),
+            &ImageConfig::default(),
+            false,
+            None,
+            &ImageCache::new(),
+            &HtmlOptions::default(),
+        )
+        .unwrap();
+        let back = parse(&crate::from_html::html_to_markdown(&html));
+        compare(&outline(&original), &outline(&back))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_html_round_trip() {
+        assert!(html_round_trip("# Title\n\nSome *text* and `code`.\n").is_empty());
+
+        // Alerts become a styled box, which reads back as plain paragraphs
+        let changes = html_round_trip("> [!NOTE]\n> Hi\n");
+        assert!(
+            changes.contains(&"    1 - blockquote".to_string()),
+            "{:?}",
+            changes
+        );
+    }
+}
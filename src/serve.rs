@@ -0,0 +1,416 @@
+//! `mdcopy serve`: a long-running conversion daemon. Requests arrive as
+//! HTTP/1.1 on a local socket and are converted by a bounded worker pool
+//! that shares the syntax and theme sets and the image cache, so only the
+//! first request pays for loading them. Queue and latency metrics are on a
+//! status endpoint.
+
+use crate::batch;
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Completed requests the latency percentiles are computed over
+const LATENCY_WINDOW: usize = 1000;
+
+/// Time a client gets to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP request
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Query parameters, percent-decoded
+    pub query: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The first query parameter called `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /// A plain text response, for errors
+    pub fn text(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::new(
+            status,
+            "text/plain; charset=utf-8",
+            format!("{}\n", message),
+        )
+    }
+}
+
+/// Queue and latency metrics, as served on `/status`
+#[derive(Debug, Default, Serialize)]
+pub struct Status {
+    pub workers: usize,
+    /// Requests the queue holds before new ones are turned away
+    pub queue_capacity: usize,
+    /// Requests waiting for a worker
+    pub queued: usize,
+    /// Requests being converted
+    pub active: usize,
+    pub completed: u64,
+    /// Requests answered with an error status
+    pub failed: u64,
+    /// Requests turned away because the queue was full
+    pub rejected: u64,
+    pub uptime_secs: u64,
+    /// Percentiles over the last completed requests, from arrival until the
+    /// response is ready
+    pub latency_ms: Latency,
+    /// Longest time a request waited in the queue
+    pub max_wait_ms: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Latency {
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+}
+
+#[derive(Default)]
+struct Metrics {
+    queued: usize,
+    active: usize,
+    completed: u64,
+    failed: u64,
+    rejected: u64,
+    latencies: VecDeque<Duration>,
+    max_wait: Duration,
+}
+
+impl Metrics {
+    fn status(&self, workers: usize, queue_capacity: usize, started: Instant) -> Status {
+        let latencies: Vec<Duration> = self.latencies.iter().copied().collect();
+        let ms = |p| batch::percentile(&latencies, p).as_millis() as u64;
+        Status {
+            workers,
+            queue_capacity,
+            queued: self.queued,
+            active: self.active,
+            completed: self.completed,
+            failed: self.failed,
+            rejected: self.rejected,
+            uptime_secs: started.elapsed().as_secs(),
+            latency_ms: Latency {
+                p50: ms(50),
+                p95: ms(95),
+                p99: ms(99),
+            },
+            max_wait_ms: self.max_wait.as_millis() as u64,
+        }
+    }
+}
+
+/// A request waiting for a worker
+struct Job {
+    stream: TcpStream,
+    request: Request,
+    arrived: Instant,
+}
+
+/// Serve requests on `listener` until it fails. `GET /status` is answered
+/// directly; everything else is queued for one of `workers` threads
+/// (0 = one per CPU) running `handle`. A request arriving while `queue`
+/// requests are already waiting gets a 503 instead of waiting too.
+pub fn serve<F>(listener: TcpListener, workers: usize, queue: usize, handle: F) -> io::Result<()>
+where
+    F: Fn(&Request) -> Response + Sync,
+{
+    let workers = batch::worker_count(workers);
+    let queue = queue.max(1);
+    info!(
+        "Serving on http://{} with {} worker(s)",
+        listener.local_addr()?,
+        workers
+    );
+
+    let started = Instant::now();
+    let metrics = Mutex::new(Metrics::default());
+    let (sender, receiver) = mpsc::sync_channel::<Job>(queue);
+    let receiver = Arc::new(Mutex::new(receiver));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let (metrics, handle) = (&metrics, &handle);
+            scope.spawn(move || {
+                loop {
+                    // Released before converting, so other workers can take
+                    // the next job; ends once accepting stops
+                    let Ok(job) = receiver.lock().unwrap().recv() else {
+                        break;
+                    };
+                    let wait = job.arrived.elapsed();
+                    {
+                        let mut metrics = metrics.lock().unwrap();
+                        metrics.queued -= 1;
+                        metrics.active += 1;
+                        metrics.max_wait = metrics.max_wait.max(wait);
+                    }
+                    let response = handle(&job.request);
+                    {
+                        let mut metrics = metrics.lock().unwrap();
+                        metrics.active -= 1;
+                        if response.status < 400 {
+                            metrics.completed += 1;
+                        } else {
+                            metrics.failed += 1;
+                        }
+                        if metrics.latencies.len() == LATENCY_WINDOW {
+                            metrics.latencies.pop_front();
+                        }
+                        metrics.latencies.push_back(job.arrived.elapsed());
+                    }
+                    reply(job.stream, &response);
+                }
+            });
+        }
+
+        let result = (|| {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let arrived = Instant::now();
+                let request = match read_request(&stream) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        debug!("Bad request: {}", e);
+                        reply(stream, &Response::text(400, e));
+                        continue;
+                    }
+                };
+                if request.method == "GET" && request.path == "/status" {
+                    let status = metrics.lock().unwrap().status(workers, queue, started);
+                    let json = serde_json::to_vec_pretty(&status).map_err(io::Error::other)?;
+                    reply(stream, &Response::new(200, "application/json", json));
+                    continue;
+                }
+
+                metrics.lock().unwrap().queued += 1;
+                let job = Job {
+                    stream,
+                    request,
+                    arrived,
+                };
+                if let Err(TrySendError::Full(job) | TrySendError::Disconnected(job)) =
+                    sender.try_send(job)
+                {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.queued -= 1;
+                    metrics.rejected += 1;
+                    drop(metrics);
+                    reply(job.stream, &Response::text(503, "Queue full, retry later"));
+                }
+            }
+            Ok(())
+        })();
+        drop(sender);
+        result
+    })
+}
+
+/// Write `response`, ignoring clients that went away
+fn reply(mut stream: TcpStream, response: &Response) {
+    if let Err(e) = write_response(&mut stream, response) {
+        debug!("Failed to send response: {}", e);
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    parse_request(&mut BufReader::new(stream))
+}
+
+fn parse_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    let mut length = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("Request ended in the headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value
+                .trim()
+                .parse()
+                .map_err(|_| invalid("Invalid Content-Length"))?;
+        }
+    }
+    if length > MAX_BODY_BYTES {
+        return Err(invalid("Request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        body,
+    })
+}
+
+/// `+` as space and `%XX` escapes; malformed escapes are kept as written
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()) =>
+            {
+                out.push(byte);
+                i += 2;
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn write_response(stream: &mut impl Write, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Content",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_parse_request() {
+        let raw = "POST /convert?format=html%2Crtf&title=A+B HTTP/1.1\r\n\
+                   Host: localhost\r\ncontent-length: 5\r\n\r\n# Hi!";
+        let request = parse_request(&mut raw.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/convert");
+        assert_eq!(request.param("format"), Some("html,rtf"));
+        assert_eq!(request.param("title"), Some("A B"));
+        assert_eq!(request.body, b"# Hi!");
+
+        assert!(parse_request(&mut "GET /status HTTP/1.1\r\n".as_bytes()).is_err());
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    fn send(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        static HANDLED: AtomicUsize = AtomicUsize::new(0);
+        thread::spawn(move || {
+            serve(listener, 2, 4, |request| {
+                HANDLED.fetch_add(1, Ordering::Relaxed);
+                match request.path.as_str() {
+                    "/upper" => Response::new(
+                        200,
+                        "text/plain",
+                        String::from_utf8_lossy(&request.body).to_uppercase(),
+                    ),
+                    _ => Response::text(404, "Not found"),
+                }
+            })
+        });
+
+        let response = send(addr, "POST /upper HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nHI"), "{}", response);
+        assert!(send(addr, "GET /nope HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+
+        let status = send(addr, "GET /status HTTP/1.1\r\n\r\n");
+        let json = status.split_once("\r\n\r\n").unwrap().1;
+        let status: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(status["workers"], 2);
+        assert_eq!(status["queue_capacity"], 4);
+        assert_eq!(status["completed"], 1);
+        assert_eq!(status["failed"], 1);
+        assert_eq!(status["queued"], 0);
+        assert_eq!(HANDLED.load(Ordering::Relaxed), 2);
+    }
+}
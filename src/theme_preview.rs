@@ -0,0 +1,504 @@
+//! `mdcopy themes preview`: a sample code block highlighted in every theme,
+//! for the terminal or as an HTML gallery.
+
+use crate::highlight::HighlightContext;
+use crate::to_html::html_escape;
+use std::collections::BTreeMap;
+use syntect::highlighting::{Color, Style, Theme};
+
+/// Code shown for each language, keyed by the tokens that pick it
+const SAMPLES: &[(&[&str], &str)] = &[
+    (
+        &["rust", "rs"],
+        r#"use std::collections::HashMap;
+
+/// Count the words in `text`
+pub fn word_counts(text: &str) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split_whitespace() {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn main() {
+    let counts = word_counts("the quick brown fox jumps over the lazy dog");
+    println!("{} distinct words, 'the' x{}", counts.len(), counts["the"]);
+}
+"#,
+    ),
+    (
+        &["python", "py"],
+        r#"from collections import Counter
+
+
+class Report:
+    """Word counts of a text."""
+
+    def __init__(self, text: str, limit: int = 3):
+        self.counts = Counter(text.split())
+        self.limit = limit
+
+    def top(self):
+        return self.counts.most_common(self.limit)
+
+
+if __name__ == "__main__":
+    print(Report("the quick brown fox jumps over the lazy dog").top())
+"#,
+    ),
+    (
+        &["js", "javascript", "ts", "typescript"],
+        r#"import { readFile } from "node:fs/promises";
+
+// Count the words in a file
+export async function wordCounts(path) {
+  const text = await readFile(path, "utf8");
+  const counts = new Map();
+  for (const word of text.split(/\s+/).filter(Boolean)) {
+    counts.set(word, (counts.get(word) ?? 0) + 1);
+  }
+  return counts;
+}
+
+const counts = await wordCounts("README.md");
+console.log(`${counts.size} distinct words`, counts.get("the") === 3);
+"#,
+    ),
+    (
+        &["go", "golang"],
+        r#"package main
+
+import (
+	"fmt"
+	"strings"
+)
+
+// wordCounts counts the words in text
+func wordCounts(text string) map[string]int {
+	counts := make(map[string]int)
+	for _, word := range strings.Fields(text) {
+		counts[word]++
+	}
+	return counts
+}
+
+func main() {
+	counts := wordCounts("the quick brown fox jumps over the lazy dog")
+	fmt.Printf("%d distinct words, 'the' x%d\n", len(counts), counts["the"])
+}
+"#,
+    ),
+    (
+        &["c", "cpp", "c++"],
+        r#"#include <stdio.h>
+#include <string.h>
+
+#define MAX_WORDS 64
+
+/* Count the words in a line */
+static int count_words(char *line) {
+    int count = 0;
+    for (char *word = strtok(line, " \n"); word != NULL; word = strtok(NULL, " \n")) {
+        count++;
+    }
+    return count;
+}
+
+int main(void) {
+    char line[] = "the quick brown fox jumps over the lazy dog";
+    printf("%d words (max %d)\n", count_words(line), MAX_WORDS);
+    return 0;
+}
+"#,
+    ),
+    (
+        &["java"],
+        r#"import java.util.HashMap;
+import java.util.Map;
+
+public class WordCounts {
+    /** Count the words in a text */
+    public static Map<String, Integer> count(String text) {
+        Map<String, Integer> counts = new HashMap<>();
+        for (String word : text.split("\\s+")) {
+            counts.merge(word, 1, Integer::sum);
+        }
+        return counts;
+    }
+
+    public static void main(String[] args) {
+        var counts = count("the quick brown fox jumps over the lazy dog");
+        System.out.println(counts.size() + " distinct words");
+    }
+}
+"#,
+    ),
+    (
+        &["ruby", "rb"],
+        r#"require "set"
+
+# Word counts of a text
+class Report
+  attr_reader :counts
+
+  def initialize(text, limit: 3)
+    @counts = text.split.tally
+    @limit = limit
+  end
+
+  def top
+    counts.max_by(@limit) { |_word, count| count }
+  end
+end
+
+puts Report.new("the quick brown fox jumps over the lazy dog").top.inspect
+"#,
+    ),
+    (
+        &["bash", "sh", "shell", "zsh"],
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+
+# Count the words in each markdown file
+total=0
+for file in "${1:-.}"/*.md; do
+  words=$(wc -w < "$file")
+  printf '%6d  %s\n' "$words" "$(basename "$file")"
+  total=$((total + words))
+done
+
+if [[ $total -gt 0 ]]; then
+  echo "total: $total"
+fi
+"#,
+    ),
+    (
+        &["json"],
+        r#"{
+  "name": "mdcopy",
+  "version": "0.4.0",
+  "private": true,
+  "keywords": ["markdown", "clipboard", "rtf"],
+  "limits": { "max_size_mb": 100, "quality": 0.85 },
+  "repository": null
+}
+"#,
+    ),
+    (
+        &["yaml", "yml"],
+        r#"# Build and test on every push
+name: ci
+on:
+  push:
+    branches: [main]
+jobs:
+  test:
+    runs-on: ubuntu-latest
+    timeout-minutes: 15
+    steps:
+      - uses: actions/checkout@v4
+      - run: cargo test --workspace
+        env:
+          RUST_BACKTRACE: "1"
+"#,
+    ),
+    (
+        &["toml"],
+        r#"# Highlighting for shared docs
+[highlight]
+enable = true
+theme = "InspiredGitHub"
+badge = "header"
+check_contrast = false
+
+[highlight.languages]
+jsx = "JavaScript"
+
+[image]
+max_dimension = 1200
+quality = 85
+"#,
+    ),
+    (
+        &["html", "xml"],
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Word counts</title>
+  <link rel="stylesheet" href="style.css">
+</head>
+<body>
+  <!-- Filled in by script.js -->
+  <table id="counts" class="wide">
+    <tr><th>Word</th><th>Count</th></tr>
+  </table>
+  <script src="script.js" defer></script>
+</body>
+</html>
+"#,
+    ),
+    (
+        &["css"],
+        r#"/* Code blocks */
+pre {
+  background-color: #f6f8fa;
+  padding: 16px;
+  border-radius: 8px;
+  font: 14px/1.4 ui-monospace, monospace;
+}
+
+@media (prefers-color-scheme: dark) {
+  pre:not(.plain) > code::before {
+    content: "code";
+    color: rgb(139 148 158 / 80%);
+  }
+}
+"#,
+    ),
+    (
+        &["sql"],
+        r#"-- Most used words across documents
+SELECT w.word, COUNT(*) AS uses, MAX(d.updated_at) AS last_used
+FROM words AS w
+JOIN documents AS d ON d.id = w.document_id
+WHERE d.archived = FALSE
+  AND w.word NOT IN ('the', 'a', 'an')
+GROUP BY w.word
+HAVING COUNT(*) > 10
+ORDER BY uses DESC
+LIMIT 20;
+"#,
+    ),
+    (
+        &["diff", "patch"],
+        r#"diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -10,7 +10,8 @@ fn main() {
+     let counts = word_counts(&text);
+-    println!("{}", counts.len());
++    let total: usize = counts.values().sum();
++    println!("{} distinct, {} total", counts.len(), total);
+ }
+"#,
+    ),
+    (
+        &["console", "shellsession"],
+        r#"$ cargo install mdcopy
+    Updating crates.io index
+  Installed package `mdcopy v0.4.0` (executable `mdcopy`)
+$ mdcopy README.md --highlight-theme InspiredGitHub \
+    --code-badge header
+Copied README.md as HTML and RTF (48 KB)
+$ echo $?
+0
+"#,
+    ),
+];
+
+/// Language tokens with a built-in sample, one per sample
+pub fn sample_languages() -> Vec<&'static str> {
+    SAMPLES.iter().map(|(tokens, _)| tokens[0]).collect()
+}
+
+/// The built-in sample for `lang`
+pub fn sample(lang: &str) -> Option<&'static str> {
+    SAMPLES
+        .iter()
+        .find(|(tokens, _)| tokens.iter().any(|t| t.eq_ignore_ascii_case(lang)))
+        .map(|(_, code)| *code)
+}
+
+/// `code` highlighted in one theme
+pub struct ThemeSample {
+    pub name: String,
+    pub background: Color,
+    pub foreground: Color,
+    /// Styled pieces of each line, without line endings
+    pub lines: Vec<Vec<(Style, String)>>,
+}
+
+/// `code` in `lang` highlighted with each of `themes`, in turn set as the
+/// theme of `hl`
+pub fn highlight_in_each(
+    hl: &mut HighlightContext,
+    themes: BTreeMap<String, Theme>,
+    lang: &str,
+    code: &str,
+) -> Vec<ThemeSample> {
+    // Tabs would break the terminal's padding
+    let code = &code.replace('\t', "    ");
+    themes
+        .into_iter()
+        .map(|(name, theme)| {
+            hl.theme = theme;
+            let mut highlighter = hl.highlighter(Some(lang), code);
+            let lines = code
+                .lines()
+                .map(|line| {
+                    let line = format!("{}\n", line);
+                    match highlighter.highlight_line(&line) {
+                        Ok(ranges) => ranges
+                            .into_iter()
+                            .map(|(style, text)| (style, text.trim_end_matches('\n').to_string()))
+                            .filter(|(_, text)| !text.is_empty())
+                            .collect(),
+                        Err(_) => vec![(Style::default(), line.trim_end().to_string())],
+                    }
+                })
+                .collect();
+            ThemeSample {
+                name,
+                background: hl.background(),
+                foreground: hl.theme.settings.foreground.unwrap_or(Color::WHITE),
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// The samples for a 24-bit color terminal: each theme's name over its
+/// block, padded to a common width so the background forms a box
+pub fn to_ansi(samples: &[ThemeSample]) -> String {
+    let width = samples
+        .iter()
+        .flat_map(|sample| &sample.lines)
+        .map(|line| line.iter().map(|(_, text)| text.chars().count()).sum())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for sample in samples {
+        let bg = sample.background;
+        let background = format!("\x1b[48;2;{};{};{}m", bg.r, bg.g, bg.b);
+        out.push_str(&format!("\x1b[1m{}\x1b[0m\n", sample.name));
+        let blank = format!("{}{}\x1b[0m\n", background, " ".repeat(width + 4));
+        out.push_str(&blank);
+        for line in &sample.lines {
+            out.push_str(&background);
+            out.push_str("  ");
+            let mut len = 0;
+            for (style, text) in line {
+                let fg = style.foreground;
+                out.push_str(&format!("\x1b[38;2;{};{};{}m{}", fg.r, fg.g, fg.b, text));
+                len += text.chars().count();
+            }
+            out.push_str(&" ".repeat(width - len + 2));
+            out.push_str("\x1b[0m\n");
+        }
+        out.push_str(&blank);
+        out.push('\n');
+    }
+    out
+}
+
+/// The samples as a standalone HTML page, one block per theme, styled like
+/// mdcopy's HTML code blocks
+pub fn to_html(samples: &[ThemeSample], lang: &str) -> String {
+    let hex = |c: Color| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>mdcopy themes: {lang}</title>\n</head>\n\
+         <body style=\"font-family:-apple-system,'Segoe UI',sans-serif; margin:32px;\">\n\
+         <h1>{} themes highlighting {lang}</h1>\n",
+        samples.len(),
+        lang = html_escape(lang)
+    );
+    for sample in samples {
+        html.push_str(&format!(
+            "<h2 id=\"{name}\">{name}</h2>\n<pre style=\"background-color:{}; color:{}; \
+             padding:16px; font-family:monospace,monospace; font-size:14px; \
+             border-radius:8px;\"><code>",
+            hex(sample.background),
+            hex(sample.foreground),
+            name = html_escape(&sample.name)
+        ));
+        let lines: Vec<String> = sample
+            .lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|(style, text)| {
+                        format!(
+                            "<span style=\"color:{}\">{}</span>",
+                            hex(style.foreground),
+                            html_escape(text)
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        html.push_str(&lines.join("\n"));
+        html.push_str("</code></pre>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn samples() -> Vec<ThemeSample> {
+        let mut hl =
+            HighlightContext::new("InspiredGitHub", &HashMap::new(), &HashMap::new(), &[], &[])
+                .unwrap();
+        let themes = HighlightContext::load_themes(&[]);
+        let count = themes.len();
+        let samples = highlight_in_each(&mut hl, themes, "rust", sample("rust").unwrap());
+        assert_eq!(samples.len(), count);
+        samples
+    }
+
+    #[test]
+    fn test_samples_cover_their_languages() {
+        assert_eq!(sample("RS"), sample("rust"));
+        assert!(sample("cobol").is_none());
+        assert_eq!(sample_languages().len(), SAMPLES.len());
+        assert!(SAMPLES.iter().all(|(_, code)| code.ends_with('\n')));
+    }
+
+    #[test]
+    fn test_preview_outputs() {
+        let samples = samples();
+        let light = samples
+            .iter()
+            .find(|sample| sample.name == "InspiredGitHub")
+            .unwrap();
+        assert_eq!(light.lines.len(), sample("rust").unwrap().lines().count());
+        // Keywords stand out from the plain text
+        let colors: Vec<Color> = light.lines[3].iter().map(|(s, _)| s.foreground).collect();
+        assert!(colors.iter().any(|&c| c != colors[0]));
+
+        let ansi = to_ansi(&samples);
+        assert!(ansi.contains("\x1b[1mInspiredGitHub\x1b[0m\n"));
+        assert!(ansi.contains("\x1b[48;2;255;255;255m"));
+        // Every line of a block has the same visible width
+        let widths: Vec<usize> = ansi
+            .lines()
+            .filter(|line| line.starts_with("\x1b[48"))
+            .map(|line| {
+                let mut plain = String::new();
+                let mut escape = false;
+                for c in line.chars() {
+                    match c {
+                        '\x1b' => escape = true,
+                        'm' if escape => escape = false,
+                        _ if !escape => plain.push(c),
+                        _ => {}
+                    }
+                }
+                plain.chars().count()
+            })
+            .collect();
+        assert!(widths.windows(2).all(|pair| pair[0] == pair[1]));
+
+        let html = to_html(&samples, "rust");
+        assert!(html.contains("<h2 id=\"InspiredGitHub\">InspiredGitHub</h2>"));
+        assert!(html.contains("background-color:#ffffff"));
+        assert!(html.contains(">&amp;<") || html.contains("&amp;str"));
+        assert_eq!(html.matches("<pre ").count(), samples.len());
+    }
+}
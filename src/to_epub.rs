@@ -0,0 +1,379 @@
+//! EPUB 3 books for reading notes on e-readers: the HTML output as a single
+//! XHTML chapter, its embedded images as files of their own, and a table of
+//! contents built from the headings. Raw HTML is left out, since readers
+//! refuse chapters that aren't well-formed XML.
+
+use crate::anchors::Anchors;
+use crate::details;
+use crate::render::{self, Output, Renderer};
+use crate::to_html::{self, ExtractedImage, HtmlOptions};
+use markdown::mdast::Node;
+use sha2::{Digest, Sha256};
+use std::io::{self, Cursor, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Headings deeper than this stay out of the table of contents
+const TOC_DEPTH: u8 = 3;
+
+const CONTAINER_XML: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n\
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+</rootfiles>\n\
+</container>\n";
+
+pub fn mdast_to_epub(root: &Node, ctx: &render::Context) -> io::Result<Vec<u8>> {
+    let mut ast = details::folded(root).into_owned();
+    strip_html(&mut ast);
+    let options = HtmlOptions {
+        list_style: ctx.cfg.typography.list_style,
+        heading_ids: true,
+        code_badge: ctx.cfg.highlight.badge,
+        code_style: ctx.cfg.html.code_style,
+        code: ctx.cfg.html.code,
+        ..HtmlOptions::default()
+    };
+    let html = to_html::mdast_to_html(
+        &ast,
+        ctx.base_dir,
+        ctx.image,
+        ctx.cfg.strict.images,
+        ctx.highlight,
+        ctx.image_cache,
+        &options,
+    )
+    .map_err(io::Error::other)?;
+    // Table cells carry the one bare attribute the HTML output uses; text
+    // can't match, as its `>` is escaped
+    let html = html.replace(" nowrap>", " nowrap=\"nowrap\">");
+    let (body, images) = to_html::extract_images(&html, |index, mime_type| {
+        format!("images/image{}.{}", index, extension(mime_type))
+    });
+
+    let mut headings = Vec::new();
+    collect_headings(&ast, &mut headings);
+    let front_matter = ctx.front_matter;
+    let title = front_matter
+        .and_then(|fm| fm.title.clone())
+        .or_else(|| headings.first().map(|(_, text)| text.clone()))
+        .unwrap_or_else(|| "Untitled".to_string());
+    let toc: Vec<(u8, String, String)> = headings
+        .into_iter()
+        .zip(Anchors::collect(&ast).slugs().iter().cloned())
+        .filter(|((depth, _), _)| *depth <= TOC_DEPTH)
+        .map(|((depth, text), id)| (depth, text, id))
+        .collect();
+
+    let package = Package {
+        title: &title,
+        author: front_matter.and_then(|fm| fm.author.as_deref()),
+        identifier: identifier(&body),
+        modified: timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        ),
+        // Images that weren't embedded are still fetched by the reader
+        remote: body.contains("src=\"http"),
+        images: &images,
+    };
+    write_epub(&package, &chapter(&title, &body), &nav(&title, &toc))
+}
+
+pub struct EpubRenderer;
+
+impl Renderer for EpubRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_epub(ast, ctx).map(Output::Bytes)
+    }
+}
+
+/// What goes into `content.opf` besides the chapter and navigation
+struct Package<'a> {
+    title: &'a str,
+    author: Option<&'a str>,
+    identifier: String,
+    /// `dcterms:modified`, which EPUB 3 requires
+    modified: String,
+    remote: bool,
+    images: &'a [ExtractedImage],
+}
+
+fn strip_html(node: &mut Node) {
+    if let Some(children) = node.children_mut() {
+        children.retain(|child| !matches!(child, Node::Html(_)));
+        for child in children {
+            strip_html(child);
+        }
+    }
+}
+
+fn extension(mime_type: &str) -> &str {
+    match mime_type {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        other => other.strip_prefix("image/").unwrap_or("bin"),
+    }
+}
+
+/// Text and depth of every heading, in the order `Anchors` numbers them
+fn collect_headings(node: &Node, headings: &mut Vec<(u8, String)>) {
+    if let Node::Heading(heading) = node {
+        headings.push((heading.depth, node.to_string()));
+        return;
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_headings(child, headings);
+    }
+}
+
+fn chapter(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\">\n<head>\n\
+         <meta charset=\"utf-8\"/>\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        xml_escape(title),
+        body
+    )
+}
+
+/// The navigation document: nested lists of links to the headings, or a
+/// single entry for the chapter when there are none
+fn nav(title: &str, toc: &[(u8, String, String)]) -> String {
+    let mut list = String::new();
+    if toc.is_empty() {
+        list.push_str(&format!(
+            "<ol>\n<li><a href=\"chapter.xhtml\">{}</a></li>\n</ol>\n",
+            xml_escape(title)
+        ));
+    }
+    // Levels start at the shallowest heading and never skip one, so every
+    // nested list sits inside an entry
+    let top = toc.iter().map(|(depth, _, _)| *depth).min().unwrap_or(1);
+    let mut open: Vec<u8> = Vec::new();
+    let mut previous = top - 1;
+    for (depth, text, id) in toc {
+        let level = (*depth).clamp(top, previous + 1);
+        previous = level;
+        while open.last().is_some_and(|&l| l > level) {
+            list.push_str("</li>\n</ol>\n");
+            open.pop();
+        }
+        if open.last() == Some(&level) {
+            list.push_str("</li>\n");
+        } else {
+            list.push_str("<ol>\n");
+            open.push(level);
+        }
+        list.push_str(&format!(
+            "<li><a href=\"chapter.xhtml#{}\">{}</a>",
+            xml_escape(id),
+            xml_escape(text)
+        ));
+    }
+    for _ in open {
+        list.push_str("</li>\n</ol>\n");
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n</head>\n<body>\n\
+         <nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n{}</nav>\n</body>\n</html>\n",
+        xml_escape(title),
+        list
+    )
+}
+
+fn package_document(package: &Package) -> String {
+    let mut metadata = format!(
+        "<dc:identifier id=\"book-id\">{}</dc:identifier>\n\
+         <dc:title>{}</dc:title>\n<dc:language>und</dc:language>\n",
+        package.identifier,
+        xml_escape(package.title)
+    );
+    if let Some(author) = package.author {
+        metadata.push_str(&format!(
+            "<dc:creator>{}</dc:creator>\n",
+            xml_escape(author)
+        ));
+    }
+    metadata.push_str(&format!(
+        "<meta property=\"dcterms:modified\">{}</meta>\n",
+        package.modified
+    ));
+
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    manifest.push_str(&format!(
+        "<item id=\"chapter\" href=\"chapter.xhtml\" media-type=\"application/xhtml+xml\"{}/>\n",
+        if package.remote {
+            " properties=\"remote-resources\""
+        } else {
+            ""
+        }
+    ));
+    for (index, image) in package.images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"image{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            image.name,
+            xml_escape(&image.mime_type)
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+         <manifest>\n{}</manifest>\n<spine>\n<itemref idref=\"chapter\"/>\n</spine>\n</package>\n",
+        metadata, manifest
+    )
+}
+
+/// The OCF container: `mimetype` first and uncompressed, as readers
+/// identify the file by it, then everything else deflated
+fn write_epub(package: &Package, chapter: &str, nav: &str) -> io::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    let opf = package_document(package);
+    let files = [
+        ("META-INF/container.xml", CONTAINER_XML.as_bytes()),
+        ("OEBPS/content.opf", opf.as_bytes()),
+        ("OEBPS/nav.xhtml", nav.as_bytes()),
+        ("OEBPS/chapter.xhtml", chapter.as_bytes()),
+    ];
+    for (name, data) in files {
+        zip.start_file(name, deflated)?;
+        zip.write_all(data)?;
+    }
+    for image in package.images {
+        zip.start_file(format!("OEBPS/{}", image.name), stored)?;
+        zip.write_all(&image.data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// A stable id for the book, from its content
+fn identifier(body: &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("urn:mdcopy:{}", hex)
+}
+
+/// `CCYY-MM-DDThh:mm:ssZ` for seconds since the Unix epoch
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+    // Civil date from days, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::frontmatter::FrontMatter;
+    use crate::image::ImageCache;
+    use markdown::ParseOptions;
+    use std::io::Read;
+    use std::path::Path;
+    use zip::ZipArchive;
+
+    fn epub(md: &str, front_matter: Option<&FrontMatter>) -> ZipArchive<Cursor<Vec<u8>>> {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        let cfg = Config::default();
+        let ctx = render::Context {
+            cfg: &cfg,
+            base_dir: Path::new("."),
+            highlight: None,
+            image: &cfg.image,
+            image_cache: &ImageCache::with_config(&cfg.image),
+            front_matter,
+        };
+        let bytes = mdast_to_epub(&ast, &ctx).unwrap();
+        ZipArchive::new(Cursor::new(bytes)).unwrap()
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_document() {
+        let front_matter = FrontMatter {
+            raw: String::new(),
+            title: Some("Field <Notes>".to_string()),
+            author: Some("Ada".to_string()),
+            bibliography: None,
+        };
+        let mut archive = epub(
+            "# Intro\n\nHi<br>there.\n\n### Deep\n\n## Setup\n\n| a |\n|---|\n| 1 |\n\n#### Skipped\n",
+            Some(&front_matter),
+        );
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Field &lt;Notes&gt;</dc:title>"));
+        assert!(opf.contains("<dc:creator>Ada</dc:creator>"));
+        assert!(opf.contains("properties=\"nav\""));
+
+        let chapter = read(&mut archive, "OEBPS/chapter.xhtml");
+        assert!(chapter.contains("<h2 id=\"setup\">"), "{}", chapter);
+        assert!(!chapter.contains("<br>"));
+        assert!(chapter.contains("<th nowrap=\"nowrap\">"));
+
+        // h3 and h2 under the h1 are both one level in, h4 is past the
+        // depth limit
+        let nav = read(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains(
+            "<ol>\n<li><a href=\"chapter.xhtml#intro\">Intro</a><ol>\n\
+             <li><a href=\"chapter.xhtml#deep\">Deep</a></li>\n\
+             <li><a href=\"chapter.xhtml#setup\">Setup</a></li>\n</ol>\n</li>\n</ol>\n"
+        ));
+        assert!(!nav.contains("Skipped"));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(1_709_210_096), "2024-02-29T12:34:56Z");
+    }
+}
@@ -1,10 +1,52 @@
-use crate::config::ImageConfig;
-use crate::highlight::HighlightContext;
-use crate::image::{ImageCache, ImageError};
+use crate::alerts::{self, AlertKind};
+use crate::anchors::Anchors;
+use crate::config::{CodeBadge, CodeLayout, CodeStyle, EmojiImages, ImageConfig, ListStyle};
+use crate::emoji::{self, Segment};
+use crate::frontmatter::FrontMatter;
+use crate::highlight::{self, CodeLines, HighlightContext};
+use crate::image::{self, ImageCache, ImageError};
+use crate::inline::Definitions;
+use crate::render::{self, Output, Renderer};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use markdown::mdast::{AlignKind, Node};
+use std::collections::HashMap;
+use std::io;
 use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::util::LinesWithEndings;
+use syntect::highlighting::Color;
+
+/// Rendering options for HTML output
+#[derive(Debug, Clone, Default)]
+pub struct HtmlOptions {
+    /// Emit ProseMirror slice marker for Confluence/ProseMirror paste compatibility
+    pub prosemirror: bool,
+    /// Numbering style for ordered lists
+    pub list_style: ListStyle,
+    /// Wrap sections headed at or below this level in `<details>`
+    pub collapsible_headings: Option<u8>,
+    /// GitHub-style `id` slugs on headings, with `#heading` links matched to them
+    pub heading_ids: bool,
+    /// Language label on fenced code blocks
+    pub code_badge: CodeBadge,
+    /// Markup of code blocks
+    pub code_style: CodeStyle,
+    /// Wrapping and width of code blocks
+    pub code: CodeLayout,
+    /// Let unwrapped code scroll sideways, for pages viewed on their own
+    pub scroll_code: bool,
+    /// Lay out for email clients (Outlook, Gmail): a 600px table layout,
+    /// conservative inline CSS and sized images
+    pub email: bool,
+}
+
+/// Widest an email body gets, and so its widest image
+const EMAIL_WIDTH: usize = 600;
+
+/// Fonts every mail client has; Outlook doesn't pass fonts into tables, so
+/// each cell names them again
+const EMAIL_FONT: &str = "font-family:Arial,Helvetica,sans-serif;";
+
+const EMAIL_MONO_FONT: &str = "font-family:Consolas,Menlo,monospace;";
 
 pub fn mdast_to_html(
     node: &Node,
@@ -13,29 +55,278 @@ pub fn mdast_to_html(
     strict: bool,
     highlight: Option<&HighlightContext>,
     image_cache: &ImageCache,
-    prosemirror: bool,
+    options: &HtmlOptions,
 ) -> Result<String, ImageError> {
-    let ctx = HtmlContext::new(
+    let mut ctx = HtmlContext::new(
         base_dir,
         image_config,
         strict,
         highlight,
         image_cache,
-        prosemirror,
+        options,
     );
+    ctx.definitions = Definitions::collect(node);
+    if options.heading_ids {
+        ctx.anchors = Anchors::collect(node);
+    }
     let mut html = String::new();
     node_to_html(node, &mut html, &ctx)?;
+    if options.email {
+        html = email_layout(&html);
+    }
     Ok(html)
 }
 
+/// Write the HTML `mdast_to_html` renders to `out` one top-level block at a
+/// time, so a huge document's output never sits in memory whole. Email
+/// layout and collapsible sections wrap the whole body, so with those the
+/// body is rendered first.
+#[allow(clippy::too_many_arguments)]
+pub fn write_html(
+    node: &Node,
+    base_dir: &Path,
+    image_config: &ImageConfig,
+    strict: bool,
+    highlight: Option<&HighlightContext>,
+    image_cache: &ImageCache,
+    options: &HtmlOptions,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let root = match node {
+        Node::Root(root) if !options.email && options.collapsible_headings.is_none() => root,
+        _ => {
+            let html = mdast_to_html(
+                node,
+                base_dir,
+                image_config,
+                strict,
+                highlight,
+                image_cache,
+                options,
+            )
+            .map_err(io::Error::other)?;
+            return out.write_all(html.as_bytes());
+        }
+    };
+    let mut ctx = HtmlContext::new(
+        base_dir,
+        image_config,
+        strict,
+        highlight,
+        image_cache,
+        options,
+    );
+    ctx.definitions = Definitions::collect(node);
+    if options.heading_ids {
+        ctx.anchors = Anchors::collect(node);
+    }
+    let mut html = String::new();
+    if options.prosemirror {
+        html.push_str("<p data-pm-slice=\"1 1 []\"></p>");
+    }
+    for child in &root.children {
+        node_to_html(child, &mut html, &ctx).map_err(io::Error::other)?;
+        out.write_all(html.as_bytes())?;
+        html.clear();
+    }
+    out.write_all(html.as_bytes())
+}
+
+pub struct HtmlRenderer;
+
+impl HtmlRenderer {
+    fn options(ctx: &render::Context) -> HtmlOptions {
+        HtmlOptions {
+            prosemirror: ctx.cfg.prosemirror,
+            list_style: ctx.cfg.typography.list_style,
+            collapsible_headings: ctx.cfg.collapsible_headings,
+            heading_ids: ctx.cfg.heading_ids,
+            code_badge: ctx.cfg.highlight.badge,
+            code_style: ctx.cfg.html.code_style,
+            code: ctx.cfg.html.code,
+            scroll_code: ctx.cfg.standalone,
+            email: ctx.cfg.email,
+        }
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_html(
+            ast,
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.highlight,
+            ctx.image_cache,
+            &Self::options(ctx),
+        )
+        .map(|html| {
+            if ctx.cfg.standalone {
+                standalone_document(&html, ctx.front_matter)
+            } else {
+                html
+            }
+        })
+        .map(Output::Text)
+        .map_err(io::Error::other)
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn render_to(
+        &self,
+        ast: &Node,
+        ctx: &render::Context,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        let (open, close) = if ctx.cfg.standalone {
+            standalone_parts(ctx.front_matter)
+        } else {
+            (String::new(), "")
+        };
+        out.write_all(open.as_bytes())?;
+        write_html(
+            ast,
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.highlight,
+            ctx.image_cache,
+            &Self::options(ctx),
+            out,
+        )?;
+        out.write_all(close.as_bytes())
+    }
+}
+
+/// Wrap rendered HTML in a complete document, with the front matter's title
+/// and author in its head
+pub fn standalone_document(body: &str, front_matter: Option<&FrontMatter>) -> String {
+    let (open, close) = standalone_parts(front_matter);
+    format!("{}{}{}", open, body, close)
+}
+
+/// What goes before and after the body of a standalone document
+fn standalone_parts(front_matter: Option<&FrontMatter>) -> (String, &'static str) {
+    let mut head = String::from("<meta charset=\"utf-8\">\n");
+    if let Some(title) = front_matter.and_then(|f| f.title.as_deref()) {
+        head.push_str(&format!("<title>{}</title>\n", html_escape(title)));
+    }
+    if let Some(author) = front_matter.and_then(|f| f.author.as_deref()) {
+        head.push_str(&format!(
+            "<meta name=\"author\" content=\"{}\">\n",
+            html_escape(author)
+        ));
+    }
+    (
+        format!("<!DOCTYPE html>\n<html>\n<head>\n{}</head>\n<body>\n", head),
+        "</body>\n</html>\n",
+    )
+}
+
+/// Center `body` in a 600px column. Outlook ignores `max-width`, so it gets
+/// a fixed-width table of its own in a conditional comment.
+fn email_layout(body: &str) -> String {
+    format!(
+        "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\">\n\
+         <tr>\n<td align=\"center\">\n\
+         <!--[if mso]><table role=\"presentation\" width=\"{width}\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr><td><![endif]-->\n\
+         <div style=\"max-width:{width}px; margin:0 auto; text-align:left;\">\n\
+         <table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\">\n\
+         <tr>\n<td style=\"{font} font-size:16px; line-height:1.5; color:#24292f;\">\n\
+         {body}</td>\n</tr>\n</table>\n</div>\n\
+         <!--[if mso]></td></tr></table><![endif]-->\n\
+         </td>\n</tr>\n</table>\n",
+        width = EMAIL_WIDTH,
+        font = EMAIL_FONT,
+        body = body
+    )
+}
+
+/// Open a full-width single-cell table with a solid background, which
+/// Outlook draws where it ignores backgrounds on other blocks. `style` adds
+/// to the cell's own.
+fn push_email_box_open(background: &str, style: &str, html: &mut String) {
+    html.push_str(&format!(
+        "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"margin:16px 0;\">\n\
+         <tr>\n<td bgcolor=\"{bg}\" style=\"background-color:{bg}; {style}\">\n",
+        bg = background,
+        style = style
+    ));
+}
+
+fn push_email_box_close(html: &mut String) {
+    html.push_str("</td>\n</tr>\n</table>\n");
+}
+
+/// An embedded image taken out of rendered HTML
+pub struct ExtractedImage {
+    /// What the image's `src` now points at
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Replace the `data:` URLs of embedded images with the names `name` gives
+/// them (from the image's 1-based index and media type), returning the
+/// rewritten HTML and the images. The same image used twice is taken once.
+pub fn extract_images(
+    html: &str,
+    name: impl Fn(usize, &str) -> String,
+) -> (String, Vec<ExtractedImage>) {
+    const MARKER: &str = "src=\"data:";
+    let mut out = String::with_capacity(html.len());
+    let mut images: Vec<ExtractedImage> = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(MARKER) {
+        let url_start = start + MARKER.len() - "data:".len();
+        let Some(len) = rest[url_start..].find('"') else {
+            break;
+        };
+        let url = &rest[url_start..url_start + len];
+        let index = match seen.get(url) {
+            Some(&index) => Some(index),
+            None => decode_data_url(url).map(|(mime_type, data)| {
+                images.push(ExtractedImage {
+                    name: name(images.len() + 1, &mime_type),
+                    mime_type,
+                    data,
+                });
+                seen.insert(url, images.len() - 1);
+                images.len() - 1
+            }),
+        };
+        out.push_str(&rest[..url_start]);
+        match index {
+            Some(index) => out.push_str(&images[index].name),
+            None => out.push_str(url),
+        }
+        rest = &rest[url_start + len..];
+    }
+    out.push_str(rest);
+    (out, images)
+}
+
+/// The media type and bytes of a base64 `data:` URL
+fn decode_data_url(url: &str) -> Option<(String, Vec<u8>)> {
+    let (mime_type, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+    Some((mime_type.to_string(), STANDARD.decode(data).ok()?))
+}
+
 struct HtmlContext<'a> {
     base_dir: &'a Path,
     image_config: &'a ImageConfig,
     strict: bool,
     highlight: Option<&'a HighlightContext>,
     image_cache: &'a ImageCache,
-    /// Emit ProseMirror slice marker for Confluence/ProseMirror paste compatibility
-    prosemirror: bool,
+    options: &'a HtmlOptions,
+    definitions: Definitions<'a>,
+    /// Heading ids (none without `heading_ids`)
+    anchors: Anchors,
 }
 
 impl<'a> HtmlContext<'a> {
@@ -45,7 +336,7 @@ impl<'a> HtmlContext<'a> {
         strict: bool,
         highlight: Option<&'a HighlightContext>,
         image_cache: &'a ImageCache,
-        prosemirror: bool,
+        options: &'a HtmlOptions,
     ) -> Self {
         Self {
             base_dir,
@@ -53,38 +344,189 @@ impl<'a> HtmlContext<'a> {
             strict,
             highlight,
             image_cache,
-            prosemirror,
+            options,
+            definitions: Definitions::default(),
+            anchors: Anchors::default(),
         }
     }
 }
 
+/// A callout box with a colored left border, tinted background and title,
+/// styled inline so it survives pasting
+fn alert_to_html(
+    kind: AlertKind,
+    children: &[Node],
+    html: &mut String,
+    ctx: &HtmlContext,
+) -> Result<(), ImageError> {
+    let (r, g, b) = kind.color();
+    let color = format!("#{:02x}{:02x}{:02x}", r, g, b);
+    if ctx.options.email {
+        // Outlook has no alpha colors, so the tint is mixed with white
+        let tint = |c: u8| (c as u16 * 26 + 255 * 230) / 256;
+        let background = format!("#{:02x}{:02x}{:02x}", tint(r), tint(g), tint(b));
+        push_email_box_open(
+            &background,
+            &format!(
+                "border-left:4px solid {}; padding:8px 16px; {}",
+                color, EMAIL_FONT
+            ),
+            html,
+        );
+        html.push_str(&format!(
+            "<p style=\"color:{color}; font-weight:bold; margin:0 0 8px 0;\">{}</p>\n",
+            kind.label()
+        ));
+        for child in children {
+            node_to_html(child, html, ctx)?;
+        }
+        push_email_box_close(html);
+        return Ok(());
+    }
+    html.push_str(&format!(
+        "<div class=\"markdown-alert markdown-alert-{}\" style=\"border-left:4px solid {color}; background-color:{color}1a; padding:8px 16px; margin:16px 0;\">\n",
+        kind.label().to_lowercase()
+    ));
+    html.push_str(&format!(
+        "<p style=\"color:{color}; font-weight:bold; margin:0 0 8px 0;\">{}</p>\n",
+        kind.label()
+    ));
+    for child in children {
+        node_to_html(child, html, ctx)?;
+    }
+    html.push_str("</div>\n");
+    Ok(())
+}
+
+fn link_to_html(
+    url: &str,
+    children: &[Node],
+    html: &mut String,
+    ctx: &HtmlContext,
+) -> Result<(), ImageError> {
+    let url = ctx.anchors.resolve_url(url);
+    html.push_str(&format!("<a href=\"{}\">", html_escape(&url)));
+    for child in children {
+        node_to_html(child, html, ctx)?;
+    }
+    html.push_str("</a>");
+    Ok(())
+}
+
+fn image_to_html(
+    url: &str,
+    alt: &str,
+    html: &mut String,
+    ctx: &HtmlContext,
+) -> Result<(), ImageError> {
+    let img = ctx
+        .image_cache
+        .get_or_load(url, ctx.base_dir, ctx.image_config, ctx.strict)?;
+    let size = img
+        .as_ref()
+        .filter(|_| ctx.options.email)
+        .and_then(|i| image::dimensions(i).ok());
+    let src = img
+        .map(|i| i.to_data_url())
+        .unwrap_or_else(|| url.to_string());
+    let alt = if !alt.is_empty() { alt } else { url };
+    if ctx.options.email {
+        // Outlook sizes images by their attributes alone and ignores
+        // `max-width`, so they're scaled down to the column up front
+        html.push_str(&format!(
+            "<img src=\"{}\" alt=\"{}\"",
+            html_escape(&src),
+            html_escape(alt)
+        ));
+        if let Some((width, height)) = size.filter(|(width, _)| *width > 0) {
+            let shown = width.min(EMAIL_WIDTH);
+            html.push_str(&format!(
+                " width=\"{}\" height=\"{}\"",
+                shown,
+                height * shown / width
+            ));
+        }
+        html.push_str(
+            " border=\"0\" style=\"border:0; outline:none; max-width:100%; height:auto;\" />",
+        );
+        return Ok(());
+    }
+    html.push_str(&format!(
+        "<img src=\"{}\" alt=\"{}\" />",
+        html_escape(&src),
+        html_escape(alt)
+    ));
+    Ok(())
+}
+
+/// Open the span giving an added or removed line of a diff its
+/// full-width background
+fn push_diff_line_open(background: Color, html: &mut String) {
+    html.push_str(&format!(
+        "<span style=\"display:inline-block; min-width:100%; background-color:#{:02x}{:02x}{:02x};\">",
+        background.r, background.g, background.b
+    ));
+}
+
 fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(), ImageError> {
     match node {
         Node::Root(root) => {
-            if ctx.prosemirror {
+            if ctx.options.prosemirror {
                 // Signals valid block-level paste to ProseMirror-based editors (Confluence)
                 html.push_str("<p data-pm-slice=\"1 1 []\"></p>");
             }
-            for child in &root.children {
-                node_to_html(child, html, ctx)?;
+            // Mail clients don't open `<details>`
+            match ctx
+                .options
+                .collapsible_headings
+                .filter(|_| !ctx.options.email)
+            {
+                Some(level) => collapsible_sections(&root.children, level, html, ctx)?,
+                None => {
+                    for child in &root.children {
+                        node_to_html(child, html, ctx)?;
+                    }
+                }
             }
         }
         Node::Heading(heading) => {
-            html.push_str(&format!("<h{}>", heading.depth));
+            match ctx.anchors.next() {
+                Some(slug) => {
+                    html.push_str(&format!("<h{} id=\"{}\"", heading.depth, html_escape(slug)))
+                }
+                None => html.push_str(&format!("<h{}", heading.depth)),
+            }
+            if ctx.options.email {
+                let size = [28, 24, 20, 18, 16, 14][(heading.depth as usize).clamp(1, 6) - 1];
+                html.push_str(&format!(
+                    " style=\"{} font-size:{}px; line-height:1.25; margin:24px 0 16px 0;\"",
+                    EMAIL_FONT, size
+                ));
+            }
+            html.push('>');
             for child in &heading.children {
                 node_to_html(child, html, ctx)?;
             }
             html.push_str(&format!("</h{}>\n", heading.depth));
         }
         Node::Paragraph(para) => {
-            html.push_str("<p>");
+            // Outlook's paragraphs have no spacing of their own
+            html.push_str(if ctx.options.email {
+                "<p style=\"margin:0 0 16px 0;\">"
+            } else {
+                "<p>"
+            });
             for child in &para.children {
                 node_to_html(child, html, ctx)?;
             }
             html.push_str("</p>\n");
         }
         Node::Text(text) => {
-            html.push_str(&html_escape(&text.value));
+            if ctx.image_config.emoji_images == EmojiImages::All {
+                push_text_with_emoji_glyphs(html, &text.value, ctx)?;
+            } else {
+                html.push_str(&html_escape(&text.value));
+            }
         }
         Node::Strong(strong) => {
             html.push_str("<strong>");
@@ -101,47 +543,113 @@ fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(),
             html.push_str("</em>");
         }
         Node::InlineCode(code) => {
-            html.push_str("<code>");
+            if ctx.options.email {
+                html.push_str(&format!(
+                    "<code style=\"{} font-size:90%; background-color:#eff1f3; padding:1px 4px;\">",
+                    EMAIL_MONO_FONT
+                ));
+            } else {
+                html.push_str("<code>");
+            }
             html.push_str(&html_escape(&code.value));
             html.push_str("</code>");
         }
         Node::Code(code) => {
-            // <pre data-language="..."> for ProseMirror/Confluence
-            // <code class="language-..."> for Google Docs
-            html.push_str("<pre");
-            if let Some(lang) = &code.lang {
-                html.push_str(&format!(" data-language=\"{}\"", html_escape(lang)));
-            }
-
-            if let Some(hl) = ctx.highlight {
-                let syntax = code
-                    .lang
-                    .as_ref()
-                    .map(|lang| hl.find_syntax(lang))
-                    .unwrap_or_else(|| hl.syntax_set.find_syntax_plain_text());
-
-                // Get background color from theme
-                let bg_color = hl
-                    .theme
+            let lines = CodeLines::new(code.lang.as_deref(), &code.value);
+            let style = ctx.options.code_style;
+            // Classed markup leaves coloring to the app pasted into
+            let highlight = ctx.highlight.filter(|_| style != CodeStyle::PreClasses);
+            let background = highlight.map(|hl| {
+                hl.theme
                     .settings
                     .background
                     .map(|c| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b))
-                    .unwrap_or_else(|| "#2b303b".to_string());
+                    .unwrap_or_else(|| "#2b303b".to_string())
+            });
+            let foreground = match highlight {
+                Some(hl) => hl
+                    .theme
+                    .settings
+                    .foreground
+                    .map(|c| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b))
+                    .unwrap_or_else(|| "#c0c5ce".to_string()),
+                None => "#57606a".to_string(),
+            };
+            if ctx.options.email {
+                let background = background.as_deref().unwrap_or("#f6f8fa");
+                push_email_box_open(background, "padding:16px;", html);
+            }
 
+            // The surrounding email box has the background and padding
+            let font = if ctx.options.email {
+                format!(" {} font-size:13px;", EMAIL_MONO_FONT)
+            } else if highlight.is_some() {
+                " font-family:monospace,monospace; font-size:14px;".to_string()
+            } else {
+                String::new()
+            };
+            let layout = code_layout_css(ctx.options);
+            let boxed = background.as_deref().filter(|_| !ctx.options.email);
+            let pre_style = if style == CodeStyle::Table {
+                let boxed = boxed.map(|bg| (bg, "16px", "16px 0 16px 16px"));
+                let (padding, numbers_padding) = boxed.map_or(("0", "0 12px 0 0"), |b| (b.1, b.2));
+                html.push_str("<table style=\"border-collapse:collapse;");
+                if let Some((bg, ..)) = boxed {
+                    html.push_str(&format!(" background-color:{}; border-radius:8px;", bg));
+                }
+                html.push_str("\"><tr>");
+                push_line_numbers(
+                    lines.lines.len(),
+                    &format!(
+                        "padding:{}; vertical-align:top; text-align:right; color:{}; opacity:0.5; user-select:none;",
+                        numbers_padding, foreground
+                    ),
+                    &format!("margin:0;{}", font),
+                    html,
+                );
                 html.push_str(&format!(
-                    " style=\"background-color:{}; padding:16px; font-family:monospace,monospace; font-size:14px; border-radius:8px;\">",
-                    bg_color
+                    "<td style=\"padding:{}; vertical-align:top;\">",
+                    padding
                 ));
-                html.push_str("<code");
-                if let Some(lang) = &code.lang {
-                    html.push_str(&format!(" class=\"language-{}\"", html_escape(lang)));
-                }
-                html.push('>');
+                format!("margin:0;{}{}", font, layout)
+            } else if ctx.options.email {
+                format!("margin:0;{}{}", font, layout)
+            } else if let Some(bg) = boxed {
+                format!(
+                    "background-color:{}; padding:16px;{} border-radius:8px;{}",
+                    bg, font, layout
+                )
+            } else {
+                layout.trim_start().to_string()
+            };
 
-                let mut highlighter = HighlightLines::new(syntax, &hl.theme);
-                let lines: Vec<&str> = LinesWithEndings::from(&code.value).collect();
-                for (i, line) in lines.iter().enumerate() {
-                    if let Ok(ranges) = highlighter.highlight_line(line, &hl.syntax_set) {
+            // <pre data-language="..."> for ProseMirror/Confluence
+            // <code class="language-..."> for Google Docs
+            html.push_str("<pre");
+            if let Some(lang) = lines.lang {
+                html.push_str(&format!(" data-language=\"{}\"", html_escape(lang)));
+            }
+            if !pre_style.is_empty() {
+                html.push_str(&format!(" style=\"{}\"", pre_style));
+            }
+            html.push('>');
+            if let Some(lang) = lines.lang {
+                push_code_badge(ctx.options.code_badge, lang, &foreground, html);
+            }
+            html.push_str("<code");
+            if let Some(lang) = lines.lang {
+                html.push_str(&format!(" class=\"language-{}\"", html_escape(lang)));
+            }
+            html.push('>');
+
+            if let Some(hl) = highlight {
+                let mut highlighter = hl.highlighter(lines.lang, &code.value);
+                let count = lines.lines.len();
+                for (i, &(line, change)) in lines.lines.iter().enumerate() {
+                    if let Some(change) = change {
+                        push_diff_line_open(change.background(hl.background()), html);
+                    }
+                    if let Ok(ranges) = highlighter.highlight_line(line) {
                         for (style, text) in ranges {
                             // Skip rendering the trailing newline character
                             let text = text.trim_end_matches('\n');
@@ -161,53 +669,71 @@ fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(),
                     } else {
                         html.push_str(&html_escape(line.trim_end_matches('\n')));
                     }
-                    if i < lines.len() - 1 {
+                    if change.is_some() {
+                        html.push_str("</span>");
+                    }
+                    if i < count - 1 {
                         html.push('\n');
                     }
                 }
-
-                html.push_str("</code></pre>\n");
             } else {
-                html.push_str("><code");
-                if let Some(lang) = &code.lang {
-                    html.push_str(&format!(" class=\"language-{}\"", html_escape(lang)));
+                for &(line, change) in &lines.lines {
+                    let text = line.strip_suffix('\n').unwrap_or(line);
+                    match change {
+                        Some(change) => {
+                            push_diff_line_open(
+                                change.background(highlight::PLAIN_BACKGROUND),
+                                html,
+                            );
+                            html.push_str(&html_escape(text));
+                            html.push_str("</span>");
+                        }
+                        None => html.push_str(&html_escape(text)),
+                    }
+                    if text.len() < line.len() {
+                        html.push('\n');
+                    }
                 }
-                html.push('>');
-                html.push_str(&html_escape(&code.value));
-                html.push_str("</code></pre>\n");
             }
-        }
-        Node::Link(link) => {
-            html.push_str(&format!("<a href=\"{}\">", html_escape(&link.url)));
-            for child in &link.children {
-                node_to_html(child, html, ctx)?;
+            html.push_str("</code></pre>");
+            if style == CodeStyle::Table {
+                html.push_str("</td></tr></table>");
+            }
+            html.push('\n');
+            if ctx.options.email {
+                push_email_box_close(html);
             }
-            html.push_str("</a>");
-        }
-        Node::Image(image) => {
-            let img = ctx.image_cache.get_or_load(
-                &image.url,
-                ctx.base_dir,
-                ctx.image_config,
-                ctx.strict,
-            )?;
-            let src = img
-                .map(|i| i.to_data_url())
-                .unwrap_or_else(|| image.url.clone());
-            let alt = if !image.alt.is_empty() {
-                &image.alt
-            } else {
-                &image.url
-            };
-            html.push_str(&format!(
-                "<img src=\"{}\" alt=\"{}\" />",
-                html_escape(&src),
-                html_escape(alt)
-            ));
         }
+        Node::Link(link) => link_to_html(&link.url, &link.children, html, ctx)?,
+        Node::LinkReference(linkref) => match ctx.definitions.get(&linkref.identifier) {
+            Some(url) => link_to_html(url, &linkref.children, html, ctx)?,
+            None => {
+                for child in &linkref.children {
+                    node_to_html(child, html, ctx)?;
+                }
+            }
+        },
+        Node::Image(image) => image_to_html(&image.url, &image.alt, html, ctx)?,
+        Node::ImageReference(imgref) => match ctx.definitions.get(&imgref.identifier) {
+            Some(url) => image_to_html(url, &imgref.alt, html, ctx)?,
+            None => html.push_str(&html_escape(&imgref.alt)),
+        },
         Node::List(list) => {
             let tag = if list.ordered { "ol" } else { "ul" };
-            html.push_str(&format!("<{}>\n", tag));
+            html.push('<');
+            html.push_str(tag);
+            if list.ordered {
+                if let Some(start) = list.start.filter(|&n| n != 1) {
+                    html.push_str(&format!(" start=\"{}\"", start));
+                }
+                // 1) has no HTML equivalent and renders as decimal
+                match ctx.options.list_style {
+                    ListStyle::LowerAlpha => html.push_str(" type=\"a\""),
+                    ListStyle::LowerRoman => html.push_str(" type=\"i\""),
+                    ListStyle::Decimal | ListStyle::DecimalParen => {}
+                }
+            }
+            html.push_str(">\n");
             for child in &list.children {
                 if let Node::ListItem(item) = child {
                     html.push_str("<li>");
@@ -236,6 +762,9 @@ fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(),
         Node::ListItem(_) => {
             // ListItem is handled inline in List for tight/loose list support
         }
+        Node::Blockquote(bq) if let Some((kind, children)) = alerts::alert(bq) => {
+            alert_to_html(kind, &children, html, ctx)?;
+        }
         Node::Blockquote(bq) => {
             html.push_str("<blockquote>\n");
             for child in &bq.children {
@@ -258,7 +787,15 @@ fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(),
         }
         Node::Table(table) => {
             // Use old-school HTML attributes for email/paste compatibility
-            html.push_str("<table border=\"0\" cellpadding=\"8\" cellspacing=\"0\">\n<thead>\n");
+            if ctx.options.email {
+                html.push_str(
+                    "<table border=\"0\" cellpadding=\"8\" cellspacing=\"0\" style=\"border-collapse:collapse; margin:0 0 16px 0;\">\n<thead>\n",
+                );
+            } else {
+                html.push_str(
+                    "<table border=\"0\" cellpadding=\"8\" cellspacing=\"0\">\n<thead>\n",
+                );
+            }
             if let Some(first_row) = table.children.first() {
                 render_table_row(first_row, html, &table.align, true, ctx)?;
             }
@@ -276,6 +813,118 @@ fn node_to_html(node: &Node, html: &mut String, ctx: &HtmlContext) -> Result<(),
     Ok(())
 }
 
+/// Render top-level blocks, opening a `<details>` for every heading at
+/// `level` or deeper with the heading as its `<summary>`. A section runs
+/// until the next heading of the same or a higher rank.
+fn collapsible_sections(
+    children: &[Node],
+    level: u8,
+    html: &mut String,
+    ctx: &HtmlContext,
+) -> Result<(), ImageError> {
+    let mut open: Vec<u8> = Vec::new();
+    for child in children {
+        if let Node::Heading(heading) = child {
+            while open.last().is_some_and(|&depth| depth >= heading.depth) {
+                open.pop();
+                html.push_str("</details>\n");
+            }
+            if heading.depth >= level {
+                let mut summary = String::new();
+                node_to_html(child, &mut summary, ctx)?;
+                html.push_str("<details>\n<summary>");
+                html.push_str(summary.trim_end());
+                html.push_str("</summary>\n");
+                open.push(heading.depth);
+                continue;
+            }
+        }
+        node_to_html(child, html, ctx)?;
+    }
+    for _ in open {
+        html.push_str("</details>\n");
+    }
+    Ok(())
+}
+
+/// Push text with each emoji replaced by an inline glyph image sized to the
+/// surrounding text, keeping the emoji itself when its glyph can't be loaded
+fn push_text_with_emoji_glyphs(
+    html: &mut String,
+    text: &str,
+    ctx: &HtmlContext,
+) -> Result<(), ImageError> {
+    for segment in emoji::segments(text) {
+        match segment {
+            Segment::Text(s) => html.push_str(&html_escape(s)),
+            Segment::Emoji(e) => {
+                match emoji::load_glyph(
+                    e,
+                    ctx.base_dir,
+                    ctx.image_config,
+                    ctx.strict,
+                    ctx.image_cache,
+                )? {
+                    Some(glyph) => html.push_str(&format!(
+                        "<img src=\"{}\" alt=\"{}\" style=\"height:1.2em;width:1.2em;vertical-align:-0.2em\">",
+                        glyph.to_data_url(),
+                        e
+                    )),
+                    None => html.push_str(e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Declarations, each after a space, that wrap or keep long lines of code
+/// and cap the block's width
+fn code_layout_css(options: &HtmlOptions) -> String {
+    let mut css = String::new();
+    match options.code.wrap {
+        Some(true) => css.push_str(" white-space:pre-wrap; overflow-wrap:anywhere;"),
+        Some(false) if options.scroll_code => css.push_str(" white-space:pre; overflow-x:auto;"),
+        Some(false) => css.push_str(" white-space:pre;"),
+        None => {}
+    }
+    if let Some(width) = options.code.max_width {
+        css.push_str(&format!(" max-width:{}ch;", width));
+    }
+    css
+}
+
+/// The language label of a code block, in the block's text color
+/// Cell numbering the lines of a code block laid out as a table
+fn push_line_numbers(count: usize, cell_style: &str, pre_style: &str, html: &mut String) {
+    html.push_str(&format!(
+        "<td style=\"{}\"><pre style=\"{}\">",
+        cell_style, pre_style
+    ));
+    let numbers: Vec<String> = (1..=count).map(|n| n.to_string()).collect();
+    html.push_str(&numbers.join("\n"));
+    html.push_str("</pre></td>");
+}
+
+fn push_code_badge(badge: CodeBadge, lang: &str, color: &str, html: &mut String) {
+    const LABEL: &str = "font-family:sans-serif; font-size:11px; opacity:0.7;";
+    match badge {
+        CodeBadge::None => {}
+        CodeBadge::Corner => html.push_str(&format!(
+            "<span style=\"float:right; margin-left:8px; {} color:{};\">{}</span>",
+            LABEL,
+            color,
+            html_escape(lang)
+        )),
+        CodeBadge::Header => html.push_str(&format!(
+            "<div style=\"{} color:{}; margin-bottom:8px;\">{}</div>",
+            LABEL,
+            color,
+            html_escape(lang)
+        )),
+    }
+}
+
 fn render_table_row(
     node: &Node,
     html: &mut String,
@@ -294,7 +943,14 @@ fn render_table_row(
                 _ => "",
             };
             // Use nowrap attribute (deprecated but widely supported) for paste compatibility
-            html.push_str(&format!("<{}{} nowrap>", tag, align_attr));
+            if ctx.options.email {
+                html.push_str(&format!(
+                    "<{}{} nowrap style=\"border:1px solid #d0d7de; {}\">",
+                    tag, align_attr, EMAIL_FONT
+                ));
+            } else {
+                html.push_str(&format!("<{}{} nowrap>", tag, align_attr));
+            }
             if let Node::TableCell(cell) = cell {
                 for child in &cell.children {
                     node_to_html(child, html, ctx)?;
@@ -307,7 +963,7 @@ fn render_table_row(
     Ok(())
 }
 
-fn html_escape(s: &str) -> String {
+pub fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -331,6 +987,10 @@ mod tests {
     }
 
     fn render_html(md: &str) -> String {
+        render_html_with(md, &HtmlOptions::default())
+    }
+
+    fn render_html_with(md: &str, options: &HtmlOptions) -> String {
         let ast = parse_markdown(md);
         let cache = crate::image::ImageCache::new();
         let image_config = crate::config::ImageConfig {
@@ -340,6 +1000,7 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         };
         mdast_to_html(
             &ast,
@@ -348,7 +1009,7 @@ mod tests {
             false,
             None,
             &cache,
-            false,
+            options,
         )
         .unwrap()
     }
@@ -373,6 +1034,119 @@ mod tests {
         assert_eq!(render_html("###### Heading 6"), "<h6>Heading 6</h6>\n");
     }
 
+    #[test]
+    fn test_heading_ids_and_fragment_links() {
+        let options = HtmlOptions {
+            heading_ids: true,
+            ..Default::default()
+        };
+        let html = render_html_with(
+            "# Release Notes!\n\n## Setup\n\n## Setup\n\n\
+             [a](#Release-Notes) [b](#setup-1) [c](#release%20notes) [d](#missing)",
+            &options,
+        );
+        assert!(html.contains("<h1 id=\"release-notes\">Release Notes!</h1>"));
+        assert!(html.contains("<h2 id=\"setup\">Setup</h2>\n<h2 id=\"setup-1\">"));
+        assert!(html.contains("<a href=\"#release-notes\">a</a>"));
+        assert!(html.contains("<a href=\"#setup-1\">b</a>"));
+        assert!(html.contains("<a href=\"#release-notes\">c</a>"));
+        assert!(html.contains("<a href=\"#missing\">d</a>"));
+    }
+
+    #[test]
+    fn test_email_layout() {
+        let options = HtmlOptions {
+            email: true,
+            collapsible_headings: Some(2),
+            ..Default::default()
+        };
+        let html = render_html_with(
+            "## Title\n\nText with `code`.\n\n```\nx\n```\n\n> [!NOTE]\n> Careful.\n\n| a |\n|---|\n| 1 |\n",
+            &options,
+        );
+        // A 600px column, with a fixed-width table for Outlook
+        assert!(html.starts_with("<table role=\"presentation\" width=\"100%\""));
+        assert!(html.contains("<!--[if mso]><table role=\"presentation\" width=\"600\""));
+        assert!(html.contains("max-width:600px"));
+        assert!(!html.contains("<details>"));
+        assert!(html.contains(
+            "<p style=\"margin:0 0 16px 0;\">Text with <code style=\"font-family:Consolas"
+        ));
+        // Code blocks and alerts are boxes with solid backgrounds
+        assert!(html.contains("<td bgcolor=\"#f6f8fa\" style=\"background-color:#f6f8fa; padding:16px;\">\n<pre style=\"margin:0;"));
+        assert!(html.contains("<td bgcolor=\"#e6effb\" style=\"background-color:#e6effb; border-left:4px solid #0969da;"));
+        assert!(!html.contains("#0969da1a"));
+        assert!(html.contains("<th nowrap style=\"border:1px solid #d0d7de;"));
+    }
+
+    #[test]
+    fn test_extract_images() {
+        let html = "<img src=\"data:image/png;base64,AQID\" /><img src=\"data:image/png;base64,AQID\" />\
+                    <img src=\"https://x.io/a.png\" />";
+        let (body, images) = extract_images(html, |index, _| format!("image{}.png", index));
+        assert_eq!(
+            body,
+            "<img src=\"image1.png\" /><img src=\"image1.png\" />\
+             <img src=\"https://x.io/a.png\" />"
+        );
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].mime_type, "image/png");
+        assert_eq!(images[0].data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_collapsible_headings() {
+        let options = HtmlOptions {
+            collapsible_headings: Some(2),
+            ..Default::default()
+        };
+        let html = render_html_with(
+            "# Runbook\n\nIntro\n\n## Setup\n\nA\n\n### Detail\n\nB\n\n## Deploy\n\nC\n\n# Next",
+            &options,
+        );
+        assert_eq!(
+            html,
+            "<h1>Runbook</h1>\n<p>Intro</p>\n\
+             <details>\n<summary><h2>Setup</h2></summary>\n<p>A</p>\n\
+             <details>\n<summary><h3>Detail</h3></summary>\n<p>B</p>\n</details>\n</details>\n\
+             <details>\n<summary><h2>Deploy</h2></summary>\n<p>C</p>\n</details>\n\
+             <h1>Next</h1>\n"
+        );
+    }
+
+    #[test]
+    fn test_emoji_glyphs_only_for_all() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        std::fs::write(temp_dir.path().join("2764.png"), png).unwrap();
+
+        let render = |emoji_images| {
+            let ast = parse_markdown("I ❤️ it");
+            let image_config = ImageConfig {
+                optimize_local: false,
+                emoji_images,
+                emoji_base_url: temp_dir.path().display().to_string(),
+                ..ImageConfig::default()
+            };
+            mdast_to_html(
+                &ast,
+                Path::new("."),
+                &image_config,
+                false,
+                None,
+                &crate::image::ImageCache::new(),
+                &HtmlOptions::default(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(render(EmojiImages::Rtf), "<p>I ❤️ it</p>\n");
+        assert!(
+            render(EmojiImages::All)
+                .starts_with("<p>I <img src=\"data:image/png;base64,iVBORw0KGgo=\" alt=\"❤️\"")
+        );
+    }
+
     #[test]
     fn test_paragraph() {
         assert_eq!(render_html("Hello world"), "<p>Hello world</p>\n");
@@ -425,27 +1199,118 @@ mod tests {
     }
 
     #[test]
-    fn test_prosemirror_slice_marker() {
-        let ast = parse_markdown("```js\nconsole.log('hello')\n```");
-        let cache = crate::image::ImageCache::new();
-        let image_config = crate::config::ImageConfig {
-            embed_local: false,
-            embed_remote: false,
-            optimize_local: false,
-            optimize_remote: false,
-            max_dimension: 1200,
-            quality: 80,
+    fn test_diff_code_block() {
+        let html = render_html("```diff\n-old\n+new\n same\n```");
+        assert!(
+            html.contains(
+                "<code class=\"language-diff\"><span style=\"display:inline-block; min-width:100%; \
+             background-color:#f7cece;\">-old</span>\n<span style=\"display:inline-block; \
+             min-width:100%; background-color:#c4e2cc;\">+new</span>\n same</code>"
+            ),
+            "{}",
+            html
+        );
+
+        let html = render_html("```rust,diff\n-a();\n b();\n```");
+        assert!(html.contains("data-language=\"rust\""), "{}", html);
+        assert!(html.contains(">a();</span>\nb();</code>"), "{}", html);
+    }
+
+    #[test]
+    fn test_code_badge() {
+        let with_badge = |code_badge| {
+            let options = HtmlOptions {
+                code_badge,
+                ..Default::default()
+            };
+            render_html_with("```rust\nfn main() {}\n```\n\n```\nplain\n```", &options)
         };
-        let html = mdast_to_html(
-            &ast,
-            Path::new("."),
-            &image_config,
-            false,
-            None,
-            &cache,
-            true,
+        let corner = with_badge(CodeBadge::Corner);
+        assert!(corner.contains("<pre data-language=\"rust\"><span style=\"float:right;"));
+        assert!(corner.contains(">rust</span><code class=\"language-rust\">"));
+        // Blocks without a language get no badge
+        assert!(corner.contains("<pre><code>plain"));
+        assert!(with_badge(CodeBadge::Header).contains("margin-bottom:8px;\">rust</div><code"));
+        assert!(!with_badge(CodeBadge::None).contains(">rust<"));
+    }
+
+    #[test]
+    fn test_code_layout() {
+        let with_layout = |wrap, max_width, scroll_code| {
+            let options = HtmlOptions {
+                code: CodeLayout { wrap, max_width },
+                scroll_code,
+                ..Default::default()
+            };
+            render_html_with("```\nplain\n```", &options)
+        };
+        assert!(with_layout(None, None, true).contains("<pre><code>plain"));
+        assert!(with_layout(Some(true), Some(80), false).contains(
+            "<pre style=\"white-space:pre-wrap; overflow-wrap:anywhere; max-width:80ch;\"><code>"
+        ));
+        assert!(with_layout(Some(false), None, false).contains("<pre style=\"white-space:pre;\">"));
+        // Standalone pages scroll long lines instead
+        assert!(
+            with_layout(Some(false), None, true)
+                .contains("<pre style=\"white-space:pre; overflow-x:auto;\">")
+        );
+    }
+
+    #[test]
+    fn test_code_style() {
+        let hl = HighlightContext::new(
+            "base16-ocean.dark",
+            &std::collections::HashMap::new(),
+            &std::collections::HashMap::new(),
+            &[],
+            &[],
         )
         .unwrap();
+        let ast = parse_markdown("```rust\nfn main() {}\nmain();\n```");
+        let with_style = |code_style| {
+            let options = HtmlOptions {
+                code_style,
+                ..Default::default()
+            };
+            let cache = crate::image::ImageCache::new();
+            let image_config = ImageConfig::default();
+            mdast_to_html(
+                &ast,
+                Path::new("."),
+                &image_config,
+                false,
+                Some(&hl),
+                &cache,
+                &options,
+            )
+            .unwrap()
+        };
+
+        let inline = with_style(CodeStyle::DivInline);
+        assert!(inline.contains("<pre data-language=\"rust\" style=\"background-color:#2b303b;"));
+        assert!(inline.contains("<span style=\"color:"));
+
+        // Classes only, for the app pasted into to color
+        assert_eq!(
+            with_style(CodeStyle::PreClasses),
+            "<pre data-language=\"rust\"><code class=\"language-rust\">fn main() {}\nmain();</code></pre>\n"
+        );
+
+        let table = with_style(CodeStyle::Table);
+        assert!(table.starts_with(
+            "<table style=\"border-collapse:collapse; background-color:#2b303b; border-radius:8px;\"><tr><td"
+        ));
+        assert!(table.contains(">1\n2</pre></td><td style=\"padding:16px; vertical-align:top;\"><pre data-language=\"rust\" style=\"margin:0;"));
+        assert!(table.ends_with("</code></pre></td></tr></table>\n"));
+    }
+
+    #[test]
+    fn test_prosemirror_slice_marker() {
+        let options = HtmlOptions {
+            prosemirror: true,
+            ..Default::default()
+        };
+        let html = render_html_with("```js\nconsole.log('hello')\n```", &options);
         assert!(html.starts_with("<p data-pm-slice=\"1 1 []\"></p>"));
     }
 
@@ -457,6 +1322,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reference_link_and_image() {
+        let html = render_html(
+            "[docs][Docs] ![logo][logo] [no][missing]\n\n[docs]: https://example.com\n[logo]: logo.png",
+        );
+        assert!(html.contains("<a href=\"https://example.com\">docs</a>"));
+        assert!(html.contains("<img src=\"logo.png\" alt=\"logo\" />"));
+        assert!(html.contains("[no][missing]"));
+    }
+
+    #[test]
+    fn test_standalone_document() {
+        let front_matter = FrontMatter {
+            title: Some("A & B".to_string()),
+            author: Some("Ada".to_string()),
+            ..FrontMatter::default()
+        };
+        let html = standalone_document("<p>x</p>\n", Some(&front_matter));
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n"));
+        assert!(
+            html.contains(
+                "<title>A &amp; B</title>\n<meta name=\"author\" content=\"Ada\">\n</head>"
+            )
+        );
+        assert!(html.ends_with("<body>\n<p>x</p>\n</body>\n</html>\n"));
+        assert!(!standalone_document("", None).contains("<title>"));
+    }
+
+    #[test]
+    fn test_alert_callout() {
+        let html = render_html("> [!NOTE]\n> Read **this**.");
+        assert!(html.starts_with(
+            "<div class=\"markdown-alert markdown-alert-note\" style=\"border-left:4px solid #0969da; background-color:#0969da1a;"
+        ));
+        assert!(html.contains(">Note</p>\n<p>Read <strong>this</strong>.</p>\n</div>\n"));
+        assert!(!html.contains("[!NOTE]"));
+        assert!(!html.contains("<blockquote>"));
+    }
+
     #[test]
     fn test_link_escapes_url() {
         let html = render_html("[link](https://example.com?a=1&b=2)");
@@ -485,6 +1389,26 @@ mod tests {
         assert!(html.contains("</ol>"));
     }
 
+    #[test]
+    fn test_ordered_list_start() {
+        let html = render_html("3. third\n4. fourth");
+        assert!(html.contains("<ol start=\"3\">"));
+    }
+
+    #[test]
+    fn test_ordered_list_style() {
+        let options = HtmlOptions {
+            list_style: ListStyle::LowerRoman,
+            ..Default::default()
+        };
+        let html = render_html_with("1. first\n2. second", &options);
+        assert!(html.contains("<ol type=\"i\">"));
+
+        // Unordered lists are unaffected
+        let html = render_html_with("- item", &options);
+        assert!(html.contains("<ul>"));
+    }
+
     #[test]
     fn test_blockquote() {
         let html = render_html("> quoted text");
@@ -587,4 +1511,44 @@ fn main() {}
         assert!(html.contains("<pre ") && html.contains("<code"));
         assert!(html.contains("<blockquote>"));
     }
+
+    #[test]
+    fn test_write_html_matches_render() {
+        let md = "# Title\n\nSome *text* with `code`  \nand a break.\n\n- one\n- two\n    - nested\n\n1. first\n2. second\n\n> quote\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|:-:|\n| 1 | 2 |\n\n[link][ref]\n\n[ref]: https://example.com\n\n---\n\nend   \n\n\n";
+        let ast = parse_markdown(md);
+        let cache = crate::image::ImageCache::new();
+        let image_config = ImageConfig {
+            embed_local: false,
+            ..ImageConfig::default()
+        };
+        for options in [
+            HtmlOptions::default(),
+            HtmlOptions {
+                prosemirror: true,
+                heading_ids: true,
+                ..HtmlOptions::default()
+            },
+            HtmlOptions {
+                collapsible_headings: Some(1),
+                ..HtmlOptions::default()
+            },
+        ] {
+            let mut out = Vec::new();
+            write_html(
+                &ast,
+                Path::new("."),
+                &image_config,
+                false,
+                None,
+                &cache,
+                &options,
+                &mut out,
+            )
+            .unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                render_html_with(md, &options)
+            );
+        }
+    }
 }
@@ -0,0 +1,207 @@
+//! PNG pictures of the document, for chats and tools that strip rich text on
+//! paste. On macOS the native attributed string is drawn straight into a
+//! bitmap; elsewhere a headless Chromium takes a screenshot of the HTML
+//! output in its email layout, which carries all of its styling inline.
+
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::Node;
+use std::io;
+
+/// Page width in CSS pixels (points on macOS): the 600px email column and
+/// the body margin around it
+const WIDTH: usize = 640;
+/// Device pixels per CSS pixel, so text stays sharp on high-density screens
+const SCALE: usize = 2;
+/// Space kept around the content, in CSS pixels
+const MARGIN: usize = 16;
+/// Tallest page the browser renders, in CSS pixels; longer documents are
+/// cut off
+#[cfg(not(target_os = "macos"))]
+const MAX_HEIGHT: usize = 4096;
+/// Browsers that take headless screenshots, in order of preference
+#[cfg(not(target_os = "macos"))]
+const BROWSERS: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "microsoft-edge",
+    "brave-browser",
+    "chrome",
+    "msedge",
+];
+
+pub struct ImageRenderer;
+
+impl Renderer for ImageRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_png(ast, ctx).map(Output::Bytes)
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn mdast_to_png(root: &Node, ctx: &render::Context) -> io::Result<Vec<u8>> {
+    let native = crate::to_nsattributedstring::mdast_to_nsattributed_string(
+        &crate::details::folded(root),
+        ctx,
+    )
+    .map_err(io::Error::other)?;
+    crate::to_nsattributedstring::write_png(&native, WIDTH as f64, MARGIN as f64, SCALE as f64)
+        .map_err(io::Error::other)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn mdast_to_png(root: &Node, ctx: &render::Context) -> io::Result<Vec<u8>> {
+    use crate::clipboard::find_in_path;
+    use crate::config::ImageConfig;
+    use crate::to_html::{self, HtmlOptions};
+    use log::debug;
+    use std::fs;
+    use std::process::{Command, Stdio};
+
+    let browser = BROWSERS
+        .iter()
+        .find_map(|name| find_in_path(name))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "-f image needs Chromium or Google Chrome in PATH to render the page",
+            )
+        })?;
+
+    // The page is rendered from a temporary directory, so local images go
+    // in as data URLs
+    let image = ImageConfig {
+        embed_local: true,
+        ..ctx.image.clone()
+    };
+    let options = HtmlOptions {
+        list_style: ctx.cfg.typography.list_style,
+        code_badge: ctx.cfg.highlight.badge,
+        code_style: ctx.cfg.html.code_style,
+        code: ctx.cfg.html.code,
+        email: true,
+        ..HtmlOptions::default()
+    };
+    let html = to_html::mdast_to_html(
+        root,
+        ctx.base_dir,
+        &image,
+        ctx.cfg.strict.images,
+        ctx.highlight,
+        ctx.image_cache,
+        &options,
+    )
+    .map_err(io::Error::other)?;
+
+    let dir = tempfile::TempDir::new()?;
+    let page = dir.path().join("page.html");
+    let screenshot = dir.path().join("page.png");
+    fs::write(&page, to_html::standalone_document(&html, ctx.front_matter))?;
+
+    debug!("Taking a screenshot with {}", browser.display());
+    let output = Command::new(&browser)
+        .args(["--headless", "--disable-gpu", "--hide-scrollbars"])
+        // A profile of its own, so a running browser doesn't take the job
+        .arg(format!(
+            "--user-data-dir={}",
+            dir.path().join("profile").display()
+        ))
+        .arg(format!("--window-size={},{}", WIDTH, MAX_HEIGHT))
+        .arg(format!("--force-device-scale-factor={}", SCALE))
+        .arg("--default-background-color=ffffffff")
+        // Time for remote images to load
+        .arg("--virtual-time-budget=10000")
+        .arg(format!("--screenshot={}", screenshot.display()))
+        .arg(&page)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to run {}: {}", browser.display(), e),
+            )
+        })?;
+    if !output.status.success() || !screenshot.is_file() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{} failed to take a screenshot ({}): {}",
+            browser.display(),
+            output.status,
+            stderr.lines().last().unwrap_or_default()
+        )));
+    }
+    trim(&fs::read(&screenshot)?)
+}
+
+/// Cut the empty page off below the last content, keeping a margin. The
+/// bottom-right pixel is taken as the page background.
+#[cfg(not(target_os = "macos"))]
+fn trim(png: &[u8]) -> io::Result<Vec<u8>> {
+    use crate::image::{self, EmbeddedImage, Pixels};
+    use log::warn;
+
+    let screenshot = EmbeddedImage {
+        data: png.to_vec(),
+        mime_type: "image/png".to_string(),
+    };
+    let pixels = image::decode_pixels(&screenshot).map_err(io::Error::other)?;
+    let row = pixels.width * 3;
+    let Some(background) = pixels.rgb.rchunks_exact(3).next() else {
+        return Ok(png.to_vec());
+    };
+    let Some(last) = pixels
+        .rgb
+        .chunks_exact(row)
+        .rposition(|line| line.chunks_exact(3).any(|pixel| pixel != background))
+    else {
+        return Ok(png.to_vec());
+    };
+    if last + 1 == pixels.height {
+        warn!(
+            "The document is taller than {}px; the image is cut off",
+            MAX_HEIGHT
+        );
+    }
+
+    let height = (last + 1 + MARGIN * SCALE).min(pixels.height);
+    image::encode_png(&Pixels {
+        width: pixels.width,
+        height,
+        rgb: pixels.rgb[..height * row].to_vec(),
+        alpha: None,
+    })
+    .map_err(io::Error::other)
+}
+
+#[cfg(all(test, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+    use crate::image::{self, EmbeddedImage, Pixels};
+
+    #[test]
+    fn test_trim() {
+        // A white page with a black line across row 10
+        let (width, height) = (8, 100);
+        let mut rgb = vec![255; width * height * 3];
+        rgb[10 * width * 3..11 * width * 3].fill(0);
+        let png = image::encode_png(&Pixels {
+            width,
+            height,
+            rgb,
+            alpha: None,
+        })
+        .unwrap();
+
+        let trimmed = EmbeddedImage {
+            data: trim(&png).unwrap(),
+            mime_type: "image/png".to_string(),
+        };
+        assert_eq!(
+            image::dimensions(&trimmed).unwrap(),
+            (width, 11 + MARGIN * SCALE)
+        );
+    }
+}
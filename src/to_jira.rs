@@ -0,0 +1,401 @@
+//! Jira wiki markup, the text format of Jira Server/Data Center and of older
+//! Confluence editors, for targets that don't take HTML on paste.
+
+use crate::alerts::{self, AlertKind};
+use crate::details;
+use crate::inline::Definitions;
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{List, Node};
+use std::io;
+
+/// Languages the `{code}` macro highlights; anything else becomes `{noformat}`
+/// since an unknown language is reported as an error in the page
+const CODE_LANGUAGES: &[&str] = &[
+    "actionscript",
+    "ada",
+    "applescript",
+    "bash",
+    "c",
+    "c#",
+    "c++",
+    "css",
+    "erlang",
+    "go",
+    "groovy",
+    "haskell",
+    "html",
+    "java",
+    "javascript",
+    "json",
+    "lua",
+    "objc",
+    "perl",
+    "php",
+    "python",
+    "r",
+    "ruby",
+    "scala",
+    "sql",
+    "swift",
+    "visualbasic",
+    "xml",
+    "yaml",
+];
+
+pub fn mdast_to_jira(root: &Node) -> String {
+    let mut ctx = JiraContext {
+        definitions: Definitions::collect(root),
+        list_prefix: String::new(),
+    };
+    let mut out = String::new();
+    ctx.blocks(root.children().map_or(&[][..], Vec::as_slice), &mut out);
+    let trimmed = out.trim_end();
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", trimmed)
+    }
+}
+
+pub struct JiraRenderer;
+
+impl Renderer for JiraRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_jira(&details::folded(ast))))
+    }
+}
+
+struct JiraContext<'a> {
+    definitions: Definitions<'a>,
+    /// `*` and `#` markers of the enclosing lists
+    list_prefix: String,
+}
+
+impl JiraContext<'_> {
+    /// Blocks separated by blank lines, each ending in a newline
+    fn blocks(&mut self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            let mut block = String::new();
+            self.block(node, &mut block);
+            if block.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&block);
+        }
+    }
+
+    fn block(&mut self, node: &Node, out: &mut String) {
+        match node {
+            Node::Heading(heading) => {
+                out.push_str(&format!("h{}. ", heading.depth));
+                self.inlines(&heading.children, out);
+                out.push('\n');
+            }
+            Node::Paragraph(para) => {
+                self.inlines(&para.children, out);
+                out.push('\n');
+            }
+            Node::Code(code) => {
+                let lang = code.lang.as_deref().and_then(code_language);
+                let (open, close) = match lang {
+                    Some(lang) => (format!("{{code:{}}}", lang), "{code}"),
+                    None => ("{noformat}".to_string(), "{noformat}"),
+                };
+                out.push_str(&open);
+                out.push('\n');
+                out.push_str(&code.value);
+                if !code.value.is_empty() && !code.value.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(close);
+                out.push('\n');
+            }
+            Node::Math(math) => {
+                out.push_str("{noformat}\n");
+                out.push_str(&math.value);
+                out.push_str("\n{noformat}\n");
+            }
+            Node::Blockquote(quote) => {
+                let (open, close, children) = match alerts::alert(quote) {
+                    Some((kind, children)) => {
+                        let name = panel_macro(kind);
+                        (
+                            format!("{{{}:title={}}}", name, kind.label()),
+                            format!("{{{}}}", name),
+                            children,
+                        )
+                    }
+                    None => (
+                        "{quote}".to_string(),
+                        "{quote}".to_string(),
+                        quote.children.clone(),
+                    ),
+                };
+                out.push_str(&open);
+                out.push('\n');
+                let mut inner = String::new();
+                self.blocks(&children, &mut inner);
+                out.push_str(&inner);
+                out.push_str(&close);
+                out.push('\n');
+            }
+            Node::List(list) => self.list(list, out),
+            Node::Table(table) => {
+                for (index, row) in table.children.iter().enumerate() {
+                    let separator = if index == 0 { "||" } else { "|" };
+                    out.push_str(separator);
+                    for cell in row.children().into_iter().flatten() {
+                        let mut content = String::new();
+                        self.inlines(cell.children().map_or(&[][..], Vec::as_slice), &mut content);
+                        // Empty cells would merge their separators
+                        if content.is_empty() {
+                            content.push(' ');
+                        }
+                        out.push_str(&content);
+                        out.push_str(separator);
+                    }
+                    out.push('\n');
+                }
+            }
+            Node::ThematicBreak(_) => out.push_str("----\n"),
+            Node::FootnoteDefinition(def) => {
+                out.push_str(&format!("^{}^ ", escape(&def.identifier)));
+                let mut inner = String::new();
+                self.blocks(&def.children, &mut inner);
+                out.push_str(&inner);
+            }
+            // Raw HTML shows up verbatim in Jira, and definitions are resolved
+            // where they're used
+            _ => {}
+        }
+    }
+
+    fn list(&mut self, list: &List, out: &mut String) {
+        self.list_prefix.push(if list.ordered { '#' } else { '*' });
+        for item in &list.children {
+            let Node::ListItem(item) = item else {
+                continue;
+            };
+            out.push_str(&self.list_prefix);
+            out.push(' ');
+            match item.checked {
+                Some(true) => out.push_str("(/) "),
+                Some(false) => out.push_str("(x) "),
+                None => {}
+            }
+            // Item content has to stay on the item's line; nested lists
+            // continue on the following lines
+            let mut line_open = true;
+            let mut first = true;
+            for child in &item.children {
+                match child {
+                    Node::List(nested) => {
+                        if line_open {
+                            out.push('\n');
+                            line_open = false;
+                        }
+                        self.list(nested, out);
+                    }
+                    other => {
+                        let mut block = String::new();
+                        self.block(other, &mut block);
+                        if block.is_empty() {
+                            continue;
+                        }
+                        if !first && line_open {
+                            out.push_str(" \\\\ ");
+                        }
+                        out.push_str(block.trim_end());
+                        line_open = true;
+                        first = false;
+                    }
+                }
+            }
+            if line_open {
+                out.push('\n');
+            }
+        }
+        self.list_prefix.pop();
+    }
+
+    fn inlines(&self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            self.inline(node, out);
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String) {
+        match node {
+            Node::Text(text) => {
+                let escaped = escape(&text.value);
+                // A line starting with # would become a numbered list
+                if (out.is_empty() || out.ends_with('\n'))
+                    && let Some(rest) = escaped.strip_prefix('#')
+                {
+                    out.push_str("\\#");
+                    out.push_str(rest);
+                    return;
+                }
+                out.push_str(&escaped);
+            }
+            Node::Strong(strong) => self.wrap("*", &strong.children, out),
+            Node::Emphasis(em) => self.wrap("_", &em.children, out),
+            Node::Delete(del) => self.wrap("-", &del.children, out),
+            Node::InlineCode(code) => {
+                out.push_str("{{");
+                out.push_str(&escape(&code.value));
+                out.push_str("}}");
+            }
+            Node::InlineMath(math) => {
+                out.push_str("{{");
+                out.push_str(&escape(&math.value));
+                out.push_str("}}");
+            }
+            Node::Break(_) => out.push_str("\\\\\n"),
+            Node::Link(link) => self.link(&link.children, &link.url, out),
+            Node::LinkReference(linkref) => match self.definitions.get(&linkref.identifier) {
+                Some(url) => self.link(&linkref.children, url, out),
+                None => self.inlines(&linkref.children, out),
+            },
+            Node::Image(image) => push_image(&image.url, &image.alt, out),
+            Node::ImageReference(imgref) => match self.definitions.get(&imgref.identifier) {
+                Some(url) => push_image(url, &imgref.alt, out),
+                None => out.push_str(&escape(&imgref.alt)),
+            },
+            Node::FootnoteReference(fnref) => {
+                out.push_str(&format!("^{}^", escape(&fnref.identifier)));
+            }
+            _ => {}
+        }
+    }
+
+    fn wrap(&self, marker: &str, children: &[Node], out: &mut String) {
+        out.push_str(marker);
+        self.inlines(children, out);
+        out.push_str(marker);
+    }
+
+    fn link(&self, children: &[Node], url: &str, out: &mut String) {
+        let mut text = String::new();
+        self.inlines(children, &mut text);
+        out.push('[');
+        if !text.is_empty() && text != url {
+            out.push_str(&text);
+            out.push('|');
+        }
+        out.push_str(url);
+        out.push(']');
+    }
+}
+
+fn push_image(url: &str, alt: &str, out: &mut String) {
+    out.push('!');
+    out.push_str(url);
+    if !alt.is_empty() {
+        out.push_str("|alt=");
+        // Commas separate image attributes
+        out.push_str(&escape(alt).replace(',', " "));
+    }
+    out.push('!');
+}
+
+/// The `{code}` language for a fence info string
+fn code_language(lang: &str) -> Option<&'static str> {
+    let lang = lang.to_lowercase();
+    let lang = match lang.as_str() {
+        "sh" | "shell" | "zsh" | "console" => "bash",
+        "js" | "jsx" | "mjs" => "javascript",
+        "py" => "python",
+        "rb" => "ruby",
+        "yml" => "yaml",
+        "cpp" | "cc" | "cxx" | "hpp" => "c++",
+        "cs" | "csharp" => "c#",
+        "golang" => "go",
+        "objective-c" | "objectivec" => "objc",
+        "vb" | "vbnet" => "visualbasic",
+        other => other,
+    };
+    CODE_LANGUAGES.iter().copied().find(|known| *known == lang)
+}
+
+fn panel_macro(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::Note => "info",
+        AlertKind::Tip => "tip",
+        AlertKind::Important => "note",
+        AlertKind::Warning | AlertKind::Caution => "warning",
+    }
+}
+
+/// Backslash-escape characters that start markup. A `-` only strikes
+/// through at the start of a word, so other hyphens are left alone.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        let word_start = prev.is_none_or(char::is_whitespace);
+        match c {
+            '*' | '_' | '+' | '^' | '~' | '{' | '}' | '[' | ']' | '|' | '!' | '\\' => {
+                out.push('\\');
+            }
+            '-' if word_start => out.push('\\'),
+            _ => {}
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn jira(md: &str) -> String {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        mdast_to_jira(&ast)
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            jira("## Setup\n\nRun **this** and `a{b}`:\n\n```sh\nmake\n```\n\n```\nplain\n```\n"),
+            "h2. Setup\n\nRun *this* and {{a\\{b\\}}}:\n\n{code:bash}\nmake\n{code}\n\n\
+             {noformat}\nplain\n{noformat}\n"
+        );
+        assert_eq!(
+            jira("> [!TIP]\n> Use *it*.\n\n> quoted\n"),
+            "{tip:title=Tip}\nUse _it_.\n{tip}\n\n{quote}\nquoted\n{quote}\n"
+        );
+        assert_eq!(
+            jira("| a | b |\n|---|---|\n| 1 |   |\n"),
+            "||a||b||\n|1| |\n"
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            jira("- one\n  1. nested\n- [x] done\n"),
+            "* one\n*# nested\n* (/) done\n"
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            jira("[docs](https://example.com) ~~old~~ ![logo](logo.png) <https://x.io>"),
+            "[docs|https://example.com] -old- !logo.png|alt=logo! [https://x.io]\n"
+        );
+        // Markup characters are escaped; hyphens inside words are not
+        assert_eq!(
+            jira("well-known *not bold* -5 [x]"),
+            "well-known _not bold_ \\-5 \\[x\\]\n"
+        );
+        assert_eq!(jira("\\# not a list"), "\\# not a list\n");
+    }
+}
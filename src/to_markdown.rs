@@ -1,6 +1,8 @@
-use crate::config::ImageConfig;
+use crate::config::{ImageConfig, ListStyle};
 use crate::image::{ImageCache, ImageError};
+use crate::render::{self, Output, Renderer};
 use markdown::mdast::{AlignKind, Node};
+use std::io;
 use std::path::Path;
 
 pub fn mdast_to_markdown(
@@ -9,8 +11,9 @@ pub fn mdast_to_markdown(
     image_config: &ImageConfig,
     strict: bool,
     image_cache: &ImageCache,
+    list_style: ListStyle,
 ) -> Result<String, ImageError> {
-    let mut ctx = MarkdownContext::new(base_dir, image_config, strict, image_cache);
+    let mut ctx = MarkdownContext::new(base_dir, image_config, strict, image_cache, list_style);
     let mut output = String::new();
     node_to_markdown(node, &mut output, &mut ctx)?;
     // Trim trailing whitespace but ensure single trailing newline
@@ -22,11 +25,111 @@ pub fn mdast_to_markdown(
     }
 }
 
+/// Write the markdown `mdast_to_markdown` renders to `out` one top-level
+/// block at a time. Trailing whitespace is held back until more follows, so
+/// the output ends the same way.
+pub fn write_markdown(
+    node: &Node,
+    base_dir: &Path,
+    image_config: &ImageConfig,
+    strict: bool,
+    image_cache: &ImageCache,
+    list_style: ListStyle,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let Node::Root(root) = node else {
+        let md = mdast_to_markdown(
+            node,
+            base_dir,
+            image_config,
+            strict,
+            image_cache,
+            list_style,
+        )
+        .map_err(io::Error::other)?;
+        return out.write_all(md.as_bytes());
+    };
+    let mut ctx = MarkdownContext::new(base_dir, image_config, strict, image_cache, list_style);
+    let mut md = String::new();
+    let mut written = false;
+    for (i, child) in root.children.iter().enumerate() {
+        if i > 0 {
+            // Same separators as the root in `node_to_markdown`
+            if !md.ends_with("\n\n") && !md.ends_with("\n") {
+                md.push('\n');
+            }
+            if !md.ends_with("\n\n") {
+                md.push('\n');
+            }
+        }
+        node_to_markdown(child, &mut md, &mut ctx).map_err(io::Error::other)?;
+        let end = md.trim_end().len();
+        if end > 0 {
+            out.write_all(&md.as_bytes()[..end])?;
+            md.drain(..end);
+            written = true;
+        }
+    }
+    if written {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+pub struct MarkdownRenderer;
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_markdown(
+            ast,
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.image_cache,
+            ctx.cfg.typography.list_style,
+        )
+        .map(|md| match ctx.front_matter {
+            Some(front_matter) if ctx.cfg.keep_frontmatter => {
+                format!("{}\n{}", front_matter.to_markdown(), md)
+            }
+            _ => md,
+        })
+        .map(Output::Text)
+        .map_err(io::Error::other)
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn render_to(
+        &self,
+        ast: &Node,
+        ctx: &render::Context,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        if let Some(front_matter) = ctx.front_matter.filter(|_| ctx.cfg.keep_frontmatter) {
+            writeln!(out, "{}", front_matter.to_markdown())?;
+        }
+        write_markdown(
+            ast,
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.image_cache,
+            ctx.cfg.typography.list_style,
+            out,
+        )
+    }
+}
+
 struct MarkdownContext<'a> {
     base_dir: &'a Path,
     image_config: &'a ImageConfig,
     strict: bool,
     image_cache: &'a ImageCache,
+    /// Ordered list marker style (markdown can only express `1.` and `1)`)
+    list_style: ListStyle,
     /// Current list depth for indentation
     list_depth: usize,
     /// Stack of list types (true = ordered, false = unordered)
@@ -43,12 +146,14 @@ impl<'a> MarkdownContext<'a> {
         image_config: &'a ImageConfig,
         strict: bool,
         image_cache: &'a ImageCache,
+        list_style: ListStyle,
     ) -> Self {
         Self {
             base_dir,
             image_config,
             strict,
             image_cache,
+            list_style,
             list_depth: 0,
             list_stack: Vec::new(),
             list_indices: Vec::new(),
@@ -225,11 +330,16 @@ fn node_to_markdown(
 
             md.push_str(&indent);
             if is_ordered {
+                let delimiter = if ctx.list_style == ListStyle::DecimalParen {
+                    ')'
+                } else {
+                    '.'
+                };
                 if let Some(i) = idx {
-                    md.push_str(&format!("{}. ", *i));
+                    md.push_str(&format!("{}{} ", *i, delimiter));
                     *i += 1;
                 } else {
-                    md.push_str("1. ");
+                    md.push_str(&format!("1{} ", delimiter));
                 }
             } else {
                 md.push_str("- ");
@@ -476,6 +586,10 @@ mod tests {
     }
 
     fn roundtrip(md: &str) -> String {
+        roundtrip_with(md, ListStyle::Decimal)
+    }
+
+    fn roundtrip_with(md: &str, list_style: ListStyle) -> String {
         let ast = parse_markdown(md);
         let cache = crate::image::ImageCache::new();
         let image_config = crate::config::ImageConfig {
@@ -485,8 +599,17 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         };
-        mdast_to_markdown(&ast, Path::new("."), &image_config, false, &cache).unwrap()
+        mdast_to_markdown(
+            &ast,
+            Path::new("."),
+            &image_config,
+            false,
+            &cache,
+            list_style,
+        )
+        .unwrap()
     }
 
     #[test]
@@ -574,6 +697,13 @@ mod tests {
         assert!(output.contains("2. second"));
     }
 
+    #[test]
+    fn test_ordered_list_paren_style() {
+        let output = roundtrip_with("1. first\n2. second", ListStyle::DecimalParen);
+        assert!(output.contains("1) first"));
+        assert!(output.contains("2) second"));
+    }
+
     #[test]
     fn test_blockquote() {
         let output = roundtrip("> quoted text");
@@ -682,4 +812,41 @@ fn main() {}
         let output = roundtrip("<div>raw html</div>");
         assert!(output.contains("<div>raw html</div>"));
     }
+
+    #[test]
+    fn test_write_markdown_matches_render() {
+        let md = "# Title\n\nSome *text* with `code`  \nand a break.\n\n- one\n- two\n    - nested\n\n1. first\n2. second\n\n> quote\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|:-:|\n| 1 | 2 |\n\n[link][ref]\n\n[ref]: https://example.com\n\n---\n\nend   \n\n\n";
+        let ast = parse_markdown(md);
+        let cache = crate::image::ImageCache::new();
+        let image_config = ImageConfig {
+            embed_local: false,
+            ..ImageConfig::default()
+        };
+        let mut out = Vec::new();
+        write_markdown(
+            &ast,
+            Path::new("."),
+            &image_config,
+            false,
+            &cache,
+            ListStyle::Decimal,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), roundtrip(md));
+
+        let mut out = Vec::new();
+        let empty = parse_markdown("");
+        write_markdown(
+            &empty,
+            Path::new("."),
+            &image_config,
+            false,
+            &cache,
+            ListStyle::Decimal,
+            &mut out,
+        )
+        .unwrap();
+        assert!(out.is_empty());
+    }
 }
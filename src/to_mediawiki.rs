@@ -0,0 +1,385 @@
+//! MediaWiki markup, for pasting into wikis that run MediaWiki or take its
+//! syntax (many internal wikis do). Code blocks use the SyntaxHighlight
+//! extension's `<syntaxhighlight>` tag, which ships with MediaWiki.
+
+use crate::alerts;
+use crate::details;
+use crate::image::is_remote_url;
+use crate::inline::Definitions;
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{AlignKind, List, Node};
+use std::io;
+
+pub fn mdast_to_mediawiki(root: &Node) -> String {
+    let mut ctx = WikiContext {
+        definitions: Definitions::collect(root),
+        list_prefix: String::new(),
+    };
+    let mut out = String::new();
+    ctx.blocks(root.children().map_or(&[][..], Vec::as_slice), &mut out);
+    let trimmed = out.trim_end();
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", trimmed)
+    }
+}
+
+pub struct MediaWikiRenderer;
+
+impl Renderer for MediaWikiRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_mediawiki(&details::folded(ast))))
+    }
+}
+
+struct WikiContext<'a> {
+    definitions: Definitions<'a>,
+    /// `*`, `#` and `:` markers of the enclosing lists
+    list_prefix: String,
+}
+
+impl WikiContext<'_> {
+    /// Blocks separated by blank lines, each ending in a newline
+    fn blocks(&mut self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            let mut block = String::new();
+            self.block(node, &mut block);
+            if block.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&block);
+        }
+    }
+
+    fn block(&mut self, node: &Node, out: &mut String) {
+        match node {
+            Node::Heading(heading) => {
+                let marker = "=".repeat(heading.depth as usize);
+                out.push_str(&marker);
+                out.push(' ');
+                self.inlines(&heading.children, out);
+                out.push(' ');
+                out.push_str(&marker);
+                out.push('\n');
+            }
+            Node::Paragraph(para) => {
+                self.inlines(&para.children, out);
+                out.push('\n');
+            }
+            Node::Code(code) => {
+                match code.lang.as_deref() {
+                    Some(lang) => out.push_str(&format!(
+                        "<syntaxhighlight lang=\"{}\">\n",
+                        escape_attribute(lang)
+                    )),
+                    None => out.push_str("<pre>\n"),
+                }
+                // Only the closing tag ends the block
+                let close = if code.lang.is_some() {
+                    "</syntaxhighlight>"
+                } else {
+                    "</pre>"
+                };
+                out.push_str(&code.value.replace(close, &escape_html(close)));
+                if !code.value.is_empty() && !code.value.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(close);
+                out.push('\n');
+            }
+            Node::Math(math) => {
+                out.push_str("<math display=\"block\">");
+                out.push_str(&math.value);
+                out.push_str("</math>\n");
+            }
+            Node::Blockquote(quote) => {
+                let mut inner = String::new();
+                match alerts::alert(quote) {
+                    Some((kind, children)) => {
+                        self.blocks(&children, &mut inner);
+                        inner.insert_str(0, &format!("'''{}'''\n\n", kind.label()));
+                    }
+                    None => self.blocks(&quote.children, &mut inner),
+                }
+                out.push_str("<blockquote>\n");
+                out.push_str(&inner);
+                out.push_str("</blockquote>\n");
+            }
+            Node::List(list) => self.list(list, out),
+            Node::Table(table) => {
+                out.push_str("{| class=\"wikitable\"\n");
+                for (index, row) in table.children.iter().enumerate() {
+                    out.push_str("|-\n");
+                    let marker = if index == 0 { '!' } else { '|' };
+                    for (column, cell) in row.children().into_iter().flatten().enumerate() {
+                        out.push(marker);
+                        if let Some(align) = table.align.get(column).and_then(alignment) {
+                            out.push_str(&format!(" style=\"text-align:{}\" |", align));
+                        }
+                        let mut content = String::new();
+                        self.inlines(cell.children().map_or(&[][..], Vec::as_slice), &mut content);
+                        out.push(' ');
+                        out.push_str(&content);
+                        out.push('\n');
+                    }
+                }
+                out.push_str("|}\n");
+            }
+            Node::ThematicBreak(_) => out.push_str("----\n"),
+            Node::FootnoteDefinition(def) => {
+                out.push_str(&format!("<sup>{}</sup> ", escape_html(&def.identifier)));
+                let mut inner = String::new();
+                self.blocks(&def.children, &mut inner);
+                out.push_str(&inner);
+            }
+            // MediaWiki takes a subset of HTML itself
+            Node::Html(html) => {
+                out.push_str(&html.value);
+                out.push('\n');
+            }
+            // Definitions are resolved where they're used
+            _ => {}
+        }
+    }
+
+    fn list(&mut self, list: &List, out: &mut String) {
+        self.list_prefix.push(if list.ordered { '#' } else { '*' });
+        for item in &list.children {
+            let Node::ListItem(item) = item else {
+                continue;
+            };
+            out.push_str(&self.list_prefix);
+            out.push(' ');
+            match item.checked {
+                Some(true) => out.push_str("☑ "),
+                Some(false) => out.push_str("☐ "),
+                None => {}
+            }
+            // Item content has to stay on the item's line; later paragraphs
+            // continue with `:` and nested lists follow
+            let mut line_open = true;
+            let mut first = true;
+            for child in &item.children {
+                match child {
+                    Node::List(nested) => {
+                        if line_open {
+                            out.push('\n');
+                            line_open = false;
+                        }
+                        self.list(nested, out);
+                    }
+                    other => {
+                        let mut block = String::new();
+                        self.block(other, &mut block);
+                        if block.is_empty() {
+                            continue;
+                        }
+                        if !first {
+                            if line_open {
+                                out.push('\n');
+                            }
+                            out.push_str(&self.list_prefix);
+                            out.push_str(": ");
+                        }
+                        out.push_str(&block.trim_end().replace('\n', " "));
+                        line_open = true;
+                        first = false;
+                    }
+                }
+            }
+            if line_open {
+                out.push('\n');
+            }
+        }
+        self.list_prefix.pop();
+    }
+
+    fn inlines(&self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            self.inline(node, out);
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String) {
+        match node {
+            Node::Text(text) => {
+                let escaped = escape(&text.value);
+                // Lines starting with these are lists, indents or headings
+                if (out.is_empty() || out.ends_with('\n'))
+                    && let Some(first) = escaped.chars().next()
+                    && matches!(first, '*' | '#' | ':' | ';' | '=' | ' ')
+                {
+                    out.push_str(&format!("<nowiki>{}</nowiki>", first));
+                    out.push_str(&escaped[first.len_utf8()..]);
+                    return;
+                }
+                out.push_str(&escaped);
+            }
+            Node::Strong(strong) => self.wrap("'''", "'''", &strong.children, out),
+            Node::Emphasis(em) => self.wrap("''", "''", &em.children, out),
+            Node::Delete(del) => self.wrap("<s>", "</s>", &del.children, out),
+            Node::InlineCode(code) => {
+                out.push_str("<code><nowiki>");
+                out.push_str(&code.value.replace("</nowiki>", "&lt;/nowiki>"));
+                out.push_str("</nowiki></code>");
+            }
+            Node::InlineMath(math) => {
+                out.push_str("<math>");
+                out.push_str(&math.value);
+                out.push_str("</math>");
+            }
+            Node::Break(_) => out.push_str("<br />\n"),
+            Node::Link(link) => self.link(&link.children, &link.url, out),
+            Node::LinkReference(linkref) => match self.definitions.get(&linkref.identifier) {
+                Some(url) => self.link(&linkref.children, url, out),
+                None => self.inlines(&linkref.children, out),
+            },
+            Node::Image(image) => push_image(&image.url, &image.alt, out),
+            Node::ImageReference(imgref) => match self.definitions.get(&imgref.identifier) {
+                Some(url) => push_image(url, &imgref.alt, out),
+                None => out.push_str(&escape(&imgref.alt)),
+            },
+            Node::FootnoteReference(fnref) => {
+                out.push_str(&format!("<sup>{}</sup>", escape_html(&fnref.identifier)));
+            }
+            Node::Html(html) => out.push_str(&html.value),
+            _ => {}
+        }
+    }
+
+    fn wrap(&self, open: &str, close: &str, children: &[Node], out: &mut String) {
+        out.push_str(open);
+        self.inlines(children, out);
+        out.push_str(close);
+    }
+
+    /// External links are `[url text]`; relative ones can only be pages
+    fn link(&self, children: &[Node], url: &str, out: &mut String) {
+        let mut text = String::new();
+        self.inlines(children, &mut text);
+        if !url.contains("://") && !url.starts_with("mailto:") {
+            // Internal link to another page: [[Page|text]]
+            let page = url.trim_start_matches("./");
+            let page = page
+                .strip_suffix(".md")
+                .or_else(|| page.strip_suffix(".markdown"))
+                .unwrap_or(page);
+            out.push_str("[[");
+            out.push_str(page);
+            if !text.is_empty() && text != page {
+                out.push('|');
+                out.push_str(&text);
+            }
+            out.push_str("]]");
+            return;
+        }
+        out.push('[');
+        out.push_str(&url.replace(' ', "%20"));
+        if !text.is_empty() && text != url {
+            out.push(' ');
+            out.push_str(&text);
+        }
+        out.push(']');
+    }
+}
+
+/// Remote images are inserted by URL, which works on wikis with
+/// `$wgAllowExternalImages`; anything else is an uploaded file
+fn push_image(url: &str, alt: &str, out: &mut String) {
+    if is_remote_url(url) {
+        out.push_str(url);
+        return;
+    }
+    let name = url.rsplit('/').next().unwrap_or(url);
+    out.push_str("[[File:");
+    out.push_str(name);
+    if !alt.is_empty() {
+        // `|` separates the file's options; the last one is the caption
+        let alt = escape_html(alt).replace('|', "&#124;");
+        out.push_str(&format!("|alt={}|{}", alt, alt));
+    }
+    out.push_str("]]");
+}
+
+fn alignment(align: &AlignKind) -> Option<&'static str> {
+    match align {
+        AlignKind::Left => Some("left"),
+        AlignKind::Center => Some("center"),
+        AlignKind::Right => Some("right"),
+        AlignKind::None => None,
+    }
+}
+
+/// Text that would read as wiki markup (`''`, `[[`, `{{`, `~~~`, `__TOC__`
+/// and the like) is wrapped in `<nowiki>`; anything else only has its HTML
+/// escaped
+fn escape(text: &str) -> String {
+    const MARKUP: &[&str] = &["''", "[", "]]", "{{", "}}", "~~~", "__"];
+    let text = escape_html(text);
+    if MARKUP.iter().any(|m| text.contains(m)) {
+        format!("<nowiki>{}</nowiki>", text)
+    } else {
+        text
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;")
+}
+
+fn escape_attribute(text: &str) -> String {
+    escape_html(text).replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn wiki(md: &str) -> String {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        mdast_to_mediawiki(&ast)
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            wiki(
+                "## Setup\n\nRun **this** and *that*:\n\n```rust\nfn main() {}\n```\n\n```\nplain\n```\n"
+            ),
+            "== Setup ==\n\nRun '''this''' and ''that'':\n\n\
+             <syntaxhighlight lang=\"rust\">\nfn main() {}\n</syntaxhighlight>\n\n<pre>\nplain\n</pre>\n"
+        );
+        assert_eq!(
+            wiki("| a | b |\n|---|--:|\n| 1 | 2 |\n"),
+            "{| class=\"wikitable\"\n|-\n! a\n! style=\"text-align:right\" | b\n\
+             |-\n| 1\n| style=\"text-align:right\" | 2\n|}\n"
+        );
+        assert_eq!(
+            wiki("- one\n  1. nested\n- [x] done\n"),
+            "* one\n*# nested\n* ☑ done\n"
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            wiki(
+                "[docs](https://example.com) [guide](guide.md) ![logo](img/logo.png) ![x](https://x.io/a.png)"
+            ),
+            "[https://example.com docs] [[guide]] [[File:logo.png|alt=logo|logo]] https://x.io/a.png\n"
+        );
+        assert_eq!(
+            wiki("`a<b` ~~old~~ [not a link] it's"),
+            "<code><nowiki>a<b</nowiki></code> <s>old</s><nowiki> [not a link] it's</nowiki>\n"
+        );
+        assert_eq!(
+            wiki("\\# not a list, it's"),
+            "<nowiki>#</nowiki> not a list, it's\n"
+        );
+    }
+}
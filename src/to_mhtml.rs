@@ -0,0 +1,189 @@
+//! MHTML archives: the HTML output as a standalone page in a
+//! `multipart/related` MIME message, with each embedded image as a part of
+//! its own referenced by `cid:` URL instead of a `data:` URL. Outlook opens
+//! them as drafts and browsers as saved pages. Images that weren't embedded
+//! keep their URLs.
+
+use crate::render::{self, Output, Renderer};
+use crate::to_html::{self, ExtractedImage, HtmlOptions};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use markdown::mdast::Node;
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// Longest line MIME allows in encoded bodies, besides the line break
+const LINE_LENGTH: usize = 76;
+
+pub fn mdast_to_mhtml(root: &Node, ctx: &render::Context) -> io::Result<String> {
+    let options = HtmlOptions {
+        list_style: ctx.cfg.typography.list_style,
+        collapsible_headings: ctx.cfg.collapsible_headings,
+        heading_ids: ctx.cfg.heading_ids,
+        code_badge: ctx.cfg.highlight.badge,
+        code_style: ctx.cfg.html.code_style,
+        code: ctx.cfg.html.code,
+        // A saved page is viewed on its own
+        scroll_code: true,
+        email: ctx.cfg.email,
+        ..HtmlOptions::default()
+    };
+    let html = to_html::mdast_to_html(
+        root,
+        ctx.base_dir,
+        ctx.image,
+        ctx.cfg.strict.images,
+        ctx.highlight,
+        ctx.image_cache,
+        &options,
+    )
+    .map_err(io::Error::other)?;
+    let page = to_html::standalone_document(&html, ctx.front_matter);
+    let (page, images) =
+        to_html::extract_images(&page, |index, _| format!("cid:{}", content_id(index)));
+    let title = ctx.front_matter.and_then(|fm| fm.title.as_deref());
+    Ok(message(&page, &images, title))
+}
+
+pub struct MhtmlRenderer;
+
+impl Renderer for MhtmlRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_mhtml(ast, ctx).map(|mhtml| Output::Bytes(mhtml.into_bytes()))
+    }
+}
+
+fn content_id(index: usize) -> String {
+    format!("image{}@mdcopy", index)
+}
+
+/// The MIME message: headers, the page quoted-printable so it stays
+/// readable, then each image in base64
+fn message(page: &str, images: &[ExtractedImage], title: Option<&str>) -> String {
+    // Neither encoding can produce `=_`, so the boundary never shows up in
+    // a part
+    let digest = Sha256::digest(page.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    let boundary = format!("----=_mdcopy_{}", hex);
+
+    let mut out = String::from("From: <Saved by mdcopy>\r\n");
+    if let Some(title) = title {
+        out.push_str(&format!("Subject: {}\r\n", header_text(title)));
+    }
+    out.push_str(&format!(
+        "MIME-Version: 1.0\r\n\
+         Content-Type: multipart/related;\r\n\ttype=\"text/html\";\r\n\tboundary=\"{}\"\r\n\r\n",
+        boundary
+    ));
+    out.push_str(&format!(
+        "--{}\r\n\
+         Content-Type: text/html; charset=\"utf-8\"\r\n\
+         Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+        boundary
+    ));
+    out.push_str(&quoted_printable(page));
+    for image in images {
+        out.push_str(&format!(
+            "--{}\r\n\
+             Content-Type: {}\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             Content-ID: <{}>\r\n\r\n",
+            boundary,
+            image.mime_type,
+            image.name.trim_start_matches("cid:")
+        ));
+        let encoded = STANDARD.encode(&image.data);
+        for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+            out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+            out.push_str("\r\n");
+        }
+    }
+    out.push_str(&format!("--{}--\r\n", boundary));
+    out
+}
+
+/// A header value, as an RFC 2047 encoded word when it isn't plain ASCII
+fn header_text(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    if text.is_ascii() {
+        text
+    } else {
+        format!("=?utf-8?B?{}?=", STANDARD.encode(text))
+    }
+}
+
+/// Quoted-printable text with CRLF line breaks, lines kept under the MIME
+/// limit with soft breaks
+fn quoted_printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / 8);
+    for line in text.lines() {
+        let bytes = line.as_bytes();
+        let mut width = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            // Trailing whitespace would be stripped in transit
+            let literal = match byte {
+                b' ' | b'\t' => i + 1 < bytes.len(),
+                b'=' => false,
+                b'!'..=b'~' => true,
+                _ => false,
+            };
+            let encoded = if literal {
+                (byte as char).to_string()
+            } else {
+                format!("={:02X}", byte)
+            };
+            // Room for the `=` of a soft break
+            if width + encoded.len() > LINE_LENGTH - 1 {
+                out.push_str("=\r\n");
+                width = 0;
+            }
+            out.push_str(&encoded);
+            width += encoded.len();
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let page = to_html::standalone_document(
+            "<p>Hi</p>\n<img src=\"data:image/png;base64,AQID\" />\n",
+            None,
+        );
+        let (page, images) =
+            to_html::extract_images(&page, |index, _| format!("cid:{}", content_id(index)));
+        let mhtml = message(&page, &images, Some("Café notes"));
+        let boundary = mhtml
+            .split("boundary=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+
+        assert!(
+            mhtml.starts_with(
+                "From: <Saved by mdcopy>\r\nSubject: =?utf-8?B?Q2Fmw6kgbm90ZXM=?=\r\n"
+            )
+        );
+        assert!(mhtml.contains("<img src=3D\"cid:image1@mdcopy\" />\r\n"));
+        assert!(mhtml.contains(
+            "Content-Type: image/png\r\nContent-Transfer-Encoding: base64\r\n\
+             Content-ID: <image1@mdcopy>\r\n\r\nAQID\r\n"
+        ));
+        assert!(mhtml.ends_with(&format!("--{}--\r\n", boundary)));
+        assert_eq!(mhtml.matches(&format!("--{}\r\n", boundary)).count(), 2);
+    }
+
+    #[test]
+    fn test_quoted_printable() {
+        assert_eq!(quoted_printable("a=b é \n"), "a=3Db =C3=A9=20\r\n");
+        // Long lines get soft breaks and stay within the limit
+        let encoded = quoted_printable(&"x".repeat(200));
+        assert!(encoded.lines().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(encoded.replace("=\r\n", "").trim_end(), "x".repeat(200));
+    }
+}
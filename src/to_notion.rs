@@ -0,0 +1,443 @@
+//! Notion API block objects, as JSON. The output is the body the "append
+//! block children" endpoint takes (`{"children": [...]}`), so it can be piped
+//! to `curl` or another tool that writes pages. Headings past level 3 become
+//! `heading_3`, alerts become callouts, and images can only be embedded by
+//! URL, so local ones stay links.
+
+use crate::alerts::{self, AlertKind};
+use crate::details;
+use crate::image::is_remote_url;
+use crate::inline::{self, Definitions, Span, Style};
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{List, Node};
+use serde_json::{Map, Value, json};
+use std::io;
+
+/// Longest text Notion accepts in one rich text object
+const MAX_TEXT: usize = 2000;
+
+/// Code block languages Notion knows, besides "plain text"
+const LANGUAGES: &[&str] = &[
+    "bash",
+    "c",
+    "c#",
+    "c++",
+    "clojure",
+    "css",
+    "dart",
+    "diff",
+    "docker",
+    "elixir",
+    "erlang",
+    "go",
+    "graphql",
+    "haskell",
+    "html",
+    "java",
+    "javascript",
+    "json",
+    "kotlin",
+    "latex",
+    "lua",
+    "makefile",
+    "markdown",
+    "mermaid",
+    "objective-c",
+    "perl",
+    "php",
+    "powershell",
+    "python",
+    "r",
+    "ruby",
+    "rust",
+    "scala",
+    "shell",
+    "sql",
+    "swift",
+    "toml",
+    "typescript",
+    "xml",
+    "yaml",
+];
+
+pub fn mdast_to_notion(root: &Node) -> String {
+    let ctx = NotionContext {
+        definitions: Definitions::collect(root),
+    };
+    let children = ctx.blocks(root.children().map_or(&[][..], Vec::as_slice));
+    json!({ "children": children }).to_string()
+}
+
+pub struct NotionRenderer;
+
+impl Renderer for NotionRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_notion(&details::folded(ast))))
+    }
+}
+
+struct NotionContext<'a> {
+    definitions: Definitions<'a>,
+}
+
+impl NotionContext<'_> {
+    fn blocks(&self, nodes: &[Node]) -> Vec<Value> {
+        nodes.iter().flat_map(|node| self.block(node)).collect()
+    }
+
+    fn block(&self, node: &Node) -> Vec<Value> {
+        match node {
+            Node::Paragraph(para) => match para.children.as_slice() {
+                // A remote image on its own becomes an image block; local ones
+                // would need an upload, so they stay links
+                [Node::Image(image)] if is_remote_url(&image.url) => {
+                    let mut image_block = json!({
+                        "type": "external",
+                        "external": {"url": image.url},
+                    });
+                    if !image.alt.is_empty() {
+                        image_block["caption"] =
+                            json!(text_objects(&image.alt, Style::default(), None));
+                    }
+                    vec![block("image", image_block)]
+                }
+                children => vec![block(
+                    "paragraph",
+                    json!({"rich_text": self.rich_text(children)}),
+                )],
+            },
+            Node::Heading(heading) => {
+                let kind = format!("heading_{}", heading.depth.clamp(1, 3));
+                vec![block(
+                    &kind,
+                    json!({"rich_text": self.rich_text(&heading.children)}),
+                )]
+            }
+            Node::Code(code) => vec![code_block(code.lang.as_deref(), &code.value)],
+            Node::Math(math) => vec![block("equation", json!({"expression": math.value}))],
+            Node::Blockquote(quote) => match alerts::alert(quote) {
+                Some((kind, children)) => {
+                    let (rich_text, children) = self.lead(&children);
+                    let mut callout = json!({
+                        "rich_text": rich_text,
+                        "icon": {"type": "emoji", "emoji": icon(kind)},
+                        "color": color(kind),
+                    });
+                    set_children(&mut callout, children);
+                    vec![block("callout", callout)]
+                }
+                None => {
+                    let (rich_text, children) = self.lead(&quote.children);
+                    let mut body = json!({"rich_text": rich_text});
+                    set_children(&mut body, children);
+                    vec![block("quote", body)]
+                }
+            },
+            Node::List(list) => self.list(list),
+            Node::Table(table) => {
+                let width = table
+                    .children
+                    .iter()
+                    .map(|row| row.children().map_or(0, Vec::len))
+                    .max()
+                    .unwrap_or(0);
+                let rows: Vec<Value> = table
+                    .children
+                    .iter()
+                    .map(|row| {
+                        let cells = row.children().map_or(&[][..], Vec::as_slice);
+                        // Every row needs `table_width` cells
+                        let mut cells: Vec<Value> = cells
+                            .iter()
+                            .map(|cell| {
+                                json!(
+                                    self.rich_text(cell.children().map_or(&[][..], Vec::as_slice))
+                                )
+                            })
+                            .collect();
+                        cells.resize(width, json!([]));
+                        block("table_row", json!({"cells": cells}))
+                    })
+                    .collect();
+                vec![block(
+                    "table",
+                    json!({
+                        "table_width": width,
+                        "has_column_header": true,
+                        "has_row_header": false,
+                        "children": rows,
+                    }),
+                )]
+            }
+            Node::ThematicBreak(_) => vec![block("divider", json!({}))],
+            Node::FootnoteDefinition(def) => {
+                let mut content = self.blocks(&def.children);
+                // Lead with the label the references point at
+                if let Some(rich_text) = content
+                    .first_mut()
+                    .filter(|block| block["type"] == "paragraph")
+                    .and_then(|block| block["paragraph"]["rich_text"].as_array_mut())
+                {
+                    let label = format!("[{}] ", def.identifier);
+                    rich_text.splice(0..0, text_objects(&label, Style::default(), None));
+                }
+                content
+            }
+            // Raw HTML, definitions and front matter have no block
+            _ => Vec::new(),
+        }
+    }
+
+    /// One list item block per item; Notion has no list container
+    fn list(&self, list: &List) -> Vec<Value> {
+        list.children
+            .iter()
+            .filter_map(|node| {
+                let Node::ListItem(item) = node else {
+                    return None;
+                };
+                let (rich_text, children) = self.lead(&item.children);
+                let mut body = json!({"rich_text": rich_text});
+                let kind = match item.checked {
+                    Some(checked) => {
+                        body["checked"] = json!(checked);
+                        "to_do"
+                    }
+                    None if list.ordered => "numbered_list_item",
+                    None => "bulleted_list_item",
+                };
+                set_children(&mut body, children);
+                Some(block(kind, body))
+            })
+            .collect()
+    }
+
+    /// Blocks that carry text themselves (list items, quotes, callouts) take
+    /// a leading paragraph as their text, with the rest nested below
+    fn lead(&self, nodes: &[Node]) -> (Vec<Value>, Vec<Value>) {
+        match nodes.split_first() {
+            Some((Node::Paragraph(para), rest)) => {
+                (self.rich_text(&para.children), self.blocks(rest))
+            }
+            _ => (Vec::new(), self.blocks(nodes)),
+        }
+    }
+
+    fn rich_text(&self, children: &[Node]) -> Vec<Value> {
+        inline::runs(children, &self.definitions)
+            .into_iter()
+            .flat_map(|run| match run.span {
+                Span::Text(text) => text_objects(text, run.style, run.link),
+                Span::Break => text_objects("\n", Style::default(), None),
+                // Inline images can't be embedded in text; link to them instead
+                Span::Image { url, alt } => {
+                    text_objects(if alt.is_empty() { url } else { alt }, run.style, Some(url))
+                }
+                Span::FootnoteReference(id) => {
+                    text_objects(&format!("[{}]", id), Style::default(), None)
+                }
+            })
+            .collect()
+    }
+}
+
+fn block(kind: &str, body: Value) -> Value {
+    json!({"object": "block", "type": kind, kind: body})
+}
+
+fn set_children(body: &mut Value, children: Vec<Value>) {
+    if !children.is_empty() {
+        body["children"] = json!(children);
+    }
+}
+
+fn code_block(lang: Option<&str>, code: &str) -> Value {
+    block(
+        "code",
+        json!({
+            "rich_text": text_objects(code, Style::default(), None),
+            "language": language(lang),
+        }),
+    )
+}
+
+/// Notion's name for a fence language, or "plain text" when it has none
+fn language(lang: Option<&str>) -> &'static str {
+    let Some(lang) = lang else {
+        return "plain text";
+    };
+    let lang = lang.to_lowercase();
+    let lang = match lang.as_str() {
+        "sh" | "zsh" => "shell",
+        "js" | "jsx" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "py" => "python",
+        "rb" => "ruby",
+        "rs" => "rust",
+        "cpp" | "cc" | "hpp" => "c++",
+        "cs" | "csharp" => "c#",
+        "objc" => "objective-c",
+        "yml" => "yaml",
+        "md" => "markdown",
+        "tex" => "latex",
+        "dockerfile" => "docker",
+        "kt" => "kotlin",
+        "ps1" => "powershell",
+        other => other,
+    };
+    LANGUAGES
+        .iter()
+        .find(|known| **known == lang)
+        .copied()
+        .unwrap_or("plain text")
+}
+
+/// Rich text objects for `text`, split into pieces Notion accepts
+fn text_objects(text: &str, style: Style, link: Option<&str>) -> Vec<Value> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(MAX_TEXT)
+        .map(|chunk| {
+            let content: String = chunk.iter().collect();
+            let mut object = json!({
+                "type": "text",
+                "text": {"content": content},
+            });
+            if let Some(url) = link {
+                object["text"]["link"] = json!({"url": url});
+            }
+            let annotations = annotations(style);
+            if !annotations.is_empty() {
+                object["annotations"] = Value::Object(annotations);
+            }
+            object
+        })
+        .collect()
+}
+
+/// Annotations for a run, leaving out the ones that are off
+fn annotations(style: Style) -> Map<String, Value> {
+    [
+        ("bold", style.strong),
+        ("italic", style.emphasis),
+        ("strikethrough", style.strikethrough),
+        ("code", style.code),
+    ]
+    .into_iter()
+    .filter(|(_, on)| *on)
+    .map(|(name, _)| (name.to_string(), Value::Bool(true)))
+    .collect()
+}
+
+fn icon(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::Note => "ℹ️",
+        AlertKind::Tip => "💡",
+        AlertKind::Important => "❗",
+        AlertKind::Warning => "⚠️",
+        AlertKind::Caution => "🛑",
+    }
+}
+
+fn color(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::Note => "blue_background",
+        AlertKind::Tip => "green_background",
+        AlertKind::Important => "purple_background",
+        AlertKind::Warning => "yellow_background",
+        AlertKind::Caution => "red_background",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn children(md: &str) -> Vec<Value> {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        let body: Value = serde_json::from_str(&mdast_to_notion(&ast)).unwrap();
+        body["children"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let blocks = children(
+            "#### Setup\n\n```rs\nfn main() {}\n```\n\n> [!TIP]\n> Back up first.\n\n\
+             | a | b |\n|---|---|\n| 1 |\n\n![Logo](https://x.io/logo.png)\n\n---\n",
+        );
+        assert_eq!(blocks[0]["object"], "block");
+        assert_eq!(blocks[0]["type"], "heading_3");
+        assert_eq!(
+            blocks[0]["heading_3"]["rich_text"][0]["text"]["content"],
+            "Setup"
+        );
+        assert_eq!(blocks[1]["code"]["language"], "rust");
+        assert_eq!(
+            blocks[1]["code"]["rich_text"][0]["text"]["content"],
+            "fn main() {}"
+        );
+        assert_eq!(blocks[2]["type"], "callout");
+        assert_eq!(blocks[2]["callout"]["icon"]["emoji"], "💡");
+        assert_eq!(
+            blocks[2]["callout"]["rich_text"][0]["text"]["content"],
+            "Back up first."
+        );
+
+        let table = &blocks[3]["table"];
+        assert_eq!(table["table_width"], 2);
+        assert_eq!(table["has_column_header"], true);
+        // Short rows are padded to the table width
+        assert_eq!(
+            table["children"][1]["table_row"]["cells"],
+            json!([[{"type": "text", "text": {"content": "1"}}], []])
+        );
+
+        assert_eq!(
+            blocks[4]["image"]["external"]["url"],
+            "https://x.io/logo.png"
+        );
+        assert_eq!(blocks[5]["type"], "divider");
+    }
+
+    #[test]
+    fn test_rich_text_and_lists() {
+        let blocks = children(
+            "**[bold `code`](https://example.com)**\n\n- [x] done\n- [ ] todo\n\n\
+             1. first\n\n   nested\n2. second\n",
+        );
+        let rich_text = &blocks[0]["paragraph"]["rich_text"];
+        assert_eq!(
+            rich_text[0],
+            json!({
+                "type": "text",
+                "text": {"content": "bold ", "link": {"url": "https://example.com"}},
+                "annotations": {"bold": true},
+            })
+        );
+        assert_eq!(
+            rich_text[1]["annotations"],
+            json!({"bold": true, "code": true})
+        );
+
+        assert_eq!(blocks[1]["type"], "to_do");
+        assert_eq!(blocks[1]["to_do"]["checked"], true);
+        assert_eq!(blocks[2]["to_do"]["checked"], false);
+        assert_eq!(blocks[3]["type"], "numbered_list_item");
+        assert_eq!(
+            blocks[3]["numbered_list_item"]["children"][0]["paragraph"]["rich_text"][0]["text"]["content"],
+            "nested"
+        );
+        assert_eq!(blocks[4]["type"], "numbered_list_item");
+    }
+
+    #[test]
+    fn test_long_text_is_split() {
+        let long = "x".repeat(MAX_TEXT + 10);
+        let blocks = children(&format!("```\n{}\n```", long));
+        let rich_text = blocks[0]["code"]["rich_text"].as_array().unwrap();
+        assert_eq!(blocks[0]["code"]["language"], "plain text");
+        assert_eq!(rich_text.len(), 2);
+        assert_eq!(rich_text[1]["text"]["content"], "x".repeat(10));
+    }
+}
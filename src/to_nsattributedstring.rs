@@ -37,7 +37,10 @@
 //! - **Code blocks** ✅: `NSPresentationIntent::codeBlock` with language hint
 //! - **Links** ✅: Clickable links using `NSLinkAttributeName`
 //! - **Strikethrough** ✅: Visual + `NSInlinePresentationIntent::Strikethrough`
-//! - **Lists** ✅: Using `NSTextList` with disc/decimal markers in paragraph style
+//! - **Lists** ✅: Using `NSTextList` with disc/circle/square/decimal markers in paragraph
+//!   style, one `NSTextList` per nesting level plus head indent for sublists
+//! - **Task lists** ✅: `☐`/`☑` prefix on task items (the checklist attribute Apple Notes
+//!   uses for interactive checkboxes is private, so there's no public marker to emit)
 //! - **Blockquotes** ✅: `NSPresentationIntent::blockQuote` + gray text
 //! - **Tables** ✅: Using `NSTextTable` and `NSTextTableBlock` with borders and padding
 //!
@@ -49,13 +52,19 @@
 
 use log::{debug, warn};
 use markdown::mdast::Node;
+use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::util::LinesWithEndings;
 
-use crate::config::ImageConfig;
-use crate::highlight::HighlightContext;
-use crate::image::{ImageCache, is_remote_url};
+use crate::alerts::{self, AlertKind};
+use crate::config::{CodeBadge, EmbedFormat, ImageConfig, NativeConfig, TableOverflow};
+use crate::details;
+use crate::grid;
+use crate::highlight::{self, CodeLines, HighlightContext};
+use crate::image::{
+    ImageCache, asset_stem, is_remote_url, optimize_image, transcode_for_embedding,
+};
+use crate::render::{self, Output, Renderer};
 
 use objc2::AnyThread;
 use objc2::rc::{Retained, autoreleasepool};
@@ -68,8 +77,8 @@ use objc2_app_kit::{
     NSForegroundColorAttributeName, NSImage, NSLinkAttributeName, NSMutableParagraphStyle,
     NSParagraphStyleAttributeName, NSPasteboard, NSPasteboardWriting,
     NSStrikethroughStyleAttributeName, NSTextAttachment, NSTextBlock, NSTextList,
-    NSTextListMarkerDecimal, NSTextListMarkerDisc, NSTextListOptions, NSTextTable,
-    NSTextTableBlock,
+    NSTextListMarkerCircle, NSTextListMarkerDecimal, NSTextListMarkerDisc, NSTextListMarkerSquare,
+    NSTextListOptions, NSTextTable, NSTextTableBlock,
 };
 use objc2_foundation::{
     NSAttributedString, NSDictionary, NSInlinePresentationIntent,
@@ -93,33 +102,38 @@ pub struct NativeConversionResult {
 /// attributed string can be written directly to NSPasteboard.
 ///
 /// Image handling: Images are always embedded in the NSAttributedString for native apps.
-/// The `image_config` only affects HTML generation:
+/// `ctx.image` only affects HTML generation:
 /// - `embed_local: true, embed_remote: true`: Convert to data URIs in HTML
 /// - `embed_local: true, embed_remote: false`: Data URIs for local, original URLs for remote
 /// - `embed_local: false, embed_remote: false`: Keep original URLs in HTML
 pub fn mdast_to_nsattributed_string(
     node: &Node,
-    base_dir: &Path,
-    image_config: &ImageConfig,
-    strict: bool,
-    highlight: Option<&HighlightContext>,
-    image_cache: &ImageCache,
+    render_ctx: &render::Context,
 ) -> Result<NativeConversionResult, String> {
     autoreleasepool(|_| {
         let attr_string = NSMutableAttributedString::new();
-        let mut ctx =
-            AttributedStringContext::new(base_dir, image_config, strict, highlight, image_cache);
+        let mut ctx = AttributedStringContext::new(render_ctx);
 
         node_to_attributed_string(node, &attr_string, &mut ctx)?;
 
         Ok(NativeConversionResult {
             attr_string,
             image_urls: ctx.image_urls,
-            image_config: image_config.clone(),
+            image_config: render_ctx.image.clone(),
         })
     })
 }
 
+pub struct NativeRenderer;
+
+impl Renderer for NativeRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_nsattributed_string(&details::folded(ast), ctx)
+            .map(Output::Native)
+            .map_err(io::Error::other)
+    }
+}
+
 /// Write NSAttributedString to the macOS pasteboard
 ///
 /// This writes the attributed string directly to NSPasteboard, allowing macOS apps
@@ -182,6 +196,134 @@ pub fn write_to_pasteboard(
     })
 }
 
+/// Write the attributed string, attachments included, as RTFD
+///
+/// Paths ending in `.rtfd` get a standard RTFD bundle (a directory that TextEdit
+/// opens as one document). Any other path, or `-` for stdout, gets the flat
+/// serialized form from `RTFDFromRange:documentAttributes:`.
+pub fn write_rtfd(result: &NativeConversionResult, path: &Path) -> Result<(), String> {
+    use objc2_app_kit::{
+        NSAttributedStringDocumentFormats, NSDocumentTypeDocumentAttribute, NSRTFDTextDocumentType,
+    };
+    use objc2_foundation::{NSFileWrapperWritingOptions, NSURL};
+
+    autoreleasepool(|_| unsafe {
+        let range = NSRange::new(0, result.attr_string.length());
+        let doc_type_key: &NSString = NSDocumentTypeDocumentAttribute;
+        let rtfd_type: &AnyObject = NSRTFDTextDocumentType.as_ref();
+        let doc_attrs: Retained<NSDictionary<NSString, AnyObject>> =
+            NSDictionary::from_slices(&[doc_type_key], &[rtfd_type]);
+
+        let is_bundle = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("rtfd"));
+        if is_bundle {
+            let wrapper = result
+                .attr_string
+                .fileWrapperFromRange_documentAttributes_error(range, &doc_attrs)
+                .map_err(|e| format!("Failed to serialize RTFD: {}", e.localizedDescription()))?;
+            let url = NSURL::fileURLWithPath(&NSString::from_str(&path.to_string_lossy()));
+            wrapper
+                .writeToURL_options_originalContentsURL_error(
+                    &url,
+                    NSFileWrapperWritingOptions::Atomic,
+                    None,
+                )
+                .map_err(|e| {
+                    format!(
+                        "Failed to write {}: {}",
+                        path.display(),
+                        e.localizedDescription()
+                    )
+                })?;
+        } else {
+            let data = result
+                .attr_string
+                .RTFDFromRange_documentAttributes(range, &doc_attrs)
+                .ok_or("Failed to serialize RTFD")?;
+            let bytes = data.as_bytes_unchecked();
+            if path.as_os_str() == "-" {
+                io::stdout().write_all(bytes)
+            } else {
+                fs::write(path, bytes)
+            }
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        debug!("Wrote RTFD to {}", path.display());
+        Ok(())
+    })
+}
+
+/// Draw the attributed string on white into a PNG `width` points wide,
+/// `scale` pixels per point, with `margin` points around the text
+pub fn write_png(
+    result: &NativeConversionResult,
+    width: f64,
+    margin: f64,
+    scale: f64,
+) -> Result<Vec<u8>, String> {
+    use objc2_app_kit::{
+        NSAttributedStringNSExtendedStringDrawing, NSBitmapImageFileType, NSBitmapImageRep,
+        NSDeviceRGBColorSpace, NSGraphicsContext, NSRectFill, NSStringDrawingOptions,
+    };
+    use objc2_foundation::{NSPoint, NSRect, NSSize};
+
+    autoreleasepool(|_| unsafe {
+        let string: &NSAttributedString = &result.attr_string;
+        let options = NSStringDrawingOptions::UsesLineFragmentOrigin
+            | NSStringDrawingOptions::UsesFontLeading;
+        let bounds = string.boundingRectWithSize_options_context(
+            NSSize::new(width - 2.0 * margin, f64::MAX),
+            options,
+            None,
+        );
+        let size = NSSize::new(width, bounds.size.height.ceil() + 2.0 * margin);
+
+        let rep = NSBitmapImageRep::initWithBitmapDataPlanes_pixelsWide_pixelsHigh_bitsPerSample_samplesPerPixel_hasAlpha_isPlanar_colorSpaceName_bytesPerRow_bitsPerPixel(
+            NSBitmapImageRep::alloc(),
+            std::ptr::null_mut(),
+            (size.width * scale) as isize,
+            (size.height * scale) as isize,
+            8,
+            4,
+            true,
+            false,
+            NSDeviceRGBColorSpace,
+            0,
+            0,
+        )
+        .ok_or("Failed to create a bitmap")?;
+        // Points, so drawing is scaled up to the pixel size
+        rep.setSize(size);
+        let context = NSGraphicsContext::graphicsContextWithBitmapImageRep(&rep)
+            .ok_or("Failed to create a bitmap graphics context")?;
+
+        NSGraphicsContext::saveGraphicsState_class();
+        NSGraphicsContext::setCurrentContext(Some(&context));
+        NSColor::whiteColor().setFill();
+        NSRectFill(NSRect::new(NSPoint::new(0.0, 0.0), size));
+        string.drawWithRect_options_context(
+            NSRect::new(
+                NSPoint::new(margin, margin),
+                NSSize::new(width - 2.0 * margin, bounds.size.height.ceil()),
+            ),
+            options,
+            None,
+        );
+        NSGraphicsContext::restoreGraphicsState_class();
+
+        let data = rep
+            .representationUsingType_properties(NSBitmapImageFileType::PNG, &NSDictionary::new())
+            .ok_or("Failed to encode PNG")?;
+        debug!(
+            "Drew the attributed string into a {}x{} PNG",
+            size.width * scale,
+            size.height * scale
+        );
+        Ok(data.to_vec())
+    })
+}
+
 /// Convert NSAttributedString to HTML, replacing file:// URLs based on image_config
 ///
 /// - embed_local + embed_remote: All images become data URIs
@@ -222,8 +364,8 @@ fn convert_to_html(result: &NativeConversionResult) -> Option<String> {
             {
                 let filename_str = filename.to_string();
 
-                // Only process images we explicitly handled (image_N.ext or remote_N.ext pattern)
-                if !filename_str.starts_with("image_") && !filename_str.starts_with("remote_") {
+                // Only process images we explicitly handled (tracked in image_urls)
+                if !result.image_urls.contains_key(&filename_str) {
                     index = effective_range.location + effective_range.length;
                     if effective_range.length == 0 {
                         index += 1;
@@ -312,26 +454,29 @@ struct AttributedStringContext<'a> {
     image_config: &'a ImageConfig,
     strict: bool,
     highlight: Option<&'a HighlightContext>,
+    /// Language label above fenced code blocks
+    code_badge: CodeBadge,
+    /// Table size limit
+    native: NativeConfig,
     image_cache: &'a ImageCache,
-    /// Maps generated filenames (image_N.ext) to original URLs for HTML post-processing
+    /// Maps generated attachment filenames to original URLs for HTML post-processing
     image_urls: std::collections::HashMap<String, String>,
+    /// Text lists of the enclosing lists, outermost first
+    text_lists: Vec<Retained<NSTextList>>,
 }
 
 impl<'a> AttributedStringContext<'a> {
-    fn new(
-        base_dir: &'a Path,
-        image_config: &'a ImageConfig,
-        strict: bool,
-        highlight: Option<&'a HighlightContext>,
-        image_cache: &'a ImageCache,
-    ) -> Self {
+    fn new(ctx: &render::Context<'a>) -> Self {
         Self {
-            base_dir,
-            image_config,
-            strict,
-            highlight,
-            image_cache,
+            base_dir: ctx.base_dir,
+            image_config: ctx.image,
+            strict: ctx.cfg.strict.images,
+            highlight: ctx.highlight,
+            code_badge: ctx.cfg.highlight.badge,
+            native: ctx.cfg.native,
+            image_cache: ctx.image_cache,
             image_urls: std::collections::HashMap::new(),
+            text_lists: Vec::new(),
         }
     }
 }
@@ -431,20 +576,18 @@ fn node_to_attributed_string(
         }
         Node::Code(code) => {
             let temp_string = NSMutableAttributedString::new();
+            let lines = CodeLines::new(code.lang.as_deref(), &code.value);
+            // Added and removed lines of a diff, shaded over the block's
+            // background once it's applied
+            let mut changed = Vec::new();
 
             if let Some(highlight_ctx) = ctx.highlight {
                 // Syntax highlighted code block
-                let syntax = code
-                    .lang
-                    .as_ref()
-                    .map(|lang| highlight_ctx.find_syntax(lang))
-                    .unwrap_or_else(|| highlight_ctx.syntax_set.find_syntax_plain_text());
-
-                let mut highlighter = HighlightLines::new(syntax, &highlight_ctx.theme);
+                let mut highlighter = highlight_ctx.highlighter(lines.lang, &code.value);
 
-                for line in LinesWithEndings::from(&code.value) {
-                    if let Ok(ranges) = highlighter.highlight_line(line, &highlight_ctx.syntax_set)
-                    {
+                for &(line, change) in &lines.lines {
+                    let start = temp_string.length();
+                    if let Ok(ranges) = highlighter.highlight_line(line) {
                         for (style, text) in ranges {
                             let text_without_newline = text.trim_end_matches('\n');
                             if !text_without_newline.is_empty() {
@@ -462,24 +605,49 @@ fn node_to_attributed_string(
                     } else {
                         append_text(&temp_string, line);
                     }
+                    if let Some(change) = change {
+                        let range = NSRange::new(start, temp_string.length() - start);
+                        changed.push((range, change.background(highlight_ctx.background())));
+                    }
                 }
             } else {
                 // Plain code block without highlighting
-                append_text(&temp_string, &code.value);
+                for &(line, change) in &lines.lines {
+                    let start = temp_string.length();
+                    append_text(&temp_string, line);
+                    if let Some(change) = change {
+                        let range = NSRange::new(start, temp_string.length() - start);
+                        changed.push((range, change.background(highlight::PLAIN_BACKGROUND)));
+                    }
+                }
             }
 
             append_text(&temp_string, "\n");
             let range = NSRange::new(0, temp_string.length());
-            apply_code_block(&temp_string, range, code.lang.as_deref(), ctx.highlight);
+            apply_code_block(&temp_string, range, lines.lang, ctx.highlight);
+            for (range, background) in changed {
+                apply_line_background(&temp_string, range, background);
+            }
+            if ctx.code_badge != CodeBadge::None
+                && let Some(lang) = lines.lang
+            {
+                append_code_badge(attr_string, lang);
+            }
             attr_string.appendAttributedString(&temp_string);
         }
         Node::List(list) => {
+            let depth = ctx.text_lists.len() + 1;
             unsafe {
-                // Create NSTextList with appropriate marker format
+                // Create NSTextList with appropriate marker format; unordered
+                // sublists cycle through disc, circle and square
                 let marker_format = if list.ordered {
                     NSTextListMarkerDecimal
                 } else {
-                    NSTextListMarkerDisc
+                    match depth % 3 {
+                        1 => NSTextListMarkerDisc,
+                        2 => NSTextListMarkerCircle,
+                        _ => NSTextListMarkerSquare,
+                    }
                 };
 
                 let start_number = list.start.unwrap_or(1) as isize;
@@ -489,42 +657,72 @@ fn node_to_attributed_string(
                     NSTextListOptions::empty(),
                     start_number,
                 );
+                ctx.text_lists.push(text_list);
+            }
 
-                // Create array containing just this list
-                let lists_array = objc2_foundation::NSArray::from_slice(&[&*text_list]);
-
-                // Process each list item
-                for child in &list.children {
-                    if let Node::ListItem(item) = child {
-                        let item_string = NSMutableAttributedString::new();
+            // Nested lists carry every enclosing NSTextList, outermost first,
+            // and are indented one step per level
+            let levels: Vec<&NSTextList> = ctx.text_lists.iter().map(|l| &**l).collect();
+            let lists_array = objc2_foundation::NSArray::from_slice(&levels);
+            let para_style = NSMutableParagraphStyle::new();
+            para_style.setTextLists(&lists_array);
+            let indent = LIST_INDENT * depth as f64;
+            para_style.setHeadIndent(indent);
+            para_style.setFirstLineHeadIndent(indent - LIST_INDENT / 2.0);
+
+            // Process each list item
+            for child in &list.children {
+                if let Node::ListItem(item) = child {
+                    // Task items get a checkbox glyph before their first paragraph
+                    let mut checkbox = item.checked.map(checkbox_marker);
+                    for item_child in &item.children {
+                        // Sublists apply their own paragraph style
+                        if matches!(item_child, Node::List(_)) {
+                            node_to_attributed_string(item_child, attr_string, ctx)?;
+                            continue;
+                        }
 
                         // Process item content (no manual bullet - NSTextList handles it)
-                        for item_child in &item.children {
-                            node_to_attributed_string(item_child, &item_string, ctx)?;
+                        let item_string = NSMutableAttributedString::new();
+                        if let Some(marker) = checkbox.take() {
+                            append_text(&item_string, marker);
                         }
+                        node_to_attributed_string(item_child, &item_string, ctx)?;
 
-                        // Create paragraph style with the text list
-                        let para_style = NSMutableParagraphStyle::new();
-                        para_style.setTextLists(&lists_array);
-
-                        // Apply paragraph style to the item
                         let range = NSRange::new(0, item_string.length());
-                        item_string.addAttribute_value_range(
-                            NSParagraphStyleAttributeName,
-                            &*para_style as &AnyObject,
-                            range,
-                        );
-
+                        unsafe {
+                            item_string.addAttribute_value_range(
+                                NSParagraphStyleAttributeName,
+                                &*para_style as &AnyObject,
+                                range,
+                            );
+                        }
                         attr_string.appendAttributedString(&item_string);
                     }
                 }
             }
-            append_text(attr_string, "\n");
+            ctx.text_lists.pop();
+
+            if depth == 1 {
+                append_text(attr_string, "\n");
+            }
         }
         Node::ListItem(_) => {
             // List items are handled by the parent List node
             // This branch handles orphaned list items (shouldn't happen in valid markdown)
         }
+        Node::Blockquote(quote) if let Some((kind, children)) = alerts::alert(quote) => {
+            let temp_string = NSMutableAttributedString::new();
+            append_text(&temp_string, &format!("{}\n", kind.label()));
+            let label_range = NSRange::new(0, temp_string.length());
+            apply_bold(&temp_string, label_range);
+            for child in &children {
+                node_to_attributed_string(child, &temp_string, ctx)?;
+            }
+            let range = NSRange::new(0, temp_string.length());
+            apply_alert(&temp_string, range, label_range, kind);
+            attr_string.appendAttributedString(&temp_string);
+        }
         Node::Blockquote(quote) => {
             let temp_string = NSMutableAttributedString::new();
             for child in &quote.children {
@@ -550,6 +748,14 @@ fn node_to_attributed_string(
     Ok(())
 }
 
+/// Head indent added per list nesting level, in points
+const LIST_INDENT: f64 = 36.0;
+
+/// Checkbox glyph prefixed to a task list item
+fn checkbox_marker(checked: bool) -> &'static str {
+    if checked { "☑ " } else { "☐ " }
+}
+
 /// Append plain text to attributed string
 fn append_text(attr_string: &NSMutableAttributedString, text: &str) {
     let ns_string = NSString::from_str(text);
@@ -588,6 +794,24 @@ fn append_highlighted_text(
     }
 }
 
+/// The code block's language on a line above it in small gray text.
+/// Attributed strings can't float a label into a corner, so both badge
+/// styles render this way.
+fn append_code_badge(attr_string: &NSMutableAttributedString, lang: &str) {
+    unsafe {
+        let label = NSMutableAttributedString::initWithString(
+            NSMutableAttributedString::alloc(),
+            &NSString::from_str(&format!("{}\n", lang)),
+        );
+        let range = NSRange::new(0, label.length());
+        let font = NSFont::systemFontOfSize(NSFont::smallSystemFontSize());
+        label.addAttribute_value_range(NSFontAttributeName, &font as &AnyObject, range);
+        let gray = NSColor::colorWithRed_green_blue_alpha(0.34, 0.38, 0.42, 1.0);
+        label.addAttribute_value_range(NSForegroundColorAttributeName, &gray as &AnyObject, range);
+        attr_string.appendAttributedString(&label);
+    }
+}
+
 /// Apply bold formatting to a range
 ///
 /// Applies both visual bold font and semantic StronglyEmphasized intent.
@@ -937,6 +1161,27 @@ fn apply_code_block(
     }
 }
 
+/// Shade an added or removed line of a diff code block
+fn apply_line_background(
+    attr_string: &NSMutableAttributedString,
+    range: NSRange,
+    color: syntect::highlighting::Color,
+) {
+    unsafe {
+        let ns_color = NSColor::colorWithRed_green_blue_alpha(
+            color.r as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.b as f64 / 255.0,
+            1.0,
+        );
+        attr_string.addAttribute_value_range(
+            NSBackgroundColorAttributeName,
+            &ns_color as &AnyObject,
+            range,
+        );
+    }
+}
+
 /// Apply blockquote formatting to a range
 ///
 /// Applies visual formatting (gray text) and semantic NSPresentationIntent.
@@ -965,6 +1210,46 @@ fn apply_blockquote(attr_string: &NSMutableAttributedString, range: NSRange) {
     }
 }
 
+/// Apply alert formatting to a range
+///
+/// A blockquote intent with a background tinted in the alert's color, and the
+/// label in that color.
+fn apply_alert(
+    attr_string: &NSMutableAttributedString,
+    range: NSRange,
+    label_range: NSRange,
+    kind: AlertKind,
+) {
+    static INTENT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(3000);
+
+    let (r, g, b) = kind.color();
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    unsafe {
+        let identity = INTENT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst) as isize;
+        let quote_intent =
+            NSPresentationIntent::blockQuoteIntentWithIdentity_nestedInsideIntent(identity, None);
+        attr_string.addAttribute_value_range(
+            NSPresentationIntentAttributeName,
+            &*quote_intent as &AnyObject,
+            range,
+        );
+
+        let tint = NSColor::colorWithRed_green_blue_alpha(r, g, b, 0.1);
+        attr_string.addAttribute_value_range(
+            NSBackgroundColorAttributeName,
+            &tint as &AnyObject,
+            range,
+        );
+
+        let accent = NSColor::colorWithRed_green_blue_alpha(r, g, b, 1.0);
+        attr_string.addAttribute_value_range(
+            NSForegroundColorAttributeName,
+            &accent as &AnyObject,
+            label_range,
+        );
+    }
+}
+
 /// Render image as a clickable link (fallback when embedding fails)
 fn render_image_as_link(attr_string: &NSMutableAttributedString, url: &str, alt: &str) {
     let start = attr_string.length();
@@ -1004,6 +1289,7 @@ fn embed_image(
         optimize_remote: should_optimize_remote,
         max_dimension: ctx.image_config.max_dimension,
         quality: ctx.image_config.quality,
+        ..ctx.image_config.clone()
     };
 
     // Use the ImageCache for consistent behavior with HTML/RTF
@@ -1024,6 +1310,51 @@ fn embed_image(
         }
     };
 
+    // Images over the inline threshold are replaced by a thumbnail that links to
+    // the original, so giant pastes don't make apps like Notes sluggish
+    let inline_max_bytes = ctx.image_config.native_inline_max_kb * 1024;
+    let link_original = inline_max_bytes > 0 && embedded.data.len() as u64 > inline_max_bytes;
+    let embedded = if link_original {
+        let thumb_config = ImageConfig {
+            max_dimension: ctx.image_config.native_thumbnail_dimension,
+            format: EmbedFormat::Auto,
+            ..load_config.clone()
+        };
+        match optimize_image(&embedded.data, &thumb_config) {
+            Ok(thumb) => {
+                debug!(
+                    "Linking large image as thumbnail: {} ({} -> {} bytes)",
+                    url,
+                    embedded.data.len(),
+                    thumb.data.len()
+                );
+                thumb
+            }
+            Err(e) => {
+                warn!("Failed to create thumbnail for {}: {}", url, e);
+                render_image_as_link(attr_string, url, alt);
+                return Ok(());
+            }
+        }
+    } else {
+        embedded
+    };
+
+    // Apps reading the attachments (Pages, Word, Mail) don't all show WebP,
+    // so images optimized to it go back to JPEG or PNG
+    let embedded = if embedded.mime_type == "image/webp" {
+        match transcode_for_embedding(&embedded, ctx.image_config.quality, EmbedFormat::Auto) {
+            Ok(converted) => converted,
+            Err(e) => {
+                warn!("Failed to convert WebP image {}: {}", url, e);
+                render_image_as_link(attr_string, url, alt);
+                return Ok(());
+            }
+        }
+    } else {
+        embedded
+    };
+
     // Create NSData from the bytes
     let ns_data = objc2_foundation::NSData::with_bytes(&embedded.data);
 
@@ -1046,14 +1377,15 @@ fn embed_image(
         _ => "png",
     };
 
-    // Generate unique filename
-    static IMAGE_COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
-    let counter = IMAGE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-    let filename = if is_remote {
-        format!("remote_{}.{}", counter, extension)
-    } else {
-        format!("image_{}.{}", counter, extension)
-    };
+    // Name the attachment after its source; repeated images get a numeric suffix
+    // so names stay unique within the bundle
+    let stem = asset_stem(url);
+    let mut filename = format!("{}.{}", stem, extension);
+    let mut n = 2;
+    while ctx.image_urls.contains_key(&filename) {
+        filename = format!("{}-{}.{}", stem, n, extension);
+        n += 1;
+    }
 
     // Set the image name - this is what HTML conversion uses for src attribute
     ns_image.setName(Some(&NSString::from_str(&filename)));
@@ -1067,10 +1399,6 @@ fn embed_image(
     attachment.setImage(Some(&ns_image));
     attachment.setFileWrapper(Some(&file_wrapper));
 
-    // Create attributed string from attachment
-    let attachment_string = NSAttributedString::attributedStringWithAttachment(&attachment);
-    attr_string.appendAttributedString(&attachment_string);
-
     // Track original URL for HTML post-processing
     // For local files, store the absolute path so HTML can reference it
     let tracked_url = if is_remote {
@@ -1082,19 +1410,77 @@ fn embed_image(
             abs_path.canonicalize().unwrap_or(abs_path).display()
         )
     };
+
+    // Create attributed string from attachment, linking thumbnails to the original
+    let attachment_string = NSAttributedString::attributedStringWithAttachment(&attachment);
+    let temp_string = NSMutableAttributedString::new();
+    temp_string.appendAttributedString(&attachment_string);
+    if link_original {
+        let range = NSRange::new(0, temp_string.length());
+        apply_link(&temp_string, range, &tracked_url);
+    }
+    attr_string.appendAttributedString(&temp_string);
+
     ctx.image_urls.insert(filename, tracked_url);
 
     debug!("Image embedded with fileWrapper: {}", url);
     Ok(())
 }
 
+/// Render a table, falling back to a monospace grid or several smaller
+/// tables when it has more rows than `NativeConfig::table_max_rows`. Pages
+/// can hang on paste when an NSTextTable gets large.
+fn render_table(
+    attr_string: &NSMutableAttributedString,
+    table: &markdown::mdast::Table,
+    ctx: &mut AttributedStringContext,
+) -> Result<(), String> {
+    let max_rows = ctx.native.table_max_rows;
+    let body_rows = table.children.len().saturating_sub(1);
+    if max_rows == 0 || body_rows <= max_rows {
+        return render_text_table(attr_string, table, ctx);
+    }
+    match ctx.native.table_overflow {
+        TableOverflow::Grid => {
+            warn!(
+                "Table with {} rows is over the native limit of {}; pasted as a text grid",
+                body_rows, max_rows
+            );
+            let grid = grid::table_grid(&grid::table_cells(table), &table.align);
+            let temp_string = NSMutableAttributedString::new();
+            append_text(&temp_string, &grid);
+            let range = NSRange::new(0, temp_string.length());
+            apply_code_block(&temp_string, range, None, None);
+            if attr_string.length() > 0 {
+                append_text(attr_string, "\n");
+            }
+            attr_string.appendAttributedString(&temp_string);
+        }
+        TableOverflow::Split => {
+            let chunks = body_rows.div_ceil(max_rows);
+            warn!(
+                "Table with {} rows is over the native limit of {}; split into {} tables",
+                body_rows, max_rows, chunks
+            );
+            for rows in table.children[1..].chunks(max_rows) {
+                let mut part = table.clone();
+                part.children = std::iter::once(table.children[0].clone())
+                    .chain(rows.iter().cloned())
+                    .collect();
+                render_text_table(attr_string, &part, ctx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Render a table using NSTextTable and NSTextTableBlock
 ///
 /// This uses the NSTextTable API to create proper table layouts. Each cell gets
 /// its own NSTextTableBlock which is attached to the text via NSParagraphStyle.
 /// Cell content is recursively rendered, so all formatting (bold, italic, code, etc.)
 /// works inside table cells.
-fn render_table(
+fn render_text_table(
     attr_string: &NSMutableAttributedString,
     table: &markdown::mdast::Table,
     ctx: &mut AttributedStringContext,
@@ -1203,6 +1589,7 @@ fn render_table(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
     use crate::image::ImageCache;
     use markdown::{Constructs, Options, ParseOptions};
 
@@ -1217,24 +1604,37 @@ mod tests {
         markdown::to_mdast(md, &options.parse).unwrap()
     }
 
-    fn test_image_config() -> ImageConfig {
-        ImageConfig {
-            embed_local: true,
-            embed_remote: true,
-            optimize_local: false,
-            optimize_remote: false,
-            max_dimension: 1200,
-            quality: 80,
+    fn test_config() -> Config {
+        Config {
+            image: ImageConfig {
+                embed_local: true,
+                embed_remote: true,
+                optimize_local: false,
+                optimize_remote: false,
+                max_dimension: 1200,
+                quality: 80,
+                ..ImageConfig::default()
+            },
+            ..Config::default()
         }
     }
 
+    fn convert(ast: &Node, cfg: &Config) -> Result<NativeConversionResult, String> {
+        let ctx = render::Context {
+            cfg,
+            base_dir: Path::new("."),
+            highlight: None,
+            image: &cfg.image,
+            image_cache: &ImageCache::new(),
+            front_matter: None,
+        };
+        mdast_to_nsattributed_string(ast, &ctx)
+    }
+
     #[test]
     fn test_basic_text() {
         let ast = parse_markdown("Hello world");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
         let conversion = result.unwrap();
         assert!(conversion.attr_string.length() > 0);
@@ -1243,110 +1643,113 @@ mod tests {
     #[test]
     fn test_bold_text() {
         let ast = parse_markdown("**bold**");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_italic_text() {
         let ast = parse_markdown("*italic*");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_bold_italic() {
         let ast = parse_markdown("***bold and italic***");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_heading() {
         let ast = parse_markdown("# Heading 1");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_inline_code() {
         let ast = parse_markdown("`code`");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_link() {
         let ast = parse_markdown("[example](https://example.com)");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_strikethrough() {
         let ast = parse_markdown("~~deleted~~");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_mixed_formatting() {
         let ast = parse_markdown("**bold** and `code` and [link](url) and ~~strike~~");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_code_block() {
         let ast = parse_markdown("```rust\nfn main() {}\n```");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_list() {
         let ast = parse_markdown("- Item 1\n- Item 2\n- Item 3");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_nested_list_text_lists() {
+        let ast = parse_markdown("- Outer\n  - Inner\n- Next");
+        let result = convert(&ast, &test_config()).unwrap();
+
+        let text = result.attr_string.string().to_string();
+        let style_at = |needle: &str| {
+            let index = text.find(needle).unwrap();
+            let value = unsafe {
+                result.attr_string.attribute_atIndex_effectiveRange(
+                    NSParagraphStyleAttributeName,
+                    index,
+                    std::ptr::null_mut(),
+                )
+            }
+            .unwrap();
+            let style = value.downcast::<objc2_app_kit::NSParagraphStyle>().unwrap();
+            (style.textLists().count(), style.headIndent())
+        };
+
+        assert_eq!(style_at("Outer"), (1, LIST_INDENT));
+        assert_eq!(style_at("Inner"), (2, LIST_INDENT * 2.0));
+        assert_eq!(style_at("Next"), (1, LIST_INDENT));
+    }
+
+    #[test]
+    fn test_task_list_checkboxes() {
+        let ast = parse_markdown("- [ ] Todo\n- [x] Done\n- Plain");
+        let result = convert(&ast, &test_config()).unwrap();
+
+        let text = result.attr_string.string().to_string();
+        assert!(text.contains("☐ Todo"));
+        assert!(text.contains("☑ Done"));
+        assert!(!text.contains("☐ Plain"));
+    }
+
     #[test]
     fn test_blockquote() {
         let ast = parse_markdown("> This is a quote\n> with multiple lines");
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
@@ -1358,10 +1761,7 @@ mod tests {
              | Cell 1   | Cell 2   |\n\
              | Cell 3   | Cell 4   |",
         );
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
 
@@ -1372,10 +1772,31 @@ mod tests {
              |----------|----------|\n\
              | `code`   | [link](url) |",
         );
-        let cache = ImageCache::new();
-        let config = test_image_config();
-        let result =
-            mdast_to_nsattributed_string(&ast, Path::new("."), &config, false, None, &cache);
+        let result = convert(&ast, &test_config());
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_table_over_row_limit() {
+        let ast = parse_markdown("| A |\n|---|\n| 1 |\n| 2 |\n| 3 |");
+        let render = |table_overflow| {
+            let config = Config {
+                native: NativeConfig {
+                    table_max_rows: 2,
+                    table_overflow,
+                },
+                ..test_config()
+            };
+            convert(&ast, &config)
+                .unwrap()
+                .attr_string
+                .string()
+                .to_string()
+        };
+        let grid = render(TableOverflow::Grid);
+        assert!(grid.contains("│ 3 │"), "{}", grid);
+        // Each part repeats the header
+        let split = render(TableOverflow::Split);
+        assert_eq!(split.matches('A').count(), 2, "{}", split);
+    }
 }
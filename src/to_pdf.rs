@@ -0,0 +1,1366 @@
+//! PDF documents laid out straight from the tree, for one-off files to share.
+//! Text is set in the standard PDF fonts (Helvetica and Courier), which every
+//! reader ships, so only images get embedded. Those fonts cover Windows-1252,
+//! so other characters (CJK, emoji) show up as `?`.
+
+use crate::alerts;
+use crate::anchors::Anchors;
+use crate::config::ImageConfig;
+use crate::details;
+use crate::highlight::{self, CodeLines, HighlightContext};
+use crate::image::{self, ImageCache, ImageError, Pixels};
+use crate::inline::{self, Definitions, Span};
+use crate::render::{self, Output, Renderer};
+use log::warn;
+use markdown::mdast::{AlignKind, List, Node, Table};
+use miniz_oxide::deflate::{CompressionLevel, compress_to_vec_zlib};
+use pdf_writer::types::{ActionType, AnnotationType};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use syntect::highlighting::FontStyle;
+
+/// A4, in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.0;
+const HEADING_SIZES: [f32; 6] = [20.0, 16.0, 13.5, 12.0, 11.0, 11.0];
+/// Line height as a multiple of the font size
+const LEADING: f32 = 1.4;
+const BLOCK_GAP: f32 = 8.0;
+/// Indent of list items and quotes
+const INDENT: f32 = 18.0;
+/// Padding inside code blocks and table cells
+const PADDING: f32 = 5.0;
+/// Images are laid out at 96 dpi, as browsers do
+const POINTS_PER_PIXEL: f32 = 0.75;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Rgb(f32, f32, f32);
+
+const TEXT_COLOR: Rgb = Rgb(0.14, 0.16, 0.18);
+const MUTED_COLOR: Rgb = Rgb(0.4, 0.43, 0.47);
+const LINK_COLOR: Rgb = Rgb(0.02, 0.39, 0.76);
+const RULE_COLOR: Rgb = Rgb(0.82, 0.84, 0.87);
+const SHADE_COLOR: Rgb = Rgb(0.95, 0.96, 0.97);
+
+pub fn mdast_to_pdf(
+    root: &Node,
+    base_dir: &Path,
+    image_config: &ImageConfig,
+    strict: bool,
+    highlight: Option<&HighlightContext>,
+    image_cache: &ImageCache,
+    title: Option<&str>,
+) -> Result<Vec<u8>, ImageError> {
+    let mut ctx = PdfContext {
+        base_dir,
+        image_config,
+        strict,
+        highlight,
+        image_cache,
+        definitions: Definitions::collect(root),
+        anchors: Anchors::collect(root),
+        images: Vec::new(),
+        image_index: HashMap::new(),
+        content_index: HashMap::new(),
+        layout: Layout::new(),
+    };
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    ctx.blocks(
+        root.children().map_or(&[][..], Vec::as_slice),
+        MARGIN,
+        width,
+    )?;
+    Ok(write_pdf(ctx.layout.pages, &ctx.images, title))
+}
+
+pub struct PdfRenderer;
+
+impl Renderer for PdfRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_pdf(
+            &details::folded(ast),
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.highlight,
+            ctx.image_cache,
+            ctx.front_matter.and_then(|fm| fm.title.as_deref()),
+        )
+        .map(Output::Bytes)
+        .map_err(io::Error::other)
+    }
+}
+
+/// The standard fonts in use, all with WinAnsiEncoding
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+    MonoBold,
+}
+
+impl Font {
+    const ALL: [Font; 6] = [
+        Font::Regular,
+        Font::Bold,
+        Font::Italic,
+        Font::BoldItalic,
+        Font::Mono,
+        Font::MonoBold,
+    ];
+
+    fn resource(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"F1",
+            Font::Bold => b"F2",
+            Font::Italic => b"F3",
+            Font::BoldItalic => b"F4",
+            Font::Mono => b"F5",
+            Font::MonoBold => b"F6",
+        })
+    }
+
+    fn base_font(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"Helvetica",
+            Font::Bold => b"Helvetica-Bold",
+            Font::Italic => b"Helvetica-Oblique",
+            Font::BoldItalic => b"Helvetica-BoldOblique",
+            Font::Mono => b"Courier",
+            Font::MonoBold => b"Courier-Bold",
+        })
+    }
+
+    fn styled(bold: bool, italic: bool, mono: bool) -> Font {
+        match (mono, bold, italic) {
+            (true, false, _) => Font::Mono,
+            (true, true, _) => Font::MonoBold,
+            (false, false, false) => Font::Regular,
+            (false, true, false) => Font::Bold,
+            (false, false, true) => Font::Italic,
+            (false, true, true) => Font::BoldItalic,
+        }
+    }
+
+    /// Width of `text` set at `size`, from the fonts' AFM metrics
+    fn width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text
+            .chars()
+            .map(|c| {
+                let code = win_ansi(c);
+                match self {
+                    Font::Mono | Font::MonoBold => 600,
+                    _ if code < 32 => 0,
+                    Font::Bold | Font::BoldItalic => HELVETICA_BOLD[code as usize - 32] as u32,
+                    Font::Regular | Font::Italic => HELVETICA[code as usize - 32] as u32,
+                }
+            })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// The Windows-1252 byte for `c`, or `?` when it has none
+fn win_ansi(c: char) -> u8 {
+    match c as u32 {
+        0x20..=0x7E | 0xA0..=0xFF => c as u32 as u8,
+        _ => match c {
+            '€' => 0x80,
+            '‚' => 0x82,
+            'ƒ' => 0x83,
+            '„' => 0x84,
+            '…' => 0x85,
+            '†' => 0x86,
+            '‡' => 0x87,
+            'ˆ' => 0x88,
+            '‰' => 0x89,
+            'Š' => 0x8A,
+            '‹' => 0x8B,
+            'Œ' => 0x8C,
+            'Ž' => 0x8E,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '˜' => 0x98,
+            '™' => 0x99,
+            'š' => 0x9A,
+            '›' => 0x9B,
+            'œ' => 0x9C,
+            'ž' => 0x9E,
+            'Ÿ' => 0x9F,
+            '\t' => b' ',
+            _ => b'?',
+        },
+    }
+}
+
+fn encode(text: &str) -> Vec<u8> {
+    text.chars().map(win_ansi).collect()
+}
+
+/// A stretch of text in one style
+#[derive(Clone, Debug)]
+struct Piece {
+    text: String,
+    font: Font,
+    size: f32,
+    color: Rgb,
+    link: Option<String>,
+    strike: bool,
+    /// Inline code sits on a tinted background
+    shade: bool,
+}
+
+impl Piece {
+    fn plain(text: impl Into<String>, font: Font, size: f32, color: Rgb) -> Self {
+        Piece {
+            text: text.into(),
+            font,
+            size,
+            color,
+            link: None,
+            strike: false,
+            shade: false,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Piece {
+        Piece {
+            text: text.to_string(),
+            ..self.clone()
+        }
+    }
+
+    fn width(&self) -> f32 {
+        self.font.width(&self.text, self.size)
+    }
+}
+
+fn width_of(pieces: &[Piece]) -> f32 {
+    pieces.iter().map(Piece::width).sum()
+}
+
+enum Token {
+    /// Pieces with no space between them, and whether a space came before
+    Word(Vec<Piece>, bool),
+    Break,
+}
+
+fn tokens(pieces: &[Piece]) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut word: Option<(Vec<Piece>, bool)> = None;
+    let mut space = false;
+    let flush = |word: &mut Option<(Vec<Piece>, bool)>, out: &mut Vec<Token>| {
+        if let Some((pieces, space)) = word.take() {
+            out.push(Token::Word(pieces, space));
+        }
+    };
+    for piece in pieces {
+        for (i, segment) in piece.text.split('\n').enumerate() {
+            if i > 0 {
+                flush(&mut word, &mut out);
+                out.push(Token::Break);
+                space = false;
+            }
+            for (j, part) in segment.split(char::is_whitespace).enumerate() {
+                if j > 0 {
+                    flush(&mut word, &mut out);
+                    space = true;
+                }
+                if part.is_empty() {
+                    continue;
+                }
+                let (fragments, _) = word.get_or_insert_with(|| (Vec::new(), space));
+                fragments.push(piece.with_text(part));
+                space = false;
+            }
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// Break `pieces` into lines no wider than `width`: at spaces and hard
+/// breaks, and inside words too long for a line of their own
+fn break_lines(pieces: &[Piece], width: f32) -> Vec<Vec<Piece>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<Piece> = Vec::new();
+    let mut line_width = 0.0;
+    for token in tokens(pieces) {
+        let (word, space) = match token {
+            Token::Break => {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+                continue;
+            }
+            Token::Word(word, space) => (word, space),
+        };
+        let word_width = width_of(&word);
+        let space = (space && !line.is_empty()).then(|| {
+            let first = &word[0];
+            let previous = line.last().and_then(|p: &Piece| p.link.as_ref());
+            Piece {
+                // A space inside a link belongs to it
+                link: first.link.clone().filter(|link| previous == Some(link)),
+                ..Piece::plain(" ", first.font, first.size, first.color)
+            }
+        });
+        let space_width = space.as_ref().map_or(0.0, Piece::width);
+        if !line.is_empty() && line_width + space_width + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+        } else if let Some(space) = space {
+            line.push(space);
+            line_width += space_width;
+        }
+        if word_width > width {
+            let mut chunks = split_word(&word, width);
+            let last = chunks.pop().unwrap_or_default();
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.extend(chunks);
+            line_width = width_of(&last);
+            line = last;
+        } else {
+            line.extend(word);
+            line_width += word_width;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// A word cut into lines of at most `width` (at least one character each)
+fn split_word(word: &[Piece], width: f32) -> Vec<Vec<Piece>> {
+    let mut lines = vec![Vec::new()];
+    let mut line_width = 0.0;
+    for piece in word {
+        let mut text = String::new();
+        for c in piece.text.chars() {
+            let c_width = piece.font.width(c.encode_utf8(&mut [0; 4]), piece.size);
+            let line = lines.last_mut().expect("lines is never empty");
+            if line_width + c_width > width && (line_width > 0.0 || !text.is_empty()) {
+                if !text.is_empty() {
+                    line.push(piece.with_text(&std::mem::take(&mut text)));
+                }
+                lines.push(Vec::new());
+                line_width = 0.0;
+            }
+            text.push(c);
+            line_width += c_width;
+        }
+        if !text.is_empty() {
+            lines
+                .last_mut()
+                .expect("lines is never empty")
+                .push(piece.with_text(&text));
+        }
+    }
+    lines
+}
+
+/// Monospace `pieces` cut every `columns` characters
+fn chop(pieces: &[Piece], columns: usize) -> Vec<Vec<Piece>> {
+    let mut lines = vec![Vec::new()];
+    let mut used = 0;
+    for piece in pieces {
+        let mut rest = piece.text.as_str();
+        while !rest.is_empty() {
+            if used == columns {
+                lines.push(Vec::new());
+                used = 0;
+            }
+            let take = rest
+                .char_indices()
+                .nth(columns - used)
+                .map_or(rest.len(), |(i, _)| i);
+            let (head, tail) = rest.split_at(take);
+            lines
+                .last_mut()
+                .expect("lines is never empty")
+                .push(piece.with_text(head));
+            used += head.chars().count();
+            rest = tail;
+        }
+    }
+    lines
+}
+
+struct Page {
+    content: Content,
+    /// Link areas and their targets
+    links: Vec<(Rect, String)>,
+    /// Headings starting on this page and the top of their first line
+    anchors: Vec<(String, f32)>,
+}
+
+/// Pages being filled top to bottom
+struct Layout {
+    pages: Vec<Page>,
+    /// Top of the free space on the current page
+    y: f32,
+    /// Space owed before the next block, dropped at the top of a page
+    gap: f32,
+    /// Left edges and colors of the bars of enclosing quotes
+    bars: Vec<(f32, Rgb)>,
+    /// List marker and its left edge, waiting for the item's first line
+    marker: Option<(Vec<Piece>, f32)>,
+}
+
+impl Layout {
+    fn new() -> Self {
+        let mut layout = Layout {
+            pages: Vec::new(),
+            y: 0.0,
+            gap: 0.0,
+            bars: Vec::new(),
+            marker: None,
+        };
+        layout.new_page();
+        layout
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page {
+            content: Content::new(),
+            links: Vec::new(),
+            anchors: Vec::new(),
+        });
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn at_top(&self) -> bool {
+        self.y >= PAGE_HEIGHT - MARGIN
+    }
+
+    fn content(&mut self) -> &mut Content {
+        &mut self
+            .pages
+            .last_mut()
+            .expect("there is always a page")
+            .content
+    }
+
+    /// Ask for `gap` before the next block (the larger request wins)
+    fn gap(&mut self, gap: f32) {
+        self.gap = self.gap.max(gap);
+    }
+
+    /// Make room for `height` below the owed gap, starting a page if needed
+    fn room(&mut self, height: f32) {
+        let gap = std::mem::take(&mut self.gap);
+        if self.at_top() {
+            return;
+        }
+        if self.y - gap - height < MARGIN {
+            self.new_page();
+        } else {
+            self.advance(gap);
+        }
+    }
+
+    /// Move down `height`, extending the quote bars alongside
+    fn advance(&mut self, height: f32) {
+        let y = self.y - height;
+        for (x, color) in self.bars.clone() {
+            fill_rect(self.content(), x, y, 3.0, height, color);
+        }
+        self.y = y;
+    }
+
+    fn draw_marker(&mut self, baseline: f32) {
+        if let Some((marker, x)) = self.marker.take() {
+            self.draw_pieces(&marker, x, baseline);
+        }
+    }
+
+    /// One line of text at `x`, as tall as its largest piece
+    fn line(&mut self, pieces: &[Piece], x: f32, size: f32) {
+        let size = pieces.iter().map(|p| p.size).fold(size, f32::max);
+        let height = size * LEADING;
+        self.room(height);
+        let baseline = self.y - (height - size) / 2.0 - size * 0.8;
+        self.draw_marker(baseline);
+        self.draw_pieces(pieces, x, baseline);
+        self.advance(height);
+    }
+
+    fn draw_pieces(&mut self, pieces: &[Piece], mut x: f32, baseline: f32) {
+        let page = self.pages.last_mut().expect("there is always a page");
+        for piece in pieces {
+            let width = piece.width();
+            let size = piece.size;
+            let content = &mut page.content;
+            if piece.shade {
+                let bottom = baseline - size * 0.3;
+                fill_rect(
+                    content,
+                    x - 1.0,
+                    bottom,
+                    width + 2.0,
+                    size * 1.25,
+                    SHADE_COLOR,
+                );
+            }
+            let Rgb(r, g, b) = piece.color;
+            content.set_fill_rgb(r, g, b);
+            content
+                .begin_text()
+                .set_font(piece.font.resource(), size)
+                .next_line(x, baseline)
+                .show(Str(&encode(&piece.text)))
+                .end_text();
+            let rule = if piece.strike {
+                Some(baseline + size * 0.3)
+            } else if piece.link.is_some() {
+                Some(baseline - size * 0.15)
+            } else {
+                None
+            };
+            if let Some(y) = rule {
+                content
+                    .set_stroke_rgb(r, g, b)
+                    .set_line_width(size / 16.0)
+                    .move_to(x, y)
+                    .line_to(x + width, y)
+                    .stroke();
+            }
+            if let Some(url) = &piece.link {
+                let area = Rect::new(x, baseline - size * 0.25, x + width, baseline + size * 0.9);
+                page.links.push((area, url.clone()));
+            }
+            x += width;
+        }
+    }
+
+    /// A horizontal rule across `width`, in the middle of `height`
+    fn rule(&mut self, x: f32, width: f32, height: f32) {
+        self.room(height);
+        let y = self.y - height / 2.0;
+        let Rgb(r, g, b) = RULE_COLOR;
+        self.content()
+            .set_stroke_rgb(r, g, b)
+            .set_line_width(0.75)
+            .move_to(x, y)
+            .line_to(x + width, y)
+            .stroke();
+        self.advance(height);
+    }
+}
+
+fn fill_rect(content: &mut Content, x: f32, y: f32, width: f32, height: f32, color: Rgb) {
+    let Rgb(r, g, b) = color;
+    content
+        .set_fill_rgb(r, g, b)
+        .rect(x, y, width, height)
+        .fill_nonzero();
+}
+
+fn rgb(color: syntect::highlighting::Color) -> Rgb {
+    Rgb(
+        color.r as f32 / 255.0,
+        color.g as f32 / 255.0,
+        color.b as f32 / 255.0,
+    )
+}
+
+struct PdfContext<'a> {
+    base_dir: &'a Path,
+    image_config: &'a ImageConfig,
+    strict: bool,
+    highlight: Option<&'a HighlightContext>,
+    image_cache: &'a ImageCache,
+    definitions: Definitions<'a>,
+    anchors: Anchors,
+    /// Decoded images, each embedded once however often it's used
+    images: Vec<Pixels>,
+    /// Index into `images` by source, and by content for the same picture
+    /// under another source
+    image_index: HashMap<String, usize>,
+    content_index: HashMap<String, usize>,
+    layout: Layout,
+}
+
+impl PdfContext<'_> {
+    fn blocks(&mut self, nodes: &[Node], x: f32, width: f32) -> Result<(), ImageError> {
+        for node in nodes {
+            self.block(node, x, width)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, node: &Node, x: f32, width: f32) -> Result<(), ImageError> {
+        match node {
+            Node::Heading(heading) => {
+                let size = HEADING_SIZES[(heading.depth as usize).clamp(1, 6) - 1];
+                self.layout.gap(size);
+                // Settle the page first, so the anchor marks where the
+                // heading really starts
+                self.layout.room(size * LEADING);
+                if let Some(slug) = self.anchors.next() {
+                    let y = self.layout.y;
+                    let page = self
+                        .layout
+                        .pages
+                        .last_mut()
+                        .expect("there is always a page");
+                    page.anchors.push((slug.to_string(), y));
+                }
+                self.flow(&heading.children, x, width, size, true)?;
+                if heading.depth <= 2 {
+                    self.layout.rule(x, width, 6.0);
+                }
+                self.layout.gap(BLOCK_GAP * 0.75);
+            }
+            Node::Paragraph(para) => {
+                self.flow(&para.children, x, width, BODY_SIZE, false)?;
+                self.layout.gap(BLOCK_GAP);
+            }
+            Node::Code(code) => self.code(&code.value, code.lang.as_deref(), x, width),
+            Node::Math(math) => self.code(&math.value, None, x, width),
+            Node::Blockquote(quote) => {
+                self.layout.bars.push((x, RULE_COLOR));
+                match alerts::alert(quote) {
+                    Some((kind, children)) => {
+                        let (r, g, b) = kind.color();
+                        let color = Rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+                        if let Some(bar) = self.layout.bars.last_mut() {
+                            bar.1 = color;
+                        }
+                        let label = Piece::plain(kind.label(), Font::Bold, BODY_SIZE, color);
+                        self.layout.line(&[label], x + INDENT, BODY_SIZE);
+                        self.blocks(&children, x + INDENT, width - INDENT)?;
+                    }
+                    None => self.blocks(&quote.children, x + INDENT, width - INDENT)?,
+                }
+                self.layout.bars.pop();
+                self.layout.gap(BLOCK_GAP);
+            }
+            Node::List(list) => self.list(list, x, width)?,
+            Node::Table(table) => self.table(table, x, width),
+            Node::ThematicBreak(_) => {
+                self.layout.gap(BLOCK_GAP);
+                self.layout.rule(x, width, 12.0);
+                self.layout.gap(BLOCK_GAP);
+            }
+            Node::Html(raw) if crate::concat::is_page_break(&raw.value) => {
+                if !self.layout.at_top() {
+                    self.layout.new_page();
+                }
+                self.layout.gap = 0.0;
+            }
+            Node::FootnoteDefinition(def) => {
+                let label = format!("[{}]", def.identifier);
+                let marker = Piece::plain(label, Font::Regular, BODY_SIZE, MUTED_COLOR);
+                self.layout.marker = Some((vec![marker], x));
+                self.blocks(&def.children, x + INDENT * 1.5, width - INDENT * 1.5)?;
+                self.layout.marker = None;
+            }
+            // Raw HTML can't be laid out, and definitions are resolved
+            // where they're used
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Inline content wrapped to `width`, with images on lines of their own
+    fn flow(
+        &mut self,
+        children: &[Node],
+        x: f32,
+        width: f32,
+        size: f32,
+        bold: bool,
+    ) -> Result<(), ImageError> {
+        let runs = inline::runs(children, &self.definitions);
+        let mut pieces = Vec::new();
+        for run in &runs {
+            let style = run.style;
+            let text = match run.span {
+                Span::Text(text) => text.to_string(),
+                Span::Break => "\n".to_string(),
+                Span::FootnoteReference(id) => format!("[{}]", id),
+                Span::Image { url, alt } => {
+                    self.lines(&std::mem::take(&mut pieces), x, width, size);
+                    self.image(url, alt, run.link, x, width)?;
+                    continue;
+                }
+            };
+            let font = Font::styled(bold || style.strong, style.emphasis, style.code);
+            let color = if run.link.is_some() {
+                LINK_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            pieces.push(Piece {
+                link: run
+                    .link
+                    .map(|url| self.anchors.resolve_url(url).into_owned()),
+                strike: style.strikethrough,
+                shade: style.code,
+                ..Piece::plain(
+                    text,
+                    font,
+                    if style.code { size * 0.9 } else { size },
+                    color,
+                )
+            });
+        }
+        self.lines(&pieces, x, width, size);
+        Ok(())
+    }
+
+    fn lines(&mut self, pieces: &[Piece], x: f32, width: f32, size: f32) {
+        if pieces.is_empty() {
+            return;
+        }
+        for line in break_lines(pieces, width) {
+            self.layout.line(&line, x, size);
+        }
+    }
+
+    fn code(&mut self, code: &str, lang: Option<&str>, x: f32, width: f32) {
+        let text = code.replace('\t', "    ");
+        let code = CodeLines::new(lang, &text);
+        let (lines, background, tinted): (Vec<Vec<Piece>>, _, _) = match self.highlight {
+            Some(hl) => {
+                let mut highlighter = hl.highlighter(code.lang, &text);
+                let lines = code
+                    .lines
+                    .iter()
+                    .map(|&(line, _)| match highlighter.highlight_line(line) {
+                        Ok(ranges) => ranges
+                            .into_iter()
+                            .map(|(style, text)| {
+                                let bold = style.font_style.contains(FontStyle::BOLD);
+                                let font = Font::styled(bold, false, true);
+                                let text = text.trim_end_matches(['\n', '\r']);
+                                Piece::plain(text, font, CODE_SIZE, rgb(style.foreground))
+                            })
+                            .collect(),
+                        Err(_) => vec![Piece::plain(
+                            line.trim_end(),
+                            Font::Mono,
+                            CODE_SIZE,
+                            TEXT_COLOR,
+                        )],
+                    })
+                    .collect();
+                (
+                    lines,
+                    hl.theme.settings.background.map_or(SHADE_COLOR, rgb),
+                    hl.background(),
+                )
+            }
+            None => (
+                code.lines
+                    .iter()
+                    .map(|&(line, _)| {
+                        let line = line.trim_end_matches(['\n', '\r']);
+                        vec![Piece::plain(line, Font::Mono, CODE_SIZE, TEXT_COLOR)]
+                    })
+                    .collect(),
+                SHADE_COLOR,
+                highlight::PLAIN_BACKGROUND,
+            ),
+        };
+
+        let columns =
+            ((width - 2.0 * PADDING) / Font::Mono.width(" ", CODE_SIZE)).max(1.0) as usize;
+        let height = CODE_SIZE * LEADING;
+        self.layout.room(PADDING + height);
+        let y = self.layout.y;
+        fill_rect(
+            self.layout.content(),
+            x,
+            y - PADDING,
+            width,
+            PADDING,
+            background,
+        );
+        self.layout.advance(PADDING);
+        for (line, &(_, change)) in lines.iter().zip(&code.lines) {
+            let background = change.map_or(background, |change| rgb(change.background(tinted)));
+            for row in chop(line, columns) {
+                self.layout.room(height);
+                let y = self.layout.y;
+                fill_rect(
+                    self.layout.content(),
+                    x,
+                    y - height,
+                    width,
+                    height,
+                    background,
+                );
+                let baseline = y - (height - CODE_SIZE) / 2.0 - CODE_SIZE * 0.8;
+                self.layout.draw_marker(baseline);
+                self.layout.draw_pieces(&row, x + PADDING, baseline);
+                self.layout.advance(height);
+            }
+        }
+        let y = self.layout.y;
+        fill_rect(
+            self.layout.content(),
+            x,
+            y - PADDING,
+            width,
+            PADDING,
+            background,
+        );
+        self.layout.advance(PADDING);
+        self.layout.gap(BLOCK_GAP);
+    }
+
+    fn list(&mut self, list: &List, x: f32, width: f32) -> Result<(), ImageError> {
+        let start = list.start.unwrap_or(1);
+        for (index, item) in list.children.iter().enumerate() {
+            let Node::ListItem(item) = item else {
+                continue;
+            };
+            let marker = match item.checked {
+                Some(checked) => {
+                    let text = if checked { "[x]" } else { "[ ]" };
+                    Piece::plain(text, Font::Mono, CODE_SIZE, TEXT_COLOR)
+                }
+                None if list.ordered => {
+                    let text = format!("{}.", start as usize + index);
+                    Piece::plain(text, Font::Regular, BODY_SIZE, TEXT_COLOR)
+                }
+                None => Piece::plain("•", Font::Regular, BODY_SIZE, TEXT_COLOR),
+            };
+            // Markers end just before the item's text
+            let marker_x = (x + INDENT - marker.width() - 4.0).max(x);
+            self.layout.marker = Some((vec![marker], marker_x));
+            self.blocks(&item.children, x + INDENT, width - INDENT)?;
+            self.layout.marker = None;
+            self.layout.gap = if list.spread { BLOCK_GAP } else { 2.0 };
+        }
+        self.layout.gap(BLOCK_GAP);
+        Ok(())
+    }
+
+    fn table(&mut self, table: &Table, x: f32, width: f32) {
+        let rows: Vec<Vec<Vec<Piece>>> = table
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                row.children()
+                    .into_iter()
+                    .flatten()
+                    .map(|cell| self.cell_pieces(cell, index == 0))
+                    .collect()
+            })
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        // Each column gets its natural width if everything fits, otherwise
+        // the slack beyond its longest word in proportion
+        let mut natural = vec![0.0f32; columns];
+        let mut minimum = vec![0.0f32; columns];
+        for row in &rows {
+            for (column, cell) in row.iter().enumerate() {
+                natural[column] = natural[column].max(width_of(cell) + 2.0 * PADDING);
+                let longest = tokens(cell)
+                    .iter()
+                    .map(|token| match token {
+                        Token::Word(word, _) => width_of(word),
+                        Token::Break => 0.0,
+                    })
+                    .fold(0.0, f32::max);
+                minimum[column] = minimum[column].max(longest.min(120.0) + 2.0 * PADDING);
+            }
+        }
+        let total_natural: f32 = natural.iter().sum();
+        let total_minimum: f32 = minimum.iter().sum();
+        let widths: Vec<f32> = if total_natural <= width {
+            natural
+        } else if total_minimum >= width {
+            minimum.iter().map(|m| m * width / total_minimum).collect()
+        } else {
+            let share = (width - total_minimum) / (total_natural - total_minimum);
+            (0..columns)
+                .map(|c| minimum[c] + (natural[c] - minimum[c]) * share)
+                .collect()
+        };
+
+        let line_height = BODY_SIZE * LEADING;
+        for (index, row) in rows.iter().enumerate() {
+            let cells: Vec<Vec<Vec<Piece>>> = (0..columns)
+                .map(|c| match row.get(c) {
+                    Some(cell) => break_lines(cell, widths[c] - 2.0 * PADDING),
+                    None => Vec::new(),
+                })
+                .collect();
+            let lines = cells.iter().map(Vec::len).max().unwrap_or(1).max(1);
+            let height = lines as f32 * line_height + 2.0 * PADDING;
+            self.layout.room(height);
+            let top = self.layout.y;
+            let mut cell_x = x;
+            for (column, lines) in cells.iter().enumerate() {
+                let cell_width = widths[column];
+                let content = self.layout.content();
+                if index == 0 {
+                    fill_rect(
+                        content,
+                        cell_x,
+                        top - height,
+                        cell_width,
+                        height,
+                        SHADE_COLOR,
+                    );
+                }
+                let Rgb(r, g, b) = RULE_COLOR;
+                content
+                    .set_stroke_rgb(r, g, b)
+                    .set_line_width(0.75)
+                    .rect(cell_x, top - height, cell_width, height)
+                    .stroke();
+                let align = table.align.get(column).unwrap_or(&AlignKind::None);
+                for (n, line) in lines.iter().enumerate() {
+                    let slack = cell_width - 2.0 * PADDING - width_of(line);
+                    let offset = match align {
+                        AlignKind::Right => slack,
+                        AlignKind::Center => slack / 2.0,
+                        AlignKind::Left | AlignKind::None => 0.0,
+                    };
+                    let line_top = top - PADDING - n as f32 * line_height;
+                    let baseline = line_top - (line_height - BODY_SIZE) / 2.0 - BODY_SIZE * 0.8;
+                    self.layout
+                        .draw_pieces(line, cell_x + PADDING + offset.max(0.0), baseline);
+                }
+                cell_x += cell_width;
+            }
+            self.layout.advance(height);
+        }
+        self.layout.gap(BLOCK_GAP);
+    }
+
+    fn cell_pieces(&self, cell: &Node, header: bool) -> Vec<Piece> {
+        let children = cell.children().map_or(&[][..], Vec::as_slice);
+        inline::runs(children, &self.definitions)
+            .iter()
+            .filter_map(|run| {
+                let style = run.style;
+                let text = match run.span {
+                    Span::Text(text) => text.to_string(),
+                    Span::Break => "\n".to_string(),
+                    Span::FootnoteReference(id) => format!("[{}]", id),
+                    Span::Image { alt, .. } if !alt.is_empty() => alt.to_string(),
+                    Span::Image { .. } => return None,
+                };
+                let font = Font::styled(header || style.strong, style.emphasis, style.code);
+                let color = if run.link.is_some() {
+                    LINK_COLOR
+                } else {
+                    TEXT_COLOR
+                };
+                Some(Piece {
+                    link: run
+                        .link
+                        .map(|url| self.anchors.resolve_url(url).into_owned()),
+                    strike: style.strikethrough,
+                    shade: style.code,
+                    ..Piece::plain(text, font, BODY_SIZE, color)
+                })
+            })
+            .collect()
+    }
+
+    /// An image scaled to fit the column and the page, or its alt text
+    /// when it can't be loaded or decoded
+    fn image(
+        &mut self,
+        url: &str,
+        alt: &str,
+        link: Option<&str>,
+        x: f32,
+        width: f32,
+    ) -> Result<(), ImageError> {
+        let Some(index) = self.load_image(url)? else {
+            let text = if alt.is_empty() {
+                format!("[image: {}]", url)
+            } else {
+                format!("[image: {}]", alt)
+            };
+            let piece = Piece::plain(text, Font::Italic, BODY_SIZE, MUTED_COLOR);
+            self.lines(&[piece], x, width, BODY_SIZE);
+            return Ok(());
+        };
+        let pixels = &self.images[index];
+        let (natural_width, natural_height) = (
+            pixels.width as f32 * POINTS_PER_PIXEL,
+            pixels.height as f32 * POINTS_PER_PIXEL,
+        );
+        let scale = (width / natural_width)
+            .min((PAGE_HEIGHT - 2.0 * MARGIN) / natural_height)
+            .min(1.0);
+        let (w, h) = (natural_width * scale, natural_height * scale);
+
+        self.layout.room(h);
+        let bottom = self.layout.y - h;
+        self.layout.draw_marker(self.layout.y - BODY_SIZE);
+        let name = image_name(index);
+        self.layout
+            .content()
+            .save_state()
+            .transform([w, 0.0, 0.0, h, x, bottom])
+            .x_object(Name(name.as_bytes()))
+            .restore_state();
+        if let Some(url) = link {
+            let page = self
+                .layout
+                .pages
+                .last_mut()
+                .expect("there is always a page");
+            page.links
+                .push((Rect::new(x, bottom, x + w, bottom + h), url.to_string()));
+        }
+        self.layout.advance(h);
+        self.layout.gap(BLOCK_GAP / 2.0);
+        Ok(())
+    }
+
+    fn load_image(&mut self, url: &str) -> Result<Option<usize>, ImageError> {
+        if let Some(&index) = self.image_index.get(url) {
+            return Ok(Some(index));
+        }
+        let Some(img) =
+            self.image_cache
+                .get_or_load(url, self.base_dir, self.image_config, self.strict)?
+        else {
+            return Ok(None);
+        };
+        let digest = image::content_digest(&img.data);
+        if let Some(&index) = self.content_index.get(&digest) {
+            self.image_index.insert(url.to_string(), index);
+            return Ok(Some(index));
+        }
+        match image::decode_pixels(&img) {
+            Ok(pixels) => {
+                self.images.push(pixels);
+                let index = self.images.len() - 1;
+                self.image_index.insert(url.to_string(), index);
+                self.content_index.insert(digest, index);
+                Ok(Some(index))
+            }
+            Err(e) => {
+                warn!(
+                    "PDF can't embed {} images, showing alt text: {} ({})",
+                    img.mime_type, url, e
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn image_name(index: usize) -> String {
+    format!("Im{}", index + 1)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    compress_to_vec_zlib(data, CompressionLevel::DefaultLevel as u8)
+}
+
+/// Assemble the document: pages with numbered footers and their links,
+/// the fonts and the images, all sharing one resource dictionary
+fn write_pdf(pages: Vec<Page>, images: &[Pixels], title: Option<&str>) -> Vec<u8> {
+    let mut ids = (1..).map(Ref::new);
+    let mut next_id = || ids.next().expect("ids are unbounded");
+    let catalog_id = next_id();
+    let tree_id = next_id();
+    let font_ids: Vec<Ref> = Font::ALL.iter().map(|_| next_id()).collect();
+    let image_ids: Vec<(Ref, Ref)> = images.iter().map(|_| (next_id(), next_id())).collect();
+    let page_ids: Vec<(Ref, Ref)> = pages.iter().map(|_| (next_id(), next_id())).collect();
+    let dests_id = next_id();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id)
+        .pages(tree_id)
+        .destinations(dests_id);
+    {
+        // Named destinations for `#fragment` links, one per heading
+        let mut dests = pdf.destinations(dests_id);
+        for (page, (page_id, _)) in pages.iter().zip(&page_ids) {
+            for (slug, y) in &page.anchors {
+                dests
+                    .insert(Name(slug.as_bytes()))
+                    .page(*page_id)
+                    .xyz(0.0, *y, None);
+            }
+        }
+    }
+    let known: HashSet<String> = pages
+        .iter()
+        .flat_map(|page| page.anchors.iter().map(|(slug, _)| format!("#{}", slug)))
+        .collect();
+    {
+        let mut tree = pdf.pages(tree_id);
+        tree.kids(page_ids.iter().map(|(page, _)| *page))
+            .count(pages.len() as i32);
+        let mut resources = tree.resources();
+        resources.fonts().pairs(
+            Font::ALL
+                .iter()
+                .map(|f| f.resource())
+                .zip(font_ids.iter().copied()),
+        );
+        let names: Vec<String> = (0..images.len()).map(image_name).collect();
+        resources.x_objects().pairs(
+            names
+                .iter()
+                .map(|name| Name(name.as_bytes()))
+                .zip(image_ids.iter().map(|(image, _)| *image)),
+        );
+    }
+
+    let count = pages.len();
+    for (number, (page, (page_id, content_id))) in pages.into_iter().zip(&page_ids).enumerate() {
+        let mut content = page.content;
+        if count > 1 {
+            let footer = format!("{} / {}", number + 1, count);
+            let x = (PAGE_WIDTH - Font::Regular.width(&footer, 9.0)) / 2.0;
+            let Rgb(r, g, b) = MUTED_COLOR;
+            content
+                .set_fill_rgb(r, g, b)
+                .begin_text()
+                .set_font(Font::Regular.resource(), 9.0)
+                .next_line(x, MARGIN / 2.0)
+                .show(Str(footer.as_bytes()))
+                .end_text();
+        }
+        pdf.stream(*content_id, &deflate(&content.finish()))
+            .filter(Filter::FlateDecode);
+
+        let mut writer = pdf.page(*page_id);
+        writer
+            .parent(tree_id)
+            .media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .contents(*content_id);
+        let mut annotations = writer.annotations();
+        for (area, url) in &page.links {
+            // Fragments that name no heading lead nowhere
+            let internal = url.starts_with('#');
+            if internal && !known.contains(url) {
+                continue;
+            }
+            let mut annotation = annotations.push();
+            annotation
+                .subtype(AnnotationType::Link)
+                .rect(*area)
+                .border(0.0, 0.0, 0.0, None);
+            let mut action = annotation.action();
+            if internal {
+                action
+                    .action_type(ActionType::GoTo)
+                    .destination_named(Name(&url.as_bytes()[1..]));
+            } else {
+                action.action_type(ActionType::Uri).uri(Str(url.as_bytes()));
+            }
+        }
+        annotations.finish();
+    }
+
+    for (font, id) in Font::ALL.iter().zip(&font_ids) {
+        pdf.type1_font(*id)
+            .base_font(font.base_font())
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (pixels, (image_id, mask_id)) in images.iter().zip(&image_ids) {
+        let data = deflate(&pixels.rgb);
+        let mut image = pdf.image_xobject(*image_id, &data);
+        image.filter(Filter::FlateDecode);
+        image
+            .width(pixels.width as i32)
+            .height(pixels.height as i32)
+            .bits_per_component(8);
+        image.color_space().device_rgb();
+        if pixels.alpha.is_some() {
+            image.s_mask(*mask_id);
+        }
+        image.finish();
+        if let Some(alpha) = &pixels.alpha {
+            let data = deflate(alpha);
+            let mut mask = pdf.image_xobject(*mask_id, &data);
+            mask.filter(Filter::FlateDecode);
+            mask.width(pixels.width as i32)
+                .height(pixels.height as i32)
+                .bits_per_component(8);
+            mask.color_space().device_gray();
+        }
+    }
+
+    let mut info = pdf.document_info(next_id());
+    info.producer(TextStr("mdcopy"));
+    if let Some(title) = title {
+        info.title(TextStr(title));
+    }
+    info.finish();
+
+    pdf.finish()
+}
+
+/// Helvetica glyph widths for WinAnsi codes 32 to 255, in 1/1000 em
+const HELVETICA: [u16; 224] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+    350, 556, 350, 222, 556, 333, 1000, 556, 556, 333, 1000, 667, 333, 1000, 350, 611, 350, 350,
+    222, 222, 333, 333, 350, 556, 1000, 333, 1000, 500, 333, 944, 350, 500, 667, 278, 333, 556,
+    556, 556, 556, 260, 556, 333, 737, 370, 556, 584, 333, 737, 333, 400, 584, 333, 333, 333, 556,
+    537, 278, 333, 333, 365, 556, 834, 834, 834, 611, 667, 667, 667, 667, 667, 667, 1000, 722, 667,
+    667, 667, 667, 278, 278, 278, 278, 722, 722, 778, 778, 778, 778, 778, 584, 778, 722, 722, 722,
+    722, 667, 667, 611, 556, 556, 556, 556, 556, 556, 889, 500, 556, 556, 556, 556, 278, 278, 278,
+    278, 556, 556, 556, 556, 556, 556, 556, 584, 611, 556, 556, 556, 556, 500, 556, 500,
+];
+
+/// Helvetica-Bold glyph widths for WinAnsi codes 32 to 255
+const HELVETICA_BOLD: [u16; 224] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+    350, 556, 350, 278, 556, 500, 1000, 556, 556, 333, 1000, 667, 333, 1000, 350, 611, 350, 350,
+    278, 278, 500, 500, 350, 556, 1000, 333, 1000, 556, 333, 944, 350, 500, 667, 278, 333, 556,
+    556, 556, 556, 280, 556, 333, 737, 370, 556, 584, 333, 737, 333, 400, 584, 333, 333, 333, 611,
+    556, 278, 333, 333, 365, 556, 834, 834, 834, 611, 722, 722, 722, 722, 722, 722, 1000, 722, 667,
+    667, 667, 667, 278, 278, 278, 278, 722, 722, 778, 778, 778, 778, 778, 584, 778, 722, 722, 722,
+    722, 667, 667, 611, 556, 556, 556, 556, 556, 556, 889, 556, 556, 556, 556, 556, 278, 278, 278,
+    278, 611, 611, 611, 611, 611, 611, 611, 584, 611, 611, 611, 611, 611, 556, 611, 556,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn pdf(md: &str) -> Vec<u8> {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        let config = ImageConfig::default();
+        mdast_to_pdf(
+            &ast,
+            Path::new("."),
+            &config,
+            false,
+            None,
+            &ImageCache::with_config(&config),
+            Some("Notes"),
+        )
+        .unwrap()
+    }
+
+    fn text(pieces: &[Piece]) -> String {
+        pieces.iter().map(|p| p.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_break_lines() {
+        let piece = |s: &str| Piece::plain(s, Font::Mono, 10.0, TEXT_COLOR);
+        // Courier is 6pt a character at 10pt
+        let lines = break_lines(&[piece("one two "), piece("three\nfour")], 48.0);
+        let lines: Vec<String> = lines.iter().map(|l| text(l)).collect();
+        assert_eq!(lines, vec!["one two", "three", "four"]);
+
+        let lines = break_lines(&[piece("a abcdefghijkl")], 30.0);
+        let lines: Vec<String> = lines.iter().map(|l| text(l)).collect();
+        assert_eq!(lines, vec!["a", "abcde", "fghij", "kl"]);
+
+        // Words keep their styles across pieces
+        let bold = Piece::plain("bold", Font::Bold, 10.0, TEXT_COLOR);
+        let lines = break_lines(&[bold, piece(", then")], 1000.0);
+        assert_eq!(lines[0][0].font, Font::Bold);
+        assert_eq!(text(&lines[0]), "bold, then");
+    }
+
+    #[test]
+    fn test_metrics_and_encoding() {
+        assert_eq!(Font::Regular.width("Hi", 10.0), (722.0 + 222.0) / 100.0);
+        assert_eq!(Font::Mono.width("“x”", 10.0), 18.0);
+        assert_eq!(encode("“Café” — ok ✓"), b"\x93Caf\xe9\x94 \x97 ok ?");
+    }
+
+    #[test]
+    fn test_document() {
+        let long: String = (0..120).map(|n| format!("Paragraph {}.\n\n", n)).collect();
+        let out = pdf(&format!(
+            "# Title\n\nSee [docs](https://x.io), [below](#Title-1) and [nowhere](#x).\n\n\
+             ## Title\n\n```\ncode\n```\n\n\
+             | a | b |\n|---|---|\n| 1 | 2 |\n\n{}",
+            long
+        ));
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.starts_with("%PDF-"));
+        assert!(out.contains("/BaseFont /Helvetica-Bold"));
+        assert!(out.contains("/URI (https://x.io)"));
+        // Each heading is a named destination, and fragment links go to them
+        assert!(out.contains("/title [") && out.contains("/title-1 ["));
+        assert!(out.contains("/S /GoTo\n      /D /title-1"));
+        assert!(!out.contains("/D /x"));
+        assert!(out.contains("/Title (Notes)"));
+        // 120 paragraphs don't fit on one page
+        let count: usize = out
+            .split("/Count ")
+            .nth(1)
+            .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|n| n.parse().ok())
+            .unwrap();
+        assert!(count > 1, "{} page(s)", count);
+    }
+
+    #[test]
+    fn test_same_image_embedded_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let png = image::encode_png(&Pixels {
+            width: 2,
+            height: 2,
+            rgb: vec![200; 12],
+            alpha: None,
+        })
+        .unwrap();
+        std::fs::create_dir(dir.path().join("img")).unwrap();
+        std::fs::write(dir.path().join("logo.png"), &png).unwrap();
+        std::fs::write(dir.path().join("img/copy.png"), &png).unwrap();
+
+        let ast = markdown::to_mdast(
+            "![a](logo.png)\n\n![b](img/copy.png)\n\n![c](./logo.png)\n",
+            &ParseOptions::gfm(),
+        )
+        .unwrap();
+        let config = ImageConfig::default();
+        let out = mdast_to_pdf(
+            &ast,
+            dir.path(),
+            &config,
+            true,
+            None,
+            &ImageCache::with_config(&config),
+            None,
+        )
+        .unwrap();
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.contains("/Im1 ") && !out.contains("/Im2"));
+    }
+}
@@ -0,0 +1,369 @@
+//! ProseMirror slice in the Atlassian document format, the JSON the Confluence
+//! Cloud and Jira editors put on the clipboard next to HTML. Pasting it keeps
+//! code blocks, panels, tables and task lists as native editor nodes instead
+//! of whatever the editor makes of our HTML.
+
+use crate::alerts::{self, AlertKind};
+use crate::details;
+use crate::image::is_remote_url;
+use crate::inline::{self, Definitions, Span, Style};
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{AlignKind, List, Node};
+use serde_json::{Value, json};
+use std::cell::Cell;
+use std::io;
+
+/// Clipboard type the Atlassian editor reads slices from
+pub const MIME_TYPE: &str = "application/x-vnd.atlassian.fabric-editor-slice+json";
+
+pub fn mdast_to_prosemirror(root: &Node) -> String {
+    let ctx = PmContext {
+        definitions: Definitions::collect(root),
+        next_id: Cell::new(0),
+    };
+    let content = ctx.blocks(root.children().map_or(&[][..], Vec::as_slice));
+    json!({
+        "content": content,
+        "openStart": 0,
+        "openEnd": 0,
+    })
+    .to_string()
+}
+
+pub struct ProseMirrorRenderer;
+
+impl Renderer for ProseMirrorRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_prosemirror(&details::folded(ast))))
+    }
+}
+
+struct PmContext<'a> {
+    definitions: Definitions<'a>,
+    /// Counter for the `localId` task items need
+    next_id: Cell<usize>,
+}
+
+impl PmContext<'_> {
+    fn blocks(&self, nodes: &[Node]) -> Vec<Value> {
+        nodes.iter().flat_map(|node| self.block(node)).collect()
+    }
+
+    fn block(&self, node: &Node) -> Vec<Value> {
+        match node {
+            Node::Paragraph(para) => match para.children.as_slice() {
+                // A remote image on its own becomes a media node; local ones
+                // would need an upload, so they stay links
+                [Node::Image(image)] if is_remote_url(&image.url) => vec![json!({
+                    "type": "mediaSingle",
+                    "attrs": {"layout": "center"},
+                    "content": [{
+                        "type": "media",
+                        "attrs": {"type": "external", "url": image.url, "alt": image.alt},
+                    }],
+                })],
+                children => vec![paragraph(self.inlines(children))],
+            },
+            Node::Heading(heading) => vec![json!({
+                "type": "heading",
+                "attrs": {"level": heading.depth},
+                "content": self.inlines(&heading.children),
+            })],
+            Node::Code(code) => vec![code_block(code.lang.as_deref(), &code.value)],
+            Node::Math(math) => vec![code_block(Some("latex"), &math.value)],
+            Node::Blockquote(quote) => match alerts::alert(quote) {
+                Some((kind, children)) => vec![json!({
+                    "type": "panel",
+                    "attrs": {"panelType": panel_type(kind)},
+                    "content": self.blocks(&children),
+                })],
+                None => vec![json!({
+                    "type": "blockquote",
+                    "content": self.blocks(&quote.children),
+                })],
+            },
+            Node::List(list) => vec![self.list(list)],
+            Node::Table(table) => {
+                let rows: Vec<Value> = table
+                    .children
+                    .iter()
+                    .enumerate()
+                    .map(|(index, row)| {
+                        let cell_type = if index == 0 {
+                            "tableHeader"
+                        } else {
+                            "tableCell"
+                        };
+                        let cells: Vec<Value> = row
+                            .children()
+                            .into_iter()
+                            .flatten()
+                            .enumerate()
+                            .map(|(column, cell)| {
+                                let mut para = paragraph(
+                                    self.inlines(cell.children().map_or(&[][..], Vec::as_slice)),
+                                );
+                                if let Some(align) = table.align.get(column).and_then(alignment) {
+                                    para["marks"] =
+                                        json!([{"type": "alignment", "attrs": {"align": align}}]);
+                                }
+                                json!({"type": cell_type, "attrs": {}, "content": [para]})
+                            })
+                            .collect();
+                        json!({"type": "tableRow", "content": cells})
+                    })
+                    .collect();
+                vec![json!({
+                    "type": "table",
+                    "attrs": {"isNumberColumnEnabled": false, "layout": "default"},
+                    "content": rows,
+                })]
+            }
+            Node::ThematicBreak(_) => vec![json!({"type": "rule"})],
+            Node::FootnoteDefinition(def) => {
+                let mut content = self.blocks(&def.children);
+                // Lead with the label the references point at
+                if let Some(inlines) = content
+                    .first_mut()
+                    .filter(|block| block["type"] == "paragraph")
+                    .and_then(|block| block["content"].as_array_mut())
+                {
+                    inlines.insert(0, superscript(&format!("{} ", def.identifier)));
+                }
+                content
+            }
+            // Raw HTML, definitions and front matter have no editor node
+            _ => Vec::new(),
+        }
+    }
+
+    fn list(&self, list: &List) -> Value {
+        let is_task_list = !list.children.is_empty()
+            && list
+                .children
+                .iter()
+                .all(|item| matches!(item, Node::ListItem(item) if item.checked.is_some()));
+        if is_task_list {
+            return self.task_list(list);
+        }
+        let items: Vec<Value> = list
+            .children
+            .iter()
+            .map(|item| {
+                json!({
+                    "type": "listItem",
+                    "content": self.blocks(item.children().map_or(&[][..], Vec::as_slice)),
+                })
+            })
+            .collect();
+        if list.ordered {
+            json!({
+                "type": "orderedList",
+                "attrs": {"order": list.start.unwrap_or(1)},
+                "content": items,
+            })
+        } else {
+            json!({"type": "bulletList", "content": items})
+        }
+    }
+
+    /// Task items only hold inline content: paragraphs are joined with line
+    /// breaks and nested task lists follow the item
+    fn task_list(&self, list: &List) -> Value {
+        let mut content = Vec::new();
+        for node in &list.children {
+            let Node::ListItem(item) = node else {
+                continue;
+            };
+            let mut inlines = Vec::new();
+            let mut nested = Vec::new();
+            for child in &item.children {
+                match child {
+                    Node::Paragraph(para) => {
+                        if !inlines.is_empty() {
+                            inlines.push(json!({"type": "hardBreak"}));
+                        }
+                        inlines.extend(self.inlines(&para.children));
+                    }
+                    Node::List(list) => nested.push(self.list(list)),
+                    _ => {}
+                }
+            }
+            let state = if item.checked == Some(true) {
+                "DONE"
+            } else {
+                "TODO"
+            };
+            content.push(json!({
+                "type": "taskItem",
+                "attrs": {"localId": self.local_id(), "state": state},
+                "content": inlines,
+            }));
+            // Plain lists can't nest in a task list
+            content.extend(nested.into_iter().filter(|list| list["type"] == "taskList"));
+        }
+        json!({
+            "type": "taskList",
+            "attrs": {"localId": self.local_id()},
+            "content": content,
+        })
+    }
+
+    fn local_id(&self) -> String {
+        let id = self.next_id.replace(self.next_id.get() + 1);
+        format!("mdcopy-{}", id)
+    }
+
+    fn inlines(&self, children: &[Node]) -> Vec<Value> {
+        inline::runs(children, &self.definitions)
+            .into_iter()
+            .filter_map(|run| match run.span {
+                Span::Text("") => None,
+                Span::Text(text) => Some(text_node(text, marks(run.style, run.link))),
+                Span::Break => Some(json!({"type": "hardBreak"})),
+                // Inline images can't be media nodes; link to them instead
+                Span::Image { url, alt } => Some(text_node(
+                    if alt.is_empty() { url } else { alt },
+                    marks(Style::default(), Some(url)),
+                )),
+                Span::FootnoteReference(id) => Some(superscript(id)),
+            })
+            .collect()
+    }
+}
+
+fn paragraph(content: Vec<Value>) -> Value {
+    json!({"type": "paragraph", "content": content})
+}
+
+fn code_block(lang: Option<&str>, code: &str) -> Value {
+    let content = if code.is_empty() {
+        json!([])
+    } else {
+        json!([{"type": "text", "text": code}])
+    };
+    json!({
+        "type": "codeBlock",
+        "attrs": {"language": lang},
+        "content": content,
+    })
+}
+
+fn text_node(text: &str, marks: Vec<Value>) -> Value {
+    if marks.is_empty() {
+        json!({"type": "text", "text": text})
+    } else {
+        json!({"type": "text", "text": text, "marks": marks})
+    }
+}
+
+fn superscript(text: &str) -> Value {
+    text_node(
+        text,
+        vec![json!({"type": "subsup", "attrs": {"type": "sup"}})],
+    )
+}
+
+/// Marks for a run. Code can only be combined with a link.
+fn marks(style: Style, link: Option<&str>) -> Vec<Value> {
+    let mut marks = Vec::new();
+    if style.code {
+        marks.push(json!({"type": "code"}));
+    } else {
+        if style.strong {
+            marks.push(json!({"type": "strong"}));
+        }
+        if style.emphasis {
+            marks.push(json!({"type": "em"}));
+        }
+        if style.strikethrough {
+            marks.push(json!({"type": "strike"}));
+        }
+    }
+    if let Some(href) = link {
+        marks.push(json!({"type": "link", "attrs": {"href": href}}));
+    }
+    marks
+}
+
+fn alignment(align: &AlignKind) -> Option<&'static str> {
+    match align {
+        AlignKind::Center => Some("center"),
+        AlignKind::Right => Some("end"),
+        AlignKind::Left | AlignKind::None => None,
+    }
+}
+
+fn panel_type(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::Note => "info",
+        AlertKind::Tip => "success",
+        AlertKind::Important => "note",
+        AlertKind::Warning => "warning",
+        AlertKind::Caution => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn slice(md: &str) -> Value {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        serde_json::from_str(&mdast_to_prosemirror(&ast)).unwrap()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let slice = slice(
+            "## Setup\n\n```rust\nfn main() {}\n```\n\n> [!WARNING]\n> Back up first.\n\n\
+             | a | b |\n|---|:-:|\n| 1 | 2 |\n",
+        );
+        let content = slice["content"].as_array().unwrap();
+        assert_eq!(slice["openStart"], 0);
+        assert_eq!(content[0]["attrs"]["level"], 2);
+        assert_eq!(content[1]["type"], "codeBlock");
+        assert_eq!(content[1]["attrs"]["language"], "rust");
+        assert_eq!(content[1]["content"][0]["text"], "fn main() {}");
+        assert_eq!(content[2]["type"], "panel");
+        assert_eq!(content[2]["attrs"]["panelType"], "warning");
+        assert_eq!(
+            content[2]["content"][0]["content"][0]["text"],
+            "Back up first."
+        );
+
+        let rows = &content[3]["content"];
+        assert_eq!(rows[0]["content"][0]["type"], "tableHeader");
+        assert_eq!(rows[1]["content"][0]["type"], "tableCell");
+        assert_eq!(
+            rows[1]["content"][1]["content"][0]["marks"][0]["attrs"]["align"],
+            "center"
+        );
+    }
+
+    #[test]
+    fn test_inline_marks_and_tasks() {
+        let slice = slice("**[bold `code`](https://example.com)**\n\n- [x] done\n- [ ] todo\n");
+        let inlines = &slice["content"][0]["content"];
+        assert_eq!(
+            inlines[0]["marks"],
+            json!([
+                {"type": "strong"},
+                {"type": "link", "attrs": {"href": "https://example.com"}},
+            ])
+        );
+        // Code drops the marks the editor doesn't allow with it
+        assert_eq!(inlines[1]["marks"][0]["type"], "code");
+        assert_eq!(inlines[1]["marks"][1]["type"], "link");
+
+        let tasks = &slice["content"][1];
+        assert_eq!(tasks["type"], "taskList");
+        assert_eq!(tasks["content"][0]["attrs"]["state"], "DONE");
+        assert_eq!(tasks["content"][1]["attrs"]["state"], "TODO");
+        assert_ne!(
+            tasks["content"][0]["attrs"]["localId"],
+            tasks["content"][1]["attrs"]["localId"]
+        );
+    }
+}
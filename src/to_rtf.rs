@@ -1,11 +1,18 @@
-use crate::config::ImageConfig;
-use crate::highlight::HighlightContext;
-use crate::image::{ImageCache, ImageError};
+use crate::alerts;
+use crate::anchors::Anchors;
+use crate::config::{CodeLayout, EmbedFormat, EmojiImages, ImageConfig};
+use crate::details;
+use crate::emoji::{self, Segment};
+use crate::highlight::{self, CodeLines, HighlightContext};
+use crate::image::{ImageCache, ImageError, transcode_for_embedding, transcode_to_png};
+use crate::inline::{self, Definitions, Run, Span};
+use crate::render::{self, Output, Renderer};
 use log::warn;
 use markdown::mdast::{AlignKind, Node};
 use std::collections::HashMap;
+use std::io;
 use std::path::Path;
-use syntect::easy::HighlightLines;
+use syntect::highlighting::Color;
 
 pub fn mdast_to_rtf(
     node: &Node,
@@ -14,8 +21,12 @@ pub fn mdast_to_rtf(
     strict: bool,
     highlight: Option<&HighlightContext>,
     image_cache: &ImageCache,
+    code: CodeLayout,
 ) -> Result<String, ImageError> {
     let mut ctx = RtfContext::new(base_dir, image_config, strict, highlight, image_cache);
+    ctx.code = code;
+    ctx.definitions = Definitions::collect(node);
+    ctx.anchors = Anchors::collect(node);
     let mut body = String::new();
     node_to_rtf(node, &mut body, &mut ctx)?;
 
@@ -39,16 +50,46 @@ pub fn mdast_to_rtf(
     Ok(rtf)
 }
 
+pub struct RtfRenderer;
+
+impl Renderer for RtfRenderer {
+    fn render(&self, ast: &Node, ctx: &render::Context) -> io::Result<Output> {
+        mdast_to_rtf(
+            &details::folded(ast),
+            ctx.base_dir,
+            ctx.image,
+            ctx.cfg.strict.images,
+            ctx.highlight,
+            ctx.image_cache,
+            ctx.cfg.html.code,
+        )
+        .map(Output::Text)
+        .map_err(io::Error::other)
+    }
+}
+
 struct RtfContext<'a> {
     base_dir: &'a Path,
     image_config: &'a ImageConfig,
     strict: bool,
     highlight: Option<&'a HighlightContext>,
     image_cache: &'a ImageCache,
+    /// Wrapping and width of code blocks
+    code: CodeLayout,
+    definitions: Definitions<'a>,
+    anchors: Anchors,
     colors: HashMap<(u8, u8, u8), usize>,
     table_align: Vec<AlignKind>,
     table_cell_index: usize,
     in_table_header: bool,
+    /// Enclosing lists, innermost last
+    lists: Vec<ListState>,
+}
+
+struct ListState {
+    ordered: bool,
+    /// Number of the next ordered item
+    next: u32,
 }
 
 impl<'a> RtfContext<'a> {
@@ -65,10 +106,14 @@ impl<'a> RtfContext<'a> {
             strict,
             highlight,
             image_cache,
+            code: CodeLayout::default(),
+            definitions: Definitions::default(),
+            anchors: Anchors::default(),
             colors: HashMap::new(),
             table_align: Vec::new(),
             table_cell_index: 0,
             in_table_header: false,
+            lists: Vec::new(),
         }
     }
 
@@ -96,53 +141,46 @@ fn node_to_rtf(node: &Node, rtf: &mut String, ctx: &mut RtfContext) -> Result<()
                 _ => 20,
             };
             rtf.push_str(&format!("{{\\b\\fs{} ", size));
-            for child in &heading.children {
-                node_to_rtf(child, rtf, ctx)?;
+            // Zero-width bookmark that `#fragment` links jump to
+            if let Some(slug) = ctx.anchors.next() {
+                rtf.push_str("{\\*\\bkmkstart ");
+                push_rtf_escaped(rtf, slug);
+                rtf.push_str("}{\\*\\bkmkend ");
+                push_rtf_escaped(rtf, slug);
+                rtf.push('}');
             }
+            inlines_to_rtf(&heading.children, rtf, ctx)?;
             rtf.push_str("}\\par\\par ");
         }
         Node::Paragraph(para) => {
-            for child in &para.children {
-                node_to_rtf(child, rtf, ctx)?;
-            }
+            inlines_to_rtf(&para.children, rtf, ctx)?;
             rtf.push_str("\\par ");
         }
-        Node::Text(text) => {
-            push_rtf_escaped(rtf, &text.value);
-        }
-        Node::Strong(strong) => {
-            rtf.push_str("{\\b ");
-            for child in &strong.children {
-                node_to_rtf(child, rtf, ctx)?;
-            }
-            rtf.push('}');
-        }
-        Node::Emphasis(em) => {
-            rtf.push_str("{\\i ");
-            for child in &em.children {
-                node_to_rtf(child, rtf, ctx)?;
-            }
-            rtf.push('}');
-        }
-        Node::InlineCode(code) => {
-            rtf.push_str("{\\f1 ");
-            push_rtf_escaped(rtf, &code.value);
-            rtf.push('}');
-        }
         Node::Code(code) => {
+            let lines = CodeLines::new(code.lang.as_deref(), &code.value);
+            let cell = code_cell(ctx.code);
+            if let Some(row) = &cell {
+                rtf.push_str(row);
+            }
+            let end = if cell.is_some() {
+                "\\cell\\row\\pard "
+            } else {
+                "\\par "
+            };
             if let Some(highlight_ctx) = ctx.highlight {
-                let syntax = code
-                    .lang
-                    .as_ref()
-                    .map(|lang| highlight_ctx.find_syntax(lang))
-                    .unwrap_or_else(|| highlight_ctx.syntax_set.find_syntax_plain_text());
-
-                let mut highlighter = HighlightLines::new(syntax, &highlight_ctx.theme);
+                let mut highlighter = highlight_ctx.highlighter(lines.lang, &code.value);
                 rtf.push_str("{\\f1\\fs20 ");
 
-                for line in code.value.lines() {
-                    if let Ok(ranges) = highlighter.highlight_line(line, &highlight_ctx.syntax_set)
-                    {
+                for &(line, change) in &lines.lines {
+                    let line = line.trim_end_matches(['\n', '\r']);
+                    if let Some(change) = change {
+                        push_diff_line_open(
+                            change.background(highlight_ctx.background()),
+                            rtf,
+                            ctx,
+                        );
+                    }
+                    if let Ok(ranges) = highlighter.highlight_line(line) {
                         for (style, text) in ranges {
                             let color_idx = ctx.get_color_index(
                                 style.foreground.r,
@@ -155,31 +193,91 @@ fn node_to_rtf(node: &Node, rtf: &mut String, ctx: &mut RtfContext) -> Result<()
                     } else {
                         push_rtf_escaped(rtf, line);
                     }
+                    if change.is_some() {
+                        rtf.push('}');
+                    }
                     rtf.push_str("\\line ");
                 }
 
-                rtf.push_str("}\\par ");
+                rtf.push('}');
+                rtf.push_str(end);
             } else {
                 rtf.push_str("{\\f1\\fs20 ");
-                push_rtf_escaped(rtf, &code.value);
-                rtf.push_str("}\\par ");
-            }
-        }
-        Node::Link(link) => {
-            for child in &link.children {
-                node_to_rtf(child, rtf, ctx)?;
+                for &(line, change) in &lines.lines {
+                    match change {
+                        Some(change) => {
+                            let text = line.strip_suffix('\n').unwrap_or(line);
+                            let background = change.background(highlight::PLAIN_BACKGROUND);
+                            push_diff_line_open(background, rtf, ctx);
+                            push_rtf_escaped(rtf, text);
+                            rtf.push('}');
+                            push_rtf_escaped(rtf, &line[text.len()..]);
+                        }
+                        None => push_rtf_escaped(rtf, line),
+                    }
+                }
+                rtf.push('}');
+                rtf.push_str(end);
             }
         }
         Node::List(list) => {
+            ctx.lists.push(ListState {
+                ordered: list.ordered,
+                next: list.start.unwrap_or(1),
+            });
             for child in &list.children {
                 node_to_rtf(child, rtf, ctx)?;
             }
+            ctx.lists.pop();
         }
         Node::ListItem(item) => {
-            rtf.push_str("\\bullet  ");
-            for child in &item.children {
+            let level = ctx.lists.len().max(1);
+            // Hanging indent: marker at the previous level's text position
+            let indent = 360 * (level + 1);
+            rtf.push_str(&format!("{{\\li{} \\fi-360\\tx{} ", indent, indent));
+            match ctx.lists.last_mut() {
+                Some(list) if list.ordered => {
+                    // \pntext is the rendered marker; \pn lets older readers renumber
+                    rtf.push_str(&format!(
+                        "{{\\pntext {}.\\tab}}{{\\*\\pn\\pnlvlbody\\pndec\\pnstart{}\\pnindent360{{\\pntxta .}}}}",
+                        list.next, list.next
+                    ));
+                    list.next += 1;
+                }
+                _ => {
+                    let bullet = match level % 3 {
+                        1 => "\\bullet",
+                        2 => "\\u9702?",
+                        _ => "\\u9642?",
+                    };
+                    rtf.push_str(&format!(
+                        "{{\\pntext {}\\tab}}{{\\*\\pn\\pnlvlblt\\pnindent360{{\\pntxtb {}}}}}",
+                        bullet, bullet
+                    ));
+                }
+            }
+            for (i, child) in item.children.iter().enumerate() {
+                if i == 1 {
+                    // Continuation paragraphs align with the item text
+                    rtf.push_str("\\fi0 ");
+                }
                 node_to_rtf(child, rtf, ctx)?;
             }
+            rtf.push('}');
+        }
+        Node::Blockquote(bq) if let Some((kind, children)) = alerts::alert(bq) => {
+            // Indented like a quote, headed by the label in the alert's color
+            let (r, g, b) = kind.color();
+            let color = ctx.get_color_index(r, g, b);
+            rtf.push_str(&format!(
+                "{{\\li400 {{\\b\\cf{} {}}}\\par ",
+                color,
+                kind.label()
+            ));
+            for child in &children {
+                node_to_rtf(child, rtf, ctx)?;
+            }
+            rtf.push('}');
         }
         Node::Blockquote(bq) => {
             rtf.push_str("{\\li400 ");
@@ -191,16 +289,6 @@ fn node_to_rtf(node: &Node, rtf: &mut String, ctx: &mut RtfContext) -> Result<()
         Node::ThematicBreak(_) => {
             rtf.push_str("\\par\\brdrb\\brdrs\\brdrw10\\brsp20 \\par ");
         }
-        Node::Break(_) => {
-            rtf.push_str("\\line ");
-        }
-        Node::Delete(del) => {
-            rtf.push_str("{\\strike ");
-            for child in &del.children {
-                node_to_rtf(child, rtf, ctx)?;
-            }
-            rtf.push('}');
-        }
         Node::Table(table) => {
             ctx.table_align = table.align.clone();
             for (i, child) in table.children.iter().enumerate() {
@@ -237,60 +325,221 @@ fn node_to_rtf(node: &Node, rtf: &mut String, ctx: &mut RtfContext) -> Result<()
                 rtf.push_str("{\\b ");
             }
             rtf.push_str("\\intbl ");
-            for child in &cell.children {
-                node_to_rtf(child, rtf, ctx)?;
-            }
+            inlines_to_rtf(&cell.children, rtf, ctx)?;
             if ctx.in_table_header {
                 rtf.push('}');
             }
             rtf.push_str("\\cell ");
             ctx.table_cell_index += 1;
         }
-        Node::Image(image) => {
-            let img = ctx.image_cache.get_or_load(
-                &image.url,
-                ctx.base_dir,
-                ctx.image_config,
-                ctx.strict,
-            )?;
-
-            if let Some(img) = img {
-                if let Some(format) = img.rtf_format() {
-                    // RTF embedded image: {\pict\pngblip <hex data>}
-                    rtf.push_str(&format!("{{\\pict{} ", format));
-                    rtf.push_str(&img.to_rtf_hex());
-                    rtf.push('}');
-                    return Ok(());
-                } else {
-                    warn!(
-                        "RTF does not support {} images, using hyperlink fallback: {}",
-                        img.mime_type, image.url
-                    );
+        Node::Html(raw) if crate::concat::is_page_break(&raw.value) => rtf.push_str("\\page "),
+        Node::Html(_) => {}
+        Node::Definition(_) => {}
+        Node::FootnoteDefinition(_) => {}
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Write inline content as styled runs, wrapping each stretch that shares a
+/// link in one hyperlink field
+fn inlines_to_rtf(
+    children: &[Node],
+    rtf: &mut String,
+    ctx: &mut RtfContext,
+) -> Result<(), ImageError> {
+    let runs = inline::runs(children, &ctx.definitions);
+    for (link, group) in inline::link_groups(&runs) {
+        if let Some(url) = link {
+            // Hyperlink field; the result is styled like a typical link
+            let color = ctx.get_color_index(5, 99, 193);
+            rtf.push_str("{\\field{\\*\\fldinst{HYPERLINK ");
+            match ctx.anchors.resolve(url) {
+                // Jump to the heading's bookmark
+                Some(slug) => {
+                    rtf.push_str("\\\\l \"");
+                    push_rtf_escaped(rtf, slug);
+                }
+                None => {
+                    rtf.push('"');
+                    push_rtf_escaped(rtf, &url.replace('"', "%22"));
                 }
             }
-            // Fallback: link to the image with alt text or URL as display text
-            let text = if !image.alt.is_empty() {
-                &image.alt
+            rtf.push_str(&format!("\"}}}}{{\\fldrslt{{\\ul\\cf{} ", color));
+        }
+        for run in group {
+            run_to_rtf(run, rtf, ctx)?;
+        }
+        if link.is_some() {
+            rtf.push_str("}}}");
+        }
+    }
+    Ok(())
+}
+
+fn run_to_rtf(run: &Run, rtf: &mut String, ctx: &RtfContext) -> Result<(), ImageError> {
+    match run.span {
+        Span::Text(text) => {
+            let style = run.style;
+            if !style.is_plain() {
+                rtf.push('{');
+                for (on, word) in [
+                    (style.strong, "\\b"),
+                    (style.emphasis, "\\i"),
+                    (style.strikethrough, "\\strike"),
+                    (style.code, "\\f1"),
+                ] {
+                    if on {
+                        rtf.push_str(word);
+                    }
+                }
+                rtf.push(' ');
+            }
+            if style.code || ctx.image_config.emoji_images == EmojiImages::None {
+                push_rtf_escaped(rtf, text);
+            } else {
+                push_text_with_emoji_glyphs(rtf, text, ctx)?;
+            }
+            if !style.is_plain() {
+                rtf.push('}');
+            }
+        }
+        Span::Break => rtf.push_str("\\line "),
+        Span::Image { url, alt } => image_to_rtf(url, alt, rtf, ctx)?,
+        Span::FootnoteReference(id) => rtf.push_str(&format!("[^{}]", id)),
+    }
+    Ok(())
+}
+
+fn image_to_rtf(
+    url: &str,
+    alt: &str,
+    rtf: &mut String,
+    ctx: &RtfContext,
+) -> Result<(), ImageError> {
+    let img = ctx
+        .image_cache
+        .get_or_load(url, ctx.base_dir, ctx.image_config, ctx.strict)?;
+
+    if let Some(img) = img {
+        // RTF only embeds PNG and JPEG; convert other raster formats, and
+        // WebP (as images optimized to it are) to JPEG unless transparent
+        let img = if img.rtf_format().is_some() {
+            Some(img)
+        } else {
+            let converted = if img.mime_type == "image/webp" {
+                transcode_for_embedding(&img, ctx.image_config.quality, EmbedFormat::Auto)
             } else {
-                &image.url
+                transcode_to_png(&img)
             };
-            rtf.push_str("{\\field{\\*\\fldinst{HYPERLINK \"");
-            push_rtf_escaped(rtf, &image.url);
-            rtf.push_str("\"}}{\\fldrslt ");
-            push_rtf_escaped(rtf, text);
-            rtf.push_str("}}");
+            match converted {
+                Ok(png) => Some(png),
+                Err(e) => {
+                    warn!(
+                        "RTF does not support {} images, using hyperlink fallback: {} ({})",
+                        img.mime_type, url, e
+                    );
+                    None
+                }
+            }
+        };
+        if let Some(img) = img
+            && let Some(format) = img.rtf_format()
+        {
+            // RTF embedded image: {\pict\pngblip <hex data>}
+            rtf.push_str(&format!("{{\\pict{} ", format));
+            rtf.push_str(&img.to_rtf_hex());
+            rtf.push('}');
+            return Ok(());
         }
-        Node::Html(_) => {}
-        Node::Definition(_) => {}
-        Node::FootnoteDefinition(_) => {}
-        Node::FootnoteReference(fnref) => {
-            rtf.push_str(&format!("[^{}]", fnref.identifier));
+    }
+    // Fallback: link to the image with alt text or URL as display text
+    let text = if !alt.is_empty() { alt } else { url };
+    rtf.push_str("{\\field{\\*\\fldinst{HYPERLINK \"");
+    push_rtf_escaped(rtf, url);
+    rtf.push_str("\"}}{\\fldrslt ");
+    push_rtf_escaped(rtf, text);
+    rtf.push_str("}}");
+
+    Ok(())
+}
+
+/// Emoji glyph size in twips, matching the 12pt body text
+const EMOJI_GLYPH_TWIPS: u32 = 240;
+
+/// Push text with each emoji replaced by an inline PNG glyph, keeping the
+/// emoji itself when its glyph can't be loaded
+fn push_text_with_emoji_glyphs(
+    rtf: &mut String,
+    text: &str,
+    ctx: &RtfContext,
+) -> Result<(), ImageError> {
+    for segment in emoji::segments(text) {
+        match segment {
+            Segment::Text(s) => push_rtf_escaped(rtf, s),
+            Segment::Emoji(e) => {
+                let glyph = emoji::load_glyph(
+                    e,
+                    ctx.base_dir,
+                    ctx.image_config,
+                    ctx.strict,
+                    ctx.image_cache,
+                )?;
+                match glyph.as_ref().and_then(|g| Some((g, g.rtf_format()?))) {
+                    Some((glyph, format)) => {
+                        rtf.push_str(&format!(
+                            "{{\\pict{}\\picwgoal{size}\\pichgoal{size} ",
+                            format,
+                            size = EMOJI_GLYPH_TWIPS
+                        ));
+                        rtf.push_str(&glyph.to_rtf_hex());
+                        rtf.push('}');
+                    }
+                    None => push_rtf_escaped(rtf, e),
+                }
+            }
         }
-        _ => {}
     }
     Ok(())
 }
 
+/// Open a group shading an added or removed line of a diff: `\\chcbpat` for
+/// Word, `\\cb` for Cocoa
+/// Width of a Courier character at the code font size (10pt), in twips
+const CODE_CHAR_TWIPS: u32 = 120;
+
+/// Space between a cell's border and its text, in twips
+const CELL_GAP: u32 = 108;
+
+/// Width code cells get without a `max_width`, that of a full table
+const CODE_CELL_WIDTH: u32 = 9000;
+
+/// Row opening a code block set in a one-cell table, which holds its width
+/// and, with `\clNoWrap`, keeps long lines whole; None leaves the block a
+/// plain paragraph
+fn code_cell(code: CodeLayout) -> Option<String> {
+    if code.wrap != Some(false) && code.max_width.is_none() {
+        return None;
+    }
+    let width = code.max_width.map_or(CODE_CELL_WIDTH, |columns| {
+        columns * CODE_CHAR_TWIPS + 2 * CELL_GAP
+    });
+    let no_wrap = if code.wrap == Some(false) {
+        "\\clNoWrap"
+    } else {
+        ""
+    };
+    Some(format!(
+        "\\trowd\\trgaph{}{}\\cellx{} \\pard\\intbl ",
+        CELL_GAP, no_wrap, width
+    ))
+}
+
+fn push_diff_line_open(background: Color, rtf: &mut String, ctx: &mut RtfContext) {
+    let index = ctx.get_color_index(background.r, background.g, background.b);
+    rtf.push_str(&format!("{{\\chcbpat{}\\cb{} ", index, index));
+}
+
 fn push_rtf_escaped(rtf: &mut String, text: &str) {
     for c in text.chars() {
         match c {
@@ -330,8 +579,18 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         };
-        mdast_to_rtf(&ast, Path::new("."), &image_config, false, None, &cache).unwrap()
+        mdast_to_rtf(
+            &ast,
+            Path::new("."),
+            &image_config,
+            false,
+            None,
+            &cache,
+            CodeLayout::default(),
+        )
+        .unwrap()
     }
 
     #[test]
@@ -425,6 +684,49 @@ mod tests {
         assert!(rtf.contains("code"));
     }
 
+    #[test]
+    fn test_diff_code_block() {
+        let rtf = render_rtf("```rust,diff\n-a\n+b\n c\n```");
+        assert!(
+            rtf.contains("{\\colortbl;\\red247\\green206\\blue206;\\red196\\green226\\blue204;}")
+        );
+        assert!(
+            rtf.contains(
+                "{\\f1\\fs20 {\\chcbpat1\\cb1 a}\\line {\\chcbpat2\\cb2 b}\\line c}\\par "
+            ),
+            "{}",
+            rtf
+        );
+    }
+
+    #[test]
+    fn test_code_layout() {
+        let ast =
+            markdown::to_mdast("```\nlong line\n```", &markdown::ParseOptions::gfm()).unwrap();
+        let cache = ImageCache::new();
+        let render = |wrap, max_width| {
+            let code = CodeLayout { wrap, max_width };
+            mdast_to_rtf(
+                &ast,
+                Path::new("."),
+                &ImageConfig::default(),
+                false,
+                None,
+                &cache,
+                code,
+            )
+            .unwrap()
+        };
+        assert!(render(None, None).contains("{\\f1\\fs20 long line}\\par "));
+        assert!(render(Some(false), None).contains(
+            "\\trowd\\trgaph108\\clNoWrap\\cellx9000 \\pard\\intbl {\\f1\\fs20 long line}\\cell\\row\\pard "
+        ));
+        // 80 characters of 120 twips, plus the gaps on both sides
+        assert!(
+            render(Some(true), Some(80)).contains("\\trowd\\trgaph108\\cellx9816 \\pard\\intbl ")
+        );
+    }
+
     #[test]
     fn test_list_item() {
         let rtf = render_rtf("- item");
@@ -432,6 +734,38 @@ mod tests {
         assert!(rtf.contains("item"));
     }
 
+    #[test]
+    fn test_list_bullet_markup() {
+        let rtf = render_rtf("- item");
+        assert!(rtf.contains(
+            "{\\li720 \\fi-360\\tx720 {\\pntext \\bullet\\tab}{\\*\\pn\\pnlvlblt\\pnindent360{\\pntxtb \\bullet}}item\\par }"
+        ));
+    }
+
+    #[test]
+    fn test_ordered_list_start() {
+        let rtf = render_rtf("3. three\n4. four");
+        assert!(rtf.contains("{\\pntext 3.\\tab}"));
+        assert!(rtf.contains("\\pnstart3"));
+        assert!(rtf.contains("{\\pntext 4.\\tab}"));
+        assert!(!rtf.contains("\\bullet"));
+    }
+
+    #[test]
+    fn test_nested_list_indent() {
+        let rtf = render_rtf("1. outer\n   - inner\n2. next");
+        assert!(rtf.contains("{\\li720 \\fi-360\\tx720 {\\pntext 1.\\tab}"));
+        assert!(rtf.contains("{\\li1080 \\fi-360\\tx1080 {\\pntext \\u9702?\\tab}"));
+        // Numbering continues after the nested list
+        assert!(rtf.contains("{\\pntext 2.\\tab}"));
+    }
+
+    #[test]
+    fn test_list_continuation_paragraph() {
+        let rtf = render_rtf("- first\n\n  second");
+        assert!(rtf.contains("first\\par \\fi0 second\\par }"));
+    }
+
     #[test]
     fn test_blockquote() {
         let rtf = render_rtf("> quoted");
@@ -440,6 +774,13 @@ mod tests {
         assert!(rtf.contains("quoted"));
     }
 
+    #[test]
+    fn test_alert_label() {
+        let rtf = render_rtf("> [!CAUTION]\n> Hot.");
+        assert!(rtf.contains("{\\colortbl;\\red209\\green36\\blue47;}"));
+        assert!(rtf.contains("{\\li400 {\\b\\cf1 Caution}\\par Hot.\\par }"));
+    }
+
     #[test]
     fn test_thematic_break() {
         let rtf = render_rtf("---");
@@ -493,10 +834,32 @@ mod tests {
     }
 
     #[test]
-    fn test_link_text_only() {
-        // Links in RTF just show the text (no hyperlink in basic RTF)
+    fn test_link_hyperlink() {
         let rtf = render_rtf("[link text](https://example.com)");
-        assert!(rtf.contains("link text"));
+        assert!(rtf.contains("{\\field{\\*\\fldinst{HYPERLINK \"https://example.com\"}}"));
+        assert!(rtf.contains("{\\fldrslt{\\ul\\cf1 link text}}}"));
+        assert!(rtf.contains("{\\colortbl;\\red5\\green99\\blue193;}"));
+    }
+
+    #[test]
+    fn test_heading_bookmarks() {
+        let rtf = render_rtf("# Setup\n\n## Setup\n\nSee [again](#Setup-1) or [web](#nowhere).");
+        assert!(rtf.contains("{\\*\\bkmkstart setup}{\\*\\bkmkend setup}Setup"));
+        assert!(rtf.contains("{\\*\\bkmkstart setup-1}{\\*\\bkmkend setup-1}Setup"));
+        assert!(rtf.contains("{\\field{\\*\\fldinst{HYPERLINK \\\\l \"setup-1\"}}"));
+        assert!(rtf.contains("{\\field{\\*\\fldinst{HYPERLINK \"#nowhere\"}}"));
+    }
+
+    #[test]
+    fn test_link_nested_formatting() {
+        let rtf = render_rtf("[**bold** link](https://example.com)");
+        assert!(rtf.contains("{\\fldrslt{\\ul\\cf1 {\\b bold} link}}}"));
+    }
+
+    #[test]
+    fn test_link_url_escaping() {
+        let rtf = render_rtf("[x](<https://example.com/a\"b{c}>)");
+        assert!(rtf.contains("HYPERLINK \"https://example.com/a%22b\\{c\\}\""));
     }
 
     #[test]
@@ -507,11 +870,89 @@ mod tests {
         assert!(rtf.contains("image.png"));
     }
 
+    #[test]
+    fn test_gif_transcoded_to_png() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut data = Vec::new();
+        {
+            let mut encoder =
+                gif::Encoder::new(&mut data, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+            let frame = gif::Frame {
+                width: 1,
+                height: 1,
+                buffer: std::borrow::Cow::Borrowed(&[1]),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        std::fs::write(temp_dir.path().join("anim.gif"), data).unwrap();
+
+        let ast = parse_markdown("![anim](anim.gif)");
+        let cache = crate::image::ImageCache::new();
+        let image_config = ImageConfig {
+            embed_local: true,
+            quality: 80,
+            ..ImageConfig::default()
+        };
+        let rtf = mdast_to_rtf(
+            &ast,
+            temp_dir.path(),
+            &image_config,
+            false,
+            None,
+            &cache,
+            CodeLayout::default(),
+        )
+        .unwrap();
+        assert!(rtf.contains("{\\pict\\pngblip "));
+        assert!(!rtf.contains("HYPERLINK"));
+    }
+
+    #[test]
+    fn test_emoji_glyphs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        std::fs::write(temp_dir.path().join("1f680.png"), png).unwrap();
+
+        let ast = parse_markdown("Launch 🚀 `🚀`");
+        let cache = crate::image::ImageCache::new();
+        let image_config = ImageConfig {
+            embed_local: false,
+            optimize_local: false,
+            emoji_images: EmojiImages::Rtf,
+            emoji_base_url: temp_dir.path().display().to_string(),
+            ..ImageConfig::default()
+        };
+        let rtf = mdast_to_rtf(
+            &ast,
+            Path::new("."),
+            &image_config,
+            false,
+            None,
+            &cache,
+            CodeLayout::default(),
+        )
+        .unwrap();
+        // Glyphs load even with embedding off; inline code keeps the emoji
+        assert_eq!(
+            rtf.matches("{\\pict\\pngblip\\picwgoal240\\pichgoal240 89504e47")
+                .count(),
+            1
+        );
+    }
+
     #[test]
     fn test_nested_formatting() {
         let rtf = render_rtf("**bold *and italic* text**");
-        assert!(rtf.contains("{\\b"));
-        assert!(rtf.contains("{\\i"));
+        assert!(rtf.contains("{\\b bold }{\\b\\i and italic}{\\b  text}"));
+    }
+
+    #[test]
+    fn test_reference_link_hyperlink() {
+        let rtf = render_rtf("See [the docs][docs].\n\n[docs]: https://example.com/docs");
+        assert!(rtf.contains(
+            "{\\field{\\*\\fldinst{HYPERLINK \"https://example.com/docs\"}}{\\fldrslt{\\ul\\cf1 the docs}}}"
+        ));
     }
 
     #[test]
@@ -531,6 +972,7 @@ mod tests {
             optimize_remote: false,
             max_dimension: 1200,
             quality: 80,
+            ..ImageConfig::default()
         };
         let mut ctx = RtfContext::new(Path::new("."), &image_config, false, None, &cache);
 
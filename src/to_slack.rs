@@ -0,0 +1,341 @@
+//! Slack mrkdwn, the markup Slack messages, webhooks and the composer's paste
+//! handling understand. It has no headings, tables or nested emphasis
+//! markers, so headings become bold lines and tables aligned code blocks.
+
+use crate::alerts;
+use crate::details;
+use crate::inline::{self, Definitions, Span};
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{AlignKind, List, Node, Table};
+use std::io;
+
+pub fn mdast_to_slack(root: &Node) -> String {
+    let mut ctx = SlackContext {
+        definitions: Definitions::collect(root),
+        depth: 0,
+    };
+    let mut out = String::new();
+    ctx.blocks(root.children().map_or(&[][..], Vec::as_slice), &mut out);
+    let trimmed = out.trim_end();
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", trimmed)
+    }
+}
+
+pub struct SlackRenderer;
+
+impl Renderer for SlackRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_slack(&details::folded(ast))))
+    }
+}
+
+struct SlackContext<'a> {
+    definitions: Definitions<'a>,
+    /// Nesting level of the enclosing lists
+    depth: usize,
+}
+
+impl SlackContext<'_> {
+    /// Blocks separated by blank lines, each ending in a newline
+    fn blocks(&mut self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            let mut block = String::new();
+            self.block(node, &mut block);
+            if block.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&block);
+        }
+    }
+
+    fn block(&mut self, node: &Node, out: &mut String) {
+        match node {
+            // Slack has no headings; a bold line is the closest it gets
+            Node::Heading(heading) => {
+                let mut text = String::new();
+                self.inlines(&heading.children, &mut text);
+                if !text.is_empty() {
+                    out.push_str(&format!("*{}*\n", text.replace('*', "")));
+                }
+            }
+            Node::Paragraph(para) => {
+                self.inlines(&para.children, out);
+                out.push('\n');
+            }
+            // The language would show up as the first line of the block
+            Node::Code(code) => push_code_block(&code.value, out),
+            Node::Math(math) => push_code_block(&math.value, out),
+            Node::Blockquote(quote) => {
+                let mut inner = String::new();
+                match alerts::alert(quote) {
+                    Some((kind, children)) => {
+                        self.blocks(&children, &mut inner);
+                        inner.insert_str(0, &format!("*{}*\n", kind.label()));
+                    }
+                    None => self.blocks(&quote.children, &mut inner),
+                }
+                for line in inner.trim_end().lines() {
+                    out.push_str(format!("> {}", line).trim_end());
+                    out.push('\n');
+                }
+            }
+            Node::List(list) => self.list(list, out),
+            Node::Table(table) => self.table(table, out),
+            Node::ThematicBreak(_) => out.push_str("──────────\n"),
+            Node::FootnoteDefinition(def) => {
+                out.push_str(&format!("[{}] ", escape(&def.identifier)));
+                let mut inner = String::new();
+                self.blocks(&def.children, &mut inner);
+                out.push_str(&inner);
+            }
+            // Raw HTML would show up verbatim, and definitions are resolved
+            // where they're used
+            _ => {}
+        }
+    }
+
+    fn list(&mut self, list: &List, out: &mut String) {
+        let indent = "    ".repeat(self.depth);
+        self.depth += 1;
+        for (index, item) in list.children.iter().enumerate() {
+            let Node::ListItem(item) = item else {
+                continue;
+            };
+            out.push_str(&indent);
+            match item.checked {
+                Some(true) => out.push_str("☑ "),
+                Some(false) => out.push_str("☐ "),
+                None if list.ordered => {
+                    out.push_str(&format!("{}. ", list.start.unwrap_or(1) as usize + index));
+                }
+                None => out.push_str("• "),
+            }
+            // Paragraphs of an item share its line; nested lists follow it
+            let mut line_open = true;
+            let mut first = true;
+            for child in &item.children {
+                match child {
+                    Node::List(nested) => {
+                        if line_open {
+                            out.push('\n');
+                            line_open = false;
+                        }
+                        self.list(nested, out);
+                    }
+                    other => {
+                        let mut block = String::new();
+                        self.block(other, &mut block);
+                        if block.is_empty() {
+                            continue;
+                        }
+                        if !first && line_open {
+                            out.push(' ');
+                        }
+                        out.push_str(&block.trim_end().replace('\n', " "));
+                        line_open = true;
+                        first = false;
+                    }
+                }
+            }
+            if line_open {
+                out.push('\n');
+            }
+        }
+        self.depth -= 1;
+    }
+
+    /// Tables become a code block with padded columns, since Slack renders
+    /// code in a monospace font and has no table markup
+    fn table(&self, table: &Table, out: &mut String) {
+        let rows: Vec<Vec<String>> = table
+            .children
+            .iter()
+            .map(|row| {
+                row.children()
+                    .into_iter()
+                    .flatten()
+                    .map(|cell| self.plain_text(cell.children().map_or(&[][..], Vec::as_slice)))
+                    .collect()
+            })
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in &rows {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(cell.chars().count());
+            }
+        }
+
+        let mut text = String::new();
+        for (index, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = (0..columns)
+                .map(|column| {
+                    let cell = row.get(column).map_or("", String::as_str);
+                    let align = table.align.get(column).unwrap_or(&AlignKind::None);
+                    pad(cell, widths[column], align)
+                })
+                .collect();
+            text.push_str(cells.join(" | ").trim_end());
+            text.push('\n');
+            if index == 0 {
+                let rules: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                text.push_str(&rules.join("-+-"));
+                text.push('\n');
+            }
+        }
+        push_code_block(&text, out);
+    }
+
+    /// Inline content without markup, for code blocks
+    fn plain_text(&self, children: &[Node]) -> String {
+        inline::runs(children, &self.definitions)
+            .into_iter()
+            .map(|run| match run.span {
+                Span::Text(text) => text.to_string(),
+                Span::Break => " ".to_string(),
+                Span::Image { alt, .. } => alt.to_string(),
+                Span::FootnoteReference(id) => format!("[{}]", id),
+            })
+            .collect()
+    }
+
+    fn inlines(&self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            self.inline(node, out);
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String) {
+        match node {
+            Node::Text(text) => out.push_str(&escape(&text.value)),
+            Node::Strong(strong) => self.wrap('*', &strong.children, out),
+            Node::Emphasis(em) => self.wrap('_', &em.children, out),
+            Node::Delete(del) => self.wrap('~', &del.children, out),
+            Node::InlineCode(code) => push_inline_code(&code.value, out),
+            Node::InlineMath(math) => push_inline_code(&math.value, out),
+            Node::Break(_) => out.push('\n'),
+            Node::Link(link) => self.link(&link.children, &link.url, out),
+            Node::LinkReference(linkref) => match self.definitions.get(&linkref.identifier) {
+                Some(url) => self.link(&linkref.children, url, out),
+                None => self.inlines(&linkref.children, out),
+            },
+            Node::Image(image) => push_link(&escape(&image.alt), &image.url, out),
+            Node::ImageReference(imgref) => match self.definitions.get(&imgref.identifier) {
+                Some(url) => push_link(&escape(&imgref.alt), url, out),
+                None => out.push_str(&escape(&imgref.alt)),
+            },
+            Node::FootnoteReference(fnref) => {
+                out.push_str(&format!("[{}]", escape(&fnref.identifier)));
+            }
+            _ => {}
+        }
+    }
+
+    /// Slack markers don't nest inside the same marker, so an inner `*`
+    /// (from `***x***` or similar) is dropped rather than closing early
+    fn wrap(&self, marker: char, children: &[Node], out: &mut String) {
+        let mut inner = String::new();
+        self.inlines(children, &mut inner);
+        if inner.trim().is_empty() {
+            out.push_str(&inner);
+            return;
+        }
+        out.push(marker);
+        out.push_str(&inner.replace(marker, ""));
+        out.push(marker);
+    }
+
+    fn link(&self, children: &[Node], url: &str, out: &mut String) {
+        let mut text = String::new();
+        self.inlines(children, &mut text);
+        push_link(&text, url, out);
+    }
+}
+
+/// `<url|text>`, or `<url>` when the text is the URL itself
+fn push_link(text: &str, url: &str, out: &mut String) {
+    out.push('<');
+    out.push_str(&url.replace('|', "%7C").replace('>', "%3E"));
+    if !text.is_empty() && text != url {
+        out.push('|');
+        out.push_str(text);
+    }
+    out.push('>');
+}
+
+fn push_inline_code(code: &str, out: &mut String) {
+    out.push('`');
+    out.push_str(&escape(&code.replace('`', "'")));
+    out.push('`');
+}
+
+fn push_code_block(code: &str, out: &mut String) {
+    out.push_str("```\n");
+    out.push_str(&escape(&code.replace("```", "ʼʼʼ")));
+    if !code.is_empty() && !code.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str("```\n");
+}
+
+fn pad(cell: &str, width: usize, align: &AlignKind) -> String {
+    let fill = width - cell.chars().count();
+    let (left, right) = match align {
+        AlignKind::Right => (fill, 0),
+        AlignKind::Center => (fill / 2, fill - fill / 2),
+        AlignKind::Left | AlignKind::None => (0, fill),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
+/// The three characters mrkdwn reserves for links and mentions
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn slack(md: &str) -> String {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        mdast_to_slack(&ast)
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            slack("## Setup\n\nRun **this** and _that_:\n\n```sh\nmake\n```\n"),
+            "*Setup*\n\nRun *this* and _that_:\n\n```\nmake\n```\n"
+        );
+        assert_eq!(
+            slack("> [!NOTE]\n> Heads up.\n\n- one\n  1. nested\n- [x] done\n"),
+            "> *Note*\n> Heads up.\n\n• one\n    1. nested\n☑ done\n"
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            slack("| Name | Qty |\n|---|--:|\n| apple | 3 |\n| fig | 12 |\n"),
+            "```\nName  | Qty\n------+----\napple |   3\nfig   |  12\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            slack("[docs](https://example.com) ~~old~~ ***both*** <https://x.io> a < b & c"),
+            "<https://example.com|docs> ~old~ _*both*_ <https://x.io> a &lt; b &amp; c\n"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Rendered plain text, for plain-text email and other places where markdown
+//! syntax would be noise: paragraphs wrapped to a fixed width, `*` bullets,
+//! underlined headings, indented code and tables drawn as text grids.
+
+use crate::alerts;
+use crate::details;
+use crate::grid;
+use crate::inline::Definitions;
+use crate::render::{self, Output, Renderer};
+use markdown::mdast::{List, Node};
+use std::io;
+
+/// Line width paragraphs are wrapped to, the usual limit for plain-text mail
+const WIDTH: usize = 72;
+
+/// Paragraphs never get narrower than this, however deeply they're nested
+const MIN_WIDTH: usize = 20;
+
+pub fn mdast_to_text(root: &Node) -> String {
+    let ctx = TextContext {
+        definitions: Definitions::collect(root),
+    };
+    let lines = ctx.blocks(root.children().map_or(&[][..], Vec::as_slice), WIDTH);
+    let text = lines.join("\n");
+    let trimmed = text.trim_end();
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", trimmed)
+    }
+}
+
+pub struct TextRenderer;
+
+impl Renderer for TextRenderer {
+    fn render(&self, ast: &Node, _ctx: &render::Context) -> io::Result<Output> {
+        Ok(Output::Text(mdast_to_text(&details::folded(ast))))
+    }
+}
+
+struct TextContext<'a> {
+    definitions: Definitions<'a>,
+}
+
+impl TextContext<'_> {
+    /// Lines of each block, with a blank line between blocks
+    fn blocks(&self, nodes: &[Node], width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for node in nodes {
+            let block = self.block(node, width);
+            if block.is_empty() {
+                continue;
+            }
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(block);
+        }
+        lines
+    }
+
+    fn block(&self, node: &Node, width: usize) -> Vec<String> {
+        match node {
+            Node::Heading(heading) => {
+                let mut text = String::new();
+                self.inlines(&heading.children, &mut text);
+                let mut lines = wrap(&text, width);
+                let underline = if heading.depth == 1 { "=" } else { "-" };
+                let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+                if longest > 0 {
+                    lines.push(underline.repeat(longest));
+                }
+                lines
+            }
+            Node::Paragraph(para) => {
+                let mut text = String::new();
+                self.inlines(&para.children, &mut text);
+                wrap(&text, width)
+            }
+            // Code keeps its lines, however long
+            Node::Code(code) => indented(&code.value),
+            Node::Math(math) => indented(&math.value),
+            Node::Blockquote(quote) => {
+                let inner_width = width.saturating_sub(2).max(MIN_WIDTH);
+                let mut lines = match alerts::alert(quote) {
+                    Some((kind, children)) => {
+                        let mut lines = vec![format!("{}:", kind.label())];
+                        lines.extend(self.blocks(&children, inner_width));
+                        lines
+                    }
+                    None => self.blocks(&quote.children, inner_width),
+                };
+                for line in &mut lines {
+                    *line = format!("> {}", line).trim_end().to_string();
+                }
+                lines
+            }
+            Node::List(list) => self.list(list, width),
+            Node::Table(table) => grid::table_grid(&grid::table_cells(table), &table.align)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            Node::ThematicBreak(_) => vec!["-".repeat(WIDTH.min(width) / 2)],
+            Node::FootnoteDefinition(def) => {
+                let marker = format!("[{}] ", def.identifier);
+                self.item(&marker, &def.children, width)
+            }
+            // Raw HTML would show up as markup, and definitions are resolved
+            // where they're used
+            _ => Vec::new(),
+        }
+    }
+
+    fn list(&self, list: &List, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let start = list.start.unwrap_or(1) as usize;
+        let last = start + list.children.len().saturating_sub(1);
+        for (index, item) in list.children.iter().enumerate() {
+            let Node::ListItem(item) = item else {
+                continue;
+            };
+            let mut marker = if list.ordered {
+                // Right-aligned so item text lines up past 9
+                let number = format!("{}.", start + index);
+                format!("{:>width$} ", number, width = last.to_string().len() + 1)
+            } else {
+                "* ".to_string()
+            };
+            match item.checked {
+                Some(true) => marker.push_str("[x] "),
+                Some(false) => marker.push_str("[ ] "),
+                None => {}
+            }
+            if item.spread && !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(self.item(&marker, &item.children, width));
+        }
+        lines
+    }
+
+    /// `children` rendered beside `marker`, continuation lines indented to
+    /// line up with the first
+    fn item(&self, marker: &str, children: &[Node], width: usize) -> Vec<String> {
+        let indent = marker.chars().count();
+        let inner_width = width.saturating_sub(indent).max(MIN_WIDTH);
+        // Items of a tight list are separated by nothing, so neither are
+        // their paragraphs and sublists
+        let mut lines = Vec::new();
+        for child in children {
+            let block = self.block(child, inner_width);
+            if block.is_empty() {
+                continue;
+            }
+            if !lines.is_empty() && !matches!(child, Node::List(_)) {
+                lines.push(String::new());
+            }
+            lines.extend(block);
+        }
+        let pad = " ".repeat(indent);
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| match (i, line.is_empty()) {
+                (0, _) => format!("{}{}", marker, line).trim_end().to_string(),
+                (_, true) => line,
+                (_, false) => format!("{}{}", pad, line),
+            })
+            .collect()
+    }
+
+    fn inlines(&self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            self.inline(node, out);
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String) {
+        match node {
+            Node::Text(text) => out.push_str(&text.value),
+            Node::Strong(strong) => self.wrap_inline("*", &strong.children, out),
+            Node::Emphasis(em) => self.wrap_inline("_", &em.children, out),
+            Node::Delete(del) => self.wrap_inline("~", &del.children, out),
+            Node::InlineCode(code) => {
+                out.push('`');
+                out.push_str(&code.value);
+                out.push('`');
+            }
+            Node::InlineMath(math) => out.push_str(&math.value),
+            Node::Break(_) => out.push('\n'),
+            Node::Link(link) => self.link(&link.children, &link.url, out),
+            Node::LinkReference(linkref) => match self.definitions.get(&linkref.identifier) {
+                Some(url) => self.link(&linkref.children, url, out),
+                None => self.inlines(&linkref.children, out),
+            },
+            Node::Image(image) => push_image(&image.alt, out),
+            Node::ImageReference(imgref) => push_image(&imgref.alt, out),
+            Node::FootnoteReference(fnref) => {
+                out.push_str(&format!("[{}]", fnref.identifier));
+            }
+            _ => {}
+        }
+    }
+
+    fn wrap_inline(&self, marker: &str, children: &[Node], out: &mut String) {
+        out.push_str(marker);
+        self.inlines(children, out);
+        out.push_str(marker);
+    }
+
+    /// "text <url>", or just the URL when it is the text
+    fn link(&self, children: &[Node], url: &str, out: &mut String) {
+        let mut text = String::new();
+        self.inlines(children, &mut text);
+        let bare = url.strip_prefix("mailto:").unwrap_or(url);
+        if text.is_empty() || text == url || text == bare {
+            out.push_str(bare);
+        } else {
+            out.push_str(&format!("{} <{}>", text, url));
+        }
+    }
+}
+
+fn push_image(alt: &str, out: &mut String) {
+    if alt.is_empty() {
+        out.push_str("[image]");
+    } else {
+        out.push_str(&format!("[image: {}]", alt));
+    }
+}
+
+fn indented(code: &str) -> Vec<String> {
+    code.lines()
+        .map(|line| format!("    {}", line).trim_end().to_string())
+        .collect()
+}
+
+/// Greedy word wrap; hard breaks (`\n`) always start a new line, and words
+/// longer than `width` get a line of their own
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for segment in text.split('\n') {
+        let mut line = String::new();
+        let mut len = 0;
+        for word in segment.split_whitespace() {
+            let word_len = word.chars().count();
+            if len > 0 && len + 1 + word_len > width {
+                lines.push(std::mem::take(&mut line));
+                len = 0;
+            }
+            if len > 0 {
+                line.push(' ');
+                len += 1;
+            }
+            line.push_str(word);
+            len += word_len;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn text(md: &str) -> String {
+        let ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        mdast_to_text(&ast)
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("one two three four\nfive", 9),
+            vec!["one two", "three", "four", "five"]
+        );
+        assert_eq!(wrap("a verylongword b", 5), vec!["a", "verylongword", "b"]);
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            text(
+                "# Title\n\n## Setup\n\nRun **this** and [docs](https://x.io).\n\n\
+                 ```sh\nmake\n```\n\n> [!NOTE]\n> Heads up.\n"
+            ),
+            "Title\n=====\n\nSetup\n-----\n\nRun *this* and docs <https://x.io>.\n\n    make\n\n\
+             > Note:\n> Heads up.\n"
+        );
+        assert_eq!(
+            text("| a | b |\n|---|---|\n| 1 | 2 |\n"),
+            "┌───┬───┐\n│ a │ b │\n├───┼───┤\n│ 1 │ 2 │\n└───┴───┘\n"
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let long = "word ".repeat(20);
+        assert_eq!(
+            text(&format!(
+                "- one\n  - nested\n- [x] done\n- {}\n",
+                long.trim()
+            )),
+            format!(
+                "* one\n  * nested\n* [x] done\n* {}\n  {}\n",
+                "word ".repeat(14).trim(),
+                "word ".repeat(6).trim()
+            )
+        );
+        let ordered: String = (1..=10).map(|n| format!("{}. item\n", n)).collect();
+        let out = text(&ordered);
+        assert!(out.starts_with(" 1. item\n 2. item\n"), "{}", out);
+        assert!(out.ends_with("10. item\n"), "{}", out);
+    }
+}
@@ -0,0 +1,955 @@
+use crate::config::{FootnotePlacement, HeadingCase, ImageRewrite, QuoteStyle};
+use markdown::mdast::{
+    FootnoteDefinition, FootnoteReference, Link, LinkReference, Node, Paragraph, Text,
+    ThematicBreak,
+};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Rewrite every image into a plain link labelled with its alt text (or URL)
+pub fn images_to_links(node: &mut Node) {
+    match node {
+        Node::Image(image) => {
+            let label = if image.alt.is_empty() {
+                image.url.clone()
+            } else {
+                image.alt.clone()
+            };
+            *node = Node::Link(Link {
+                children: vec![text(label)],
+                position: image.position.clone(),
+                url: std::mem::take(&mut image.url),
+                title: image.title.take(),
+            });
+        }
+        Node::ImageReference(imgref) => {
+            let label = if imgref.alt.is_empty() {
+                imgref.identifier.clone()
+            } else {
+                imgref.alt.clone()
+            };
+            *node = Node::LinkReference(LinkReference {
+                children: vec![text(label)],
+                position: imgref.position.clone(),
+                reference_kind: imgref.reference_kind,
+                identifier: std::mem::take(&mut imgref.identifier),
+                label: imgref.label.take(),
+            });
+        }
+        _ => {
+            if let Some(children) = node.children_mut() {
+                for child in children {
+                    images_to_links(child);
+                }
+            }
+        }
+    }
+}
+
+/// Apply `rules` to the URL of every image, and of the definitions image
+/// references use, before anything loads them
+pub fn rewrite_image_urls(root: &mut Node, rules: &[ImageRewrite]) {
+    fn image_references(node: &Node, ids: &mut HashSet<String>) {
+        if let Node::ImageReference(imgref) = node {
+            ids.insert(imgref.identifier.to_lowercase());
+        }
+        for child in node.children().into_iter().flatten() {
+            image_references(child, ids);
+        }
+    }
+
+    fn rewrite(node: &mut Node, rules: &[ImageRewrite], ids: &HashSet<String>) {
+        match node {
+            Node::Image(image) => {
+                if let Some(url) = ImageRewrite::apply(rules, &image.url) {
+                    image.url = url;
+                }
+            }
+            Node::Definition(def) if ids.contains(&def.identifier.to_lowercase()) => {
+                if let Some(url) = ImageRewrite::apply(rules, &def.url) {
+                    def.url = url;
+                }
+            }
+            _ => {}
+        }
+        for child in node.children_mut().into_iter().flatten() {
+            rewrite(child, rules, ids);
+        }
+    }
+
+    let mut ids = HashSet::new();
+    image_references(root, &mut ids);
+    rewrite(root, rules, &ids);
+}
+
+/// Point relative links to markdown documents (`.md`, `.markdown`) at the
+/// converted file with extension `ext`, keeping any query or fragment
+pub fn rewrite_md_links(node: &mut Node, ext: &str) {
+    match node {
+        Node::Link(link) => {
+            if let Some(url) = rewrite_md_url(&link.url, ext) {
+                link.url = url;
+            }
+        }
+        Node::Definition(def) => {
+            if let Some(url) = rewrite_md_url(&def.url, ext) {
+                def.url = url;
+            }
+        }
+        _ => {}
+    }
+    if let Some(children) = node.children_mut() {
+        for child in children {
+            rewrite_md_links(child, ext);
+        }
+    }
+}
+
+fn rewrite_md_url(url: &str, ext: &str) -> Option<String> {
+    // Leave URLs with a scheme (https:, mailto:) and protocol-relative URLs alone
+    let has_scheme = url
+        .find(':')
+        .is_some_and(|i| !url[..i].contains(['/', '?', '#']));
+    if has_scheme || url.starts_with("//") {
+        return None;
+    }
+
+    let split = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(split);
+    let lower = path.to_ascii_lowercase();
+    let stem = if lower.ends_with(".md") {
+        &path[..path.len() - 3]
+    } else if lower.ends_with(".markdown") {
+        &path[..path.len() - 9]
+    } else {
+        return None;
+    };
+    Some(format!("{}.{}{}", stem, ext, suffix))
+}
+
+/// Keep only the section under the first top-level heading whose text matches
+/// `title` (ignoring case), optionally only at `level`: the heading and the
+/// blocks up to the next heading of the same or a higher level. Link and
+/// footnote definitions elsewhere stay so references in the section resolve.
+/// Returns the section's byte range in the source, or `None` without a match.
+pub fn extract_section(root: &mut Node, title: &str, level: Option<u8>) -> Option<Range<usize>> {
+    let children = root.children_mut()?;
+    let title = title.trim().to_lowercase();
+    let (start, depth) = children
+        .iter()
+        .enumerate()
+        .find_map(|(i, node)| match node {
+            Node::Heading(h)
+                if level.is_none_or(|l| h.depth == l)
+                    && node.to_string().trim().to_lowercase() == title =>
+            {
+                Some((i, h.depth))
+            }
+            _ => None,
+        })?;
+    let end = children[start + 1..]
+        .iter()
+        .position(|node| matches!(node, Node::Heading(h) if h.depth <= depth))
+        .map_or(children.len(), |i| start + 1 + i);
+
+    let range = match (children[start].position(), children[end - 1].position()) {
+        (Some(first), Some(last)) => first.start.offset..last.end.offset,
+        _ => 0..0,
+    };
+    let section: Vec<Node> = children.drain(start..end).collect();
+    children.retain(|node| matches!(node, Node::Definition(_) | Node::FootnoteDefinition(_)));
+    children.splice(0..0, section);
+    Some(range)
+}
+
+/// Resolve footnotes into plain content so every output format shows them.
+/// For `End` and `Section`, references become `[n]` markers numbered in order
+/// of first use, and the notes follow a rule at the end of the document or
+/// before the next H1/H2. For `Inline`, the note text replaces the reference
+/// in parentheses.
+pub fn place_footnotes(root: &mut Node, placement: FootnotePlacement) {
+    let mut definitions = HashMap::new();
+    take_footnote_definitions(root, &mut definitions);
+    let Some(children) = root.children_mut() else {
+        return;
+    };
+
+    if placement == FootnotePlacement::Inline {
+        for child in children.iter_mut() {
+            inline_footnotes(child, &definitions);
+        }
+        return;
+    }
+
+    let mut numbers = HashMap::new();
+    let mut pending = Vec::new();
+    let mut blocks = Vec::with_capacity(children.len());
+    for mut child in std::mem::take(children) {
+        let new_section = matches!(&child, Node::Heading(h) if h.depth <= 2);
+        if placement == FootnotePlacement::Section && new_section {
+            flush_notes(&mut blocks, &mut pending, &mut numbers, &definitions);
+        }
+        number_footnotes(&mut child, &mut numbers, &mut pending);
+        blocks.push(child);
+    }
+    flush_notes(&mut blocks, &mut pending, &mut numbers, &definitions);
+    *children = blocks;
+}
+
+/// Turn `^[note]` inline footnotes into references to definitions appended
+/// to the document, numbered after the identifiers already in use, so they
+/// go through the same pipeline as `[^label]` footnotes
+pub fn inline_footnote_syntax(root: &mut Node) {
+    let mut used = HashSet::new();
+    footnote_identifiers(root, &mut used);
+    let mut notes = Vec::new();
+    let mut next_id = || {
+        let id = (1..)
+            .map(|n: usize| n.to_string())
+            .find(|id| !used.contains(id))
+            .expect("unbounded range");
+        used.insert(id.clone());
+        id
+    };
+    extract_inline_notes(root, &mut notes, &mut next_id);
+    if let Some(children) = root.children_mut() {
+        children.extend(notes.into_iter().map(|(identifier, content)| {
+            Node::FootnoteDefinition(FootnoteDefinition {
+                children: vec![Node::Paragraph(Paragraph {
+                    children: content,
+                    position: None,
+                })],
+                position: None,
+                label: Some(identifier.clone()),
+                identifier,
+            })
+        }));
+    }
+}
+
+fn footnote_identifiers(node: &Node, used: &mut HashSet<String>) {
+    match node {
+        Node::FootnoteReference(r) => {
+            used.insert(r.identifier.clone());
+        }
+        Node::FootnoteDefinition(d) => {
+            used.insert(d.identifier.clone());
+        }
+        _ => {}
+    }
+    for child in node.children().into_iter().flatten() {
+        footnote_identifiers(child, used);
+    }
+}
+
+/// Replace `^[...]` runs among `node`'s children, which may span several
+/// inline nodes (`^[see *this*]`), with references
+fn extract_inline_notes(
+    node: &mut Node,
+    notes: &mut Vec<(String, Vec<Node>)>,
+    next_id: &mut dyn FnMut() -> String,
+) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    let mut i = 0;
+    while i < children.len() {
+        let start = match &children[i] {
+            Node::Text(t) => t.value.find("^["),
+            _ => None,
+        };
+        let Some(start) = start else {
+            extract_inline_notes(&mut children[i], notes, next_id);
+            i += 1;
+            continue;
+        };
+        let Some((end_node, end)) = closing_bracket(children, i, start + 2) else {
+            // Unclosed: leave the text, but look inside the other children
+            i += 1;
+            continue;
+        };
+
+        let mut tail: Vec<Node> = children.drain(i + 1..=end_node).collect();
+        let Node::Text(first) = &mut children[i] else {
+            unreachable!("matched above");
+        };
+        let mut content = Vec::new();
+        let after = if tail.is_empty() {
+            let after = first.value[end + 1..].to_string();
+            content.push(text(first.value[start + 2..end].to_string()));
+            after
+        } else {
+            content.push(text(first.value[start + 2..].to_string()));
+            let Some(Node::Text(last)) = tail.pop() else {
+                unreachable!("the bracket closes in a text node");
+            };
+            content.extend(tail);
+            content.push(text(last.value[..end].to_string()));
+            last.value[end + 1..].to_string()
+        };
+        first.value.truncate(start);
+        content.retain(|n| !matches!(n, Node::Text(t) if t.value.is_empty()));
+
+        // Notes can nest
+        let mut holder = Node::Paragraph(Paragraph {
+            children: content,
+            position: None,
+        });
+        extract_inline_notes(&mut holder, notes, next_id);
+        let identifier = next_id();
+        notes.push((
+            identifier.clone(),
+            holder
+                .children_mut()
+                .map(std::mem::take)
+                .unwrap_or_default(),
+        ));
+
+        let reference = Node::FootnoteReference(FootnoteReference {
+            position: None,
+            label: Some(identifier.clone()),
+            identifier,
+        });
+        children.insert(i + 1, reference);
+        children.insert(i + 2, text(after));
+        // Carry on with the text after the note
+        i += 2;
+    }
+    children.retain(|n| !matches!(n, Node::Text(t) if t.value.is_empty()));
+}
+
+/// The node and byte offset of the `]` closing a bracket opened before
+/// `from` in `children[index]`, counting nested brackets in text nodes
+fn closing_bracket(children: &[Node], index: usize, from: usize) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    for (n, child) in children.iter().enumerate().skip(index) {
+        let Node::Text(t) = child else {
+            continue;
+        };
+        let offset = if n == index { from } else { 0 };
+        for (pos, c) in t.value[offset..].char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' if depth == 0 => return Some((n, offset + pos)),
+                ']' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Remove footnote definitions from the tree, keyed by identifier
+fn take_footnote_definitions(node: &mut Node, definitions: &mut HashMap<String, Vec<Node>>) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    for mut child in std::mem::take(children) {
+        if let Node::FootnoteDefinition(def) = child {
+            definitions.insert(def.identifier, def.children);
+        } else {
+            take_footnote_definitions(&mut child, definitions);
+            children.push(child);
+        }
+    }
+}
+
+/// Replace references with `[n]`, queueing notes seen for the first time
+fn number_footnotes(
+    node: &mut Node,
+    numbers: &mut HashMap<String, usize>,
+    pending: &mut Vec<(usize, String)>,
+) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    for child in children {
+        if let Node::FootnoteReference(reference) = child {
+            let next = numbers.len() + 1;
+            let number = *numbers
+                .entry(reference.identifier.clone())
+                .or_insert_with(|| {
+                    pending.push((next, reference.identifier.clone()));
+                    next
+                });
+            *child = text(format!("[{}]", number));
+        } else {
+            number_footnotes(child, numbers, pending);
+        }
+    }
+}
+
+/// Append a rule and the queued notes; references inside notes queue more
+fn flush_notes(
+    blocks: &mut Vec<Node>,
+    pending: &mut Vec<(usize, String)>,
+    numbers: &mut HashMap<String, usize>,
+    definitions: &HashMap<String, Vec<Node>>,
+) {
+    if pending.is_empty() {
+        return;
+    }
+    blocks.push(Node::ThematicBreak(ThematicBreak { position: None }));
+    while !pending.is_empty() {
+        for (number, identifier) in std::mem::take(pending) {
+            let mut note = definitions.get(&identifier).cloned().unwrap_or_default();
+            let marker = text(format!("[{}] ", number));
+            match note.first_mut() {
+                Some(Node::Paragraph(para)) => para.children.insert(0, marker),
+                _ => note.insert(
+                    0,
+                    Node::Paragraph(Paragraph {
+                        children: vec![marker],
+                        position: None,
+                    }),
+                ),
+            }
+            for block in &mut note {
+                number_footnotes(block, numbers, pending);
+            }
+            blocks.extend(note);
+        }
+    }
+}
+
+/// Replace references with ` (note text)`
+fn inline_footnotes(node: &mut Node, definitions: &HashMap<String, Vec<Node>>) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    for mut child in std::mem::take(children) {
+        let note = match &child {
+            Node::FootnoteReference(reference) => definitions.get(&reference.identifier),
+            _ => None,
+        };
+        match note {
+            Some(note) => {
+                children.push(text(" (".to_string()));
+                for (i, block) in note.iter().enumerate() {
+                    if i > 0 {
+                        children.push(text(" ".to_string()));
+                    }
+                    match block {
+                        Node::Paragraph(para) => children.extend(para.children.iter().cloned()),
+                        other => children.push(text(other.to_string())),
+                    }
+                }
+                children.push(text(")".to_string()));
+            }
+            None => {
+                inline_footnotes(&mut child, definitions);
+                children.push(child);
+            }
+        }
+    }
+}
+
+/// Turn `---` into an em dash, `--` into an en dash and `...` into an
+/// ellipsis in text. Code is left untouched.
+pub fn smart_punctuation(node: &mut Node) {
+    match node {
+        Node::Text(text) => {
+            if text.value.contains("--") || text.value.contains("...") {
+                text.value = text
+                    .value
+                    .replace("---", "—")
+                    .replace("--", "–")
+                    .replace("...", "…");
+            }
+        }
+        _ => {
+            for child in node.children_mut().into_iter().flatten() {
+                smart_punctuation(child);
+            }
+        }
+    }
+}
+
+/// Words title case leaves in lower case unless they start or end the heading
+const MINOR_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "if", "in", "into", "nor", "of",
+    "off", "on", "or", "per", "so", "than", "the", "to", "up", "via", "vs", "with", "yet",
+];
+
+/// Recapitalize heading text. Words with capitals past their first letter
+/// (API, GitHub, iOS) are kept as written, as is code; sentence case can't
+/// tell proper nouns apart and lowercases them too.
+pub fn heading_case(node: &mut Node, case: HeadingCase) {
+    match node {
+        Node::Heading(heading) => {
+            let mut texts = Vec::new();
+            heading_texts(&mut heading.children, &mut texts);
+            let total = texts.iter().map(|t| words(&t.value).count()).sum();
+            let mut index = 0;
+            let mut starts = true;
+            for text in texts {
+                text.value = recase(&text.value, case, total, &mut index, &mut starts);
+            }
+        }
+        _ => {
+            for child in node.children_mut().into_iter().flatten() {
+                heading_case(child, case);
+            }
+        }
+    }
+}
+
+fn heading_texts<'a>(nodes: &'a mut [Node], out: &mut Vec<&'a mut Text>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push(text),
+            other => heading_texts(
+                other.children_mut().map_or(&mut [][..], Vec::as_mut_slice),
+                out,
+            ),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\'' || c == '’'
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c| !is_word_char(c)).filter(|w| !w.is_empty())
+}
+
+/// Recase the words of one text node. `index` counts words across the
+/// heading, and `starts` is set at the start of the heading and of a
+/// sentence (or, in title case, a subtitle after a colon).
+fn recase(
+    text: &str,
+    case: HeadingCase,
+    total: usize,
+    index: &mut usize,
+    starts: &mut bool,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if !is_word_char(c) {
+            out.push(c);
+            match (c, case) {
+                ('.' | '?' | '!', _) | (':', HeadingCase::Title) => *starts = true,
+                _ => {}
+            }
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let end = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+        let word = &rest[..end];
+        rest = &rest[end..];
+
+        let keep = word == "I" || word.chars().skip(1).any(char::is_uppercase);
+        let lower = word.to_lowercase();
+        let capitalize = match case {
+            HeadingCase::Keep => false,
+            HeadingCase::Title => {
+                *starts || *index + 1 == total || !MINOR_WORDS.contains(&lower.as_str())
+            }
+            HeadingCase::Sentence => *starts,
+        };
+        if keep || case == HeadingCase::Keep {
+            out.push_str(word);
+        } else if capitalize {
+            let mut chars = lower.chars();
+            out.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+            out.push_str(chars.as_str());
+        } else {
+            out.push_str(&lower);
+        }
+        *index += 1;
+        *starts = false;
+    }
+    out
+}
+
+/// Replace straight quotes in text with the given quotation marks.
+/// Code is left untouched; quote context carries across inline formatting.
+pub fn smart_quotes(node: &mut Node, style: &QuoteStyle) {
+    let mut prev = None;
+    quotes_in_node(node, style, &mut prev);
+}
+
+fn quotes_in_node(node: &mut Node, style: &QuoteStyle, prev: &mut Option<char>) {
+    match node {
+        Node::Text(text) => text.value = replace_quotes(&text.value, style, prev),
+        // Inline code reads like a word for the surrounding quotes
+        Node::InlineCode(_) | Node::InlineMath(_) => *prev = Some('x'),
+        Node::Code(_) | Node::Math(_) | Node::Html(_) => {}
+        Node::Emphasis(_)
+        | Node::Strong(_)
+        | Node::Delete(_)
+        | Node::Link(_)
+        | Node::LinkReference(_) => {
+            if let Some(children) = node.children_mut() {
+                for child in children {
+                    quotes_in_node(child, style, prev);
+                }
+            }
+        }
+        _ => {
+            // Block boundaries reset the context
+            *prev = None;
+            if let Some(children) = node.children_mut() {
+                for child in children {
+                    quotes_in_node(child, style, prev);
+                }
+            }
+            *prev = None;
+        }
+    }
+}
+
+fn replace_quotes(text: &str, style: &QuoteStyle, prev: &mut Option<char>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let opens = prev.is_none_or(|p| p.is_whitespace() || "([{-–—/".contains(p));
+        match c {
+            '"' if opens => out.push_str(&style.double_open),
+            '"' => out.push_str(&style.double_close),
+            '\'' => {
+                let next = chars.get(i + 1);
+                let in_word = prev.is_some_and(|p| p.is_alphanumeric())
+                    && next.is_some_and(|n| n.is_alphanumeric());
+                if in_word {
+                    out.push('’');
+                } else if opens {
+                    out.push_str(&style.single_open);
+                } else {
+                    out.push_str(&style.single_close);
+                }
+            }
+            _ => out.push(c),
+        }
+        *prev = Some(c);
+    }
+    out
+}
+
+fn text(value: String) -> Node {
+    Node::Text(Text {
+        value,
+        position: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    fn parse_markdown(md: &str) -> Node {
+        markdown::to_mdast(md, &ParseOptions::gfm()).unwrap()
+    }
+
+    fn first_inline(ast: &Node) -> &Node {
+        &ast.children().unwrap()[0].children().unwrap()[0]
+    }
+
+    #[test]
+    fn test_extract_section() {
+        let md = "# Guide\n\nIntro\n\n## Release Notes\n\nSee [docs][d].\n\n\
+                  ### Fixes\n\nMany\n\n## Install\n\nSteps\n\n[d]: https://example.com\n";
+        let mut ast = parse_markdown(md);
+        let range = extract_section(&mut ast, " release notes", None).unwrap();
+
+        assert_eq!(
+            &md[range],
+            "## Release Notes\n\nSee [docs][d].\n\n### Fixes\n\nMany"
+        );
+        let kinds: Vec<&str> = ast
+            .children()
+            .unwrap()
+            .iter()
+            .map(|node| match node {
+                Node::Heading(_) => "heading",
+                Node::Paragraph(_) => "paragraph",
+                Node::Definition(_) => "definition",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["heading", "paragraph", "heading", "paragraph", "definition"]
+        );
+    }
+
+    #[test]
+    fn test_extract_section_level() {
+        let md = "# Notes\n\nTop\n\n## Notes\n\nNested\n";
+        let mut ast = parse_markdown(md);
+        let range = extract_section(&mut ast, "Notes", Some(2)).unwrap();
+        assert_eq!(&md[range], "## Notes\n\nNested");
+
+        let mut ast = parse_markdown(md);
+        assert!(extract_section(&mut ast, "Notes", Some(3)).is_none());
+        assert!(extract_section(&mut ast, "Missing", None).is_none());
+    }
+
+    #[test]
+    fn test_images_to_links() {
+        let mut ast = parse_markdown("![Logo](logo.png \"Title\")");
+        images_to_links(&mut ast);
+
+        match first_inline(&ast) {
+            Node::Link(link) => {
+                assert_eq!(link.url, "logo.png");
+                assert_eq!(link.title.as_deref(), Some("Title"));
+                assert!(matches!(&link.children[0], Node::Text(t) if t.value == "Logo"));
+            }
+            other => panic!("expected link, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_images_to_links_empty_alt_uses_url() {
+        let mut ast = parse_markdown("- ![](https://example.com/a.png)");
+        images_to_links(&mut ast);
+
+        let item = &ast.children().unwrap()[0].children().unwrap()[0];
+        match first_inline(item) {
+            Node::Link(link) => {
+                assert!(
+                    matches!(&link.children[0], Node::Text(t) if t.value == "https://example.com/a.png")
+                );
+            }
+            other => panic!("expected link, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rewrite_image_urls() {
+        let mut ast = parse_markdown(
+            "![a](/assets/a.png) [doc](/assets/doc.pdf) ![b][logo]\n\n[logo]: /assets/b.png\n",
+        );
+        let rules = [ImageRewrite::new("^/assets/", "https://cdn.test/").unwrap()];
+        rewrite_image_urls(&mut ast, &rules);
+        let urls: Vec<&str> = ast
+            .children()
+            .unwrap()
+            .iter()
+            .flat_map(|block| block.children().into_iter().flatten().chain([block]))
+            .filter_map(|node| match node {
+                Node::Image(image) => Some(image.url.as_str()),
+                Node::Link(link) => Some(link.url.as_str()),
+                Node::Definition(def) => Some(def.url.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Links are left alone
+        assert_eq!(
+            urls,
+            vec![
+                "https://cdn.test/a.png",
+                "/assets/doc.pdf",
+                "https://cdn.test/b.png"
+            ]
+        );
+    }
+
+    fn quoted(md: &str, locale: &str) -> String {
+        let mut ast = parse_markdown(md);
+        smart_quotes(&mut ast, &QuoteStyle::for_locale(locale).unwrap());
+        ast.to_string()
+    }
+
+    #[test]
+    fn test_smart_quotes_german() {
+        assert_eq!(
+            quoted("Er sagte \"Hallo 'Welt'\" und ging.", "de"),
+            "Er sagte „Hallo ‚Welt‘“ und ging."
+        );
+    }
+
+    #[test]
+    fn test_smart_quotes_apostrophe() {
+        assert_eq!(quoted("It's \"fine\"", "en"), "It’s “fine”");
+    }
+
+    #[test]
+    fn test_smart_quotes_across_formatting() {
+        // Closing quote after emphasis still closes
+        assert_eq!(quoted("\"*word*\"", "en"), "“word”");
+    }
+
+    #[test]
+    fn test_smart_punctuation() {
+        let mut ast = parse_markdown("Wait... pages 3--5 --- \"done\" `a -- b...`");
+        smart_punctuation(&mut ast);
+        smart_quotes(&mut ast, &QuoteStyle::for_locale("en").unwrap());
+        assert_eq!(ast.to_string(), "Wait… pages 3–5 — “done” a -- b...");
+        assert!(matches!(
+            &ast.children().unwrap()[0].children().unwrap()[1],
+            Node::InlineCode(code) if code.value == "a -- b..."
+        ));
+    }
+
+    #[test]
+    fn test_heading_case() {
+        let cased = |md: &str, case| {
+            let mut ast = parse_markdown(md);
+            heading_case(&mut ast, case);
+            ast.to_string()
+        };
+        assert_eq!(
+            cased(
+                "# getting started with the GitHub API: a guide to *it*",
+                HeadingCase::Title
+            ),
+            "Getting Started with the GitHub API: A Guide to It"
+        );
+        assert_eq!(
+            cased(
+                "## What I Learned From `fooBar` In iOS. Next Steps",
+                HeadingCase::Sentence
+            ),
+            "What I learned from fooBar in iOS. Next steps"
+        );
+        // Body text is left alone
+        assert_eq!(cased("the end of it", HeadingCase::Title), "the end of it");
+    }
+
+    #[test]
+    fn test_inline_footnote_syntax() {
+        let mut ast =
+            parse_markdown("Text[^1] and^[a *short* note] end^[outer ^[inner]].\n\n[^1]: One\n");
+        inline_footnote_syntax(&mut ast);
+        let para = ast.children().unwrap()[0].children().unwrap();
+        assert_eq!(para.len(), 7);
+        assert!(matches!(&para[3], Node::FootnoteReference(r) if r.identifier == "2"));
+        assert!(matches!(&para[5], Node::FootnoteReference(r) if r.identifier == "4"));
+        assert!(matches!(&para[6], Node::Text(t) if t.value == "."));
+
+        let notes: Vec<(String, String)> = ast.children().unwrap()[1..]
+            .iter()
+            .filter_map(|node| match node {
+                Node::FootnoteDefinition(def) => Some((def.identifier.clone(), node.to_string())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("1".to_string(), "One".to_string()),
+                ("2".to_string(), "a short note".to_string()),
+                ("3".to_string(), "inner".to_string()),
+                ("4".to_string(), "outer ".to_string()),
+            ]
+        );
+
+        // They take part in footnote placement like any other
+        let mut ast = parse_markdown("See^[the *docs*] and[^a].\n\n[^a]: More\n");
+        inline_footnote_syntax(&mut ast);
+        place_footnotes(&mut ast, FootnotePlacement::Inline);
+        assert_eq!(ast.to_string(), "See (the docs) and (More).");
+    }
+
+    #[test]
+    fn test_smart_quotes_skip_code() {
+        let mut ast = parse_markdown("Use `\"x\"` here");
+        smart_quotes(&mut ast, &QuoteStyle::for_locale("en").unwrap());
+        let para = &ast.children().unwrap()[0];
+        assert!(matches!(&para.children().unwrap()[1], Node::InlineCode(c) if c.value == "\"x\""));
+    }
+
+    #[test]
+    fn test_rewrite_md_links() {
+        let mut ast = parse_markdown(
+            "[a](guide/intro.md#setup) [b](https://example.com/x.md) [c](notes.txt) \
+             [d][ref]\n\n[ref]: ../README.markdown",
+        );
+        rewrite_md_links(&mut ast, "html");
+
+        let para = ast.children().unwrap()[0].children().unwrap();
+        let urls: Vec<_> = para
+            .iter()
+            .filter_map(|n| match n {
+                Node::Link(link) => Some(link.url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "guide/intro.html#setup",
+                "https://example.com/x.md",
+                "notes.txt"
+            ]
+        );
+        assert!(
+            matches!(&ast.children().unwrap()[1], Node::Definition(d) if d.url == "../README.html")
+        );
+    }
+
+    fn footnote_blocks(md: &str, placement: FootnotePlacement) -> Vec<String> {
+        let mut ast = parse_markdown(md);
+        place_footnotes(&mut ast, placement);
+        ast.children()
+            .unwrap()
+            .iter()
+            .map(|block| match block {
+                Node::ThematicBreak(_) => "---".to_string(),
+                other => other.to_string(),
+            })
+            .collect()
+    }
+
+    const FOOTNOTES: &str = "# Intro\n\nA[^b] and B[^a].\n\n## Next\n\nAgain[^b], C[^c].\n\n\
+                             [^a]: Note A\n[^b]: Note B\n[^c]: Note C";
+
+    #[test]
+    fn test_place_footnotes_end() {
+        assert_eq!(
+            footnote_blocks(FOOTNOTES, FootnotePlacement::End),
+            vec![
+                "Intro",
+                "A[1] and B[2].",
+                "Next",
+                "Again[1], C[3].",
+                "---",
+                "[1] Note B",
+                "[2] Note A",
+                "[3] Note C"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_place_footnotes_section() {
+        assert_eq!(
+            footnote_blocks(FOOTNOTES, FootnotePlacement::Section),
+            vec![
+                "Intro",
+                "A[1] and B[2].",
+                "---",
+                "[1] Note B",
+                "[2] Note A",
+                "Next",
+                "Again[1], C[3].",
+                "---",
+                "[3] Note C"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_place_footnotes_inline() {
+        assert_eq!(
+            footnote_blocks(
+                "See this[^1].\n\n[^1]: A *short* note",
+                FootnotePlacement::Inline
+            ),
+            vec!["See this (A short note)."]
+        );
+    }
+
+    #[test]
+    fn test_image_reference_to_link_reference() {
+        let mut ast = parse_markdown("![Chart][c]\n\n[c]: chart.png");
+        images_to_links(&mut ast);
+
+        assert!(matches!(first_inline(&ast), Node::LinkReference(r) if r.identifier == "c"));
+    }
+}
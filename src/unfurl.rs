@@ -0,0 +1,346 @@
+use crate::config::ImageConfig;
+use crate::image::{fetch_bytes, is_remote_url, request_headers, tls_config, url_host};
+use log::{debug, warn};
+use markdown::mdast::{Blockquote, Image, Link, Node, Paragraph, Strong, Text};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Largest page fetched for metadata
+const MAX_PAGE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Longest description kept on a card, in characters
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// Preview metadata of a web page (Open Graph, falling back to plain HTML)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PageMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Replace paragraphs holding only a bare URL with a preview card: a
+/// blockquote with the linked page title, its description and thumbnail.
+/// Pages that can't be fetched or have no title keep the plain link.
+pub fn unfurl_links(root: &mut Node, image_config: &ImageConfig) {
+    let mut pages: HashMap<String, Option<PageMeta>> = HashMap::new();
+    add_cards(root, &mut |url| {
+        pages
+            .entry(url.to_string())
+            .or_insert_with(|| match fetch_meta(url, image_config) {
+                Ok(meta) => Some(meta),
+                Err(e) => {
+                    warn!("Failed to unfurl {}: {}", url, e);
+                    None
+                }
+            })
+            .clone()
+    });
+}
+
+fn add_cards(node: &mut Node, lookup: &mut dyn FnMut(&str) -> Option<PageMeta>) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    for child in children {
+        if let Some(url) = bare_url(child)
+            && let Some(meta) = lookup(&url)
+            && let Some(card) = card(&url, meta)
+        {
+            *child = card;
+        } else {
+            add_cards(child, lookup);
+        }
+    }
+}
+
+/// The URL of a paragraph consisting of a single link whose text is the URL itself
+fn bare_url(node: &Node) -> Option<String> {
+    let Node::Paragraph(para) = node else {
+        return None;
+    };
+    let [Node::Link(link)] = para.children.as_slice() else {
+        return None;
+    };
+    let [Node::Text(text)] = link.children.as_slice() else {
+        return None;
+    };
+    let is_bare = text.value == link.url || link.url.ends_with(&format!("://{}", text.value));
+    (is_bare && is_remote_url(&link.url) && !link.url.starts_with("//")).then(|| link.url.clone())
+}
+
+fn card(url: &str, meta: PageMeta) -> Option<Node> {
+    let title = meta.title?;
+    let mut blocks = vec![paragraph(vec![Node::Strong(Strong {
+        children: vec![Node::Link(Link {
+            children: vec![text(title.clone())],
+            position: None,
+            url: url.to_string(),
+            title: None,
+        })],
+        position: None,
+    })])];
+    if let Some(description) = meta.description {
+        blocks.push(paragraph(vec![text(truncate(&description))]));
+    }
+    if let Some(image) = meta.image {
+        blocks.push(paragraph(vec![Node::Image(Image {
+            position: None,
+            alt: title,
+            url: image,
+            title: None,
+        })]));
+    }
+    Some(Node::Blockquote(Blockquote {
+        children: blocks,
+        position: None,
+    }))
+}
+
+fn fetch_meta(url: &str, image_config: &ImageConfig) -> Result<PageMeta, String> {
+    debug!("Fetching preview metadata: {}", url);
+    let timeout = (image_config.fetch_timeout_ms > 0)
+        .then(|| Duration::from_millis(image_config.fetch_timeout_ms));
+    let headers = request_headers(url, image_config);
+    let tls = tls_config(image_config)?;
+    let (mime_type, data) = match fetch_bytes(url, &headers, &tls, timeout, MAX_PAGE_BYTES) {
+        Ok(page) => page,
+        Err(ureq::Error::BodyExceedsLimit(_)) => return Err("page larger than 4 MiB".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if !mime_type.contains("html") {
+        return Err(format!("not an HTML page ({})", mime_type));
+    }
+    let mut meta = parse_meta(&String::from_utf8_lossy(&data));
+    meta.image = meta.image.map(|image| resolve_url(url, &image));
+    Ok(meta)
+}
+
+/// Extract Open Graph title, description and image, falling back to
+/// `<title>` and `<meta name="description">`
+pub fn parse_meta(html: &str) -> PageMeta {
+    let mut og = PageMeta::default();
+    let mut fallback = PageMeta::default();
+
+    // ASCII lowercasing keeps byte offsets, so positions carry over to `html`
+    let lower = html.to_ascii_lowercase();
+    let mut pos = 0;
+    while let Some(i) = lower[pos..].find("<meta") {
+        let start = pos + i;
+        let tag_end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        let tag = &html[start..tag_end];
+        pos = tag_end;
+
+        let key = attribute(tag, "property")
+            .or_else(|| attribute(tag, "name"))
+            .map(|k| k.to_ascii_lowercase());
+        let (Some(key), Some(content)) = (key, attribute(tag, "content")) else {
+            continue;
+        };
+        let content = decode_entities(content.trim());
+        if content.is_empty() {
+            continue;
+        }
+        let slot = match key.as_str() {
+            "og:title" => &mut og.title,
+            "og:description" => &mut og.description,
+            "og:image" | "og:image:url" => &mut og.image,
+            "twitter:title" => &mut fallback.title,
+            "description" | "twitter:description" => &mut fallback.description,
+            "twitter:image" => &mut fallback.image,
+            _ => continue,
+        };
+        slot.get_or_insert(content);
+    }
+
+    if fallback.title.is_none()
+        && let Some(start) = lower.find("<title")
+        && let Some(open_end) = lower[start..].find('>')
+    {
+        let body_start = start + open_end + 1;
+        let end = lower[body_start..]
+            .find("</title")
+            .map_or(html.len(), |i| body_start + i);
+        let words: Vec<&str> = html[body_start..end].split_whitespace().collect();
+        let title = decode_entities(&words.join(" "));
+        if !title.is_empty() {
+            fallback.title = Some(title);
+        }
+    }
+
+    PageMeta {
+        title: og.title.or(fallback.title),
+        description: og.description.or(fallback.description),
+        image: og.image.or(fallback.image),
+    }
+}
+
+/// Value of `name="..."` (or single-quoted) in a tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(name) {
+        let at = from + i;
+        from = at + name.len();
+        // Must be a whole attribute name followed by `=`
+        let before = lower[..at].chars().next_back();
+        if !before.is_some_and(|c| c.is_whitespace()) {
+            continue;
+        }
+        let after = lower[from..].trim_start();
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value_start = tag.len() - value.trim_start().len();
+        let value = &tag[value_start..];
+        let quote = value.chars().next()?;
+        return if quote == '"' || quote == '\'' {
+            value[1..].split(quote).next()
+        } else {
+            value.split(|c: char| c.is_whitespace() || c == '/').next()
+        };
+    }
+    None
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Resolve a possibly relative `href` against the page URL
+fn resolve_url(base: &str, href: &str) -> String {
+    if is_remote_url(href) && !href.starts_with("//") {
+        return href.to_string();
+    }
+    let scheme = base.split_once("://").map_or("https", |(scheme, _)| scheme);
+    if let Some(rest) = href.strip_prefix("//") {
+        return format!("{}://{}", scheme, rest);
+    }
+    let host = url_host(base).unwrap_or_default();
+    if href.starts_with('/') {
+        return format!("{}://{}{}", scheme, host, href);
+    }
+    let path = base
+        .split_once("://")
+        .map_or(base, |(_, rest)| rest)
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+    let dir = path.rsplit_once('/').map_or(path, |(dir, _)| dir);
+    let dir = if dir.contains('/') { dir } else { host };
+    format!("{}://{}/{}", scheme, dir, href)
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return s.to_string();
+    }
+    let cut: String = s.chars().take(MAX_DESCRIPTION_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end())
+}
+
+fn paragraph(children: Vec<Node>) -> Node {
+    Node::Paragraph(Paragraph {
+        children,
+        position: None,
+    })
+}
+
+fn text(value: String) -> Node {
+    Node::Text(Text {
+        value,
+        position: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use markdown::ParseOptions;
+
+    #[test]
+    fn test_parse_meta_open_graph() {
+        let html = r#"<html><head><title>Fallback</title>
+            <meta property="og:title" content="Release &amp; notes">
+            <meta name="description" content='Plain description'>
+            <meta property="og:image" content="/img/card.png" />
+            </head>"#;
+        assert_eq!(
+            parse_meta(html),
+            PageMeta {
+                title: Some("Release & notes".to_string()),
+                description: Some("Plain description".to_string()),
+                image: Some("/img/card.png".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_meta_title_fallback() {
+        let meta = parse_meta("<HTML><TITLE>\n  My   page\n</TITLE></HTML>");
+        assert_eq!(meta.title.as_deref(), Some("My page"));
+        assert_eq!(meta.image, None);
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.com/blog/post?id=1";
+        assert_eq!(resolve_url(base, "/a.png"), "https://example.com/a.png");
+        assert_eq!(resolve_url(base, "a.png"), "https://example.com/blog/a.png");
+        assert_eq!(
+            resolve_url(base, "//cdn.example.com/a.png"),
+            "https://cdn.example.com/a.png"
+        );
+        assert_eq!(
+            resolve_url("https://example.com", "a.png"),
+            "https://example.com/a.png"
+        );
+        assert_eq!(
+            resolve_url(base, "http://other/a.png"),
+            "http://other/a.png"
+        );
+    }
+
+    #[test]
+    fn test_add_cards_only_for_bare_urls() {
+        let md = "https://example.com/post\n\n[named](https://example.com/post)\n\n\
+                  - https://example.com/none";
+        let mut ast = markdown::to_mdast(md, &ParseOptions::gfm()).unwrap();
+        add_cards(&mut ast, &mut |url| {
+            (url == "https://example.com/post").then(|| PageMeta {
+                title: Some("Post".to_string()),
+                description: Some("About it".to_string()),
+                image: Some("https://example.com/card.png".to_string()),
+            })
+        });
+
+        let blocks = ast.children().unwrap();
+        let Node::Blockquote(card) = &blocks[0] else {
+            panic!("expected card, got {:?}", blocks[0]);
+        };
+        assert_eq!(card.children.len(), 3);
+        assert_eq!(card.children[0].to_string(), "Post");
+        assert!(
+            matches!(&card.children[2].children().unwrap()[0], Node::Image(i) if i.url == "https://example.com/card.png")
+        );
+        // Named links and pages without metadata are left alone
+        assert!(matches!(&blocks[1], Node::Paragraph(_)));
+        assert!(matches!(&blocks[2], Node::List(_)));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let long = "word ".repeat(60);
+        let short = truncate(&long);
+        assert!(short.ends_with("word…"));
+        assert!(short.chars().count() <= MAX_DESCRIPTION_CHARS + 1);
+    }
+}
@@ -0,0 +1,69 @@
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+/// What the installed binary can do, for `--version --json`
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub target: &'static str,
+    /// Optional capabilities and whether this build has them
+    pub features: Vec<(&'static str, bool)>,
+    /// Syntaxes and themes bundled with syntect (custom ones not included)
+    pub syntaxes: usize,
+    pub themes: usize,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            target: env!("MDCOPY_TARGET"),
+            features: vec![
+                ("native", cfg!(target_os = "macos")),
+                ("wayland", cfg!(target_os = "linux")),
+                ("clipboard_hold", cfg!(target_os = "linux")),
+                ("osc52", true),
+                ("avif", cfg!(feature = "avif")),
+            ],
+            syntaxes: SyntaxSet::load_defaults_newlines().syntaxes().len(),
+            themes: ThemeSet::load_defaults().themes.len(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let features: Vec<String> = self
+            .features
+            .iter()
+            .map(|(name, enabled)| format!("\"{}\": {}", name, enabled))
+            .collect();
+        format!(
+            "{{\n  \"name\": \"mdcopy\",\n  \"version\": \"{}\",\n  \"target\": \"{}\",\n  \"features\": {{{}}},\n  \"syntaxes\": {},\n  \"themes\": {}\n}}",
+            self.version,
+            self.target,
+            features.join(", "),
+            self.syntaxes,
+            self.themes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info_json() {
+        let info = BuildInfo {
+            version: "1.2.3",
+            target: "x86_64-unknown-linux-gnu",
+            features: vec![("native", false), ("osc52", true)],
+            syntaxes: 75,
+            themes: 7,
+        };
+        assert_eq!(
+            info.to_json(),
+            "{\n  \"name\": \"mdcopy\",\n  \"version\": \"1.2.3\",\n  \"target\": \"x86_64-unknown-linux-gnu\",\n  \
+             \"features\": {\"native\": false, \"osc52\": true},\n  \"syntaxes\": 75,\n  \"themes\": 7\n}"
+        );
+        assert!(BuildInfo::current().syntaxes > 0);
+    }
+}
@@ -0,0 +1,119 @@
+use log::{Level, Log, Metadata, Record};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+/// Warnings held back for the end-of-run summary
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Logger that collects warnings for the summary and forwards every other
+/// record to the wrapped logger
+pub struct SummaryLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> SummaryLogger<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+}
+
+impl<L: Log> Log for SummaryLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            if self.inner.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        } else {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Number of warnings collected so far
+pub fn count() -> usize {
+    WARNINGS.lock().unwrap().len()
+}
+
+/// Print collected warnings to stderr, colored when stderr is a terminal
+/// and NO_COLOR is unset
+pub fn print_summary() {
+    let warnings = std::mem::take(&mut *WARNINGS.lock().unwrap());
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    if let Some(summary) = format_summary(&warnings, color) {
+        eprint!("{}", summary);
+    }
+}
+
+/// Format warnings as a summary block, merging repeats with a count
+fn format_summary(warnings: &[String], color: bool) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+
+    let mut unique: Vec<(&str, usize)> = Vec::new();
+    for warning in warnings {
+        match unique.iter_mut().find(|(w, _)| *w == warning) {
+            Some((_, count)) => *count += 1,
+            None => unique.push((warning, 1)),
+        }
+    }
+
+    let (yellow, dim, reset) = if color {
+        ("\x1b[1;33m", "\x1b[2m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    let noun = if warnings.len() == 1 {
+        "warning"
+    } else {
+        "warnings"
+    };
+
+    let mut out = format!("{}{} {}:{}\n", yellow, warnings.len(), noun, reset);
+    for (warning, count) in unique {
+        out.push_str(&format!("  {}-{} {}", yellow, reset, warning));
+        if count > 1 {
+            out.push_str(&format!(" {}(x{}){}", dim, count, reset));
+        }
+        out.push('\n');
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_summary_empty() {
+        assert_eq!(format_summary(&[], false), None);
+    }
+
+    #[test]
+    fn test_format_summary_merges_repeats() {
+        let warnings = vec![
+            "Image not found: a.png".to_string(),
+            "Unknown language 'foo'".to_string(),
+            "Image not found: a.png".to_string(),
+        ];
+        assert_eq!(
+            format_summary(&warnings, false).unwrap(),
+            "3 warnings:\n  - Image not found: a.png (x2)\n  - Unknown language 'foo'\n"
+        );
+    }
+
+    #[test]
+    fn test_format_summary_color() {
+        let summary = format_summary(&["oops".to_string()], true).unwrap();
+        assert!(summary.starts_with("\x1b[1;33m1 warning:\x1b[0m\n"));
+        assert!(summary.contains("oops"));
+    }
+}